@@ -0,0 +1,14 @@
+// An example material shader. Opening it with a model selected draws the model's materials with
+// this surface function in place of the built in one, see SurfaceInput in src/shaders/model.wgsl
+fn surface(in: SurfaceInput) -> SurfaceOutput {
+    var out: SurfaceOutput;
+    out.base_color = in.base_color;
+    out.metallic = in.metallic;
+    out.roughness = in.roughness;
+    out.normal = in.normal;
+    out.occlusion = in.occlusion;
+    // Glows where the surface turns away from the camera
+    let rim = pow(1.0 - max(dot(in.normal, in.view), 0.0), 4.0);
+    out.emissive = in.emissive + vec3<f32>(0.2, 0.6, 1.0) * rim * 2.0;
+    return out;
+}
@@ -0,0 +1,213 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use winit::event::{MouseButton, VirtualKeyCode};
+
+use crate::bookmarks::BOOKMARK_SLOTS;
+
+// What the app and camera controllers respond to, independent of the keys or buttons bound to it
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Action {
+    MoveForward,
+    MoveBackward,
+    MoveLeft,
+    MoveRight,
+    MoveUp,
+    MoveDown,
+    // Held while dragging the cursor to orbit the camera
+    Orbit,
+    ZoomIn,
+    ZoomOut,
+    Select,
+    ToggleGui,
+    // Switches between a free cursor and relative mouse movement for looking around
+    ToggleCursorGrab,
+    ReleaseCursor,
+    // Cycles through windowed, borderless and exclusive fullscreen
+    ToggleFullscreen,
+    // Switches between the 3D camera and the 2D one
+    Toggle2d,
+    Undo,
+    Redo,
+    // Captures the next frame in RenderDoc, when the app is running under it
+    CaptureFrame,
+    // Starts or stops recording presented frames
+    ToggleRecording,
+    // Frames the selection, or the whole scene when nothing is selected
+    Focus,
+    // Saves the camera to a bookmark slot, or moves it to the one saved there
+    SaveBookmark(u8),
+    GoToBookmark(u8),
+}
+
+// Named by position so layouts with different labels bind the same way. Only pressed when
+// built with the gamepad feature
+#[cfg_attr(not(feature = "gamepad"), allow(dead_code))]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum GamepadButton {
+    South,
+    East,
+    North,
+    West,
+    LeftBumper,
+    RightBumper,
+    LeftTrigger,
+    RightTrigger,
+    Select,
+    Start,
+    LeftThumb,
+    RightThumb,
+    DPadUp,
+    DPadDown,
+    DPadLeft,
+    DPadRight,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Binding {
+    Key(VirtualKeyCode),
+    Mouse(MouseButton),
+    Gamepad(GamepadButton),
+    // A key pressed while a modifier is held, such as Alt+Enter
+    KeyChord(VirtualKeyCode, VirtualKeyCode),
+}
+
+// Any of an action's bindings triggers it, and a binding may trigger several actions
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ActionMap {
+    bindings: HashMap<Action, Vec<Binding>>,
+}
+
+impl Default for ActionMap {
+    fn default() -> Self {
+        let mut action_map = Self {
+            bindings: HashMap::new(),
+        };
+        for (action, binding) in [
+            (Action::MoveForward, Binding::Key(VirtualKeyCode::W)),
+            (Action::MoveBackward, Binding::Key(VirtualKeyCode::S)),
+            (Action::MoveLeft, Binding::Key(VirtualKeyCode::A)),
+            (Action::MoveRight, Binding::Key(VirtualKeyCode::D)),
+            (Action::MoveUp, Binding::Key(VirtualKeyCode::E)),
+            (Action::MoveDown, Binding::Key(VirtualKeyCode::Q)),
+            (Action::ZoomIn, Binding::Key(VirtualKeyCode::Equals)),
+            (Action::ZoomOut, Binding::Key(VirtualKeyCode::Minus)),
+            (Action::Orbit, Binding::Mouse(MouseButton::Right)),
+            (Action::Select, Binding::Mouse(MouseButton::Left)),
+            (Action::ToggleGui, Binding::Key(VirtualKeyCode::F1)),
+            (Action::ToggleCursorGrab, Binding::Key(VirtualKeyCode::Tab)),
+            (Action::ReleaseCursor, Binding::Key(VirtualKeyCode::Escape)),
+            (Action::ToggleFullscreen, Binding::Key(VirtualKeyCode::F11)),
+            (Action::Toggle2d, Binding::Key(VirtualKeyCode::F2)),
+            (Action::CaptureFrame, Binding::Key(VirtualKeyCode::F9)),
+            (Action::ToggleRecording, Binding::Key(VirtualKeyCode::F10)),
+            (Action::Focus, Binding::Key(VirtualKeyCode::F)),
+            (
+                Action::ToggleFullscreen,
+                Binding::KeyChord(VirtualKeyCode::LAlt, VirtualKeyCode::Return),
+            ),
+            (
+                Action::ToggleFullscreen,
+                Binding::KeyChord(VirtualKeyCode::RAlt, VirtualKeyCode::Return),
+            ),
+            (
+                Action::Undo,
+                Binding::KeyChord(VirtualKeyCode::LControl, VirtualKeyCode::Z),
+            ),
+            (
+                Action::Undo,
+                Binding::KeyChord(VirtualKeyCode::RControl, VirtualKeyCode::Z),
+            ),
+            (
+                Action::Redo,
+                Binding::KeyChord(VirtualKeyCode::LControl, VirtualKeyCode::Y),
+            ),
+            (
+                Action::Redo,
+                Binding::KeyChord(VirtualKeyCode::RControl, VirtualKeyCode::Y),
+            ),
+            (Action::MoveForward, Binding::Gamepad(GamepadButton::DPadUp)),
+            (
+                Action::MoveBackward,
+                Binding::Gamepad(GamepadButton::DPadDown),
+            ),
+            (Action::MoveLeft, Binding::Gamepad(GamepadButton::DPadLeft)),
+            (
+                Action::MoveRight,
+                Binding::Gamepad(GamepadButton::DPadRight),
+            ),
+            (Action::MoveUp, Binding::Gamepad(GamepadButton::RightBumper)),
+            (
+                Action::MoveDown,
+                Binding::Gamepad(GamepadButton::LeftBumper),
+            ),
+            (
+                Action::ZoomIn,
+                Binding::Gamepad(GamepadButton::RightTrigger),
+            ),
+            (
+                Action::ZoomOut,
+                Binding::Gamepad(GamepadButton::LeftTrigger),
+            ),
+            (Action::ToggleGui, Binding::Gamepad(GamepadButton::Start)),
+        ] {
+            action_map.bind(action, binding);
+        }
+        let number_keys = [
+            VirtualKeyCode::Key1,
+            VirtualKeyCode::Key2,
+            VirtualKeyCode::Key3,
+            VirtualKeyCode::Key4,
+            VirtualKeyCode::Key5,
+            VirtualKeyCode::Key6,
+            VirtualKeyCode::Key7,
+            VirtualKeyCode::Key8,
+            VirtualKeyCode::Key9,
+        ];
+        for (slot, key) in number_keys.into_iter().enumerate().take(BOOKMARK_SLOTS) {
+            let slot = slot as u8;
+            action_map.bind(Action::GoToBookmark(slot), Binding::Key(key));
+            for control in [VirtualKeyCode::LControl, VirtualKeyCode::RControl] {
+                action_map.bind(Action::SaveBookmark(slot), Binding::KeyChord(control, key));
+            }
+        }
+        action_map
+    }
+}
+
+impl ActionMap {
+    pub fn bind(&mut self, action: Action, binding: Binding) {
+        let bindings = self.bindings.entry(action).or_default();
+        if !bindings.contains(&binding) {
+            bindings.push(binding);
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn unbind(&mut self, action: Action, binding: Binding) {
+        if let Some(bindings) = self.bindings.get_mut(&action) {
+            bindings.retain(|bound| *bound != binding);
+        }
+    }
+
+    // Replaces every binding of the action with a single one
+    #[allow(dead_code)]
+    pub fn rebind(&mut self, action: Action, binding: Binding) {
+        self.bindings.insert(action, vec![binding]);
+    }
+
+    // Actions added since these bindings were saved get their default bindings, while actions
+    // unbound on purpose stay unbound
+    pub fn with_missing_defaults(mut self) -> Self {
+        for (action, bindings) in Self::default().bindings {
+            self.bindings.entry(action).or_insert(bindings);
+        }
+        self
+    }
+
+    pub fn bindings(&self, action: Action) -> &[Binding] {
+        self.bindings
+            .get(&action)
+            .map(|bindings| bindings.as_slice())
+            .unwrap_or_default()
+    }
+}
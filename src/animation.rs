@@ -0,0 +1,309 @@
+use instant::Instant;
+use nalgebra_glm as glm;
+
+use crate::{
+    lighting::PunctualLight,
+    model::{ModelDesc, NodeTransform},
+    scene::Scene,
+};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Interpolation {
+    Step,
+    Linear,
+    // Each keyframe has an in tangent, a value and an out tangent, in that order
+    CubicSpline,
+}
+
+#[derive(Debug, Clone)]
+pub enum ChannelValues {
+    Translations(Vec<glm::Vec3>),
+    Rotations(Vec<glm::Quat>),
+    Scales(Vec<glm::Vec3>),
+}
+
+// Keyframes animating one property of one node
+#[derive(Debug, Clone)]
+pub struct AnimationChannel {
+    pub node: usize,
+    pub times: Vec<f32>,
+    pub values: ChannelValues,
+    pub interpolation: Interpolation,
+}
+
+#[derive(Debug, Clone)]
+pub struct AnimationClip {
+    pub name: Option<String>,
+    // Seconds until the last keyframe
+    pub duration: f32,
+    pub channels: Vec<AnimationChannel>,
+}
+
+// A node of the model's hierarchy in its rest pose, which channels replace parts of
+#[derive(Debug, Clone)]
+pub struct AnimationNode {
+    // Parents always come before their children
+    pub parent: Option<usize>,
+    pub translation: glm::Vec3,
+    pub rotation: glm::Quat,
+    pub scale: glm::Vec3,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct AnimationPlayer {
+    pub clip: usize,
+    // Seconds into the clip
+    pub time: f32,
+    pub playing: bool,
+    pub looping: bool,
+    // Negative speeds play backwards
+    pub speed: f32,
+}
+
+impl Default for AnimationPlayer {
+    fn default() -> Self {
+        Self {
+            clip: 0,
+            time: 0.0,
+            playing: true,
+            looping: true,
+            speed: 1.0,
+        }
+    }
+}
+
+impl AnimationPlayer {
+    // Moves the playhead to a time in the clip, where it stays until played again
+    pub fn seek(&mut self, time: f32) {
+        self.time = time;
+        self.playing = false;
+    }
+
+    fn advance(&mut self, duration: f32, delta_time: f32) {
+        if !self.playing {
+            return;
+        }
+        self.time += delta_time * self.speed;
+        if duration <= 0.0 {
+            self.time = 0.0;
+        } else if self.looping {
+            self.time = self.time.rem_euclid(duration);
+        } else if !(0.0..=duration).contains(&self.time) {
+            self.time = self.time.clamp(0.0, duration);
+            self.playing = false;
+        }
+    }
+}
+
+// The clips of a glTF model and what they move. Posing the hierarchy writes the transforms of
+// the model's instances, lights and skeleton joints
+#[derive(Debug, Clone)]
+pub struct Animations {
+    pub clips: Vec<AnimationClip>,
+    pub nodes: Vec<AnimationNode>,
+    // The node each of the model's instances is placed at
+    pub instance_nodes: Vec<usize>,
+    // The node each of the model's lights is carried by, with the light relative to it
+    pub light_nodes: Vec<(usize, PunctualLight)>,
+    // The node of every joint in each skeleton, None for joints outside the scene
+    pub joint_nodes: Vec<Vec<Option<usize>>>,
+    pub player: AnimationPlayer,
+    // The clip and time last posed, so a paused model is left as it is and can be edited
+    posed: Option<(usize, f32)>,
+}
+
+impl Animations {
+    pub fn new(
+        clips: Vec<AnimationClip>,
+        nodes: Vec<AnimationNode>,
+        instance_nodes: Vec<usize>,
+        light_nodes: Vec<(usize, PunctualLight)>,
+        joint_nodes: Vec<Vec<Option<usize>>>,
+    ) -> Self {
+        Self {
+            clips,
+            nodes,
+            instance_nodes,
+            light_nodes,
+            joint_nodes,
+            player: AnimationPlayer::default(),
+            posed: None,
+        }
+    }
+
+    pub fn clip(&self) -> Option<&AnimationClip> {
+        self.clips.get(self.player.clip)
+    }
+
+    // Node transforms relative to the model in the pose of the player's clip at its time
+    fn pose(&self) -> Vec<NodeTransform> {
+        let mut translations = self
+            .nodes
+            .iter()
+            .map(|node| node.translation)
+            .collect::<Vec<_>>();
+        let mut rotations = self
+            .nodes
+            .iter()
+            .map(|node| node.rotation)
+            .collect::<Vec<_>>();
+        let mut scales = self.nodes.iter().map(|node| node.scale).collect::<Vec<_>>();
+        if let Some(clip) = self.clip() {
+            for channel in clip.channels.iter() {
+                if channel.node >= self.nodes.len() {
+                    continue;
+                }
+                match &channel.values {
+                    ChannelValues::Translations(values) => {
+                        if let Some(value) = sample(channel, values, self.player.time, vec3_lerp) {
+                            translations[channel.node] = value;
+                        }
+                    }
+                    ChannelValues::Rotations(values) => {
+                        if let Some(value) = sample(channel, values, self.player.time, quat_slerp) {
+                            // Cubic splines blend rotations like vectors, leaving them unnormalized
+                            rotations[channel.node] = glm::quat_normalize(&value);
+                        }
+                    }
+                    ChannelValues::Scales(values) => {
+                        if let Some(value) = sample(channel, values, self.player.time, vec3_lerp) {
+                            scales[channel.node] = value;
+                        }
+                    }
+                }
+            }
+        }
+
+        let mut transforms: Vec<NodeTransform> = Vec::with_capacity(self.nodes.len());
+        for (index, node) in self.nodes.iter().enumerate() {
+            let local = (glm::translation(&translations[index])
+                * glm::quat_to_mat4(&rotations[index])
+                * glm::scaling(&scales[index]))
+            .cast();
+            let parent = node
+                .parent
+                .and_then(|parent| transforms.get(parent))
+                .copied()
+                .unwrap_or_else(NodeTransform::identity);
+            transforms.push(parent * local);
+        }
+        transforms
+    }
+
+    fn apply(&self, model: &mut ModelDesc) {
+        let transforms = self.pose();
+        for (instance, node) in model.instances.iter_mut().zip(self.instance_nodes.iter()) {
+            if let Some(transform) = transforms.get(*node) {
+                instance.transform = *transform;
+            }
+        }
+        for (light, (node, local)) in model.lights.iter_mut().zip(self.light_nodes.iter()) {
+            if let Some(transform) = transforms.get(*node) {
+                *light = local.transformed(&transform.cast());
+            }
+        }
+        for (skeleton, nodes) in model.skeletons.iter_mut().zip(self.joint_nodes.iter()) {
+            for (joint, node) in skeleton.joints.iter_mut().zip(nodes.iter()) {
+                if let Some(transform) = node.and_then(|node| transforms.get(node)) {
+                    joint.transform = transform.cast();
+                }
+            }
+        }
+    }
+}
+
+// Blends between keyframes a fraction of the way apart
+type Blend<T> = fn(&T, &T, f32) -> T;
+
+fn vec3_lerp(a: &glm::Vec3, b: &glm::Vec3, amount: f32) -> glm::Vec3 {
+    glm::lerp(a, b, amount)
+}
+
+fn quat_slerp(a: &glm::Quat, b: &glm::Quat, amount: f32) -> glm::Quat {
+    glm::quat_slerp(a, b, amount)
+}
+
+fn sample<T>(channel: &AnimationChannel, values: &[T], time: f32, blend: Blend<T>) -> Option<T>
+where
+    T: Copy + std::ops::Add<Output = T> + std::ops::Mul<f32, Output = T>,
+{
+    let times = &channel.times;
+    let stride = match channel.interpolation {
+        Interpolation::CubicSpline => 3,
+        _ => 1,
+    };
+    // The value of a keyframe, skipping the tangents of cubic splines
+    let value = |keyframe: usize| values.get(keyframe * stride + stride / 2).copied();
+    let first = *times.first()?;
+    let last = *times.last()?;
+    if times.len() == 1 || time <= first {
+        return value(0);
+    }
+    if time >= last {
+        return value(times.len() - 1);
+    }
+    let next = times.partition_point(|keyframe| *keyframe <= time);
+    let previous = next - 1;
+    let span = times[next] - times[previous];
+    let amount = if span > 0.0 {
+        (time - times[previous]) / span
+    } else {
+        0.0
+    };
+    match channel.interpolation {
+        Interpolation::Step => value(previous),
+        Interpolation::Linear => Some(blend(&value(previous)?, &value(next)?, amount)),
+        Interpolation::CubicSpline => {
+            let start = value(previous)?;
+            let end = value(next)?;
+            let out_tangent = *values.get(previous * 3 + 2)?;
+            let in_tangent = *values.get(next * 3)?;
+            let t = amount;
+            let t2 = t * t;
+            let t3 = t2 * t;
+            Some(
+                start * (2.0 * t3 - 3.0 * t2 + 1.0)
+                    + out_tangent * ((t3 - 2.0 * t2 + t) * span)
+                    + end * (-2.0 * t3 + 3.0 * t2)
+                    + in_tangent * ((t3 - t2) * span),
+            )
+        }
+    }
+}
+
+// Advances the players of animated models and poses them, once per frame
+pub struct AnimationSystem {
+    last_update: Instant,
+}
+
+impl Default for AnimationSystem {
+    fn default() -> Self {
+        Self {
+            last_update: Instant::now(),
+        }
+    }
+}
+
+impl AnimationSystem {
+    // Advances by the fixed step when given one instead of the time that passed
+    pub fn update(&mut self, scene: &mut Scene, step: Option<f32>) {
+        let now = Instant::now();
+        let delta_time = step.unwrap_or((now - self.last_update).as_secs_f32());
+        self.last_update = now;
+
+        for model in scene.models.iter_mut() {
+            let mut animations = match model.animations.take() {
+                Some(animations) => animations,
+                None => continue,
+            };
+            let duration = animations.clip().map_or(0.0, |clip| clip.duration);
+            animations.player.advance(duration, delta_time);
+            let pose = (animations.player.clip, animations.player.time);
+            if animations.posed != Some(pose) {
+                animations.apply(model);
+                animations.posed = Some(pose);
+            }
+            model.animations = Some(animations);
+        }
+    }
+}
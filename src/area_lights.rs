@@ -0,0 +1,251 @@
+use nalgebra_glm as glm;
+
+use crate::{
+    layers,
+    lighting::{self, LightUnit},
+    material::PAPER_WHITE_NITS,
+    memory::{self, MemoryCategory, Tracked},
+    scene::Scene,
+};
+
+// Lights past this many are ignored while shading
+pub const MAX_AREA_LIGHTS: usize = 8;
+
+// Linearly transformed cosines fitted to the GGX specular lobe following Heitz et al. 2016, 64 by
+// 64 entries over roughness and the square root of one minus the view's cosine. The inverse
+// matrices come first, then the magnitude and fresnel terms of each entry
+const LTC_TABLE: &[u8] = include_bytes!("../assets/ltc.bin");
+const LTC_TEXELS: usize = 2 * 64 * 64;
+
+// Textured emission is downsampled to this size, then halved down to a single texel so rough
+// surfaces reflect a blurrier image
+const EMISSION_SIZE: u32 = 64;
+const EMISSION_LEVELS: u32 = 7;
+const EMISSION_TEXELS: usize = 5461;
+
+// A rectangle emitting light evenly from its front
+#[derive(Clone)]
+pub struct AreaLightDesc {
+    // The rectangle lies in the local XY plane centered on the origin and shines along local -Z
+    pub transform: glm::Mat4,
+    pub width: f32,
+    pub height: f32,
+    pub color: glm::Vec3,
+    // Candela are measured straight in front of the rectangle, lumens are spread over every side
+    // that shines and lux are what a surface right in front of it receives
+    pub intensity: f32,
+    pub unit: LightUnit,
+    // Black body temperature in Kelvin the color tints, see lighting::color_temperature
+    pub temperature: Option<f32>,
+    // Shines from its back as well
+    pub two_sided: bool,
+    // Tints the light across the rectangle, read once when the light is first drawn
+    pub texture: Option<image::DynamicImage>,
+    // The layers the light shines on, see layers.rs
+    pub layers: u32,
+}
+
+impl Default for AreaLightDesc {
+    fn default() -> Self {
+        Self {
+            transform: glm::Mat4::identity(),
+            width: 1.0,
+            height: 1.0,
+            color: glm::vec3(1.0, 1.0, 1.0),
+            intensity: 1.0,
+            unit: LightUnit::Candela,
+            temperature: None,
+            two_sided: false,
+            texture: None,
+            layers: layers::ALL,
+        }
+    }
+}
+
+impl AreaLightDesc {
+    // Luminance in nits of every point on the rectangle
+    pub fn luminance(&self) -> f32 {
+        let area = (self.width * self.height).abs().max(f32::EPSILON);
+        let sides = if self.two_sided { 2.0 } else { 1.0 };
+        match self.unit {
+            LightUnit::Candela => self.intensity / area,
+            LightUnit::Lumens => self.intensity / (std::f32::consts::PI * area * sides),
+            LightUnit::Lux => self.intensity / std::f32::consts::PI,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Default, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct AreaLightUniform {
+    position: [f32; 4],
+    // Half the rectangle's width and height along its sides
+    right: [f32; 4],
+    up: [f32; 4],
+    // Luminance in units of scene color
+    color: [f32; 4],
+    // x: the bits of the layers it shines on, y: one when it shines from both sides, z: one when
+    // its emission is textured
+    parameters: [u32; 4],
+}
+
+impl AreaLightUniform {
+    // The transform is relative to the scene's origin
+    fn new(light: &AreaLightDesc, transform: &glm::Mat4) -> Self {
+        let position = transform * glm::vec4(0.0, 0.0, 0.0, 1.0);
+        let right = transform * glm::vec4(light.width * 0.5, 0.0, 0.0, 0.0);
+        let up = transform * glm::vec4(0.0, light.height * 0.5, 0.0, 0.0);
+        let color = lighting::tinted(&light.color, light.temperature) * light.luminance()
+            / PAPER_WHITE_NITS;
+        Self {
+            position: position.into(),
+            right: right.into(),
+            up: up.into(),
+            color: glm::vec3_to_vec4(&color).into(),
+            parameters: [
+                light.layers,
+                light.two_sided as u32,
+                light.texture.is_some() as u32,
+                0,
+            ],
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Default, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct AreaLightsUniform {
+    lights: [AreaLightUniform; MAX_AREA_LIGHTS],
+    count: u32,
+    _padding: [u32; 3],
+}
+
+// Rectangular lights shaded with linearly transformed cosines, lit surfaces find them through the
+// light bind group. The lookup table and the emission of each light are read from one storage
+// buffer, as model shading has no room for more textures
+pub struct AreaLightSystem {
+    uniform_buffer: Tracked<wgpu::Buffer>,
+    data_buffer: Tracked<wgpu::Buffer>,
+    // Lights whose emission has been uploaded, which like decal images is only read once
+    uploaded: usize,
+}
+
+impl AreaLightSystem {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let uniform_buffer = memory::create_buffer(
+            device,
+            MemoryCategory::Transient,
+            &wgpu::BufferDescriptor {
+                label: Some("Area Light Uniform Buffer"),
+                size: std::mem::size_of::<AreaLightsUniform>() as wgpu::BufferAddress,
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            },
+        );
+
+        let mut data = LTC_TABLE.to_vec();
+        data.resize((LTC_TEXELS + MAX_AREA_LIGHTS * EMISSION_TEXELS) * 16, 0);
+        let data_buffer = memory::create_buffer_init(
+            device,
+            MemoryCategory::Textures,
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("Area Light Data Buffer"),
+                contents: &data,
+                usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            },
+        );
+
+        Self {
+            uniform_buffer,
+            data_buffer,
+            uploaded: 0,
+        }
+    }
+
+    pub fn uniform_buffer(&self) -> &wgpu::Buffer {
+        &self.uniform_buffer
+    }
+
+    pub fn data_buffer(&self) -> &wgpu::Buffer {
+        &self.data_buffer
+    }
+
+    pub fn update(&mut self, queue: &wgpu::Queue, scene: &Scene) {
+        let area_lights = &scene.area_lights;
+        let count = area_lights.len().min(MAX_AREA_LIGHTS);
+        while self.uploaded < count {
+            if let Some(texture) = area_lights[self.uploaded].texture.as_ref() {
+                let offset = (LTC_TEXELS + self.uploaded * EMISSION_TEXELS) * 16;
+                queue.write_buffer(
+                    &self.data_buffer,
+                    offset as wgpu::BufferAddress,
+                    bytemuck::cast_slice(&emission_levels(texture)),
+                );
+            }
+            self.uploaded += 1;
+        }
+        // Lights spawned after others were removed have their emission uploaded again
+        self.uploaded = self.uploaded.min(area_lights.len());
+
+        let mut uniform = AreaLightsUniform {
+            count: count as u32,
+            ..Default::default()
+        };
+        for (slot, light) in uniform.lights.iter_mut().zip(area_lights.iter()) {
+            *slot = AreaLightUniform::new(light, &scene.local_transform(&light.transform.cast()));
+        }
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[uniform]));
+    }
+}
+
+// Linear texels of every level, each a quarter the size of the one before it
+fn emission_levels(texture: &image::DynamicImage) -> Vec<[f32; 4]> {
+    let image = image::imageops::resize(
+        &texture.to_rgba8(),
+        EMISSION_SIZE,
+        EMISSION_SIZE,
+        image::imageops::FilterType::Triangle,
+    );
+    let mut level = image
+        .pixels()
+        .map(|pixel| {
+            [
+                srgb_to_linear(pixel[0]),
+                srgb_to_linear(pixel[1]),
+                srgb_to_linear(pixel[2]),
+                1.0,
+            ]
+        })
+        .collect::<Vec<_>>();
+
+    let mut texels = Vec::with_capacity(EMISSION_TEXELS);
+    let mut size = EMISSION_SIZE as usize;
+    for _ in 0..EMISSION_LEVELS {
+        texels.extend_from_slice(&level);
+        let half = (size / 2).max(1);
+        level = (0..half * half)
+            .map(|index| {
+                let (x, y) = (index % half * 2, index / half * 2);
+                let mut texel = [0.0; 4];
+                for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+                    let source = level[(y + dy).min(size - 1) * size + (x + dx).min(size - 1)];
+                    for (channel, value) in texel.iter_mut().zip(source.iter()) {
+                        *channel += value * 0.25;
+                    }
+                }
+                texel
+            })
+            .collect();
+        size = half;
+    }
+    texels
+}
+
+fn srgb_to_linear(value: u8) -> f32 {
+    let value = value as f32 / 255.0;
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
@@ -0,0 +1,105 @@
+use std::path::{Path, PathBuf};
+
+use crate::assets::{AssetKind, OpenMode};
+
+// Lists the models, images and HDRIs under a directory and the recently opened files, so they
+// can be opened without restarting the viewer
+pub struct AssetBrowser {
+    // Also used for files dropped on the window
+    pub open_mode: OpenMode,
+    directory: PathBuf,
+    // Found on the first frame the browser is shown and again when refreshed
+    assets: Option<Vec<PathBuf>>,
+}
+
+impl AssetBrowser {
+    pub fn new(directory: PathBuf) -> Self {
+        Self {
+            open_mode: OpenMode::Add,
+            directory,
+            assets: None,
+        }
+    }
+
+    // The asset picked this frame, if any
+    pub fn show(&mut self, context: &egui::CtxRef, recent_files: &[PathBuf]) -> Option<PathBuf> {
+        let mut picked = None;
+        egui::Window::new("Assets").show(context, |ui| {
+            ui.horizontal(|ui| {
+                ui.radio_value(&mut self.open_mode, OpenMode::Add, "Add to scene");
+                ui.radio_value(&mut self.open_mode, OpenMode::Replace, "Replace models");
+            });
+            egui::CollapsingHeader::new("Recent")
+                .default_open(true)
+                .show(ui, |ui| {
+                    if recent_files.is_empty() {
+                        ui.label("No recent files");
+                    }
+                    for path in recent_files.iter() {
+                        let name = Path::new(path.file_name().unwrap_or_default());
+                        if asset_button(ui, name, path).clicked() {
+                            picked = Some(path.clone());
+                        }
+                    }
+                });
+            egui::CollapsingHeader::new(self.directory.display().to_string())
+                .default_open(true)
+                .show(ui, |ui| {
+                    if ui.button("Refresh").clicked() {
+                        self.assets = None;
+                    }
+                    let directory = &self.directory;
+                    let assets = self.assets.get_or_insert_with(|| find_assets(directory));
+                    if assets.is_empty() {
+                        ui.label("No assets found");
+                    }
+                    // HDR images are listed apart, since they replace the environment instead of
+                    // adding to the scene
+                    let (environments, others): (Vec<_>, Vec<_>) = assets
+                        .iter()
+                        .partition(|path| AssetKind::of(path) == Some(AssetKind::Environment));
+                    for (heading, paths) in [("Models and images", others), ("HDRIs", environments)]
+                    {
+                        if paths.is_empty() {
+                            continue;
+                        }
+                        ui.label(heading);
+                        for path in paths {
+                            let name = path.strip_prefix(directory).unwrap_or(path);
+                            if asset_button(ui, name, path).clicked() {
+                                picked = Some(path.clone());
+                            }
+                        }
+                    }
+                });
+        });
+        picked
+    }
+}
+
+fn asset_button(ui: &mut egui::Ui, name: &Path, path: &Path) -> egui::Response {
+    ui.add(egui::Button::new(name.display().to_string()).frame(false))
+        .on_hover_text(path.display().to_string())
+}
+
+// Every asset that can be loaded under the directory and its subdirectories, sorted by path.
+// Directories that can't be read, such as in the browser, are skipped
+fn find_assets(directory: &Path) -> Vec<PathBuf> {
+    let mut assets = Vec::new();
+    let mut directories = vec![directory.to_path_buf()];
+    while let Some(directory) = directories.pop() {
+        let entries = match std::fs::read_dir(&directory) {
+            Ok(entries) => entries,
+            Err(_) => continue,
+        };
+        for path in entries.flatten().map(|entry| entry.path()) {
+            if path.is_dir() {
+                directories.push(path);
+            } else if AssetKind::of(&path).is_some() {
+                assets.push(path);
+            }
+        }
+    }
+    assets.sort();
+    assets
+}
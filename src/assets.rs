@@ -0,0 +1,245 @@
+use anyhow::{bail, Context, Result};
+use std::path::Path;
+
+use crate::{
+    bvh::SceneHit,
+    environment::EnvironmentDesc,
+    import::{fit_model, import_gltf, import_obj, import_ply, import_xyz},
+    material::Material,
+    model::Primitive,
+    scene::{MaterialHandle, Scene},
+    settings::ImportSettings,
+    terrain::TerrainDesc,
+};
+
+// Resources are bundled alongside the executable inside the .app on iOS
+#[cfg(target_os = "ios")]
+pub fn asset_path(path: &str) -> Result<std::path::PathBuf> {
+    let executable = std::env::current_exe()?;
+    let bundle = executable
+        .parent()
+        .context("Failed to find the application bundle directory!")?;
+    Ok(bundle.join(path))
+}
+
+// Pages fetch their assets from the server instead, and Android packs them into the app
+#[cfg(not(any(target_os = "ios", target_os = "android", target_arch = "wasm32")))]
+pub fn asset_path(path: &str) -> Result<std::path::PathBuf> {
+    Ok(path.into())
+}
+
+pub fn load_asset(scene: &mut Scene, path: &Path, import: &ImportSettings) -> Result<()> {
+    load_asset_from_memory(scene, path, &read_asset(path)?, import)
+}
+
+fn read_asset(path: &Path) -> Result<Vec<u8>> {
+    std::fs::read(path).with_context(|| format!("Failed to read asset: {}", path.display()))
+}
+
+// Whether an opened model joins the models already in the scene or takes their place
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum OpenMode {
+    Add,
+    Replace,
+}
+
+// Opens a file dropped on the window or picked in the asset browser. Images become the base
+// color of the selected model, and are only loaded as heightmaps when nothing is selected.
+// Shaders draw the selected model's materials, and HDR images replace the environment
+pub fn open_asset(
+    scene: &mut Scene,
+    path: &Path,
+    open_mode: OpenMode,
+    selection: &mut Option<SceneHit>,
+    import: &ImportSettings,
+) -> Result<()> {
+    match (AssetKind::of(path), selection.as_ref()) {
+        (Some(kind @ (AssetKind::Gltf | AssetKind::Obj)), _) => {
+            // Imported before anything is replaced, so a file that fails leaves the scene as it was
+            let bytes = read_asset(path)?;
+            let mut model = match kind {
+                AssetKind::Obj => import_obj(&bytes, path)?,
+                _ => import_gltf(&bytes, path)?,
+            };
+            fit_model(&mut model, import, scene.unit_scale);
+            if open_mode == OpenMode::Replace {
+                scene.clear_models();
+                *selection = None;
+            }
+            scene.spawn_model(model);
+        }
+        (Some(AssetKind::Heightmap), Some(hit)) => {
+            let image = image::load_from_memory(&read_asset(path)?)?;
+            apply_base_color(scene, hit, image, path)?;
+        }
+        (Some(AssetKind::Shader), Some(hit)) => apply_shader(scene, hit, path)?,
+        _ => load_asset(scene, path, import)?,
+    }
+    Ok(())
+}
+
+// Replaces the base color texture of every material the selected instance's mesh is drawn with
+fn apply_base_color(
+    scene: &mut Scene,
+    hit: &SceneHit,
+    image: image::DynamicImage,
+    path: &Path,
+) -> Result<()> {
+    let materials = selected_materials(scene, hit)?;
+    let model = &mut scene.models[hit.model.0];
+    model.images.push(image);
+    let texture = model.images.len() - 1;
+    model.image_files.insert(texture, path.to_path_buf());
+    for material in materials {
+        let handle = MaterialHandle {
+            model: hit.model,
+            material,
+        };
+        if let Some(material) = scene.materials().get_mut(handle) {
+            material.base_color_texture = Some(texture);
+        }
+    }
+    scene.invalidate_models();
+    Ok(())
+}
+
+// Draws every material the selected instance's mesh is drawn with using a custom shader
+fn apply_shader(scene: &mut Scene, hit: &SceneHit, path: &Path) -> Result<()> {
+    for material in selected_materials(scene, hit)? {
+        let handle = MaterialHandle {
+            model: hit.model,
+            material,
+        };
+        if let Some(material) = scene.materials().get_mut(handle) {
+            material.shader = Some(path.to_path_buf());
+        }
+    }
+    Ok(())
+}
+
+// The materials of the selected instance's mesh. Primitives drawn with the default material get a
+// material of their own, so other models aren't changed
+fn selected_materials(scene: &mut Scene, hit: &SceneHit) -> Result<Vec<usize>> {
+    let model = scene
+        .models
+        .get(hit.model.0)
+        .with_context(|| format!("Selection refers to missing model {}!", hit.model.0))?;
+    let mesh = model
+        .instances
+        .get(hit.instance)
+        .map(|instance| instance.mesh)
+        .filter(|mesh| *mesh < model.meshes.len())
+        .with_context(|| format!("Selection refers to missing instance {}!", hit.instance))?;
+
+    let material_count = model.materials.len();
+    let owned_material = |primitive: &Primitive| {
+        primitive
+            .material
+            .filter(|material| *material < material_count)
+    };
+    let added_material = if model.meshes[mesh]
+        .primitives
+        .iter()
+        .any(|primitive| owned_material(primitive).is_none())
+    {
+        let added = scene.materials().add(hit.model, Material::default());
+        scene.invalidate_models();
+        added.map(|handle| handle.material)
+    } else {
+        None
+    };
+
+    let mut selected = Vec::new();
+    for primitive in scene.models[hit.model.0].meshes[mesh].primitives.iter_mut() {
+        let material = match owned_material(primitive).or(added_material) {
+            Some(material) => material,
+            None => continue,
+        };
+        primitive.material = Some(material);
+        if !selected.contains(&material) {
+            selected.push(material);
+        }
+    }
+    Ok(selected)
+}
+
+fn extension(path: &Path) -> String {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .map(str::to_lowercase)
+        .unwrap_or_default()
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum AssetKind {
+    // Images are treated as terrain heightmaps
+    Heightmap,
+    Gltf,
+    Obj,
+    Ply,
+    Xyz,
+    // Material shaders, applied to the selected model
+    Shader,
+    // Equirectangular HDR images drawn around the scene
+    Environment,
+}
+
+impl AssetKind {
+    // Decided by the file extension
+    pub fn of(path: &Path) -> Option<Self> {
+        match extension(path).as_str() {
+            "png" | "jpg" | "jpeg" | "bmp" | "tga" | "tif" | "tiff" => Some(Self::Heightmap),
+            "gltf" | "glb" => Some(Self::Gltf),
+            "obj" => Some(Self::Obj),
+            "ply" => Some(Self::Ply),
+            "xyz" => Some(Self::Xyz),
+            "wgsl" => Some(Self::Shader),
+            "hdr" | "exr" => Some(Self::Environment),
+            _ => None,
+        }
+    }
+}
+
+// The path only decides how the asset is imported and where files it refers to are found
+pub fn load_asset_from_memory(
+    scene: &mut Scene,
+    path: &Path,
+    bytes: &[u8],
+    import: &ImportSettings,
+) -> Result<()> {
+    match AssetKind::of(path) {
+        Some(AssetKind::Heightmap) => {
+            let heightmap = image::load_from_memory(bytes)?;
+            scene.spawn_terrain(TerrainDesc {
+                heightmap,
+                ..Default::default()
+            });
+        }
+        Some(kind @ (AssetKind::Gltf | AssetKind::Obj)) => {
+            let mut model = match kind {
+                AssetKind::Obj => import_obj(bytes, path)?,
+                _ => import_gltf(bytes, path)?,
+            };
+            fit_model(&mut model, import, scene.unit_scale);
+            scene.spawn_model(model);
+        }
+        Some(AssetKind::Ply) => {
+            scene.spawn_point_cloud(import_ply(bytes, path)?);
+        }
+        Some(AssetKind::Xyz) => {
+            scene.spawn_point_cloud(import_xyz(bytes, path)?);
+        }
+        Some(AssetKind::Shader) => {
+            bail!("Select a model to draw with the shader {}", path.display())
+        }
+        Some(AssetKind::Environment) => {
+            let environment = match extension(path).as_str() {
+                "exr" => EnvironmentDesc::from_exr(bytes)?,
+                _ => EnvironmentDesc::from_hdr(bytes)?,
+            };
+            scene.set_environment(Some(environment));
+        }
+        None => bail!("Unsupported asset type: {}", path.display()),
+    }
+    Ok(())
+}
@@ -0,0 +1,345 @@
+use std::rc::Rc;
+
+use crate::{
+    memory::{self, MemoryCategory, Tracked},
+    render_targets::{FramePhase, RenderTargetPool},
+    settings::Settings,
+    shader_snippets,
+    texture::Texture,
+};
+
+// Levels allocated for the largest bloom, the effect quality decides how many are used
+const MAX_BLOOM_LEVELS: usize = 6;
+
+#[repr(C)]
+#[derive(Default, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct BloomUniform {
+    threshold: f32,
+    knee: f32,
+    radius: f32,
+    _padding: f32,
+}
+
+impl BloomUniform {
+    fn new(settings: &Settings) -> Self {
+        let bloom = &settings.bloom;
+        Self {
+            threshold: bloom.threshold,
+            knee: bloom.knee,
+            radius: bloom.radius,
+            _padding: 0.0,
+        }
+    }
+}
+
+struct BloomLevel {
+    texture: Rc<Texture>,
+    // Samples this level while rendering the next level down or up the chain
+    bind_group: wgpu::BindGroup,
+}
+
+// Blurs the bright parts of the HDR scene color through a chain of progressively smaller targets
+pub struct BloomSystem {
+    uniform_buffer: Tracked<wgpu::Buffer>,
+    enabled: bool,
+    active_levels: usize,
+    bind_group_layout: wgpu::BindGroupLayout,
+    scene_bind_group: wgpu::BindGroup,
+    levels: Vec<BloomLevel>,
+    prefilter_pipeline: wgpu::RenderPipeline,
+    downsample_pipeline: wgpu::RenderPipeline,
+    upsample_pipeline: wgpu::RenderPipeline,
+}
+
+impl BloomSystem {
+    pub fn new(
+        device: &wgpu::Device,
+        target_pool: &mut RenderTargetPool,
+        scene_color: &Texture,
+        dimensions: &[u32; 2],
+    ) -> Self {
+        let uniform_buffer = memory::create_buffer(
+            device,
+            MemoryCategory::Transient,
+            &wgpu::BufferDescriptor {
+                label: Some("Bloom Uniform Buffer"),
+                size: std::mem::size_of::<BloomUniform>() as wgpu::BufferAddress,
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            },
+        );
+
+        let bind_group_layout = memory::create_bind_group_layout(
+            device,
+            &wgpu::BindGroupLayoutDescriptor {
+                label: Some("Bloom Bind Group Layout"),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Sampler {
+                            filtering: true,
+                            comparison: false,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 2,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                ],
+            },
+        );
+
+        let module = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("Bloom Shader"),
+            source: wgpu::ShaderSource::Wgsl(
+                shader_snippets::splice(include_str!("shaders/bloom.wgsl")).into(),
+            ),
+        });
+
+        let pipeline_layout = memory::create_pipeline_layout(
+            device,
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("Bloom Pipeline Layout"),
+                bind_group_layouts: &[&bind_group_layout],
+                push_constant_ranges: &[],
+            },
+        );
+
+        let create_pipeline = |label, entry_point, blend| {
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some(label),
+                layout: Some(&pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: &module,
+                    entry_point: "vs_main",
+                    buffers: &[],
+                },
+                primitive: wgpu::PrimitiveState::default(),
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState::default(),
+                fragment: Some(wgpu::FragmentState {
+                    module: &module,
+                    entry_point,
+                    targets: &[wgpu::ColorTargetState {
+                        format: Texture::HDR_FORMAT,
+                        blend: Some(blend),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    }],
+                }),
+            })
+        };
+
+        let prefilter_pipeline = create_pipeline(
+            "Bloom Prefilter Pipeline",
+            "fs_prefilter",
+            wgpu::BlendState::REPLACE,
+        );
+        let downsample_pipeline = create_pipeline(
+            "Bloom Downsample Pipeline",
+            "fs_downsample",
+            wgpu::BlendState::REPLACE,
+        );
+        // Each level accumulates the blurred levels below it
+        let additive = wgpu::BlendComponent {
+            src_factor: wgpu::BlendFactor::One,
+            dst_factor: wgpu::BlendFactor::One,
+            operation: wgpu::BlendOperation::Add,
+        };
+        let upsample_pipeline = create_pipeline(
+            "Bloom Upsample Pipeline",
+            "fs_upsample",
+            wgpu::BlendState {
+                color: additive,
+                alpha: additive,
+            },
+        );
+
+        let scene_bind_group =
+            Self::create_bind_group(device, &bind_group_layout, &uniform_buffer, scene_color);
+        let levels = Self::create_levels(
+            device,
+            target_pool,
+            &bind_group_layout,
+            &uniform_buffer,
+            dimensions,
+        );
+
+        Self {
+            uniform_buffer,
+            enabled: false,
+            active_levels: 0,
+            bind_group_layout,
+            scene_bind_group,
+            levels,
+            prefilter_pipeline,
+            downsample_pipeline,
+            upsample_pipeline,
+        }
+    }
+
+    fn create_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        uniform_buffer: &wgpu::Buffer,
+        texture: &Texture,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Bloom Bind Group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&texture.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&texture.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+            ],
+        })
+    }
+
+    // Each level is half the size of the one above it, starting at half the scene resolution
+    fn create_levels(
+        device: &wgpu::Device,
+        target_pool: &mut RenderTargetPool,
+        layout: &wgpu::BindGroupLayout,
+        uniform_buffer: &wgpu::Buffer,
+        dimensions: &[u32; 2],
+    ) -> Vec<BloomLevel> {
+        let mut levels = Vec::new();
+        let [mut width, mut height] = *dimensions;
+        while levels.len() < MAX_BLOOM_LEVELS && (levels.is_empty() || width.min(height) > 1) {
+            width = (width / 2).max(1);
+            height = (height / 2).max(1);
+            // Only the top level is read past the bloom pass, by the post process
+            let last_phase = if levels.is_empty() {
+                FramePhase::PostProcess
+            } else {
+                FramePhase::Bloom
+            };
+            let texture = target_pool.acquire(
+                device,
+                width,
+                height,
+                Texture::HDR_FORMAT,
+                "Bloom Texture",
+                FramePhase::Bloom..=last_phase,
+            );
+            let bind_group = Self::create_bind_group(device, layout, uniform_buffer, &texture);
+            levels.push(BloomLevel {
+                texture,
+                bind_group,
+            });
+        }
+        levels
+    }
+
+    // The fully accumulated bloom, sampled while post processing
+    pub fn texture(&self) -> &Texture {
+        &self.levels[0].texture
+    }
+
+    pub fn resize(
+        &mut self,
+        device: &wgpu::Device,
+        target_pool: &mut RenderTargetPool,
+        scene_color: &Texture,
+        dimensions: &[u32; 2],
+    ) {
+        self.scene_bind_group = Self::create_bind_group(
+            device,
+            &self.bind_group_layout,
+            &self.uniform_buffer,
+            scene_color,
+        );
+        self.levels = Self::create_levels(
+            device,
+            target_pool,
+            &self.bind_group_layout,
+            &self.uniform_buffer,
+            dimensions,
+        );
+    }
+
+    pub fn update(&mut self, queue: &wgpu::Queue, settings: &Settings) {
+        self.enabled = settings.bloom.enabled && settings.bloom.intensity > 0.0;
+        self.active_levels =
+            (settings.effect_quality.bloom_levels() as usize).min(self.levels.len());
+        queue.write_buffer(
+            &self.uniform_buffer,
+            0,
+            bytemuck::cast_slice(&[BloomUniform::new(settings)]),
+        );
+    }
+
+    pub fn render(&self, encoder: &mut wgpu::CommandEncoder) {
+        // Post processing scales the bloom by zero while disabled, so the stale levels are left alone
+        if !self.enabled {
+            return;
+        }
+
+        let levels = &self.levels[..self.active_levels];
+        for (index, level) in levels.iter().enumerate() {
+            let (pipeline, source) = match index {
+                0 => (&self.prefilter_pipeline, &self.scene_bind_group),
+                _ => (&self.downsample_pipeline, &levels[index - 1].bind_group),
+            };
+            let mut downsample_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Bloom Downsample Pass"),
+                color_attachments: &[wgpu::RenderPassColorAttachment {
+                    view: &level.texture.view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                        store: true,
+                    },
+                }],
+                depth_stencil_attachment: None,
+            });
+            downsample_pass.set_pipeline(pipeline);
+            downsample_pass.set_bind_group(0, source, &[]);
+            downsample_pass.draw(0..3, 0..1);
+        }
+
+        for index in (1..levels.len()).rev() {
+            let mut upsample_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Bloom Upsample Pass"),
+                color_attachments: &[wgpu::RenderPassColorAttachment {
+                    view: &levels[index - 1].texture.view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: true,
+                    },
+                }],
+                depth_stencil_attachment: None,
+            });
+            upsample_pass.set_pipeline(&self.upsample_pipeline);
+            upsample_pass.set_bind_group(0, &levels[index].bind_group, &[]);
+            upsample_pass.draw(0..3, 0..1);
+        }
+    }
+}
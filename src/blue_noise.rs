@@ -0,0 +1,72 @@
+use crate::{
+    memory::{self, MemoryCategory, Tracked},
+    texture,
+};
+
+// Width and height of the tile, which repeats across the screen
+pub const BLUE_NOISE_SIZE: u32 = 64;
+
+const TEXELS: usize = (BLUE_NOISE_SIZE * BLUE_NOISE_SIZE) as usize;
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct BlueNoiseUniform {
+    // xy: the pixel the tile starts from
+    offset: [u32; 4],
+    // Four texels to each component
+    texels: [[u32; 4]; TEXELS / 16],
+}
+
+// Per pixel thresholds and rotations for dithered transparency, shadow filtering, SSAO and output
+// dithering. The model shader has no sampled texture slots left, so the tile is packed into a
+// uniform instead of being bound as a texture
+pub struct BlueNoise {
+    uniform: BlueNoiseUniform,
+    buffer: Tracked<wgpu::Buffer>,
+}
+
+impl BlueNoise {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let image = texture::blue_noise(BLUE_NOISE_SIZE, 0);
+        let mut texels = [[0; 4]; TEXELS / 16];
+        for (index, value) in image.pixels().enumerate() {
+            texels[index / 16][(index / 4) % 4] |= (value.0[0] as u32) << ((index % 4) * 8);
+        }
+        let uniform = BlueNoiseUniform {
+            offset: [0; 4],
+            texels,
+        };
+        let buffer = memory::create_buffer_init(
+            device,
+            MemoryCategory::Textures,
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("Blue Noise Uniform Buffer"),
+                contents: bytemuck::cast_slice(&[uniform]),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            },
+        );
+        Self { uniform, buffer }
+    }
+
+    // While temporal upscaling averages frames together, the tile moves every frame so each pixel
+    // sees many thresholds over time. Otherwise it stays put so the pattern doesn't crawl
+    pub fn update(&mut self, queue: &wgpu::Queue, frame_index: u64, animate: bool) {
+        let offset = if animate {
+            // The R2 sequence spreads consecutive offsets evenly over the tile
+            let index = (frame_index % 1024) as f32;
+            [0.754_877_7, 0.569_840_3]
+                .map(|step| ((index * step).fract() * BLUE_NOISE_SIZE as f32) as u32)
+        } else {
+            [0; 2]
+        };
+        if self.uniform.offset[..2] == offset {
+            return;
+        }
+        self.uniform.offset = [offset[0], offset[1], 0, 0];
+        queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&self.uniform.offset));
+    }
+
+    pub fn buffer(&self) -> &wgpu::Buffer {
+        &self.buffer
+    }
+}
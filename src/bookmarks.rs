@@ -0,0 +1,112 @@
+use nalgebra_glm as glm;
+use serde::{Deserialize, Serialize};
+
+use crate::{camera::Camera, scene::Scene};
+
+// Bound to the number keys 1 through 9
+pub const BOOKMARK_SLOTS: usize = 9;
+
+// A saved camera pose that can be returned to later. It's kept in the world rather than relative
+// to the scene's origin, so it stays put when the origin moves
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CameraBookmark {
+    pub position: glm::DVec3,
+    pub target: glm::DVec3,
+    pub up: glm::Vec3,
+    // Vertical field of view in radians
+    pub fov: f32,
+}
+
+impl CameraBookmark {
+    pub fn of(camera: &Camera, scene: &Scene) -> Self {
+        Self {
+            position: scene.world_position(&camera.position),
+            target: scene.world_position(&camera.target),
+            up: camera.up,
+            fov: camera.fov,
+        }
+    }
+
+    pub fn pose(&self, scene: &Scene) -> CameraPose {
+        CameraPose {
+            position: scene.local_position(&self.position),
+            target: scene.local_position(&self.target),
+            up: self.up,
+            fov: self.fov,
+        }
+    }
+}
+
+// Where the camera is and what it looks at, relative to the scene's origin like the camera
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct CameraPose {
+    pub position: glm::Vec3,
+    pub target: glm::Vec3,
+    pub up: glm::Vec3,
+    pub fov: f32,
+}
+
+impl CameraPose {
+    pub fn of(camera: &Camera) -> Self {
+        Self {
+            position: camera.position,
+            target: camera.target,
+            up: camera.up,
+            fov: camera.fov,
+        }
+    }
+
+    pub fn apply(&self, camera: &mut Camera) {
+        camera.position = self.position;
+        camera.target = self.target;
+        camera.up = self.up;
+        camera.fov = self.fov;
+    }
+
+    fn lerp(&self, other: &Self, t: f32) -> Self {
+        Self {
+            position: glm::lerp(&self.position, &other.position, t),
+            target: glm::lerp(&self.target, &other.target, t),
+            up: glm::lerp(&self.up, &other.up, t)
+                .try_normalize(f32::EPSILON)
+                .unwrap_or(other.up),
+            fov: self.fov + (other.fov - self.fov) * t,
+        }
+    }
+}
+
+// Moves the camera from where it was to a pose, easing in and out
+pub struct CameraTransition {
+    from: CameraPose,
+    to: CameraPose,
+    time: f32,
+    duration: f32,
+}
+
+impl CameraTransition {
+    pub fn new(camera: &Camera, to: CameraPose, duration: f32) -> Self {
+        Self {
+            from: CameraPose::of(camera),
+            to,
+            time: 0.0,
+            duration,
+        }
+    }
+
+    // Returns whether the camera has arrived
+    pub fn update(&mut self, camera: &mut Camera, delta_time: f32) -> bool {
+        self.time += delta_time;
+        let t = (self.time / self.duration.max(f32::EPSILON)).min(1.0);
+        let eased = t * t * (3.0 - 2.0 * t);
+        self.from.lerp(&self.to, eased).apply(camera);
+        t >= 1.0
+    }
+
+    // Keeps both ends where they are in the world when the scene's origin moves by the offset
+    pub fn translate(&mut self, offset: &glm::Vec3) {
+        for pose in [&mut self.from, &mut self.to] {
+            pose.position -= offset;
+            pose.target -= offset;
+        }
+    }
+}
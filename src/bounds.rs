@@ -0,0 +1,128 @@
+use nalgebra_glm as glm;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Aabb {
+    pub min: glm::Vec3,
+    pub max: glm::Vec3,
+}
+
+impl Default for Aabb {
+    fn default() -> Self {
+        Self {
+            min: glm::vec3(f32::MAX, f32::MAX, f32::MAX),
+            max: glm::vec3(f32::MIN, f32::MIN, f32::MIN),
+        }
+    }
+}
+
+impl Aabb {
+    pub fn expand_to_include(&mut self, point: &glm::Vec3) {
+        self.min = glm::min2(&self.min, point);
+        self.max = glm::max2(&self.max, point);
+    }
+
+    pub fn center(&self) -> glm::Vec3 {
+        (self.min + self.max) * 0.5
+    }
+
+    pub fn extents(&self) -> glm::Vec3 {
+        self.max - self.min
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.min.x > self.max.x
+    }
+
+    pub fn merge(&mut self, other: &Aabb) {
+        if other.is_empty() {
+            return;
+        }
+        self.expand_to_include(&other.min);
+        self.expand_to_include(&other.max);
+    }
+
+    pub fn translated(&self, offset: &glm::Vec3) -> Aabb {
+        if self.is_empty() {
+            return *self;
+        }
+        Aabb {
+            min: self.min + offset,
+            max: self.max + offset,
+        }
+    }
+
+    // Bounds enclosing all eight corners after transformation
+    pub fn transformed(&self, transform: &glm::Mat4) -> Aabb {
+        let mut bounds = Aabb::default();
+        if self.is_empty() {
+            return bounds;
+        }
+        for corner in 0..8 {
+            let point = glm::vec3(
+                if corner & 1 == 0 {
+                    self.min.x
+                } else {
+                    self.max.x
+                },
+                if corner & 2 == 0 {
+                    self.min.y
+                } else {
+                    self.max.y
+                },
+                if corner & 4 == 0 {
+                    self.min.z
+                } else {
+                    self.max.z
+                },
+            );
+            bounds.expand_to_include(&(transform * point.push(1.0)).xyz());
+        }
+        bounds
+    }
+}
+
+pub struct Frustum {
+    // Plane normals point into the frustum
+    planes: [glm::Vec4; 6],
+}
+
+impl Frustum {
+    // Extracts the clip planes of a view projection matrix with a zero to one depth range
+    pub fn from_matrix(view_projection: &glm::Mat4) -> Self {
+        let row = |index: usize| view_projection.row(index).transpose();
+        let (x, y, z, w) = (row(0), row(1), row(2), row(3));
+        let planes = [w + x, w - x, w + y, w - y, z, w - z].map(|plane| {
+            let length = plane.xyz().magnitude();
+            if length > f32::EPSILON {
+                plane / length
+            } else {
+                plane
+            }
+        });
+        Self { planes }
+    }
+
+    pub fn intersects_aabb(&self, aabb: &Aabb) -> bool {
+        self.planes.iter().all(|plane| {
+            // Test the corner furthest along the plane normal
+            let corner = glm::vec3(
+                if plane.x >= 0.0 {
+                    aabb.max.x
+                } else {
+                    aabb.min.x
+                },
+                if plane.y >= 0.0 {
+                    aabb.max.y
+                } else {
+                    aabb.min.y
+                },
+                if plane.z >= 0.0 {
+                    aabb.max.z
+                } else {
+                    aabb.min.z
+                },
+            );
+            plane.xyz().dot(&corner) + plane.w >= 0.0
+        })
+    }
+}
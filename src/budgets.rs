@@ -0,0 +1,216 @@
+use anyhow::{Context, Result};
+use instant::Instant;
+use std::{collections::VecDeque, fmt, str::FromStr, time::Duration};
+
+use crate::profiler::PassTiming;
+
+// A budget that stays exceeded is reported again at most this often
+const ALERT_INTERVAL: Duration = Duration::from_secs(1);
+const ALERT_HISTORY: usize = 8;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BudgetTarget {
+    // Wall clock time between frames
+    Frame,
+    // Time spent recording and submitting a frame
+    Cpu,
+    // Total time spent on the GPU across every pass
+    Gpu,
+    Pass(String),
+}
+
+impl fmt::Display for BudgetTarget {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Frame => write!(formatter, "frame"),
+            Self::Cpu => write!(formatter, "cpu"),
+            Self::Gpu => write!(formatter, "gpu"),
+            Self::Pass(label) => write!(formatter, "{}", label),
+        }
+    }
+}
+
+impl FromStr for BudgetTarget {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        Ok(match value.trim() {
+            "frame" => Self::Frame,
+            "cpu" => Self::Cpu,
+            "gpu" => Self::Gpu,
+            label => Self::Pass(label.to_string()),
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FrameBudget {
+    pub target: BudgetTarget,
+    pub limit: Duration,
+}
+
+// Parses budgets written as `target=milliseconds`, such as `frame=16.6` or `Water Pass=2`
+impl FromStr for FrameBudget {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        let (target, milliseconds) = value
+            .split_once('=')
+            .with_context(|| format!("Expected 'target=milliseconds' but found '{}'!", value))?;
+        let milliseconds = milliseconds
+            .trim()
+            .parse::<f32>()
+            .with_context(|| format!("Expected milliseconds but found '{}'!", milliseconds))?;
+        Ok(Self {
+            target: target.parse()?,
+            limit: Duration::from_secs_f32(milliseconds.max(0.0) / 1000.0),
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BudgetAlert {
+    pub frame: u64,
+    pub target: BudgetTarget,
+    pub limit: Duration,
+    pub measured: Duration,
+    // The most expensive GPU pass from the latest timings
+    pub slowest_pass: Option<PassTiming>,
+}
+
+impl fmt::Display for BudgetAlert {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            formatter,
+            "Frame {} exceeded the {} budget: {:.2}ms > {:.2}ms",
+            self.frame,
+            self.target,
+            milliseconds(self.measured),
+            milliseconds(self.limit)
+        )?;
+        if let Some(pass) = self.slowest_pass.as_ref() {
+            write!(
+                formatter,
+                " (slowest pass: {} at {:.2}ms)",
+                pass.label,
+                milliseconds(pass.duration)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+pub fn milliseconds(duration: Duration) -> f32 {
+    duration.as_secs_f32() * 1000.0
+}
+
+struct RegisteredBudget {
+    budget: FrameBudget,
+    last_alert: Option<Instant>,
+}
+
+#[derive(Default)]
+pub struct BudgetMonitor {
+    budgets: Vec<RegisteredBudget>,
+    alerts: Vec<BudgetAlert>,
+    recent_alerts: VecDeque<BudgetAlert>,
+    frame_time: Duration,
+    cpu_time: Duration,
+    gpu_timings: Vec<PassTiming>,
+}
+
+impl BudgetMonitor {
+    pub fn register(&mut self, budget: FrameBudget) {
+        self.budgets.push(RegisteredBudget {
+            budget,
+            last_alert: None,
+        });
+    }
+
+    pub fn budgets(&self) -> impl Iterator<Item = &FrameBudget> {
+        self.budgets.iter().map(|registered| &registered.budget)
+    }
+
+    pub fn frame_time(&self) -> Duration {
+        self.frame_time
+    }
+
+    pub fn cpu_time(&self) -> Duration {
+        self.cpu_time
+    }
+
+    pub fn gpu_timings(&self) -> &[PassTiming] {
+        &self.gpu_timings
+    }
+
+    pub fn recent_alerts(&self) -> impl Iterator<Item = &BudgetAlert> {
+        self.recent_alerts.iter()
+    }
+
+    // Alerts raised since the last call
+    pub fn drain_alerts(&mut self) -> Vec<BudgetAlert> {
+        std::mem::take(&mut self.alerts)
+    }
+
+    pub fn record_cpu(&mut self, frame: u64, frame_time: Duration, cpu_time: Duration) {
+        self.frame_time = frame_time;
+        self.cpu_time = cpu_time;
+        self.check(frame, |target| match target {
+            BudgetTarget::Frame => Some(frame_time),
+            BudgetTarget::Cpu => Some(cpu_time),
+            _ => None,
+        });
+    }
+
+    // GPU timings arrive a few frames after the frame they measure
+    pub fn record_gpu(&mut self, frame: u64, timings: Vec<PassTiming>) {
+        self.gpu_timings = timings;
+        let total = self.gpu_timings.iter().map(|pass| pass.duration).sum();
+        let timings = self.gpu_timings.clone();
+        self.check(frame, |target| match target {
+            BudgetTarget::Gpu => Some(total),
+            BudgetTarget::Pass(label) => timings
+                .iter()
+                .find(|pass| pass.label == label)
+                .map(|pass| pass.duration),
+            _ => None,
+        });
+    }
+
+    fn check(&mut self, frame: u64, measure: impl Fn(&BudgetTarget) -> Option<Duration>) {
+        let slowest_pass = self
+            .gpu_timings
+            .iter()
+            .max_by_key(|pass| pass.duration)
+            .cloned();
+        for registered in self.budgets.iter_mut() {
+            let measured = match measure(&registered.budget.target) {
+                Some(measured) => measured,
+                None => continue,
+            };
+            if measured <= registered.budget.limit {
+                registered.last_alert = None;
+                continue;
+            }
+            let due = registered
+                .last_alert
+                .is_none_or(|last_alert| last_alert.elapsed() >= ALERT_INTERVAL);
+            if !due {
+                continue;
+            }
+            registered.last_alert = Some(Instant::now());
+            let alert = BudgetAlert {
+                frame,
+                target: registered.budget.target.clone(),
+                limit: registered.budget.limit,
+                measured,
+                slowest_pass: slowest_pass.clone(),
+            };
+            if self.recent_alerts.len() == ALERT_HISTORY {
+                self.recent_alerts.pop_front();
+            }
+            self.recent_alerts.push_back(alert.clone());
+            self.alerts.push(alert);
+        }
+    }
+}
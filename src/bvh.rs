@@ -0,0 +1,678 @@
+use nalgebra_glm as glm;
+
+use crate::{
+    bounds::Aabb,
+    model::{ModelVertex, Primitive},
+    scene::{ModelHandle, Scene},
+};
+
+const BINS: usize = 16;
+// Leaves are only split further when it's cheaper than testing their triangles
+const MAX_LEAF_TRIANGLES: usize = 8;
+// Cost of visiting a node relative to intersecting a triangle
+const TRAVERSAL_COST: f32 = 1.0;
+// Entries in the traversal stacks of the shaders that walk the hierarchy
+const STACK_SIZE: usize = 64;
+// Traversal keeps at most one sibling per level on the stack, so leaves are forced at this depth
+// to keep it from overflowing however the triangles are laid out
+const MAX_DEPTH: usize = STACK_SIZE / 2 - 1;
+
+pub type Triangle = [glm::Vec3; 3];
+
+#[derive(Debug, Copy, Clone)]
+pub struct BvhNode {
+    pub bounds: Aabb,
+    // The first of a leaf's triangles in the triangle order, or an interior node's second
+    // child. An interior node's first child always follows it
+    pub index: usize,
+    // Zero for interior nodes
+    pub count: usize,
+}
+
+impl BvhNode {
+    pub fn is_leaf(&self) -> bool {
+        self.count > 0
+    }
+}
+
+// Leaves hold the bits of their first triangle in min's w and of their triangle count in max's
+// w. Interior nodes have a count of zero, their first child follows them and min's w holds the
+// second
+#[repr(C)]
+#[derive(Default, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct GpuBvhNode {
+    min: [f32; 4],
+    max: [f32; 4],
+}
+
+impl GpuBvhNode {
+    fn new(bounds: &Aabb, index: usize, count: usize) -> Self {
+        Self {
+            min: [
+                bounds.min.x,
+                bounds.min.y,
+                bounds.min.z,
+                f32::from_bits(index as u32),
+            ],
+            max: [
+                bounds.max.x,
+                bounds.max.y,
+                bounds.max.z,
+                f32::from_bits(count as u32),
+            ],
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct Ray {
+    pub origin: glm::Vec3,
+    pub direction: glm::Vec3,
+}
+
+impl Ray {
+    pub fn at(&self, distance: f32) -> glm::Vec3 {
+        self.origin + self.direction * distance
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct RayHit {
+    pub distance: f32,
+    pub triangle: usize,
+}
+
+// Bounding volume hierarchy over triangles, split with a binned surface area heuristic. The
+// triangles are left in place and leaves refer to them through the triangle order
+#[derive(Debug, Clone, Default)]
+pub struct Bvh {
+    nodes: Vec<BvhNode>,
+    triangle_order: Vec<usize>,
+}
+
+impl Bvh {
+    pub fn build(triangles: &[Triangle]) -> Self {
+        let mut bvh = Self {
+            nodes: Vec::new(),
+            triangle_order: (0..triangles.len()).collect(),
+        };
+        if triangles.is_empty() {
+            return bvh;
+        }
+        let bounds = triangles.iter().map(triangle_bounds).collect::<Vec<_>>();
+        let centroids = bounds.iter().map(Aabb::center).collect::<Vec<_>>();
+        bvh.build_node(&bounds, &centroids, 0, triangles.len(), 0);
+        bvh
+    }
+
+    // Nodes laid out for storage buffers, which can't be empty
+    pub fn gpu_nodes(&self) -> Vec<GpuBvhNode> {
+        if self.nodes.is_empty() {
+            // Empty bounds that no ray enters
+            return vec![GpuBvhNode::new(&Aabb::default(), 0, 1)];
+        }
+        self.nodes
+            .iter()
+            .map(|node| GpuBvhNode::new(&node.bounds, node.index, node.count))
+            .collect()
+    }
+
+    // Triangle indices in the order leaves refer to them
+    pub fn triangle_order(&self) -> &[usize] {
+        &self.triangle_order
+    }
+
+    fn build_node(
+        &mut self,
+        bounds: &[Aabb],
+        centroids: &[glm::Vec3],
+        start: usize,
+        end: usize,
+        depth: usize,
+    ) {
+        let mut node_bounds = Aabb::default();
+        let mut centroid_bounds = Aabb::default();
+        for triangle in self.triangle_order[start..end].iter() {
+            node_bounds.merge(&bounds[*triangle]);
+            centroid_bounds.expand_to_include(&centroids[*triangle]);
+        }
+
+        let index = self.nodes.len();
+        self.nodes.push(BvhNode {
+            bounds: node_bounds,
+            index: start,
+            count: end - start,
+        });
+        if depth >= MAX_DEPTH {
+            return;
+        }
+
+        let (axis, split_bin, scale) = match self.find_split(
+            bounds,
+            centroids,
+            start,
+            end,
+            &node_bounds,
+            &centroid_bounds,
+        ) {
+            Some(split) => split,
+            None => return,
+        };
+        let bin = |triangle: usize| {
+            let offset = centroids[triangle][axis] - centroid_bounds.min[axis];
+            ((offset * scale) as usize).min(BINS - 1)
+        };
+        let mut middle = start;
+        for position in start..end {
+            if bin(self.triangle_order[position]) <= split_bin {
+                self.triangle_order.swap(position, middle);
+                middle += 1;
+            }
+        }
+        if middle == start || middle == end {
+            return;
+        }
+
+        self.build_node(bounds, centroids, start, middle, depth + 1);
+        let second = self.nodes.len();
+        self.build_node(bounds, centroids, middle, end, depth + 1);
+        self.nodes[index].index = second;
+        self.nodes[index].count = 0;
+    }
+
+    // The axis and last bin of the left side that is cheapest to split at, if splitting is
+    // cheaper than keeping the triangles in a leaf
+    fn find_split(
+        &self,
+        bounds: &[Aabb],
+        centroids: &[glm::Vec3],
+        start: usize,
+        end: usize,
+        node_bounds: &Aabb,
+        centroid_bounds: &Aabb,
+    ) -> Option<(usize, usize, f32)> {
+        let count = end - start;
+        if count <= 1 {
+            return None;
+        }
+
+        let node_area = surface_area(node_bounds).max(f32::EPSILON);
+        let mut best: Option<(f32, usize, usize, f32)> = None;
+        for axis in [0, 1, 2] {
+            let extent = centroid_bounds.max[axis] - centroid_bounds.min[axis];
+            if extent <= f32::EPSILON {
+                continue;
+            }
+            let scale = BINS as f32 / extent;
+
+            let mut bins = [(Aabb::default(), 0); BINS];
+            for triangle in self.triangle_order[start..end].iter() {
+                let offset = centroids[*triangle][axis] - centroid_bounds.min[axis];
+                let bin = ((offset * scale) as usize).min(BINS - 1);
+                bins[bin].0.merge(&bounds[*triangle]);
+                bins[bin].1 += 1;
+            }
+
+            // Swept from the right first so each split's cost is found in a single pass
+            let mut right_areas = [0.0; BINS];
+            let mut right_counts = [0; BINS];
+            let mut right_bounds = Aabb::default();
+            let mut right_count = 0;
+            for bin in (1..BINS).rev() {
+                right_bounds.merge(&bins[bin].0);
+                right_count += bins[bin].1;
+                right_areas[bin] = surface_area(&right_bounds);
+                right_counts[bin] = right_count;
+            }
+
+            let mut left_bounds = Aabb::default();
+            let mut left_count = 0;
+            for bin in 0..BINS - 1 {
+                left_bounds.merge(&bins[bin].0);
+                left_count += bins[bin].1;
+                let right_count = right_counts[bin + 1];
+                if left_count == 0 || right_count == 0 {
+                    continue;
+                }
+                let cost = TRAVERSAL_COST
+                    + (surface_area(&left_bounds) * left_count as f32
+                        + right_areas[bin + 1] * right_count as f32)
+                        / node_area;
+                if best.is_none_or(|(best_cost, ..)| cost < best_cost) {
+                    best = Some((cost, axis, bin, scale));
+                }
+            }
+        }
+
+        let (cost, axis, bin, scale) = best?;
+        if cost >= count as f32 && count <= MAX_LEAF_TRIANGLES {
+            return None;
+        }
+        Some((axis, bin, scale))
+    }
+
+    // Updates the bounds after the triangles move without changing the hierarchy, which stays
+    // fast to traverse as long as the triangles keep roughly the same neighbors
+    pub fn refit(&mut self, triangles: &[Triangle]) {
+        // Children always come after their parents
+        for index in (0..self.nodes.len()).rev() {
+            let node = self.nodes[index];
+            let mut bounds = Aabb::default();
+            if node.is_leaf() {
+                for triangle in self.triangle_order[node.index..node.index + node.count].iter() {
+                    bounds.merge(&triangle_bounds(&triangles[*triangle]));
+                }
+            } else {
+                bounds.merge(&self.nodes[index + 1].bounds);
+                bounds.merge(&self.nodes[node.index].bounds);
+            }
+            self.nodes[index].bounds = bounds;
+        }
+    }
+
+    // Closest of the triangles the filter accepts
+    pub fn intersect(
+        &self,
+        triangles: &[Triangle],
+        ray: &Ray,
+        max_distance: f32,
+        accept: impl Fn(usize) -> bool,
+    ) -> Option<RayHit> {
+        if self.nodes.is_empty() {
+            return None;
+        }
+        let inverse_direction = glm::vec3(
+            1.0 / ray.direction.x,
+            1.0 / ray.direction.y,
+            1.0 / ray.direction.z,
+        );
+
+        let mut closest: Option<RayHit> = None;
+        let mut stack = vec![0];
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+            let distance = closest.map_or(max_distance, |hit| hit.distance);
+            if !enters_bounds(ray, &inverse_direction, &node.bounds, distance) {
+                continue;
+            }
+            if !node.is_leaf() {
+                stack.push(node.index);
+                stack.push(index + 1);
+                continue;
+            }
+            for triangle in self.triangle_order[node.index..node.index + node.count].iter() {
+                if !accept(*triangle) {
+                    continue;
+                }
+                if let Some(distance) = intersect_triangle(&triangles[*triangle], ray) {
+                    if distance < closest.map_or(max_distance, |hit| hit.distance) {
+                        closest = Some(RayHit {
+                            distance,
+                            triangle: *triangle,
+                        });
+                    }
+                }
+            }
+        }
+        closest
+    }
+}
+
+fn triangle_bounds(triangle: &Triangle) -> Aabb {
+    let mut bounds = Aabb::default();
+    for position in triangle.iter() {
+        bounds.expand_to_include(position);
+    }
+    bounds
+}
+
+fn surface_area(bounds: &Aabb) -> f32 {
+    if bounds.is_empty() {
+        return 0.0;
+    }
+    let extents = bounds.extents();
+    2.0 * (extents.x * extents.y + extents.y * extents.z + extents.z * extents.x)
+}
+
+fn enters_bounds(ray: &Ray, inverse_direction: &glm::Vec3, bounds: &Aabb, closest: f32) -> bool {
+    let t0 = (bounds.min - ray.origin).component_mul(inverse_direction);
+    let t1 = (bounds.max - ray.origin).component_mul(inverse_direction);
+    let near = glm::min2(&t0, &t1);
+    let far = glm::max2(&t0, &t1);
+    let enter = near.max();
+    let exit = far.min();
+    exit >= enter.max(0.0) && enter < closest
+}
+
+// Distance along the ray, both sides of a triangle are hit
+fn intersect_triangle(triangle: &Triangle, ray: &Ray) -> Option<f32> {
+    let edge1 = triangle[1] - triangle[0];
+    let edge2 = triangle[2] - triangle[0];
+    let p = ray.direction.cross(&edge2);
+    let determinant = edge1.dot(&p);
+    if determinant.abs() < 1e-10 {
+        return None;
+    }
+    let inverse_determinant = 1.0 / determinant;
+    let s = ray.origin - triangle[0];
+    let u = s.dot(&p) * inverse_determinant;
+    if !(0.0..=1.0).contains(&u) {
+        return None;
+    }
+    let q = s.cross(&edge1);
+    let v = ray.direction.dot(&q) * inverse_determinant;
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+    let distance = edge2.dot(&q) * inverse_determinant;
+    (distance > 1e-4).then_some(distance)
+}
+
+// A triangle of one of a model's mesh instances, with its vertices in the model's space
+pub struct SceneTriangle<'a> {
+    pub model: usize,
+    pub instance: usize,
+    pub primitive: &'a Primitive,
+    pub vertices: [&'a ModelVertex; 3],
+    pub transform: &'a glm::Mat4,
+    pub normal_matrix: &'a glm::Mat3,
+}
+
+impl<'a> SceneTriangle<'a> {
+    pub fn positions(&self) -> Triangle {
+        self.vertices
+            .map(|vertex| (self.transform * glm::Vec3::from(vertex.position).push(1.0)).xyz())
+    }
+}
+
+// Visits every model triangle in the same order for as long as the models don't change, placed
+// relative to the scene's origin like the camera
+pub fn for_each_scene_triangle(scene: &Scene, mut visit: impl FnMut(SceneTriangle)) {
+    for (model_index, model) in scene.models.iter().enumerate() {
+        for (instance_index, instance) in model.instances.iter().enumerate() {
+            let mesh = match model.meshes.get(instance.mesh) {
+                Some(mesh) => mesh,
+                None => continue,
+            };
+            let transform = scene.local_transform(&model.instance_transform(instance));
+            let normal_matrix = glm::inverse_transpose(glm::mat4_to_mat3(&transform));
+            for primitive in mesh.primitives.iter() {
+                for triangle in primitive.indices.chunks_exact(3) {
+                    let vertex = |corner: usize| primitive.vertices.get(triangle[corner] as usize);
+                    if let (Some(a), Some(b), Some(c)) = (vertex(0), vertex(1), vertex(2)) {
+                        visit(SceneTriangle {
+                            model: model_index,
+                            instance: instance_index,
+                            primitive,
+                            vertices: [a, b, c],
+                            transform: &transform,
+                            normal_matrix: &normal_matrix,
+                        });
+                    }
+                }
+            }
+        }
+    }
+}
+
+// Each instance's transform, which the hierarchy is refit to whenever they or the scene's origin
+// change
+fn instance_transforms(scene: &Scene) -> Vec<glm::Mat4> {
+    scene
+        .models
+        .iter()
+        .flat_map(|model| {
+            model
+                .instances
+                .iter()
+                .map(move |instance| scene.local_transform(&model.instance_transform(instance)))
+        })
+        .collect()
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BvhUpdate {
+    Unchanged,
+    Refit,
+    Rebuilt,
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct SceneHit {
+    pub model: ModelHandle,
+    // Index into the model's instances
+    pub instance: usize,
+    // Relative to the scene's origin, like the camera
+    pub position: glm::Vec3,
+    pub distance: f32,
+}
+
+// A hierarchy over every model triangle, rebuilt when models are added and refit when they move
+#[derive(Default)]
+pub struct SceneBvh {
+    bvh: Bvh,
+    triangles: Vec<Triangle>,
+    // The model and instance each triangle belongs to
+    owners: Vec<(usize, usize)>,
+    model_count: Option<usize>,
+    models_revision: u64,
+    transforms: Vec<glm::Mat4>,
+}
+
+impl SceneBvh {
+    pub fn update(&mut self, scene: &Scene) -> BvhUpdate {
+        let transforms = instance_transforms(scene);
+        let same_models = self.model_count == Some(scene.models.len())
+            && self.models_revision == scene.models_revision();
+        if same_models && self.transforms == transforms {
+            return BvhUpdate::Unchanged;
+        }
+
+        let rebuild = !same_models || self.transforms.len() != transforms.len();
+        self.triangles.clear();
+        self.owners.clear();
+        for_each_scene_triangle(scene, |triangle| {
+            self.triangles.push(triangle.positions());
+            self.owners.push((triangle.model, triangle.instance));
+        });
+        self.model_count = Some(scene.models.len());
+        self.models_revision = scene.models_revision();
+        self.transforms = transforms;
+
+        if rebuild {
+            self.bvh = Bvh::build(&self.triangles);
+            BvhUpdate::Rebuilt
+        } else {
+            self.bvh.refit(&self.triangles);
+            BvhUpdate::Refit
+        }
+    }
+
+    pub fn bvh(&self) -> &Bvh {
+        &self.bvh
+    }
+
+    // Every model triangle in world space, in the order the scene's triangles are visited
+    pub fn triangles(&self) -> &[Triangle] {
+        &self.triangles
+    }
+
+    // Skips the triangles of the ignored model, such as the one a camera is following
+    pub fn intersect(
+        &self,
+        ray: &Ray,
+        max_distance: f32,
+        ignored: Option<ModelHandle>,
+    ) -> Option<SceneHit> {
+        let hit = self
+            .bvh
+            .intersect(&self.triangles, ray, max_distance, |triangle| {
+                ignored.is_none_or(|ignored| self.owners[triangle].0 != ignored.0)
+            })?;
+        let (model, instance) = self.owners[hit.triangle];
+        Some(SceneHit {
+            model: ModelHandle(model),
+            instance,
+            position: ray.at(hit.distance),
+            distance: hit.distance,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn triangle_at(center: glm::Vec3) -> Triangle {
+        [
+            center + glm::vec3(-0.25, -0.25, 0.0),
+            center + glm::vec3(0.25, -0.25, 0.0),
+            center + glm::vec3(0.0, 0.25, 0.0),
+        ]
+    }
+
+    // Two layers of small triangles facing along z, one behind the other
+    fn layered_grid() -> Vec<Triangle> {
+        let mut triangles = Vec::new();
+        for z in [0.0, -2.0] {
+            for x in 0..16 {
+                for y in 0..16 {
+                    triangles.push(triangle_at(glm::vec3(x as f32, y as f32, z)));
+                }
+            }
+        }
+        triangles
+    }
+
+    fn depth(bvh: &Bvh, index: usize) -> usize {
+        let node = &bvh.nodes[index];
+        if node.is_leaf() {
+            return 0;
+        }
+        1 + depth(bvh, index + 1).max(depth(bvh, node.index))
+    }
+
+    fn contains(outer: &Aabb, inner: &Aabb) -> bool {
+        (0..3).all(|axis| outer.min[axis] <= inner.min[axis] && inner.max[axis] <= outer.max[axis])
+    }
+
+    // Checks that every triangle is in exactly one leaf and that all bounds enclose their contents
+    fn assert_valid(bvh: &Bvh, triangles: &[Triangle]) {
+        let mut seen = vec![0; triangles.len()];
+        for (index, node) in bvh.nodes.iter().enumerate() {
+            if node.is_leaf() {
+                for triangle in bvh.triangle_order[node.index..node.index + node.count].iter() {
+                    seen[*triangle] += 1;
+                    assert!(contains(
+                        &node.bounds,
+                        &triangle_bounds(&triangles[*triangle])
+                    ));
+                }
+            } else {
+                assert!(contains(&node.bounds, &bvh.nodes[index + 1].bounds));
+                assert!(contains(&node.bounds, &bvh.nodes[node.index].bounds));
+            }
+        }
+        assert!(seen.iter().all(|count| *count == 1));
+        assert!(depth(bvh, 0) <= MAX_DEPTH);
+    }
+
+    fn brute_force(triangles: &[Triangle], ray: &Ray) -> Option<usize> {
+        triangles
+            .iter()
+            .enumerate()
+            .filter_map(|(index, triangle)| Some((index, intersect_triangle(triangle, ray)?)))
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(index, _)| index)
+    }
+
+    fn ray_down(x: f32, y: f32) -> Ray {
+        Ray {
+            origin: glm::vec3(x, y, 10.0),
+            direction: glm::vec3(0.0, 0.0, -1.0),
+        }
+    }
+
+    // Rays through the middle of every triangle of the grid and through the gaps between them
+    fn assert_matches_brute_force(bvh: &Bvh, triangles: &[Triangle]) {
+        for x in 0..32 {
+            for y in 0..32 {
+                let ray = ray_down(x as f32 * 0.5, y as f32 * 0.5);
+                let hit = bvh.intersect(triangles, &ray, f32::MAX, |_| true);
+                assert_eq!(hit.map(|hit| hit.triangle), brute_force(triangles, &ray));
+            }
+        }
+    }
+
+    #[test]
+    fn builds_a_hierarchy_over_every_triangle() {
+        let triangles = layered_grid();
+        let bvh = Bvh::build(&triangles);
+        assert_valid(&bvh, &triangles);
+        assert!(bvh.nodes.len() > 1);
+        assert_eq!(Bvh::build(&[]).gpu_nodes().len(), 1);
+    }
+
+    #[test]
+    fn intersects_the_closest_accepted_triangle() {
+        let triangles = layered_grid();
+        let bvh = Bvh::build(&triangles);
+        assert_matches_brute_force(&bvh, &triangles);
+
+        let ray = ray_down(3.0, 5.0);
+        let front = bvh.intersect(&triangles, &ray, f32::MAX, |_| true).unwrap();
+        assert_eq!(front.distance, 10.0);
+        let back = bvh
+            .intersect(&triangles, &ray, f32::MAX, |triangle| {
+                triangle != front.triangle
+            })
+            .unwrap();
+        assert_eq!(back.distance, 12.0);
+        assert!(bvh.intersect(&triangles, &ray, 9.0, |_| true).is_none());
+    }
+
+    #[test]
+    fn refits_to_moved_triangles() {
+        let mut triangles = layered_grid();
+        let mut bvh = Bvh::build(&triangles);
+        for triangle in triangles.iter_mut() {
+            for position in triangle.iter_mut() {
+                position.x += 0.5;
+                position.z -= 3.0;
+            }
+        }
+        bvh.refit(&triangles);
+        assert_valid(&bvh, &triangles);
+        assert_matches_brute_force(&bvh, &triangles);
+        let hit = bvh.intersect(&triangles, &ray_down(3.5, 5.0), f32::MAX, |_| true);
+        assert_eq!(hit.map(|hit| hit.distance), Some(13.0));
+    }
+
+    #[test]
+    fn bounds_the_depth_of_degenerate_input() {
+        // Shared centroids can't be split apart
+        let triangles = vec![triangle_at(glm::vec3(1.0, 1.0, 0.0)); 100];
+        let bvh = Bvh::build(&triangles);
+        assert_valid(&bvh, &triangles);
+        let hit = bvh.intersect(&triangles, &ray_down(1.0, 1.0), f32::MAX, |_| true);
+        assert_eq!(hit.map(|hit| hit.distance), Some(10.0));
+
+        // Each split only peels off the farthest triangle along one of the axes, which would
+        // otherwise nest a level per triangle
+        let triangles = (0..90)
+            .map(|index| {
+                let mut center = glm::Vec3::zeros();
+                center[index % 3] = 17f32.powi(index as i32 / 3);
+                triangle_at(center)
+            })
+            .collect::<Vec<_>>();
+        let bvh = Bvh::build(&triangles);
+        assert_valid(&bvh, &triangles);
+        for triangle in triangles.iter() {
+            let center = triangle_bounds(triangle).center();
+            let ray = ray_down(center.x, center.y);
+            let hit = bvh.intersect(&triangles, &ray, f32::MAX, |_| true);
+            assert_eq!(hit.map(|hit| hit.triangle), brute_force(&triangles, &ray));
+        }
+    }
+}
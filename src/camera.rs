@@ -0,0 +1,318 @@
+use nalgebra_glm as glm;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    bounds::{Aabb, Frustum},
+    layers,
+    material::PAPER_WHITE_NITS,
+    texture::DepthConfig,
+};
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Projection {
+    Perspective,
+    // Height is the extent of the view in world units, the width follows the aspect ratio
+    Orthographic { height: f32 },
+}
+
+// The settings of a real camera, so lights in physical units come out as bright as they would
+// through it
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PhysicalCamera {
+    // The f-number
+    pub aperture: f32,
+    // In seconds
+    pub shutter_speed: f32,
+    pub iso: f32,
+    // In millimeters, replaces the field of view when set
+    pub focal_length: Option<f32>,
+    // In millimeters, 24 for a full frame sensor
+    pub sensor_height: f32,
+}
+
+impl Default for PhysicalCamera {
+    // Sunny sixteen, which exposes for daylight
+    fn default() -> Self {
+        Self {
+            aperture: 16.0,
+            shutter_speed: 0.01,
+            iso: 100.0,
+            focal_length: None,
+            sensor_height: 24.0,
+        }
+    }
+}
+
+impl PhysicalCamera {
+    // Exposure value at an ISO of 100
+    pub fn ev100(&self) -> f32 {
+        (self.aperture * self.aperture / self.shutter_speed.max(f32::EPSILON) * 100.0
+            / self.iso.max(f32::EPSILON))
+        .log2()
+    }
+
+    // Scales scene color so the brightest luminance the sensor records without saturating is
+    // displayed as white
+    pub fn exposure(&self) -> f32 {
+        let max_luminance = 1.2 * self.ev100().exp2();
+        PAPER_WHITE_NITS / max_luminance
+    }
+
+    // Vertical field of view in radians
+    pub fn fov(&self) -> Option<f32> {
+        self.focal_length
+            .map(|focal_length| 2.0 * (self.sensor_height / (2.0 * focal_length.max(0.1))).atan())
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Camera {
+    pub position: glm::Vec3,
+    pub target: glm::Vec3,
+    pub up: glm::Vec3,
+    pub fov: f32,
+    pub z_near: f32,
+    pub z_far: f32,
+    pub projection: Projection,
+    // Used instead of the aspect ratio of the target rendered to, such as for viewports
+    pub aspect_ratio: Option<f32>,
+    // The layers the camera sees, see layers.rs
+    pub layers: u32,
+    // Sets the exposure unless it adapts automatically, along with the field of view when it
+    // has a focal length
+    pub physical: Option<PhysicalCamera>,
+    // A world space plane, normal and distance, behind which nothing is drawn. The near plane is
+    // tilted onto it so reflections don't show what is behind the mirror
+    #[serde(skip)]
+    pub clip_plane: Option<glm::Vec4>,
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Self {
+            position: glm::vec3(0.0, 2.0, 6.0),
+            target: glm::vec3(0.0, 1.0, 0.0),
+            up: glm::Vec3::y(),
+            fov: 70_f32.to_radians(),
+            z_near: 0.1,
+            z_far: 1000.0,
+            projection: Projection::Perspective,
+            aspect_ratio: None,
+            layers: layers::ALL,
+            physical: None,
+            clip_plane: None,
+        }
+    }
+}
+
+impl Camera {
+    // Vertical field of view in radians
+    pub fn vertical_fov(&self) -> f32 {
+        self.physical
+            .and_then(|physical| physical.fov())
+            .unwrap_or(self.fov)
+    }
+
+    pub fn exposure(&self) -> Option<f32> {
+        self.physical.map(|physical| physical.exposure())
+    }
+
+    pub fn view_matrix(&self) -> glm::Mat4 {
+        glm::look_at_rh(&self.position, &self.target, &self.up)
+    }
+
+    pub fn projection_matrix(&self, aspect_ratio: f32) -> glm::Mat4 {
+        let aspect_ratio = self.aspect_ratio.unwrap_or(aspect_ratio);
+        let projection = match self.projection {
+            Projection::Perspective => {
+                glm::perspective_rh_zo(aspect_ratio, self.vertical_fov(), self.z_near, self.z_far)
+            }
+            Projection::Orthographic { height } => {
+                let half_height = height * 0.5;
+                let half_width = half_height * aspect_ratio;
+                glm::ortho_rh_zo(
+                    -half_width,
+                    half_width,
+                    -half_height,
+                    half_height,
+                    self.z_near,
+                    self.z_far,
+                )
+            }
+        };
+        match self.clip_plane {
+            Some(plane) => {
+                let plane = glm::transpose(&glm::inverse(&self.view_matrix())) * plane;
+                oblique_projection(projection, &plane)
+            }
+            None => projection,
+        }
+    }
+
+    pub fn frustum(&self, aspect_ratio: f32) -> Frustum {
+        Frustum::from_matrix(&(self.projection_matrix(aspect_ratio) * self.view_matrix()))
+    }
+
+    pub fn orbit(&mut self, yaw_delta: f32, pitch_delta: f32) {
+        let offset = self.position - self.target;
+        let radius = offset.magnitude();
+        if radius <= f32::EPSILON {
+            return;
+        }
+
+        let max_pitch = 89_f32.to_radians();
+        let yaw = offset.x.atan2(offset.z) + yaw_delta;
+        let pitch = ((offset.y / radius).asin() + pitch_delta).clamp(-max_pitch, max_pitch);
+
+        self.position = self.target
+            + glm::vec3(
+                pitch.cos() * yaw.sin(),
+                pitch.sin(),
+                pitch.cos() * yaw.cos(),
+            ) * radius;
+    }
+
+    // Turns the camera in place, the counterpart of orbit for first person cameras
+    pub fn look(&mut self, yaw_delta: f32, pitch_delta: f32) {
+        let offset = self.target - self.position;
+        let radius = offset.magnitude();
+        if radius <= f32::EPSILON {
+            return;
+        }
+
+        let max_pitch = 89_f32.to_radians();
+        let yaw = offset.x.atan2(offset.z) + yaw_delta;
+        let pitch = ((offset.y / radius).asin() + pitch_delta).clamp(-max_pitch, max_pitch);
+
+        self.target = self.position
+            + glm::vec3(
+                pitch.cos() * yaw.sin(),
+                pitch.sin(),
+                pitch.cos() * yaw.cos(),
+            ) * radius;
+    }
+
+    pub fn translate(&mut self, offset: glm::Vec3) {
+        self.position += offset;
+        self.target += offset;
+    }
+
+    // Moves the camera back along its view until the sphere around the bounds fits in both the
+    // vertical and horizontal field of view, aimed at their center
+    pub fn frame(&mut self, bounds: &Aabb, aspect_ratio: f32) {
+        if bounds.is_empty() {
+            return;
+        }
+        let center = bounds.center();
+        let radius = (bounds.extents().magnitude() * 0.5).max(self.z_near);
+        let direction = (self.target - self.position)
+            .try_normalize(f32::EPSILON)
+            .unwrap_or_else(|| -glm::Vec3::z());
+        let aspect_ratio = self.aspect_ratio.unwrap_or(aspect_ratio);
+        let distance = match &mut self.projection {
+            Projection::Perspective => {
+                let vertical_fov = self.vertical_fov();
+                let horizontal_fov = 2.0 * ((vertical_fov * 0.5).tan() * aspect_ratio).atan();
+                let half_fov = vertical_fov.min(horizontal_fov) * 0.5;
+                radius / half_fov.sin().max(0.01)
+            }
+            Projection::Orthographic { height } => {
+                *height = radius * 2.0 / aspect_ratio.min(1.0);
+                radius * 2.0
+            }
+        };
+        self.target = center;
+        self.position = center - direction * distance;
+    }
+
+    pub fn zoom(&mut self, factor: f32) {
+        let offset = (self.position - self.target) * factor;
+        if offset.magnitude() > self.z_near {
+            self.position = self.target + offset;
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Default, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct CameraUniform {
+    pub view: [[f32; 4]; 4],
+    pub projection: [[f32; 4]; 4],
+    pub inverse_view_projection: [[f32; 4]; 4],
+    pub position: [f32; 4],
+    // x: z_near, y: z_far, z: viewport width, w: viewport height
+    pub parameters: [f32; 4],
+    // Reprojects positions into the previous frame for velocity, the current one until replaced
+    pub previous_view_projection: [[f32; 4]; 4],
+    // xy: sub pixel offset of this frame in texture coordinates, zero unless upsampling temporally
+    pub jitter: [f32; 4],
+    // x: one when depth is reversed, where the near plane is at one and the far plane at zero
+    pub depth: [f32; 4],
+}
+
+impl CameraUniform {
+    pub fn new(camera: &Camera, dimensions: &[u32; 2], depth: DepthConfig) -> Self {
+        let aspect_ratio = aspect_ratio(dimensions);
+        let view = camera.view_matrix();
+        let projection = depth.projection(camera.projection_matrix(aspect_ratio));
+        let view_projection = projection * view;
+        Self {
+            view: view.into(),
+            projection: projection.into(),
+            inverse_view_projection: glm::inverse(&view_projection).into(),
+            position: [camera.position.x, camera.position.y, camera.position.z, 1.0],
+            parameters: [
+                camera.z_near,
+                camera.z_far,
+                dimensions[0] as f32,
+                dimensions[1] as f32,
+            ],
+            previous_view_projection: view_projection.into(),
+            jitter: [0.0; 4],
+            depth: [depth.reversed as u32 as f32, 0.0, 0.0, 0.0],
+        }
+    }
+
+    // Offset is in pixels. Shifting the image against it samples each pixel at its center plus
+    // the offset, the previous view projection is left unjittered for velocity
+    pub fn jittered(mut self, offset: glm::Vec2, dimensions: &[u32; 2]) -> Self {
+        let width = dimensions[0].max(1) as f32;
+        let height = dimensions[1].max(1) as f32;
+        let translation = glm::translation(&glm::vec3(
+            -2.0 * offset.x / width,
+            2.0 * offset.y / height,
+            0.0,
+        ));
+        let projection = translation * glm::Mat4::from(self.projection);
+        self.projection = projection.into();
+        self.inverse_view_projection =
+            glm::inverse(&(projection * glm::Mat4::from(self.view))).into();
+        self.jitter = [offset.x / width, offset.y / height, 0.0, 0.0];
+        self
+    }
+}
+
+// Replaces the near plane with the view space plane following Lengyel's oblique frustum clipping,
+// which keeps the far corners in place so depth stays within range
+fn oblique_projection(mut projection: glm::Mat4, plane: &glm::Vec4) -> glm::Mat4 {
+    // The camera has to be behind the plane, otherwise everything in front of it would be clipped
+    if plane.w >= 0.0 {
+        return projection;
+    }
+    let corner =
+        glm::inverse(&projection) * glm::vec4(plane.x.signum(), plane.y.signum(), 1.0, 1.0);
+    let scaled = plane / plane.dot(&corner);
+    projection.set_row(2, &scaled.transpose());
+    projection
+}
+
+pub fn aspect_ratio(dimensions: &[u32; 2]) -> f32 {
+    let height = if dimensions[1] > 0 {
+        dimensions[1] as f32
+    } else {
+        1.0
+    };
+    dimensions[0] as f32 / height
+}
@@ -0,0 +1,80 @@
+use nalgebra_glm as glm;
+
+use crate::camera::{Camera, Projection};
+
+pub const MIN_ZOOM: f32 = 0.05;
+pub const MAX_ZOOM: f32 = 64.0;
+
+// A camera for 2D applications that maps world units to window pixels, one to one at a zoom of
+// 1. Sprites are placed in its world units instead of window pixels, with y pointing down like
+// window pixels, and the 3D camera becomes an orthographic view of the XY plane lined up with
+// them, where sprite position (x, y) is world position (x, -y, 0)
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Camera2d {
+    // The world position shown at the top left corner of the window
+    pub origin: glm::Vec2,
+    // Window pixels per world unit
+    pub zoom: f32,
+    // Rounds the origin to whole pixels, so pixel art drawn at whole units stays crisp and
+    // doesn't shimmer while panning
+    pub pixel_snap: bool,
+}
+
+impl Default for Camera2d {
+    fn default() -> Self {
+        Self {
+            origin: glm::Vec2::zeros(),
+            zoom: 1.0,
+            pixel_snap: true,
+        }
+    }
+}
+
+impl Camera2d {
+    // Moves the view with the cursor, so what was under it stays under it
+    pub fn pan(&mut self, pixels: glm::Vec2) {
+        self.origin -= pixels / self.zoom;
+    }
+
+    // Scales the view around a point in window pixels, which stays where it is
+    pub fn zoom_at(&mut self, pixel: glm::Vec2, factor: f32) {
+        let world = self.origin + pixel / self.zoom;
+        self.zoom = (self.zoom * factor).clamp(MIN_ZOOM, MAX_ZOOM);
+        self.origin = world - pixel / self.zoom;
+    }
+
+    pub fn snapped_origin(&self) -> glm::Vec2 {
+        if self.pixel_snap {
+            (self.origin * self.zoom).map(f32::round) / self.zoom
+        } else {
+            self.origin
+        }
+    }
+
+    // Maps world units to clip space for sprites
+    pub fn projection(&self, dimensions: &[u32; 2]) -> glm::Mat4 {
+        let origin = self.snapped_origin();
+        let extent = glm::vec2(dimensions[0] as f32, dimensions[1] as f32) / self.zoom;
+        glm::ortho(
+            origin.x,
+            origin.x + extent.x,
+            origin.y + extent.y,
+            origin.y,
+            -1.0,
+            1.0,
+        )
+    }
+
+    // Points the 3D camera at the same part of the XY plane the sprites show
+    pub fn apply(&self, camera: &mut Camera, dimensions: &[u32; 2]) {
+        let extent = glm::vec2(dimensions[0] as f32, dimensions[1] as f32) / self.zoom;
+        let center = self.snapped_origin() + extent * 0.5;
+        // Halfway through the depth range, so geometry on either side of the plane is drawn
+        let distance = (camera.z_near + camera.z_far) * 0.5;
+        camera.target = glm::vec3(center.x, -center.y, 0.0);
+        camera.position = camera.target + glm::Vec3::z() * distance;
+        camera.up = glm::Vec3::y();
+        camera.projection = Projection::Orthographic { height: extent.y };
+        camera.aspect_ratio = None;
+    }
+}
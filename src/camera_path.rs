@@ -0,0 +1,170 @@
+use nalgebra_glm as glm;
+
+use crate::{bounds::Aabb, camera::Camera};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CameraPathKind {
+    // Circles the scene's bounds, far enough back to keep all of it in view
+    Turntable,
+    // Passes through each keyframe in turn along a Catmull-Rom spline
+    Spline,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct CameraKeyframe {
+    pub position: glm::Vec3,
+    pub target: glm::Vec3,
+}
+
+// Moves the camera along a path by the time it's given rather than the time that passed, so a
+// path stepped at a fixed rate always lands on the same views
+pub struct ScriptedCamera {
+    pub kind: CameraPathKind,
+    // Seconds for one turn of the turntable or to travel the whole spline
+    pub duration: f32,
+    // Degrees the turntable looks down on the scene from
+    pub elevation: f32,
+    pub keyframes: Vec<CameraKeyframe>,
+    // Starts the path over once it ends, the spline returns to its first keyframe
+    pub looping: bool,
+    time: f32,
+    // The scene's bounds when the path started, which the turntable circles
+    bounds: Aabb,
+}
+
+impl Default for ScriptedCamera {
+    fn default() -> Self {
+        Self {
+            kind: CameraPathKind::Turntable,
+            duration: 10.0,
+            elevation: 20.0,
+            keyframes: Vec::new(),
+            looping: true,
+            time: 0.0,
+            bounds: Aabb::default(),
+        }
+    }
+}
+
+impl ScriptedCamera {
+    pub fn restart(&mut self, bounds: Aabb) {
+        self.time = 0.0;
+        self.bounds = bounds;
+    }
+
+    pub fn time(&self) -> f32 {
+        self.time
+    }
+
+    pub fn finished(&self) -> bool {
+        !self.looping && self.time >= self.duration
+    }
+
+    pub fn add_keyframe(&mut self, camera: &Camera) {
+        self.keyframes.push(CameraKeyframe {
+            position: camera.position,
+            target: camera.target,
+        });
+    }
+
+    pub fn update(&mut self, camera: &mut Camera, delta_time: f32) {
+        let progress = self.progress();
+        self.time += delta_time;
+        camera.up = glm::Vec3::y();
+        match self.kind {
+            CameraPathKind::Turntable => self.place_on_turntable(camera, progress),
+            CameraPathKind::Spline => self.place_on_spline(camera, progress),
+        }
+    }
+
+    // How far along the path the camera is, from zero to one
+    fn progress(&self) -> f32 {
+        let progress = self.time / self.duration.max(f32::EPSILON);
+        if self.looping {
+            progress.fract()
+        } else {
+            progress.min(1.0)
+        }
+    }
+
+    fn place_on_turntable(&self, camera: &mut Camera, progress: f32) {
+        // An empty scene is circled around the camera's target at its current distance
+        let (center, distance) = if self.bounds.is_empty() {
+            (
+                camera.target,
+                glm::distance(&camera.position, &camera.target),
+            )
+        } else {
+            let radius = self.bounds.extents().magnitude() * 0.5;
+            let half_fov = camera.vertical_fov() * 0.5;
+            (self.bounds.center(), radius / half_fov.sin().max(0.01))
+        };
+        let yaw = progress * std::f32::consts::TAU;
+        let pitch = self.elevation.to_radians();
+        camera.target = center;
+        camera.position = center
+            + glm::vec3(
+                pitch.cos() * yaw.sin(),
+                pitch.sin(),
+                pitch.cos() * yaw.cos(),
+            ) * distance;
+    }
+
+    fn place_on_spline(&self, camera: &mut Camera, progress: f32) {
+        let count = self.keyframes.len();
+        if count < 2 {
+            return;
+        }
+        // A looping spline has a segment back from the last keyframe to the first
+        let segments = if self.looping { count } else { count - 1 };
+        let position = progress * segments as f32;
+        let segment = (position.floor() as usize).min(segments - 1);
+        let t = position - segment as f32;
+        let keyframe = |index: isize| {
+            let index = if self.looping {
+                index.rem_euclid(count as isize)
+            } else {
+                index.clamp(0, count as isize - 1)
+            };
+            &self.keyframes[index as usize]
+        };
+        let segment = segment as isize;
+        let points = [
+            keyframe(segment - 1),
+            keyframe(segment),
+            keyframe(segment + 1),
+            keyframe(segment + 2),
+        ];
+        camera.position = catmull_rom(
+            &points[0].position,
+            &points[1].position,
+            &points[2].position,
+            &points[3].position,
+            t,
+        );
+        camera.target = catmull_rom(
+            &points[0].target,
+            &points[1].target,
+            &points[2].target,
+            &points[3].target,
+            t,
+        );
+    }
+}
+
+// Passes through the middle two points, heading towards each next one
+fn catmull_rom(
+    p0: &glm::Vec3,
+    p1: &glm::Vec3,
+    p2: &glm::Vec3,
+    p3: &glm::Vec3,
+    t: f32,
+) -> glm::Vec3 {
+    let t2 = t * t;
+    let t3 = t2 * t;
+    (p1 * 2.0
+        + (p2 - p0) * t
+        + (p0 * 2.0 - p1 * 5.0 + p2 * 4.0 - p3) * t2
+        + (p1 * 3.0 - p0 - p2 * 3.0 + p3) * t3)
+        * 0.5
+}
@@ -0,0 +1,418 @@
+use nalgebra_glm as glm;
+
+use crate::{
+    lighting::{DirectionalLight, PunctualLight},
+    material::Material,
+    model::ModelDesc,
+    scene::{LightHandle, MaterialHandle, ModelHandle, Scene},
+};
+
+// Older commands are forgotten past this many
+const MAX_UNDO: usize = 100;
+
+// A reversible scene edit. Commands refer to models and lights by index, which stays valid as
+// long as every edit that adds or removes them goes through the same history
+pub trait Command {
+    fn name(&self) -> String;
+    fn apply(&mut self, scene: &mut Scene);
+    fn revert(&mut self, scene: &mut Scene);
+}
+
+#[derive(Default)]
+pub struct CommandHistory {
+    undo: Vec<Box<dyn Command>>,
+    redo: Vec<Box<dyn Command>>,
+}
+
+impl CommandHistory {
+    pub fn execute(&mut self, mut command: Box<dyn Command>, scene: &mut Scene) {
+        command.apply(scene);
+        self.record(command);
+    }
+
+    // For edits already made to the scene, such as values dragged in the inspector
+    pub fn record(&mut self, command: Box<dyn Command>) {
+        self.redo.clear();
+        self.undo.push(command);
+        if self.undo.len() > MAX_UNDO {
+            self.undo.remove(0);
+        }
+    }
+
+    pub fn undo(&mut self, scene: &mut Scene) {
+        if let Some(mut command) = self.undo.pop() {
+            command.revert(scene);
+            self.redo.push(command);
+        }
+    }
+
+    pub fn redo(&mut self, scene: &mut Scene) {
+        if let Some(mut command) = self.redo.pop() {
+            command.apply(scene);
+            self.undo.push(command);
+        }
+    }
+
+    // Needed when the scene changes outside the history, such as when its models are replaced
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+    }
+
+    pub fn undo_name(&self) -> Option<String> {
+        self.undo.last().map(|command| command.name())
+    }
+
+    pub fn redo_name(&self) -> Option<String> {
+        self.redo.last().map(|command| command.name())
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TransformTarget {
+    Model(ModelHandle),
+    Instance { model: ModelHandle, instance: usize },
+}
+
+impl TransformTarget {
+    // Models are placed in f64 and their instances relative to them in f32
+    pub fn set(self, scene: &mut Scene, transform: &glm::DMat4) {
+        match self {
+            Self::Model(model) => {
+                if let Some(model) = scene.models.get_mut(model.0) {
+                    model.transform = *transform;
+                }
+            }
+            Self::Instance { model, instance } => {
+                if let Some(instance) = scene
+                    .models
+                    .get_mut(model.0)
+                    .and_then(|model| model.instances.get_mut(instance))
+                {
+                    instance.transform = transform.cast();
+                }
+            }
+        }
+    }
+}
+
+pub struct SetTransform {
+    pub target: TransformTarget,
+    pub before: glm::DMat4,
+    pub after: glm::DMat4,
+}
+
+impl Command for SetTransform {
+    fn name(&self) -> String {
+        "Transform".to_string()
+    }
+
+    fn apply(&mut self, scene: &mut Scene) {
+        self.target.set(scene, &self.after);
+    }
+
+    fn revert(&mut self, scene: &mut Scene) {
+        self.target.set(scene, &self.before);
+    }
+}
+
+pub struct SetLayers {
+    pub model: ModelHandle,
+    pub instance: usize,
+    pub before: u32,
+    pub after: u32,
+}
+
+impl Command for SetLayers {
+    fn name(&self) -> String {
+        "Layers".to_string()
+    }
+
+    fn apply(&mut self, scene: &mut Scene) {
+        if let Some(layers) = layers_mut(scene, self.model, self.instance) {
+            *layers = self.after;
+        }
+    }
+
+    fn revert(&mut self, scene: &mut Scene) {
+        if let Some(layers) = layers_mut(scene, self.model, self.instance) {
+            *layers = self.before;
+        }
+    }
+}
+
+fn layers_mut(scene: &mut Scene, model: ModelHandle, instance: usize) -> Option<&mut u32> {
+    scene
+        .models
+        .get_mut(model.0)
+        .and_then(|model| model.instances.get_mut(instance))
+        .map(|instance| &mut instance.layers)
+}
+
+// Only the factors the inspector edits change, so the model's textures don't need reloading
+pub struct SetMaterial {
+    pub model: ModelHandle,
+    pub material: usize,
+    pub before: Material,
+    pub after: Material,
+}
+
+impl Command for SetMaterial {
+    fn name(&self) -> String {
+        format!("Material {}", self.material)
+    }
+
+    fn apply(&mut self, scene: &mut Scene) {
+        if let Some(material) = material_mut(scene, self.model, self.material) {
+            *material = self.after.clone();
+        }
+    }
+
+    fn revert(&mut self, scene: &mut Scene) {
+        if let Some(material) = material_mut(scene, self.model, self.material) {
+            *material = self.before.clone();
+        }
+    }
+}
+
+fn material_mut(scene: &mut Scene, model: ModelHandle, material: usize) -> Option<&mut Material> {
+    scene
+        .materials()
+        .get_mut(MaterialHandle { model, material })
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum LightTarget {
+    Scene(LightHandle),
+    Model { model: ModelHandle, light: usize },
+}
+
+impl LightTarget {
+    pub fn light_mut(self, scene: &mut Scene) -> Option<&mut PunctualLight> {
+        match self {
+            Self::Scene(light) => scene.lights.get_mut(light.0),
+            Self::Model { model, light } => scene
+                .models
+                .get_mut(model.0)
+                .and_then(|model| model.lights.get_mut(light)),
+        }
+    }
+}
+
+pub struct SetLight {
+    pub target: LightTarget,
+    pub before: PunctualLight,
+    pub after: PunctualLight,
+}
+
+impl Command for SetLight {
+    fn name(&self) -> String {
+        "Light".to_string()
+    }
+
+    fn apply(&mut self, scene: &mut Scene) {
+        if let Some(light) = self.target.light_mut(scene) {
+            *light = self.after;
+        }
+    }
+
+    fn revert(&mut self, scene: &mut Scene) {
+        if let Some(light) = self.target.light_mut(scene) {
+            *light = self.before;
+        }
+    }
+}
+
+pub struct SetSun {
+    pub before: DirectionalLight,
+    pub after: DirectionalLight,
+}
+
+impl Command for SetSun {
+    fn name(&self) -> String {
+        "Sun".to_string()
+    }
+
+    fn apply(&mut self, scene: &mut Scene) {
+        scene.sun = self.after;
+    }
+
+    fn revert(&mut self, scene: &mut Scene) {
+        scene.sun = self.before;
+    }
+}
+
+// Spawns the model at the end of the scene's models, holding on to it while undone. Redoing puts
+// it back where it was spawned, even if models were added after it
+pub struct AddModel {
+    desc: Option<ModelDesc>,
+    model: Option<ModelHandle>,
+}
+
+impl AddModel {
+    pub fn new(desc: ModelDesc) -> Self {
+        Self {
+            desc: Some(desc),
+            model: None,
+        }
+    }
+}
+
+impl Command for AddModel {
+    fn name(&self) -> String {
+        "Add model".to_string()
+    }
+
+    fn apply(&mut self, scene: &mut Scene) {
+        let desc = match self.desc.take() {
+            Some(desc) => desc,
+            None => return,
+        };
+        self.model = Some(match self.model {
+            Some(model) => {
+                scene.insert_model(model, desc);
+                model
+            }
+            None => scene.spawn_model(desc),
+        });
+    }
+
+    fn revert(&mut self, scene: &mut Scene) {
+        if let Some(model) = self.model {
+            self.desc = scene.remove_model(model);
+        }
+    }
+}
+
+// Later models move down to fill the gap, and move back up when the removal is undone
+pub struct RemoveModel {
+    model: ModelHandle,
+    desc: Option<ModelDesc>,
+}
+
+impl RemoveModel {
+    pub fn new(model: ModelHandle) -> Self {
+        Self { model, desc: None }
+    }
+}
+
+impl Command for RemoveModel {
+    fn name(&self) -> String {
+        "Remove model".to_string()
+    }
+
+    fn apply(&mut self, scene: &mut Scene) {
+        self.desc = scene.remove_model(self.model);
+    }
+
+    fn revert(&mut self, scene: &mut Scene) {
+        if let Some(desc) = self.desc.take() {
+            scene.insert_model(self.model, desc);
+        }
+    }
+}
+
+pub struct AddLight {
+    pub light: PunctualLight,
+}
+
+impl Command for AddLight {
+    fn name(&self) -> String {
+        "Add light".to_string()
+    }
+
+    fn apply(&mut self, scene: &mut Scene) {
+        scene.spawn_light(self.light);
+    }
+
+    fn revert(&mut self, scene: &mut Scene) {
+        scene.lights.pop();
+    }
+}
+
+pub struct RemoveLight {
+    light: LightHandle,
+    removed: Option<PunctualLight>,
+}
+
+impl RemoveLight {
+    pub fn new(light: LightHandle) -> Self {
+        Self {
+            light,
+            removed: None,
+        }
+    }
+}
+
+impl Command for RemoveLight {
+    fn name(&self) -> String {
+        "Remove light".to_string()
+    }
+
+    fn apply(&mut self, scene: &mut Scene) {
+        self.removed =
+            (self.light.0 < scene.lights.len()).then(|| scene.lights.remove(self.light.0));
+    }
+
+    fn revert(&mut self, scene: &mut Scene) {
+        if let Some(light) = self.removed.take() {
+            let index = self.light.0.min(scene.lights.len());
+            scene.lights.insert(index, light);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn model(name: &str) -> ModelDesc {
+        ModelDesc {
+            name: Some(name.to_string()),
+            ..Default::default()
+        }
+    }
+
+    fn names(scene: &Scene) -> Vec<&str> {
+        scene
+            .models
+            .iter()
+            .map(|model| model.name.as_deref().unwrap_or_default())
+            .collect()
+    }
+
+    #[test]
+    fn undoes_and_redoes_adding_and_removing_models() {
+        let mut scene = Scene::default();
+        let mut history = CommandHistory::default();
+        history.execute(Box::new(AddModel::new(model("a"))), &mut scene);
+        history.execute(Box::new(AddModel::new(model("b"))), &mut scene);
+        history.execute(Box::new(RemoveModel::new(ModelHandle(0))), &mut scene);
+        assert_eq!(names(&scene), ["b"]);
+
+        history.undo(&mut scene);
+        assert_eq!(names(&scene), ["a", "b"]);
+        history.undo(&mut scene);
+        assert_eq!(names(&scene), ["a"]);
+        history.redo(&mut scene);
+        assert_eq!(names(&scene), ["a", "b"]);
+        history.redo(&mut scene);
+        assert_eq!(names(&scene), ["b"]);
+        assert_eq!(history.redo_name(), None);
+    }
+
+    #[test]
+    fn undoing_an_add_keeps_models_added_outside_the_history() {
+        let mut scene = Scene::default();
+        let mut history = CommandHistory::default();
+        history.execute(Box::new(AddModel::new(model("added"))), &mut scene);
+        scene.spawn_model(model("opened"));
+
+        history.undo(&mut scene);
+        assert_eq!(names(&scene), ["opened"]);
+        history.redo(&mut scene);
+        assert_eq!(names(&scene), ["added", "opened"]);
+        history.undo(&mut scene);
+        assert_eq!(names(&scene), ["opened"]);
+    }
+}
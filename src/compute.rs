@@ -0,0 +1,435 @@
+use anyhow::{Context, Result};
+
+use crate::{
+    memory::{self, MemoryCategory, Tracked},
+    readback::{Readback, TextureLayout},
+    scene::Scene,
+    shader_errors::{ComposedShader, ShaderError},
+};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct ComputeBufferHandle(pub usize);
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct ComputeTextureHandle(pub usize);
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct ComputePipelineHandle(pub usize);
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ComputeBufferDesc {
+    // In bytes, rounded up to a multiple of 16
+    pub size: u64,
+    contents: Vec<u8>,
+    // Changes whenever the contents have to be uploaded again
+    revision: u64,
+}
+
+impl ComputeBufferDesc {
+    #[allow(dead_code)]
+    pub fn new(size: u64) -> Self {
+        Self {
+            size,
+            contents: Vec::new(),
+            revision: 0,
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn with_contents(contents: &[u8]) -> Self {
+        let mut desc = Self::new(contents.len() as u64);
+        desc.set_contents(contents);
+        desc
+    }
+
+    // Replaces the start of the buffer before the next frame's dispatches
+    #[allow(dead_code)]
+    pub fn set_contents(&mut self, contents: &[u8]) {
+        self.contents = contents.to_vec();
+        self.revision += 1;
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ComputeTextureDesc {
+    pub dimensions: [u32; 2],
+    pub format: wgpu::TextureFormat,
+}
+
+#[allow(dead_code)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum StorageAccess {
+    ReadOnly,
+    WriteOnly,
+}
+
+// Bound to group 0 in order, the first binding at index 0
+#[allow(dead_code)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ComputeBinding {
+    Uniform(ComputeBufferHandle),
+    Storage {
+        buffer: ComputeBufferHandle,
+        read_only: bool,
+    },
+    // Read with textureLoad
+    Texture(ComputeTextureHandle),
+    StorageTexture {
+        texture: ComputeTextureHandle,
+        access: StorageAccess,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ComputePipelineDesc {
+    pub label: String,
+    // WGSL source
+    pub source: String,
+    pub entry_point: String,
+    pub bindings: Vec<ComputeBinding>,
+}
+
+// Where dispatches run relative to the passes of the frame
+#[allow(dead_code)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ComputeStage {
+    // Before the shadow and scene passes, for work the frame is drawn from
+    BeforeRender,
+    // After the scene and its transparent passes, before bloom and post processing
+    AfterRender,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct DispatchDesc {
+    pub pipeline: ComputePipelineHandle,
+    pub workgroups: [u32; 3],
+    pub stage: ComputeStage,
+    // Disabled dispatches are kept but skipped
+    pub enabled: bool,
+}
+
+// Shaders come from users, so they are checked up front instead of letting wgpu abort on them
+fn validate_shader(desc: &ComputePipelineDesc) -> Result<(), ShaderError> {
+    let mut shader = ComposedShader::new(desc.label.clone());
+    shader.push(desc.label.clone(), &desc.source, 1);
+    let module = shader.validate()?;
+    if !module.entry_points.iter().any(|entry_point| {
+        entry_point.stage == naga::ShaderStage::Compute && entry_point.name == desc.entry_point
+    }) {
+        return Err(ShaderError::new(
+            desc.label.clone(),
+            format!("No compute entry point named '{}'", desc.entry_point),
+        ));
+    }
+    Ok(())
+}
+
+// Buffers and textures that can be read back to the CPU
+#[allow(dead_code)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ComputeResource {
+    Buffer(ComputeBufferHandle),
+    Texture(ComputeTextureHandle),
+}
+
+struct GpuComputeBuffer {
+    buffer: Tracked<wgpu::Buffer>,
+    size: u64,
+    revision: u64,
+}
+
+struct GpuComputeTexture {
+    texture: Tracked<wgpu::Texture>,
+    view: wgpu::TextureView,
+}
+
+struct GpuComputePipeline {
+    pipeline: wgpu::ComputePipeline,
+    bind_group: wgpu::BindGroup,
+}
+
+#[derive(Default)]
+pub struct ComputeSystem {
+    buffers: Vec<GpuComputeBuffer>,
+    textures: Vec<GpuComputeTexture>,
+    pipelines: Vec<GpuComputePipeline>,
+}
+
+impl ComputeSystem {
+    fn create_buffer(device: &wgpu::Device, desc: &ComputeBufferDesc) -> GpuComputeBuffer {
+        let size = desc
+            .size
+            .max(desc.contents.len() as u64)
+            .max(1)
+            .div_ceil(16)
+            * 16;
+        let buffer = memory::create_buffer(
+            device,
+            MemoryCategory::Targets,
+            &wgpu::BufferDescriptor {
+                label: Some("Compute Buffer"),
+                size,
+                usage: wgpu::BufferUsages::STORAGE
+                    | wgpu::BufferUsages::UNIFORM
+                    | wgpu::BufferUsages::COPY_DST
+                    | wgpu::BufferUsages::COPY_SRC,
+                mapped_at_creation: false,
+            },
+        );
+        GpuComputeBuffer {
+            buffer,
+            size,
+            // Forces the first upload when there are contents
+            revision: 0,
+        }
+    }
+
+    fn create_texture(device: &wgpu::Device, desc: &ComputeTextureDesc) -> GpuComputeTexture {
+        let texture = memory::create_texture(
+            device,
+            MemoryCategory::Targets,
+            &wgpu::TextureDescriptor {
+                label: Some("Compute Texture"),
+                size: wgpu::Extent3d {
+                    width: desc.dimensions[0].max(1),
+                    height: desc.dimensions[1].max(1),
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: desc.format,
+                usage: wgpu::TextureUsages::STORAGE_BINDING
+                    | wgpu::TextureUsages::TEXTURE_BINDING
+                    | wgpu::TextureUsages::COPY_SRC,
+            },
+        );
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        GpuComputeTexture { texture, view }
+    }
+
+    fn create_pipeline(
+        &self,
+        device: &wgpu::Device,
+        scene: &Scene,
+        desc: &ComputePipelineDesc,
+    ) -> Result<GpuComputePipeline> {
+        let buffer = |handle: ComputeBufferHandle| {
+            self.buffers.get(handle.0).with_context(|| {
+                format!(
+                    "Compute pipeline '{}' binds missing buffer {}!",
+                    desc.label, handle.0
+                )
+            })
+        };
+        let texture = |handle: ComputeTextureHandle| {
+            self.textures
+                .get(handle.0)
+                .zip(scene.compute_textures.get(handle.0))
+                .with_context(|| {
+                    format!(
+                        "Compute pipeline '{}' binds missing texture {}!",
+                        desc.label, handle.0
+                    )
+                })
+        };
+
+        validate_shader(desc)?;
+
+        let mut layout_entries = Vec::new();
+        let mut entries = Vec::new();
+        for (index, binding) in desc.bindings.iter().enumerate() {
+            let (ty, resource) = match *binding {
+                ComputeBinding::Uniform(handle) => (
+                    wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    buffer(handle)?.buffer.as_entire_binding(),
+                ),
+                ComputeBinding::Storage {
+                    buffer: handle,
+                    read_only,
+                } => (
+                    wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    buffer(handle)?.buffer.as_entire_binding(),
+                ),
+                ComputeBinding::Texture(handle) => (
+                    wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    wgpu::BindingResource::TextureView(&texture(handle)?.0.view),
+                ),
+                ComputeBinding::StorageTexture {
+                    texture: handle,
+                    access,
+                } => {
+                    let (texture, texture_desc) = texture(handle)?;
+                    (
+                        wgpu::BindingType::StorageTexture {
+                            access: match access {
+                                StorageAccess::ReadOnly => wgpu::StorageTextureAccess::ReadOnly,
+                                StorageAccess::WriteOnly => wgpu::StorageTextureAccess::WriteOnly,
+                            },
+                            format: texture_desc.format,
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                        },
+                        wgpu::BindingResource::TextureView(&texture.view),
+                    )
+                }
+            };
+            layout_entries.push(wgpu::BindGroupLayoutEntry {
+                binding: index as u32,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty,
+                count: None,
+            });
+            entries.push(wgpu::BindGroupEntry {
+                binding: index as u32,
+                resource,
+            });
+        }
+
+        let bind_group_layout = memory::create_bind_group_layout(
+            device,
+            &wgpu::BindGroupLayoutDescriptor {
+                label: Some("Compute Bind Group Layout"),
+                entries: &layout_entries,
+            },
+        );
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Compute Bind Group"),
+            layout: &bind_group_layout,
+            entries: &entries,
+        });
+
+        let module = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some(&desc.label),
+            source: wgpu::ShaderSource::Wgsl(desc.source.as_str().into()),
+        });
+
+        let pipeline_layout = memory::create_pipeline_layout(
+            device,
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("Compute Pipeline Layout"),
+                bind_group_layouts: &[&bind_group_layout],
+                push_constant_ranges: &[],
+            },
+        );
+
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some(&desc.label),
+            layout: Some(&pipeline_layout),
+            module: &module,
+            entry_point: &desc.entry_point,
+        });
+
+        Ok(GpuComputePipeline {
+            pipeline,
+            bind_group,
+        })
+    }
+
+    pub fn update(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        scene: &Scene,
+    ) -> Result<()> {
+        while self.buffers.len() < scene.compute_buffers.len() {
+            let buffer = Self::create_buffer(device, &scene.compute_buffers[self.buffers.len()]);
+            self.buffers.push(buffer);
+        }
+
+        while self.textures.len() < scene.compute_textures.len() {
+            let texture =
+                Self::create_texture(device, &scene.compute_textures[self.textures.len()]);
+            self.textures.push(texture);
+        }
+
+        // Resources are created first so pipelines can bind any of them
+        while self.pipelines.len() < scene.compute_pipelines.len() {
+            let desc = &scene.compute_pipelines[self.pipelines.len()];
+            let pipeline = self.create_pipeline(device, scene, desc)?;
+            self.pipelines.push(pipeline);
+        }
+
+        for (desc, buffer) in scene.compute_buffers.iter().zip(self.buffers.iter_mut()) {
+            if buffer.revision == desc.revision {
+                continue;
+            }
+            buffer.revision = desc.revision;
+            // Writes must be a multiple of four bytes
+            let mut contents = desc.contents.clone();
+            contents.resize(contents.len().div_ceil(4) * 4, 0);
+            if !contents.is_empty() {
+                queue.write_buffer(&buffer.buffer, 0, &contents);
+            }
+        }
+
+        Ok(())
+    }
+
+    // Records a copy of a resource as it is at this point of the frame
+    pub fn readback(
+        &self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        scene: &Scene,
+        resource: ComputeResource,
+    ) -> Result<Readback> {
+        Ok(match resource {
+            ComputeResource::Buffer(handle) => {
+                let buffer = self
+                    .buffers
+                    .get(handle.0)
+                    .with_context(|| format!("Can't read back missing buffer {}!", handle.0))?;
+                Readback::from_buffer(device, encoder, &buffer.buffer, 0, buffer.size)
+            }
+            ComputeResource::Texture(handle) => {
+                let (texture, desc) = self
+                    .textures
+                    .get(handle.0)
+                    .zip(scene.compute_textures.get(handle.0))
+                    .with_context(|| format!("Can't read back missing texture {}!", handle.0))?;
+                let [width, height] = desc.dimensions.map(|dimension| dimension.max(1));
+                let bytes_per_pixel = desc.format.describe().block_size as u32;
+                Readback::from_texture(
+                    device,
+                    encoder,
+                    &texture.texture,
+                    TextureLayout::new(width, height, bytes_per_pixel),
+                )
+            }
+        })
+    }
+
+    // Each dispatch gets its own pass, so wgpu places the barriers between dispatches
+    // and the render passes around them that read or write the same resources
+    pub fn dispatch(&self, encoder: &mut wgpu::CommandEncoder, scene: &Scene, stage: ComputeStage) {
+        for desc in scene.dispatches.iter() {
+            if !desc.enabled || desc.stage != stage {
+                continue;
+            }
+            let pipeline = match self.pipelines.get(desc.pipeline.0) {
+                Some(pipeline) => pipeline,
+                None => continue,
+            };
+            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("Compute Pass"),
+            });
+            compute_pass.set_pipeline(&pipeline.pipeline);
+            compute_pass.set_bind_group(0, &pipeline.bind_group, &[]);
+            let [x, y, z] = desc.workgroups;
+            compute_pass.dispatch(x, y, z);
+        }
+    }
+}
@@ -0,0 +1,47 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+use crate::{actions::ActionMap, camera::Camera, settings::Settings, window_mode::WindowMode};
+
+const MAX_RECENT_FILES: usize = 10;
+
+// The viewer's state remembered between sessions. Anything missing from the file, such as
+// settings added since it was saved, keeps its default
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub window_dimensions: Option<[u32; 2]>,
+    pub window_mode: Option<WindowMode>,
+    pub camera: Option<Camera>,
+    pub settings: Settings,
+    // Most recently opened first
+    pub recent_files: Vec<PathBuf>,
+    pub bindings: Option<ActionMap>,
+}
+
+impl Config {
+    // A missing file is a first run rather than an error
+    pub fn load(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read config: {}", path.display()))?;
+        ron::from_str(&contents)
+            .with_context(|| format!("Failed to parse config: {}", path.display()))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let contents = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())?;
+        std::fs::write(path, contents)
+            .with_context(|| format!("Failed to write config: {}", path.display()))
+    }
+
+    pub fn add_recent_file(&mut self, path: &Path) {
+        let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+        self.recent_files.retain(|recent| *recent != path);
+        self.recent_files.insert(0, path);
+        self.recent_files.truncate(MAX_RECENT_FILES);
+    }
+}
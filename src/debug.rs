@@ -0,0 +1,1061 @@
+use crate::{
+    bookmarks::{CameraBookmark, BOOKMARK_SLOTS},
+    budgets::{milliseconds, BudgetMonitor},
+    camera::{Camera, PhysicalCamera},
+    camera_2d::{Camera2d, MAX_ZOOM, MIN_ZOOM},
+    camera_path::{CameraPathKind, ScriptedCamera},
+    input::CameraMode,
+    layers,
+    material::{AlphaMode, Material, OcclusionBlend, ShadingModel, MATERIAL_TEXTURE_NAMES},
+    renderer::{AdapterDetails, Renderer},
+    scene::{MaterialHandle, ModelHandle, Scene},
+    settings::{
+        BloomSettings, CameraImperfectionSettings, CullMode, DepthOfFieldSettings, EffectQuality,
+        ImportSettings, MotionBlurSettings, RayTracedShadowSettings, RecordingSettings, Settings,
+        SharpenMode, SharpenSettings, SkySettings, SsaoSettings, ToneMapping, Upscaling,
+        VolumetricSettings, MAX_RENDER_SCALE, MAX_UI_SCALE, MIN_RENDER_SCALE, MIN_UI_SCALE,
+    },
+    texture::ProceduralTexture,
+    viewports::{ViewMode, Viewport},
+};
+
+// Width and height of images generated in the material editor
+const GENERATED_TEXTURE_SIZE: u32 = 256;
+
+// Changes requested through the debug interface, applied once the frame is done
+pub enum DebugAction {
+    SwitchAdapter(usize),
+    Follow(ModelHandle),
+    StopFollowing,
+    SetCameraMode(CameraMode),
+    GrabCursor,
+    OpenWindow,
+    ToggleRecording,
+    GoToBookmark(usize),
+    Focus,
+    ExportScene,
+    PlayPath,
+    RecordPath,
+}
+
+pub fn debug_window(
+    context: &egui::CtxRef,
+    renderer: &mut Renderer,
+    scene: &mut Scene,
+    scripted_camera: &mut ScriptedCamera,
+) -> Vec<DebugAction> {
+    let mut actions = Vec::new();
+    egui::Window::new("Debug").show(context, |ui| {
+        egui::CollapsingHeader::new("Performance")
+            .default_open(true)
+            .show(ui, |ui| {
+                ui.label(format!("Power source: {:?}", renderer.power_source()));
+                performance_settings(ui, &mut renderer.settings);
+            });
+        egui::CollapsingHeader::new("Display").show(ui, |ui| {
+            let [width, height] = renderer.physical_size();
+            let logical_size = renderer.logical_size();
+            ui.label(format!("Physical size: {}x{}", width, height));
+            ui.label(format!(
+                "Logical size: {:.0}x{:.0}",
+                logical_size.x, logical_size.y
+            ));
+            ui.label(format!("Scale factor: {:.2}", renderer.scale_factor()));
+            ui.add(
+                egui::Slider::new(&mut renderer.settings.ui_scale, MIN_UI_SCALE..=MAX_UI_SCALE)
+                    .text("Interface scale"),
+            );
+        });
+        egui::CollapsingHeader::new("Profiler").show(ui, |ui| {
+            let gpu_timing_supported = renderer.gpu_timing_supported();
+            profiler_details(ui, &renderer.budgets, gpu_timing_supported);
+            let stats = renderer.draw_stats();
+            ui.label(format!(
+                "Model draws: {}, pipelines: {}, bind groups: {}, buffers: {}",
+                stats.draws, stats.pipelines, stats.bind_groups, stats.buffers
+            ));
+            ui.label(format!(
+                "Push constants: {}, dynamic offsets: {}",
+                stats.push_constants, stats.dynamic_offsets
+            ));
+            ui.label(format!("GPU memory: {}", renderer.memory_stats()));
+            if renderer.frame_capture.available() {
+                if ui.button("Capture next frame (F9)").clicked() {
+                    if let Err(error) = renderer.capture_next_frame() {
+                        eprintln!("Failed to capture the frame: {}", error);
+                    }
+                }
+                ui.label(format!(
+                    "RenderDoc captures: {}",
+                    renderer.frame_capture.captures()
+                ));
+            } else {
+                ui.label("RenderDoc isn't attached");
+            }
+            let (lights, views) = renderer.shadow_stats();
+            ui.label(format!(
+                "Local shadows: {} lights in {} atlas views",
+                lights, views
+            ));
+            let (allocated, acquired) = renderer.render_target_stats();
+            ui.label(format!(
+                "Render targets: {} allocated for {} uses",
+                allocated, acquired
+            ));
+            ui.label(format!("Redundant binds skipped: {}", stats.redundant));
+        });
+        egui::CollapsingHeader::new("Exposure").show(ui, |ui| {
+            exposure_settings(ui, &mut renderer.settings);
+            ui.checkbox(&mut renderer.settings.dithering, "Dither output");
+            physical_camera_settings(ui, &mut renderer.camera);
+        });
+        egui::CollapsingHeader::new("Bloom").show(ui, |ui| {
+            bloom_settings(ui, &mut renderer.settings.bloom);
+        });
+        egui::CollapsingHeader::new("Depth Of Field").show(ui, |ui| {
+            depth_of_field_settings(ui, &mut renderer.settings.depth_of_field);
+        });
+        egui::CollapsingHeader::new("Motion Blur").show(ui, |ui| {
+            motion_blur_settings(ui, &mut renderer.settings.motion_blur);
+        });
+        egui::CollapsingHeader::new("Camera Imperfections").show(ui, |ui| {
+            imperfection_settings(ui, &mut renderer.settings.imperfections);
+            ui.checkbox(&mut renderer.settings.lens_flares, "Lens flares");
+        });
+        egui::CollapsingHeader::new("Sharpening").show(ui, |ui| {
+            sharpen_settings(ui, &mut renderer.settings.sharpen);
+        });
+        egui::CollapsingHeader::new("Sky").show(ui, |ui| {
+            sky_settings(ui, &mut renderer.settings.sky);
+            environment_settings(ui, &mut renderer.settings.environment);
+        });
+        egui::CollapsingHeader::new("Volumetric Lighting").show(ui, |ui| {
+            volumetric_settings(ui, &mut renderer.settings.volumetric);
+        });
+        egui::CollapsingHeader::new("Ambient Occlusion").show(ui, |ui| {
+            ssao_settings(ui, &mut renderer.settings.ssao);
+        });
+        egui::CollapsingHeader::new("Ray Traced Shadows").show(ui, |ui| {
+            ray_traced_shadow_settings(ui, &mut renderer.settings.ray_traced_shadows);
+        });
+        egui::CollapsingHeader::new("Reflection Probes").show(ui, |ui| {
+            ui.label(format!("Probes: {}", scene.reflection_probes.len()));
+            if ui.button("Recapture").clicked() {
+                renderer.capture_reflection_probes();
+            }
+        });
+        egui::CollapsingHeader::new("Irradiance Volume").show(ui, |ui| {
+            ui.checkbox(&mut renderer.settings.irradiance_probes, "Show probes");
+            if ui.button("Rebake").clicked() {
+                renderer.bake_irradiance_volume();
+            }
+        });
+        egui::CollapsingHeader::new("Camera").show(ui, |ui| {
+            ui.horizontal(|ui| {
+                if ui.button("Orbit").clicked() {
+                    actions.push(DebugAction::SetCameraMode(CameraMode::Orbit));
+                }
+                if ui.button("First person").clicked() {
+                    actions.push(DebugAction::SetCameraMode(CameraMode::FirstPerson));
+                }
+            });
+            let mut two_dimensional = renderer.is_2d();
+            if ui
+                .checkbox(&mut two_dimensional, "2D (F2)")
+                .on_hover_text("Pans and zooms sprites in world units mapped to pixels")
+                .changed()
+            {
+                renderer.set_2d(two_dimensional);
+            }
+            if two_dimensional {
+                ui.add(
+                    egui::Slider::new(&mut renderer.camera_2d.zoom, MIN_ZOOM..=MAX_ZOOM)
+                        .logarithmic(true)
+                        .text("Pixels per unit"),
+                );
+                ui.checkbox(&mut renderer.camera_2d.pixel_snap, "Snap to pixels");
+                if ui.button("Reset view").clicked() {
+                    renderer.camera_2d = Camera2d::default();
+                }
+            }
+            layer_settings(ui, "Visible layers", &mut renderer.camera.layers);
+            ui.add(
+                egui::Slider::new(&mut renderer.settings.rebase_distance, 0.0..=10000.0)
+                    .text("Rebase distance"),
+            )
+            .on_hover_text("Moves the scene's origin to the camera past this distance");
+            ui.label(format!(
+                "Origin: {:.2}, {:.2}, {:.2}",
+                scene.origin.x, scene.origin.y, scene.origin.z
+            ));
+            if ui
+                .button("Focus (F)")
+                .on_hover_text("Frames the selection, or the whole scene")
+                .clicked()
+            {
+                actions.push(DebugAction::Focus);
+            }
+            bookmark_settings(ui, renderer, scene, &mut actions);
+            if ui.button("Grab cursor").clicked() {
+                actions.push(DebugAction::GrabCursor);
+            }
+            ui.label("Tab toggles the cursor grab, Escape releases it");
+            if ui.button("Open window").clicked() {
+                actions.push(DebugAction::OpenWindow);
+            }
+        });
+        egui::CollapsingHeader::new("Import And Export").show(ui, |ui| {
+            import_settings(ui, &mut renderer.settings.import);
+            ui.add(
+                egui::Slider::new(&mut scene.unit_scale, 0.001..=1000.0)
+                    .logarithmic(true)
+                    .text("Scene unit scale"),
+            )
+            .on_hover_text("Scene units per unit of opened files");
+            if ui
+                .button("Export scene")
+                .on_hover_text("Saves the models to a numbered .glb in exports")
+                .clicked()
+            {
+                actions.push(DebugAction::ExportScene);
+            }
+        });
+        egui::CollapsingHeader::new("Recording").show(ui, |ui| {
+            let label = if renderer.recording.is_recording() {
+                "Stop recording (F10)"
+            } else {
+                "Start recording (F10)"
+            };
+            if ui.button(label).clicked() {
+                actions.push(DebugAction::ToggleRecording);
+            }
+            ui.label(format!("Frames: {}", renderer.recording.frames()));
+            recording_settings(ui, &mut renderer.settings.recording);
+        });
+        egui::CollapsingHeader::new("Camera Path").show(ui, |ui| {
+            ui.horizontal(|ui| {
+                if ui.button("Play").clicked() {
+                    actions.push(DebugAction::PlayPath);
+                }
+                if ui
+                    .button("Record")
+                    .on_hover_text("Plays the path from the start while recording it")
+                    .clicked()
+                {
+                    actions.push(DebugAction::RecordPath);
+                }
+            });
+            ui.label(format!("Time: {:.2}s", scripted_camera.time()));
+            camera_path_settings(ui, scripted_camera, &renderer.camera);
+        });
+        egui::CollapsingHeader::new("Viewports").show(ui, |ui| {
+            ui.horizontal(|ui| {
+                if ui.button("Single").clicked() {
+                    renderer.viewports.clear();
+                }
+                if ui.button("Split").clicked() {
+                    renderer.viewports = Viewport::split_screen(&renderer.camera, 2);
+                }
+                if ui.button("Quad").clicked() {
+                    renderer.viewports = Viewport::quad();
+                }
+            });
+            for (index, viewport) in renderer.viewports.iter_mut().enumerate() {
+                egui::ComboBox::from_label(format!("Viewport {}", index + 1))
+                    .selected_text(format!("{:?}", viewport.view_mode))
+                    .show_ui(ui, |ui| {
+                        for view_mode in [
+                            ViewMode::Perspective,
+                            ViewMode::Top,
+                            ViewMode::Front,
+                            ViewMode::Side,
+                        ] {
+                            ui.selectable_value(
+                                &mut viewport.view_mode,
+                                view_mode,
+                                format!("{:?}", view_mode),
+                            );
+                        }
+                    });
+            }
+        });
+        egui::CollapsingHeader::new("Picking").show(ui, |ui| match renderer.selection {
+            Some(selection) => {
+                let name = scene
+                    .models
+                    .get(selection.model.0)
+                    .and_then(|model| model.name.clone())
+                    .unwrap_or_else(|| format!("Model {}", selection.model.0));
+                ui.label(format!(
+                    "Selected: {}, instance {}",
+                    name, selection.instance
+                ));
+                ui.label(format!(
+                    "Position: {:.2}, {:.2}, {:.2}",
+                    selection.position.x, selection.position.y, selection.position.z
+                ));
+                ui.label(format!("Distance: {:.2}", selection.distance));
+                ui.horizontal(|ui| {
+                    if ui.button("Follow").clicked() {
+                        actions.push(DebugAction::Follow(selection.model));
+                    }
+                    if ui.button("Stop following").clicked() {
+                        actions.push(DebugAction::StopFollowing);
+                    }
+                });
+            }
+            None => {
+                ui.label("Click a model to select it");
+            }
+        });
+        egui::CollapsingHeader::new("Skeletons").show(ui, |ui| {
+            ui.checkbox(&mut renderer.settings.skeletons, "Show bones");
+            ui.add_enabled(
+                renderer.settings.skeletons,
+                egui::Checkbox::new(&mut renderer.settings.joint_names, "Show joint names"),
+            );
+        });
+        #[cfg(feature = "physics")]
+        egui::CollapsingHeader::new("Physics").show(ui, |ui| {
+            ui.checkbox(&mut renderer.settings.physics_colliders, "Show colliders");
+        });
+        #[cfg(feature = "gamepad")]
+        egui::CollapsingHeader::new("Gamepad").show(ui, |ui| {
+            ui.add(
+                egui::Slider::new(&mut renderer.settings.gamepad_dead_zone, 0.0..=0.9)
+                    .text("Dead zone"),
+            );
+        });
+        egui::CollapsingHeader::new("Path Tracing").show(ui, |ui| {
+            ui.checkbox(&mut renderer.settings.path_tracing, "Enabled");
+        });
+        egui::CollapsingHeader::new("Static Batching").show(ui, |ui| {
+            ui.checkbox(
+                &mut renderer.settings.static_batching,
+                "Merge static meshes",
+            );
+            ui.checkbox(
+                &mut renderer.settings.rebuild_static_batches,
+                "Rebuild when objects move",
+            );
+            if ui.button("Rebuild").clicked() {
+                renderer.rebuild_static_batches();
+            }
+            if renderer.settings.static_batching {
+                let (merged, unmerged) = renderer.static_batch_draws();
+                ui.label(format!("{} draws per pass instead of {}", merged, unmerged));
+            }
+        });
+        egui::CollapsingHeader::new("Occlusion Culling").show(ui, |ui| {
+            ui.checkbox(
+                &mut renderer.settings.occlusion_culling,
+                "Skip models hidden behind others",
+            );
+        });
+        egui::CollapsingHeader::new("Materials").show(ui, |ui| {
+            ui.checkbox(
+                &mut renderer.settings.pack_materials,
+                "Pack textures into arrays",
+            );
+            if ui
+                .button("Reload shaders")
+                .on_hover_text("Material shaders are read from their files again")
+                .clicked()
+            {
+                renderer.reload_material_shaders();
+            }
+            egui::ComboBox::from_label("Face culling")
+                .selected_text(format!("{:?}", renderer.settings.cull_mode))
+                .show_ui(ui, |ui| {
+                    for cull_mode in [
+                        CullMode::Material,
+                        CullMode::None,
+                        CullMode::Front,
+                        CullMode::Back,
+                    ] {
+                        ui.selectable_value(
+                            &mut renderer.settings.cull_mode,
+                            cull_mode,
+                            format!("{:?}", cull_mode),
+                        );
+                    }
+                });
+            for model_index in 0..scene.models.len() {
+                let model = &scene.models[model_index];
+                let name = model
+                    .name
+                    .clone()
+                    .unwrap_or_else(|| format!("Model {}", model_index));
+                let materials = model.materials.len();
+                ui.collapsing(name, |ui| {
+                    for material in 0..materials {
+                        let handle = MaterialHandle {
+                            model: ModelHandle(model_index),
+                            material,
+                        };
+                        material_editor(ui, scene, handle);
+                    }
+                });
+            }
+        });
+        egui::CollapsingHeader::new("Adapters")
+            .default_open(true)
+            .show(ui, |ui| {
+                for (index, adapter) in renderer.adapters().iter().enumerate() {
+                    if adapter_details(ui, adapter) {
+                        actions.push(DebugAction::SwitchAdapter(index));
+                    }
+                }
+            });
+    });
+    actions
+}
+
+fn performance_settings(ui: &mut egui::Ui, settings: &mut Settings) {
+    ui.checkbox(&mut settings.power_saving, "Save power on battery");
+    ui.checkbox(&mut settings.vsync, "Vsync");
+    egui::ComboBox::from_label("Multisampling, on restart")
+        .selected_text(format!("{}x", settings.msaa_samples))
+        .show_ui(ui, |ui| {
+            for samples in [1, 4] {
+                ui.selectable_value(&mut settings.msaa_samples, samples, format!("{}x", samples));
+            }
+        });
+    ui.checkbox(&mut settings.stats_overlay, "Stats overlay");
+    ui.add(
+        egui::Slider::new(&mut settings.frame_rate_limit, 0.0..=240.0)
+            .text("Frame rate limit")
+            .suffix(" fps"),
+    );
+    ui.add(
+        egui::Slider::new(&mut settings.memory_budget, 0.0..=16384.0)
+            .text("Memory budget")
+            .suffix(" MB"),
+    );
+    ui.add(
+        egui::Slider::new(
+            &mut settings.render_scale,
+            MIN_RENDER_SCALE..=MAX_RENDER_SCALE,
+        )
+        .text("Render scale"),
+    );
+    egui::ComboBox::from_label("Upscaling")
+        .selected_text(format!("{:?}", settings.upscaling))
+        .show_ui(ui, |ui| {
+            for upscaling in [Upscaling::Bilinear, Upscaling::Fsr, Upscaling::Temporal] {
+                ui.selectable_value(
+                    &mut settings.upscaling,
+                    upscaling,
+                    format!("{:?}", upscaling),
+                );
+            }
+        });
+    let dynamic_resolution = &mut settings.dynamic_resolution;
+    ui.checkbox(&mut dynamic_resolution.enabled, "Dynamic resolution");
+    ui.add(
+        egui::Slider::new(&mut dynamic_resolution.target_frame_time, 4.0..=50.0)
+            .text("Target frame time")
+            .suffix(" ms"),
+    );
+    ui.add(
+        egui::Slider::new(
+            &mut dynamic_resolution.min_scale,
+            MIN_RENDER_SCALE..=MAX_RENDER_SCALE,
+        )
+        .text("Min scale"),
+    );
+    ui.add(
+        egui::Slider::new(
+            &mut dynamic_resolution.max_scale,
+            MIN_RENDER_SCALE..=MAX_RENDER_SCALE,
+        )
+        .text("Max scale"),
+    );
+    egui::ComboBox::from_label("Effect quality")
+        .selected_text(format!("{:?}", settings.effect_quality))
+        .show_ui(ui, |ui| {
+            for quality in [
+                EffectQuality::Low,
+                EffectQuality::Medium,
+                EffectQuality::High,
+            ] {
+                ui.selectable_value(
+                    &mut settings.effect_quality,
+                    quality,
+                    format!("{:?}", quality),
+                );
+            }
+        });
+}
+
+fn profiler_details(ui: &mut egui::Ui, budgets: &BudgetMonitor, gpu_timing_supported: bool) {
+    ui.label(format!(
+        "Frame: {:.2}ms, CPU: {:.2}ms",
+        milliseconds(budgets.frame_time()),
+        milliseconds(budgets.cpu_time())
+    ));
+    if gpu_timing_supported {
+        ui.collapsing("GPU passes", |ui| {
+            for pass in budgets.gpu_timings() {
+                ui.small(format!(
+                    "{}: {:.2}ms",
+                    pass.label,
+                    milliseconds(pass.duration)
+                ));
+            }
+        });
+    } else {
+        ui.label("GPU timings are unsupported by this adapter");
+    }
+    ui.collapsing("Budgets", |ui| {
+        for budget in budgets.budgets() {
+            ui.small(format!(
+                "{}: {:.2}ms",
+                budget.target,
+                milliseconds(budget.limit)
+            ));
+        }
+    });
+    ui.collapsing("Recent alerts", |ui| {
+        for alert in budgets.recent_alerts() {
+            ui.small(alert.to_string());
+        }
+    });
+}
+
+fn physical_camera_settings(ui: &mut egui::Ui, camera: &mut Camera) {
+    let mut enabled = camera.physical.is_some();
+    ui.checkbox(&mut enabled, "Physical camera");
+    if enabled != camera.physical.is_some() {
+        camera.physical = enabled.then(PhysicalCamera::default);
+    }
+    let physical = match camera.physical.as_mut() {
+        Some(physical) => physical,
+        None => return,
+    };
+    ui.add(
+        egui::Slider::new(&mut physical.aperture, 1.0..=32.0)
+            .logarithmic(true)
+            .text("Aperture (f-number)"),
+    );
+    ui.add(
+        egui::Slider::new(&mut physical.shutter_speed, 1.0 / 8000.0..=30.0)
+            .logarithmic(true)
+            .text("Shutter speed (s)"),
+    );
+    ui.add(
+        egui::Slider::new(&mut physical.iso, 50.0..=51200.0)
+            .logarithmic(true)
+            .text("ISO"),
+    );
+    let mut focal_length = physical.focal_length.is_some();
+    ui.checkbox(&mut focal_length, "Field of view from focal length");
+    if focal_length != physical.focal_length.is_some() {
+        physical.focal_length = focal_length.then_some(50.0);
+    }
+    if let Some(focal_length) = physical.focal_length.as_mut() {
+        ui.add(
+            egui::Slider::new(focal_length, 8.0..=400.0)
+                .logarithmic(true)
+                .text("Focal length (mm)"),
+        );
+        ui.add(
+            egui::Slider::new(&mut physical.sensor_height, 4.0..=56.0).text("Sensor height (mm)"),
+        );
+    }
+    ui.label(format!(
+        "EV100: {:.2}, exposure: {:.4}",
+        physical.ev100(),
+        physical.exposure()
+    ));
+}
+
+fn exposure_settings(ui: &mut egui::Ui, settings: &mut Settings) {
+    ui.add(
+        egui::Slider::new(&mut settings.exposure, 0.01..=16.0)
+            .logarithmic(true)
+            .text("Exposure"),
+    );
+    let auto_exposure = &mut settings.auto_exposure;
+    ui.checkbox(&mut auto_exposure.enabled, "Automatic exposure");
+    ui.add(egui::Slider::new(&mut auto_exposure.min_ev, -16.0..=16.0).text("Min EV"));
+    ui.add(egui::Slider::new(&mut auto_exposure.max_ev, -16.0..=16.0).text("Max EV"));
+    ui.add(egui::Slider::new(&mut auto_exposure.speed_up, 0.0..=10.0).text("Speed up"));
+    ui.add(egui::Slider::new(&mut auto_exposure.speed_down, 0.0..=10.0).text("Speed down"));
+    ui.add(egui::Slider::new(&mut auto_exposure.compensation, -5.0..=5.0).text("Compensation"));
+    egui::ComboBox::from_label("Tone mapping")
+        .selected_text(format!("{:?}", settings.tone_mapping))
+        .show_ui(ui, |ui| {
+            for tone_mapping in [ToneMapping::None, ToneMapping::Reinhard, ToneMapping::Aces] {
+                ui.selectable_value(
+                    &mut settings.tone_mapping,
+                    tone_mapping,
+                    format!("{:?}", tone_mapping),
+                );
+            }
+        });
+}
+
+fn bloom_settings(ui: &mut egui::Ui, bloom: &mut BloomSettings) {
+    ui.checkbox(&mut bloom.enabled, "Enabled");
+    ui.add(egui::Slider::new(&mut bloom.threshold, 0.0..=8.0).text("Threshold"));
+    ui.add(egui::Slider::new(&mut bloom.knee, 0.0..=2.0).text("Knee"));
+    ui.add(egui::Slider::new(&mut bloom.intensity, 0.0..=1.0).text("Intensity"));
+    ui.add(egui::Slider::new(&mut bloom.radius, 0.0..=4.0).text("Radius"));
+}
+
+fn depth_of_field_settings(ui: &mut egui::Ui, depth_of_field: &mut DepthOfFieldSettings) {
+    ui.checkbox(&mut depth_of_field.enabled, "Enabled");
+    ui.add(
+        egui::Slider::new(&mut depth_of_field.focus_distance, 0.1..=1000.0)
+            .logarithmic(true)
+            .text("Focus distance"),
+    );
+    ui.add(egui::Slider::new(&mut depth_of_field.aperture, 0.0..=32.0).text("Aperture"));
+    ui.add(egui::Slider::new(&mut depth_of_field.max_radius, 0.0..=32.0).text("Max radius"));
+    ui.checkbox(&mut depth_of_field.click_to_focus, "Click to focus");
+}
+
+fn motion_blur_settings(ui: &mut egui::Ui, motion_blur: &mut MotionBlurSettings) {
+    ui.checkbox(&mut motion_blur.enabled, "Enabled");
+    ui.add(egui::Slider::new(&mut motion_blur.samples, 2..=16).text("Samples"));
+    ui.add(egui::Slider::new(&mut motion_blur.shutter_angle, 0.0..=360.0).text("Shutter angle"));
+}
+
+fn imperfection_settings(ui: &mut egui::Ui, imperfections: &mut CameraImperfectionSettings) {
+    ui.add(egui::Slider::new(&mut imperfections.vignette, 0.0..=1.0).text("Vignette"));
+    ui.add(
+        egui::Slider::new(&mut imperfections.chromatic_aberration, 0.0..=1.0)
+            .text("Chromatic aberration"),
+    );
+    ui.add(egui::Slider::new(&mut imperfections.film_grain, 0.0..=1.0).text("Film grain"));
+}
+
+fn sharpen_settings(ui: &mut egui::Ui, sharpen: &mut SharpenSettings) {
+    egui::ComboBox::from_label("Mode")
+        .selected_text(format!("{:?}", sharpen.mode))
+        .show_ui(ui, |ui| {
+            for mode in [
+                SharpenMode::None,
+                SharpenMode::Unsharp,
+                SharpenMode::ContrastAdaptive,
+            ] {
+                ui.selectable_value(&mut sharpen.mode, mode, format!("{:?}", mode));
+            }
+        });
+    ui.add(egui::Slider::new(&mut sharpen.strength, 0.0..=2.0).text("Strength"));
+}
+
+fn sky_settings(ui: &mut egui::Ui, sky: &mut SkySettings) {
+    ui.checkbox(&mut sky.enabled, "Enabled");
+    ui.add(egui::Slider::new(&mut sky.time_of_day, 0.0..=24.0).text("Time of day"));
+    ui.add(egui::Slider::new(&mut sky.latitude, -90.0..=90.0).text("Latitude"));
+    ui.add(egui::Slider::new(&mut sky.day_of_year, 1..=365).text("Day of year"));
+    ui.add(egui::Slider::new(&mut sky.turbidity, 2.0..=10.0).text("Turbidity"));
+    ui.add(egui::Slider::new(&mut sky.sun_intensity, 0.0..=10.0).text("Sun intensity"));
+    ui.add(egui::Slider::new(&mut sky.cloud_coverage, 0.0..=1.0).text("Cloud coverage"));
+    ui.add(egui::Slider::new(&mut sky.cloud_speed, -0.2..=0.2).text("Cloud speed"));
+    ui.add(
+        egui::Slider::new(&mut sky.lighting_interval, 0.0..=120.0)
+            .text("Seconds between lighting updates"),
+    );
+    ui.add(
+        egui::Slider::new(&mut sky.environment_intensity, 0.0..=10.0).text("Environment intensity"),
+    );
+    ui.add(
+        egui::Slider::new(&mut sky.environment_rotation, 0.0..=360.0).text("Environment rotation"),
+    );
+}
+
+fn environment_settings(ui: &mut egui::Ui, environment: &mut Option<std::path::PathBuf>) {
+    ui.horizontal(|ui| {
+        match environment.as_ref() {
+            Some(path) => ui.label(format!("Environment: {}", path.display())),
+            None => ui.label("Environment: none, drop an .hdr or .exr image to open one"),
+        };
+        if environment.is_some() && ui.button("Clear").clicked() {
+            *environment = None;
+        }
+    });
+}
+
+fn import_settings(ui: &mut egui::Ui, import: &mut ImportSettings) {
+    ui.checkbox(&mut import.recenter, "Recenter at origin");
+    ui.checkbox(&mut import.normalize, "Normalize size");
+    ui.add_enabled(
+        import.normalize,
+        egui::Slider::new(&mut import.target_size, 0.01..=100.0)
+            .logarithmic(true)
+            .text("Target size"),
+    );
+    ui.checkbox(&mut import.hot_reload, "Reload edited textures")
+        .on_hover_text("Images are reloaded when their files change on disk");
+}
+
+fn recording_settings(ui: &mut egui::Ui, recording: &mut RecordingSettings) {
+    ui.checkbox(&mut recording.ffmpeg, "Video through ffmpeg");
+    ui.add(egui::Slider::new(&mut recording.frame_rate, 1.0..=120.0).text("Frame rate"));
+    ui.checkbox(&mut recording.offline, "Offline")
+        .on_hover_text("Waits for every frame to be written, however long it takes");
+}
+
+fn bookmark_settings(
+    ui: &mut egui::Ui,
+    renderer: &mut Renderer,
+    scene: &Scene,
+    actions: &mut Vec<DebugAction>,
+) {
+    ui.label("Bookmarks, Ctrl and a number key saves, the number key goes to it");
+    egui::Grid::new("bookmarks").show(ui, |ui| {
+        for slot in 0..BOOKMARK_SLOTS {
+            ui.label(format!("{}", slot + 1));
+            if ui.button("Save").clicked() {
+                renderer.settings.camera_bookmarks[slot] =
+                    Some(CameraBookmark::of(&renderer.camera, scene));
+            }
+            let saved = renderer.settings.camera_bookmarks[slot].is_some();
+            if ui.add_enabled(saved, egui::Button::new("Go")).clicked() {
+                actions.push(DebugAction::GoToBookmark(slot));
+            }
+            if ui.add_enabled(saved, egui::Button::new("Clear")).clicked() {
+                renderer.settings.camera_bookmarks[slot] = None;
+            }
+            ui.end_row();
+        }
+    });
+    ui.add(
+        egui::Slider::new(&mut renderer.settings.camera_transition, 0.0..=5.0)
+            .text("Transition")
+            .suffix("s"),
+    );
+}
+
+fn camera_path_settings(ui: &mut egui::Ui, scripted_camera: &mut ScriptedCamera, camera: &Camera) {
+    egui::ComboBox::from_label("Path")
+        .selected_text(format!("{:?}", scripted_camera.kind))
+        .show_ui(ui, |ui| {
+            for kind in [CameraPathKind::Turntable, CameraPathKind::Spline] {
+                ui.selectable_value(&mut scripted_camera.kind, kind, format!("{:?}", kind));
+            }
+        });
+    ui.add(
+        egui::Slider::new(&mut scripted_camera.duration, 1.0..=120.0)
+            .text("Duration")
+            .suffix("s"),
+    );
+    ui.checkbox(&mut scripted_camera.looping, "Loop");
+    match scripted_camera.kind {
+        CameraPathKind::Turntable => {
+            ui.add(
+                egui::Slider::new(&mut scripted_camera.elevation, -89.0..=89.0)
+                    .text("Elevation")
+                    .suffix("°"),
+            );
+        }
+        CameraPathKind::Spline => {
+            ui.label(format!("Keyframes: {}", scripted_camera.keyframes.len()));
+            ui.horizontal(|ui| {
+                if ui.button("Add keyframe").clicked() {
+                    scripted_camera.add_keyframe(camera);
+                }
+                if ui.button("Clear").clicked() {
+                    scripted_camera.keyframes.clear();
+                }
+            });
+        }
+    }
+}
+
+fn volumetric_settings(ui: &mut egui::Ui, volumetric: &mut VolumetricSettings) {
+    ui.checkbox(&mut volumetric.enabled, "Enabled");
+    ui.add(egui::Slider::new(&mut volumetric.density, 0.0..=0.2).text("Density"));
+    ui.add(egui::Slider::new(&mut volumetric.anisotropy, -0.99..=0.99).text("Anisotropy"));
+    ui.add(egui::Slider::new(&mut volumetric.samples, 1..=128).text("Samples"));
+    ui.add(egui::Slider::new(&mut volumetric.max_distance, 1.0..=1000.0).text("Max distance"));
+}
+
+fn ssao_settings(ui: &mut egui::Ui, ssao: &mut SsaoSettings) {
+    ui.checkbox(&mut ssao.enabled, "Screen space");
+    ui.add(egui::Slider::new(&mut ssao.radius, 0.05..=4.0).text("Radius"));
+    ui.add(egui::Slider::new(&mut ssao.bias, 0.0..=0.2).text("Bias"));
+    ui.add(egui::Slider::new(&mut ssao.intensity, 0.0..=4.0).text("Intensity"));
+    ui.add(egui::Slider::new(&mut ssao.samples, 1..=32).text("Samples"));
+}
+
+fn ray_traced_shadow_settings(ui: &mut egui::Ui, shadows: &mut RayTracedShadowSettings) {
+    ui.checkbox(&mut shadows.enabled, "Enabled");
+    ui.checkbox(&mut shadows.half_resolution, "Half resolution");
+    ui.add(egui::Slider::new(&mut shadows.softness, 0.0..=10.0).text("Softness"));
+}
+
+// The named layers are always shown, the rest are folded away
+// Shown while shaders or pipelines have failed, instead of aborting on them
+pub fn shader_error_window(context: &egui::CtxRef, renderer: &mut Renderer) {
+    if renderer.shader_errors.errors().is_empty() {
+        return;
+    }
+    egui::Window::new("Shader Errors").show(context, |ui| {
+        egui::ScrollArea::vertical().show(ui, |ui| {
+            for error in renderer.shader_errors.errors() {
+                ui.colored_label(egui::Color32::LIGHT_RED, error.to_string());
+                ui.separator();
+            }
+        });
+        ui.horizontal(|ui| {
+            if ui.button("Reload shaders").clicked() {
+                renderer.reload_material_shaders();
+            }
+            if ui.button("Dismiss").clicked() {
+                renderer.shader_errors.clear();
+            }
+        });
+    });
+}
+
+pub fn layer_settings(ui: &mut egui::Ui, label: &str, mask: &mut u32) {
+    ui.label(label);
+    ui.horizontal(|ui| {
+        for layer in 0..3 {
+            layer_checkbox(ui, mask, layer);
+        }
+    });
+    egui::CollapsingHeader::new("More layers")
+        .id_source(label)
+        .show(ui, |ui| {
+            egui::Grid::new(label).num_columns(4).show(ui, |ui| {
+                for layer in 3..layers::COUNT {
+                    layer_checkbox(ui, mask, layer);
+                    if layer % 4 == 2 {
+                        ui.end_row();
+                    }
+                }
+            });
+            ui.horizontal(|ui| {
+                if ui.button("All").clicked() {
+                    *mask = layers::ALL;
+                }
+                if ui.button("None").clicked() {
+                    *mask = 0;
+                }
+            });
+        });
+}
+
+fn layer_checkbox(ui: &mut egui::Ui, mask: &mut u32, layer: usize) {
+    let bit = 1 << layer;
+    let mut enabled = *mask & bit != 0;
+    if ui.checkbox(&mut enabled, layers::name(layer)).changed() {
+        *mask ^= bit;
+    }
+}
+
+// Edits a copy of the material, so it's only uploaded again when something changed
+pub fn material_editor(ui: &mut egui::Ui, scene: &mut Scene, handle: MaterialHandle) {
+    let images = match scene.models.get(handle.model.0) {
+        Some(model) => model.images.len(),
+        None => return,
+    };
+    let mut material = match scene.material(handle) {
+        Some(material) => material.clone(),
+        None => return,
+    };
+    material_settings(ui, handle, images, &mut material);
+    if scene.material(handle) != Some(&material) {
+        if let Some(edited) = scene.materials().get_mut(handle) {
+            *edited = material;
+        }
+    }
+    // Generated images are added to the model, where any of its materials' textures can use them
+    let mut generated = None;
+    egui::ComboBox::from_id_source(("generate_texture", handle))
+        .selected_text("Add generated image")
+        .show_ui(ui, |ui| {
+            for procedural in ProceduralTexture::ALL {
+                if ui.selectable_label(false, procedural.name()).clicked() {
+                    generated = Some(procedural);
+                }
+            }
+        });
+    if let Some(procedural) = generated {
+        scene.models[handle.model.0]
+            .images
+            .push(procedural.generate(GENERATED_TEXTURE_SIZE));
+        scene.invalidate_models();
+    }
+}
+
+fn material_settings(
+    ui: &mut egui::Ui,
+    handle: MaterialHandle,
+    images: usize,
+    material: &mut Material,
+) {
+    let name = material
+        .name
+        .clone()
+        .unwrap_or_else(|| format!("Material {}", handle.material));
+    ui.group(|ui| {
+        ui.strong(name);
+        let mut base_color: [f32; 4] = material.base_color_factor.into();
+        ui.horizontal(|ui| {
+            ui.color_edit_button_rgba_unmultiplied(&mut base_color);
+            ui.label("Base color");
+        });
+        material.base_color_factor = base_color.into();
+        ui.add(egui::Slider::new(&mut material.metallic_factor, 0.0..=1.0).text("Metallic"));
+        ui.add(egui::Slider::new(&mut material.roughness_factor, 0.0..=1.0).text("Roughness"));
+        let mut emissive_color: [f32; 3] = material.emissive_factor.into();
+        ui.horizontal(|ui| {
+            ui.color_edit_button_rgb(&mut emissive_color);
+            ui.label("Emissive color");
+        });
+        material.emissive_factor = emissive_color.into();
+        if material.occlusion_texture.is_some() {
+            ui.add(
+                egui::Slider::new(&mut material.occlusion_strength, 0.0..=1.0)
+                    .text("Baked occlusion"),
+            );
+        }
+        if material.lightmap_texture.is_some() {
+            ui.add(egui::Slider::new(&mut material.lightmap_intensity, 0.0..=8.0).text("Lightmap"));
+        }
+        ui.checkbox(&mut material.double_sided, "Double sided");
+        ui.add(egui::Slider::new(&mut material.ssao_strength, 0.0..=1.0).text("SSAO strength"));
+        ui.add(
+            egui::Slider::new(&mut material.emissive_luminance, 0.0..=100_000.0)
+                .logarithmic(true)
+                .text("Emissive")
+                .suffix(" nits"),
+        );
+        egui::ComboBox::from_id_source(("occlusion_blend", handle))
+            .selected_text(format!("{:?}", material.occlusion_blend))
+            .show_ui(ui, |ui| {
+                for blend in [OcclusionBlend::Multiply, OcclusionBlend::Min] {
+                    ui.selectable_value(
+                        &mut material.occlusion_blend,
+                        blend,
+                        format!("{:?}", blend),
+                    );
+                }
+            });
+        egui::ComboBox::from_id_source(("alpha_mode", handle))
+            .selected_text(format!("{:?}", material.alpha_mode))
+            .show_ui(ui, |ui| {
+                for mode in [
+                    AlphaMode::Opaque,
+                    AlphaMode::Mask,
+                    AlphaMode::Blend,
+                    AlphaMode::Dithered,
+                ] {
+                    ui.selectable_value(&mut material.alpha_mode, mode, format!("{:?}", mode));
+                }
+            });
+        if material.alpha_mode == AlphaMode::Mask {
+            ui.add(egui::Slider::new(&mut material.alpha_cutoff, 0.0..=1.0).text("Alpha cutoff"));
+        }
+        egui::ComboBox::from_id_source(("shading_model", handle))
+            .selected_text(format!("{:?}", material.shading_model))
+            .show_ui(ui, |ui| {
+                for model in [ShadingModel::Pbr, ShadingModel::Unlit, ShadingModel::Toon] {
+                    ui.selectable_value(&mut material.shading_model, model, format!("{:?}", model));
+                }
+            });
+        if material.shading_model == ShadingModel::Toon {
+            ui.add(egui::Slider::new(&mut material.toon_bands, 1..=8).text("Bands"));
+        }
+        ui.add(
+            egui::Slider::new(&mut material.outline_width, 0.0..=8.0)
+                .text("Outline")
+                .suffix(" px"),
+        );
+        if material.has_outline() {
+            let mut outline_color: [f32; 3] = material.outline_color.into();
+            ui.horizontal(|ui| {
+                ui.color_edit_button_rgb(&mut outline_color);
+                ui.label("Outline color");
+            });
+            material.outline_color = outline_color.into();
+        }
+        // Shaders are set by opening a WGSL file with the model selected
+        if let Some(shader) = material.shader.clone() {
+            ui.horizontal(|ui| {
+                let name = shader.file_name().unwrap_or_default().to_string_lossy();
+                ui.label(format!("Shader: {}", name))
+                    .on_hover_text(shader.display().to_string());
+                if ui.button("Built in").clicked() {
+                    material.shader = None;
+                }
+            });
+        }
+        ui.collapsing("Textures", |ui| {
+            egui::Grid::new(("material_textures", handle)).show(ui, |ui| {
+                for (slot, (name, texture)) in MATERIAL_TEXTURE_NAMES
+                    .into_iter()
+                    .zip(material.textures_mut())
+                    .enumerate()
+                {
+                    ui.label(name);
+                    let selected = match texture {
+                        Some(image) => format!("Image {}", image),
+                        None => "None".to_string(),
+                    };
+                    egui::ComboBox::from_id_source(("material_texture", handle, slot))
+                        .selected_text(selected)
+                        .show_ui(ui, |ui| {
+                            ui.selectable_value(texture, None, "None");
+                            for image in 0..images {
+                                ui.selectable_value(
+                                    texture,
+                                    Some(image),
+                                    format!("Image {}", image),
+                                );
+                            }
+                        });
+                    ui.end_row();
+                }
+            });
+        });
+    });
+}
+
+// Returns true when the adapter was selected
+fn adapter_details(ui: &mut egui::Ui, adapter: &AdapterDetails) -> bool {
+    let info = &adapter.info;
+    let mut selected = false;
+    ui.group(|ui| {
+        ui.horizontal(|ui| {
+            ui.strong(&info.name);
+            if adapter.active {
+                ui.label("(active)");
+            } else {
+                selected = ui
+                    .add_enabled(adapter.supports_surface, egui::Button::new("Use"))
+                    .on_disabled_hover_text("This adapter can't present to the window")
+                    .clicked();
+            }
+        });
+        ui.label(format!("{:?} via {:?}", info.device_type, info.backend));
+        ui.label(format!(
+            "Vendor 0x{:04x}, device 0x{:04x}",
+            info.vendor, info.device
+        ));
+        ui.label(format!(
+            "Max texture size: {}",
+            adapter.limits.max_texture_dimension_2d
+        ));
+        ui.label(format!(
+            "Max storage buffer binding: {} MiB",
+            adapter.limits.max_storage_buffer_binding_size / (1024 * 1024)
+        ));
+        ui.collapsing("Features", |ui| {
+            if adapter.features.is_empty() {
+                ui.label("None");
+                return;
+            }
+            for feature in format!("{:?}", adapter.features).split(" | ") {
+                ui.small(feature);
+            }
+        });
+    });
+    selected
+}
@@ -0,0 +1,398 @@
+use anyhow::Result;
+use nalgebra_glm as glm;
+
+use crate::{
+    memory::{self, MemoryCategory, Tracked},
+    scene::Scene,
+    shader_snippets,
+    texture::Texture,
+};
+
+#[allow(dead_code)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DecalBlendMode {
+    AlphaBlended,
+    Additive,
+    Multiply,
+}
+
+#[derive(Clone)]
+pub struct DecalDesc {
+    // Maps a unit cube to the projection volume, the decal projects along its local -Y axis
+    pub transform: glm::Mat4,
+    pub albedo: image::DynamicImage,
+    pub normal_map: Option<image::DynamicImage>,
+    pub color: glm::Vec4,
+    pub opacity: f32,
+    pub normal_strength: f32,
+    // Surfaces whose normal is less aligned with the projection axis than this cosine are rejected
+    pub angle_threshold: f32,
+    pub blend_mode: DecalBlendMode,
+}
+
+impl Default for DecalDesc {
+    fn default() -> Self {
+        Self {
+            transform: glm::Mat4::identity(),
+            albedo: image::DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(
+                1,
+                1,
+                image::Rgba([255, 255, 255, 255]),
+            )),
+            normal_map: None,
+            color: glm::vec4(1.0, 1.0, 1.0, 1.0),
+            opacity: 1.0,
+            normal_strength: 1.0,
+            angle_threshold: 0.1,
+            blend_mode: DecalBlendMode::AlphaBlended,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Default, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct DecalUniform {
+    model: [[f32; 4]; 4],
+    inverse_model: [[f32; 4]; 4],
+    color: [f32; 4],
+    opacity: f32,
+    normal_strength: f32,
+    angle_threshold: f32,
+    blend_mode: u32,
+}
+
+impl DecalUniform {
+    // The transform is relative to the scene's origin
+    fn new(desc: &DecalDesc, transform: &glm::Mat4) -> Self {
+        Self {
+            model: (*transform).into(),
+            inverse_model: glm::inverse(transform).into(),
+            color: desc.color.into(),
+            opacity: desc.opacity,
+            normal_strength: desc.normal_strength,
+            angle_threshold: desc.angle_threshold,
+            blend_mode: match desc.blend_mode {
+                DecalBlendMode::AlphaBlended => 0,
+                DecalBlendMode::Additive => 1,
+                DecalBlendMode::Multiply => 2,
+            },
+        }
+    }
+}
+
+struct GpuDecal {
+    uniform_buffer: Tracked<wgpu::Buffer>,
+    bind_group: wgpu::BindGroup,
+    _albedo: Texture,
+    _normal_map: Texture,
+}
+
+pub struct DecalSystem {
+    decal_bind_group_layout: wgpu::BindGroupLayout,
+    depth_bind_group_layout: wgpu::BindGroupLayout,
+    depth_bind_group: wgpu::BindGroup,
+    alpha_blended_pipeline: wgpu::RenderPipeline,
+    additive_pipeline: wgpu::RenderPipeline,
+    multiply_pipeline: wgpu::RenderPipeline,
+    decals: Vec<GpuDecal>,
+}
+
+impl DecalSystem {
+    pub fn new(
+        device: &wgpu::Device,
+        camera_bind_group_layout: &wgpu::BindGroupLayout,
+        color_format: wgpu::TextureFormat,
+        depth_texture: &Texture,
+    ) -> Self {
+        let texture_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        };
+
+        let decal_bind_group_layout = memory::create_bind_group_layout(
+            device,
+            &wgpu::BindGroupLayoutDescriptor {
+                label: Some("Decal Bind Group Layout"),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    texture_entry(1),
+                    texture_entry(2),
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 3,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Sampler {
+                            filtering: true,
+                            comparison: false,
+                        },
+                        count: None,
+                    },
+                ],
+            },
+        );
+
+        let depth_bind_group_layout = memory::create_bind_group_layout(
+            device,
+            &wgpu::BindGroupLayoutDescriptor {
+                label: Some("Decal Depth Bind Group Layout"),
+                entries: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Depth,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                }],
+            },
+        );
+
+        let depth_bind_group =
+            Self::create_depth_bind_group(device, &depth_bind_group_layout, depth_texture);
+
+        let module = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("Decal Shader"),
+            source: wgpu::ShaderSource::Wgsl(
+                shader_snippets::splice(include_str!("shaders/decal.wgsl")).into(),
+            ),
+        });
+
+        let pipeline_layout = memory::create_pipeline_layout(
+            device,
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("Decal Pipeline Layout"),
+                bind_group_layouts: &[
+                    camera_bind_group_layout,
+                    &decal_bind_group_layout,
+                    &depth_bind_group_layout,
+                ],
+                push_constant_ranges: &[],
+            },
+        );
+
+        let create_pipeline = |label: &str, blend: wgpu::BlendState| {
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some(label),
+                layout: Some(&pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: &module,
+                    entry_point: "vs_main",
+                    buffers: &[],
+                },
+                // Only back faces are drawn so decals still apply with the camera inside the box
+                primitive: wgpu::PrimitiveState {
+                    topology: wgpu::PrimitiveTopology::TriangleList,
+                    front_face: wgpu::FrontFace::Ccw,
+                    cull_mode: Some(wgpu::Face::Front),
+                    ..Default::default()
+                },
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState::default(),
+                fragment: Some(wgpu::FragmentState {
+                    module: &module,
+                    entry_point: "fs_main",
+                    targets: &[wgpu::ColorTargetState {
+                        format: color_format,
+                        blend: Some(blend),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    }],
+                }),
+            })
+        };
+
+        let alpha_blended_pipeline = create_pipeline(
+            "Decal Alpha Blended Pipeline",
+            wgpu::BlendState::ALPHA_BLENDING,
+        );
+
+        let additive_pipeline = create_pipeline(
+            "Decal Additive Pipeline",
+            wgpu::BlendState {
+                color: wgpu::BlendComponent {
+                    src_factor: wgpu::BlendFactor::SrcAlpha,
+                    dst_factor: wgpu::BlendFactor::One,
+                    operation: wgpu::BlendOperation::Add,
+                },
+                alpha: wgpu::BlendComponent {
+                    src_factor: wgpu::BlendFactor::Zero,
+                    dst_factor: wgpu::BlendFactor::One,
+                    operation: wgpu::BlendOperation::Add,
+                },
+            },
+        );
+
+        let multiply_pipeline = create_pipeline(
+            "Decal Multiply Pipeline",
+            wgpu::BlendState {
+                color: wgpu::BlendComponent {
+                    src_factor: wgpu::BlendFactor::Dst,
+                    dst_factor: wgpu::BlendFactor::Zero,
+                    operation: wgpu::BlendOperation::Add,
+                },
+                alpha: wgpu::BlendComponent {
+                    src_factor: wgpu::BlendFactor::Zero,
+                    dst_factor: wgpu::BlendFactor::One,
+                    operation: wgpu::BlendOperation::Add,
+                },
+            },
+        );
+
+        Self {
+            decal_bind_group_layout,
+            depth_bind_group_layout,
+            depth_bind_group,
+            alpha_blended_pipeline,
+            additive_pipeline,
+            multiply_pipeline,
+            decals: Vec::new(),
+        }
+    }
+
+    fn create_depth_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        depth_texture: &Texture,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Decal Depth Bind Group"),
+            layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&depth_texture.view),
+            }],
+        })
+    }
+
+    pub fn resize(&mut self, device: &wgpu::Device, depth_texture: &Texture) {
+        self.depth_bind_group =
+            Self::create_depth_bind_group(device, &self.depth_bind_group_layout, depth_texture);
+    }
+
+    fn create_decal(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        desc: &DecalDesc,
+    ) -> Result<GpuDecal> {
+        let uniform_buffer = memory::create_buffer(
+            device,
+            MemoryCategory::Transient,
+            &wgpu::BufferDescriptor {
+                label: Some("Decal Uniform Buffer"),
+                size: std::mem::size_of::<DecalUniform>() as wgpu::BufferAddress,
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            },
+        );
+
+        let albedo =
+            Texture::from_image(device, queue, &desc.albedo, Some("Decal Albedo Texture"))?;
+
+        // A flat normal leaves the surface shading untouched
+        let normal_image = match desc.normal_map.as_ref() {
+            Some(normal_map) => normal_map.clone(),
+            None => image::DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(
+                1,
+                1,
+                image::Rgba([128, 128, 255, 255]),
+            )),
+        };
+        let normal_map = Texture::from_image_with_format(
+            device,
+            queue,
+            &normal_image,
+            wgpu::TextureFormat::Rgba8Unorm,
+            Some("Decal Normal Texture"),
+        )?;
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Decal Bind Group"),
+            layout: &self.decal_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&albedo.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(&normal_map.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::Sampler(&albedo.sampler),
+                },
+            ],
+        });
+
+        Ok(GpuDecal {
+            uniform_buffer,
+            bind_group,
+            _albedo: albedo,
+            _normal_map: normal_map,
+        })
+    }
+
+    pub fn update(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        scene: &Scene,
+    ) -> Result<()> {
+        while self.decals.len() < scene.decals.len() {
+            let decal = self.create_decal(device, queue, &scene.decals[self.decals.len()])?;
+            self.decals.push(decal);
+        }
+
+        for (desc, decal) in scene.decals.iter().zip(self.decals.iter()) {
+            queue.write_buffer(
+                &decal.uniform_buffer,
+                0,
+                bytemuck::cast_slice(&[DecalUniform::new(
+                    desc,
+                    &scene.local_transform(&desc.transform.cast()),
+                )]),
+            );
+        }
+
+        Ok(())
+    }
+
+    pub fn render<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        camera_bind_group: &'a wgpu::BindGroup,
+        scene: &Scene,
+    ) {
+        render_pass.set_bind_group(0, camera_bind_group, &[]);
+        render_pass.set_bind_group(2, &self.depth_bind_group, &[]);
+        for (desc, decal) in scene.decals.iter().zip(self.decals.iter()) {
+            let pipeline = match desc.blend_mode {
+                DecalBlendMode::AlphaBlended => &self.alpha_blended_pipeline,
+                DecalBlendMode::Additive => &self.additive_pipeline,
+                DecalBlendMode::Multiply => &self.multiply_pipeline,
+            };
+            render_pass.set_pipeline(pipeline);
+            render_pass.set_bind_group(1, &decal.bind_group, &[]);
+            render_pass.draw(0..36, 0..1);
+        }
+    }
+}
@@ -0,0 +1,408 @@
+use std::rc::Rc;
+
+use crate::{
+    memory::{self, MemoryCategory, Tracked},
+    render_targets::{FramePhase, RenderTargetPool},
+    settings::Settings,
+    shader_snippets,
+    texture::Texture,
+};
+
+#[repr(C)]
+#[derive(Default, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct DepthOfFieldUniform {
+    focus_distance: f32,
+    aperture: f32,
+    max_radius: f32,
+    samples: u32,
+}
+
+impl DepthOfFieldUniform {
+    fn new(settings: &Settings) -> Self {
+        let depth_of_field = &settings.depth_of_field;
+        Self {
+            focus_distance: depth_of_field.focus_distance.max(0.001),
+            aperture: depth_of_field.aperture,
+            max_radius: depth_of_field.max_radius,
+            samples: settings.effect_quality.depth_of_field_samples(),
+        }
+    }
+}
+
+// Targets that depend on the scene resolution
+struct DepthOfFieldTargets {
+    // Half resolution scene color with the signed circle of confusion in alpha
+    prefilter: Rc<Texture>,
+    far: Rc<Texture>,
+    near: Rc<Texture>,
+    output: Rc<Texture>,
+    prefilter_bind_group: wgpu::BindGroup,
+    gather_bind_group: wgpu::BindGroup,
+    composite_bind_group: wgpu::BindGroup,
+    dimensions: [u32; 2],
+}
+
+// Blurs the HDR scene color by each pixel's distance from the focus, gathering the far and near
+// fields separately at half resolution so the foreground can blur over in focus pixels
+pub struct DepthOfFieldSystem {
+    uniform_buffer: Tracked<wgpu::Buffer>,
+    enabled: bool,
+    source_layout: wgpu::BindGroupLayout,
+    composite_layout: wgpu::BindGroupLayout,
+    targets: DepthOfFieldTargets,
+    prefilter_pipeline: wgpu::RenderPipeline,
+    gather_pipeline: wgpu::RenderPipeline,
+    composite_pipeline: wgpu::RenderPipeline,
+}
+
+impl DepthOfFieldSystem {
+    pub fn new(
+        device: &wgpu::Device,
+        target_pool: &mut RenderTargetPool,
+        camera_bind_group_layout: &wgpu::BindGroupLayout,
+        scene_color: &Texture,
+        depth_texture: &Texture,
+        dimensions: &[u32; 2],
+    ) -> Self {
+        let uniform_buffer = memory::create_buffer(
+            device,
+            MemoryCategory::Transient,
+            &wgpu::BufferDescriptor {
+                label: Some("Depth Of Field Uniform Buffer"),
+                size: std::mem::size_of::<DepthOfFieldUniform>() as wgpu::BufferAddress,
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            },
+        );
+
+        let texture_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        };
+        let source_entries = [
+            texture_entry(0),
+            wgpu::BindGroupLayoutEntry {
+                binding: 1,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Sampler {
+                    filtering: true,
+                    comparison: false,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 2,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Depth,
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 3,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+        ];
+
+        let source_layout = memory::create_bind_group_layout(
+            device,
+            &wgpu::BindGroupLayoutDescriptor {
+                label: Some("Depth Of Field Source Bind Group Layout"),
+                entries: &source_entries,
+            },
+        );
+
+        // The blurred fields can't be bound while the gather pass renders into them
+        let mut composite_entries = source_entries.to_vec();
+        composite_entries.extend([texture_entry(4), texture_entry(5)]);
+        let composite_layout = memory::create_bind_group_layout(
+            device,
+            &wgpu::BindGroupLayoutDescriptor {
+                label: Some("Depth Of Field Composite Bind Group Layout"),
+                entries: &composite_entries,
+            },
+        );
+
+        let module = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("Depth Of Field Shader"),
+            source: wgpu::ShaderSource::Wgsl(
+                shader_snippets::splice(include_str!("shaders/depth_of_field.wgsl")).into(),
+            ),
+        });
+
+        let create_pipeline = |label, layout, entry_point, target_count| {
+            let pipeline_layout = memory::create_pipeline_layout(
+                device,
+                &wgpu::PipelineLayoutDescriptor {
+                    label: Some(label),
+                    bind_group_layouts: &[camera_bind_group_layout, layout],
+                    push_constant_ranges: &[],
+                },
+            );
+            let targets = vec![
+                wgpu::ColorTargetState {
+                    format: Texture::HDR_FORMAT,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                };
+                target_count
+            ];
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some(label),
+                layout: Some(&pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: &module,
+                    entry_point: "vs_main",
+                    buffers: &[],
+                },
+                primitive: wgpu::PrimitiveState::default(),
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState::default(),
+                fragment: Some(wgpu::FragmentState {
+                    module: &module,
+                    entry_point,
+                    targets: &targets,
+                }),
+            })
+        };
+
+        let prefilter_pipeline = create_pipeline(
+            "Depth Of Field Prefilter Pipeline",
+            &source_layout,
+            "fs_prefilter",
+            1,
+        );
+        // Renders the far field and near field at once
+        let gather_pipeline = create_pipeline(
+            "Depth Of Field Gather Pipeline",
+            &source_layout,
+            "fs_gather",
+            2,
+        );
+        let composite_pipeline = create_pipeline(
+            "Depth Of Field Composite Pipeline",
+            &composite_layout,
+            "fs_composite",
+            1,
+        );
+
+        let targets = Self::create_targets(
+            device,
+            target_pool,
+            &source_layout,
+            &composite_layout,
+            &uniform_buffer,
+            scene_color,
+            depth_texture,
+            dimensions,
+        );
+
+        Self {
+            uniform_buffer,
+            enabled: false,
+            source_layout,
+            composite_layout,
+            targets,
+            prefilter_pipeline,
+            gather_pipeline,
+            composite_pipeline,
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn create_targets(
+        device: &wgpu::Device,
+        target_pool: &mut RenderTargetPool,
+        source_layout: &wgpu::BindGroupLayout,
+        composite_layout: &wgpu::BindGroupLayout,
+        uniform_buffer: &wgpu::Buffer,
+        scene_color: &Texture,
+        depth_texture: &Texture,
+        dimensions: &[u32; 2],
+    ) -> DepthOfFieldTargets {
+        let [width, height] = *dimensions;
+        let [half_width, half_height] = [(width / 2).max(1), (height / 2).max(1)];
+        // Every target is done with once the result is copied back into the scene color
+        let mut create_target = |width, height, label| {
+            target_pool.acquire(
+                device,
+                width,
+                height,
+                Texture::HDR_FORMAT,
+                label,
+                FramePhase::DepthOfField..=FramePhase::DepthOfField,
+            )
+        };
+        let prefilter = create_target(half_width, half_height, "Depth Of Field Prefilter Texture");
+        let far = create_target(half_width, half_height, "Depth Of Field Far Texture");
+        let near = create_target(half_width, half_height, "Depth Of Field Near Texture");
+        let output = create_target(width, height, "Depth Of Field Output Texture");
+
+        let create_bind_group = |layout, source: &Texture, fields: Option<(&Texture, &Texture)>| {
+            let mut entries = vec![
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&source.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&source.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(&depth_texture.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+            ];
+            if let Some((far, near)) = fields {
+                entries.extend([
+                    wgpu::BindGroupEntry {
+                        binding: 4,
+                        resource: wgpu::BindingResource::TextureView(&far.view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 5,
+                        resource: wgpu::BindingResource::TextureView(&near.view),
+                    },
+                ]);
+            }
+            device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("Depth Of Field Bind Group"),
+                layout,
+                entries: &entries,
+            })
+        };
+        let prefilter_bind_group = create_bind_group(source_layout, scene_color, None);
+        let gather_bind_group = create_bind_group(source_layout, &prefilter, None);
+        let composite_bind_group =
+            create_bind_group(composite_layout, scene_color, Some((&far, &near)));
+
+        DepthOfFieldTargets {
+            prefilter,
+            far,
+            near,
+            output,
+            prefilter_bind_group,
+            gather_bind_group,
+            composite_bind_group,
+            dimensions: *dimensions,
+        }
+    }
+
+    pub fn resize(
+        &mut self,
+        device: &wgpu::Device,
+        target_pool: &mut RenderTargetPool,
+        scene_color: &Texture,
+        depth_texture: &Texture,
+        dimensions: &[u32; 2],
+    ) {
+        self.targets = Self::create_targets(
+            device,
+            target_pool,
+            &self.source_layout,
+            &self.composite_layout,
+            &self.uniform_buffer,
+            scene_color,
+            depth_texture,
+            dimensions,
+        );
+    }
+
+    pub fn update(&mut self, queue: &wgpu::Queue, settings: &Settings) {
+        let depth_of_field = &settings.depth_of_field;
+        self.enabled = depth_of_field.enabled
+            && depth_of_field.aperture > 0.0
+            && depth_of_field.max_radius > 0.0;
+        queue.write_buffer(
+            &self.uniform_buffer,
+            0,
+            bytemuck::cast_slice(&[DepthOfFieldUniform::new(settings)]),
+        );
+    }
+
+    // Replaces the scene color with its blurred version
+    pub fn render(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        camera_bind_group: &wgpu::BindGroup,
+        scene_color: &Texture,
+    ) {
+        if !self.enabled {
+            return;
+        }
+
+        let targets = &self.targets;
+        let passes = [
+            (
+                "Depth Of Field Prefilter Pass",
+                &self.prefilter_pipeline,
+                &targets.prefilter_bind_group,
+                vec![&targets.prefilter],
+            ),
+            (
+                "Depth Of Field Gather Pass",
+                &self.gather_pipeline,
+                &targets.gather_bind_group,
+                vec![&targets.far, &targets.near],
+            ),
+            (
+                "Depth Of Field Composite Pass",
+                &self.composite_pipeline,
+                &targets.composite_bind_group,
+                vec![&targets.output],
+            ),
+        ];
+        for (label, pipeline, bind_group, textures) in passes {
+            let color_attachments = textures
+                .iter()
+                .map(|texture| wgpu::RenderPassColorAttachment {
+                    view: &texture.view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                        store: true,
+                    },
+                })
+                .collect::<Vec<_>>();
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some(label),
+                color_attachments: &color_attachments,
+                depth_stencil_attachment: None,
+            });
+            render_pass.set_pipeline(pipeline);
+            render_pass.set_bind_group(0, camera_bind_group, &[]);
+            render_pass.set_bind_group(1, bind_group, &[]);
+            render_pass.draw(0..3, 0..1);
+        }
+
+        // The effects after this one keep sampling the scene color
+        let [width, height] = targets.dimensions;
+        encoder.copy_texture_to_texture(
+            targets.output.texture.as_image_copy(),
+            scene_color.texture.as_image_copy(),
+            wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+        );
+    }
+}
@@ -0,0 +1,2793 @@
+use anyhow::{bail, ensure, Context, Result};
+use std::collections::HashMap;
+
+// Decoder for primitives compressed with KHR_draco_mesh_compression, covering the
+// sequential and edgebreaker encodings of the Draco 2.2 mesh bitstream
+
+const INVALID: u32 = u32::MAX;
+
+const TOPOLOGY_C: u32 = 0;
+const TOPOLOGY_S: u32 = 1;
+const TOPOLOGY_L: u32 = 3;
+const TOPOLOGY_R: u32 = 5;
+const TOPOLOGY_E: u32 = 7;
+const VALENCE_TOPOLOGY: [u32; 5] = [TOPOLOGY_C, TOPOLOGY_S, TOPOLOGY_L, TOPOLOGY_R, TOPOLOGY_E];
+
+const ATTRIBUTE_POSITION: u8 = 0;
+
+const DATA_TYPE_FLOAT32: u8 = 9;
+
+const PREDICTION_NONE: i8 = -2;
+const PREDICTION_PARALLELOGRAM: i8 = 1;
+const PREDICTION_MULTI_PARALLELOGRAM: i8 = 2;
+const PREDICTION_TEX_COORDS_DEPRECATED: i8 = 3;
+const PREDICTION_CONSTRAINED_MULTI_PARALLELOGRAM: i8 = 4;
+const PREDICTION_TEX_COORDS_PORTABLE: i8 = 5;
+const PREDICTION_GEOMETRIC_NORMAL: i8 = 6;
+
+const TRANSFORM_WRAP: i8 = 1;
+const TRANSFORM_NORMAL_OCTAHEDRON: i8 = 2;
+const TRANSFORM_NORMAL_OCTAHEDRON_CANONICALIZED: i8 = 3;
+
+const DECODER_GENERIC: u8 = 0;
+const DECODER_INTEGER: u8 = 1;
+const DECODER_QUANTIZATION: u8 = 2;
+const DECODER_NORMALS: u8 = 3;
+
+// A decoded mesh with one value per point for every attribute
+#[derive(Debug, Clone)]
+pub struct DracoMesh {
+    pub indices: Vec<u32>,
+    pub attributes: Vec<DracoAttribute>,
+}
+
+impl DracoMesh {
+    pub fn attribute(&self, unique_id: u32) -> Option<&DracoAttribute> {
+        self.attributes
+            .iter()
+            .find(|attribute| attribute.unique_id == unique_id)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct DracoAttribute {
+    pub unique_id: u32,
+    pub components: usize,
+    pub values: Vec<f32>,
+}
+
+impl DracoAttribute {
+    pub fn points<const N: usize>(&self) -> Vec<[f32; N]> {
+        self.values
+            .chunks_exact(self.components)
+            .map(|value| {
+                let mut point = [0.0; N];
+                point
+                    .iter_mut()
+                    .zip(value)
+                    .for_each(|(component, value)| *component = *value);
+                point
+            })
+            .collect()
+    }
+}
+
+pub fn decode(data: &[u8]) -> Result<DracoMesh> {
+    let mut buffer = Buffer::new(data);
+    ensure!(buffer.bytes(5)? == b"DRACO", "Data isn't a Draco bitstream");
+    let (major, minor) = (buffer.u8()?, buffer.u8()?);
+    ensure!(
+        (major, minor) == (2, 2),
+        "Draco bitstream version {}.{} isn't supported, expected 2.2",
+        major,
+        minor
+    );
+    ensure!(
+        buffer.u8()? == 1,
+        "Draco data holds a point cloud instead of a triangle mesh"
+    );
+    let method = buffer.u8()?;
+    if buffer.u16()? & 0x8000 != 0 {
+        skip_geometry_metadata(&mut buffer)?;
+    }
+
+    let mut connectivity = match method {
+        0 => decode_sequential(&mut buffer)?,
+        1 => decode_edgebreaker(&mut buffer)?,
+        _ => bail!("Unknown Draco encoding method {}", method),
+    };
+    let attributes = decode_attributes(&mut buffer, &mut connectivity)?;
+
+    let num_points = connectivity.num_points;
+    let indices = connectivity
+        .faces
+        .iter()
+        .flatten()
+        .copied()
+        .collect::<Vec<_>>();
+    ensure!(
+        indices.iter().all(|index| (*index as usize) < num_points),
+        "Draco face references a point outside of the mesh"
+    );
+
+    let attributes = attributes
+        .into_iter()
+        .map(|attribute| {
+            let components = attribute.components;
+            let mut values = vec![0.0; num_points * components];
+            for (point, value) in values.chunks_exact_mut(components).enumerate() {
+                let index = match &attribute.mapping {
+                    Some(mapping) => mapping[point] as usize,
+                    None => point,
+                };
+                if let Some(source) = attribute
+                    .values
+                    .get(index * components..(index + 1) * components)
+                {
+                    value.copy_from_slice(source);
+                }
+            }
+            DracoAttribute {
+                unique_id: attribute.unique_id,
+                components,
+                values,
+            }
+        })
+        .collect();
+
+    Ok(DracoMesh {
+        indices,
+        attributes,
+    })
+}
+
+#[derive(Clone, Copy)]
+struct Buffer<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Buffer<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, position: 0 }
+    }
+
+    fn remaining(&self) -> &'a [u8] {
+        &self.data[self.position..]
+    }
+
+    fn bytes(&mut self, length: usize) -> Result<&'a [u8]> {
+        let bytes = self
+            .position
+            .checked_add(length)
+            .and_then(|end| self.data.get(self.position..end))
+            .context("Draco data ends unexpectedly")?;
+        self.position += length;
+        Ok(bytes)
+    }
+
+    fn advance(&mut self, length: usize) -> Result<()> {
+        self.bytes(length).map(|_| ())
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn i8(&mut self) -> Result<i8> {
+        Ok(self.u8()? as i8)
+    }
+
+    fn u16(&mut self) -> Result<u16> {
+        let bytes = self.bytes(2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        let bytes = self.bytes(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn i32(&mut self) -> Result<i32> {
+        Ok(self.u32()? as i32)
+    }
+
+    fn f32(&mut self) -> Result<f32> {
+        Ok(f32::from_bits(self.u32()?))
+    }
+
+    fn varint(&mut self) -> Result<u32> {
+        let mut value = 0u64;
+        for group in 0..5 {
+            let byte = self.u8()?;
+            value |= u64::from(byte & 0x7f) << (7 * group);
+            if byte & 0x80 == 0 {
+                return u32::try_from(value).context("Draco varint is out of range");
+            }
+        }
+        bail!("Draco varint is too long")
+    }
+
+    // Bits are read from the current position and skipped with end_bits
+    fn start_bits(&self) -> BitReader<'a> {
+        BitReader {
+            data: self.remaining(),
+            offset: 0,
+        }
+    }
+
+    fn end_bits(&mut self, reader: &BitReader) -> Result<()> {
+        self.advance(reader.offset.div_ceil(8))
+    }
+}
+
+struct BitReader<'a> {
+    data: &'a [u8],
+    offset: usize,
+}
+
+impl BitReader<'_> {
+    fn bits(&mut self, count: u32) -> Result<u32> {
+        ensure!(count <= 32, "Draco bit field of {} bits is too wide", count);
+        let mut value = 0;
+        for bit in 0..count {
+            value |= self.bit() << bit;
+        }
+        Ok(value)
+    }
+
+    // Reading past the end yields zeros like the reference decoder
+    fn bit(&mut self) -> u32 {
+        match self.data.get(self.offset >> 3) {
+            Some(byte) => {
+                let bit = u32::from(byte >> (self.offset & 7)) & 1;
+                self.offset += 1;
+                bit
+            }
+            None => 0,
+        }
+    }
+}
+
+// Binary rANS decoder with an 8-bit probability of zero
+struct RansBits<'a> {
+    data: &'a [u8],
+    offset: usize,
+    state: u32,
+    probability_zero: u32,
+}
+
+impl<'a> RansBits<'a> {
+    const BASE: u32 = 4096;
+
+    fn start(buffer: &mut Buffer<'a>) -> Result<Self> {
+        let probability_zero = u32::from(buffer.u8()?);
+        let size = buffer.varint()? as usize;
+        let data = buffer.bytes(size)?;
+        let (offset, state) = match data.last().map(|byte| byte >> 6) {
+            Some(0) => (size - 1, u32::from(data[size - 1] & 0x3f)),
+            Some(1) if size >= 2 => (size - 2, read_le(&data[size - 2..]) & 0x3fff),
+            Some(2) if size >= 3 => (size - 3, read_le(&data[size - 3..]) & 0x3f_ffff),
+            _ => bail!("Invalid Draco binary rANS state"),
+        };
+        let state = state + Self::BASE;
+        ensure!(state < Self::BASE * 256, "Invalid Draco binary rANS state");
+        Ok(Self {
+            data,
+            offset,
+            state,
+            probability_zero,
+        })
+    }
+
+    fn bit(&mut self) -> bool {
+        let probability = 256 - self.probability_zero;
+        if self.state < Self::BASE && self.offset > 0 {
+            self.offset -= 1;
+            self.state = self.state * 256 + u32::from(self.data[self.offset]);
+        }
+        let quotient = self.state / 256;
+        let remainder = self.state % 256;
+        let scaled = quotient * probability;
+        if remainder < probability {
+            self.state = scaled + remainder;
+            true
+        } else {
+            self.state -= scaled + probability;
+            false
+        }
+    }
+}
+
+// Multi-symbol rANS decoder whose precision follows the longest symbol
+struct RansSymbols<'a> {
+    lookup: Vec<u32>,
+    probabilities: Vec<(u32, u32)>,
+    precision: u32,
+    base: u32,
+    data: &'a [u8],
+    offset: usize,
+    state: u32,
+}
+
+impl<'a> RansSymbols<'a> {
+    fn start(buffer: &mut Buffer<'a>, bit_length: u32) -> Result<Self> {
+        let precision = 1 << ((3 * bit_length) / 2).clamp(12, 20);
+        let num_symbols = buffer.varint()? as usize;
+        ensure!(
+            num_symbols / 64 <= buffer.remaining().len(),
+            "Draco symbol table is larger than its data"
+        );
+        ensure!(num_symbols > 0, "Draco symbol table is empty");
+
+        let mut symbol_probabilities = vec![0u32; num_symbols];
+        let mut symbol = 0;
+        while symbol < num_symbols {
+            let data = buffer.u8()?;
+            let token = data & 3;
+            if token == 3 {
+                // Run of zero probabilities
+                let run = usize::from(data >> 2);
+                ensure!(symbol + run < num_symbols, "Draco symbol table overflows");
+                symbol += run + 1;
+            } else {
+                let mut probability = u32::from(data >> 2);
+                for extra in 0..u32::from(token) {
+                    probability |= u32::from(buffer.u8()?) << (8 * (extra + 1) - 2);
+                }
+                symbol_probabilities[symbol] = probability;
+                symbol += 1;
+            }
+        }
+
+        let mut lookup = vec![0; precision as usize];
+        let mut probabilities = Vec::with_capacity(num_symbols);
+        let mut cumulative = 0u32;
+        for (symbol, probability) in symbol_probabilities.into_iter().enumerate() {
+            let start = cumulative;
+            cumulative = cumulative
+                .checked_add(probability)
+                .filter(|cumulative| *cumulative <= precision)
+                .context("Draco symbol probabilities exceed their precision")?;
+            lookup[start as usize..cumulative as usize].fill(symbol as u32);
+            probabilities.push((probability, start));
+        }
+        ensure!(
+            cumulative == precision,
+            "Draco symbol probabilities don't sum to their precision"
+        );
+
+        let size = buffer.varint()? as usize;
+        let data = buffer.bytes(size)?;
+        let (offset, state) = match data.last().map(|byte| byte >> 6) {
+            Some(0) => (size - 1, u32::from(data[size - 1] & 0x3f)),
+            Some(1) if size >= 2 => (size - 2, read_le(&data[size - 2..]) & 0x3fff),
+            Some(2) if size >= 3 => (size - 3, read_le(&data[size - 3..]) & 0x3f_ffff),
+            Some(3) if size >= 4 => (size - 4, read_le(&data[size - 4..]) & 0x3fff_ffff),
+            _ => bail!("Invalid Draco rANS state"),
+        };
+        let base = 4 * precision;
+        let state = state + base;
+        ensure!(state < base * 256, "Invalid Draco rANS state");
+
+        Ok(Self {
+            lookup,
+            probabilities,
+            precision,
+            base,
+            data,
+            offset,
+            state,
+        })
+    }
+
+    fn symbol(&mut self) -> u32 {
+        while self.state < self.base && self.offset > 0 {
+            self.offset -= 1;
+            self.state = self.state * 256 + u32::from(self.data[self.offset]);
+        }
+        let quotient = self.state / self.precision;
+        let remainder = self.state % self.precision;
+        let symbol = self.lookup[remainder as usize];
+        let (probability, cumulative) = self.probabilities[symbol as usize];
+        self.state = quotient * probability + remainder - cumulative;
+        symbol
+    }
+}
+
+fn read_le(bytes: &[u8]) -> u32 {
+    bytes
+        .iter()
+        .take(4)
+        .rev()
+        .fold(0, |value, byte| (value << 8) | u32::from(*byte))
+}
+
+fn decode_symbols(buffer: &mut Buffer, count: usize, components: usize) -> Result<Vec<u32>> {
+    if count == 0 {
+        return Ok(Vec::new());
+    }
+    match buffer.u8()? {
+        // Each group of components shares a bit length coded as a tag
+        0 => {
+            let mut tags = RansSymbols::start(buffer, 5)?;
+            let mut bits = buffer.start_bits();
+            let mut values = Vec::new();
+            while values.len() < count {
+                let bit_length = tags.symbol();
+                for _ in 0..components {
+                    values.push(bits.bits(bit_length)?);
+                }
+            }
+            buffer.end_bits(&bits)?;
+            values.truncate(count);
+            Ok(values)
+        }
+        1 => {
+            let bit_length = u32::from(buffer.u8()?);
+            ensure!(
+                (1..=18).contains(&bit_length),
+                "Invalid Draco symbol bit length {}",
+                bit_length
+            );
+            let mut symbols = RansSymbols::start(buffer, bit_length)?;
+            Ok((0..count).map(|_| symbols.symbol()).collect())
+        }
+        scheme => bail!("Unknown Draco symbol coding {}", scheme),
+    }
+}
+
+fn skip_geometry_metadata(buffer: &mut Buffer) -> Result<()> {
+    for _ in 0..buffer.varint()? {
+        buffer.varint()?;
+        skip_metadata(buffer, 0)?;
+    }
+    skip_metadata(buffer, 0)
+}
+
+fn skip_metadata(buffer: &mut Buffer, depth: usize) -> Result<()> {
+    ensure!(depth <= 1000, "Draco metadata is nested too deeply");
+    for _ in 0..buffer.varint()? {
+        let name = usize::from(buffer.u8()?);
+        buffer.advance(name)?;
+        let size = buffer.varint()? as usize;
+        ensure!(size > 0, "Draco metadata entry is empty");
+        buffer.advance(size)?;
+    }
+    let children = buffer.varint()?;
+    ensure!(
+        children as usize <= buffer.remaining().len(),
+        "Draco metadata has more children than data"
+    );
+    for _ in 0..children {
+        let name = usize::from(buffer.u8()?);
+        buffer.advance(name)?;
+        skip_metadata(buffer, depth + 1)?;
+    }
+    Ok(())
+}
+
+fn next(corner: u32) -> u32 {
+    match corner {
+        INVALID => INVALID,
+        _ if corner % 3 == 2 => corner - 2,
+        _ => corner + 1,
+    }
+}
+
+fn previous(corner: u32) -> u32 {
+    match corner {
+        INVALID => INVALID,
+        _ if corner.is_multiple_of(3) => corner + 2,
+        _ => corner - 1,
+    }
+}
+
+// Triangle connectivity where every corner knows its vertex and the corner across
+// its opposite edge. Attribute seams are represented by a table whose opposite
+// corners are cut along the seam.
+#[derive(Debug, Default, Clone)]
+struct CornerTable {
+    corner_to_vertex: Vec<u32>,
+    opposite: Vec<u32>,
+    left_most: Vec<u32>,
+}
+
+impl CornerTable {
+    fn num_corners(&self) -> usize {
+        self.corner_to_vertex.len()
+    }
+
+    fn num_faces(&self) -> usize {
+        self.num_corners() / 3
+    }
+
+    fn num_vertices(&self) -> usize {
+        self.left_most.len()
+    }
+
+    fn vertex(&self, corner: u32) -> u32 {
+        self.corner_to_vertex
+            .get(corner as usize)
+            .copied()
+            .unwrap_or(INVALID)
+    }
+
+    fn opposite(&self, corner: u32) -> u32 {
+        self.opposite
+            .get(corner as usize)
+            .copied()
+            .unwrap_or(INVALID)
+    }
+
+    fn left_most(&self, vertex: u32) -> u32 {
+        self.left_most
+            .get(vertex as usize)
+            .copied()
+            .unwrap_or(INVALID)
+    }
+
+    fn swing_left(&self, corner: u32) -> u32 {
+        next(self.opposite(next(corner)))
+    }
+
+    fn swing_right(&self, corner: u32) -> u32 {
+        previous(self.opposite(previous(corner)))
+    }
+
+    fn right_corner(&self, corner: u32) -> u32 {
+        self.opposite(next(corner))
+    }
+
+    fn left_corner(&self, corner: u32) -> u32 {
+        self.opposite(previous(corner))
+    }
+
+    fn is_on_boundary(&self, vertex: u32) -> bool {
+        self.swing_left(self.left_most(vertex)) == INVALID
+    }
+
+    fn set_opposite(&mut self, first: u32, second: u32) {
+        self.opposite[first as usize] = second;
+        self.opposite[second as usize] = first;
+    }
+
+    fn map(&mut self, corner: u32, vertex: u32) {
+        self.corner_to_vertex[corner as usize] = vertex;
+    }
+
+    fn set_left_most(&mut self, vertex: u32, corner: u32) -> Result<()> {
+        *self
+            .left_most
+            .get_mut(vertex as usize)
+            .context("Draco connectivity references a missing vertex")? = corner;
+        Ok(())
+    }
+
+    fn add_vertex(&mut self) -> u32 {
+        self.left_most.push(INVALID);
+        self.left_most.len() as u32 - 1
+    }
+
+    // Corners around the vertex of a corner, swinging left first and then right
+    // from the start when a boundary is reached
+    fn vertex_corners(&self, start: u32) -> Vec<u32> {
+        let mut corners = Vec::new();
+        let mut corner = start;
+        let mut left = true;
+        while corner != INVALID && corners.len() <= self.num_corners() {
+            corners.push(corner);
+            if left {
+                corner = self.swing_left(corner);
+                if corner == INVALID {
+                    corner = self.swing_right(start);
+                    left = false;
+                } else if corner == start {
+                    break;
+                }
+            } else {
+                corner = self.swing_right(corner);
+            }
+        }
+        corners
+    }
+}
+
+// Maps each value of an attribute to the corner it was first reached from
+#[derive(Debug, Default)]
+struct Encoding {
+    corner_map: Vec<u32>,
+    vertex_to_value: Vec<u32>,
+}
+
+impl Encoding {
+    fn new(num_vertices: usize) -> Self {
+        Self {
+            corner_map: Vec::new(),
+            vertex_to_value: vec![0; num_vertices],
+        }
+    }
+}
+
+struct AttributeData {
+    decoder: Option<usize>,
+    seams: Vec<u32>,
+    table: CornerTable,
+    vertex_on_seam: Vec<bool>,
+    connectivity_used: bool,
+    encoding: Encoding,
+}
+
+struct Edgebreaker {
+    table: CornerTable,
+    attribute_data: Vec<AttributeData>,
+    position_encoding: Encoding,
+    position_decoder: Option<usize>,
+}
+
+struct Connectivity {
+    faces: Vec<[u32; 3]>,
+    num_points: usize,
+    edgebreaker: Option<Edgebreaker>,
+}
+
+fn decode_sequential(buffer: &mut Buffer) -> Result<Connectivity> {
+    let num_faces = buffer.varint()? as usize;
+    let num_points = buffer.varint()? as usize;
+    ensure!(
+        num_faces <= buffer.remaining().len() / 3,
+        "Draco mesh has more faces than data"
+    );
+    let faces = match buffer.u8()? {
+        0 => {
+            // Indices are coded as signed differences from the previous index
+            let mut last = 0i32;
+            let mut indices = Vec::with_capacity(num_faces * 3);
+            for value in decode_symbols(buffer, num_faces * 3, 1)? {
+                let difference = (value >> 1) as i32;
+                if value & 1 != 0 {
+                    ensure!(difference <= last, "Invalid Draco index difference");
+                    last -= difference;
+                } else {
+                    ensure!(
+                        difference <= i32::MAX - last,
+                        "Invalid Draco index difference"
+                    );
+                    last += difference;
+                }
+                indices.push(last as u32);
+            }
+            indices
+        }
+        _ => {
+            let mut indices = Vec::with_capacity(num_faces * 3);
+            for _ in 0..num_faces * 3 {
+                indices.push(if num_points < 1 << 8 {
+                    u32::from(buffer.u8()?)
+                } else if num_points < 1 << 16 {
+                    u32::from(buffer.u16()?)
+                } else if num_points < 1 << 21 {
+                    buffer.varint()?
+                } else {
+                    buffer.u32()?
+                });
+            }
+            indices
+        }
+    };
+    Ok(Connectivity {
+        faces: faces
+            .chunks_exact(3)
+            .map(|face| [face[0], face[1], face[2]])
+            .collect(),
+        num_points,
+        edgebreaker: None,
+    })
+}
+
+struct TopologySplit {
+    source: u32,
+    split: u32,
+    right_edge: bool,
+}
+
+enum Symbols<'a> {
+    Standard(BitReader<'a>),
+    // Symbols are grouped in contexts chosen by the valence of the active vertex
+    Valence {
+        contexts: Vec<Vec<u32>>,
+        valences: Vec<u32>,
+        active: Option<usize>,
+        last: u32,
+    },
+}
+
+struct Traversal<'a> {
+    symbols: Symbols<'a>,
+    start_faces: RansBits<'a>,
+    seams: Vec<RansBits<'a>>,
+}
+
+impl<'a> Traversal<'a> {
+    fn start(
+        valence: bool,
+        mut buffer: Buffer<'a>,
+        num_attribute_data: usize,
+        num_vertices: usize,
+        num_faces: usize,
+    ) -> Result<(Self, Buffer<'a>)> {
+        let reader = if valence {
+            None
+        } else {
+            let size = buffer.varint()? as usize;
+            let reader = buffer.start_bits();
+            buffer.advance(size)?;
+            Some(reader)
+        };
+        let start_faces = RansBits::start(&mut buffer)?;
+        let seams = (0..num_attribute_data)
+            .map(|_| RansBits::start(&mut buffer))
+            .collect::<Result<Vec<_>>>()?;
+        let symbols = match reader {
+            Some(reader) => Symbols::Standard(reader),
+            None => {
+                let contexts = (2..=7)
+                    .map(|_| {
+                        let count = buffer.varint()? as usize;
+                        ensure!(
+                            count <= num_faces,
+                            "Draco valence context has more symbols than faces"
+                        );
+                        decode_symbols(&mut buffer, count, 1)
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                Symbols::Valence {
+                    contexts,
+                    valences: vec![0; num_vertices],
+                    active: None,
+                    last: TOPOLOGY_E,
+                }
+            }
+        };
+        Ok((
+            Self {
+                symbols,
+                start_faces,
+                seams,
+            },
+            buffer,
+        ))
+    }
+
+    fn symbol(&mut self) -> Result<u32> {
+        match &mut self.symbols {
+            Symbols::Standard(reader) => {
+                let symbol = reader.bits(1)?;
+                if symbol == TOPOLOGY_C {
+                    return Ok(symbol);
+                }
+                Ok(symbol | (reader.bits(2)? << 1))
+            }
+            Symbols::Valence {
+                contexts,
+                active,
+                last,
+                ..
+            } => {
+                *last = match active {
+                    Some(context) => {
+                        let symbol = contexts[*context]
+                            .pop()
+                            .context("Draco valence context ran out of symbols")?;
+                        *VALENCE_TOPOLOGY
+                            .get(symbol as usize)
+                            .context("Invalid Draco valence symbol")?
+                    }
+                    None => TOPOLOGY_E,
+                };
+                Ok(*last)
+            }
+        }
+    }
+
+    fn new_active_corner(&mut self, table: &CornerTable, corner: u32) -> Result<()> {
+        let Symbols::Valence {
+            valences,
+            active,
+            last,
+            ..
+        } = &mut self.symbols
+        else {
+            return Ok(());
+        };
+        let (next, previous) = (next(corner), previous(corner));
+        let increments = match *last {
+            TOPOLOGY_C | TOPOLOGY_S => [(next, 1), (previous, 1), (INVALID, 0)],
+            TOPOLOGY_R => [(corner, 1), (next, 1), (previous, 2)],
+            TOPOLOGY_L => [(corner, 1), (next, 2), (previous, 1)],
+            _ => [(corner, 2), (next, 2), (previous, 2)],
+        };
+        for (corner, increment) in increments {
+            if increment > 0 {
+                *valences
+                    .get_mut(table.vertex(corner) as usize)
+                    .context("Draco valence references a missing vertex")? += increment;
+            }
+        }
+        let valence = valences
+            .get(table.vertex(next) as usize)
+            .context("Draco valence references a missing vertex")?;
+        *active = Some(*valence.clamp(&2, &7) as usize - 2);
+        Ok(())
+    }
+
+    fn merge_vertices(&mut self, destination: u32, source: u32) {
+        if let Symbols::Valence { valences, .. } = &mut self.symbols {
+            if let (Some(&source), Some(_)) = (
+                valences.get(source as usize),
+                valences.get(destination as usize),
+            ) {
+                valences[destination as usize] += source;
+            }
+        }
+    }
+}
+
+fn decode_edgebreaker(buffer: &mut Buffer) -> Result<Connectivity> {
+    let valence = match buffer.u8()? {
+        0 => false,
+        2 => true,
+        traversal => bail!("Draco edgebreaker traversal {} isn't supported", traversal),
+    };
+    let num_encoded_vertices = buffer.varint()? as usize;
+    let num_faces = buffer.varint()? as usize;
+    ensure!(
+        num_faces <= u32::MAX as usize / 3,
+        "Draco mesh has too many faces"
+    );
+    ensure!(
+        num_encoded_vertices <= num_faces * 3,
+        "Draco mesh has more vertices than corners"
+    );
+    let vertices = num_encoded_vertices as u64;
+    ensure!(
+        vertices.wrapping_mul(vertices.wrapping_sub(1)) / 2 >= (3 * num_faces / 2) as u64,
+        "Draco mesh has more edges than its vertices allow"
+    );
+    let num_attribute_data = usize::from(buffer.u8()?);
+    let num_symbols = buffer.varint()? as usize;
+    ensure!(
+        num_symbols <= num_faces && num_faces <= num_symbols + num_symbols / 3,
+        "Draco mesh face count doesn't match its symbols"
+    );
+    let num_split_symbols = buffer.varint()? as usize;
+    ensure!(
+        num_split_symbols <= num_symbols,
+        "Draco mesh has more split symbols than symbols"
+    );
+
+    let max_vertices = num_encoded_vertices + num_split_symbols;
+    let mut table = CornerTable {
+        corner_to_vertex: vec![INVALID; num_faces * 3],
+        opposite: vec![INVALID; num_faces * 3],
+        left_most: Vec::with_capacity(max_vertices),
+    };
+    let mut holes = vec![true; max_vertices];
+
+    let mut splits = Vec::new();
+    let num_splits = buffer.varint()? as usize;
+    if num_splits > 0 {
+        ensure!(
+            num_splits <= num_faces,
+            "Draco mesh has more topology splits than faces"
+        );
+        let mut last_source = 0u32;
+        for _ in 0..num_splits {
+            let source = buffer.varint()?.wrapping_add(last_source);
+            let difference = buffer.varint()?;
+            ensure!(difference <= source, "Invalid Draco topology split");
+            splits.push(TopologySplit {
+                source,
+                split: source - difference,
+                right_edge: false,
+            });
+            last_source = source;
+        }
+        let mut bits = buffer.start_bits();
+        for split in &mut splits {
+            split.right_edge = bits.bits(1)? == 1;
+        }
+        buffer.end_bits(&bits)?;
+    }
+
+    let (mut traversal, end) = Traversal::start(
+        valence,
+        *buffer,
+        num_attribute_data,
+        max_vertices,
+        num_faces,
+    )?;
+
+    let mut active_corners: Vec<u32> = Vec::new();
+    let mut split_corners: HashMap<usize, u32> = HashMap::new();
+    let mut invalid_vertices = Vec::new();
+    let mut face = 0;
+    let malformed = || anyhow::anyhow!("Draco edgebreaker connectivity is malformed");
+    for symbol_id in 0..num_symbols {
+        let corner = 3 * face as u32;
+        face += 1;
+        let mut check_split = false;
+        match traversal.symbol()? {
+            TOPOLOGY_C => {
+                let corner_a = *active_corners.last().ok_or_else(malformed)?;
+                let vertex_x = table.vertex(next(corner_a));
+                let corner_b = next(table.left_most(vertex_x));
+                ensure!(
+                    corner_a != corner_b
+                        && corner_b != INVALID
+                        && table.opposite(corner_a) == INVALID
+                        && table.opposite(corner_b) == INVALID,
+                    malformed()
+                );
+                table.set_opposite(corner_a, corner + 1);
+                table.set_opposite(corner_b, corner + 2);
+                let vertex_a_previous = table.vertex(previous(corner_a));
+                let vertex_b_next = table.vertex(next(corner_b));
+                ensure!(
+                    vertex_x != vertex_a_previous && vertex_x != vertex_b_next,
+                    malformed()
+                );
+                table.map(corner, vertex_x);
+                table.map(corner + 1, vertex_b_next);
+                table.map(corner + 2, vertex_a_previous);
+                table.set_left_most(vertex_a_previous, corner + 2)?;
+                *holes.get_mut(vertex_x as usize).ok_or_else(malformed)? = false;
+                *active_corners.last_mut().ok_or_else(malformed)? = corner;
+            }
+            symbol @ (TOPOLOGY_R | TOPOLOGY_L) => {
+                let corner_a = *active_corners.last().ok_or_else(malformed)?;
+                ensure!(table.opposite(corner_a) == INVALID, malformed());
+                let (opposite, corner_l, corner_r) = if symbol == TOPOLOGY_R {
+                    (corner + 2, corner + 1, corner)
+                } else {
+                    (corner + 1, corner, corner + 2)
+                };
+                table.set_opposite(opposite, corner_a);
+                let vertex = table.add_vertex();
+                ensure!(table.num_vertices() <= max_vertices, malformed());
+                table.map(opposite, vertex);
+                table.set_left_most(vertex, opposite)?;
+                let vertex_r = table.vertex(previous(corner_a));
+                table.map(corner_r, vertex_r);
+                table.set_left_most(vertex_r, corner_r)?;
+                table.map(corner_l, table.vertex(next(corner_a)));
+                *active_corners.last_mut().ok_or_else(malformed)? = corner;
+                check_split = true;
+            }
+            TOPOLOGY_S => {
+                let corner_b = active_corners.pop().ok_or_else(malformed)?;
+                if let Some(corner) = split_corners.get(&symbol_id) {
+                    active_corners.push(*corner);
+                }
+                let corner_a = *active_corners.last().ok_or_else(malformed)?;
+                ensure!(
+                    corner_a != corner_b
+                        && table.opposite(corner_a) == INVALID
+                        && table.opposite(corner_b) == INVALID,
+                    malformed()
+                );
+                table.set_opposite(corner_a, corner + 2);
+                table.set_opposite(corner_b, corner + 1);
+                let vertex_p = table.vertex(previous(corner_a));
+                table.map(corner, vertex_p);
+                table.map(corner + 1, table.vertex(next(corner_a)));
+                let vertex_b_previous = table.vertex(previous(corner_b));
+                table.map(corner + 2, vertex_b_previous);
+                table.set_left_most(vertex_b_previous, corner + 2)?;
+
+                // Merge the vertex reached from the other side into vertex_p
+                let mut corner_n = next(corner_b);
+                let vertex_n = table.vertex(corner_n);
+                traversal.merge_vertices(vertex_p, vertex_n);
+                table.set_left_most(vertex_p, table.left_most(vertex_n))?;
+                let first = corner_n;
+                while corner_n != INVALID {
+                    table.map(corner_n, vertex_p);
+                    corner_n = table.swing_left(corner_n);
+                    ensure!(corner_n != first, malformed());
+                }
+                table.set_left_most(vertex_n, INVALID)?;
+                if num_attribute_data == 0 {
+                    invalid_vertices.push(vertex_n);
+                }
+                *active_corners.last_mut().ok_or_else(malformed)? = corner;
+            }
+            TOPOLOGY_E => {
+                let first = table.add_vertex();
+                table.add_vertex();
+                table.add_vertex();
+                ensure!(table.num_vertices() <= max_vertices, malformed());
+                for offset in 0..3 {
+                    table.map(corner + offset, first + offset);
+                    table.set_left_most(first + offset, corner + offset)?;
+                }
+                active_corners.push(corner);
+                check_split = true;
+            }
+            _ => bail!(malformed()),
+        }
+        traversal.new_active_corner(&table, *active_corners.last().ok_or_else(malformed)?)?;
+
+        if check_split {
+            let encoder_symbol = (num_symbols - symbol_id - 1) as u32;
+            while let Some(split) = splits.last() {
+                ensure!(split.source <= encoder_symbol, malformed());
+                if split.source != encoder_symbol {
+                    break;
+                }
+                let top = *active_corners.last().ok_or_else(malformed)?;
+                let corner = if split.right_edge {
+                    next(top)
+                } else {
+                    previous(top)
+                };
+                split_corners.insert(num_symbols - split.split as usize - 1, corner);
+                splits.pop();
+            }
+        }
+    }
+    ensure!(table.num_vertices() <= max_vertices, malformed());
+
+    // Faces that close interior loops are marked by the start face decoder
+    while let Some(corner) = active_corners.pop() {
+        if !traversal.start_faces.bit() {
+            continue;
+        }
+        ensure!(face < num_faces, malformed());
+        let vertex_n = table.vertex(next(corner));
+        let corner_b = next(table.left_most(vertex_n));
+        let vertex_x = table.vertex(next(corner_b));
+        let corner_c = next(table.left_most(vertex_x));
+        ensure!(
+            corner != corner_b
+                && corner != corner_c
+                && corner_b != corner_c
+                && corner_b != INVALID
+                && corner_c != INVALID,
+            malformed()
+        );
+        ensure!(
+            table.opposite(corner) == INVALID
+                && table.opposite(corner_b) == INVALID
+                && table.opposite(corner_c) == INVALID,
+            malformed()
+        );
+        let vertex_p = table.vertex(next(corner_c));
+        let new_corner = 3 * face as u32;
+        face += 1;
+        table.set_opposite(new_corner, corner);
+        table.set_opposite(new_corner + 1, corner_b);
+        table.set_opposite(new_corner + 2, corner_c);
+        table.map(new_corner, vertex_x);
+        table.map(new_corner + 1, vertex_p);
+        table.map(new_corner + 2, vertex_n);
+        for vertex in [vertex_x, vertex_p, vertex_n] {
+            *holes.get_mut(vertex as usize).ok_or_else(malformed)? = false;
+        }
+    }
+    ensure!(face == num_faces, malformed());
+
+    // Fill the vertices removed by splits with the last vertices
+    let mut num_vertices = table.num_vertices();
+    for invalid in invalid_vertices {
+        ensure!(num_vertices > 0, malformed());
+        let mut source = num_vertices as u32 - 1;
+        while table.left_most(source) == INVALID {
+            num_vertices -= 1;
+            ensure!(num_vertices > 0, malformed());
+            source = num_vertices as u32 - 1;
+        }
+        if source < invalid {
+            continue;
+        }
+        for corner in table.vertex_corners(table.left_most(source)) {
+            ensure!(table.vertex(corner) == source, malformed());
+            table.map(corner, invalid);
+        }
+        table.set_left_most(invalid, table.left_most(source))?;
+        table.set_left_most(source, INVALID)?;
+        holes[invalid as usize] = holes[source as usize];
+        holes[source as usize] = false;
+        num_vertices -= 1;
+    }
+
+    *buffer = end;
+
+    let mut attribute_data = (0..num_attribute_data)
+        .map(|_| AttributeData {
+            decoder: None,
+            seams: Vec::new(),
+            table: CornerTable::default(),
+            vertex_on_seam: Vec::new(),
+            connectivity_used: true,
+            encoding: Encoding::default(),
+        })
+        .collect::<Vec<_>>();
+    if num_attribute_data > 0 {
+        for first in (0..table.num_corners() as u32).step_by(3) {
+            let face = first / 3;
+            for corner in [first, next(first), previous(first)] {
+                let opposite = table.opposite(corner);
+                if opposite == INVALID {
+                    for data in &mut attribute_data {
+                        data.seams.push(corner);
+                    }
+                    continue;
+                }
+                if opposite / 3 < face {
+                    continue;
+                }
+                for (data, seams) in attribute_data.iter_mut().zip(&mut traversal.seams) {
+                    if seams.bit() {
+                        data.seams.push(corner);
+                    }
+                }
+            }
+        }
+    }
+    for data in &mut attribute_data {
+        let (attribute_table, vertex_on_seam) = attribute_table(&table, &data.seams)?;
+        data.encoding = Encoding::new(attribute_table.num_vertices().max(table.num_vertices()));
+        data.table = attribute_table;
+        data.vertex_on_seam = vertex_on_seam;
+    }
+
+    let (faces, num_points) = assign_points(&table, &attribute_data, &holes, num_vertices)?;
+    Ok(Connectivity {
+        faces,
+        num_points,
+        edgebreaker: Some(Edgebreaker {
+            position_encoding: Encoding::new(table.num_vertices()),
+            table,
+            attribute_data,
+            position_decoder: None,
+        }),
+    })
+}
+
+// Splits vertices along the seams of an attribute so that every attribute value
+// gets its own vertex
+fn attribute_table(table: &CornerTable, seams: &[u32]) -> Result<(CornerTable, Vec<bool>)> {
+    let mut on_seam = vec![false; table.num_corners()];
+    let mut vertex_on_seam = vec![false; table.num_vertices()];
+    let mut mark = |corner: u32, on_seam: &mut Vec<bool>| -> Result<()> {
+        on_seam[corner as usize] = true;
+        for vertex in [table.vertex(next(corner)), table.vertex(previous(corner))] {
+            *vertex_on_seam
+                .get_mut(vertex as usize)
+                .context("Draco seam references a missing vertex")? = true;
+        }
+        Ok(())
+    };
+    for &corner in seams {
+        ensure!(
+            (corner as usize) < table.num_corners(),
+            "Draco seam references a missing corner"
+        );
+        mark(corner, &mut on_seam)?;
+        let opposite = table.opposite(corner);
+        if opposite != INVALID {
+            mark(opposite, &mut on_seam)?;
+        }
+    }
+
+    let mut attribute = CornerTable {
+        corner_to_vertex: vec![INVALID; table.num_corners()],
+        opposite: table
+            .opposite
+            .iter()
+            .zip(&on_seam)
+            .map(|(opposite, on_seam)| if *on_seam { INVALID } else { *opposite })
+            .collect(),
+        left_most: Vec::with_capacity(table.num_vertices()),
+    };
+    for vertex in 0..table.num_vertices() as u32 {
+        let corner = table.left_most(vertex);
+        if corner == INVALID {
+            continue;
+        }
+        let mut first = corner;
+        if vertex_on_seam[vertex as usize] {
+            let mut current = attribute.swing_left(first);
+            while current != INVALID {
+                first = current;
+                current = attribute.swing_left(current);
+                ensure!(current != corner, "Draco attribute seams are malformed");
+            }
+        }
+        let mut attribute_vertex = attribute.add_vertex();
+        attribute.map(first, attribute_vertex);
+        attribute.left_most[attribute_vertex as usize] = first;
+        let mut current = table.swing_right(first);
+        while current != INVALID && current != first {
+            if on_seam[next(current) as usize] {
+                attribute_vertex = attribute.add_vertex();
+                attribute.left_most[attribute_vertex as usize] = current;
+            }
+            attribute.map(current, attribute_vertex);
+            current = table.swing_right(current);
+        }
+    }
+    Ok((attribute, vertex_on_seam))
+}
+
+// Creates a point for every distinct combination of attribute vertices around each
+// vertex and returns the faces in terms of those points
+fn assign_points(
+    table: &CornerTable,
+    attribute_data: &[AttributeData],
+    holes: &[bool],
+    num_vertices: usize,
+) -> Result<(Vec<[u32; 3]>, usize)> {
+    let faces_from = |corner_to_point: &[u32]| {
+        corner_to_point
+            .chunks_exact(3)
+            .map(|face| [face[0], face[1], face[2]])
+            .collect::<Vec<_>>()
+    };
+    if attribute_data.is_empty() {
+        return Ok((faces_from(&table.corner_to_vertex), num_vertices));
+    }
+
+    let mut corner_to_point = vec![0u32; table.num_corners()];
+    let mut num_points = 0u32;
+    for vertex in 0..table.num_vertices() as u32 {
+        let corner = table.left_most(vertex);
+        if corner == INVALID {
+            continue;
+        }
+        let mut first = corner;
+        if !holes[vertex as usize] {
+            // Start at a seam so that points don't wrap around it
+            for data in attribute_data {
+                if !data.vertex_on_seam[table.vertex(corner) as usize] {
+                    continue;
+                }
+                let attribute_vertex = data.table.vertex(corner);
+                let mut current = table.swing_right(corner);
+                let mut found = false;
+                while current != corner {
+                    ensure!(current != INVALID, "Draco attribute seams are malformed");
+                    if data.table.vertex(current) != attribute_vertex {
+                        first = current;
+                        found = true;
+                        break;
+                    }
+                    current = table.swing_right(current);
+                }
+                if found {
+                    break;
+                }
+            }
+        }
+
+        corner_to_point[first as usize] = num_points;
+        num_points += 1;
+        let mut last = first;
+        let mut current = table.swing_right(first);
+        while current != INVALID && current != first {
+            let seam = attribute_data
+                .iter()
+                .any(|data| data.table.vertex(current) != data.table.vertex(last));
+            corner_to_point[current as usize] = if seam {
+                num_points += 1;
+                num_points - 1
+            } else {
+                corner_to_point[last as usize]
+            };
+            last = current;
+            current = table.swing_right(current);
+        }
+    }
+    Ok((faces_from(&corner_to_point), num_points as usize))
+}
+
+// Records the order in which a traversal reaches each attribute value
+struct Observer<'a> {
+    faces: &'a [[u32; 3]],
+    encoding: &'a mut Encoding,
+    point_ids: Vec<u32>,
+}
+
+impl Observer<'_> {
+    fn visit(&mut self, vertex: u32, corner: u32) -> Result<()> {
+        let point = self
+            .faces
+            .get(corner as usize / 3)
+            .context("Draco traversal reached a missing face")?[corner as usize % 3];
+        self.point_ids.push(point);
+        self.encoding.corner_map.push(corner);
+        *self
+            .encoding
+            .vertex_to_value
+            .get_mut(vertex as usize)
+            .context("Draco traversal reached a missing vertex")? =
+            self.encoding.corner_map.len() as u32 - 1;
+        Ok(())
+    }
+}
+
+fn is_face_visited(visited: &[bool], corner: u32) -> bool {
+    corner == INVALID || visited[corner as usize / 3]
+}
+
+fn traverse_depth_first(table: &CornerTable, observer: &mut Observer) -> Result<()> {
+    let mut face_visited = vec![false; table.num_faces()];
+    let mut vertex_visited = vec![false; table.num_vertices()];
+    let mut stack = Vec::new();
+    for start in (0..table.num_corners() as u32).step_by(3) {
+        if face_visited[start as usize / 3] {
+            continue;
+        }
+        stack.clear();
+        stack.push(start);
+        for corner in [next(start), previous(start)] {
+            let vertex = table.vertex(corner);
+            ensure!(
+                vertex != INVALID,
+                "Draco traversal reached a missing vertex"
+            );
+            if !vertex_visited[vertex as usize] {
+                vertex_visited[vertex as usize] = true;
+                observer.visit(vertex, corner)?;
+            }
+        }
+        while let Some(&top) = stack.last() {
+            let mut corner = top;
+            if is_face_visited(&face_visited, corner) {
+                stack.pop();
+                continue;
+            }
+            loop {
+                face_visited[corner as usize / 3] = true;
+                let vertex = table.vertex(corner);
+                ensure!(
+                    vertex != INVALID,
+                    "Draco traversal reached a missing vertex"
+                );
+                if !vertex_visited[vertex as usize] {
+                    let on_boundary = table.is_on_boundary(vertex);
+                    vertex_visited[vertex as usize] = true;
+                    observer.visit(vertex, corner)?;
+                    if !on_boundary {
+                        corner = table.right_corner(corner);
+                        ensure!(corner != INVALID, "Draco traversal left the mesh");
+                        continue;
+                    }
+                }
+                let right = table.right_corner(corner);
+                let left = table.left_corner(corner);
+                match (
+                    is_face_visited(&face_visited, right),
+                    is_face_visited(&face_visited, left),
+                ) {
+                    (true, true) => {
+                        stack.pop();
+                        break;
+                    }
+                    (true, false) => corner = left,
+                    (false, true) => corner = right,
+                    (false, false) => {
+                        *stack.last_mut().unwrap() = left;
+                        stack.push(right);
+                        break;
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+// Visits vertices in an order that maximizes the number of known neighbors used by
+// parallelogram prediction
+fn traverse_prediction_degree(table: &CornerTable, observer: &mut Observer) -> Result<()> {
+    let mut face_visited = vec![false; table.num_faces()];
+    let mut vertex_visited = vec![false; table.num_vertices()];
+    let mut degree = vec![0u32; table.num_vertices()];
+    let mut stacks: [Vec<u32>; 3] = Default::default();
+    if degree.is_empty() {
+        return Ok(());
+    }
+
+    let mut priority = |corner: u32, vertex_visited: &[bool]| -> usize {
+        let tip = table.vertex(corner) as usize;
+        if vertex_visited[tip] {
+            return 0;
+        }
+        degree[tip] += 1;
+        if degree[tip] > 1 {
+            1
+        } else {
+            2
+        }
+    };
+
+    for start in (0..table.num_corners() as u32).step_by(3) {
+        stacks[0].push(start);
+        let mut best = 0;
+        for corner in [next(start), previous(start), start] {
+            let vertex = table.vertex(corner);
+            ensure!(
+                vertex != INVALID,
+                "Draco traversal reached a missing vertex"
+            );
+            if !vertex_visited[vertex as usize] {
+                vertex_visited[vertex as usize] = true;
+                observer.visit(vertex, corner)?;
+            }
+        }
+        while let Some(level) = (best..3).find(|level| !stacks[*level].is_empty()) {
+            best = level;
+            let mut corner = stacks[level].pop().unwrap();
+            if face_visited[corner as usize / 3] {
+                continue;
+            }
+            loop {
+                face_visited[corner as usize / 3] = true;
+                let vertex = table.vertex(corner);
+                ensure!(
+                    vertex != INVALID,
+                    "Draco traversal reached a missing vertex"
+                );
+                if !vertex_visited[vertex as usize] {
+                    vertex_visited[vertex as usize] = true;
+                    observer.visit(vertex, corner)?;
+                }
+                let right = table.right_corner(corner);
+                let left = table.left_corner(corner);
+                let right_visited = is_face_visited(&face_visited, right);
+                if !is_face_visited(&face_visited, left) {
+                    let level = priority(left, &vertex_visited);
+                    if right_visited && level <= best {
+                        corner = left;
+                        continue;
+                    }
+                    stacks[level].push(left);
+                    best = best.min(level);
+                }
+                if !right_visited {
+                    let level = priority(right, &vertex_visited);
+                    if level <= best {
+                        corner = right;
+                        continue;
+                    }
+                    stacks[level].push(right);
+                    best = best.min(level);
+                }
+                break;
+            }
+        }
+    }
+    Ok(())
+}
+
+struct Attribute {
+    kind: u8,
+    data_type: u8,
+    components: usize,
+    normalized: bool,
+    unique_id: u32,
+    decoder: u8,
+    portable: Option<Vec<i32>>,
+    values: Vec<f32>,
+    mapping: Option<Vec<u32>>,
+}
+
+fn data_type_size(data_type: u8) -> usize {
+    match data_type {
+        1 | 2 | 11 => 1,
+        3 | 4 => 2,
+        5 | 6 | 9 => 4,
+        _ => 8,
+    }
+}
+
+fn normalize(value: f64, data_type: u8, normalized: bool) -> f32 {
+    if !normalized {
+        return value as f32;
+    }
+    let maximum = match data_type {
+        1 => i8::MAX as f64,
+        2 => u8::MAX as f64,
+        3 => i16::MAX as f64,
+        4 => u16::MAX as f64,
+        5 => i32::MAX as f64,
+        6 => u32::MAX as f64,
+        _ => return value as f32,
+    };
+    (value / maximum).max(-1.0) as f32
+}
+
+fn raw_value(bytes: &[u8], data_type: u8, normalized: bool) -> f32 {
+    let mut word = [0; 8];
+    word[..bytes.len()].copy_from_slice(bytes);
+    let value = match data_type {
+        1 => bytes[0] as i8 as f64,
+        2 | 11 => bytes[0] as f64,
+        3 => i16::from_le_bytes([bytes[0], bytes[1]]) as f64,
+        4 => u16::from_le_bytes([bytes[0], bytes[1]]) as f64,
+        5 => i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f64,
+        6 => u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f64,
+        7 => i64::from_le_bytes(word) as f64,
+        8 => u64::from_le_bytes(word) as f64,
+        9 => return f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+        _ => f64::from_le_bytes(word),
+    };
+    normalize(value, data_type, normalized)
+}
+
+fn integer_value(value: i32, data_type: u8, normalized: bool) -> Result<f32> {
+    let value = match data_type {
+        1 => value as i8 as f64,
+        2 => value as u8 as f64,
+        3 => value as i16 as f64,
+        4 => value as u16 as f64,
+        5 => value as f64,
+        6 => value as u32 as f64,
+        _ => bail!("Draco integer attribute has data type {}", data_type),
+    };
+    Ok(normalize(value, data_type, normalized))
+}
+
+struct DecoderSpec {
+    data: Option<usize>,
+    corner: bool,
+    prediction_degree: bool,
+}
+
+fn decode_attributes(
+    buffer: &mut Buffer,
+    connectivity: &mut Connectivity,
+) -> Result<Vec<Attribute>> {
+    let num_decoders = usize::from(buffer.u8()?);
+    let mut specs = Vec::with_capacity(num_decoders);
+    for decoder in 0..num_decoders {
+        let Some(edgebreaker) = &mut connectivity.edgebreaker else {
+            specs.push(None);
+            continue;
+        };
+        let data = buffer.i8()?;
+        let corner = buffer.u8()? != 0;
+        let traversal = buffer.u8()?;
+        ensure!(
+            traversal < 2,
+            "Unknown Draco traversal method {}",
+            traversal
+        );
+        let data = if data >= 0 {
+            let attribute_data = edgebreaker
+                .attribute_data
+                .get_mut(data as usize)
+                .context("Draco attribute decoder references missing attribute data")?;
+            ensure!(
+                attribute_data.decoder.is_none(),
+                "Draco attribute data is used by two decoders"
+            );
+            attribute_data.decoder = Some(decoder);
+            attribute_data.connectivity_used = corner;
+            Some(data as usize)
+        } else {
+            ensure!(
+                edgebreaker.position_decoder.is_none(),
+                "Draco position data is used by two decoders"
+            );
+            edgebreaker.position_decoder = Some(decoder);
+            None
+        };
+        ensure!(
+            !corner || (traversal == 0 && data.is_some()),
+            "Invalid Draco corner attribute decoder"
+        );
+        specs.push(Some(DecoderSpec {
+            data,
+            corner,
+            prediction_degree: traversal == 1,
+        }));
+    }
+
+    let mut attributes = Vec::new();
+    let mut members = Vec::with_capacity(num_decoders);
+    for _ in 0..num_decoders {
+        let count = buffer.varint()? as usize;
+        ensure!(
+            count > 0 && count <= 5 * buffer.remaining().len(),
+            "Invalid Draco attribute count {}",
+            count
+        );
+        let first = attributes.len();
+        for _ in 0..count {
+            let kind = buffer.u8()?;
+            let data_type = buffer.u8()?;
+            let components = usize::from(buffer.u8()?);
+            let normalized = buffer.u8()? > 0;
+            ensure!(kind < 5, "Unknown Draco attribute type {}", kind);
+            ensure!(
+                (1..=11).contains(&data_type),
+                "Unknown Draco data type {}",
+                data_type
+            );
+            ensure!(components > 0, "Draco attribute has no components");
+            attributes.push(Attribute {
+                kind,
+                data_type,
+                components,
+                normalized,
+                unique_id: buffer.varint()?,
+                decoder: DECODER_GENERIC,
+                portable: None,
+                values: Vec::new(),
+                mapping: None,
+            });
+        }
+        for attribute in &mut attributes[first..] {
+            attribute.decoder = buffer.u8()?;
+            match attribute.decoder {
+                DECODER_GENERIC | DECODER_INTEGER => {}
+                DECODER_QUANTIZATION => ensure!(
+                    attribute.data_type == DATA_TYPE_FLOAT32,
+                    "Quantized Draco attribute isn't a float"
+                ),
+                DECODER_NORMALS => ensure!(
+                    attribute.data_type == DATA_TYPE_FLOAT32 && attribute.components == 3,
+                    "Draco normal attribute isn't a float vector"
+                ),
+                decoder => bail!("Unknown Draco attribute decoder {}", decoder),
+            }
+        }
+        members.push(first..attributes.len());
+    }
+
+    for (spec, members) in specs.iter().zip(members) {
+        let (point_ids, mapping) = match (spec, &mut connectivity.edgebreaker) {
+            (Some(spec), Some(edgebreaker)) => traverse_attributes(
+                edgebreaker,
+                &connectivity.faces,
+                connectivity.num_points,
+                spec,
+            )?,
+            _ => ((0..connectivity.num_points as u32).collect(), None),
+        };
+        for attribute in &mut attributes[members.clone()] {
+            attribute.mapping = mapping.clone();
+        }
+
+        let mesh = match (spec, &connectivity.edgebreaker) {
+            (Some(spec), Some(edgebreaker)) => Some(match spec.data {
+                Some(data) => {
+                    let data = &edgebreaker.attribute_data[data];
+                    MeshData {
+                        table: if data.connectivity_used {
+                            &data.table
+                        } else {
+                            &edgebreaker.table
+                        },
+                        encoding: &data.encoding,
+                    }
+                }
+                None => MeshData {
+                    table: &edgebreaker.table,
+                    encoding: &edgebreaker.position_encoding,
+                },
+            }),
+            _ => None,
+        };
+
+        for index in members.clone() {
+            let attribute = &attributes[index];
+            if attribute.decoder == DECODER_GENERIC {
+                let size = data_type_size(attribute.data_type);
+                let bytes = buffer.bytes(
+                    point_ids
+                        .len()
+                        .checked_mul(size * attribute.components)
+                        .context("Draco attribute is too large")?,
+                )?;
+                let values = bytes
+                    .chunks_exact(size)
+                    .map(|value| raw_value(value, attribute.data_type, attribute.normalized))
+                    .collect();
+                attributes[index].values = values;
+                continue;
+            }
+            let components = if attribute.decoder == DECODER_NORMALS {
+                2
+            } else {
+                attribute.components
+            };
+            let positions = attributes
+                .iter()
+                .find(|attribute| attribute.kind == ATTRIBUTE_POSITION)
+                .and_then(|position| {
+                    Some(Positions {
+                        values: position.portable.as_deref()?,
+                        mapping: position.mapping.as_deref(),
+                        components: position.components,
+                    })
+                });
+            let portable = decode_integer_values(
+                buffer,
+                attribute.decoder,
+                components,
+                &point_ids,
+                mesh.as_ref(),
+                positions.as_ref(),
+            )?;
+            attributes[index].portable = Some(portable);
+        }
+
+        for attribute in &mut attributes[members] {
+            let portable = attribute.portable.as_deref().unwrap_or_default();
+            match attribute.decoder {
+                DECODER_INTEGER => {
+                    attribute.values = portable
+                        .iter()
+                        .map(|value| {
+                            integer_value(*value, attribute.data_type, attribute.normalized)
+                        })
+                        .collect::<Result<_>>()?;
+                }
+                DECODER_QUANTIZATION => {
+                    let minimums = (0..attribute.components)
+                        .map(|_| buffer.f32())
+                        .collect::<Result<Vec<_>>>()?;
+                    let range = buffer.f32()?;
+                    let bits = u32::from(buffer.u8()?);
+                    ensure!(
+                        (1..=30).contains(&bits),
+                        "Invalid Draco quantization of {} bits",
+                        bits
+                    );
+                    let delta = range / ((1u32 << bits) - 1) as f32;
+                    attribute.values = portable
+                        .chunks_exact(attribute.components)
+                        .flat_map(|value| {
+                            value
+                                .iter()
+                                .zip(&minimums)
+                                .map(|(value, minimum)| *value as f32 * delta + minimum)
+                        })
+                        .collect();
+                }
+                DECODER_NORMALS => {
+                    let octahedron = Octahedron::new(u32::from(buffer.u8()?))?;
+                    attribute.values = portable
+                        .chunks_exact(2)
+                        .flat_map(|value| octahedron.unit_vector(value[0], value[1]))
+                        .collect();
+                }
+                _ => {}
+            }
+        }
+    }
+    Ok(attributes)
+}
+
+// Runs the traversal of an attributes decoder, returning the point of each decoded
+// value and the value used by each point
+fn traverse_attributes(
+    edgebreaker: &mut Edgebreaker,
+    faces: &[[u32; 3]],
+    num_points: usize,
+    spec: &DecoderSpec,
+) -> Result<(Vec<u32>, Option<Vec<u32>>)> {
+    let (table, encoding) = match spec.data {
+        Some(data) => {
+            let data = &mut edgebreaker.attribute_data[data];
+            let table = if spec.corner {
+                &data.table
+            } else {
+                &edgebreaker.table
+            };
+            (table, &mut data.encoding)
+        }
+        None => (&edgebreaker.table, &mut edgebreaker.position_encoding),
+    };
+    let mut observer = Observer {
+        faces,
+        encoding,
+        point_ids: Vec::new(),
+    };
+    if spec.prediction_degree {
+        traverse_prediction_degree(table, &mut observer)?;
+    } else {
+        traverse_depth_first(table, &mut observer)?;
+    }
+    let Observer {
+        encoding,
+        point_ids,
+        ..
+    } = observer;
+
+    let mut mapping = vec![INVALID; num_points];
+    for (face, points) in faces.iter().enumerate() {
+        for (offset, point) in points.iter().enumerate() {
+            let vertex = table.vertex((3 * face + offset) as u32);
+            let value = *encoding
+                .vertex_to_value
+                .get(vertex as usize)
+                .context("Draco face references a missing vertex")?;
+            ensure!(
+                (*point as usize) < num_points && (value as usize) < num_points,
+                "Draco attribute mapping is out of range"
+            );
+            mapping[*point as usize] = value;
+        }
+    }
+    Ok((point_ids, Some(mapping)))
+}
+
+struct MeshData<'a> {
+    table: &'a CornerTable,
+    encoding: &'a Encoding,
+}
+
+impl MeshData<'_> {
+    fn value(&self, corner: u32) -> Option<usize> {
+        self.encoding
+            .vertex_to_value
+            .get(self.table.vertex(corner) as usize)
+            .map(|value| *value as usize)
+    }
+
+    fn corner(&self, entry: usize) -> Result<u32> {
+        self.encoding
+            .corner_map
+            .get(entry)
+            .copied()
+            .context("Draco prediction references a missing value")
+    }
+
+    // Predicts a value from the triangle across the edge opposite to a corner
+    fn parallelogram(
+        &self,
+        entry: usize,
+        corner: u32,
+        values: &[i32],
+        components: usize,
+        prediction: &mut [i32],
+    ) -> bool {
+        let opposite = self.table.opposite(corner);
+        if opposite == INVALID {
+            return false;
+        }
+        let (Some(opposite), Some(next), Some(previous)) = (
+            self.value(opposite),
+            self.value(next(opposite)),
+            self.value(previous(opposite)),
+        ) else {
+            return false;
+        };
+        if opposite >= entry || next >= entry || previous >= entry {
+            return false;
+        }
+        for (component, prediction) in prediction.iter_mut().enumerate() {
+            let value = |entry: usize| i64::from(values[entry * components + component]);
+            *prediction = (value(next) + value(previous) - value(opposite)) as i32;
+        }
+        true
+    }
+}
+
+struct Positions<'a> {
+    values: &'a [i32],
+    mapping: Option<&'a [u32]>,
+    components: usize,
+}
+
+impl Positions<'_> {
+    fn at(&self, point: u32) -> Result<[i64; 3]> {
+        let value = match self.mapping {
+            Some(mapping) => *mapping
+                .get(point as usize)
+                .context("Draco prediction references a missing point")?
+                as usize,
+            None => point as usize,
+        };
+        let position = self
+            .values
+            .get(value * self.components..value * self.components + 3)
+            .context("Draco prediction references a missing position")?;
+        Ok([
+            i64::from(position[0]),
+            i64::from(position[1]),
+            i64::from(position[2]),
+        ])
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Transform {
+    Wrap {
+        minimum: i32,
+        maximum: i32,
+        range: i32,
+    },
+    Octahedron(Octahedron),
+}
+
+impl Transform {
+    fn decode(buffer: &mut Buffer, octahedron: bool) -> Result<Self> {
+        if octahedron {
+            let maximum = buffer.i32()?;
+            buffer.i32()?;
+            ensure!(
+                maximum > 0 && maximum % 2 == 1,
+                "Invalid Draco octahedron maximum {}",
+                maximum
+            );
+            return Ok(Self::Octahedron(Octahedron::new(
+                32 - maximum.leading_zeros(),
+            )?));
+        }
+        let minimum = buffer.i32()?;
+        let maximum = buffer.i32()?;
+        let difference = i64::from(maximum) - i64::from(minimum);
+        ensure!(
+            (0..i64::from(i32::MAX)).contains(&difference),
+            "Invalid Draco wrap range {}..{}",
+            minimum,
+            maximum
+        );
+        Ok(Self::Wrap {
+            minimum,
+            maximum,
+            range: difference as i32 + 1,
+        })
+    }
+
+    fn restore(&self, prediction: &[i32], correction: &[i32], value: &mut [i32]) {
+        match *self {
+            Self::Wrap {
+                minimum,
+                maximum,
+                range,
+            } => {
+                for ((prediction, correction), value) in
+                    prediction.iter().zip(correction).zip(value.iter_mut())
+                {
+                    let prediction = (*prediction).clamp(minimum, maximum);
+                    *value = prediction.wrapping_add(*correction);
+                    if *value > maximum {
+                        *value = value.wrapping_sub(range);
+                    } else if *value < minimum {
+                        *value = value.wrapping_add(range);
+                    }
+                }
+            }
+            Self::Octahedron(octahedron) => {
+                let restored = octahedron.restore(
+                    [prediction[0], prediction[1]],
+                    [correction[0], correction[1]],
+                );
+                value[..2].copy_from_slice(&restored);
+            }
+        }
+    }
+}
+
+// Quantized octahedral coordinates used for normals
+#[derive(Debug, Clone, Copy)]
+struct Octahedron {
+    max_quantized: i32,
+    max_value: i32,
+    center: i32,
+    scale: f32,
+}
+
+impl Octahedron {
+    fn new(bits: u32) -> Result<Self> {
+        ensure!(
+            (2..=30).contains(&bits),
+            "Invalid Draco normal quantization of {} bits",
+            bits
+        );
+        let max_quantized = (1 << bits) - 1;
+        let max_value = max_quantized - 1;
+        Ok(Self {
+            max_quantized,
+            max_value,
+            center: max_value / 2,
+            scale: 2.0 / max_value as f32,
+        })
+    }
+
+    fn unit_vector(&self, s: i32, t: i32) -> [f32; 3] {
+        let mut y = s as f32 * self.scale - 1.0;
+        let mut z = t as f32 * self.scale - 1.0;
+        let x = 1.0 - y.abs() - z.abs();
+        let offset = (-x).max(0.0);
+        y += if y < 0.0 { offset } else { -offset };
+        z += if z < 0.0 { offset } else { -offset };
+        let norm_squared = x * x + y * y + z * z;
+        if f64::from(norm_squared) < 1e-6 {
+            return [0.0; 3];
+        }
+        let scale = 1.0 / norm_squared.sqrt();
+        [x * scale, y * scale, z * scale]
+    }
+
+    fn canonicalize_coordinates(&self, mut s: i32, mut t: i32) -> [i32; 2] {
+        let (maximum, center) = (self.max_value, self.center);
+        if (s == 0 && (t == 0 || t == maximum)) || (s == maximum && t == 0) {
+            s = maximum;
+            t = maximum;
+        } else if s == 0 && t > center {
+            t = center - (t - center);
+        } else if s == maximum && t < center {
+            t = center + (center - t);
+        } else if t == maximum && s < center {
+            s = center + (center - s);
+        } else if t == 0 && s > center {
+            s = center - (s - center);
+        }
+        [s, t]
+    }
+
+    fn integer_vector_to_coordinates(&self, vector: [i32; 3]) -> [i32; 2] {
+        let (s, t) = if vector[0] >= 0 {
+            (vector[1] + self.center, vector[2] + self.center)
+        } else {
+            (
+                if vector[1] < 0 {
+                    vector[2].abs()
+                } else {
+                    self.max_value - vector[2].abs()
+                },
+                if vector[2] < 0 {
+                    vector[1].abs()
+                } else {
+                    self.max_value - vector[1].abs()
+                },
+            )
+        };
+        self.canonicalize_coordinates(s, t)
+    }
+
+    // Scales an integer vector so that its components sum to the center value
+    fn canonicalize_vector(&self, mut vector: [i32; 3]) -> [i32; 3] {
+        let sum = vector
+            .iter()
+            .map(|component| i64::from(component.unsigned_abs()))
+            .sum::<i64>();
+        if sum == 0 {
+            vector[0] = self.center;
+            return vector;
+        }
+        let center = i64::from(self.center);
+        vector[0] = (i64::from(vector[0]) * center / sum) as i32;
+        vector[1] = (i64::from(vector[1]) * center / sum) as i32;
+        let remainder = self.center - vector[0].abs() - vector[1].abs();
+        vector[2] = if vector[2] >= 0 {
+            remainder
+        } else {
+            -remainder
+        };
+        vector
+    }
+
+    fn in_diamond(&self, [s, t]: [i32; 2]) -> bool {
+        s.unsigned_abs().wrapping_add(t.unsigned_abs()) <= self.center as u32
+    }
+
+    fn invert_diamond(&self, [s, t]: [i32; 2]) -> [i32; 2] {
+        let (sign_s, sign_t) = if s >= 0 && t >= 0 {
+            (1, 1)
+        } else if s <= 0 && t <= 0 {
+            (-1, -1)
+        } else {
+            (if s > 0 { 1 } else { -1 }, if t > 0 { 1 } else { -1 })
+        };
+        let corner_s = (sign_s * self.center) as u32;
+        let corner_t = (sign_t * self.center) as u32;
+        let mut us = (s as u32).wrapping_add(s as u32).wrapping_sub(corner_s);
+        let mut ut = (t as u32).wrapping_add(t as u32).wrapping_sub(corner_t);
+        if sign_s * sign_t >= 0 {
+            (us, ut) = (ut.wrapping_neg(), us.wrapping_neg());
+        } else {
+            (us, ut) = (ut, us);
+        }
+        [
+            (us.wrapping_add(corner_s) as i32) / 2,
+            (ut.wrapping_add(corner_t) as i32) / 2,
+        ]
+    }
+
+    fn mod_max(&self, value: i32) -> i32 {
+        if value > self.center {
+            value - self.max_quantized
+        } else if value < -self.center {
+            value + self.max_quantized
+        } else {
+            value
+        }
+    }
+
+    fn rotate([x, y]: [i32; 2], count: u32) -> [i32; 2] {
+        match count {
+            1 => [y, x.wrapping_neg()],
+            2 => [x.wrapping_neg(), y.wrapping_neg()],
+            3 => [y.wrapping_neg(), x],
+            _ => [x, y],
+        }
+    }
+
+    // Inverse of the canonicalized octahedron correction, which rotates predictions
+    // into the bottom left quadrant before adding the correction
+    fn restore(&self, prediction: [i32; 2], correction: [i32; 2]) -> [i32; 2] {
+        let mut prediction = [
+            prediction[0].wrapping_sub(self.center),
+            prediction[1].wrapping_sub(self.center),
+        ];
+        let in_diamond = self.in_diamond(prediction);
+        if !in_diamond {
+            prediction = self.invert_diamond(prediction);
+        }
+        let [x, y] = prediction;
+        let bottom_left = (x == 0 && y == 0) || (x < 0 && y <= 0);
+        let rotation = match (x.signum(), y.signum()) {
+            (0, 0) => 0,
+            (0, 1) => 3,
+            (0, _) => 1,
+            (1, 0 | 1) => 2,
+            (1, _) => 1,
+            (_, -1 | 0) => 0,
+            _ => 3,
+        };
+        if !bottom_left {
+            prediction = Self::rotate(prediction, rotation);
+        }
+        let mut value = [
+            self.mod_max(prediction[0].wrapping_add(correction[0])),
+            self.mod_max(prediction[1].wrapping_add(correction[1])),
+        ];
+        if !bottom_left {
+            value = Self::rotate(value, (4 - rotation) % 4);
+        }
+        if !in_diamond {
+            value = self.invert_diamond(value);
+        }
+        [
+            value[0].wrapping_add(self.center),
+            value[1].wrapping_add(self.center),
+        ]
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Prediction {
+    Difference,
+    Parallelogram,
+    MultiParallelogram,
+    ConstrainedMultiParallelogram,
+    TexCoords,
+    GeometricNormal,
+}
+
+fn decode_integer_values(
+    buffer: &mut Buffer,
+    decoder: u8,
+    components: usize,
+    point_ids: &[u32],
+    mesh: Option<&MeshData>,
+    positions: Option<&Positions>,
+) -> Result<Vec<i32>> {
+    let method = buffer.i8()?;
+    ensure!(
+        (PREDICTION_NONE..=PREDICTION_GEOMETRIC_NORMAL).contains(&method),
+        "Unknown Draco prediction scheme {}",
+        method
+    );
+    let mut prediction = None;
+    if method != PREDICTION_NONE {
+        let transform = buffer.i8()?;
+        ensure!(
+            (-1..=TRANSFORM_NORMAL_OCTAHEDRON_CANONICALIZED).contains(&transform),
+            "Unknown Draco prediction transform {}",
+            transform
+        );
+        let octahedron = match (decoder, transform) {
+            (DECODER_INTEGER | DECODER_QUANTIZATION, TRANSFORM_WRAP) => Some(false),
+            (DECODER_NORMALS, TRANSFORM_NORMAL_OCTAHEDRON_CANONICALIZED) => Some(true),
+            (DECODER_NORMALS, TRANSFORM_NORMAL_OCTAHEDRON) => {
+                bail!("Draco normals using the legacy octahedron transform aren't supported")
+            }
+            _ => None,
+        };
+        // Mesh predictions fall back to differences without connectivity
+        prediction = octahedron.map(|octahedron| {
+            let scheme = match (mesh.is_some(), octahedron, method) {
+                (true, false, PREDICTION_PARALLELOGRAM) => Prediction::Parallelogram,
+                (true, false, PREDICTION_MULTI_PARALLELOGRAM) => Prediction::MultiParallelogram,
+                (true, false, PREDICTION_CONSTRAINED_MULTI_PARALLELOGRAM) => {
+                    Prediction::ConstrainedMultiParallelogram
+                }
+                (true, false, PREDICTION_TEX_COORDS_DEPRECATED) => return None,
+                (true, false, PREDICTION_TEX_COORDS_PORTABLE) => Prediction::TexCoords,
+                (true, false, PREDICTION_GEOMETRIC_NORMAL) => return None,
+                (true, true, PREDICTION_GEOMETRIC_NORMAL) => Prediction::GeometricNormal,
+                _ => Prediction::Difference,
+            };
+            Some((scheme, octahedron))
+        });
+        if let Some(None) = prediction {
+            bail!("Draco prediction scheme {} isn't supported", method);
+        }
+    }
+    let prediction = prediction.flatten();
+
+    let count = point_ids
+        .len()
+        .checked_mul(components)
+        .context("Draco attribute is too large")?;
+    let mut values = if buffer.u8()? > 0 {
+        decode_symbols(buffer, count, components)?
+            .into_iter()
+            .map(|value| value as i32)
+            .collect::<Vec<_>>()
+    } else {
+        let size = usize::from(buffer.u8()?);
+        ensure!(size <= 4, "Draco values of {} bytes aren't supported", size);
+        let bytes = buffer.bytes(
+            count
+                .checked_mul(size)
+                .context("Draco attribute is too large")?,
+        )?;
+        if size == 0 {
+            vec![0; count]
+        } else {
+            bytes
+                .chunks_exact(size)
+                .map(|value| read_le(value) as i32)
+                .collect()
+        }
+    };
+
+    let Some((scheme, octahedron)) = prediction else {
+        return Ok(values
+            .into_iter()
+            .map(|value| to_signed(value as u32))
+            .collect());
+    };
+    if count > 0 && !octahedron {
+        values = values
+            .into_iter()
+            .map(|value| to_signed(value as u32))
+            .collect();
+    }
+
+    let mut creases: [Vec<bool>; 4] = Default::default();
+    let mut orientations = Vec::new();
+    let mut flips = None;
+    if scheme == Prediction::ConstrainedMultiParallelogram {
+        let num_corners = mesh.map_or(0, |mesh| mesh.table.num_corners());
+        for context in &mut creases {
+            let count = buffer.varint()? as usize;
+            ensure!(count <= num_corners, "Draco crease flags exceed the mesh");
+            if count > 0 {
+                let mut bits = RansBits::start(buffer)?;
+                *context = (0..count).map(|_| bits.bit()).collect();
+            }
+        }
+    }
+    if scheme == Prediction::TexCoords {
+        let count = buffer.i32()?;
+        ensure!(count >= 0, "Invalid Draco texture coordinate orientations");
+        let mut bits = RansBits::start(buffer)?;
+        let mut last = true;
+        for _ in 0..count {
+            if !bits.bit() {
+                last = !last;
+            }
+            orientations.push(last);
+        }
+    }
+    let transform = Transform::decode(buffer, octahedron)?;
+    if scheme == Prediction::GeometricNormal {
+        flips = Some(RansBits::start(buffer)?);
+    }
+    if count == 0 {
+        return Ok(values);
+    }
+
+    let corrections = values.clone();
+    let entries = count / components;
+    let mut predicted = vec![0; components];
+    let restore = |values: &mut [i32], entry: usize, predicted: &[i32]| {
+        let range = entry * components..(entry + 1) * components;
+        transform.restore(predicted, &corrections[range.clone()], &mut values[range]);
+    };
+    let from_previous = |values: &[i32], entry: usize, predicted: &mut [i32]| {
+        predicted.copy_from_slice(&values[(entry - 1) * components..entry * components]);
+    };
+
+    match (scheme, mesh) {
+        (Prediction::Difference, _) => {
+            restore(&mut values, 0, &predicted);
+            for entry in 1..entries {
+                from_previous(&values, entry, &mut predicted);
+                restore(&mut values, entry, &predicted);
+            }
+        }
+        (Prediction::Parallelogram, Some(mesh)) => {
+            restore(&mut values, 0, &predicted);
+            for entry in 1..entries {
+                let corner = mesh.corner(entry)?;
+                if !mesh.parallelogram(entry, corner, &values, components, &mut predicted) {
+                    from_previous(&values, entry, &mut predicted);
+                }
+                restore(&mut values, entry, &predicted);
+            }
+        }
+        (Prediction::MultiParallelogram, Some(mesh)) => {
+            let mut parallelogram = vec![0; components];
+            restore(&mut values, 0, &predicted);
+            for entry in 1..entries {
+                let start = mesh.corner(entry)?;
+                let mut corner = start;
+                let mut count = 0;
+                predicted.fill(0);
+                while corner != INVALID {
+                    if mesh.parallelogram(entry, corner, &values, components, &mut parallelogram) {
+                        for (sum, value) in predicted.iter_mut().zip(&parallelogram) {
+                            *sum = sum.wrapping_add(*value);
+                        }
+                        count += 1;
+                    }
+                    corner = mesh.table.swing_right(corner);
+                    if corner == start {
+                        break;
+                    }
+                }
+                if count == 0 {
+                    from_previous(&values, entry, &mut predicted);
+                } else {
+                    predicted.iter_mut().for_each(|sum| *sum /= count);
+                }
+                restore(&mut values, entry, &predicted);
+            }
+        }
+        (Prediction::ConstrainedMultiParallelogram, Some(mesh)) => {
+            let mut parallelograms = vec![vec![0; components]; 4];
+            let mut positions = [0; 4];
+            restore(&mut values, 0, &predicted);
+            for entry in 1..entries {
+                let start = mesh.corner(entry)?;
+                let mut corner = start;
+                let mut count = 0;
+                let mut first_pass = true;
+                while corner != INVALID {
+                    if mesh.parallelogram(
+                        entry,
+                        corner,
+                        &values,
+                        components,
+                        &mut parallelograms[count],
+                    ) {
+                        count += 1;
+                        if count == 4 {
+                            break;
+                        }
+                    }
+                    corner = if first_pass {
+                        mesh.table.swing_left(corner)
+                    } else {
+                        mesh.table.swing_right(corner)
+                    };
+                    if corner == start {
+                        break;
+                    }
+                    if corner == INVALID && first_pass {
+                        first_pass = false;
+                        corner = mesh.table.swing_right(start);
+                    }
+                }
+
+                // Parallelograms across crease edges are left out of the average
+                let mut used = 0;
+                predicted.fill(0);
+                for parallelogram in &parallelograms[..count] {
+                    let context = count - 1;
+                    let crease = *creases[context]
+                        .get(positions[context])
+                        .context("Draco crease flags ran out")?;
+                    positions[context] += 1;
+                    if !crease {
+                        used += 1;
+                        for (sum, value) in predicted.iter_mut().zip(parallelogram) {
+                            *sum = sum.wrapping_add(*value);
+                        }
+                    }
+                }
+                if used == 0 {
+                    from_previous(&values, entry, &mut predicted);
+                } else {
+                    predicted.iter_mut().for_each(|sum| *sum /= used);
+                }
+                restore(&mut values, entry, &predicted);
+            }
+        }
+        (Prediction::TexCoords, Some(mesh)) => {
+            ensure!(
+                components == 2,
+                "Draco texture coordinates must have two components"
+            );
+            let positions = positions.context("Draco texture coordinates need positions")?;
+            for entry in 0..entries {
+                let corner = mesh.corner(entry)?;
+                let uv = predict_tex_coords(
+                    mesh,
+                    positions,
+                    point_ids,
+                    &values,
+                    entry,
+                    corner,
+                    &mut orientations,
+                )?;
+                restore(&mut values, entry, &uv);
+            }
+        }
+        (Prediction::GeometricNormal, Some(mesh)) => {
+            let (Transform::Octahedron(octahedron), Some(flips)) = (transform, flips.as_mut())
+            else {
+                bail!("Draco geometric normal prediction needs an octahedron transform");
+            };
+            let positions = positions.context("Draco normal prediction needs positions")?;
+            let position = |corner: u32| -> Result<[i64; 3]> {
+                let value = mesh
+                    .value(corner)
+                    .context("Draco prediction references a missing vertex")?;
+                positions.at(*point_ids
+                    .get(value)
+                    .context("Draco prediction references a missing point")?)
+            };
+            for entry in 0..entries {
+                let corner = mesh.corner(entry)?;
+                let center = position(corner)?;
+                let mut normal = [0i64; 3];
+                for around in mesh.table.vertex_corners(corner) {
+                    let next = position(next(around))?;
+                    let previous = position(previous(around))?;
+                    let delta_next = [0, 1, 2].map(|axis| next[axis].wrapping_sub(center[axis]));
+                    let delta_previous =
+                        [0, 1, 2].map(|axis| previous[axis].wrapping_sub(center[axis]));
+                    let cross = [
+                        delta_next[1]
+                            .wrapping_mul(delta_previous[2])
+                            .wrapping_sub(delta_next[2].wrapping_mul(delta_previous[1])),
+                        delta_next[2]
+                            .wrapping_mul(delta_previous[0])
+                            .wrapping_sub(delta_next[0].wrapping_mul(delta_previous[2])),
+                        delta_next[0]
+                            .wrapping_mul(delta_previous[1])
+                            .wrapping_sub(delta_next[1].wrapping_mul(delta_previous[0])),
+                    ];
+                    for (sum, value) in normal.iter_mut().zip(cross) {
+                        *sum = sum.wrapping_add(value);
+                    }
+                }
+                let upper_bound = 1i64 << 29;
+                let sum = normal
+                    .iter()
+                    .fold(0i64, |sum, value| sum.wrapping_add(value.wrapping_abs()));
+                if sum > upper_bound {
+                    let quotient = sum / upper_bound;
+                    normal = normal.map(|value| value / quotient);
+                }
+                let mut vector = octahedron.canonicalize_vector(normal.map(|value| value as i32));
+                if flips.bit() {
+                    vector = vector.map(i32::wrapping_neg);
+                }
+                let coordinates = octahedron.integer_vector_to_coordinates(vector);
+                restore(&mut values, entry, &coordinates);
+            }
+        }
+        _ => bail!("Draco mesh prediction needs edgebreaker connectivity"),
+    }
+    Ok(values)
+}
+
+fn to_signed(value: u32) -> i32 {
+    let magnitude = (value >> 1) as i32;
+    if value & 1 != 0 {
+        -magnitude - 1
+    } else {
+        magnitude
+    }
+}
+
+fn integer_sqrt(number: u64) -> u64 {
+    if number == 0 {
+        return 0;
+    }
+    let mut remaining = number;
+    let mut root = 1u64;
+    while remaining >= 2 {
+        root *= 2;
+        remaining /= 4;
+    }
+    loop {
+        root = (root + number / root) / 2;
+        if root.wrapping_mul(root) <= number {
+            return root;
+        }
+    }
+}
+
+// Predicts a texture coordinate by projecting the triangle's positions onto the
+// known coordinates of its other two corners
+fn predict_tex_coords(
+    mesh: &MeshData,
+    positions: &Positions,
+    point_ids: &[u32],
+    values: &[i32],
+    entry: usize,
+    corner: u32,
+    orientations: &mut Vec<bool>,
+) -> Result<[i32; 2]> {
+    let missing = || anyhow::anyhow!("Draco prediction references a missing value");
+    let next_entry = mesh.value(next(corner)).ok_or_else(missing)?;
+    let previous_entry = mesh.value(previous(corner)).ok_or_else(missing)?;
+    let uv = |entry: usize| {
+        [
+            i64::from(values[2 * entry]),
+            i64::from(values[2 * entry + 1]),
+        ]
+    };
+    let position = |entry: usize| -> Result<[i64; 3]> {
+        positions.at(*point_ids.get(entry).ok_or_else(missing)?)
+    };
+
+    if previous_entry < entry && next_entry < entry {
+        let next_uv = uv(next_entry);
+        let previous_uv = uv(previous_entry);
+        if previous_uv == next_uv {
+            return Ok([previous_uv[0] as i32, previous_uv[1] as i32]);
+        }
+        let tip = position(entry)?;
+        let next = position(next_entry)?;
+        let previous = position(previous_entry)?;
+        let pn = [0, 1, 2].map(|axis| previous[axis].wrapping_sub(next[axis]));
+        let pn_norm_squared = pn.iter().fold(0u64, |sum, value| {
+            sum.wrapping_add((value.wrapping_mul(*value)) as u64)
+        });
+        if pn_norm_squared != 0 {
+            let cn = [0, 1, 2].map(|axis| tip[axis].wrapping_sub(next[axis]));
+            let cn_dot_pn = (0..3).fold(0i64, |sum, axis| {
+                sum.wrapping_add(pn[axis].wrapping_mul(cn[axis]))
+            });
+            let pn_uv = [
+                previous_uv[0].wrapping_sub(next_uv[0]),
+                previous_uv[1].wrapping_sub(next_uv[1]),
+            ];
+            let overflow = || anyhow::anyhow!("Draco texture coordinate prediction overflows");
+            let next_uv_max = next_uv[0].abs().max(next_uv[1].abs());
+            ensure!(next_uv_max <= i64::MAX / pn_norm_squared as i64, overflow());
+            let pn_uv_max = pn_uv[0].abs().max(pn_uv[1].abs());
+            ensure!(cn_dot_pn.abs() <= i64::MAX / pn_uv_max, overflow());
+            let pn_max = pn[0].abs().max(pn[1].abs()).max(pn[2].abs());
+            ensure!(cn_dot_pn.abs() <= i64::MAX / pn_max, overflow());
+
+            let norm = pn_norm_squared as i64;
+            let x_uv = [0, 1].map(|axis| {
+                next_uv[axis]
+                    .wrapping_mul(norm)
+                    .wrapping_add(cn_dot_pn.wrapping_mul(pn_uv[axis]))
+            });
+            let x_position = [0, 1, 2]
+                .map(|axis| next[axis].wrapping_add(cn_dot_pn.wrapping_mul(pn[axis]) / norm));
+            let cx_norm_squared = (0..3).fold(0u64, |sum, axis| {
+                let delta = tip[axis].wrapping_sub(x_position[axis]);
+                sum.wrapping_add(delta.wrapping_mul(delta) as u64)
+            });
+            let root = integer_sqrt(cx_norm_squared.wrapping_mul(pn_norm_squared)) as i64;
+            let cx_uv = [
+                pn_uv[1].wrapping_mul(root),
+                pn_uv[0].wrapping_neg().wrapping_mul(root),
+            ];
+
+            let orientation = orientations
+                .pop()
+                .context("Draco texture coordinate orientations ran out")?;
+            let predicted = [0, 1].map(|axis| {
+                let value = if orientation {
+                    (x_uv[axis] as u64).wrapping_add(cx_uv[axis] as u64)
+                } else {
+                    (x_uv[axis] as u64).wrapping_sub(cx_uv[axis] as u64)
+                };
+                ((value as i64) / norm) as i32
+            });
+            return Ok(predicted);
+        }
+    }
+
+    let source = if next_entry < entry {
+        next_entry
+    } else if entry > 0 {
+        entry - 1
+    } else {
+        return Ok([0, 0]);
+    };
+    Ok([values[2 * source], values[2 * source + 1]])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Octahedron with per-face texture coordinates, encoded by the reference
+    // encoder with 10 bit positions and texture coordinates and 8 bit normals
+    const POSITIONS: [[f32; 3]; 6] = [
+        [1.0, 0.0, 0.0],
+        [-1.0, 0.0, 0.0],
+        [0.0, 1.0, 0.0],
+        [0.0, -1.0, 0.0],
+        [0.0, 0.0, 1.0],
+        [0.0, 0.0, -1.0],
+    ];
+    const TEX_COORDS: [[f32; 2]; 4] = [[0.0, 0.0], [1.0, 0.0], [0.5, 1.0], [0.25, 0.5]];
+    const FACES: [[(usize, usize); 3]; 8] = [
+        [(0, 0), (2, 1), (4, 2)],
+        [(2, 0), (1, 1), (4, 2)],
+        [(1, 0), (3, 1), (4, 2)],
+        [(3, 0), (0, 1), (4, 2)],
+        [(2, 0), (0, 1), (5, 3)],
+        [(1, 0), (2, 1), (5, 3)],
+        [(3, 0), (1, 1), (5, 3)],
+        [(0, 0), (3, 1), (5, 3)],
+    ];
+
+    // Sequential connectivity with quantized positions, texture coordinates and normals
+    const SEQUENTIAL: &[u8] = &[
+        0x44, 0x52, 0x41, 0x43, 0x4f, 0x02, 0x02, 0x01, 0x00, 0x00, 0x00, 0x08, 0x0a, 0x01, 0x00,
+        0x01, 0x02, 0x03, 0x04, 0x02, 0x05, 0x06, 0x02, 0x07, 0x08, 0x02, 0x03, 0x08, 0x09, 0x05,
+        0x01, 0x09, 0x07, 0x04, 0x09, 0x00, 0x06, 0x09, 0x01, 0x03, 0x00, 0x09, 0x03, 0x00, 0x00,
+        0x03, 0x09, 0x02, 0x00, 0x01, 0x01, 0x09, 0x03, 0x00, 0x02, 0x02, 0x02, 0x03, 0x00, 0x01,
+        0x01, 0x01, 0x01, 0x80, 0x08, 0xb9, 0x1b, 0x25, 0x02, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+        0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xeb, 0xad, 0x0a, 0x89, 0x08, 0xf1,
+        0x0e, 0x0a, 0xd1, 0x02, 0x87, 0xdd, 0xa1, 0xcf, 0x6b, 0x35, 0x97, 0x83, 0x00, 0x00, 0x00,
+        0x00, 0xff, 0x03, 0x00, 0x00, 0x00, 0x01, 0x01, 0x00, 0x0b, 0x03, 0x01, 0x20, 0xcd, 0x0c,
+        0x1b, 0x35, 0x13, 0x04, 0xb2, 0x25, 0xa4, 0x9e, 0xd4, 0x7f, 0x00, 0xff, 0x0b, 0x90, 0x24,
+        0x09, 0xf8, 0x3f, 0x00, 0x00, 0x00, 0x00, 0xff, 0x03, 0x00, 0x00, 0x00, 0x03, 0x01, 0x01,
+        0x01, 0x81, 0x01, 0xcd, 0x1c, 0xff, 0xf7, 0x69, 0x16, 0xcd, 0x0c, 0x06, 0x62, 0x16, 0x53,
+        0x43, 0x6b, 0x94, 0xff, 0x00, 0x00, 0x00, 0x7f, 0x00, 0x00, 0x00, 0x00, 0x00, 0x80, 0xbf,
+        0x00, 0x00, 0x80, 0xbf, 0x00, 0x00, 0x80, 0xbf, 0x00, 0x00, 0x00, 0x40, 0x0a, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x80, 0x3f, 0x0a, 0x08,
+    ];
+
+    // Standard edgebreaker connectivity with parallelogram prediction
+    const EDGEBREAKER: &[u8] = &[
+        0x44, 0x52, 0x41, 0x43, 0x4f, 0x02, 0x02, 0x01, 0x01, 0x00, 0x00, 0x00, 0x18, 0x08, 0x00,
+        0x08, 0x00, 0x00, 0x03, 0xff, 0xff, 0xff, 0xff, 0x02, 0x88, 0x40, 0x01, 0xff, 0x00, 0x00,
+        0x03, 0x00, 0x09, 0x03, 0x00, 0x00, 0x03, 0x09, 0x02, 0x00, 0x01, 0x01, 0x09, 0x03, 0x00,
+        0x02, 0x02, 0x02, 0x03, 0x01, 0x01, 0x01, 0x01, 0x02, 0x80, 0x08, 0x55, 0x1d, 0xc9, 0x01,
+        0xe4, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+        0xff, 0xe7, 0x01, 0x08, 0x01, 0x08, 0x01, 0x10, 0x14, 0xc2, 0x81, 0xc0, 0x4f, 0x56, 0x95,
+        0xa4, 0x0e, 0xd5, 0x5c, 0x25, 0x86, 0x1b, 0x35, 0x60, 0xf6, 0xd4, 0x6c, 0x4f, 0xb7, 0x00,
+        0x00, 0x00, 0x00, 0xff, 0x03, 0x00, 0x00, 0x01, 0x01, 0x01, 0x01, 0x02, 0x80, 0x08, 0xad,
+        0x0a, 0x01, 0x10, 0x55, 0x05, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xef, 0x55, 0x05,
+        0x07, 0x55, 0x05, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xe7, 0x55, 0x05, 0x03, 0x05,
+        0x10, 0x12, 0xa4, 0xe1, 0x15, 0x40, 0x6e, 0x48, 0xe9, 0xa3, 0x1e, 0x29, 0x8d, 0xd6, 0xb9,
+        0x85, 0x8d, 0x73, 0x04, 0x94, 0x00, 0x00, 0x00, 0x00, 0xff, 0x03, 0x00, 0x00, 0x00, 0x03,
+        0x01, 0x01, 0x02, 0xff, 0x01, 0x55, 0x15, 0xff, 0xf7, 0x55, 0x1d, 0xad, 0x0a, 0xff, 0xf3,
+        0xad, 0x02, 0x0c, 0x4a, 0x1c, 0x6f, 0x76, 0xb1, 0xf0, 0xe9, 0xb6, 0x83, 0x3e, 0x9f, 0xba,
+        0xff, 0x00, 0x00, 0x00, 0x7f, 0x00, 0x00, 0x00, 0x00, 0x00, 0x80, 0xbf, 0x00, 0x00, 0x80,
+        0xbf, 0x00, 0x00, 0x80, 0xbf, 0x00, 0x00, 0x00, 0x40, 0x0a, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x80, 0x3f, 0x0a, 0x08,
+    ];
+
+    // Valence edgebreaker connectivity with constrained multi-parallelogram, portable texture coordinate and geometric normal prediction
+    const VALENCE: &[u8] = &[
+        0x44, 0x52, 0x41, 0x43, 0x4f, 0x02, 0x02, 0x01, 0x01, 0x00, 0x00, 0x00, 0x06, 0x08, 0x02,
+        0x07, 0x00, 0x00, 0x02, 0x6f, 0x15, 0x01, 0x01, 0x10, 0x01, 0x02, 0xc0, 0x40, 0xff, 0x02,
+        0xcc, 0x40, 0x03, 0xff, 0x00, 0x01, 0x00, 0x01, 0x00, 0x01, 0x00, 0x00, 0x01, 0x00, 0x09,
+        0x03, 0x00, 0x00, 0x02, 0x01, 0x03, 0x09, 0x02, 0x00, 0x01, 0x02, 0x01, 0x01, 0x09, 0x03,
+        0x00, 0x02, 0x03, 0x01, 0x01, 0x01, 0x01, 0x04, 0x80, 0x08, 0x55, 0x15, 0xff, 0xff, 0xff,
+        0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xef, 0x1d, 0x07,
+        0x03, 0x91, 0x23, 0x05, 0x48, 0x1b, 0x36, 0xf1, 0x58, 0x00, 0x00, 0x00, 0x00, 0xff, 0x03,
+        0x00, 0x00, 0x00, 0x00, 0x80, 0xbf, 0x00, 0x00, 0x80, 0xbf, 0x00, 0x00, 0x80, 0xbf, 0x00,
+        0x00, 0x00, 0x40, 0x0a, 0x05, 0x01, 0x01, 0x00, 0x0b, 0x03, 0x01, 0x08, 0xad, 0x02, 0x13,
+        0xad, 0x0a, 0x03, 0xa9, 0x2a, 0x07, 0x60, 0x2f, 0xb8, 0xd8, 0x98, 0x4f, 0x80, 0x00, 0xfe,
+        0xff, 0xdf, 0xff, 0x88, 0x0f, 0x21, 0xe0, 0xff, 0xff, 0xfd, 0x8f, 0x78, 0x10, 0x02, 0xfe,
+        0xff, 0xdf, 0xff, 0x8a, 0x0f, 0x21, 0xfd, 0xb7, 0x6e, 0x80, 0xff, 0xf7, 0x1f, 0x00, 0xc0,
+        0x62, 0xfd, 0x07, 0x00, 0x90, 0x58, 0xff, 0x01, 0x00, 0x1c, 0xfe, 0x1f, 0xc0, 0xff, 0x02,
+        0x00, 0x1c, 0x02, 0x08, 0x00, 0x00, 0x00, 0x20, 0x03, 0xe0, 0x31, 0x81, 0x00, 0x00, 0x00,
+        0x00, 0xff, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x80, 0x3f, 0x0a, 0x06, 0x03, 0x01, 0x01, 0x03, 0x01, 0x01, 0x40, 0x01, 0x00, 0xff, 0x00,
+        0x00, 0x00, 0x7f, 0x00, 0x00, 0x00, 0xff, 0x02, 0x66, 0x40, 0x08,
+    ];
+    fn close(left: &[f32], right: &[f32]) -> bool {
+        left.iter()
+            .zip(right)
+            .all(|(left, right)| (left - right).abs() < 0.01)
+    }
+
+    fn assert_octahedron(data: &[u8]) {
+        let mesh = decode(data).unwrap();
+        let positions = mesh.attribute(0).unwrap().points::<3>();
+        let tex_coords = mesh.attribute(1).unwrap().points::<2>();
+        let normals = mesh.attribute(2).unwrap().points::<3>();
+        assert_eq!(mesh.indices.len(), FACES.len() * 3);
+
+        let mut matched = [false; FACES.len()];
+        for face in mesh.indices.chunks_exact(3) {
+            let corner = |index: usize| {
+                let point = face[index % 3] as usize;
+                (positions[point], tex_coords[point], normals[point])
+            };
+            let found = FACES.iter().position(|expected| {
+                (0..3).any(|rotation| {
+                    expected
+                        .iter()
+                        .enumerate()
+                        .all(|(index, (position, tex_coord))| {
+                            let (decoded_position, decoded_tex_coord, decoded_normal) =
+                                corner(index + rotation);
+                            close(&decoded_position, &POSITIONS[*position])
+                                && close(&decoded_tex_coord, &TEX_COORDS[*tex_coord])
+                                && close(&decoded_normal, &POSITIONS[*position])
+                        })
+                })
+            });
+            let found = found.expect("decoded face isn't part of the octahedron");
+            assert!(!matched[found]);
+            matched[found] = true;
+        }
+    }
+
+    #[test]
+    fn decodes_sequential_mesh() {
+        assert_octahedron(SEQUENTIAL);
+    }
+
+    #[test]
+    fn decodes_edgebreaker_mesh() {
+        assert_octahedron(EDGEBREAKER);
+    }
+
+    #[test]
+    fn decodes_valence_edgebreaker_mesh() {
+        assert_octahedron(VALENCE);
+    }
+
+    #[test]
+    fn rejects_invalid_data() {
+        assert!(decode(b"PLY").is_err());
+        for data in [SEQUENTIAL, EDGEBREAKER, VALENCE] {
+            for length in (0..data.len()).step_by(7) {
+                let _ = decode(&data[..length]);
+            }
+            assert!(decode(&data[..data.len() / 2]).is_err());
+        }
+    }
+
+    #[test]
+    fn decodes_primitives() {
+        let mut buffer = Buffer::new(&[0xac, 0x02, 0x7f, 0b1011_0101, 0xff]);
+        assert_eq!(buffer.varint().unwrap(), 300);
+        assert_eq!(buffer.varint().unwrap(), 127);
+        let mut bits = buffer.start_bits();
+        assert_eq!(bits.bits(3).unwrap(), 0b101);
+        assert_eq!(bits.bits(5).unwrap(), 0b10110);
+        assert_eq!(bits.bits(1).unwrap(), 1);
+        buffer.end_bits(&bits).unwrap();
+        assert!(buffer.remaining().is_empty());
+
+        assert_eq!(to_signed(0), 0);
+        assert_eq!(to_signed(1), -1);
+        assert_eq!(to_signed(4), 2);
+        assert_eq!(integer_sqrt(0), 0);
+        assert_eq!(integer_sqrt(99), 9);
+        assert_eq!(integer_sqrt(1 << 40), 1 << 20);
+    }
+}
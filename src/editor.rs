@@ -0,0 +1,803 @@
+use nalgebra_glm as glm;
+
+use crate::{
+    bvh::SceneHit,
+    commands::{
+        AddLight, AddModel, Command, CommandHistory, LightTarget, RemoveLight, RemoveModel,
+        SetLayers, SetLight, SetMaterial, SetSun, SetTransform, TransformTarget,
+    },
+    debug::{layer_settings, material_editor},
+    layers,
+    lighting::{DirectionalLight, LightUnit, PunctualLight, PunctualLightKind},
+    material::Material,
+    model::{Joint, ModelDesc},
+    renderer::Renderer,
+    scene::{LightHandle, MaterialHandle, ModelHandle, Scene},
+    skeletons::JointHandle,
+};
+
+// What the hierarchy lists and the inspector edits
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SceneNode {
+    Sun,
+    Light(LightHandle),
+    Model(ModelHandle),
+    // A placement of one of the model's meshes, which picking selects
+    Instance { model: ModelHandle, instance: usize },
+    // Lights carried along with a model, such as those imported from glTF
+    ModelLight { model: ModelHandle, light: usize },
+    Joint(JointHandle),
+}
+
+impl SceneNode {
+    fn model(self) -> Option<ModelHandle> {
+        match self {
+            Self::Model(model) | Self::Instance { model, .. } | Self::ModelLight { model, .. } => {
+                Some(model)
+            }
+            Self::Joint(joint) => Some(joint.model),
+            Self::Sun | Self::Light(_) => None,
+        }
+    }
+
+    fn transform_target(self) -> Option<TransformTarget> {
+        match self {
+            Self::Model(model) => Some(TransformTarget::Model(model)),
+            Self::Instance { model, instance } => {
+                Some(TransformTarget::Instance { model, instance })
+            }
+            _ => None,
+        }
+    }
+
+    fn light_target(self) -> Option<LightTarget> {
+        match self {
+            Self::Light(light) => Some(LightTarget::Scene(light)),
+            Self::ModelLight { model, light } => Some(LightTarget::Model { model, light }),
+            _ => None,
+        }
+    }
+}
+
+// The values the inspector can change on a node, compared before and after it's drawn to find
+// what an edit changed
+#[derive(Clone, PartialEq)]
+struct NodeState {
+    transform: Option<glm::DMat4>,
+    layers: Option<u32>,
+    materials: Vec<(usize, Material)>,
+    light: Option<PunctualLight>,
+    sun: Option<DirectionalLight>,
+}
+
+impl NodeState {
+    fn of(node: SceneNode, scene: &Scene) -> Option<Self> {
+        let mut state = Self {
+            transform: None,
+            layers: None,
+            materials: Vec::new(),
+            light: None,
+            sun: None,
+        };
+        match node {
+            SceneNode::Sun => state.sun = Some(scene.sun),
+            SceneNode::Light(light) => state.light = Some(*scene.lights.get(light.0)?),
+            SceneNode::Model(model) => {
+                let model = scene.models.get(model.0)?;
+                state.transform = Some(model.transform);
+                state.materials = model.materials.iter().cloned().enumerate().collect();
+            }
+            SceneNode::Instance { model, instance } => {
+                let model = scene.models.get(model.0)?;
+                let placed = model.instances.get(instance)?;
+                state.transform = Some(placed.transform.cast());
+                state.layers = Some(placed.layers);
+                state.materials = instance_materials(model, instance)
+                    .into_iter()
+                    .map(|index| (index, model.materials[index].clone()))
+                    .collect();
+            }
+            SceneNode::ModelLight { model, light } => {
+                state.light = Some(*scene.models.get(model.0)?.lights.get(light)?);
+            }
+            SceneNode::Joint(_) => {}
+        }
+        Some(state)
+    }
+
+    // The edits that turn this state into the other one
+    fn commands(self, node: SceneNode, after: Self) -> Vec<Box<dyn Command>> {
+        let mut commands: Vec<Box<dyn Command>> = Vec::new();
+        if let (Some(target), Some(before), Some(after)) =
+            (node.transform_target(), self.transform, after.transform)
+        {
+            if before != after {
+                commands.push(Box::new(SetTransform {
+                    target,
+                    before,
+                    after,
+                }));
+            }
+        }
+        if let (SceneNode::Instance { model, instance }, Some(before), Some(after)) =
+            (node, self.layers, after.layers)
+        {
+            if before != after {
+                commands.push(Box::new(SetLayers {
+                    model,
+                    instance,
+                    before,
+                    after,
+                }));
+            }
+        }
+        if let Some(model) = node.model() {
+            for ((material, before), (_, after)) in self.materials.into_iter().zip(after.materials)
+            {
+                if before != after {
+                    commands.push(Box::new(SetMaterial {
+                        model,
+                        material,
+                        before,
+                        after,
+                    }));
+                }
+            }
+        }
+        if let (Some(target), Some(before), Some(after)) =
+            (node.light_target(), self.light, after.light)
+        {
+            if before != after {
+                commands.push(Box::new(SetLight {
+                    target,
+                    before,
+                    after,
+                }));
+            }
+        }
+        if let (Some(before), Some(after)) = (self.sun, after.sun) {
+            if before != after {
+                commands.push(Box::new(SetSun { before, after }));
+            }
+        }
+        commands
+    }
+}
+
+// Only the materials the instance's mesh is drawn with
+fn instance_materials(model: &ModelDesc, instance: usize) -> Vec<usize> {
+    let mut materials = model
+        .instances
+        .get(instance)
+        .and_then(|instance| model.meshes.get(instance.mesh))
+        .map(|mesh| {
+            mesh.primitives
+                .iter()
+                .filter_map(|primitive| primitive.material)
+                .filter(|material| *material < model.materials.len())
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+    materials.sort_unstable();
+    materials.dedup();
+    materials
+}
+
+// Structural edits picked in the panels, made after they're drawn
+enum EditorAction {
+    Undo,
+    Redo,
+    AddLight,
+    Duplicate(ModelHandle),
+    RemoveModel(ModelHandle),
+    RemoveLight(LightHandle),
+}
+
+// A translation, rotation and scale edited separately and composed into a matrix, with the
+// rotation in degrees about X, then Y, then Z
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Transform {
+    pub translation: glm::Vec3,
+    pub rotation: glm::Vec3,
+    pub scale: glm::Vec3,
+}
+
+impl Transform {
+    // Shear is lost, which transforms built from these components never have
+    pub fn from_matrix(matrix: &glm::Mat4) -> Self {
+        let translation = matrix.column(3).xyz();
+        let columns = [0, 1, 2].map(|index| matrix.column(index).xyz());
+        let scale = glm::vec3(
+            columns[0].magnitude(),
+            columns[1].magnitude(),
+            columns[2].magnitude(),
+        );
+        let [x, y, z] = [0, 1, 2].map(|index| columns[index] / scale[index].max(f32::EPSILON));
+        // Decomposes R = Rz * Ry * Rx
+        let pitch = (-x.z).clamp(-1.0, 1.0).asin();
+        let (roll, yaw) = if x.z.abs() < 0.9999 {
+            (y.z.atan2(z.z), x.y.atan2(x.x))
+        } else {
+            // Gimbal lock, where roll and yaw turn about the same axis
+            ((-z.y).atan2(y.y), 0.0)
+        };
+        Self {
+            translation,
+            rotation: glm::vec3(roll, pitch, yaw).map(f32::to_degrees),
+            scale,
+        }
+    }
+
+    pub fn matrix(&self) -> glm::Mat4 {
+        let radians = self.rotation.map(f32::to_radians);
+        let rotation = glm::rotation(radians.z, &glm::Vec3::z())
+            * glm::rotation(radians.y, &glm::Vec3::y())
+            * glm::rotation(radians.x, &glm::Vec3::x());
+        glm::translation(&self.translation) * rotation * glm::scaling(&self.scale)
+    }
+}
+
+// A hierarchy of the scene's models and lights and an inspector for the selected one. The
+// selection follows picking, and selecting an instance picks it. Edits go through the history
+// so they can be undone
+#[derive(Default)]
+pub struct Editor {
+    pub selected: Option<SceneNode>,
+    // The last picked model and instance, to notice when picking selects something else
+    picked: Option<(ModelHandle, usize)>,
+    pub history: CommandHistory,
+    // The node being edited and its state before the edit began. A drag changes values every
+    // frame, and is recorded as one command once the pointer is released
+    edit: Option<(SceneNode, NodeState)>,
+}
+
+impl Editor {
+    pub fn show(&mut self, context: &egui::CtxRef, renderer: &mut Renderer, scene: &mut Scene) {
+        // Removing a model or undoing its addition leaves the pick pointing past the scene
+        if let Some(selection) = renderer.selection {
+            let exists = scene
+                .models
+                .get(selection.model.0)
+                .is_some_and(|model| selection.instance < model.instances.len());
+            if !exists {
+                renderer.selection = None;
+            }
+        }
+        let picked = renderer
+            .selection
+            .map(|selection| (selection.model, selection.instance));
+        if picked != self.picked {
+            self.picked = picked;
+            if let Some((model, instance)) = picked {
+                self.selected = Some(SceneNode::Instance { model, instance });
+            }
+        }
+
+        let mut action = None;
+        egui::Window::new("Hierarchy").show(context, |ui| {
+            ui.horizontal(|ui| {
+                let undo = self.history.undo_name();
+                let redo = self.history.redo_name();
+                if ui
+                    .add_enabled(undo.is_some(), egui::Button::new("Undo"))
+                    .on_hover_text(format!("{} (Ctrl+Z)", undo.unwrap_or_default()))
+                    .clicked()
+                {
+                    action = Some(EditorAction::Undo);
+                }
+                if ui
+                    .add_enabled(redo.is_some(), egui::Button::new("Redo"))
+                    .on_hover_text(format!("{} (Ctrl+Y)", redo.unwrap_or_default()))
+                    .clicked()
+                {
+                    action = Some(EditorAction::Redo);
+                }
+                if ui.button("Add light").clicked() {
+                    action = Some(EditorAction::AddLight);
+                }
+            });
+            ui.separator();
+            if let Some(node) = self.hierarchy(ui, scene) {
+                self.select(node, renderer, scene);
+            }
+        });
+        egui::Window::new("Inspector").show(context, |ui| match self.selected {
+            Some(node) => {
+                let before = NodeState::of(node, scene);
+                if let Some(picked) = inspect(ui, node, scene) {
+                    action = Some(picked);
+                }
+                let after = NodeState::of(node, scene);
+                if let Some(before) = before.filter(|before| Some(before) != after.as_ref()) {
+                    if self.edit.is_none() {
+                        self.edit = Some((node, before));
+                    }
+                }
+            }
+            None => {
+                ui.label("Select a node in the hierarchy or click a model");
+            }
+        });
+
+        if !context.input().pointer.any_down() {
+            self.finish_edit(scene);
+        }
+        if let Some(action) = action {
+            self.finish_edit(scene);
+            self.act(action, renderer, scene);
+        }
+    }
+
+    // The joint the skeleton view highlights
+    pub fn selected_joint(&self) -> Option<JointHandle> {
+        match self.selected {
+            Some(SceneNode::Joint(joint)) => Some(joint),
+            _ => None,
+        }
+    }
+
+    pub fn undo(&mut self, scene: &mut Scene) {
+        self.finish_edit(scene);
+        self.history.undo(scene);
+    }
+
+    pub fn redo(&mut self, scene: &mut Scene) {
+        self.finish_edit(scene);
+        self.history.redo(scene);
+    }
+
+    // Forgets the history, needed when the scene's models are replaced outside of it
+    pub fn reset(&mut self) {
+        self.selected = None;
+        self.edit = None;
+        self.history.clear();
+    }
+
+    fn finish_edit(&mut self, scene: &Scene) {
+        let (node, before) = match self.edit.take() {
+            Some(edit) => edit,
+            None => return,
+        };
+        if let Some(after) = NodeState::of(node, scene) {
+            for command in before.commands(node, after) {
+                self.history.record(command);
+            }
+        }
+    }
+
+    fn act(&mut self, action: EditorAction, renderer: &mut Renderer, scene: &mut Scene) {
+        let command: Box<dyn Command> = match action {
+            EditorAction::Undo => return self.undo(scene),
+            EditorAction::Redo => return self.redo(scene),
+            EditorAction::AddLight => {
+                // At what the camera looks at, where it's easy to see what it lights
+                let camera = &renderer.camera;
+                let light = PunctualLight {
+                    kind: PunctualLightKind::Point,
+                    position: camera.target,
+                    direction: (camera.target - camera.position)
+                        .try_normalize(f32::EPSILON)
+                        .unwrap_or(-glm::Vec3::z()),
+                    color: glm::vec3(1.0, 1.0, 1.0),
+                    intensity: 10.0,
+                    unit: LightUnit::Candela,
+                    temperature: None,
+                    range: None,
+                    layers: layers::ALL,
+                    cast_shadows: false,
+                };
+                self.selected = Some(SceneNode::Light(LightHandle(scene.lights.len())));
+                Box::new(AddLight { light })
+            }
+            EditorAction::Duplicate(model) => match scene.models.get(model.0) {
+                Some(desc) => {
+                    self.selected = Some(SceneNode::Model(ModelHandle(scene.models.len())));
+                    Box::new(AddModel::new(desc.clone()))
+                }
+                None => return,
+            },
+            EditorAction::RemoveModel(model) => {
+                self.selected = None;
+                renderer.selection = None;
+                Box::new(RemoveModel::new(model))
+            }
+            EditorAction::RemoveLight(light) => {
+                self.selected = None;
+                Box::new(RemoveLight::new(light))
+            }
+        };
+        self.history.execute(command, scene);
+    }
+
+    fn hierarchy(&self, ui: &mut egui::Ui, scene: &Scene) -> Option<SceneNode> {
+        let mut clicked = None;
+        let mut node = |ui: &mut egui::Ui, node: SceneNode, name: String| {
+            if ui
+                .selectable_label(self.selected == Some(node), name)
+                .clicked()
+            {
+                clicked = Some(node);
+            }
+        };
+        node(ui, SceneNode::Sun, "Sun".to_string());
+        for index in 0..scene.lights.len() {
+            node(
+                ui,
+                SceneNode::Light(LightHandle(index)),
+                format!("Light {}", index),
+            );
+        }
+        for (index, model) in scene.models.iter().enumerate() {
+            let handle = ModelHandle(index);
+            let name = model
+                .name
+                .clone()
+                .unwrap_or_else(|| format!("Model {}", index));
+            egui::CollapsingHeader::new(name.clone())
+                .id_source(("model", index))
+                .show(ui, |ui| {
+                    node(ui, SceneNode::Model(handle), format!("{} transform", name));
+                    for (instance, mesh_instance) in model.instances.iter().enumerate() {
+                        node(
+                            ui,
+                            SceneNode::Instance {
+                                model: handle,
+                                instance,
+                            },
+                            format!("Instance {} (mesh {})", instance, mesh_instance.mesh),
+                        );
+                    }
+                    for light in 0..model.lights.len() {
+                        node(
+                            ui,
+                            SceneNode::ModelLight {
+                                model: handle,
+                                light,
+                            },
+                            format!("Light {}", light),
+                        );
+                    }
+                    for (skeleton_index, skeleton) in model.skeletons.iter().enumerate() {
+                        let name = skeleton
+                            .name
+                            .clone()
+                            .unwrap_or_else(|| format!("Skeleton {}", skeleton_index));
+                        egui::CollapsingHeader::new(name)
+                            .id_source(("skeleton", index, skeleton_index))
+                            .show(ui, |ui| {
+                                for (joint_index, joint) in skeleton.joints.iter().enumerate() {
+                                    let joint_handle = JointHandle {
+                                        model: handle,
+                                        skeleton: skeleton_index,
+                                        joint: joint_index,
+                                    };
+                                    node(
+                                        ui,
+                                        SceneNode::Joint(joint_handle),
+                                        joint_name(joint, joint_index),
+                                    );
+                                }
+                            });
+                    }
+                });
+        }
+        clicked
+    }
+
+    // Instances are picked as if they were clicked at their origin, anything else clears the pick
+    fn select(&mut self, node: SceneNode, renderer: &mut Renderer, scene: &Scene) {
+        self.selected = Some(node);
+        renderer.selection = match node {
+            SceneNode::Instance { model, instance } => scene
+                .models
+                .get(model.0)
+                .and_then(|desc| desc.instances.get(instance).map(|placed| (desc, placed)))
+                .map(|(desc, placed)| {
+                    let position = scene
+                        .local_transform(&desc.instance_transform(placed))
+                        .column(3)
+                        .xyz();
+                    SceneHit {
+                        model,
+                        instance,
+                        position,
+                        distance: glm::distance(&renderer.camera.position, &position),
+                    }
+                }),
+            _ => None,
+        };
+        self.picked = renderer
+            .selection
+            .map(|selection| (selection.model, selection.instance));
+    }
+}
+
+fn inspect(ui: &mut egui::Ui, node: SceneNode, scene: &mut Scene) -> Option<EditorAction> {
+    let mut action = None;
+    match node {
+        SceneNode::Sun => sun_inspector(ui, &mut scene.sun),
+        SceneNode::Light(handle) => match scene.lights.get_mut(handle.0) {
+            Some(light) => {
+                light_inspector(ui, light);
+                if ui.button("Remove").clicked() {
+                    action = Some(EditorAction::RemoveLight(handle));
+                }
+            }
+            None => missing(ui),
+        },
+        SceneNode::Model(handle) => match scene.models.get_mut(handle.0) {
+            Some(model) => {
+                ui.horizontal(|ui| {
+                    ui.strong(model.name.clone().unwrap_or_default());
+                    if ui.button("Duplicate").clicked() {
+                        action = Some(EditorAction::Duplicate(handle));
+                    }
+                    if ui.button("Remove").clicked() {
+                        action = Some(EditorAction::RemoveModel(handle));
+                    }
+                });
+                transform_editor(ui, &mut model.transform);
+                let materials = model.materials.len();
+                ui.collapsing("Materials", |ui| {
+                    for material in 0..materials {
+                        material_editor(
+                            ui,
+                            scene,
+                            MaterialHandle {
+                                model: handle,
+                                material,
+                            },
+                        );
+                    }
+                });
+            }
+            None => missing(ui),
+        },
+        SceneNode::Instance {
+            model: handle,
+            instance,
+        } => {
+            let model = match scene.models.get_mut(handle.0) {
+                Some(model) => model,
+                None => {
+                    missing(ui);
+                    return None;
+                }
+            };
+            let materials = instance_materials(model, instance);
+            let placed = match model.instances.get_mut(instance) {
+                Some(placed) => placed,
+                None => {
+                    missing(ui);
+                    return None;
+                }
+            };
+            ui.label("Relative to the model");
+            let mut transform = placed.transform.cast::<f64>();
+            if transform_editor(ui, &mut transform) {
+                placed.transform = transform.cast();
+            }
+            layer_settings(ui, "Layers", &mut placed.layers);
+            for material in materials {
+                material_editor(
+                    ui,
+                    scene,
+                    MaterialHandle {
+                        model: handle,
+                        material,
+                    },
+                );
+            }
+        }
+        SceneNode::ModelLight { model, light } => {
+            match scene
+                .models
+                .get_mut(model.0)
+                .and_then(|model| model.lights.get_mut(light))
+            {
+                Some(light) => {
+                    ui.label("Relative to the model");
+                    light_inspector(ui, light);
+                }
+                None => missing(ui),
+            }
+        }
+        SceneNode::Joint(joint) => joint_inspector(ui, joint, scene),
+    }
+    action
+}
+
+fn joint_inspector(ui: &mut egui::Ui, handle: JointHandle, scene: &Scene) {
+    let skeleton = match scene
+        .models
+        .get(handle.model.0)
+        .and_then(|model| model.skeletons.get(handle.skeleton))
+    {
+        Some(skeleton) => skeleton,
+        None => return missing(ui),
+    };
+    let joint = match skeleton.joints.get(handle.joint) {
+        Some(joint) => joint,
+        None => return missing(ui),
+    };
+    ui.strong(joint_name(joint, handle.joint));
+    ui.label(format!(
+        "Parent: {}",
+        joint
+            .parent
+            .and_then(|parent| skeleton.joints.get(parent).map(|joint| (parent, joint)))
+            .map(|(parent, joint)| joint_name(joint, parent))
+            .unwrap_or_else(|| "None".to_string())
+    ));
+    let children = skeleton
+        .joints
+        .iter()
+        .filter(|child| child.parent == Some(handle.joint))
+        .count();
+    ui.label(format!("Children: {}", children));
+    let transform = Transform::from_matrix(&joint.transform);
+    ui.label(format!(
+        "Position relative to the model: {:.3}, {:.3}, {:.3}",
+        transform.translation.x, transform.translation.y, transform.translation.z
+    ));
+}
+
+fn joint_name(joint: &Joint, index: usize) -> String {
+    joint
+        .name
+        .clone()
+        .unwrap_or_else(|| format!("Joint {}", index))
+}
+
+// Shown when the selected node was removed from the scene
+fn missing(ui: &mut egui::Ui) {
+    ui.label("The selected node no longer exists");
+}
+
+// Translations are edited in f64 so models far from the origin keep their precision, while
+// rotation and scale go through f32
+fn transform_editor(ui: &mut egui::Ui, matrix: &mut glm::DMat4) -> bool {
+    let mut translation = matrix.column(3).xyz();
+    let mut transform = Transform::from_matrix(&matrix.cast());
+    let mut changed = vector_editor(ui, "Translation", &mut translation, 0.01);
+    changed |= vector_editor(ui, "Rotation", &mut transform.rotation, 0.5);
+    changed |= vector_editor(ui, "Scale", &mut transform.scale, 0.01);
+    // Rebuilding the matrix every frame would slowly drift it
+    if changed {
+        transform.translation = glm::Vec3::zeros();
+        *matrix = glm::translation(&translation) * transform.matrix().cast::<f64>();
+    }
+    changed
+}
+
+fn vector_editor<T: glm::Scalar + egui::emath::Numeric>(
+    ui: &mut egui::Ui,
+    label: &str,
+    vector: &mut glm::TVec3<T>,
+    speed: f64,
+) -> bool {
+    ui.horizontal(|ui| {
+        let mut changed = false;
+        for index in 0..3 {
+            changed |= ui
+                .add(egui::DragValue::new(&mut vector[index]).speed(speed))
+                .changed();
+        }
+        ui.label(label);
+        changed
+    })
+    .inner
+}
+
+fn color_editor(ui: &mut egui::Ui, label: &str, color: &mut glm::Vec3) {
+    let mut rgb: [f32; 3] = (*color).into();
+    ui.horizontal(|ui| {
+        ui.color_edit_button_rgb(&mut rgb);
+        ui.label(label);
+    });
+    *color = rgb.into();
+}
+
+// The color is multiplied with the black body color when a temperature is set
+fn temperature_editor(ui: &mut egui::Ui, temperature: &mut Option<f32>) {
+    let mut enabled = temperature.is_some();
+    ui.checkbox(&mut enabled, "Color temperature");
+    match (enabled, temperature.as_mut()) {
+        (true, Some(kelvin)) => {
+            ui.add(egui::Slider::new(kelvin, 1667.0..=25000.0).text("Kelvin"));
+        }
+        (true, None) => *temperature = Some(6500.0),
+        (false, _) => *temperature = None,
+    }
+}
+
+fn sun_inspector(ui: &mut egui::Ui, sun: &mut DirectionalLight) {
+    if vector_editor(ui, "Direction", &mut sun.direction, 0.01) {
+        sun.direction = sun
+            .direction
+            .try_normalize(f32::EPSILON)
+            .unwrap_or(-glm::Vec3::y());
+    }
+    color_editor(ui, "Color", &mut sun.color);
+    temperature_editor(ui, &mut sun.temperature);
+    ui.add(egui::Slider::new(&mut sun.intensity, 0.0..=20.0).text("Intensity"));
+    layer_settings(ui, "Lit layers", &mut sun.layers);
+}
+
+fn light_inspector(ui: &mut egui::Ui, light: &mut PunctualLight) {
+    let default_spot = PunctualLightKind::Spot {
+        inner_cone_angle: 0.0,
+        outer_cone_angle: std::f32::consts::FRAC_PI_4,
+    };
+    egui::ComboBox::from_label("Kind")
+        .selected_text(match light.kind {
+            PunctualLightKind::Directional => "Directional",
+            PunctualLightKind::Point => "Point",
+            PunctualLightKind::Spot { .. } => "Spot",
+        })
+        .show_ui(ui, |ui| {
+            for (kind, name) in [
+                (PunctualLightKind::Directional, "Directional"),
+                (PunctualLightKind::Point, "Point"),
+                (default_spot, "Spot"),
+            ] {
+                let selected = std::mem::discriminant(&light.kind) == std::mem::discriminant(&kind);
+                if ui.selectable_label(selected, name).clicked() && !selected {
+                    light.kind = kind;
+                }
+            }
+        });
+    vector_editor(ui, "Position", &mut light.position, 0.01);
+    if vector_editor(ui, "Direction", &mut light.direction, 0.01) {
+        light.direction = light
+            .direction
+            .try_normalize(f32::EPSILON)
+            .unwrap_or(-glm::Vec3::z());
+    }
+    color_editor(ui, "Color", &mut light.color);
+    temperature_editor(ui, &mut light.temperature);
+    if light.kind == PunctualLightKind::Directional {
+        light.unit = LightUnit::Lux;
+    } else {
+        egui::ComboBox::from_label("Unit")
+            .selected_text(light.unit.symbol())
+            .show_ui(ui, |ui| {
+                for unit in LightUnit::ALL {
+                    ui.selectable_value(&mut light.unit, unit, unit.symbol());
+                }
+            });
+    }
+    ui.add(
+        egui::Slider::new(&mut light.intensity, 0.0..=100_000.0)
+            .logarithmic(true)
+            .text(format!("Intensity ({})", light.unit.symbol())),
+    );
+    if let PunctualLightKind::Spot {
+        inner_cone_angle,
+        outer_cone_angle,
+    } = &mut light.kind
+    {
+        ui.add(egui::Slider::new(inner_cone_angle, 0.0..=*outer_cone_angle).text("Inner cone"));
+        ui.add(
+            egui::Slider::new(outer_cone_angle, 0.0..=std::f32::consts::FRAC_PI_2)
+                .text("Outer cone"),
+        );
+    }
+    let mut bounded = light.range.is_some();
+    ui.checkbox(&mut bounded, "Limited range");
+    match (bounded, light.range.as_mut()) {
+        (true, Some(range)) => {
+            ui.add(
+                egui::Slider::new(range, 0.01..=1000.0)
+                    .logarithmic(true)
+                    .text("Range"),
+            );
+        }
+        (true, None) => light.range = Some(10.0),
+        (false, _) => light.range = None,
+    }
+    if light.kind != PunctualLightKind::Directional {
+        ui.checkbox(&mut light.cast_shadows, "Cast shadows");
+    }
+    layer_settings(ui, "Lit layers", &mut light.layers);
+}
@@ -0,0 +1,360 @@
+use anyhow::{Context, Result};
+use exr::prelude::{f16, ReadChannels, ReadLayers};
+use image::codecs::hdr::HdrDecoder;
+use std::borrow::Cow;
+
+use crate::{
+    memory::{self, MemoryCategory, Tracked},
+    scene::Scene,
+    settings::SkySettings,
+    shader_snippets,
+    texture::DepthConfig,
+};
+
+// An equirectangular HDR image drawn behind the scene in place of the procedural sky. The
+// reflection probes and irradiance volume capture it like the sky, so it lights the scene too
+#[derive(Debug, Clone, PartialEq)]
+pub struct EnvironmentDesc {
+    pub width: u32,
+    pub height: u32,
+    // Linear radiance, row by row from the top, with the middle of the image towards -Z
+    pub pixels: Vec<[f32; 3]>,
+}
+
+impl EnvironmentDesc {
+    // Radiance .hdr images
+    pub fn from_hdr(bytes: &[u8]) -> Result<Self> {
+        let decoder = HdrDecoder::new(bytes)?;
+        let metadata = decoder.metadata();
+        let pixels = decoder
+            .read_image_hdr()?
+            .into_iter()
+            .map(|pixel| pixel.0)
+            .collect();
+        Ok(Self {
+            width: metadata.width,
+            height: metadata.height,
+            pixels,
+        })
+    }
+
+    // OpenEXR images, of which only the first layer with color channels is read
+    pub fn from_exr(bytes: &[u8]) -> Result<Self> {
+        let image = exr::prelude::read()
+            .no_deep_data()
+            .largest_resolution_level()
+            .rgba_channels(
+                |resolution, _| Self {
+                    width: resolution.width() as u32,
+                    height: resolution.height() as u32,
+                    pixels: vec![[0.0; 3]; resolution.area()],
+                },
+                |environment: &mut Self, position, (r, g, b, _): (f32, f32, f32, f32)| {
+                    let index = position.y() * environment.width as usize + position.x();
+                    environment.pixels[index] = [r, g, b];
+                },
+            )
+            .first_valid_layer()
+            .all_attributes()
+            .from_buffered(std::io::Cursor::new(bytes))
+            .context("Failed to read the OpenEXR image")?;
+        Ok(image.layer_data.channel_data.pixels)
+    }
+
+    // Averages each square of four pixels, for images larger than the GPU supports
+    fn halved(&self) -> Self {
+        let width = (self.width / 2).max(1);
+        let height = (self.height / 2).max(1);
+        let mut pixels = Vec::with_capacity((width * height) as usize);
+        for y in 0..height {
+            for x in 0..width {
+                let mut sum = [0.0; 3];
+                for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+                    let source_x = (x * 2 + dx).min(self.width - 1);
+                    let source_y = (y * 2 + dy).min(self.height - 1);
+                    let pixel = self.pixels[(source_y * self.width + source_x) as usize];
+                    for (sum, value) in sum.iter_mut().zip(pixel) {
+                        *sum += value * 0.25;
+                    }
+                }
+                pixels.push(sum);
+            }
+        }
+        Self {
+            width,
+            height,
+            pixels,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Default, Copy, Clone, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+struct EnvironmentUniform {
+    // x: scale into scene color, y: turn about the up axis in radians
+    parameters: [f32; 4],
+}
+
+impl EnvironmentUniform {
+    fn new(settings: &SkySettings) -> Self {
+        Self {
+            parameters: [
+                settings.environment_intensity,
+                settings.environment_rotation.to_radians(),
+                0.0,
+                0.0,
+            ],
+        }
+    }
+}
+
+// Draws the scene's environment wherever the depth prepass left the background, in the scene and
+// in probes
+pub struct EnvironmentSystem {
+    // The revision of the scene's environment that was last uploaded
+    revision: Option<u64>,
+    uniform: EnvironmentUniform,
+    uniform_buffer: Tracked<wgpu::Buffer>,
+    _texture: Option<Tracked<wgpu::Texture>>,
+    sampler: wgpu::Sampler,
+    bind_group_layout: wgpu::BindGroupLayout,
+    bind_group: Option<wgpu::BindGroup>,
+    pipeline: wgpu::RenderPipeline,
+}
+
+impl EnvironmentSystem {
+    pub fn new(
+        device: &wgpu::Device,
+        camera_bind_group_layout: &wgpu::BindGroupLayout,
+        color_format: wgpu::TextureFormat,
+        depth: DepthConfig,
+    ) -> Self {
+        let uniform_buffer = memory::create_buffer(
+            device,
+            MemoryCategory::Transient,
+            &wgpu::BufferDescriptor {
+                label: Some("Environment Uniform Buffer"),
+                size: std::mem::size_of::<EnvironmentUniform>() as wgpu::BufferAddress,
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            },
+        );
+
+        // Wraps around horizontally, where the image's left and right edges meet
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Environment Sampler"),
+            address_mode_u: wgpu::AddressMode::Repeat,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let bind_group_layout = memory::create_bind_group_layout(
+            device,
+            &wgpu::BindGroupLayoutDescriptor {
+                label: Some("Environment Bind Group Layout"),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 2,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Sampler {
+                            filtering: true,
+                            comparison: false,
+                        },
+                        count: None,
+                    },
+                ],
+            },
+        );
+
+        let module = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("Environment Shader"),
+            source: wgpu::ShaderSource::Wgsl(
+                shader_snippets::splice(include_str!("shaders/environment.wgsl")).into(),
+            ),
+        });
+
+        let pipeline_layout = memory::create_pipeline_layout(
+            device,
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("Environment Pipeline Layout"),
+                bind_group_layouts: &[camera_bind_group_layout, &bind_group_layout],
+                push_constant_ranges: &[],
+            },
+        );
+
+        // Drawn on the far plane like the sky
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Environment Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &module,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: depth.format,
+                depth_write_enabled: false,
+                depth_compare: depth.compare(wgpu::CompareFunction::LessEqual),
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: depth.multisample(),
+            fragment: Some(wgpu::FragmentState {
+                module: &module,
+                entry_point: "fs_main",
+                targets: &[wgpu::ColorTargetState {
+                    format: color_format,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                }],
+            }),
+        });
+
+        Self {
+            revision: None,
+            uniform: EnvironmentUniform::default(),
+            uniform_buffer,
+            _texture: None,
+            sampler,
+            bind_group_layout,
+            bind_group: None,
+            pipeline,
+        }
+    }
+
+    // Whether there's an environment drawn in place of the sky
+    pub fn enabled(&self) -> bool {
+        self.bind_group.is_some()
+    }
+
+    // True when the environment or how it's drawn changed, so the reflection probes and
+    // irradiance volume are due to capture it again
+    pub fn update(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        scene: &Scene,
+        settings: &SkySettings,
+    ) -> bool {
+        let mut changed = false;
+        if self.revision != Some(scene.environment_revision()) {
+            self.revision = Some(scene.environment_revision());
+            self.upload(device, queue, scene.environment());
+            changed = true;
+        }
+
+        let uniform = EnvironmentUniform::new(settings);
+        if uniform != self.uniform {
+            self.uniform = uniform;
+            queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[uniform]));
+            changed = true;
+        }
+        changed && self.enabled()
+    }
+
+    fn upload(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        environment: Option<&EnvironmentDesc>,
+    ) {
+        let environment = match environment {
+            Some(environment) if !environment.pixels.is_empty() => environment,
+            _ => {
+                self._texture = None;
+                self.bind_group = None;
+                return;
+            }
+        };
+
+        let max_dimension = device.limits().max_texture_dimension_2d;
+        let mut environment = Cow::Borrowed(environment);
+        while environment.width > max_dimension || environment.height > max_dimension {
+            environment = Cow::Owned(environment.halved());
+        }
+
+        let texels = environment
+            .pixels
+            .iter()
+            .flat_map(|[r, g, b]| [*r, *g, *b, 1.0])
+            .map(|value| f16::from_f32(value).to_bits())
+            .collect::<Vec<_>>();
+        let texture = memory::create_texture_with_data(
+            device,
+            queue,
+            MemoryCategory::Textures,
+            &wgpu::TextureDescriptor {
+                label: Some("Environment Texture"),
+                size: wgpu::Extent3d {
+                    width: environment.width,
+                    height: environment.height,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: wgpu::TextureFormat::Rgba16Float,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            },
+            bytemuck::cast_slice(&texels),
+        );
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        self.bind_group = Some(device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Environment Bind Group"),
+            layout: &self.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: self.uniform_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+            ],
+        }));
+        self._texture = Some(texture);
+    }
+
+    pub fn render<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        camera_bind_group: &'a wgpu::BindGroup,
+    ) {
+        let bind_group = match self.bind_group.as_ref() {
+            Some(bind_group) => bind_group,
+            None => return,
+        };
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, camera_bind_group, &[]);
+        render_pass.set_bind_group(1, bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
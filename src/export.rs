@@ -0,0 +1,724 @@
+use anyhow::{bail, Context, Result};
+use gltf::json::Value;
+use nalgebra_glm as glm;
+use std::{
+    borrow::Cow,
+    collections::BTreeSet,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    animation::{AnimationClip, Animations, ChannelValues, Interpolation},
+    material::{AlphaMode, Material, ShadingModel, TextureTransform, PAPER_WHITE_NITS},
+    model::{Mesh, ModelDesc},
+    scene::Scene,
+};
+
+// Exports from the interface are numbered in here so earlier ones are kept
+const EXPORTS_DIRECTORY: &str = "exports";
+
+const FLOAT: u32 = 5126;
+const UNSIGNED_INT: u32 = 5125;
+const ARRAY_BUFFER: u32 = 34962;
+const ELEMENT_ARRAY_BUFFER: u32 = 34963;
+
+// Writes the scene's models to a .glb, or a .gltf with its buffer embedded, so files opened in
+// any supported format can be saved as glTF. Each model becomes a node with its instances below
+// it, or with its node hierarchy and clips when it's animated. Lights and anything that isn't a
+// model are left out. Skinned models are refused rather than written without their skins, since
+// vertices carry no joint weights
+pub fn export_scene(scene: &Scene, path: &Path) -> Result<()> {
+    if cfg!(target_arch = "wasm32") {
+        bail!("Exporting isn't supported in the browser");
+    }
+    if scene.models.is_empty() {
+        bail!("The scene has no models to export");
+    }
+    if let Some(model) = scene
+        .models
+        .iter()
+        .find(|model| !model.skeletons.is_empty())
+    {
+        bail!(
+            "Skinned models can't be exported yet, since their joint weights aren't imported: {}",
+            model.name.as_deref().unwrap_or("unnamed model")
+        );
+    }
+    let mut document = Document::default();
+    let nodes = scene
+        .models
+        .iter()
+        .map(|model| document.add_model(model))
+        .collect::<Result<Vec<_>>>()?;
+
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(str::to_lowercase)
+        .unwrap_or_default();
+    let contents = match extension.as_str() {
+        "glb" => {
+            let (json, buffer) = document.finish(nodes, None);
+            let glb = gltf::Glb {
+                header: gltf::binary::Header {
+                    magic: *b"glTF",
+                    version: 2,
+                    // Worked out again while writing
+                    length: 0,
+                },
+                json: Cow::Owned(gltf::json::serialize::to_vec(&json)?),
+                bin: (!buffer.is_empty()).then_some(Cow::Owned(buffer)),
+            };
+            glb.to_vec()?
+        }
+        "gltf" => {
+            let uri = format!(
+                "data:application/octet-stream;base64,{}",
+                base64::encode(&document.buffer)
+            );
+            let (json, _) = document.finish(nodes, Some(uri));
+            gltf::json::serialize::to_vec_pretty(&json)?
+        }
+        _ => bail!("Exports are .glb or .gltf files: {}", path.display()),
+    };
+    if let Some(parent) = path
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+    {
+        std::fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create {}", parent.display()))?;
+    }
+    std::fs::write(path, contents).with_context(|| format!("Failed to write {}", path.display()))
+}
+
+// The first of scene_001.glb, scene_002.glb and so on that isn't taken
+pub fn next_export_path() -> Result<PathBuf> {
+    let directory = Path::new(EXPORTS_DIRECTORY);
+    for index in 1..10000 {
+        let path = directory.join(format!("scene_{:03}.glb", index));
+        if !path.exists() {
+            return Ok(path);
+        }
+    }
+    bail!("No export names are left in {}", directory.display())
+}
+
+fn object<'a>(properties: impl IntoIterator<Item = (&'a str, Value)>) -> Value {
+    properties.into_iter().collect()
+}
+
+fn floats(values: &[f32]) -> Value {
+    values.iter().copied().collect()
+}
+
+fn matrix(transform: &glm::Mat4) -> Value {
+    floats(transform.as_slice())
+}
+
+// Everything written so far, with every buffer view in one buffer
+#[derive(Default)]
+struct Document {
+    buffer: Vec<u8>,
+    buffer_views: Vec<Value>,
+    accessors: Vec<Value>,
+    images: Vec<Value>,
+    textures: Vec<Value>,
+    materials: Vec<Value>,
+    meshes: Vec<Value>,
+    nodes: Vec<Value>,
+    animations: Vec<Value>,
+    extensions_used: BTreeSet<&'static str>,
+}
+
+impl Document {
+    // A uri is given when the buffer is embedded in the json rather than a binary chunk
+    fn finish(self, scene_nodes: Vec<usize>, uri: Option<String>) -> (Value, Vec<u8>) {
+        let mut properties = vec![
+            (
+                "asset",
+                object([("version", "2.0".into()), ("generator", "renderer".into())]),
+            ),
+            ("scene", 0.into()),
+            (
+                "scenes",
+                vec![object([("nodes", scene_nodes.into())])].into(),
+            ),
+            ("nodes", self.nodes.into()),
+        ];
+        if !self.buffer.is_empty() {
+            let mut buffer = vec![("byteLength", self.buffer.len().into())];
+            if let Some(uri) = uri {
+                buffer.push(("uri", uri.into()));
+            }
+            properties.push(("buffers", vec![object(buffer)].into()));
+            properties.push(("bufferViews", self.buffer_views.into()));
+            properties.push(("accessors", self.accessors.into()));
+        }
+        if !self.images.is_empty() {
+            // Linear filtering with mipmaps and repeating, like the renderer's own sampler
+            let sampler = object([
+                ("magFilter", 9729.into()),
+                ("minFilter", 9987.into()),
+                ("wrapS", 10497.into()),
+                ("wrapT", 10497.into()),
+            ]);
+            properties.push(("samplers", vec![sampler].into()));
+            properties.push(("images", self.images.into()));
+            properties.push(("textures", self.textures.into()));
+        }
+        for (name, values) in [
+            ("materials", self.materials),
+            ("meshes", self.meshes),
+            ("animations", self.animations),
+        ] {
+            if !values.is_empty() {
+                properties.push((name, values.into()));
+            }
+        }
+        if !self.extensions_used.is_empty() {
+            let extensions = self.extensions_used.into_iter().collect::<Vec<_>>();
+            properties.push(("extensionsUsed", extensions.into()));
+        }
+        (object(properties), self.buffer)
+    }
+
+    fn add_view(&mut self, bytes: &[u8], target: Option<u32>) -> usize {
+        // Accessors need their data aligned to their component size
+        while !self.buffer.len().is_multiple_of(4) {
+            self.buffer.push(0);
+        }
+        let mut view = vec![
+            ("buffer", 0.into()),
+            ("byteOffset", self.buffer.len().into()),
+            ("byteLength", bytes.len().into()),
+        ];
+        if let Some(target) = target {
+            view.push(("target", target.into()));
+        }
+        self.buffer.extend_from_slice(bytes);
+        self.buffer_views.push(object(view));
+        self.buffer_views.len() - 1
+    }
+
+    // Positions and keyframe times need their bounds written alongside them
+    fn add_floats<const N: usize>(
+        &mut self,
+        values: &[[f32; N]],
+        kind: &str,
+        target: Option<u32>,
+        bounded: bool,
+    ) -> usize {
+        let components = values.iter().flatten().copied().collect::<Vec<f32>>();
+        let view = self.add_view(bytemuck::cast_slice(&components), target);
+        let mut accessor = vec![
+            ("bufferView", view.into()),
+            ("componentType", FLOAT.into()),
+            ("count", values.len().into()),
+            ("type", kind.into()),
+        ];
+        if bounded {
+            let mut min = [f32::MAX; N];
+            let mut max = [f32::MIN; N];
+            for value in values {
+                for component in 0..N {
+                    min[component] = min[component].min(value[component]);
+                    max[component] = max[component].max(value[component]);
+                }
+            }
+            accessor.push(("min", floats(&min)));
+            accessor.push(("max", floats(&max)));
+        }
+        self.accessors.push(object(accessor));
+        self.accessors.len() - 1
+    }
+
+    fn add_indices(&mut self, indices: &[u32]) -> usize {
+        let view = self.add_view(bytemuck::cast_slice(indices), Some(ELEMENT_ARRAY_BUFFER));
+        self.accessors.push(object([
+            ("bufferView", view.into()),
+            ("componentType", UNSIGNED_INT.into()),
+            ("count", indices.len().into()),
+            ("type", "SCALAR".into()),
+        ]));
+        self.accessors.len() - 1
+    }
+
+    // Returns the node the model's nodes are placed under
+    fn add_model(&mut self, model: &ModelDesc) -> Result<usize> {
+        let texture_base = self.textures.len();
+        for image in model.images.iter() {
+            let mut png = Vec::new();
+            image
+                .write_to(&mut png, image::ImageOutputFormat::Png)
+                .context("Failed to encode a texture as PNG")?;
+            let view = self.add_view(&png, None);
+            self.images.push(object([
+                ("bufferView", view.into()),
+                ("mimeType", "image/png".into()),
+            ]));
+            self.textures.push(object([
+                ("sampler", 0.into()),
+                ("source", self.images.len().saturating_sub(1).into()),
+            ]));
+        }
+
+        let material_base = self.materials.len();
+        for material in model.materials.iter() {
+            let material = self.material(material, texture_base);
+            self.materials.push(material);
+        }
+
+        // Meshes without triangles aren't valid glTF, so instances of them are dropped
+        let meshes = model
+            .meshes
+            .iter()
+            .map(|mesh| self.add_mesh(mesh, material_base))
+            .collect::<Vec<_>>();
+
+        let mut root = vec![("matrix", matrix(&model.transform.cast()))];
+        if let Some(name) = model.name.as_ref() {
+            root.push(("name", name.as_str().into()));
+        }
+        self.nodes.push(object(root));
+        let root = self.nodes.len() - 1;
+
+        let children = match model.animations.as_ref() {
+            Some(animations) => self.add_hierarchy(model, animations, &meshes),
+            None => model
+                .instances
+                .iter()
+                .filter_map(|instance| {
+                    let mesh = meshes.get(instance.mesh).copied().flatten()?;
+                    self.nodes.push(object([
+                        ("mesh", mesh.into()),
+                        ("matrix", matrix(&instance.transform.cast())),
+                    ]));
+                    Some(self.nodes.len() - 1)
+                })
+                .collect(),
+        };
+        if !children.is_empty() {
+            self.nodes[root]["children"] = children.into();
+        }
+        Ok(root)
+    }
+
+    // Writes the animated node hierarchy in its rest pose with each instance's mesh on its node,
+    // returning the nodes at the top of it
+    fn add_hierarchy(
+        &mut self,
+        model: &ModelDesc,
+        animations: &Animations,
+        meshes: &[Option<usize>],
+    ) -> Vec<usize> {
+        let node_base = self.nodes.len();
+        let mut children = vec![Vec::new(); animations.nodes.len()];
+        let mut top = Vec::new();
+        for (index, node) in animations.nodes.iter().enumerate() {
+            self.nodes.push(object([
+                ("translation", floats(node.translation.as_slice())),
+                ("rotation", floats(node.rotation.coords.as_slice())),
+                ("scale", floats(node.scale.as_slice())),
+            ]));
+            match node.parent {
+                Some(parent) => children[parent].push(node_base + index),
+                None => top.push(node_base + index),
+            }
+        }
+
+        // A node holds one mesh, so instances sharing a node get children of their own
+        for (index, instance) in model.instances.iter().enumerate() {
+            let mesh = match meshes.get(instance.mesh).copied().flatten() {
+                Some(mesh) => mesh,
+                None => continue,
+            };
+            match animations.instance_nodes.get(index).copied() {
+                Some(node) if self.nodes[node_base + node].get("mesh").is_none() => {
+                    self.nodes[node_base + node]["mesh"] = mesh.into();
+                }
+                Some(node) => {
+                    self.nodes.push(object([("mesh", mesh.into())]));
+                    children[node].push(self.nodes.len() - 1);
+                }
+                // Instances added after importing are placed by their own transforms
+                None => {
+                    self.nodes.push(object([
+                        ("mesh", mesh.into()),
+                        ("matrix", matrix(&instance.transform.cast())),
+                    ]));
+                    top.push(self.nodes.len() - 1);
+                }
+            }
+        }
+        for (index, children) in children.into_iter().enumerate() {
+            if !children.is_empty() {
+                self.nodes[node_base + index]["children"] = children.into();
+            }
+        }
+
+        for clip in animations.clips.iter() {
+            let animation = self.animation(clip, node_base);
+            self.animations.push(animation);
+        }
+        top
+    }
+
+    fn add_mesh(&mut self, mesh: &Mesh, material_base: usize) -> Option<usize> {
+        let primitives = mesh
+            .primitives
+            .iter()
+            .filter(|primitive| !primitive.vertices.is_empty() && !primitive.indices.is_empty())
+            .map(|primitive| {
+                let vertices = &primitive.vertices;
+                let attribute = |document: &mut Self, values: Vec<_>, kind, bounded| {
+                    document.add_floats::<3>(&values, kind, Some(ARRAY_BUFFER), bounded)
+                };
+                let position = attribute(
+                    self,
+                    vertices.iter().map(|vertex| vertex.position).collect(),
+                    "VEC3",
+                    true,
+                );
+                let normal = attribute(
+                    self,
+                    vertices.iter().map(|vertex| vertex.normal).collect(),
+                    "VEC3",
+                    false,
+                );
+                let tangents = vertices
+                    .iter()
+                    .map(|vertex| vertex.tangent)
+                    .collect::<Vec<_>>();
+                let tangent = self.add_floats(&tangents, "VEC4", Some(ARRAY_BUFFER), false);
+                let uvs = vertices.iter().map(|vertex| vertex.uv).collect::<Vec<_>>();
+                let uv = self.add_floats(&uvs, "VEC2", Some(ARRAY_BUFFER), false);
+                let uvs_1 = vertices
+                    .iter()
+                    .map(|vertex| vertex.uv_1)
+                    .collect::<Vec<_>>();
+                let uv_1 = self.add_floats(&uvs_1, "VEC2", Some(ARRAY_BUFFER), false);
+                let colors = vertices
+                    .iter()
+                    .map(|vertex| vertex.color)
+                    .collect::<Vec<_>>();
+                let color = self.add_floats(&colors, "VEC4", Some(ARRAY_BUFFER), false);
+                let indices = self.add_indices(&primitive.indices);
+
+                let attributes = object([
+                    ("POSITION", position.into()),
+                    ("NORMAL", normal.into()),
+                    ("TANGENT", tangent.into()),
+                    ("TEXCOORD_0", uv.into()),
+                    ("TEXCOORD_1", uv_1.into()),
+                    ("COLOR_0", color.into()),
+                ]);
+                let mut properties = vec![("attributes", attributes), ("indices", indices.into())];
+                // Primitives without one are drawn with the default material either way
+                if let Some(material) = primitive.material {
+                    properties.push(("material", (material_base + material).into()));
+                }
+                object(properties)
+            })
+            .collect::<Vec<_>>();
+        if primitives.is_empty() {
+            return None;
+        }
+        self.meshes
+            .push(object([("primitives", primitives.into())]));
+        Some(self.meshes.len() - 1)
+    }
+
+    fn texture_info(
+        &mut self,
+        texture: Option<usize>,
+        texture_base: usize,
+        transform: &TextureTransform,
+    ) -> Option<Value> {
+        let mut info = vec![("index", (texture_base + texture?).into())];
+        if transform.tex_coord != 0 {
+            info.push(("texCoord", transform.tex_coord.into()));
+        }
+        let defaults = TextureTransform {
+            tex_coord: transform.tex_coord,
+            ..Default::default()
+        };
+        if *transform != defaults {
+            const TEXTURE_TRANSFORM: &str = "KHR_texture_transform";
+            self.extensions_used.insert(TEXTURE_TRANSFORM);
+            let extension = object([
+                ("offset", floats(transform.offset.as_slice())),
+                ("rotation", transform.rotation.into()),
+                ("scale", floats(transform.scale.as_slice())),
+            ]);
+            info.push(("extensions", object([(TEXTURE_TRANSFORM, extension)])));
+        }
+        Some(object(info))
+    }
+
+    // Packed clearcoat, sheen and specular images already hold their two textures in the
+    // channels glTF reads them from, so both refer to the same image
+    fn material(&mut self, material: &Material, texture_base: usize) -> Value {
+        let defaults = Material::default();
+        let transforms = &material.texture_transforms;
+        let mut info = |texture: Option<usize>,
+                        transform: &TextureTransform,
+                        extra: Option<(&'static str, f32)>| {
+            let mut info = self.texture_info(texture, texture_base, transform)?;
+            if let Some((name, value)) = extra {
+                info[name] = value.into();
+            }
+            Some(info)
+        };
+
+        let mut pbr = vec![
+            (
+                "baseColorFactor",
+                floats(material.base_color_factor.as_slice()),
+            ),
+            ("metallicFactor", material.metallic_factor.into()),
+            ("roughnessFactor", material.roughness_factor.into()),
+        ];
+        if let Some(texture) = info(material.base_color_texture, &transforms.base_color, None) {
+            pbr.push(("baseColorTexture", texture));
+        }
+        let metallic_roughness = info(
+            material.metallic_roughness_texture,
+            &transforms.metallic_roughness,
+            None,
+        );
+        if let Some(texture) = metallic_roughness {
+            pbr.push(("metallicRoughnessTexture", texture));
+        }
+
+        let mut properties = vec![
+            ("pbrMetallicRoughness", object(pbr)),
+            (
+                "emissiveFactor",
+                floats(material.emissive_factor.as_slice()),
+            ),
+            ("doubleSided", material.double_sided.into()),
+        ];
+        if let Some(name) = material.name.as_ref() {
+            properties.push(("name", name.as_str().into()));
+        }
+        let normal = info(
+            material.normal_texture,
+            &transforms.normal,
+            Some(("scale", material.normal_scale)),
+        );
+        if let Some(texture) = normal {
+            properties.push(("normalTexture", texture));
+        }
+        let occlusion = info(
+            material.occlusion_texture,
+            &transforms.occlusion,
+            Some(("strength", material.occlusion_strength)),
+        );
+        if let Some(texture) = occlusion {
+            properties.push(("occlusionTexture", texture));
+        }
+        if let Some(texture) = info(material.emissive_texture, &transforms.emissive, None) {
+            properties.push(("emissiveTexture", texture));
+        }
+        match material.alpha_mode {
+            AlphaMode::Opaque => properties.push(("alphaMode", "OPAQUE".into())),
+            AlphaMode::Mask => {
+                properties.push(("alphaMode", "MASK".into()));
+                properties.push(("alphaCutoff", material.alpha_cutoff.into()));
+            }
+            // glTF has no dithered mode, and blending is the closest look
+            AlphaMode::Blend | AlphaMode::Dithered => {
+                properties.push(("alphaMode", "BLEND".into()))
+            }
+        }
+
+        let mut extensions: Vec<(&'static str, Value)> = Vec::new();
+        let emissive_strength = material.emissive_luminance / PAPER_WHITE_NITS;
+        if (emissive_strength - 1.0).abs() > f32::EPSILON {
+            extensions.push((
+                "KHR_materials_emissive_strength",
+                object([("emissiveStrength", emissive_strength.into())]),
+            ));
+        }
+        if material.clearcoat_factor > 0.0 {
+            let mut clearcoat = vec![
+                ("clearcoatFactor", material.clearcoat_factor.into()),
+                (
+                    "clearcoatRoughnessFactor",
+                    material.clearcoat_roughness_factor.into(),
+                ),
+            ];
+            if let Some(texture) = info(material.clearcoat_texture, &transforms.clearcoat, None) {
+                clearcoat.push(("clearcoatTexture", texture.clone()));
+                clearcoat.push(("clearcoatRoughnessTexture", texture));
+            }
+            let normal = info(
+                material.clearcoat_normal_texture,
+                &transforms.clearcoat_normal,
+                Some(("scale", material.clearcoat_normal_scale)),
+            );
+            if let Some(texture) = normal {
+                clearcoat.push(("clearcoatNormalTexture", texture));
+            }
+            extensions.push(("KHR_materials_clearcoat", object(clearcoat)));
+        }
+        if material.transmission_factor > 0.0 {
+            let mut transmission =
+                vec![("transmissionFactor", material.transmission_factor.into())];
+            let texture = info(
+                material.transmission_texture,
+                &transforms.transmission,
+                None,
+            );
+            if let Some(texture) = texture {
+                transmission.push(("transmissionTexture", texture));
+            }
+            extensions.push(("KHR_materials_transmission", object(transmission)));
+        }
+        if material.sheen_color_factor != glm::Vec3::zeros() || material.sheen_texture.is_some() {
+            let mut sheen = vec![
+                (
+                    "sheenColorFactor",
+                    floats(material.sheen_color_factor.as_slice()),
+                ),
+                (
+                    "sheenRoughnessFactor",
+                    material.sheen_roughness_factor.into(),
+                ),
+            ];
+            if let Some(texture) = info(material.sheen_texture, &transforms.sheen, None) {
+                sheen.push(("sheenColorTexture", texture.clone()));
+                sheen.push(("sheenRoughnessTexture", texture));
+            }
+            extensions.push(("KHR_materials_sheen", object(sheen)));
+        }
+        if material.specular_factor != defaults.specular_factor
+            || material.specular_color_factor != defaults.specular_color_factor
+            || material.specular_texture.is_some()
+        {
+            let mut specular = vec![
+                ("specularFactor", material.specular_factor.into()),
+                (
+                    "specularColorFactor",
+                    floats(material.specular_color_factor.as_slice()),
+                ),
+            ];
+            if let Some(texture) = info(material.specular_texture, &transforms.specular, None) {
+                specular.push(("specularColorTexture", texture.clone()));
+                specular.push(("specularTexture", texture));
+            }
+            extensions.push(("KHR_materials_specular", object(specular)));
+        }
+        if material.ior != defaults.ior {
+            extensions.push(("KHR_materials_ior", object([("ior", material.ior.into())])));
+        }
+        if material.shading_model == ShadingModel::Unlit {
+            extensions.push(("KHR_materials_unlit", object([])));
+        }
+        // The lightmap extension is itself the texture info
+        let lightmap = info(
+            material.lightmap_texture,
+            &transforms.lightmap,
+            Some(("intensity", material.lightmap_intensity)),
+        );
+        if let Some(lightmap) = lightmap {
+            extensions.push(("MOZ_lightmap", lightmap));
+        }
+
+        if !extensions.is_empty() {
+            for (name, _) in extensions.iter() {
+                self.extensions_used.insert(name);
+            }
+            properties.push(("extensions", object(extensions)));
+        }
+        // Written in full, since it's read relative to wherever the exported file ends up
+        if let Some(shader) = material.shader.as_ref() {
+            let shader = std::fs::canonicalize(shader).unwrap_or_else(|_| shader.clone());
+            let shader = shader.to_string_lossy().to_string();
+            properties.push(("extras", object([("shader", shader.into())])));
+        }
+        object(properties)
+    }
+
+    fn animation(&mut self, clip: &AnimationClip, node_base: usize) -> Value {
+        let mut samplers = Vec::new();
+        let mut channels = Vec::new();
+        for channel in clip.channels.iter() {
+            let times = channel.times.iter().map(|time| [*time]).collect::<Vec<_>>();
+            let input = self.add_floats(&times, "SCALAR", None, true);
+            let (output, path) = match &channel.values {
+                ChannelValues::Translations(values) => {
+                    let values = values.iter().map(|value| [value.x, value.y, value.z]);
+                    let values = values.collect::<Vec<_>>();
+                    (self.add_floats(&values, "VEC3", None, false), "translation")
+                }
+                ChannelValues::Rotations(values) => {
+                    let values = values.iter().map(|value| value.coords.into());
+                    let values = values.collect::<Vec<[f32; 4]>>();
+                    (self.add_floats(&values, "VEC4", None, false), "rotation")
+                }
+                ChannelValues::Scales(values) => {
+                    let values = values.iter().map(|value| [value.x, value.y, value.z]);
+                    let values = values.collect::<Vec<_>>();
+                    (self.add_floats(&values, "VEC3", None, false), "scale")
+                }
+            };
+            let interpolation = match channel.interpolation {
+                Interpolation::Step => "STEP",
+                Interpolation::Linear => "LINEAR",
+                Interpolation::CubicSpline => "CUBICSPLINE",
+            };
+            samplers.push(object([
+                ("input", input.into()),
+                ("output", output.into()),
+                ("interpolation", interpolation.into()),
+            ]));
+            channels.push(object([
+                ("sampler", (samplers.len() - 1).into()),
+                (
+                    "target",
+                    object([
+                        ("node", (node_base + channel.node).into()),
+                        ("path", path.into()),
+                    ]),
+                ),
+            ]));
+        }
+        let mut properties = vec![("samplers", samplers.into()), ("channels", channels.into())];
+        if let Some(name) = clip.name.as_ref() {
+            properties.push(("name", name.as_str().into()));
+        }
+        object(properties)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::Skeleton;
+
+    #[test]
+    fn refuses_scenes_without_models() {
+        let path = std::env::temp_dir().join("renderer_export_empty.glb");
+        let error = export_scene(&Scene::default(), &path).unwrap_err();
+        assert!(error.to_string().contains("no models"));
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn refuses_skinned_models() {
+        let mut scene = Scene::default();
+        scene.spawn_model(ModelDesc {
+            name: Some("character".to_string()),
+            skeletons: vec![Skeleton {
+                name: None,
+                joints: Vec::new(),
+            }],
+            ..Default::default()
+        });
+        let path = std::env::temp_dir().join("renderer_export_skinned.glb");
+        let error = export_scene(&scene, &path).unwrap_err();
+        assert!(error.to_string().contains("character"));
+        assert!(!path.exists());
+    }
+}
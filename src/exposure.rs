@@ -0,0 +1,289 @@
+use crate::{
+    memory::{self, MemoryCategory, Tracked},
+    settings::Settings,
+    texture::Texture,
+};
+
+const HISTOGRAM_BINS: u64 = 256;
+const WORKGROUP_SIZE: u32 = 16;
+
+#[repr(C)]
+#[derive(Default, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct ExposureUniform {
+    // x: log2 luminance of the first bin, y: log2 luminance range covered by the bins
+    log_luminance: [f32; 4],
+    // x: delta time, y: adaptation speed up, z: adaptation speed down, w: compensation in stops
+    adaptation: [f32; 4],
+}
+
+impl ExposureUniform {
+    fn new(settings: &Settings, delta_time: f32) -> Self {
+        let auto_exposure = &settings.auto_exposure;
+        let min_ev = auto_exposure.min_ev.min(auto_exposure.max_ev);
+        let range = (auto_exposure.max_ev - min_ev).max(0.01);
+        Self {
+            log_luminance: [min_ev, range, 0.0, 0.0],
+            adaptation: [
+                delta_time,
+                auto_exposure.speed_up,
+                auto_exposure.speed_down,
+                auto_exposure.compensation,
+            ],
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Default, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct AdaptedExposure {
+    luminance: f32,
+    exposure: f32,
+}
+
+// The histogram and the passes that fill and average it
+struct Measurement {
+    uniform_buffer: Tracked<wgpu::Buffer>,
+    histogram_buffer: Tracked<wgpu::Buffer>,
+    bind_group_layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+    histogram_pipeline: wgpu::ComputePipeline,
+    average_pipeline: wgpu::ComputePipeline,
+}
+
+// Measures the HDR scene color with a luminance histogram and adapts the exposure to it over time
+pub struct ExposureSystem {
+    // Read by post processing, which falls back to the manual exposure while this is disabled
+    adapted_buffer: Tracked<wgpu::Buffer>,
+    // Only created where compute shaders are available
+    measurement: Option<Measurement>,
+    enabled: bool,
+    dimensions: [u32; 2],
+}
+
+impl ExposureSystem {
+    pub fn new(
+        device: &wgpu::Device,
+        compute_supported: bool,
+        scene_color: &Texture,
+        dimensions: &[u32; 2],
+    ) -> Self {
+        // Starts out adapted to a scene of middle gray
+        let adapted_buffer = memory::create_buffer_init(
+            device,
+            MemoryCategory::Transient,
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("Adapted Exposure Buffer"),
+                contents: bytemuck::cast_slice(&[AdaptedExposure {
+                    luminance: 0.18,
+                    exposure: 1.0,
+                }]),
+                usage: wgpu::BufferUsages::STORAGE,
+            },
+        );
+
+        let measurement = compute_supported
+            .then(|| Self::create_measurement(device, &adapted_buffer, scene_color));
+
+        Self {
+            adapted_buffer,
+            measurement,
+            enabled: false,
+            dimensions: *dimensions,
+        }
+    }
+
+    fn create_measurement(
+        device: &wgpu::Device,
+        adapted_buffer: &wgpu::Buffer,
+        scene_color: &Texture,
+    ) -> Measurement {
+        let uniform_buffer = memory::create_buffer(
+            device,
+            MemoryCategory::Transient,
+            &wgpu::BufferDescriptor {
+                label: Some("Exposure Uniform Buffer"),
+                size: std::mem::size_of::<ExposureUniform>() as wgpu::BufferAddress,
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            },
+        );
+
+        let histogram_buffer = memory::create_buffer_init(
+            device,
+            MemoryCategory::Transient,
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("Exposure Histogram Buffer"),
+                contents: bytemuck::cast_slice(&[0u32; HISTOGRAM_BINS as usize]),
+                usage: wgpu::BufferUsages::STORAGE,
+            },
+        );
+
+        let storage_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only: false },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+
+        let bind_group_layout = memory::create_bind_group_layout(
+            device,
+            &wgpu::BindGroupLayoutDescriptor {
+                label: Some("Exposure Bind Group Layout"),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                    storage_entry(2),
+                    storage_entry(3),
+                ],
+            },
+        );
+
+        let bind_group = Self::create_bind_group(
+            device,
+            &bind_group_layout,
+            &uniform_buffer,
+            &histogram_buffer,
+            adapted_buffer,
+            scene_color,
+        );
+
+        let module = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("Exposure Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("shaders/exposure.wgsl").into()),
+        });
+
+        let pipeline_layout = memory::create_pipeline_layout(
+            device,
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("Exposure Pipeline Layout"),
+                bind_group_layouts: &[&bind_group_layout],
+                push_constant_ranges: &[],
+            },
+        );
+
+        let create_pipeline = |label, entry_point| {
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some(label),
+                layout: Some(&pipeline_layout),
+                module: &module,
+                entry_point,
+            })
+        };
+        let histogram_pipeline = create_pipeline("Exposure Histogram Pipeline", "cs_histogram");
+        let average_pipeline = create_pipeline("Exposure Average Pipeline", "cs_average");
+
+        Measurement {
+            uniform_buffer,
+            histogram_buffer,
+            bind_group_layout,
+            bind_group,
+            histogram_pipeline,
+            average_pipeline,
+        }
+    }
+
+    fn create_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        uniform_buffer: &wgpu::Buffer,
+        histogram_buffer: &wgpu::Buffer,
+        adapted_buffer: &wgpu::Buffer,
+        scene_color: &Texture,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Exposure Bind Group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&scene_color.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: histogram_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: adapted_buffer.as_entire_binding(),
+                },
+            ],
+        })
+    }
+
+    // Holds the adapted luminance followed by the exposure
+    pub fn adapted_buffer(&self) -> &wgpu::Buffer {
+        &self.adapted_buffer
+    }
+
+    pub fn resize(&mut self, device: &wgpu::Device, scene_color: &Texture, dimensions: &[u32; 2]) {
+        if let Some(measurement) = self.measurement.as_mut() {
+            measurement.bind_group = Self::create_bind_group(
+                device,
+                &measurement.bind_group_layout,
+                &measurement.uniform_buffer,
+                &measurement.histogram_buffer,
+                &self.adapted_buffer,
+                scene_color,
+            );
+        }
+        self.dimensions = *dimensions;
+    }
+
+    pub fn update(&mut self, queue: &wgpu::Queue, settings: &Settings, delta_time: f32) {
+        self.enabled = settings.auto_exposure.enabled;
+        if let Some(measurement) = self.measurement.as_ref() {
+            queue.write_buffer(
+                &measurement.uniform_buffer,
+                0,
+                bytemuck::cast_slice(&[ExposureUniform::new(settings, delta_time)]),
+            );
+        }
+    }
+
+    pub fn render(&self, encoder: &mut wgpu::CommandEncoder) {
+        // The adapted exposure is kept while disabled, so turning it back on continues from there
+        let measurement = match self.measurement.as_ref() {
+            Some(measurement) if self.enabled => measurement,
+            _ => return,
+        };
+
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Exposure Pass"),
+        });
+        compute_pass.set_bind_group(0, &measurement.bind_group, &[]);
+        compute_pass.set_pipeline(&measurement.histogram_pipeline);
+        compute_pass.dispatch(
+            self.dimensions[0].div_ceil(WORKGROUP_SIZE),
+            self.dimensions[1].div_ceil(WORKGROUP_SIZE),
+            1,
+        );
+        compute_pass.set_pipeline(&measurement.average_pipeline);
+        compute_pass.dispatch(1, 1, 1);
+    }
+}
@@ -0,0 +1,143 @@
+use nalgebra_glm as glm;
+
+use crate::{
+    bvh::{Ray, SceneBvh},
+    camera::Camera,
+    scene::{ModelHandle, Scene},
+};
+
+const MIN_ARM_LENGTH: f32 = 0.5;
+const MAX_ARM_LENGTH: f32 = 50.0;
+
+// Keeps the camera on an arm behind a model, orbiting around a point above the model's origin.
+// The arm shortens when scene geometry comes between the camera and the model so walls never
+// hide it, and eases back out once they're cleared
+pub struct FollowCamera {
+    pub target: Option<ModelHandle>,
+    // The point orbited relative to the model's origin, such as a character's head
+    pub pivot_offset: glm::Vec3,
+    pub arm_length: f32,
+    // How quickly the pivot catches up with the model, higher is stiffer
+    pub follow_rate: f32,
+    // How quickly the arm extends again after being pushed in
+    pub arm_rate: f32,
+    // Radius of the sphere swept along the arm, kept clear of geometry
+    pub probe_radius: f32,
+    yaw: f32,
+    pitch: f32,
+    pivot: Option<glm::Vec3>,
+    current_length: f32,
+    scene_bvh: SceneBvh,
+}
+
+impl Default for FollowCamera {
+    fn default() -> Self {
+        Self {
+            target: None,
+            pivot_offset: glm::vec3(0.0, 1.5, 0.0),
+            arm_length: 5.0,
+            follow_rate: 10.0,
+            arm_rate: 4.0,
+            probe_radius: 0.2,
+            yaw: 0.0,
+            pitch: 0.3,
+            pivot: None,
+            current_length: 5.0,
+            scene_bvh: SceneBvh::default(),
+        }
+    }
+}
+
+impl FollowCamera {
+    pub fn follow(&mut self, model: ModelHandle) {
+        self.target = Some(model);
+        // The arm starts where the camera already is on the next update
+        self.pivot = None;
+    }
+
+    pub fn translate(&mut self, offset: &glm::Vec3) {
+        if let Some(pivot) = self.pivot.as_mut() {
+            *pivot -= offset;
+        }
+    }
+
+    pub fn orbit(&mut self, yaw_delta: f32, pitch_delta: f32) {
+        let max_pitch = 89_f32.to_radians();
+        self.yaw += yaw_delta;
+        self.pitch = (self.pitch + pitch_delta).clamp(-max_pitch, max_pitch);
+    }
+
+    pub fn zoom(&mut self, factor: f32) {
+        self.arm_length = (self.arm_length * factor).clamp(MIN_ARM_LENGTH, MAX_ARM_LENGTH);
+    }
+
+    pub fn update(&mut self, camera: &mut Camera, scene: &Scene, delta_time: f32) {
+        let model = match self.target.and_then(|target| scene.models.get(target.0)) {
+            Some(model) => model,
+            None => return,
+        };
+        let anchor = (scene.local_transform(&model.transform) * self.pivot_offset.push(1.0)).xyz();
+
+        let pivot = match self.pivot {
+            Some(pivot) => {
+                let blend = 1.0 - (-self.follow_rate * delta_time).exp();
+                glm::lerp(&pivot, &anchor, blend)
+            }
+            None => {
+                let offset = camera.position - anchor;
+                let length = offset.magnitude();
+                if length > f32::EPSILON {
+                    self.yaw = offset.x.atan2(offset.z);
+                    self.pitch = (offset.y / length).asin();
+                    self.current_length = length;
+                }
+                anchor
+            }
+        };
+        self.pivot = Some(pivot);
+
+        let direction = glm::vec3(
+            self.pitch.cos() * self.yaw.sin(),
+            self.pitch.sin(),
+            self.pitch.cos() * self.yaw.cos(),
+        );
+        self.scene_bvh.update(scene);
+        let clear_length = self.clear_length(&pivot, &direction);
+        self.current_length = if clear_length < self.current_length {
+            clear_length
+        } else {
+            let blend = 1.0 - (-self.arm_rate * delta_time).exp();
+            self.current_length + (clear_length - self.current_length) * blend
+        };
+
+        camera.up = glm::Vec3::y();
+        camera.target = pivot;
+        camera.position = pivot + direction * self.current_length.max(camera.z_near);
+    }
+
+    // How far the arm reaches before the probe touches geometry, sweeping it with rays from the
+    // pivot and from around it across the arm
+    fn clear_length(&self, pivot: &glm::Vec3, direction: &glm::Vec3) -> f32 {
+        let side = if direction.y.abs() > 0.99 {
+            glm::Vec3::x()
+        } else {
+            glm::normalize(&direction.cross(&glm::Vec3::y()))
+        };
+        let up = side.cross(direction);
+        let radius = self.probe_radius;
+
+        [glm::Vec3::zeros(), side, -side, up, -up]
+            .iter()
+            .filter_map(|offset| {
+                let ray = Ray {
+                    origin: pivot + offset * radius,
+                    direction: *direction,
+                };
+                self.scene_bvh
+                    .intersect(&ray, self.arm_length + radius, self.target)
+                    .map(|hit| hit.distance - radius)
+            })
+            .fold(self.arm_length, f32::min)
+            .max(0.0)
+    }
+}
@@ -0,0 +1,49 @@
+use anyhow::{bail, Result};
+
+#[cfg(all(feature = "capture", not(target_arch = "wasm32")))]
+use renderdoc::{RenderDoc, V110};
+
+// Triggers RenderDoc captures from inside the app. RenderDoc is only found when it launched the
+// app or was injected into it, and only with the capture feature
+pub struct FrameCaptureSystem {
+    #[cfg(all(feature = "capture", not(target_arch = "wasm32")))]
+    renderdoc: Option<RenderDoc<V110>>,
+    captures: u32,
+}
+
+impl FrameCaptureSystem {
+    pub fn new() -> Self {
+        Self {
+            #[cfg(all(feature = "capture", not(target_arch = "wasm32")))]
+            renderdoc: RenderDoc::new().ok(),
+            captures: 0,
+        }
+    }
+
+    pub fn available(&self) -> bool {
+        #[cfg(all(feature = "capture", not(target_arch = "wasm32")))]
+        return self.renderdoc.is_some();
+        #[cfg(not(all(feature = "capture", not(target_arch = "wasm32"))))]
+        return false;
+    }
+
+    // How many captures were triggered this run
+    pub fn captures(&self) -> u32 {
+        self.captures
+    }
+
+    // RenderDoc captures the next frame presented to the window, along with every submission
+    // leading up to it
+    pub fn capture_next_frame(&mut self) -> Result<()> {
+        #[cfg(all(feature = "capture", not(target_arch = "wasm32")))]
+        if let Some(renderdoc) = self.renderdoc.as_mut() {
+            renderdoc.trigger_capture();
+            self.captures += 1;
+            return Ok(());
+        }
+        if cfg!(all(feature = "capture", not(target_arch = "wasm32"))) {
+            bail!("RenderDoc isn't attached, launch the app from RenderDoc to capture frames")
+        }
+        bail!("Frame captures need the capture feature")
+    }
+}
@@ -0,0 +1,106 @@
+use anyhow::{bail, Result};
+use gilrs::{Axis, Button, EventType, Gilrs};
+use nalgebra_glm as glm;
+use std::collections::HashSet;
+
+use crate::{actions::GamepadButton, input::Input};
+
+const BUTTONS: [(Button, GamepadButton); 16] = [
+    (Button::South, GamepadButton::South),
+    (Button::East, GamepadButton::East),
+    (Button::North, GamepadButton::North),
+    (Button::West, GamepadButton::West),
+    (Button::LeftTrigger, GamepadButton::LeftBumper),
+    (Button::RightTrigger, GamepadButton::RightBumper),
+    (Button::LeftTrigger2, GamepadButton::LeftTrigger),
+    (Button::RightTrigger2, GamepadButton::RightTrigger),
+    (Button::Select, GamepadButton::Select),
+    (Button::Start, GamepadButton::Start),
+    (Button::LeftThumb, GamepadButton::LeftThumb),
+    (Button::RightThumb, GamepadButton::RightThumb),
+    (Button::DPadUp, GamepadButton::DPadUp),
+    (Button::DPadDown, GamepadButton::DPadDown),
+    (Button::DPadLeft, GamepadButton::DPadLeft),
+    (Button::DPadRight, GamepadButton::DPadRight),
+];
+
+// Polls every connected controller and feeds their combined state to the input, so any of
+// them drives the camera and controllers can be plugged in or pulled out at any time
+pub struct GamepadSystem {
+    gilrs: Gilrs,
+}
+
+impl GamepadSystem {
+    pub fn new() -> Result<Self> {
+        let gilrs = match Gilrs::new() {
+            Ok(gilrs) => gilrs,
+            // Platforms without gamepad support get a stand in that never reports a controller
+            Err(gilrs::Error::NotImplemented(gilrs)) => gilrs,
+            Err(error) => bail!("Failed to initialize gamepads: {}", error),
+        };
+        Ok(Self { gilrs })
+    }
+
+    pub fn update(&mut self, input: &mut Input, dead_zone: f32) {
+        while let Some(event) = self.gilrs.next_event() {
+            match event.event {
+                EventType::Connected => {
+                    let gamepad = self.gilrs.gamepad(event.id);
+                    eprintln!("Gamepad connected: {}", gamepad.name());
+                }
+                EventType::Disconnected => {
+                    let gamepad = self.gilrs.gamepad(event.id);
+                    eprintln!("Gamepad disconnected: {}", gamepad.name());
+                }
+                _ => {}
+            }
+        }
+
+        let mut buttons = HashSet::new();
+        let mut left_stick = glm::Vec2::zeros();
+        let mut right_stick = glm::Vec2::zeros();
+        for (_, gamepad) in self.gilrs.gamepads() {
+            buttons.extend(
+                BUTTONS
+                    .iter()
+                    .filter(|(button, _)| gamepad.is_pressed(*button))
+                    .map(|(_, button)| *button),
+            );
+            left_stick += remove_dead_zone(
+                glm::vec2(
+                    gamepad.value(Axis::LeftStickX),
+                    gamepad.value(Axis::LeftStickY),
+                ),
+                dead_zone,
+            );
+            right_stick += remove_dead_zone(
+                glm::vec2(
+                    gamepad.value(Axis::RightStickX),
+                    gamepad.value(Axis::RightStickY),
+                ),
+                dead_zone,
+            );
+        }
+        input.handle_gamepads(buttons, clamp_length(left_stick), clamp_length(right_stick));
+    }
+}
+
+// Zeroes deflections inside the dead zone and rescales the rest so the stick still ramps up
+// smoothly from its edge instead of jumping
+fn remove_dead_zone(stick: glm::Vec2, dead_zone: f32) -> glm::Vec2 {
+    let length = stick.magnitude();
+    if length <= dead_zone || length <= f32::EPSILON {
+        return glm::Vec2::zeros();
+    }
+    let scaled = ((length - dead_zone) / (1.0 - dead_zone)).min(1.0);
+    stick * (scaled / length)
+}
+
+fn clamp_length(stick: glm::Vec2) -> glm::Vec2 {
+    let length = stick.magnitude();
+    if length > 1.0 {
+        stick / length
+    } else {
+        stick
+    }
+}
@@ -0,0 +1,146 @@
+use anyhow::Result;
+use egui_wgpu_backend::{RenderPass, ScreenDescriptor};
+use egui_winit_platform::{Platform, PlatformDescriptor};
+use instant::Instant;
+use std::sync::Arc;
+use winit::{
+    dpi::PhysicalSize,
+    event::{Event, WindowEvent},
+    window::{Window, WindowId},
+};
+
+pub struct Gui {
+    platform: Platform,
+    start_time: Instant,
+    pub visible: bool,
+    // The window's scale factor, which the platform only knows multiplied by the interface scale
+    window_scale_factor: f64,
+    ui_scale: f32,
+}
+
+// Everything needed to draw one frame of the interface
+pub struct GuiFrame {
+    pub paint_jobs: Vec<egui::ClippedMesh>,
+    pub texture: Arc<egui::Texture>,
+    pub scale_factor: f32,
+}
+
+impl Gui {
+    pub fn new(window: &Window) -> Self {
+        let size = window.inner_size();
+        let platform = Platform::new(PlatformDescriptor {
+            physical_width: size.width,
+            physical_height: size.height,
+            scale_factor: window.scale_factor(),
+            font_definitions: egui::FontDefinitions::default(),
+            style: egui::Style::default(),
+        });
+        Self {
+            platform,
+            start_time: Instant::now(),
+            visible: false,
+            window_scale_factor: window.scale_factor(),
+            ui_scale: 1.0,
+        }
+    }
+
+    pub fn handle_event(&mut self, event: &Event<()>) {
+        self.platform.handle_event(event);
+        if let Event::WindowEvent {
+            window_id,
+            event:
+                WindowEvent::ScaleFactorChanged {
+                    scale_factor,
+                    new_inner_size,
+                },
+        } = event
+        {
+            self.window_scale_factor = *scale_factor;
+            self.apply_scale(*window_id, **new_inner_size);
+        }
+    }
+
+    pub fn set_ui_scale(&mut self, window: &Window, ui_scale: f32) {
+        if ui_scale != self.ui_scale {
+            self.ui_scale = ui_scale;
+            self.apply_scale(window.id(), window.inner_size());
+        }
+    }
+
+    fn scale_factor(&self) -> f64 {
+        self.window_scale_factor * self.ui_scale as f64
+    }
+
+    // The platform only learns of scale factors through window events, so the interface scale
+    // is passed along as one
+    fn apply_scale(&mut self, window_id: WindowId, inner_size: PhysicalSize<u32>) {
+        let mut inner_size = inner_size;
+        self.platform.handle_event::<()>(&Event::WindowEvent {
+            window_id,
+            event: WindowEvent::ScaleFactorChanged {
+                scale_factor: self.scale_factor(),
+                new_inner_size: &mut inner_size,
+            },
+        });
+    }
+
+    // Events the interface is using shouldn't also move the camera
+    pub fn captures_event(&self, event: &Event<()>) -> bool {
+        self.visible && self.platform.captures_event(event)
+    }
+
+    pub fn frame(&mut self, window: &Window, build: impl FnOnce(&egui::CtxRef)) -> GuiFrame {
+        self.platform
+            .update_time(self.start_time.elapsed().as_secs_f64());
+        self.platform.begin_frame();
+        let context = self.platform.context();
+        if self.visible {
+            build(&context);
+        }
+        let (_output, shapes) = self.platform.end_frame(Some(window));
+        GuiFrame {
+            paint_jobs: context.tessellate(shapes),
+            texture: context.texture(),
+            scale_factor: self.scale_factor() as f32,
+        }
+    }
+}
+
+pub struct GuiPass {
+    render_pass: RenderPass,
+}
+
+impl GuiPass {
+    pub fn new(device: &wgpu::Device, output_format: wgpu::TextureFormat) -> Self {
+        Self {
+            render_pass: RenderPass::new(device, output_format, 1),
+        }
+    }
+
+    pub fn render(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
+        frame: &GuiFrame,
+        dimensions: [u32; 2],
+    ) -> Result<()> {
+        if frame.paint_jobs.is_empty() {
+            return Ok(());
+        }
+        let screen_descriptor = ScreenDescriptor {
+            physical_width: dimensions[0],
+            physical_height: dimensions[1],
+            scale_factor: frame.scale_factor,
+        };
+        self.render_pass
+            .update_texture(device, queue, &frame.texture);
+        self.render_pass.update_user_textures(device, queue);
+        self.render_pass
+            .update_buffers(device, queue, &frame.paint_jobs, &screen_descriptor);
+        self.render_pass
+            .execute(encoder, view, &frame.paint_jobs, &screen_descriptor, None)?;
+        Ok(())
+    }
+}
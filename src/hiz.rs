@@ -0,0 +1,353 @@
+use crate::memory::{self, MemoryCategory, Tracked};
+use crate::texture::{DepthConfig, Texture};
+
+const WORKGROUP_SIZE: u32 = 8;
+const HIZ_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::R32Float;
+
+#[repr(C)]
+#[derive(Default, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct PyramidUniform {
+    depth_size: [u32; 2],
+    levels: u32,
+    _padding: u32,
+}
+
+// A level of the pyramid and what it's reduced from
+struct PyramidLevel {
+    bind_group: wgpu::BindGroup,
+    size: [u32; 2],
+}
+
+// A hierarchical depth pyramid built from the depth prepass, where every level holds the
+// farthest depth of the level above. Anything whose nearest depth is behind the farthest depth
+// of the texels it covers is hidden by what's already drawn there
+pub struct HiZSystem {
+    uniform_buffer: Tracked<wgpu::Buffer>,
+    copy_bind_group_layout: wgpu::BindGroupLayout,
+    downsample_bind_group_layout: wgpu::BindGroupLayout,
+    bind_group_layout: wgpu::BindGroupLayout,
+    copy_pipeline: wgpu::ComputePipeline,
+    downsample_pipeline: wgpu::ComputePipeline,
+    // The first level is filled from the depth, the rest from the level before them
+    texture: Tracked<wgpu::Texture>,
+    levels: Vec<PyramidLevel>,
+    // The whole pyramid, read by culling
+    bind_group: wgpu::BindGroup,
+}
+
+impl HiZSystem {
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        depth_texture: &Texture,
+        depth: DepthConfig,
+        dimensions: &[u32; 2],
+    ) -> Self {
+        let uniform_buffer = memory::create_buffer(
+            device,
+            MemoryCategory::Transient,
+            &wgpu::BufferDescriptor {
+                label: Some("Hi-Z Uniform Buffer"),
+                size: std::mem::size_of::<PyramidUniform>() as wgpu::BufferAddress,
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            },
+        );
+
+        let texture_entry = |binding, sample_type| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Texture {
+                sample_type,
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        };
+        let unfiltered = wgpu::TextureSampleType::Float { filterable: false };
+        let destination_entry = wgpu::BindGroupLayoutEntry {
+            binding: 2,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::StorageTexture {
+                access: wgpu::StorageTextureAccess::WriteOnly,
+                format: HIZ_FORMAT,
+                view_dimension: wgpu::TextureViewDimension::D2,
+            },
+            count: None,
+        };
+
+        let copy_bind_group_layout = memory::create_bind_group_layout(
+            device,
+            &wgpu::BindGroupLayoutDescriptor {
+                label: Some("Hi-Z Copy Bind Group Layout"),
+                entries: &[
+                    texture_entry(0, wgpu::TextureSampleType::Depth),
+                    destination_entry,
+                ],
+            },
+        );
+
+        let downsample_bind_group_layout = memory::create_bind_group_layout(
+            device,
+            &wgpu::BindGroupLayoutDescriptor {
+                label: Some("Hi-Z Downsample Bind Group Layout"),
+                entries: &[texture_entry(1, unfiltered), destination_entry],
+            },
+        );
+
+        let bind_group_layout = memory::create_bind_group_layout(
+            device,
+            &wgpu::BindGroupLayoutDescriptor {
+                label: Some("Hi-Z Bind Group Layout"),
+                entries: &[
+                    texture_entry(0, unfiltered),
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                ],
+            },
+        );
+
+        let module = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("Hi-Z Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("shaders/hiz.wgsl").into()),
+        });
+
+        let create_pipeline = |label, layout, entry_point| {
+            let pipeline_layout = memory::create_pipeline_layout(
+                device,
+                &wgpu::PipelineLayoutDescriptor {
+                    label: Some(label),
+                    bind_group_layouts: &[layout],
+                    push_constant_ranges: &[],
+                },
+            );
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some(label),
+                layout: Some(&pipeline_layout),
+                module: &module,
+                entry_point,
+            })
+        };
+        let copy_pipeline = create_pipeline(
+            "Hi-Z Copy Pipeline",
+            &copy_bind_group_layout,
+            "cs_copy_depth",
+        );
+        let downsample_pipeline = create_pipeline(
+            "Hi-Z Downsample Pipeline",
+            &downsample_bind_group_layout,
+            if depth.reversed {
+                "cs_downsample_reversed"
+            } else {
+                "cs_downsample"
+            },
+        );
+
+        let (texture, levels, bind_group) = Self::create_pyramid(
+            device,
+            queue,
+            &uniform_buffer,
+            &copy_bind_group_layout,
+            &downsample_bind_group_layout,
+            &bind_group_layout,
+            depth_texture,
+            dimensions,
+        );
+
+        Self {
+            uniform_buffer,
+            copy_bind_group_layout,
+            downsample_bind_group_layout,
+            bind_group_layout,
+            copy_pipeline,
+            downsample_pipeline,
+            texture,
+            levels,
+            bind_group,
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn create_pyramid(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        uniform_buffer: &wgpu::Buffer,
+        copy_bind_group_layout: &wgpu::BindGroupLayout,
+        downsample_bind_group_layout: &wgpu::BindGroupLayout,
+        bind_group_layout: &wgpu::BindGroupLayout,
+        depth_texture: &Texture,
+        dimensions: &[u32; 2],
+    ) -> (Tracked<wgpu::Texture>, Vec<PyramidLevel>, wgpu::BindGroup) {
+        // Power of two sizes halve evenly, so every texel covers exactly four of the level above
+        let size = [
+            dimensions[0].max(1).next_power_of_two(),
+            dimensions[1].max(1).next_power_of_two(),
+        ];
+        let level_count = 32 - size[0].max(size[1]).leading_zeros();
+        let texture = memory::create_texture(
+            device,
+            MemoryCategory::Targets,
+            &wgpu::TextureDescriptor {
+                label: Some("Hi-Z Texture"),
+                size: wgpu::Extent3d {
+                    width: size[0],
+                    height: size[1],
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: level_count,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: HIZ_FORMAT,
+                usage: wgpu::TextureUsages::STORAGE_BINDING
+                    | wgpu::TextureUsages::TEXTURE_BINDING
+                    | wgpu::TextureUsages::COPY_SRC,
+            },
+        );
+        let level_view = |level| {
+            texture.create_view(&wgpu::TextureViewDescriptor {
+                label: Some("Hi-Z Level View"),
+                base_mip_level: level,
+                mip_level_count: std::num::NonZeroU32::new(1),
+                ..Default::default()
+            })
+        };
+        let views = (0..level_count).map(level_view).collect::<Vec<_>>();
+
+        let levels = (0..level_count as usize)
+            .map(|level| {
+                let (layout, source) = match level {
+                    0 => (
+                        copy_bind_group_layout,
+                        wgpu::BindGroupEntry {
+                            binding: 0,
+                            resource: wgpu::BindingResource::TextureView(&depth_texture.view),
+                        },
+                    ),
+                    _ => (
+                        downsample_bind_group_layout,
+                        wgpu::BindGroupEntry {
+                            binding: 1,
+                            resource: wgpu::BindingResource::TextureView(&views[level - 1]),
+                        },
+                    ),
+                };
+                let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: Some("Hi-Z Level Bind Group"),
+                    layout,
+                    entries: &[
+                        source,
+                        wgpu::BindGroupEntry {
+                            binding: 2,
+                            resource: wgpu::BindingResource::TextureView(&views[level]),
+                        },
+                    ],
+                });
+                PyramidLevel {
+                    bind_group,
+                    size: [(size[0] >> level).max(1), (size[1] >> level).max(1)],
+                }
+            })
+            .collect();
+
+        queue.write_buffer(
+            uniform_buffer,
+            0,
+            bytemuck::cast_slice(&[PyramidUniform {
+                depth_size: *dimensions,
+                levels: level_count,
+                _padding: 0,
+            }]),
+        );
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Hi-Z Bind Group"),
+            layout: bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        (texture, levels, bind_group)
+    }
+
+    pub fn bind_group_layout(&self) -> &wgpu::BindGroupLayout {
+        &self.bind_group_layout
+    }
+
+    pub fn bind_group(&self) -> &wgpu::BindGroup {
+        &self.bind_group
+    }
+
+    pub fn resize(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        depth_texture: &Texture,
+        dimensions: &[u32; 2],
+    ) {
+        let (texture, levels, bind_group) = Self::create_pyramid(
+            device,
+            queue,
+            &self.uniform_buffer,
+            &self.copy_bind_group_layout,
+            &self.downsample_bind_group_layout,
+            &self.bind_group_layout,
+            depth_texture,
+            dimensions,
+        );
+        self.texture = texture;
+        self.levels = levels;
+        self.bind_group = bind_group;
+    }
+
+    // The first level holds the depth as is, which can be copied out even when the depth
+    // texture has a stencil
+    pub fn texture(&self) -> &wgpu::Texture {
+        &self.texture
+    }
+
+    // Reduces the depth written so far this frame into the pyramid
+    pub fn render(&self, encoder: &mut wgpu::CommandEncoder) {
+        self.render_levels(encoder, self.levels.len());
+    }
+
+    // Only fills the first level
+    pub fn copy_depth(&self, encoder: &mut wgpu::CommandEncoder) {
+        self.render_levels(encoder, 1);
+    }
+
+    fn render_levels(&self, encoder: &mut wgpu::CommandEncoder, count: usize) {
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Hi-Z Pass"),
+        });
+        for (index, level) in self.levels.iter().take(count).enumerate() {
+            let pipeline = match index {
+                0 => &self.copy_pipeline,
+                _ => &self.downsample_pipeline,
+            };
+            compute_pass.set_pipeline(pipeline);
+            compute_pass.set_bind_group(0, &level.bind_group, &[]);
+            compute_pass.dispatch(
+                level.size[0].div_ceil(WORKGROUP_SIZE),
+                level.size[1].div_ceil(WORKGROUP_SIZE),
+                1,
+            );
+        }
+    }
+}
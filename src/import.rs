@@ -0,0 +1,1986 @@
+use anyhow::{bail, ensure, Context, Result};
+use gltf::animation::util::ReadOutputs;
+use nalgebra_glm as glm;
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    animation::{
+        AnimationChannel, AnimationClip, AnimationNode, Animations, ChannelValues, Interpolation,
+    },
+    draco::DracoMesh,
+    layers,
+    lighting::{PunctualLight, PunctualLightKind},
+    material::{
+        AlphaMode, Material, OcclusionBlend, ShadingModel, TextureTransform, TextureTransforms,
+        PAPER_WHITE_NITS,
+    },
+    meshopt::CompressedView,
+    model::{
+        Joint, Mesh, MeshInstance, ModelDesc, ModelVertex, NodeTransform, Primitive, Skeleton,
+    },
+    points::{CloudPoint, PointCloudDesc},
+    settings::ImportSettings,
+    texture::missing_image,
+};
+
+// Scales an imported model by the scene's units, then recenters and resizes it as the import
+// settings ask. Only the model's transform changes, so its meshes are kept as they were
+pub fn fit_model(model: &mut ModelDesc, settings: &ImportSettings, unit_scale: f32) {
+    let unit_scale = f64::from(unit_scale);
+    model.transform =
+        glm::scaling(&glm::vec3(unit_scale, unit_scale, unit_scale)) * model.transform;
+    let bounds = model.bounds();
+    if bounds.is_empty() {
+        return;
+    }
+    let longest_side = bounds.extents().max();
+    let scale = if settings.normalize && longest_side > f32::EPSILON {
+        settings.target_size / longest_side
+    } else {
+        1.0
+    };
+    let center = if settings.recenter {
+        bounds.center()
+    } else {
+        glm::Vec3::zeros()
+    };
+    // Scaled about the center when recentering, otherwise about the origin
+    model.transform = (glm::scaling(&glm::vec3(scale, scale, scale)) * glm::translation(&-center))
+        .cast::<f64>()
+        * model.transform;
+}
+
+// Accessors of Draco compressed primitives may leave out their buffer view,
+// which the gltf crate's validation otherwise rejects
+fn validate_gltf(document: gltf::Document) -> Result<gltf::Document> {
+    use gltf::json::validation::{Error, Validate};
+    let root = document.into_json();
+    let mut errors = Vec::new();
+    root.validate(&root, gltf::json::Path::new, &mut |path, error| {
+        let path = path();
+        let draco_accessor = path.as_str().starts_with("accessors[")
+            && path.as_str().ends_with(".bufferView")
+            && matches!(error, Error::Missing);
+        if !draco_accessor {
+            errors.push((path, error));
+        }
+    });
+    if !errors.is_empty() {
+        return Err(gltf::Error::Validation(errors).into());
+    }
+    Ok(gltf::Document::from_json_without_validation(root))
+}
+
+// External buffers and images are read relative to the path the file was loaded from
+pub fn import_gltf(bytes: &[u8], path: &Path) -> Result<ModelDesc> {
+    let failed = || format!("Failed to import glTF file: {}", path.display());
+    let gltf::Gltf { document, blob } =
+        gltf::Gltf::from_slice_without_validation(bytes).with_context(failed)?;
+    let document = validate_gltf(document).with_context(failed)?;
+    let extensions = Extensions::from_slice(bytes)?;
+
+    let base = path.parent().unwrap_or_else(|| Path::new("./"));
+    let mut buffers = load_buffers(&document, base, blob, &extensions)?;
+    decompress_buffer_views(&extensions, &mut buffers)?;
+    let draco_primitives = decode_draco_primitives(&document, &extensions, &buffers)?;
+    let (mut images, load_errors) = load_images(&document, base, &buffers);
+    let image_files = image_files(&document, base);
+
+    let mut packed_images = PackedImages::default();
+    let materials = document
+        .materials()
+        .map(|material| {
+            convert_material(
+                &material,
+                &document,
+                &extensions,
+                base,
+                &mut images,
+                &mut packed_images,
+            )
+        })
+        .collect();
+
+    let meshes = document
+        .meshes()
+        .map(|mesh| convert_mesh(&mesh, &buffers, &draco_primitives))
+        .collect::<Result<Vec<_>>>()?;
+
+    let punctual_lights = extensions.punctual_lights();
+    let mut visit = NodeVisit {
+        node_transforms: vec![None; document.nodes().len()],
+        node_indices: vec![None; document.nodes().len()],
+        ..Default::default()
+    };
+    if let Some(scene) = document
+        .default_scene()
+        .or_else(|| document.scenes().next())
+    {
+        for node in scene.nodes() {
+            visit_node(
+                &node,
+                None,
+                &NodeTransform::identity(),
+                &extensions,
+                &punctual_lights,
+                &mut visit,
+            );
+        }
+    }
+    let skeletons = convert_skeletons(&document, &visit.node_transforms);
+    let animations = convert_animations(&document, &buffers, &skeletons, &visit);
+
+    Ok(ModelDesc {
+        name: path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string()),
+        images,
+        materials,
+        meshes,
+        instances: visit.instances,
+        lights: visit.lights,
+        skeletons,
+        animations,
+        image_files,
+        load_errors,
+        ..Default::default()
+    })
+}
+
+// Reads a buffer or image uri, either embedded as base64 or relative to the glTF file
+fn read_uri(base: &Path, uri: &str) -> Result<Vec<u8>> {
+    if let Some(data) = uri.strip_prefix("data:") {
+        let (_, encoded) = data
+            .split_once(";base64,")
+            .context("Only base64 data uris are supported")?;
+        return Ok(base64::decode(encoded)?);
+    }
+    let path = uri_path(base, uri)?;
+    std::fs::read(&path).with_context(|| format!("Failed to read {}", path.display()))
+}
+
+fn uri_path(base: &Path, uri: &str) -> Result<PathBuf> {
+    Ok(
+        match uri
+            .strip_prefix("file://")
+            .or_else(|| uri.strip_prefix("file:"))
+        {
+            Some(path) => PathBuf::from(path),
+            None if uri.contains(':') => bail!("Unsupported uri scheme: {}", uri),
+            None => base.join(uri),
+        },
+    )
+}
+
+fn load_buffers(
+    document: &gltf::Document,
+    base: &Path,
+    mut blob: Option<Vec<u8>>,
+    extensions: &Extensions,
+) -> Result<Vec<Vec<u8>>> {
+    document
+        .buffers()
+        .map(|buffer| {
+            // Fallback buffers have no data of their own, compressed views are decoded into them
+            if extensions.is_fallback_buffer(buffer.index()) {
+                return Ok(vec![0; buffer.length()]);
+            }
+            let data = match buffer.source() {
+                gltf::buffer::Source::Bin => blob
+                    .take()
+                    .context("Missing the binary chunk of the glTF file")?,
+                gltf::buffer::Source::Uri(uri) => read_uri(base, uri)?,
+            };
+            ensure!(
+                data.len() >= buffer.length(),
+                "Buffer {} is {} bytes but should be at least {}",
+                buffer.index(),
+                data.len(),
+                buffer.length()
+            );
+            Ok(data)
+        })
+        .collect()
+}
+
+// Compressed views and primitives don't depend on each other, so they are decoded on worker threads
+#[cfg(not(target_arch = "wasm32"))]
+fn decode_on_workers<T: Sync, R: Send>(
+    items: &[T],
+    decode: impl Fn(&T) -> Result<R> + Sync,
+) -> Result<Vec<R>> {
+    let threads = std::thread::available_parallelism().map_or(1, |threads| threads.get());
+    let chunk_size = items.len().div_ceil(threads).max(1);
+    let decode = &decode;
+    std::thread::scope(|scope| {
+        let workers = items
+            .chunks(chunk_size)
+            .map(|items| scope.spawn(move || items.iter().map(decode).collect::<Result<Vec<_>>>()))
+            .collect::<Vec<_>>();
+        let mut decoded = Vec::with_capacity(items.len());
+        for worker in workers {
+            let items = worker
+                .join()
+                .map_err(|_| anyhow::anyhow!("Decoding thread panicked"))??;
+            decoded.extend(items);
+        }
+        Ok(decoded)
+    })
+}
+
+#[cfg(target_arch = "wasm32")]
+fn decode_on_workers<T, R>(items: &[T], decode: impl Fn(&T) -> Result<R>) -> Result<Vec<R>> {
+    items.iter().map(decode).collect()
+}
+
+// Writes the decoded EXT_meshopt_compression views over the buffer ranges the accessors read
+fn decompress_buffer_views(extensions: &Extensions, buffers: &mut [Vec<u8>]) -> Result<()> {
+    let views = extensions.compressed_views()?;
+    if views.is_empty() {
+        return Ok(());
+    }
+    let decoded = decode_on_workers(&views, |(_, view)| view.decode(buffers))?;
+    for ((target, _), data) in views.iter().zip(decoded) {
+        let range = target.offset..target.offset + data.len();
+        buffers
+            .get_mut(target.buffer)
+            .and_then(|buffer| buffer.get_mut(range))
+            .context("Decompressed buffer view doesn't fit in its buffer")?
+            .copy_from_slice(&data);
+    }
+    Ok(())
+}
+
+// Decoded KHR_draco_mesh_compression primitives by mesh and primitive index,
+// alongside the Draco attribute id of each glTF attribute semantic
+type DracoPrimitives = HashMap<(usize, usize), (DracoMesh, HashMap<String, u32>)>;
+
+fn decode_draco_primitives(
+    document: &gltf::Document,
+    extensions: &Extensions,
+    buffers: &[Vec<u8>],
+) -> Result<DracoPrimitives> {
+    let primitives = extensions.draco_primitives()?;
+    let decoded = decode_on_workers(&primitives, |primitive| {
+        let view = document
+            .views()
+            .nth(primitive.view)
+            .with_context(|| format!("Draco data of mesh {} has no buffer view", primitive.mesh))?;
+        let data = buffers
+            .get(view.buffer().index())
+            .and_then(|buffer| buffer.get(view.offset()..view.offset() + view.length()))
+            .context("Draco buffer view is out of bounds")?;
+        crate::draco::decode(data)
+            .with_context(|| format!("Failed to decode the Draco data of mesh {}", primitive.mesh))
+    })?;
+    Ok(primitives
+        .into_iter()
+        .zip(decoded)
+        .map(|(primitive, mesh)| {
+            (
+                (primitive.mesh, primitive.primitive),
+                (mesh, primitive.attributes),
+            )
+        })
+        .collect())
+}
+
+// Images that fail to load are replaced with a placeholder, and the failures returned
+fn load_images(
+    document: &gltf::Document,
+    base: &Path,
+    buffers: &[Vec<u8>],
+) -> (Vec<image::DynamicImage>, Vec<String>) {
+    let mut errors = Vec::new();
+    let images = document
+        .images()
+        .map(|image| {
+            let load = || -> Result<image::DynamicImage> {
+                let encoded = match image.source() {
+                    gltf::image::Source::View { view, .. } => buffers[view.buffer().index()]
+                        .get(view.offset()..view.offset() + view.length())
+                        .context("Image buffer view is out of bounds")?
+                        .to_vec(),
+                    gltf::image::Source::Uri { uri, .. } => read_uri(base, uri)?,
+                };
+                image::load_from_memory(&encoded)
+                    .with_context(|| format!("Failed to decode image {}", image.index()))
+            };
+            load().unwrap_or_else(|error| {
+                errors.push(format!("{:#}", error));
+                missing_image()
+            })
+        })
+        .collect();
+    (images, errors)
+}
+
+// The files of images that aren't embedded, by image index
+fn image_files(document: &gltf::Document, base: &Path) -> HashMap<usize, PathBuf> {
+    document
+        .images()
+        .filter_map(|image| match image.source() {
+            gltf::image::Source::Uri { uri, .. } if !uri.starts_with("data:") => {
+                Some((image.index(), uri_path(base, uri).ok()?))
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+struct DracoPrimitive {
+    mesh: usize,
+    primitive: usize,
+    view: usize,
+    attributes: HashMap<String, u32>,
+}
+
+struct BufferRange {
+    buffer: usize,
+    offset: usize,
+}
+
+// Extensions the gltf crate doesn't support are dropped while parsing,
+// so they are looked up in the document's raw json instead
+struct Extensions(gltf::json::Value);
+
+impl Extensions {
+    fn from_slice(bytes: &[u8]) -> Result<Self> {
+        let glb;
+        let json = if bytes.starts_with(b"glTF") {
+            glb = gltf::Glb::from_slice(bytes)?;
+            &glb.json
+        } else {
+            bytes
+        };
+        Ok(Self(gltf::json::deserialize::from_slice(json)?))
+    }
+
+    fn is_fallback_buffer(&self, index: usize) -> bool {
+        self.0
+            .get("buffers")
+            .and_then(|buffers| {
+                buffers
+                    .get(index)?
+                    .get("extensions")?
+                    .get("EXT_meshopt_compression")?
+                    .get("fallback")?
+                    .as_bool()
+            })
+            .unwrap_or(false)
+    }
+
+    // Each compressed view alongside the range of the buffer it decompresses into
+    fn compressed_views(&self) -> Result<Vec<(BufferRange, CompressedView)>> {
+        let views = match self.0.get("bufferViews").and_then(|views| views.as_array()) {
+            Some(views) => views,
+            None => return Ok(Vec::new()),
+        };
+        let mut compressed = Vec::new();
+        for (index, view) in views.iter().enumerate() {
+            let extension = match view
+                .get("extensions")
+                .and_then(|extensions| extensions.get("EXT_meshopt_compression"))
+            {
+                Some(extension) => extension,
+                None => continue,
+            };
+            let field = |value: &gltf::json::Value, name| {
+                value
+                    .get(name)
+                    .and_then(|field| field.as_u64())
+                    .map(|field| field as usize)
+            };
+            let invalid = || format!("Invalid EXT_meshopt_compression on buffer view {}", index);
+            let target = BufferRange {
+                buffer: field(view, "buffer").with_context(invalid)?,
+                offset: field(view, "byteOffset").unwrap_or(0),
+            };
+            compressed.push((
+                target,
+                CompressedView {
+                    buffer: field(extension, "buffer").with_context(invalid)?,
+                    offset: field(extension, "byteOffset").unwrap_or(0),
+                    length: field(extension, "byteLength").with_context(invalid)?,
+                    stride: field(extension, "byteStride").with_context(invalid)?,
+                    count: field(extension, "count").with_context(invalid)?,
+                    mode: extension
+                        .get("mode")
+                        .and_then(|mode| mode.as_str())
+                        .with_context(invalid)?
+                        .parse()?,
+                    filter: extension
+                        .get("filter")
+                        .and_then(|filter| filter.as_str())
+                        .unwrap_or("NONE")
+                        .parse()?,
+                    decoded_length: field(view, "byteLength").with_context(invalid)?,
+                },
+            ));
+        }
+        Ok(compressed)
+    }
+
+    fn draco_primitives(&self) -> Result<Vec<DracoPrimitive>> {
+        let mut primitives = Vec::new();
+        let meshes = self.0.get("meshes").and_then(|meshes| meshes.as_array());
+        for (mesh, value) in meshes.into_iter().flatten().enumerate() {
+            let mesh_primitives = value
+                .get("primitives")
+                .and_then(|primitives| primitives.as_array());
+            for (primitive, value) in mesh_primitives.into_iter().flatten().enumerate() {
+                let extension = match value
+                    .get("extensions")
+                    .and_then(|extensions| extensions.get("KHR_draco_mesh_compression"))
+                {
+                    Some(extension) => extension,
+                    None => continue,
+                };
+                let invalid = || format!("Invalid KHR_draco_mesh_compression on mesh {}", mesh);
+                let view = extension
+                    .get("bufferView")
+                    .and_then(|view| view.as_u64())
+                    .with_context(invalid)?;
+                let attributes = extension
+                    .get("attributes")
+                    .and_then(|attributes| attributes.as_object())
+                    .with_context(invalid)?
+                    .iter()
+                    .map(|(semantic, id)| {
+                        let id = id.as_u64().with_context(invalid)?;
+                        Ok((semantic.clone(), id as u32))
+                    })
+                    .collect::<Result<_>>()?;
+                primitives.push(DracoPrimitive {
+                    mesh,
+                    primitive,
+                    view: view as usize,
+                    attributes,
+                });
+            }
+        }
+        Ok(primitives)
+    }
+
+    fn material_property(&self, index: Option<usize>, path: &[&str]) -> Option<&gltf::json::Value> {
+        path.iter()
+            .try_fold(self.0.get("materials")?.get(index?)?, |value, property| {
+                value.get(property)
+            })
+    }
+
+    fn material(&self, index: Option<usize>, name: &str) -> Option<&gltf::json::Value> {
+        self.material_property(index, &["extensions", name])
+    }
+
+    fn material_f32(&self, index: Option<usize>, name: &str, property: &str) -> Option<f32> {
+        json_f32(self.material(index, name)?.get(property)?)
+    }
+
+    fn material_vec3(&self, index: Option<usize>, name: &str, property: &str) -> Option<glm::Vec3> {
+        json_vec3(self.material(index, name)?.get(property)?)
+    }
+
+    fn material_texture_info(
+        &self,
+        index: Option<usize>,
+        name: &str,
+        property: &str,
+    ) -> Option<&gltf::json::Value> {
+        self.material(index, name)?.get(property)
+    }
+
+    // Resolves the texture to the image it samples
+    fn material_texture(
+        &self,
+        document: &gltf::Document,
+        index: Option<usize>,
+        name: &str,
+        property: &str,
+    ) -> Option<usize> {
+        let texture = self
+            .material_texture_info(index, name, property)?
+            .get("index")?
+            .as_u64()?;
+        document
+            .textures()
+            .nth(texture as usize)
+            .map(|texture| texture.source().index())
+    }
+
+    // Lights shine down their local -Z axis until a node places them
+    fn punctual_lights(&self) -> Vec<PunctualLight> {
+        let lights = self.0.get("extensions").and_then(|extensions| {
+            extensions
+                .get("KHR_lights_punctual")?
+                .get("lights")?
+                .as_array()
+        });
+        lights
+            .into_iter()
+            .flatten()
+            .map(|light| {
+                let defaults = PunctualLight::default();
+                let spot = light.get("spot");
+                let spot_angle = |property, default| {
+                    spot.and_then(|spot| json_f32(spot.get(property)?))
+                        .unwrap_or(default)
+                };
+                let kind = match light.get("type").and_then(|kind| kind.as_str()) {
+                    Some("directional") => PunctualLightKind::Directional,
+                    Some("spot") => PunctualLightKind::Spot {
+                        inner_cone_angle: spot_angle("innerConeAngle", 0.0),
+                        outer_cone_angle: spot_angle("outerConeAngle", std::f32::consts::FRAC_PI_4),
+                    },
+                    _ => PunctualLightKind::Point,
+                };
+                PunctualLight {
+                    kind,
+                    color: light
+                        .get("color")
+                        .and_then(json_vec3)
+                        .unwrap_or(defaults.color),
+                    intensity: light
+                        .get("intensity")
+                        .and_then(json_f32)
+                        .unwrap_or(defaults.intensity),
+                    range: light.get("range").and_then(json_f32),
+                    ..defaults
+                }
+            })
+            .collect()
+    }
+
+    fn node_light(&self, node: usize) -> Option<usize> {
+        self.0
+            .get("nodes")?
+            .get(node)?
+            .get("extensions")?
+            .get("KHR_lights_punctual")?
+            .get("light")?
+            .as_u64()
+            .map(|light| light as usize)
+    }
+}
+
+fn json_f32(value: &gltf::json::Value) -> Option<f32> {
+    value.as_f64().map(|value| value as f32)
+}
+
+fn json_components(value: &gltf::json::Value) -> Option<Vec<f32>> {
+    value.as_array()?.iter().map(json_f32).collect()
+}
+
+fn json_vec2(value: &gltf::json::Value) -> Option<glm::Vec2> {
+    match json_components(value)?.as_slice() {
+        [x, y] => Some(glm::vec2(*x, *y)),
+        _ => None,
+    }
+}
+
+fn json_vec3(value: &gltf::json::Value) -> Option<glm::Vec3> {
+    match json_components(value)?.as_slice() {
+        [x, y, z] => Some(glm::vec3(*x, *y, *z)),
+        _ => None,
+    }
+}
+
+// Read from the KHR_texture_transform extension of a texture info
+fn texture_transform(info: Option<&gltf::json::Value>) -> TextureTransform {
+    let defaults = TextureTransform::default();
+    let tex_coord = |value: Option<&gltf::json::Value>| {
+        value
+            .and_then(|value| value.get("texCoord")?.as_u64())
+            .map(|tex_coord| tex_coord as u32)
+    };
+    let info_tex_coord = tex_coord(info).unwrap_or(defaults.tex_coord);
+    let transform = match info.and_then(|info| info.get("extensions")?.get("KHR_texture_transform"))
+    {
+        Some(transform) => transform,
+        None => {
+            return TextureTransform {
+                tex_coord: info_tex_coord,
+                ..defaults
+            }
+        }
+    };
+    TextureTransform {
+        offset: transform
+            .get("offset")
+            .and_then(json_vec2)
+            .unwrap_or(defaults.offset),
+        rotation: transform
+            .get("rotation")
+            .and_then(json_f32)
+            .unwrap_or(defaults.rotation),
+        scale: transform
+            .get("scale")
+            .and_then(json_vec2)
+            .unwrap_or(defaults.scale),
+        // The transform may also move the texture onto another set
+        tex_coord: tex_coord(Some(transform)).unwrap_or(info_tex_coord),
+    }
+}
+
+// Some material extensions split what the renderer reads from one image across two textures,
+// so their channels are combined into a new image unless both already share one
+#[derive(Default)]
+struct PackedImages {
+    packed: HashMap<(Option<usize>, Option<usize>, usize), usize>,
+}
+
+impl PackedImages {
+    // The first image provides the channels before the split, the second the channels after it
+    fn pack(
+        &mut self,
+        images: &mut Vec<image::DynamicImage>,
+        first: Option<usize>,
+        second: Option<usize>,
+        split: usize,
+    ) -> Option<usize> {
+        match (first, second) {
+            (None, None) => return None,
+            (Some(first), Some(second)) if first == second => return Some(first),
+            _ => {}
+        }
+        if let Some(index) = self.packed.get(&(first, second, split)) {
+            return Some(*index);
+        }
+
+        let sources = [first, second].map(|index| index.map(|index| images[index].to_rgba8()));
+        let width = sources
+            .iter()
+            .flatten()
+            .map(|source| source.width())
+            .max()?;
+        let height = sources
+            .iter()
+            .flatten()
+            .map(|source| source.height())
+            .max()?;
+
+        // Channels without a texture are left white so only the factor applies
+        let mut packed = image::RgbaImage::from_pixel(width, height, image::Rgba([255; 4]));
+        for (source, channels) in sources.iter().zip([0..split, split..4]) {
+            let source = match source {
+                Some(source) if source.dimensions() == (width, height) => source.clone(),
+                Some(source) => image::imageops::resize(
+                    source,
+                    width,
+                    height,
+                    image::imageops::FilterType::Triangle,
+                ),
+                None => continue,
+            };
+            for (pixel, source_pixel) in packed.pixels_mut().zip(source.pixels()) {
+                for channel in channels.clone() {
+                    pixel[channel] = source_pixel[channel];
+                }
+            }
+        }
+
+        images.push(image::DynamicImage::ImageRgba8(packed));
+        self.packed.insert((first, second, split), images.len() - 1);
+        Some(images.len() - 1)
+    }
+}
+
+// What's gathered while walking the scene's node hierarchy
+#[derive(Default)]
+struct NodeVisit {
+    instances: Vec<MeshInstance>,
+    lights: Vec<PunctualLight>,
+    // World transforms by glTF node index, None for nodes outside the scene
+    node_transforms: Vec<Option<NodeTransform>>,
+    // The hierarchy in the order it was visited, so parents come before their children
+    nodes: Vec<AnimationNode>,
+    // The index in nodes of each glTF node
+    node_indices: Vec<Option<usize>>,
+    instance_nodes: Vec<usize>,
+    // Lights relative to the node carrying them
+    light_nodes: Vec<(usize, PunctualLight)>,
+}
+
+fn visit_node(
+    node: &gltf::Node,
+    parent: Option<usize>,
+    parent_transform: &NodeTransform,
+    extensions: &Extensions,
+    punctual_lights: &[PunctualLight],
+    visit: &mut NodeVisit,
+) {
+    let transform = parent_transform * glm::Mat4::from(node.transform().matrix()).cast();
+    let (translation, rotation, scale) = node.transform().decomposed();
+    let index = visit.nodes.len();
+    visit.nodes.push(AnimationNode {
+        parent,
+        translation: translation.into(),
+        rotation: glm::quat(rotation[0], rotation[1], rotation[2], rotation[3]),
+        scale: scale.into(),
+    });
+    visit.node_indices[node.index()] = Some(index);
+    visit.node_transforms[node.index()] = Some(transform);
+    if let Some(mesh) = node.mesh() {
+        visit.instances.push(MeshInstance {
+            mesh: mesh.index(),
+            transform,
+            layers: layers::DEFAULT,
+        });
+        visit.instance_nodes.push(index);
+    }
+    if let Some(light) = extensions
+        .node_light(node.index())
+        .and_then(|light| punctual_lights.get(light))
+    {
+        visit.lights.push(light.transformed(&transform.cast()));
+        visit.light_nodes.push((index, *light));
+    }
+    for child in node.children() {
+        visit_node(
+            &child,
+            Some(index),
+            &transform,
+            extensions,
+            punctual_lights,
+            visit,
+        );
+    }
+}
+
+// Each joint's parent is its closest ancestor node that's a joint of the same skin
+fn convert_skeletons(
+    document: &gltf::Document,
+    node_transforms: &[Option<NodeTransform>],
+) -> Vec<Skeleton> {
+    let mut node_parents = vec![None; document.nodes().len()];
+    for node in document.nodes() {
+        for child in node.children() {
+            node_parents[child.index()] = Some(node.index());
+        }
+    }
+    document
+        .skins()
+        .map(|skin| {
+            let joint_nodes = skin.joints().map(|joint| joint.index()).collect::<Vec<_>>();
+            let joints = skin
+                .joints()
+                .map(|joint| {
+                    let mut ancestor = node_parents[joint.index()];
+                    let parent = loop {
+                        match ancestor {
+                            Some(node) => match joint_nodes.iter().position(|joint| *joint == node)
+                            {
+                                Some(parent) => break Some(parent),
+                                None => ancestor = node_parents[node],
+                            },
+                            None => break None,
+                        }
+                    };
+                    Joint {
+                        name: joint.name().map(str::to_string),
+                        parent,
+                        transform: node_transforms[joint.index()]
+                            .map(|transform| transform.cast())
+                            .unwrap_or_else(glm::Mat4::identity),
+                    }
+                })
+                .collect();
+            Skeleton {
+                name: skin.name().map(str::to_string),
+                joints,
+            }
+        })
+        .collect()
+}
+
+// Only models with clips keep their hierarchy. Morph target weights aren't supported and
+// channels animating nodes outside the scene are dropped
+fn convert_animations(
+    document: &gltf::Document,
+    buffers: &[Vec<u8>],
+    skeletons: &[Skeleton],
+    visit: &NodeVisit,
+) -> Option<Animations> {
+    let clips = document
+        .animations()
+        .map(|animation| {
+            let mut channels = Vec::new();
+            for channel in animation.channels() {
+                let node = match visit.node_indices[channel.target().node().index()] {
+                    Some(node) => node,
+                    None => continue,
+                };
+                let reader =
+                    channel.reader(|buffer| buffers.get(buffer.index()).map(Vec::as_slice));
+                let (times, values) = match (reader.read_inputs(), reader.read_outputs()) {
+                    (Some(times), Some(values)) => (times.collect::<Vec<_>>(), values),
+                    _ => continue,
+                };
+                let values = match values {
+                    ReadOutputs::Translations(translations) => {
+                        ChannelValues::Translations(translations.map(glm::Vec3::from).collect())
+                    }
+                    ReadOutputs::Rotations(rotations) => ChannelValues::Rotations(
+                        rotations
+                            .into_f32()
+                            .map(|[x, y, z, w]| glm::quat(x, y, z, w))
+                            .collect(),
+                    ),
+                    ReadOutputs::Scales(scales) => {
+                        ChannelValues::Scales(scales.map(glm::Vec3::from).collect())
+                    }
+                    ReadOutputs::MorphTargetWeights(_) => continue,
+                };
+                channels.push(AnimationChannel {
+                    node,
+                    times,
+                    values,
+                    interpolation: match channel.sampler().interpolation() {
+                        gltf::animation::Interpolation::Step => Interpolation::Step,
+                        gltf::animation::Interpolation::Linear => Interpolation::Linear,
+                        gltf::animation::Interpolation::CubicSpline => Interpolation::CubicSpline,
+                    },
+                });
+            }
+            AnimationClip {
+                name: animation.name().map(str::to_string),
+                duration: channels
+                    .iter()
+                    .filter_map(|channel| channel.times.last().copied())
+                    .fold(0.0, f32::max),
+                channels,
+            }
+        })
+        .collect::<Vec<_>>();
+    if clips.is_empty() {
+        return None;
+    }
+
+    let joint_nodes = document
+        .skins()
+        .take(skeletons.len())
+        .map(|skin| {
+            skin.joints()
+                .map(|joint| visit.node_indices[joint.index()])
+                .collect()
+        })
+        .collect();
+    Some(Animations::new(
+        clips,
+        visit.nodes.clone(),
+        visit.instance_nodes.clone(),
+        visit.light_nodes.clone(),
+        joint_nodes,
+    ))
+}
+
+fn convert_material(
+    material: &gltf::Material,
+    document: &gltf::Document,
+    extensions: &Extensions,
+    base: &Path,
+    images: &mut Vec<image::DynamicImage>,
+    packed_images: &mut PackedImages,
+) -> Material {
+    let defaults = Material::default();
+    let index = material.index();
+    let factor = |name, property, default| {
+        extensions
+            .material_f32(index, name, property)
+            .unwrap_or(default)
+    };
+    let color = |name, property, default| {
+        extensions
+            .material_vec3(index, name, property)
+            .unwrap_or(default)
+    };
+    let texture = |name, property| extensions.material_texture(document, index, name, property);
+    let transform = |path: &[&str]| texture_transform(extensions.material_property(index, path));
+    // Packed images are sampled with the transform of the texture providing their first channels
+    let packed_transform = |name, first, second| {
+        texture_transform(
+            extensions
+                .material_texture_info(index, name, first)
+                .or_else(|| extensions.material_texture_info(index, name, second)),
+        )
+    };
+
+    const CLEARCOAT: &str = "KHR_materials_clearcoat";
+    const SHEEN: &str = "KHR_materials_sheen";
+    const SPECULAR: &str = "KHR_materials_specular";
+    const TRANSMISSION: &str = "KHR_materials_transmission";
+    // Lightmaps exported for Hubs, the extension itself is the texture info
+    const LIGHTMAP: &str = "MOZ_lightmap";
+
+    let clearcoat_texture = packed_images.pack(
+        images,
+        texture(CLEARCOAT, "clearcoatTexture"),
+        texture(CLEARCOAT, "clearcoatRoughnessTexture"),
+        1,
+    );
+    let sheen_texture = packed_images.pack(
+        images,
+        texture(SHEEN, "sheenColorTexture"),
+        texture(SHEEN, "sheenRoughnessTexture"),
+        3,
+    );
+    let specular_texture = packed_images.pack(
+        images,
+        texture(SPECULAR, "specularColorTexture"),
+        texture(SPECULAR, "specularTexture"),
+        3,
+    );
+
+    let lightmap_texture = extensions
+        .material(index, LIGHTMAP)
+        .and_then(|info| info.get("index")?.as_u64())
+        .and_then(|texture| document.textures().nth(texture as usize))
+        .map(|texture| texture.source().index());
+
+    let pbr = material.pbr_metallic_roughness();
+    let image_index =
+        |info: Option<gltf::texture::Info>| info.map(|info| info.texture().source().index());
+    let occlusion = material.occlusion_texture();
+    let normal = material.normal_texture();
+    let occlusion_texture = occlusion
+        .as_ref()
+        .map(|occlusion| occlusion.texture().source().index());
+
+    Material {
+        name: material.name().map(str::to_string),
+        base_color_factor: pbr.base_color_factor().into(),
+        base_color_texture: image_index(pbr.base_color_texture()),
+        metallic_factor: pbr.metallic_factor(),
+        roughness_factor: pbr.roughness_factor(),
+        metallic_roughness_texture: image_index(pbr.metallic_roughness_texture()),
+        normal_texture: normal
+            .as_ref()
+            .map(|normal| normal.texture().source().index()),
+        normal_scale: normal.as_ref().map_or(1.0, |normal| normal.scale()),
+        occlusion_texture,
+        occlusion_strength: occlusion
+            .as_ref()
+            .map_or(1.0, |occlusion| occlusion.strength()),
+        emissive_factor: material.emissive_factor().into(),
+        emissive_texture: image_index(material.emissive_texture()),
+        emissive_luminance: factor("KHR_materials_emissive_strength", "emissiveStrength", 1.0)
+            * PAPER_WHITE_NITS,
+        alpha_mode: match material.alpha_mode() {
+            gltf::material::AlphaMode::Opaque => AlphaMode::Opaque,
+            gltf::material::AlphaMode::Mask => AlphaMode::Mask,
+            gltf::material::AlphaMode::Blend => AlphaMode::Blend,
+        },
+        alpha_cutoff: material.alpha_cutoff().unwrap_or(0.5),
+        double_sided: material.double_sided(),
+        clearcoat_factor: factor(CLEARCOAT, "clearcoatFactor", defaults.clearcoat_factor),
+        clearcoat_roughness_factor: factor(
+            CLEARCOAT,
+            "clearcoatRoughnessFactor",
+            defaults.clearcoat_roughness_factor,
+        ),
+        clearcoat_texture,
+        clearcoat_normal_texture: texture(CLEARCOAT, "clearcoatNormalTexture"),
+        clearcoat_normal_scale: extensions
+            .material_texture_info(index, CLEARCOAT, "clearcoatNormalTexture")
+            .and_then(|info| info.get("scale")?.as_f64())
+            .map_or(defaults.clearcoat_normal_scale, |scale| scale as f32),
+        transmission_factor: factor(
+            TRANSMISSION,
+            "transmissionFactor",
+            defaults.transmission_factor,
+        ),
+        transmission_texture: texture(TRANSMISSION, "transmissionTexture"),
+        sheen_color_factor: color(SHEEN, "sheenColorFactor", defaults.sheen_color_factor),
+        sheen_roughness_factor: factor(
+            SHEEN,
+            "sheenRoughnessFactor",
+            defaults.sheen_roughness_factor,
+        ),
+        sheen_texture,
+        specular_factor: factor(SPECULAR, "specularFactor", defaults.specular_factor),
+        specular_color_factor: color(
+            SPECULAR,
+            "specularColorFactor",
+            defaults.specular_color_factor,
+        ),
+        specular_texture,
+        ior: factor("KHR_materials_ior", "ior", defaults.ior),
+        lightmap_texture,
+        lightmap_intensity: factor(LIGHTMAP, "intensity", defaults.lightmap_intensity),
+        shading_model: if extensions.material(index, "KHR_materials_unlit").is_some() {
+            ShadingModel::Unlit
+        } else {
+            ShadingModel::Pbr
+        },
+        // Set as a custom property of the material, relative to the glTF file
+        shader: extensions
+            .material_property(index, &["extras", "shader"])
+            .and_then(|shader| shader.as_str())
+            .map(|shader| base.join(shader)),
+        texture_transforms: TextureTransforms {
+            base_color: transform(&["pbrMetallicRoughness", "baseColorTexture"]),
+            metallic_roughness: transform(&["pbrMetallicRoughness", "metallicRoughnessTexture"]),
+            normal: transform(&["normalTexture"]),
+            occlusion: transform(&["occlusionTexture"]),
+            emissive: transform(&["emissiveTexture"]),
+            clearcoat: packed_transform(CLEARCOAT, "clearcoatTexture", "clearcoatRoughnessTexture"),
+            clearcoat_normal: transform(&["extensions", CLEARCOAT, "clearcoatNormalTexture"]),
+            transmission: transform(&["extensions", TRANSMISSION, "transmissionTexture"]),
+            sheen: packed_transform(SHEEN, "sheenColorTexture", "sheenRoughnessTexture"),
+            specular: packed_transform(SPECULAR, "specularColorTexture", "specularTexture"),
+            lightmap: texture_transform(extensions.material(index, LIGHTMAP)),
+        },
+        // Baked occlusion already darkens the crevices screen space occlusion would find
+        occlusion_blend: if occlusion_texture.is_some() {
+            OcclusionBlend::Min
+        } else {
+            OcclusionBlend::Multiply
+        },
+        ..Default::default()
+    }
+}
+
+fn convert_mesh(
+    mesh: &gltf::Mesh,
+    buffers: &[Vec<u8>],
+    draco_primitives: &DracoPrimitives,
+) -> Result<Mesh> {
+    let mut primitives = Vec::new();
+    for primitive in mesh.primitives() {
+        // Points and lines aren't supported by the model pipelines
+        if primitive.mode() != gltf::mesh::Mode::Triangles {
+            continue;
+        }
+
+        // Draco compressed primitives are read from their decoded data rather than the accessors
+        if let Some((decoded, attributes)) =
+            draco_primitives.get(&(mesh.index(), primitive.index()))
+        {
+            primitives.push(convert_draco_primitive(
+                decoded,
+                attributes,
+                primitive.material().index(),
+            )?);
+            continue;
+        }
+
+        let reader = primitive.reader(|buffer| buffers.get(buffer.index()).map(Vec::as_slice));
+        let positions = reader
+            .read_positions()
+            .context("glTF primitives must have vertex positions!")?;
+        let mut vertices = positions
+            .map(|position| ModelVertex {
+                position,
+                ..Default::default()
+            })
+            .collect::<Vec<_>>();
+
+        let has_normals = match reader.read_normals() {
+            Some(normals) => {
+                for (vertex, normal) in vertices.iter_mut().zip(normals) {
+                    vertex.normal = normal;
+                }
+                true
+            }
+            None => false,
+        };
+
+        let has_tangents = match reader.read_tangents() {
+            Some(tangents) => {
+                for (vertex, tangent) in vertices.iter_mut().zip(tangents) {
+                    vertex.tangent = tangent;
+                }
+                true
+            }
+            None => false,
+        };
+
+        if let Some(uvs) = reader.read_tex_coords(0) {
+            for (vertex, uv) in vertices.iter_mut().zip(uvs.into_f32()) {
+                vertex.uv = uv;
+            }
+        }
+
+        if let Some(uvs) = reader.read_tex_coords(1) {
+            for (vertex, uv) in vertices.iter_mut().zip(uvs.into_f32()) {
+                vertex.uv_1 = uv;
+            }
+        }
+
+        if let Some(colors) = reader.read_colors(0) {
+            for (vertex, color) in vertices.iter_mut().zip(colors.into_rgba_f32()) {
+                vertex.color = color;
+            }
+        }
+
+        let indices = match reader.read_indices() {
+            Some(indices) => indices.into_u32().collect(),
+            None => (0..vertices.len() as u32).collect(),
+        };
+
+        let mut primitive = Primitive {
+            vertices,
+            indices,
+            material: primitive.material().index(),
+        };
+        if !has_normals {
+            primitive.generate_normals();
+        }
+        if !has_tangents {
+            primitive.generate_tangents();
+        }
+        primitives.push(primitive);
+    }
+
+    Ok(Mesh { primitives })
+}
+
+fn convert_draco_primitive(
+    mesh: &DracoMesh,
+    attributes: &HashMap<String, u32>,
+    material: Option<usize>,
+) -> Result<Primitive> {
+    let attribute = |semantic: &str| attributes.get(semantic).and_then(|id| mesh.attribute(*id));
+    let positions = attribute("POSITION").context("glTF primitives must have vertex positions!")?;
+    let mut vertices = positions
+        .points::<3>()
+        .into_iter()
+        .map(|position| ModelVertex {
+            position,
+            ..Default::default()
+        })
+        .collect::<Vec<_>>();
+
+    let normals = attribute("NORMAL");
+    if let Some(normals) = normals {
+        for (vertex, normal) in vertices.iter_mut().zip(normals.points()) {
+            vertex.normal = normal;
+        }
+    }
+
+    let tangents = attribute("TANGENT");
+    if let Some(tangents) = tangents {
+        for (vertex, tangent) in vertices.iter_mut().zip(tangents.points()) {
+            vertex.tangent = tangent;
+        }
+    }
+
+    if let Some(uvs) = attribute("TEXCOORD_0") {
+        for (vertex, uv) in vertices.iter_mut().zip(uvs.points()) {
+            vertex.uv = uv;
+        }
+    }
+
+    if let Some(uvs) = attribute("TEXCOORD_1") {
+        for (vertex, uv) in vertices.iter_mut().zip(uvs.points()) {
+            vertex.uv_1 = uv;
+        }
+    }
+
+    if let Some(colors) = attribute("COLOR_0") {
+        let opaque = colors.components < 4;
+        for (vertex, color) in vertices.iter_mut().zip(colors.points::<4>()) {
+            vertex.color = color;
+            if opaque {
+                vertex.color[3] = 1.0;
+            }
+        }
+    }
+
+    let mut primitive = Primitive {
+        vertices,
+        indices: mesh.indices.clone(),
+        material,
+    };
+    if normals.is_none() {
+        primitive.generate_normals();
+    }
+    if tangents.is_none() {
+        primitive.generate_tangents();
+    }
+    Ok(primitive)
+}
+
+// Wavefront OBJ files with their MTL material libraries, which are read relative to the file.
+// Faces with more than three corners are split into fans
+pub fn import_obj(bytes: &[u8], path: &Path) -> Result<ModelDesc> {
+    let text = std::str::from_utf8(bytes)
+        .with_context(|| format!("Failed to import OBJ file: {}", path.display()))?;
+    let base = path.parent().unwrap_or_else(|| Path::new("./"));
+
+    let mut positions: Vec<[f32; 3]> = Vec::new();
+    let mut colors: Vec<[f32; 4]> = Vec::new();
+    let mut uvs: Vec<[f32; 2]> = Vec::new();
+    let mut normals: Vec<[f32; 3]> = Vec::new();
+    let mut images = Vec::new();
+    let mut image_files = HashMap::new();
+    let mut load_errors = Vec::new();
+    let mut materials = Vec::new();
+    let mut material_indices = HashMap::new();
+    let mut builders: Vec<ObjPrimitive> = Vec::new();
+    let mut current = None;
+
+    for (line_index, line) in text.lines().enumerate() {
+        let words = line.split_whitespace().collect::<Vec<_>>();
+        let line_number = line_index + 1;
+        let numbers = |words: &[&str]| {
+            words
+                .iter()
+                .map(|word| word.parse::<f32>())
+                .collect::<Result<Vec<_>, _>>()
+                .with_context(|| format!("Invalid number on OBJ line {}", line_number))
+        };
+        match words.as_slice() {
+            ["v", values @ ..] => {
+                let values = numbers(values)?;
+                ensure!(
+                    values.len() >= 3,
+                    "OBJ line {} has a position with fewer than three components",
+                    line_number
+                );
+                positions.push([values[0], values[1], values[2]]);
+                // Some exporters append a color to each position
+                colors.push(match values.as_slice() {
+                    [_, _, _, r, g, b] => [*r, *g, *b, 1.0],
+                    _ => [1.0; 4],
+                });
+            }
+            ["vt", values @ ..] => {
+                let values = numbers(values)?;
+                ensure!(
+                    !values.is_empty(),
+                    "OBJ line {} has an empty texture coordinate",
+                    line_number
+                );
+                // OBJ texture coordinates start from the bottom of the image
+                uvs.push([values[0], 1.0 - values.get(1).copied().unwrap_or(0.0)]);
+            }
+            ["vn", x, y, z] => {
+                let values = numbers(&[x, y, z])?;
+                normals.push([values[0], values[1], values[2]]);
+            }
+            ["mtllib", names @ ..] => {
+                for name in names {
+                    let library_path = base.join(name);
+                    let library = std::fs::read_to_string(&library_path).with_context(|| {
+                        format!("Failed to read material library {}", library_path.display())
+                    })?;
+                    for material in parse_mtl(
+                        &library,
+                        base,
+                        &mut images,
+                        &mut image_files,
+                        &mut load_errors,
+                    )
+                    .with_context(|| format!("Failed to parse {}", library_path.display()))?
+                    {
+                        if let Some(name) = material.name.clone() {
+                            material_indices.insert(name, materials.len());
+                        }
+                        materials.push(material);
+                    }
+                }
+            }
+            ["usemtl", name] => current = material_indices.get(*name).copied(),
+            ["f", corners @ ..] => {
+                ensure!(
+                    corners.len() >= 3,
+                    "OBJ line {} has a face with fewer than three corners",
+                    line_number
+                );
+                let primitive = match builders
+                    .iter()
+                    .position(|builder| builder.primitive.material == current)
+                {
+                    Some(index) => &mut builders[index],
+                    None => {
+                        builders.push(ObjPrimitive::new(current));
+                        builders.last_mut().unwrap()
+                    }
+                };
+                let mut missing_normal = false;
+                let indices = corners
+                    .iter()
+                    .map(|corner| {
+                        let mut references = corner.split('/');
+                        let mut reference = |count: usize| {
+                            references
+                                .next()
+                                .filter(|reference| !reference.is_empty())
+                                .map(|reference| obj_index(reference, count))
+                                .transpose()
+                        };
+                        let position = reference(positions.len())?.with_context(|| {
+                            format!(
+                                "OBJ line {} has a face corner without a position",
+                                line_number
+                            )
+                        })?;
+                        let uv = reference(uvs.len())?;
+                        let normal = reference(normals.len())?;
+                        missing_normal |= normal.is_none();
+                        Ok(primitive.vertex(
+                            [
+                                position,
+                                uv.unwrap_or(usize::MAX),
+                                normal.unwrap_or(usize::MAX),
+                            ],
+                            || ModelVertex {
+                                position: positions[position],
+                                normal: normal.map(|normal| normals[normal]).unwrap_or_default(),
+                                uv: uv.map(|uv| uvs[uv]).unwrap_or_default(),
+                                color: colors[position],
+                                ..Default::default()
+                            },
+                        ))
+                    })
+                    .collect::<Result<Vec<_>>>()
+                    .with_context(|| format!("Invalid face on OBJ line {}", line_number))?;
+                primitive.has_normals &= !missing_normal;
+                for index in 1..indices.len() - 1 {
+                    primitive.primitive.indices.extend_from_slice(&[
+                        indices[0],
+                        indices[index],
+                        indices[index + 1],
+                    ]);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let primitives = builders
+        .into_iter()
+        .map(|builder| {
+            let mut primitive = builder.primitive;
+            if !builder.has_normals {
+                primitive.generate_normals();
+            }
+            primitive.generate_tangents();
+            primitive
+        })
+        .collect::<Vec<_>>();
+    ensure!(
+        !primitives.is_empty(),
+        "OBJ file {} has no faces",
+        path.display()
+    );
+
+    Ok(ModelDesc {
+        name: path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string()),
+        images,
+        materials: materials.into(),
+        meshes: vec![Mesh { primitives }],
+        instances: vec![MeshInstance {
+            mesh: 0,
+            transform: NodeTransform::identity(),
+            layers: layers::DEFAULT,
+        }],
+        image_files,
+        load_errors,
+        ..Default::default()
+    })
+}
+
+// One primitive per material, with each distinct combination of position, texture coordinate
+// and normal becoming a vertex
+struct ObjPrimitive {
+    primitive: Primitive,
+    vertices: HashMap<[usize; 3], u32>,
+    has_normals: bool,
+}
+
+impl ObjPrimitive {
+    fn new(material: Option<usize>) -> Self {
+        Self {
+            primitive: Primitive {
+                vertices: Vec::new(),
+                indices: Vec::new(),
+                material,
+            },
+            vertices: HashMap::new(),
+            has_normals: true,
+        }
+    }
+
+    fn vertex(&mut self, key: [usize; 3], create: impl FnOnce() -> ModelVertex) -> u32 {
+        let vertices = &mut self.primitive.vertices;
+        *self.vertices.entry(key).or_insert_with(|| {
+            vertices.push(create());
+            vertices.len() as u32 - 1
+        })
+    }
+}
+
+// Indices start from one, and negative indices count back from the last element declared
+fn obj_index(reference: &str, count: usize) -> Result<usize> {
+    let index = reference
+        .parse::<i64>()
+        .with_context(|| format!("Expected an index but found '{}'", reference))?;
+    let resolved = if index < 0 {
+        count as i64 + index
+    } else {
+        index - 1
+    };
+    ensure!(
+        (0..count as i64).contains(&resolved),
+        "Index {} is out of bounds",
+        index
+    );
+    Ok(resolved as usize)
+}
+
+// Converts the classic Phong parameters along with the PBR extension's roughness and metalness
+fn parse_mtl(
+    text: &str,
+    base: &Path,
+    images: &mut Vec<image::DynamicImage>,
+    image_files: &mut HashMap<usize, PathBuf>,
+    load_errors: &mut Vec<String>,
+) -> Result<Vec<Material>> {
+    let mut materials: Vec<Material> = Vec::new();
+    let mut image_indices: HashMap<String, usize> = HashMap::new();
+    for line in text.lines() {
+        let words = line.split_whitespace().collect::<Vec<_>>();
+        let (keyword, arguments) = match words.split_first() {
+            Some((keyword, arguments)) => (*keyword, arguments),
+            None => continue,
+        };
+        if keyword == "newmtl" {
+            materials.push(Material {
+                name: arguments.first().map(|name| name.to_string()),
+                metallic_factor: 0.0,
+                ..Default::default()
+            });
+            continue;
+        }
+        let material = match materials.last_mut() {
+            Some(material) => material,
+            None => continue,
+        };
+        let numbers = arguments
+            .iter()
+            .filter_map(|argument| argument.parse::<f32>().ok())
+            .collect::<Vec<_>>();
+        // Texture options come before the file name, which is the last argument
+        // Textures that fail to load are drawn with a placeholder, and still watched so they're
+        // loaded once they're fixed
+        let mut texture = || -> Option<usize> {
+            let name = arguments.last()?.to_string();
+            if let Some(index) = image_indices.get(&name) {
+                return Some(*index);
+            }
+            let image_path = base.join(&name);
+            let image = image::open(&image_path).unwrap_or_else(|error| {
+                load_errors.push(format!(
+                    "Failed to load texture {}: {}",
+                    image_path.display(),
+                    error
+                ));
+                missing_image()
+            });
+            images.push(image);
+            image_files.insert(images.len() - 1, image_path);
+            image_indices.insert(name, images.len() - 1);
+            Some(images.len() - 1)
+        };
+        match (keyword, numbers.as_slice()) {
+            ("Kd", [r, g, b, ..]) => {
+                material.base_color_factor = glm::vec4(*r, *g, *b, material.base_color_factor.w)
+            }
+            ("Ke", [r, g, b, ..]) => material.emissive_factor = glm::vec3(*r, *g, *b),
+            ("d", [opacity, ..]) => material.base_color_factor.w = *opacity,
+            ("Tr", [transparency, ..]) => material.base_color_factor.w = 1.0 - transparency,
+            // Shininess is mapped to the roughness of a similarly sized highlight
+            ("Ns", [exponent, ..]) => {
+                material.roughness_factor = (2.0 / (exponent.max(0.0) + 2.0)).sqrt()
+            }
+            ("Pr", [roughness, ..]) => material.roughness_factor = *roughness,
+            ("Pm", [metallic, ..]) => material.metallic_factor = *metallic,
+            ("map_Kd", _) => material.base_color_texture = texture(),
+            ("map_Ke", _) => material.emissive_texture = texture(),
+            ("map_Bump" | "map_bump" | "bump" | "norm", _) => material.normal_texture = texture(),
+            _ => {}
+        }
+        if material.base_color_factor.w < 1.0 {
+            material.alpha_mode = AlphaMode::Blend;
+        }
+    }
+    Ok(materials)
+}
+
+// Text files with a point per line, as x y z, x y z intensity, x y z r g b or x y z intensity r g b
+pub fn import_xyz(bytes: &[u8], path: &Path) -> Result<PointCloudDesc> {
+    let text = std::str::from_utf8(bytes)
+        .with_context(|| format!("Failed to read point cloud file: {}", path.display()))?;
+
+    let mut rows = Vec::new();
+    for (line_index, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with("//") {
+            continue;
+        }
+        let values = line
+            .split(|character: char| character.is_whitespace() || character == ',')
+            .filter(|value| !value.is_empty())
+            .map(str::parse::<f32>)
+            .collect::<Result<Vec<_>, _>>()
+            .with_context(|| {
+                format!(
+                    "Invalid point on line {} of {}",
+                    line_index + 1,
+                    path.display()
+                )
+            })?;
+        ensure!(
+            values.len() >= 3,
+            "Expected at least three coordinates on line {} of {}",
+            line_index + 1,
+            path.display()
+        );
+        rows.push(values);
+    }
+
+    // Colors are either bytes or fractions, decided by the whole file
+    let color_columns = |row: &[f32]| match row.len() {
+        6 => Some(3..6),
+        7 => Some(4..7),
+        _ => None,
+    };
+    let color_scale = if rows
+        .iter()
+        .filter_map(|row| color_columns(row).map(|columns| &row[columns]))
+        .flatten()
+        .any(|value| *value > 1.0)
+    {
+        1.0
+    } else {
+        255.0
+    };
+    let max_intensity = rows
+        .iter()
+        .filter(|row| row.len() == 4)
+        .map(|row| row[3])
+        .fold(0.0, f32::max);
+
+    let points = rows
+        .iter()
+        .map(|row| {
+            let color = match color_columns(row) {
+                Some(columns) => {
+                    let rgb = &row[columns];
+                    [rgb[0], rgb[1], rgb[2]].map(|value| (value * color_scale) as u8)
+                }
+                None if row.len() == 4 && max_intensity > 0.0 => {
+                    [(row[3] / max_intensity * 255.0) as u8; 3]
+                }
+                None => [255; 3],
+            };
+            CloudPoint {
+                position: glm::vec3(row[0], row[1], row[2]),
+                color: [color[0], color[1], color[2], 255],
+            }
+        })
+        .collect();
+
+    Ok(PointCloudDesc {
+        points,
+        ..Default::default()
+    })
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum PlyFormat {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum PlyType {
+    Int8,
+    Uint8,
+    Int16,
+    Uint16,
+    Int32,
+    Uint32,
+    Float32,
+    Float64,
+}
+
+impl PlyType {
+    fn parse(name: &str) -> Result<Self> {
+        Ok(match name {
+            "char" | "int8" => Self::Int8,
+            "uchar" | "uint8" => Self::Uint8,
+            "short" | "int16" => Self::Int16,
+            "ushort" | "uint16" => Self::Uint16,
+            "int" | "int32" => Self::Int32,
+            "uint" | "uint32" => Self::Uint32,
+            "float" | "float32" => Self::Float32,
+            "double" | "float64" => Self::Float64,
+            _ => bail!("Unknown PLY property type '{}'", name),
+        })
+    }
+
+    fn size(&self) -> usize {
+        match self {
+            Self::Int8 | Self::Uint8 => 1,
+            Self::Int16 | Self::Uint16 => 2,
+            Self::Int32 | Self::Uint32 | Self::Float32 => 4,
+            Self::Float64 => 8,
+        }
+    }
+
+    // Scale that brings a color stored in this type to bytes
+    fn color_scale(&self) -> f64 {
+        match self {
+            Self::Float32 | Self::Float64 => 255.0,
+            Self::Int16 | Self::Uint16 => 1.0 / 257.0,
+            _ => 1.0,
+        }
+    }
+}
+
+struct PlyProperty {
+    name: String,
+    kind: PlyType,
+    // Lists are preceded by their length in this type
+    count: Option<PlyType>,
+}
+
+struct PlyElement {
+    name: String,
+    count: usize,
+    properties: Vec<PlyProperty>,
+}
+
+enum PlyBody<'a> {
+    Ascii(std::str::SplitAsciiWhitespace<'a>),
+    Binary {
+        data: &'a [u8],
+        offset: usize,
+        big_endian: bool,
+    },
+}
+
+impl PlyBody<'_> {
+    fn read(&mut self, kind: PlyType) -> Result<f64> {
+        match self {
+            Self::Ascii(values) => {
+                let value = values.next().context("PLY data ended early")?;
+                Ok(value
+                    .parse::<f64>()
+                    .with_context(|| format!("Invalid PLY value '{}'", value))?)
+            }
+            Self::Binary {
+                data,
+                offset,
+                big_endian,
+            } => {
+                let bytes = data
+                    .get(*offset..*offset + kind.size())
+                    .context("PLY data ended early")?;
+                *offset += kind.size();
+                let mut buffer = [0; 8];
+                buffer[..bytes.len()].copy_from_slice(bytes);
+                if *big_endian {
+                    buffer[..bytes.len()].reverse();
+                }
+                let [b0, b1, b2, b3, b4, b5, b6, b7] = buffer;
+                Ok(match kind {
+                    PlyType::Int8 => b0 as i8 as f64,
+                    PlyType::Uint8 => b0 as f64,
+                    PlyType::Int16 => i16::from_le_bytes([b0, b1]) as f64,
+                    PlyType::Uint16 => u16::from_le_bytes([b0, b1]) as f64,
+                    PlyType::Int32 => i32::from_le_bytes([b0, b1, b2, b3]) as f64,
+                    PlyType::Uint32 => u32::from_le_bytes([b0, b1, b2, b3]) as f64,
+                    PlyType::Float32 => f32::from_le_bytes([b0, b1, b2, b3]) as f64,
+                    PlyType::Float64 => f64::from_le_bytes([b0, b1, b2, b3, b4, b5, b6, b7]),
+                })
+            }
+        }
+    }
+}
+
+// Reads the vertices of ASCII or binary PLY files, with their colors when present
+pub fn import_ply(bytes: &[u8], path: &Path) -> Result<PointCloudDesc> {
+    let points = parse_ply(bytes)
+        .with_context(|| format!("Failed to import PLY file: {}", path.display()))?;
+    Ok(PointCloudDesc {
+        points,
+        ..Default::default()
+    })
+}
+
+fn parse_ply(bytes: &[u8]) -> Result<Vec<CloudPoint>> {
+    const END_HEADER: &[u8] = b"end_header";
+    let header_end = bytes
+        .windows(END_HEADER.len())
+        .position(|window| window == END_HEADER)
+        .context("Missing end of PLY header")?;
+    let mut body_start = header_end + END_HEADER.len();
+    if bytes.get(body_start) == Some(&b'\r') {
+        body_start += 1;
+    }
+    ensure!(
+        bytes.get(body_start) == Some(&b'\n'),
+        "Malformed end of PLY header"
+    );
+    body_start += 1;
+
+    let header = std::str::from_utf8(&bytes[..header_end])?;
+    let mut lines = header.lines().map(str::trim);
+    ensure!(lines.next() == Some("ply"), "Not a PLY file");
+
+    let mut format = None;
+    let mut elements: Vec<PlyElement> = Vec::new();
+    for line in lines {
+        let words = line.split_whitespace().collect::<Vec<_>>();
+        match words.as_slice() {
+            ["format", kind, _version] => {
+                format = Some(match *kind {
+                    "ascii" => PlyFormat::Ascii,
+                    "binary_little_endian" => PlyFormat::BinaryLittleEndian,
+                    "binary_big_endian" => PlyFormat::BinaryBigEndian,
+                    _ => bail!("Unknown PLY format '{}'", kind),
+                })
+            }
+            ["element", name, count] => elements.push(PlyElement {
+                name: name.to_string(),
+                count: count.parse()?,
+                properties: Vec::new(),
+            }),
+            ["property", "list", count, kind, name] => elements
+                .last_mut()
+                .context("PLY property declared before any element")?
+                .properties
+                .push(PlyProperty {
+                    name: name.to_string(),
+                    kind: PlyType::parse(kind)?,
+                    count: Some(PlyType::parse(count)?),
+                }),
+            ["property", kind, name] => elements
+                .last_mut()
+                .context("PLY property declared before any element")?
+                .properties
+                .push(PlyProperty {
+                    name: name.to_string(),
+                    kind: PlyType::parse(kind)?,
+                    count: None,
+                }),
+            _ => {}
+        }
+    }
+
+    let format = format.context("Missing PLY format")?;
+    let mut body = match format {
+        PlyFormat::Ascii => {
+            PlyBody::Ascii(std::str::from_utf8(&bytes[body_start..])?.split_ascii_whitespace())
+        }
+        format => PlyBody::Binary {
+            data: &bytes[body_start..],
+            offset: 0,
+            big_endian: format == PlyFormat::BinaryBigEndian,
+        },
+    };
+
+    // Counts come from the header, so they're checked against the data that follows it before
+    // anything is allocated for them. ASCII values take at least a byte each
+    let body_length = bytes.len() - body_start;
+    for element in elements.iter() {
+        let record_size = element
+            .properties
+            .iter()
+            .map(|property| match format {
+                PlyFormat::Ascii => 1,
+                _ => property.count.unwrap_or(property.kind).size(),
+            })
+            .sum::<usize>()
+            .max(1);
+        ensure!(
+            element
+                .count
+                .checked_mul(record_size)
+                .is_some_and(|size| size <= body_length),
+            "PLY element '{}' declares {} records, more than the file holds",
+            element.name,
+            element.count
+        );
+    }
+
+    // Elements before the vertices are read and skipped
+    for element in elements.iter() {
+        if element.name != "vertex" {
+            for _ in 0..element.count {
+                for property in element.properties.iter() {
+                    let count = match property.count {
+                        Some(count) => body.read(count)? as usize,
+                        None => 1,
+                    };
+                    for _ in 0..count {
+                        body.read(property.kind)?;
+                    }
+                }
+            }
+            continue;
+        }
+
+        let mut points = Vec::with_capacity(element.count);
+        for _ in 0..element.count {
+            let mut point = CloudPoint {
+                position: glm::Vec3::zeros(),
+                color: [255; 4],
+            };
+            for property in element.properties.iter() {
+                if let Some(count) = property.count {
+                    for _ in 0..body.read(count)? as usize {
+                        body.read(property.kind)?;
+                    }
+                    continue;
+                }
+                let value = body.read(property.kind)?;
+                let color = || {
+                    (value * property.kind.color_scale())
+                        .round()
+                        .clamp(0.0, 255.0) as u8
+                };
+                match property.name.as_str() {
+                    "x" => point.position.x = value as f32,
+                    "y" => point.position.y = value as f32,
+                    "z" => point.position.z = value as f32,
+                    "red" | "r" | "diffuse_red" => point.color[0] = color(),
+                    "green" | "g" | "diffuse_green" => point.color[1] = color(),
+                    "blue" | "b" | "diffuse_blue" => point.color[2] = color(),
+                    "alpha" | "a" => point.color[3] = color(),
+                    _ => {}
+                }
+            }
+            points.push(point);
+        }
+        return Ok(points);
+    }
+    bail!("PLY file has no vertex element")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ply(header: &str, body: &[u8]) -> Vec<u8> {
+        let mut bytes = format!("ply\n{}\nend_header\n", header).into_bytes();
+        bytes.extend_from_slice(body);
+        bytes
+    }
+
+    const POINT_HEADER: &str = "element vertex 2\n\
+        property float x\nproperty float y\nproperty float z\n\
+        property uchar red\nproperty uchar green\nproperty uchar blue";
+
+    #[test]
+    fn reads_ascii_ply() {
+        let bytes = ply(
+            &format!("format ascii 1.0\n{}", POINT_HEADER),
+            b"1 2 3 255 0 0\n-1 0.5 0 0 128 255\n",
+        );
+        let points = parse_ply(&bytes).unwrap();
+        assert_eq!(points.len(), 2);
+        assert_eq!(points[0].position, glm::vec3(1.0, 2.0, 3.0));
+        assert_eq!(points[0].color, [255, 0, 0, 255]);
+        assert_eq!(points[1].position, glm::vec3(-1.0, 0.5, 0.0));
+        assert_eq!(points[1].color, [0, 128, 255, 255]);
+    }
+
+    #[test]
+    fn reads_binary_ply() {
+        let mut body = Vec::new();
+        for (position, color) in [
+            ([1.0f32, 2.0, 3.0], [10u8, 20, 30]),
+            ([4.0, 5.0, 6.0], [0; 3]),
+        ] {
+            for value in position {
+                body.extend_from_slice(&value.to_be_bytes());
+            }
+            body.extend_from_slice(&color);
+        }
+        let bytes = ply(
+            &format!("format binary_big_endian 1.0\n{}", POINT_HEADER),
+            &body,
+        );
+        let points = parse_ply(&bytes).unwrap();
+        assert_eq!(points.len(), 2);
+        assert_eq!(points[0].color, [10, 20, 30, 255]);
+        assert_eq!(points[1].position, glm::vec3(4.0, 5.0, 6.0));
+    }
+
+    #[test]
+    fn rejects_truncated_ply() {
+        let bytes = ply(
+            &format!("format binary_little_endian 1.0\n{}", POINT_HEADER),
+            &[0; 20],
+        );
+        assert!(parse_ply(&bytes).is_err());
+    }
+
+    #[test]
+    fn rejects_ply_counts_larger_than_the_file() {
+        let bytes = ply(
+            "format binary_little_endian 1.0\nelement vertex 100000000000000\nproperty float x",
+            &[0; 16],
+        );
+        let error = parse_ply(&bytes).unwrap_err();
+        assert!(error.to_string().contains("more than the file holds"));
+
+        let bytes = ply(
+            "format ascii 1.0\nelement vertex 18446744073709551615\n\
+             property double x\nproperty double y",
+            b"0 0\n",
+        );
+        assert!(parse_ply(&bytes).is_err());
+    }
+
+    #[test]
+    fn reads_xyz_points_and_rejects_malformed_lines() {
+        let path = Path::new("points.xyz");
+        let cloud = import_xyz(b"# comment\n0 0 0 255 128 0\n1,2,3,0,0,0\n", path).unwrap();
+        assert_eq!(cloud.points.len(), 2);
+        assert_eq!(cloud.points[0].color, [255, 128, 0, 255]);
+        assert_eq!(cloud.points[1].position, glm::vec3(1.0, 2.0, 3.0));
+
+        let error = import_xyz(b"0 0 0\n1 2\n", path).unwrap_err();
+        assert!(error.to_string().contains("line 2"));
+        assert!(import_xyz(b"0 0 zero\n", path).is_err());
+    }
+
+    #[test]
+    fn reads_obj_faces_and_rejects_malformed_lines() {
+        let path = Path::new("quad.obj");
+        let model = import_obj(
+            b"v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nvt 0 0\nf 1/1 2/1 3/1 4/1\n",
+            path,
+        )
+        .unwrap();
+        let primitive = &model.meshes[0].primitives[0];
+        assert_eq!(primitive.indices, vec![0, 1, 2, 0, 2, 3]);
+        assert_eq!(model.name.as_deref(), Some("quad"));
+
+        for text in [
+            "v 0 0\n",
+            "v 0 0 zero\n",
+            "v 0 0 0\nv 1 0 0\nf 1 2\n",
+            "v 0 0 0\nv 1 0 0\nv 1 1 0\nf 1 2 4\n",
+            "v 0 0 0\n",
+        ] {
+            assert!(
+                import_obj(text.as_bytes(), path).is_err(),
+                "Accepted {:?}",
+                text
+            );
+        }
+    }
+}
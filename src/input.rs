@@ -0,0 +1,518 @@
+use instant::Instant;
+use nalgebra_glm as glm;
+use std::collections::{HashMap, HashSet};
+use winit::{
+    event::{ElementState, MouseButton, MouseScrollDelta, Touch, TouchPhase, VirtualKeyCode},
+    window::Window,
+};
+
+use crate::{
+    actions::{Action, ActionMap, Binding, GamepadButton},
+    bookmarks::{CameraPose, CameraTransition},
+    bounds::Aabb,
+    camera::Camera,
+    camera_2d::Camera2d,
+    camera_path::ScriptedCamera,
+    follow::FollowCamera,
+    motion::MotionSensor,
+    orientation::DeviceOrientationCamera,
+    scene::{ModelHandle, Scene},
+};
+
+const TOUCH_ORBIT_SENSITIVITY: f32 = 0.005;
+const MOUSE_ORBIT_SENSITIVITY: f32 = 0.005;
+// Radians per unit of raw mouse motion while the cursor is grabbed
+const MOUSE_LOOK_SENSITIVITY: f32 = 0.003;
+// Zoom applied per line scrolled, and the pixels treated as one line by precise touchpads
+const SCROLL_ZOOM_FACTOR: f32 = 0.9;
+const PIXELS_PER_SCROLL_LINE: f32 = 20.0;
+// Movement in distances to the orbit target per second, and in meters per second in first person
+const MOVE_SPEED: f32 = 1.0;
+const FIRST_PERSON_SPEED: f32 = 3.0;
+// Radians per second with a stick fully deflected, and the zoom rate of held zoom actions
+const STICK_ORBIT_SPEED: f32 = 2.5;
+const ZOOM_SPEED: f32 = 1.5;
+// Pixels per second the 2D camera scrolls while moving
+const PAN_SPEED: f32 = 600.0;
+const TAP_MAX_SECONDS: f32 = 0.25;
+const TAP_MAX_DISTANCE: f32 = 20.0;
+const DOUBLE_TAP_MAX_SECONDS: f32 = 0.35;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CameraMode {
+    Orbit,
+    DeviceOrientation,
+    // Orbits a model from behind, see FollowCamera
+    Follow,
+    // Turns in place and moves along the view, best with the cursor grabbed
+    FirstPerson,
+    // Follows a turntable or spline path, see ScriptedCamera
+    Scripted,
+}
+
+pub struct Input {
+    pub camera_mode: CameraMode,
+    touches: HashMap<u64, glm::Vec2>,
+    pub action_map: ActionMap,
+    keys: HashSet<VirtualKeyCode>,
+    previous_keys: HashSet<VirtualKeyCode>,
+    buttons: HashSet<MouseButton>,
+    previous_buttons: HashSet<MouseButton>,
+    gamepad_buttons: HashSet<GamepadButton>,
+    previous_gamepad_buttons: HashSet<GamepadButton>,
+    // Stick deflections with dead zones already removed, y pointing up
+    left_stick: glm::Vec2,
+    right_stick: glm::Vec2,
+    // Last known cursor position in pixels from the top left corner of the window
+    cursor_position: glm::Vec2,
+    // Cursor movement and lines scrolled since the last frame
+    cursor_delta: glm::Vec2,
+    scroll: f32,
+    // While grabbed the cursor is hidden and held in place, and raw mouse motion turns the camera
+    cursor_grabbed: bool,
+    look_delta: glm::Vec2,
+    orbit_delta: glm::Vec2,
+    zoom_factor: f32,
+    tap_candidate: Option<(u64, Instant, glm::Vec2)>,
+    last_tap: Option<(Instant, glm::Vec2)>,
+    motion_sensor: Option<MotionSensor>,
+    device_orientation_camera: DeviceOrientationCamera,
+    pub follow_camera: FollowCamera,
+    pub scripted_camera: ScriptedCamera,
+    // Takes over the camera until it reaches the pose it's moving to
+    transition: Option<CameraTransition>,
+    last_update: Instant,
+}
+
+impl Default for Input {
+    fn default() -> Self {
+        Self {
+            camera_mode: CameraMode::Orbit,
+            touches: HashMap::new(),
+            action_map: ActionMap::default(),
+            keys: HashSet::new(),
+            previous_keys: HashSet::new(),
+            buttons: HashSet::new(),
+            previous_buttons: HashSet::new(),
+            gamepad_buttons: HashSet::new(),
+            previous_gamepad_buttons: HashSet::new(),
+            left_stick: glm::Vec2::zeros(),
+            right_stick: glm::Vec2::zeros(),
+            cursor_position: glm::Vec2::zeros(),
+            cursor_delta: glm::Vec2::zeros(),
+            scroll: 0.0,
+            cursor_grabbed: false,
+            look_delta: glm::Vec2::zeros(),
+            orbit_delta: glm::Vec2::zeros(),
+            zoom_factor: 1.0,
+            tap_candidate: None,
+            last_tap: None,
+            motion_sensor: MotionSensor::new(),
+            device_orientation_camera: DeviceOrientationCamera::default(),
+            follow_camera: FollowCamera::default(),
+            scripted_camera: ScriptedCamera::default(),
+            transition: None,
+            last_update: Instant::now(),
+        }
+    }
+}
+
+impl Input {
+    pub fn set_camera_mode(&mut self, camera_mode: CameraMode) {
+        if camera_mode == CameraMode::DeviceOrientation && self.motion_sensor.is_none() {
+            return;
+        }
+        if camera_mode != self.camera_mode {
+            self.device_orientation_camera.recenter();
+        }
+        self.camera_mode = camera_mode;
+    }
+
+    // Starts the scripted camera's path from the beginning, circling the given bounds
+    pub fn play_path(&mut self, bounds: Aabb) {
+        self.scripted_camera.restart(bounds);
+        self.set_camera_mode(CameraMode::Scripted);
+    }
+
+    // Moves the camera to a pose over the given seconds. Cameras that place themselves are
+    // switched to orbiting so they stay where the pose leaves them
+    pub fn transition_to(&mut self, camera: &mut Camera, pose: CameraPose, duration: f32) {
+        if !matches!(
+            self.camera_mode,
+            CameraMode::Orbit | CameraMode::FirstPerson
+        ) {
+            self.set_camera_mode(CameraMode::Orbit);
+        }
+        if duration <= 0.0 {
+            pose.apply(camera);
+            self.transition = None;
+        } else {
+            self.transition = Some(CameraTransition::new(camera, pose, duration));
+        }
+    }
+
+    // Keeps the camera's transition and the followed pivot where they are in the world when the
+    // scene's origin moves by the offset
+    pub fn translate(&mut self, offset: &glm::Vec3) {
+        if let Some(transition) = self.transition.as_mut() {
+            transition.translate(offset);
+        }
+        self.follow_camera.translate(offset);
+    }
+
+    pub fn follow(&mut self, model: ModelHandle) {
+        self.follow_camera.follow(model);
+        self.set_camera_mode(CameraMode::Follow);
+    }
+
+    pub fn cursor_position(&self) -> glm::Vec2 {
+        self.cursor_position
+    }
+
+    pub fn cursor_grabbed(&self) -> bool {
+        self.cursor_grabbed
+    }
+
+    pub fn set_cursor_grabbed(&mut self, window: &Window, grabbed: bool) {
+        if grabbed == self.cursor_grabbed {
+            return;
+        }
+        // Some platforms can't confine the cursor, which leaves it free and visible
+        if let Err(error) = window.set_cursor_grab(grabbed) {
+            eprintln!("Warning: Failed to set the cursor grab: {}", error);
+            if grabbed {
+                return;
+            }
+        }
+        window.set_cursor_visible(!grabbed);
+        self.cursor_grabbed = grabbed;
+        self.look_delta = glm::Vec2::zeros();
+    }
+
+    #[allow(dead_code)]
+    pub fn cursor_delta(&self) -> glm::Vec2 {
+        self.cursor_delta
+    }
+
+    #[allow(dead_code)]
+    pub fn scroll(&self) -> f32 {
+        self.scroll
+    }
+
+    #[allow(dead_code)]
+    pub fn key_down(&self, keycode: VirtualKeyCode) -> bool {
+        self.keys.contains(&keycode)
+    }
+
+    #[allow(dead_code)]
+    pub fn button_down(&self, button: MouseButton) -> bool {
+        self.buttons.contains(&button)
+    }
+
+    fn binding_down(&self, binding: Binding, previous: bool) -> bool {
+        match (binding, previous) {
+            (Binding::Key(keycode), false) => self.keys.contains(&keycode),
+            (Binding::Key(keycode), true) => self.previous_keys.contains(&keycode),
+            (Binding::Mouse(button), false) => self.buttons.contains(&button),
+            (Binding::Mouse(button), true) => self.previous_buttons.contains(&button),
+            (Binding::Gamepad(button), false) => self.gamepad_buttons.contains(&button),
+            (Binding::Gamepad(button), true) => self.previous_gamepad_buttons.contains(&button),
+            (Binding::KeyChord(modifier, keycode), false) => {
+                self.keys.contains(&modifier) && self.keys.contains(&keycode)
+            }
+            (Binding::KeyChord(modifier, keycode), true) => {
+                self.previous_keys.contains(&modifier) && self.previous_keys.contains(&keycode)
+            }
+        }
+    }
+
+    fn action_was_down(&self, action: Action, previous: bool) -> bool {
+        self.action_map
+            .bindings(action)
+            .iter()
+            .any(|binding| self.binding_down(*binding, previous))
+    }
+
+    pub fn action_down(&self, action: Action) -> bool {
+        self.action_was_down(action, false)
+    }
+
+    // Whether the action started since the last frame
+    pub fn action_pressed(&self, action: Action) -> bool {
+        self.action_was_down(action, false) && !self.action_was_down(action, true)
+    }
+
+    #[allow(dead_code)]
+    pub fn action_released(&self, action: Action) -> bool {
+        !self.action_was_down(action, false) && self.action_was_down(action, true)
+    }
+
+    // -1 while only the negative action is down, 1 while only the positive one is
+    pub fn action_axis(&self, negative: Action, positive: Action) -> f32 {
+        let value = |action| if self.action_down(action) { 1.0 } else { 0.0 };
+        value(positive) - value(negative)
+    }
+
+    pub fn handle_cursor_moved(&mut self, position: glm::Vec2) {
+        self.cursor_delta += position - self.cursor_position;
+        self.cursor_position = position;
+    }
+
+    // Unaccelerated device movement, which keeps coming when the cursor is stuck at an edge
+    pub fn handle_mouse_motion(&mut self, delta: glm::Vec2) {
+        if self.cursor_grabbed {
+            self.look_delta += delta;
+        }
+    }
+
+    pub fn handle_keyboard_input(&mut self, state: ElementState, keycode: VirtualKeyCode) {
+        match state {
+            ElementState::Pressed => self.keys.insert(keycode),
+            ElementState::Released => self.keys.remove(&keycode),
+        };
+    }
+
+    pub fn handle_mouse_input(&mut self, state: ElementState, button: MouseButton) {
+        match state {
+            ElementState::Pressed => self.buttons.insert(button),
+            ElementState::Released => self.buttons.remove(&button),
+        };
+    }
+
+    pub fn handle_mouse_wheel(&mut self, delta: MouseScrollDelta) {
+        self.scroll += match delta {
+            MouseScrollDelta::LineDelta(_, lines) => lines,
+            MouseScrollDelta::PixelDelta(position) => position.y as f32 / PIXELS_PER_SCROLL_LINE,
+        };
+    }
+
+    // Replaces the state of every connected gamepad combined, polled once per update
+    #[cfg_attr(not(feature = "gamepad"), allow(dead_code))]
+    pub fn handle_gamepads(
+        &mut self,
+        buttons: HashSet<GamepadButton>,
+        left_stick: glm::Vec2,
+        right_stick: glm::Vec2,
+    ) {
+        self.gamepad_buttons = buttons;
+        self.left_stick = left_stick;
+        self.right_stick = right_stick;
+    }
+
+    // Releases are never seen while another window has focus, so nothing is left held down,
+    // and the cursor is handed back
+    pub fn handle_focus_lost(&mut self, window: &Window) {
+        self.keys.clear();
+        self.buttons.clear();
+        self.set_cursor_grabbed(window, false);
+    }
+
+    // Called once the frame has consumed its input, so presses and releases are seen for one frame
+    pub fn end_frame(&mut self) {
+        self.previous_keys = self.keys.clone();
+        self.previous_buttons = self.buttons.clone();
+        self.previous_gamepad_buttons = self.gamepad_buttons.clone();
+        self.cursor_delta = glm::Vec2::zeros();
+        self.look_delta = glm::Vec2::zeros();
+        self.scroll = 0.0;
+    }
+
+    pub fn handle_touch(&mut self, touch: &Touch) {
+        let position = glm::vec2(touch.location.x as f32, touch.location.y as f32);
+        match touch.phase {
+            TouchPhase::Started => {
+                self.touches.insert(touch.id, position);
+                self.tap_candidate = match self.touches.len() {
+                    1 => Some((touch.id, Instant::now(), position)),
+                    _ => None,
+                };
+
+                // A three finger tap toggles between orbiting and following the device
+                if self.touches.len() == 3 {
+                    let camera_mode = match self.camera_mode {
+                        CameraMode::Orbit => CameraMode::DeviceOrientation,
+                        CameraMode::DeviceOrientation
+                        | CameraMode::Follow
+                        | CameraMode::FirstPerson
+                        | CameraMode::Scripted => CameraMode::Orbit,
+                    };
+                    self.set_camera_mode(camera_mode);
+                }
+            }
+            TouchPhase::Moved => {
+                match self.touches.len() {
+                    1 => {
+                        if let Some(previous) = self.touches.get(&touch.id) {
+                            self.orbit_delta += position - previous;
+                        }
+                    }
+                    2 => {
+                        // Pinching scales the distance between the camera and its target
+                        let other = self
+                            .touches
+                            .iter()
+                            .find(|(id, _)| **id != touch.id)
+                            .map(|(_, position)| *position);
+                        if let (Some(previous), Some(other)) = (self.touches.get(&touch.id), other)
+                        {
+                            let previous_distance = glm::distance(previous, &other);
+                            let distance = glm::distance(&position, &other);
+                            if distance > f32::EPSILON {
+                                self.zoom_factor *= previous_distance / distance;
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+                self.touches.insert(touch.id, position);
+            }
+            TouchPhase::Ended => {
+                self.touches.remove(&touch.id);
+                self.handle_tap_end(touch.id, position);
+            }
+            TouchPhase::Cancelled => {
+                self.touches.remove(&touch.id);
+                self.tap_candidate = None;
+            }
+        }
+    }
+
+    fn handle_tap_end(&mut self, id: u64, position: glm::Vec2) {
+        let (candidate_id, started, start_position) = match self.tap_candidate.take() {
+            Some(candidate) => candidate,
+            None => return,
+        };
+        let now = Instant::now();
+        if candidate_id != id
+            || (now - started).as_secs_f32() > TAP_MAX_SECONDS
+            || glm::distance(&start_position, &position) > TAP_MAX_DISTANCE
+        {
+            return;
+        }
+
+        // Double tapping recenters the device orientation camera
+        match self.last_tap.take() {
+            Some((last_time, last_position))
+                if (now - last_time).as_secs_f32() <= DOUBLE_TAP_MAX_SECONDS
+                    && glm::distance(&last_position, &position) <= TAP_MAX_DISTANCE =>
+            {
+                self.device_orientation_camera.recenter();
+            }
+            _ => self.last_tap = Some((now, position)),
+        }
+    }
+
+    // Gathers the frame's drags, sticks and zooming into the orbit delta and zoom factor,
+    // returning the seconds since the last update
+    fn accumulate_camera_input(&mut self) -> f32 {
+        let now = Instant::now();
+        let delta_time = (now - self.last_update).as_secs_f32();
+        self.last_update = now;
+
+        // Touch drags are scaled like mouse drags, so both share the orbit delta
+        if self.action_down(Action::Orbit) {
+            self.orbit_delta +=
+                self.cursor_delta * (MOUSE_ORBIT_SENSITIVITY / TOUCH_ORBIT_SENSITIVITY);
+        }
+        self.orbit_delta += self.look_delta * (MOUSE_LOOK_SENSITIVITY / TOUCH_ORBIT_SENSITIVITY);
+        self.orbit_delta += glm::vec2(self.right_stick.x, -self.right_stick.y)
+            * (STICK_ORBIT_SPEED * delta_time / TOUCH_ORBIT_SENSITIVITY);
+        self.zoom_factor *= SCROLL_ZOOM_FACTOR.powf(self.scroll)
+            * (-self.action_axis(Action::ZoomOut, Action::ZoomIn) * ZOOM_SPEED * delta_time).exp();
+        delta_time
+    }
+
+    // Drags pan the view in pixels and zooming scales it around the cursor, while the movement
+    // actions and the left stick scroll it
+    pub fn update_camera_2d(&mut self, camera_2d: &mut Camera2d) {
+        let delta_time = self.accumulate_camera_input();
+        let scroll = glm::vec2(
+            self.action_axis(Action::MoveLeft, Action::MoveRight) + self.left_stick.x,
+            self.action_axis(Action::MoveForward, Action::MoveBackward) - self.left_stick.y,
+        );
+        camera_2d.pan(self.orbit_delta - scroll * PAN_SPEED * delta_time);
+        camera_2d.zoom_at(self.cursor_position, 1.0 / self.zoom_factor);
+
+        self.orbit_delta = glm::Vec2::zeros();
+        self.zoom_factor = 1.0;
+    }
+
+    // A fixed step replaces the time that passed, so recorded paths play out the same every time
+    pub fn update_camera(&mut self, camera: &mut Camera, scene: &Scene, step: Option<f32>) {
+        let delta_time = self.accumulate_camera_input();
+        let delta_time = step.unwrap_or(delta_time);
+
+        if let Some(transition) = self.transition.as_mut() {
+            if transition.update(camera, delta_time) {
+                self.transition = None;
+            }
+            self.orbit_delta = glm::Vec2::zeros();
+            self.zoom_factor = 1.0;
+            return;
+        }
+
+        match self.camera_mode {
+            CameraMode::Orbit => {
+                camera.up = glm::Vec3::y();
+                camera.orbit(
+                    -self.orbit_delta.x * TOUCH_ORBIT_SENSITIVITY,
+                    self.orbit_delta.y * TOUCH_ORBIT_SENSITIVITY,
+                );
+                camera.zoom(self.zoom_factor);
+                let distance = glm::distance(&camera.position, &camera.target);
+                self.move_camera(camera, MOVE_SPEED * distance * delta_time);
+            }
+            CameraMode::FirstPerson => {
+                camera.up = glm::Vec3::y();
+                camera.look(
+                    -self.orbit_delta.x * TOUCH_ORBIT_SENSITIVITY,
+                    -self.orbit_delta.y * TOUCH_ORBIT_SENSITIVITY,
+                );
+                self.move_camera(camera, FIRST_PERSON_SPEED * delta_time);
+            }
+            CameraMode::DeviceOrientation => {
+                if let Some(orientation) = self
+                    .motion_sensor
+                    .as_ref()
+                    .and_then(|sensor| sensor.orientation())
+                {
+                    self.device_orientation_camera
+                        .update(camera, orientation, delta_time);
+                }
+            }
+            CameraMode::Follow => {
+                self.follow_camera.orbit(
+                    -self.orbit_delta.x * TOUCH_ORBIT_SENSITIVITY,
+                    self.orbit_delta.y * TOUCH_ORBIT_SENSITIVITY,
+                );
+                self.follow_camera.zoom(self.zoom_factor);
+                self.follow_camera.update(camera, scene, delta_time);
+            }
+            CameraMode::Scripted => self.scripted_camera.update(camera, delta_time),
+        }
+
+        self.orbit_delta = glm::Vec2::zeros();
+        self.zoom_factor = 1.0;
+    }
+
+    // Moves the camera and its target together along the ground, and up and down
+    fn move_camera(&self, camera: &mut Camera, distance: f32) {
+        let forward = camera.target - camera.position;
+        let forward = glm::vec3(forward.x, 0.0, forward.z);
+        if forward.magnitude() <= f32::EPSILON {
+            return;
+        }
+        let forward = glm::normalize(&forward);
+        let right = forward.cross(&glm::Vec3::y());
+        let forward_amount = (self.action_axis(Action::MoveBackward, Action::MoveForward)
+            + self.left_stick.y)
+            .clamp(-1.0, 1.0);
+        let right_amount = (self.action_axis(Action::MoveLeft, Action::MoveRight)
+            + self.left_stick.x)
+            .clamp(-1.0, 1.0);
+        let movement = forward * forward_amount
+            + right * right_amount
+            + glm::Vec3::y() * self.action_axis(Action::MoveDown, Action::MoveUp);
+        camera.translate(movement * distance);
+    }
+}
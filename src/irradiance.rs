@@ -0,0 +1,636 @@
+use std::num::NonZeroU32;
+
+use nalgebra_glm as glm;
+
+use crate::{
+    camera::CameraUniform,
+    memory::{self, MemoryCategory, Tracked},
+    probes::{face_camera, ProbeFace, FACES},
+    scene::Scene,
+    texture::{DepthConfig, MultisampledTarget, Texture},
+};
+
+// Probes past this many are left out of the grid
+pub const MAX_IRRADIANCE_PROBES: usize = 512;
+
+// Irradiance is smooth, so the faces it is projected from can be small
+const CAPTURE_SIZE: u32 = 32;
+// Second order spherical harmonics
+const COEFFICIENTS: usize = 9;
+const SPHERE_SEGMENTS: u32 = 16;
+const SPHERE_RINGS: u32 = 8;
+
+// A box filled with an evenly spaced grid of probes, baked from the scene around them. Models
+// inside it are lit indirectly by the probes nearest to them
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct IrradianceVolumeDesc {
+    pub min: glm::Vec3,
+    pub max: glm::Vec3,
+    // Probes along each axis
+    pub counts: [u32; 3],
+}
+
+impl Default for IrradianceVolumeDesc {
+    fn default() -> Self {
+        Self {
+            min: glm::vec3(-10.0, 0.0, -10.0),
+            max: glm::vec3(10.0, 5.0, 10.0),
+            counts: [4, 2, 4],
+        }
+    }
+}
+
+impl IrradianceVolumeDesc {
+    fn counts(&self) -> [u32; 3] {
+        let mut counts = self.counts.map(|count| count.max(1));
+        // Thins out the grid along its longest axis until it fits
+        while counts.iter().product::<u32>() as usize > MAX_IRRADIANCE_PROBES {
+            let axis = (0..3).max_by_key(|axis| counts[*axis]).unwrap_or(0);
+            counts[axis] -= 1;
+        }
+        counts
+    }
+
+    fn probe_count(&self) -> usize {
+        self.counts().iter().product::<u32>() as usize
+    }
+
+    fn probe_position(&self, index: usize) -> glm::Vec3 {
+        let counts = self.counts();
+        let cell = [
+            index as u32 % counts[0],
+            index as u32 / counts[0] % counts[1],
+            index as u32 / (counts[0] * counts[1]),
+        ];
+        let fraction = glm::Vec3::from_fn(|axis, _| {
+            if counts[axis] > 1 {
+                cell[axis] as f32 / (counts[axis] - 1) as f32
+            } else {
+                0.5
+            }
+        });
+        self.min + (self.max - self.min).component_mul(&fraction)
+    }
+
+    // Sized to fill a fraction of the space between neighboring probes
+    fn sphere_radius(&self) -> f32 {
+        let counts = self.counts();
+        let extents = self.max - self.min;
+        let spacing = (0..3)
+            .filter(|axis| counts[*axis] > 1)
+            .map(|axis| extents[axis] / (counts[axis] - 1) as f32)
+            .fold(f32::MAX, f32::min);
+        if spacing == f32::MAX {
+            0.25
+        } else {
+            spacing * 0.15
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Default, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct IrradianceVolumeUniform {
+    // w: one once every probe has been baked
+    min: [f32; 4],
+    // w: radius of the debug spheres
+    max: [f32; 4],
+    counts: [u32; 4],
+}
+
+#[repr(C)]
+#[derive(Default, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct ProjectionUniform {
+    // x: the probe the captured faces are projected into
+    probe: [u32; 4],
+}
+
+// Bakes the volume's probes one per frame by rendering cube faces around each and projecting
+// them onto spherical harmonics
+pub struct IrradianceSystem {
+    uniform_buffer: Tracked<wgpu::Buffer>,
+    // Nine coefficients per probe, in grid order
+    coefficient_buffer: Tracked<wgpu::Buffer>,
+    volume: Option<IrradianceVolumeDesc>,
+    baked: bool,
+    // The probe whose faces are rendered this frame and the next to bake
+    pending: Option<usize>,
+    next_probe: Option<usize>,
+    // Where the pending probe is relative to the scene's origin
+    capture_position: glm::Vec3,
+    z_far: f32,
+    face_buffers: Vec<Tracked<wgpu::Buffer>>,
+    face_bind_groups: Vec<wgpu::BindGroup>,
+    capture_views: Vec<wgpu::TextureView>,
+    _capture_depth: Texture,
+    capture_depth_view: wgpu::TextureView,
+    multisampled: Option<MultisampledTarget>,
+    depth: DepthConfig,
+    projection_buffer: Tracked<wgpu::Buffer>,
+    projection: Option<(wgpu::BindGroup, wgpu::ComputePipeline)>,
+    debug_bind_group: wgpu::BindGroup,
+    debug_pipeline: wgpu::RenderPipeline,
+    sphere_vertex_buffer: Tracked<wgpu::Buffer>,
+    sphere_index_buffer: Tracked<wgpu::Buffer>,
+    sphere_index_count: u32,
+}
+
+impl IrradianceSystem {
+    pub fn new(
+        device: &wgpu::Device,
+        compute_supported: bool,
+        camera_bind_group_layout: &wgpu::BindGroupLayout,
+        color_format: wgpu::TextureFormat,
+        depth: DepthConfig,
+    ) -> Self {
+        let uniform_buffer = memory::create_buffer(
+            device,
+            MemoryCategory::Transient,
+            &wgpu::BufferDescriptor {
+                label: Some("Irradiance Volume Uniform Buffer"),
+                size: std::mem::size_of::<IrradianceVolumeUniform>() as wgpu::BufferAddress,
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            },
+        );
+
+        let coefficient_buffer = memory::create_buffer(
+            device,
+            MemoryCategory::Targets,
+            &wgpu::BufferDescriptor {
+                label: Some("Irradiance Coefficient Buffer"),
+                size: (MAX_IRRADIANCE_PROBES * COEFFICIENTS * std::mem::size_of::<[f32; 4]>())
+                    as wgpu::BufferAddress,
+                usage: wgpu::BufferUsages::STORAGE,
+                mapped_at_creation: false,
+            },
+        );
+
+        let (face_buffers, face_bind_groups) = (0..FACES)
+            .map(|_| {
+                let buffer = memory::create_buffer(
+                    device,
+                    MemoryCategory::Transient,
+                    &wgpu::BufferDescriptor {
+                        label: Some("Irradiance Probe Camera Buffer"),
+                        size: std::mem::size_of::<CameraUniform>() as wgpu::BufferAddress,
+                        usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                        mapped_at_creation: false,
+                    },
+                );
+                let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: Some("Irradiance Probe Camera Bind Group"),
+                    layout: camera_bind_group_layout,
+                    entries: &[wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: buffer.as_entire_binding(),
+                    }],
+                });
+                (buffer, bind_group)
+            })
+            .unzip();
+
+        let capture_texture = memory::create_texture(
+            device,
+            MemoryCategory::Targets,
+            &wgpu::TextureDescriptor {
+                label: Some("Irradiance Probe Capture Texture"),
+                size: wgpu::Extent3d {
+                    width: CAPTURE_SIZE,
+                    height: CAPTURE_SIZE,
+                    depth_or_array_layers: FACES,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: Texture::HDR_FORMAT,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING
+                    | wgpu::TextureUsages::RENDER_ATTACHMENT,
+            },
+        );
+        let capture_views = (0..FACES)
+            .map(|face| {
+                capture_texture.create_view(&wgpu::TextureViewDescriptor {
+                    dimension: Some(wgpu::TextureViewDimension::D2),
+                    base_array_layer: face,
+                    array_layer_count: NonZeroU32::new(1),
+                    ..Default::default()
+                })
+            })
+            .collect();
+        let capture_array_view = capture_texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(wgpu::TextureViewDimension::D2Array),
+            ..Default::default()
+        });
+        let capture_depth = Texture::create_depth_texture(
+            device,
+            CAPTURE_SIZE,
+            CAPTURE_SIZE,
+            depth,
+            "Irradiance Probe Depth Texture",
+        );
+        let capture_depth_view = capture_depth.create_attachment_view();
+        let multisampled = MultisampledTarget::new(
+            device,
+            &[CAPTURE_SIZE, CAPTURE_SIZE],
+            Texture::HDR_FORMAT,
+            depth,
+            "Irradiance Probe",
+        );
+
+        let projection_buffer = memory::create_buffer(
+            device,
+            MemoryCategory::Transient,
+            &wgpu::BufferDescriptor {
+                label: Some("Irradiance Projection Uniform Buffer"),
+                size: std::mem::size_of::<ProjectionUniform>() as wgpu::BufferAddress,
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            },
+        );
+
+        // Only created where compute shaders are available, the volume is never baked without them
+        let projection = compute_supported.then(|| {
+            let projection_bind_group_layout = memory::create_bind_group_layout(
+                device,
+                &wgpu::BindGroupLayoutDescriptor {
+                    label: Some("Irradiance Projection Bind Group Layout"),
+                    entries: &[
+                        wgpu::BindGroupLayoutEntry {
+                            binding: 0,
+                            visibility: wgpu::ShaderStages::COMPUTE,
+                            ty: wgpu::BindingType::Texture {
+                                sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                                view_dimension: wgpu::TextureViewDimension::D2Array,
+                                multisampled: false,
+                            },
+                            count: None,
+                        },
+                        wgpu::BindGroupLayoutEntry {
+                            binding: 1,
+                            visibility: wgpu::ShaderStages::COMPUTE,
+                            ty: wgpu::BindingType::Buffer {
+                                ty: wgpu::BufferBindingType::Storage { read_only: false },
+                                has_dynamic_offset: false,
+                                min_binding_size: None,
+                            },
+                            count: None,
+                        },
+                        wgpu::BindGroupLayoutEntry {
+                            binding: 2,
+                            visibility: wgpu::ShaderStages::COMPUTE,
+                            ty: wgpu::BindingType::Buffer {
+                                ty: wgpu::BufferBindingType::Uniform,
+                                has_dynamic_offset: false,
+                                min_binding_size: None,
+                            },
+                            count: None,
+                        },
+                    ],
+                },
+            );
+
+            let projection_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("Irradiance Projection Bind Group"),
+                layout: &projection_bind_group_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(&capture_array_view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: coefficient_buffer.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: projection_buffer.as_entire_binding(),
+                    },
+                ],
+            });
+
+            let projection_module = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+                label: Some("Irradiance Projection Shader"),
+                source: wgpu::ShaderSource::Wgsl(
+                    include_str!("shaders/irradiance_projection.wgsl").into(),
+                ),
+            });
+
+            let projection_pipeline_layout = memory::create_pipeline_layout(
+                device,
+                &wgpu::PipelineLayoutDescriptor {
+                    label: Some("Irradiance Projection Pipeline Layout"),
+                    bind_group_layouts: &[&projection_bind_group_layout],
+                    push_constant_ranges: &[],
+                },
+            );
+
+            let projection_pipeline =
+                device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                    label: Some("Irradiance Projection Pipeline"),
+                    layout: Some(&projection_pipeline_layout),
+                    module: &projection_module,
+                    entry_point: "main",
+                });
+
+            (projection_bind_group, projection_pipeline)
+        });
+
+        let debug_bind_group_layout = memory::create_bind_group_layout(
+            device,
+            &wgpu::BindGroupLayoutDescriptor {
+                label: Some("Irradiance Debug Bind Group Layout"),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Storage { read_only: true },
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                ],
+            },
+        );
+
+        let debug_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Irradiance Debug Bind Group"),
+            layout: &debug_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: coefficient_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        let debug_module = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("Irradiance Debug Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("shaders/irradiance_debug.wgsl").into()),
+        });
+
+        let debug_pipeline_layout = memory::create_pipeline_layout(
+            device,
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("Irradiance Debug Pipeline Layout"),
+                bind_group_layouts: &[camera_bind_group_layout, &debug_bind_group_layout],
+                push_constant_ranges: &[],
+            },
+        );
+
+        let debug_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Irradiance Debug Pipeline"),
+            layout: Some(&debug_pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &debug_module,
+                entry_point: "vs_main",
+                buffers: &[wgpu::VertexBufferLayout {
+                    array_stride: std::mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
+                    step_mode: wgpu::VertexStepMode::Vertex,
+                    attributes: &wgpu::vertex_attr_array![0 => Float32x3],
+                }],
+            },
+            primitive: wgpu::PrimitiveState {
+                cull_mode: Some(wgpu::Face::Back),
+                ..Default::default()
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: depth.format,
+                depth_write_enabled: true,
+                depth_compare: depth.compare(wgpu::CompareFunction::LessEqual),
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: depth.multisample(),
+            fragment: Some(wgpu::FragmentState {
+                module: &debug_module,
+                entry_point: "fs_main",
+                targets: &[wgpu::ColorTargetState {
+                    format: color_format,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                }],
+            }),
+        });
+
+        let (sphere_vertices, sphere_indices) = sphere();
+        let sphere_vertex_buffer = memory::create_buffer_init(
+            device,
+            MemoryCategory::Meshes,
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("Irradiance Debug Sphere Vertex Buffer"),
+                contents: bytemuck::cast_slice(&sphere_vertices),
+                usage: wgpu::BufferUsages::VERTEX,
+            },
+        );
+        let sphere_index_buffer = memory::create_buffer_init(
+            device,
+            MemoryCategory::Meshes,
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("Irradiance Debug Sphere Index Buffer"),
+                contents: bytemuck::cast_slice(&sphere_indices),
+                usage: wgpu::BufferUsages::INDEX,
+            },
+        );
+
+        Self {
+            uniform_buffer,
+            coefficient_buffer,
+            volume: None,
+            baked: false,
+            pending: None,
+            next_probe: None,
+            capture_position: glm::Vec3::zeros(),
+            z_far: 1000.0,
+            face_buffers,
+            face_bind_groups,
+            capture_views,
+            _capture_depth: capture_depth,
+            capture_depth_view,
+            multisampled,
+            depth,
+            projection_buffer,
+            projection,
+            debug_bind_group,
+            debug_pipeline,
+            sphere_vertex_buffer,
+            sphere_index_buffer,
+            sphere_index_count: sphere_indices.len() as u32,
+        }
+    }
+
+    // Lit surfaces sample the probes through the light bind group
+    pub fn coefficient_buffer(&self) -> &wgpu::Buffer {
+        &self.coefficient_buffer
+    }
+
+    pub fn uniform_buffer(&self) -> &wgpu::Buffer {
+        &self.uniform_buffer
+    }
+
+    // The volume is otherwise only baked when it is added or changed. The previous bake stays
+    // in use until the new one replaces it
+    pub fn request_bake(&mut self) {
+        if self.volume.is_some() {
+            self.next_probe = Some(0);
+        }
+    }
+
+    pub fn update(&mut self, queue: &wgpu::Queue, scene: &Scene, z_far: f32) {
+        if scene.irradiance_volume != self.volume {
+            self.volume = scene.irradiance_volume;
+            self.baked = false;
+            self.next_probe = self.volume.map(|_| 0);
+        }
+        self.z_far = z_far;
+        self.pending = self.next_probe.filter(|_| self.projection.is_some());
+
+        let volume = match self.volume {
+            Some(volume) => volume,
+            None => {
+                let uniform = IrradianceVolumeUniform::default();
+                queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[uniform]));
+                return;
+            }
+        };
+
+        if let Some(probe) = self.pending {
+            self.capture_position = scene.local_point(&volume.probe_position(probe));
+            for (face, buffer) in self.face_buffers.iter().enumerate() {
+                let camera = face_camera(self.capture_position, face, self.z_far);
+                let uniform =
+                    CameraUniform::new(&camera, &[CAPTURE_SIZE, CAPTURE_SIZE], self.depth);
+                queue.write_buffer(buffer, 0, bytemuck::cast_slice(&[uniform]));
+            }
+            let projection = ProjectionUniform {
+                probe: [probe as u32, 0, 0, 0],
+            };
+            queue.write_buffer(
+                &self.projection_buffer,
+                0,
+                bytemuck::cast_slice(&[projection]),
+            );
+        }
+
+        let counts = volume.counts();
+        let (min, max) = (
+            scene.local_point(&volume.min),
+            scene.local_point(&volume.max),
+        );
+        let uniform = IrradianceVolumeUniform {
+            min: [min.x, min.y, min.z, if self.baked { 1.0 } else { 0.0 }],
+            max: [max.x, max.y, max.z, volume.sphere_radius()],
+            counts: [counts[0], counts[1], counts[2], 0],
+        };
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[uniform]));
+    }
+
+    // Empty unless a probe needs baking this frame
+    pub fn faces(&self) -> Vec<ProbeFace<'_>> {
+        if self.volume.is_none() || self.pending.is_none() {
+            return Vec::new();
+        }
+        (0..FACES as usize)
+            .map(|face| ProbeFace {
+                camera: face_camera(self.capture_position, face, self.z_far),
+                camera_bind_group: &self.face_bind_groups[face],
+                color: &self.capture_views[face],
+                depth: &self.capture_depth_view,
+                multisampled: self.multisampled.as_ref(),
+            })
+            .collect()
+    }
+
+    // Projects the rendered faces into the pending probe's coefficients
+    pub fn finish_capture(&mut self, encoder: &mut wgpu::CommandEncoder) {
+        let (volume, probe, (projection_bind_group, projection_pipeline)) =
+            match (self.volume, self.pending.take(), self.projection.as_ref()) {
+                (Some(volume), Some(probe), Some(projection)) => (volume, probe, projection),
+                _ => return,
+            };
+
+        {
+            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("Irradiance Projection Pass"),
+            });
+            compute_pass.set_pipeline(projection_pipeline);
+            compute_pass.set_bind_group(0, projection_bind_group, &[]);
+            compute_pass.dispatch(1, 1, 1);
+        }
+
+        if probe + 1 < volume.probe_count() {
+            self.next_probe = Some(probe + 1);
+        } else {
+            self.next_probe = None;
+            self.baked = true;
+        }
+    }
+
+    // Draws a sphere at each probe lit by nothing but the probe
+    pub fn render_debug<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        camera_bind_group: &'a wgpu::BindGroup,
+    ) {
+        let volume = match self.volume {
+            Some(volume) if self.baked => volume,
+            _ => return,
+        };
+        render_pass.set_pipeline(&self.debug_pipeline);
+        render_pass.set_bind_group(0, camera_bind_group, &[]);
+        render_pass.set_bind_group(1, &self.debug_bind_group, &[]);
+        render_pass.set_vertex_buffer(0, self.sphere_vertex_buffer.slice(..));
+        render_pass.set_index_buffer(
+            self.sphere_index_buffer.slice(..),
+            wgpu::IndexFormat::Uint16,
+        );
+        render_pass.draw_indexed(
+            0..self.sphere_index_count,
+            0,
+            0..volume.probe_count() as u32,
+        );
+    }
+}
+
+// A unit sphere whose positions double as its normals
+fn sphere() -> (Vec<[f32; 3]>, Vec<u16>) {
+    let mut vertices = Vec::new();
+    for ring in 0..=SPHERE_RINGS {
+        let polar = ring as f32 / SPHERE_RINGS as f32 * std::f32::consts::PI;
+        for segment in 0..=SPHERE_SEGMENTS {
+            let azimuth = segment as f32 / SPHERE_SEGMENTS as f32 * std::f32::consts::TAU;
+            vertices.push([
+                polar.sin() * azimuth.cos(),
+                polar.cos(),
+                polar.sin() * azimuth.sin(),
+            ]);
+        }
+    }
+
+    let stride = SPHERE_SEGMENTS + 1;
+    let mut indices = Vec::new();
+    for ring in 0..SPHERE_RINGS {
+        for segment in 0..SPHERE_SEGMENTS {
+            let top = (ring * stride + segment) as u16;
+            let bottom = ((ring + 1) * stride + segment) as u16;
+            indices.extend_from_slice(&[top, top + 1, bottom, bottom, top + 1, bottom + 1]);
+        }
+    }
+    (vertices, indices)
+}
@@ -0,0 +1,927 @@
+use nalgebra_glm as glm;
+use std::collections::HashMap;
+
+use crate::{
+    bounds::{Aabb, Frustum},
+    memory::{self, MemoryCategory, Tracked},
+    scene::Scene,
+    texture::DepthConfig,
+};
+
+// Cells along each side of a region, the unit the surface is re-extracted in
+const REGION_SIZE: usize = 16;
+
+// Corner i of a cell is offset by bit 0 along x, bit 1 along y and bit 2 along z
+const EDGES: [(usize, usize); 12] = [
+    (0, 1),
+    (2, 3),
+    (4, 5),
+    (6, 7),
+    (0, 2),
+    (1, 3),
+    (4, 6),
+    (5, 7),
+    (0, 4),
+    (1, 5),
+    (2, 6),
+    (3, 7),
+];
+
+// Triangles through the edges of a cell for each combination of corners inside the surface,
+// ended by -1. Faces with two diagonal corners inside keep those corners apart,
+// so neighbouring cells always agree and the surface has no holes
+#[rustfmt::skip]
+const TRIANGLES: [[i8; 15]; 256] = [
+    [-1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [4, 8, 0, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [0, 9, 5, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [4, 8, 9, 4, 9, 5, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [1, 10, 4, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [1, 10, 8, 1, 8, 0, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [0, 9, 5, 1, 10, 4, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [8, 9, 5, 8, 5, 1, 8, 1, 10, -1, -1, -1, -1, -1, -1],
+    [5, 11, 1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [4, 8, 0, 5, 11, 1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [0, 9, 11, 0, 11, 1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [9, 11, 1, 9, 1, 4, 9, 4, 8, -1, -1, -1, -1, -1, -1],
+    [5, 11, 10, 5, 10, 4, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [10, 8, 0, 10, 0, 5, 10, 5, 11, -1, -1, -1, -1, -1, -1],
+    [11, 10, 4, 11, 4, 0, 11, 0, 9, -1, -1, -1, -1, -1, -1],
+    [9, 11, 10, 9, 10, 8, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [2, 8, 6, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [4, 6, 2, 4, 2, 0, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [0, 9, 5, 2, 8, 6, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [4, 6, 2, 4, 2, 9, 4, 9, 5, -1, -1, -1, -1, -1, -1],
+    [1, 10, 4, 2, 8, 6, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [0, 1, 10, 0, 10, 6, 0, 6, 2, -1, -1, -1, -1, -1, -1],
+    [0, 9, 5, 1, 10, 4, 2, 8, 6, -1, -1, -1, -1, -1, -1],
+    [1, 10, 6, 1, 6, 2, 1, 2, 9, 1, 9, 5, -1, -1, -1],
+    [5, 11, 1, 2, 8, 6, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [4, 6, 2, 4, 2, 0, 5, 11, 1, -1, -1, -1, -1, -1, -1],
+    [0, 9, 11, 0, 11, 1, 2, 8, 6, -1, -1, -1, -1, -1, -1],
+    [4, 6, 2, 4, 2, 9, 4, 9, 11, 4, 11, 1, -1, -1, -1],
+    [2, 8, 6, 5, 11, 10, 5, 10, 4, -1, -1, -1, -1, -1, -1],
+    [11, 10, 6, 11, 6, 2, 11, 2, 0, 11, 0, 5, -1, -1, -1],
+    [11, 10, 4, 11, 4, 0, 11, 0, 9, 2, 8, 6, -1, -1, -1],
+    [11, 10, 6, 11, 6, 2, 11, 2, 9, -1, -1, -1, -1, -1, -1],
+    [7, 9, 2, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [4, 8, 0, 7, 9, 2, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [0, 2, 7, 0, 7, 5, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [5, 4, 8, 5, 8, 2, 5, 2, 7, -1, -1, -1, -1, -1, -1],
+    [1, 10, 4, 7, 9, 2, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [1, 10, 8, 1, 8, 0, 7, 9, 2, -1, -1, -1, -1, -1, -1],
+    [0, 2, 7, 0, 7, 5, 1, 10, 4, -1, -1, -1, -1, -1, -1],
+    [10, 8, 2, 10, 2, 7, 10, 7, 5, 10, 5, 1, -1, -1, -1],
+    [5, 11, 1, 7, 9, 2, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [4, 8, 0, 5, 11, 1, 7, 9, 2, -1, -1, -1, -1, -1, -1],
+    [0, 2, 7, 0, 7, 11, 0, 11, 1, -1, -1, -1, -1, -1, -1],
+    [4, 8, 2, 4, 2, 7, 4, 7, 11, 4, 11, 1, -1, -1, -1],
+    [7, 9, 2, 5, 11, 10, 5, 10, 4, -1, -1, -1, -1, -1, -1],
+    [10, 8, 0, 10, 0, 5, 10, 5, 11, 7, 9, 2, -1, -1, -1],
+    [0, 2, 7, 0, 7, 11, 0, 11, 10, 0, 10, 4, -1, -1, -1],
+    [10, 8, 2, 10, 2, 7, 10, 7, 11, -1, -1, -1, -1, -1, -1],
+    [7, 9, 8, 7, 8, 6, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [6, 7, 9, 6, 9, 0, 6, 0, 4, -1, -1, -1, -1, -1, -1],
+    [7, 5, 0, 7, 0, 8, 7, 8, 6, -1, -1, -1, -1, -1, -1],
+    [4, 6, 7, 4, 7, 5, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [1, 10, 4, 7, 9, 8, 7, 8, 6, -1, -1, -1, -1, -1, -1],
+    [1, 10, 6, 1, 6, 7, 1, 7, 9, 1, 9, 0, -1, -1, -1],
+    [7, 5, 0, 7, 0, 8, 7, 8, 6, 1, 10, 4, -1, -1, -1],
+    [7, 5, 1, 7, 1, 10, 7, 10, 6, -1, -1, -1, -1, -1, -1],
+    [5, 11, 1, 7, 9, 8, 7, 8, 6, -1, -1, -1, -1, -1, -1],
+    [6, 7, 9, 6, 9, 0, 6, 0, 4, 5, 11, 1, -1, -1, -1],
+    [0, 8, 6, 0, 6, 7, 0, 7, 11, 0, 11, 1, -1, -1, -1],
+    [6, 7, 11, 6, 11, 1, 6, 1, 4, -1, -1, -1, -1, -1, -1],
+    [5, 11, 10, 5, 10, 4, 7, 9, 8, 7, 8, 6, -1, -1, -1],
+    [0, 5, 11, 0, 11, 10, 0, 10, 6, 0, 6, 7, 0, 7, 9],
+    [0, 8, 6, 0, 6, 7, 0, 7, 11, 0, 11, 10, 0, 10, 4],
+    [7, 11, 10, 7, 10, 6, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [6, 10, 3, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [4, 8, 0, 6, 10, 3, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [0, 9, 5, 6, 10, 3, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [6, 10, 3, 4, 8, 9, 4, 9, 5, -1, -1, -1, -1, -1, -1],
+    [1, 3, 6, 1, 6, 4, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [1, 3, 6, 1, 6, 8, 1, 8, 0, -1, -1, -1, -1, -1, -1],
+    [0, 9, 5, 1, 3, 6, 1, 6, 4, -1, -1, -1, -1, -1, -1],
+    [1, 3, 6, 1, 6, 8, 1, 8, 9, 1, 9, 5, -1, -1, -1],
+    [5, 11, 1, 6, 10, 3, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [4, 8, 0, 5, 11, 1, 6, 10, 3, -1, -1, -1, -1, -1, -1],
+    [0, 9, 11, 0, 11, 1, 6, 10, 3, -1, -1, -1, -1, -1, -1],
+    [9, 11, 1, 9, 1, 4, 9, 4, 8, 6, 10, 3, -1, -1, -1],
+    [4, 5, 11, 4, 11, 3, 4, 3, 6, -1, -1, -1, -1, -1, -1],
+    [5, 11, 3, 5, 3, 6, 5, 6, 8, 5, 8, 0, -1, -1, -1],
+    [9, 11, 3, 9, 3, 6, 9, 6, 4, 9, 4, 0, -1, -1, -1],
+    [9, 11, 3, 9, 3, 6, 9, 6, 8, -1, -1, -1, -1, -1, -1],
+    [2, 8, 10, 2, 10, 3, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [2, 0, 4, 2, 4, 10, 2, 10, 3, -1, -1, -1, -1, -1, -1],
+    [0, 9, 5, 2, 8, 10, 2, 10, 3, -1, -1, -1, -1, -1, -1],
+    [2, 9, 5, 2, 5, 4, 2, 4, 10, 2, 10, 3, -1, -1, -1],
+    [3, 2, 8, 3, 8, 4, 3, 4, 1, -1, -1, -1, -1, -1, -1],
+    [1, 3, 2, 1, 2, 0, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [0, 9, 5, 3, 2, 8, 3, 8, 4, 3, 4, 1, -1, -1, -1],
+    [3, 2, 9, 3, 9, 5, 3, 5, 1, -1, -1, -1, -1, -1, -1],
+    [5, 11, 1, 2, 8, 10, 2, 10, 3, -1, -1, -1, -1, -1, -1],
+    [2, 0, 4, 2, 4, 10, 2, 10, 3, 5, 11, 1, -1, -1, -1],
+    [0, 9, 11, 0, 11, 1, 2, 8, 10, 2, 10, 3, -1, -1, -1],
+    [4, 10, 3, 4, 3, 2, 4, 2, 9, 4, 9, 11, 4, 11, 1],
+    [2, 8, 4, 2, 4, 5, 2, 5, 11, 2, 11, 3, -1, -1, -1],
+    [2, 0, 5, 2, 5, 11, 2, 11, 3, -1, -1, -1, -1, -1, -1],
+    [4, 0, 9, 4, 9, 11, 4, 11, 3, 4, 3, 2, 4, 2, 8],
+    [2, 9, 11, 2, 11, 3, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [7, 9, 2, 6, 10, 3, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [4, 8, 0, 7, 9, 2, 6, 10, 3, -1, -1, -1, -1, -1, -1],
+    [0, 2, 7, 0, 7, 5, 6, 10, 3, -1, -1, -1, -1, -1, -1],
+    [5, 4, 8, 5, 8, 2, 5, 2, 7, 6, 10, 3, -1, -1, -1],
+    [1, 3, 6, 1, 6, 4, 7, 9, 2, -1, -1, -1, -1, -1, -1],
+    [1, 3, 6, 1, 6, 8, 1, 8, 0, 7, 9, 2, -1, -1, -1],
+    [0, 2, 7, 0, 7, 5, 1, 3, 6, 1, 6, 4, -1, -1, -1],
+    [8, 2, 7, 8, 7, 5, 8, 5, 1, 8, 1, 3, 8, 3, 6],
+    [5, 11, 1, 7, 9, 2, 6, 10, 3, -1, -1, -1, -1, -1, -1],
+    [4, 8, 0, 5, 11, 1, 7, 9, 2, 6, 10, 3, -1, -1, -1],
+    [0, 2, 7, 0, 7, 11, 0, 11, 1, 6, 10, 3, -1, -1, -1],
+    [4, 8, 2, 4, 2, 7, 4, 7, 11, 4, 11, 1, 6, 10, 3],
+    [7, 9, 2, 4, 5, 11, 4, 11, 3, 4, 3, 6, -1, -1, -1],
+    [5, 11, 3, 5, 3, 6, 5, 6, 8, 5, 8, 0, 7, 9, 2],
+    [11, 3, 6, 11, 6, 4, 11, 4, 0, 11, 0, 2, 11, 2, 7],
+    [11, 3, 6, 11, 6, 8, 11, 8, 2, 11, 2, 7, -1, -1, -1],
+    [8, 10, 3, 8, 3, 7, 8, 7, 9, -1, -1, -1, -1, -1, -1],
+    [4, 10, 3, 4, 3, 7, 4, 7, 9, 4, 9, 0, -1, -1, -1],
+    [8, 10, 3, 8, 3, 7, 8, 7, 5, 8, 5, 0, -1, -1, -1],
+    [5, 4, 10, 5, 10, 3, 5, 3, 7, -1, -1, -1, -1, -1, -1],
+    [1, 3, 7, 1, 7, 9, 1, 9, 8, 1, 8, 4, -1, -1, -1],
+    [1, 3, 7, 1, 7, 9, 1, 9, 0, -1, -1, -1, -1, -1, -1],
+    [8, 4, 1, 8, 1, 3, 8, 3, 7, 8, 7, 5, 8, 5, 0],
+    [1, 3, 7, 1, 7, 5, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [5, 11, 1, 8, 10, 3, 8, 3, 7, 8, 7, 9, -1, -1, -1],
+    [4, 10, 3, 4, 3, 7, 4, 7, 9, 4, 9, 0, 5, 11, 1],
+    [7, 11, 1, 7, 1, 0, 7, 0, 8, 7, 8, 10, 7, 10, 3],
+    [4, 10, 3, 4, 3, 7, 4, 7, 11, 4, 11, 1, -1, -1, -1],
+    [3, 7, 9, 3, 9, 8, 3, 8, 4, 3, 4, 5, 3, 5, 11],
+    [3, 7, 9, 3, 9, 0, 3, 0, 5, 3, 5, 11, -1, -1, -1],
+    [0, 8, 4, 7, 11, 3, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [7, 11, 3, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [3, 11, 7, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [4, 8, 0, 3, 11, 7, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [0, 9, 5, 3, 11, 7, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [3, 11, 7, 4, 8, 9, 4, 9, 5, -1, -1, -1, -1, -1, -1],
+    [1, 10, 4, 3, 11, 7, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [1, 10, 8, 1, 8, 0, 3, 11, 7, -1, -1, -1, -1, -1, -1],
+    [0, 9, 5, 1, 10, 4, 3, 11, 7, -1, -1, -1, -1, -1, -1],
+    [8, 9, 5, 8, 5, 1, 8, 1, 10, 3, 11, 7, -1, -1, -1],
+    [5, 7, 3, 5, 3, 1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [4, 8, 0, 5, 7, 3, 5, 3, 1, -1, -1, -1, -1, -1, -1],
+    [1, 0, 9, 1, 9, 7, 1, 7, 3, -1, -1, -1, -1, -1, -1],
+    [8, 9, 7, 8, 7, 3, 8, 3, 1, 8, 1, 4, -1, -1, -1],
+    [5, 7, 3, 5, 3, 10, 5, 10, 4, -1, -1, -1, -1, -1, -1],
+    [5, 7, 3, 5, 3, 10, 5, 10, 8, 5, 8, 0, -1, -1, -1],
+    [0, 9, 7, 0, 7, 3, 0, 3, 10, 0, 10, 4, -1, -1, -1],
+    [8, 9, 7, 8, 7, 3, 8, 3, 10, -1, -1, -1, -1, -1, -1],
+    [2, 8, 6, 3, 11, 7, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [4, 6, 2, 4, 2, 0, 3, 11, 7, -1, -1, -1, -1, -1, -1],
+    [0, 9, 5, 2, 8, 6, 3, 11, 7, -1, -1, -1, -1, -1, -1],
+    [4, 6, 2, 4, 2, 9, 4, 9, 5, 3, 11, 7, -1, -1, -1],
+    [1, 10, 4, 2, 8, 6, 3, 11, 7, -1, -1, -1, -1, -1, -1],
+    [0, 1, 10, 0, 10, 6, 0, 6, 2, 3, 11, 7, -1, -1, -1],
+    [0, 9, 5, 1, 10, 4, 2, 8, 6, 3, 11, 7, -1, -1, -1],
+    [1, 10, 6, 1, 6, 2, 1, 2, 9, 1, 9, 5, 3, 11, 7],
+    [5, 7, 3, 5, 3, 1, 2, 8, 6, -1, -1, -1, -1, -1, -1],
+    [4, 6, 2, 4, 2, 0, 5, 7, 3, 5, 3, 1, -1, -1, -1],
+    [1, 0, 9, 1, 9, 7, 1, 7, 3, 2, 8, 6, -1, -1, -1],
+    [9, 7, 3, 9, 3, 1, 9, 1, 4, 9, 4, 6, 9, 6, 2],
+    [2, 8, 6, 5, 7, 3, 5, 3, 10, 5, 10, 4, -1, -1, -1],
+    [10, 6, 2, 10, 2, 0, 10, 0, 5, 10, 5, 7, 10, 7, 3],
+    [0, 9, 7, 0, 7, 3, 0, 3, 10, 0, 10, 4, 2, 8, 6],
+    [9, 7, 3, 9, 3, 10, 9, 10, 6, 9, 6, 2, -1, -1, -1],
+    [3, 11, 9, 3, 9, 2, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [4, 8, 0, 3, 11, 9, 3, 9, 2, -1, -1, -1, -1, -1, -1],
+    [2, 3, 11, 2, 11, 5, 2, 5, 0, -1, -1, -1, -1, -1, -1],
+    [3, 11, 5, 3, 5, 4, 3, 4, 8, 3, 8, 2, -1, -1, -1],
+    [1, 10, 4, 3, 11, 9, 3, 9, 2, -1, -1, -1, -1, -1, -1],
+    [1, 10, 8, 1, 8, 0, 3, 11, 9, 3, 9, 2, -1, -1, -1],
+    [2, 3, 11, 2, 11, 5, 2, 5, 0, 1, 10, 4, -1, -1, -1],
+    [5, 1, 10, 5, 10, 8, 5, 8, 2, 5, 2, 3, 5, 3, 11],
+    [3, 1, 5, 3, 5, 9, 3, 9, 2, -1, -1, -1, -1, -1, -1],
+    [4, 8, 0, 3, 1, 5, 3, 5, 9, 3, 9, 2, -1, -1, -1],
+    [0, 2, 3, 0, 3, 1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [3, 1, 4, 3, 4, 8, 3, 8, 2, -1, -1, -1, -1, -1, -1],
+    [3, 10, 4, 3, 4, 5, 3, 5, 9, 3, 9, 2, -1, -1, -1],
+    [5, 9, 2, 5, 2, 3, 5, 3, 10, 5, 10, 8, 5, 8, 0],
+    [2, 3, 10, 2, 10, 4, 2, 4, 0, -1, -1, -1, -1, -1, -1],
+    [3, 10, 8, 3, 8, 2, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [9, 8, 6, 9, 6, 3, 9, 3, 11, -1, -1, -1, -1, -1, -1],
+    [4, 6, 3, 4, 3, 11, 4, 11, 9, 4, 9, 0, -1, -1, -1],
+    [0, 8, 6, 0, 6, 3, 0, 3, 11, 0, 11, 5, -1, -1, -1],
+    [4, 6, 3, 4, 3, 11, 4, 11, 5, -1, -1, -1, -1, -1, -1],
+    [1, 10, 4, 9, 8, 6, 9, 6, 3, 9, 3, 11, -1, -1, -1],
+    [6, 3, 11, 6, 11, 9, 6, 9, 0, 6, 0, 1, 6, 1, 10],
+    [0, 8, 6, 0, 6, 3, 0, 3, 11, 0, 11, 5, 1, 10, 4],
+    [6, 3, 11, 6, 11, 5, 6, 5, 1, 6, 1, 10, -1, -1, -1],
+    [9, 8, 6, 9, 6, 3, 9, 3, 1, 9, 1, 5, -1, -1, -1],
+    [9, 0, 4, 9, 4, 6, 9, 6, 3, 9, 3, 1, 9, 1, 5],
+    [1, 0, 8, 1, 8, 6, 1, 6, 3, -1, -1, -1, -1, -1, -1],
+    [4, 6, 3, 4, 3, 1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [3, 10, 4, 3, 4, 5, 3, 5, 9, 3, 9, 8, 3, 8, 6],
+    [5, 9, 0, 3, 10, 6, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [0, 8, 6, 0, 6, 3, 0, 3, 10, 0, 10, 4, -1, -1, -1],
+    [3, 10, 6, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [6, 10, 11, 6, 11, 7, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [4, 8, 0, 6, 10, 11, 6, 11, 7, -1, -1, -1, -1, -1, -1],
+    [0, 9, 5, 6, 10, 11, 6, 11, 7, -1, -1, -1, -1, -1, -1],
+    [4, 8, 9, 4, 9, 5, 6, 10, 11, 6, 11, 7, -1, -1, -1],
+    [6, 4, 1, 6, 1, 11, 6, 11, 7, -1, -1, -1, -1, -1, -1],
+    [1, 11, 7, 1, 7, 6, 1, 6, 8, 1, 8, 0, -1, -1, -1],
+    [0, 9, 5, 6, 4, 1, 6, 1, 11, 6, 11, 7, -1, -1, -1],
+    [1, 11, 7, 1, 7, 6, 1, 6, 8, 1, 8, 9, 1, 9, 5],
+    [7, 6, 10, 7, 10, 1, 7, 1, 5, -1, -1, -1, -1, -1, -1],
+    [4, 8, 0, 7, 6, 10, 7, 10, 1, 7, 1, 5, -1, -1, -1],
+    [0, 9, 7, 0, 7, 6, 0, 6, 10, 0, 10, 1, -1, -1, -1],
+    [1, 4, 8, 1, 8, 9, 1, 9, 7, 1, 7, 6, 1, 6, 10],
+    [5, 7, 6, 5, 6, 4, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [7, 6, 8, 7, 8, 0, 7, 0, 5, -1, -1, -1, -1, -1, -1],
+    [6, 4, 0, 6, 0, 9, 6, 9, 7, -1, -1, -1, -1, -1, -1],
+    [6, 8, 9, 6, 9, 7, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [10, 11, 7, 10, 7, 2, 10, 2, 8, -1, -1, -1, -1, -1, -1],
+    [10, 11, 7, 10, 7, 2, 10, 2, 0, 10, 0, 4, -1, -1, -1],
+    [0, 9, 5, 10, 11, 7, 10, 7, 2, 10, 2, 8, -1, -1, -1],
+    [2, 9, 5, 2, 5, 4, 2, 4, 10, 2, 10, 11, 2, 11, 7],
+    [1, 11, 7, 1, 7, 2, 1, 2, 8, 1, 8, 4, -1, -1, -1],
+    [0, 1, 11, 0, 11, 7, 0, 7, 2, -1, -1, -1, -1, -1, -1],
+    [0, 9, 5, 1, 11, 7, 1, 7, 2, 1, 2, 8, 1, 8, 4],
+    [1, 11, 7, 1, 7, 2, 1, 2, 9, 1, 9, 5, -1, -1, -1],
+    [5, 7, 2, 5, 2, 8, 5, 8, 10, 5, 10, 1, -1, -1, -1],
+    [10, 1, 5, 10, 5, 7, 10, 7, 2, 10, 2, 0, 10, 0, 4],
+    [7, 2, 8, 7, 8, 10, 7, 10, 1, 7, 1, 0, 7, 0, 9],
+    [4, 10, 1, 2, 9, 7, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [5, 7, 2, 5, 2, 8, 5, 8, 4, -1, -1, -1, -1, -1, -1],
+    [5, 7, 2, 5, 2, 0, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [7, 2, 8, 7, 8, 4, 7, 4, 0, 7, 0, 9, -1, -1, -1],
+    [2, 9, 7, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [11, 9, 2, 11, 2, 6, 11, 6, 10, -1, -1, -1, -1, -1, -1],
+    [4, 8, 0, 11, 9, 2, 11, 2, 6, 11, 6, 10, -1, -1, -1],
+    [0, 2, 6, 0, 6, 10, 0, 10, 11, 0, 11, 5, -1, -1, -1],
+    [2, 6, 10, 2, 10, 11, 2, 11, 5, 2, 5, 4, 2, 4, 8],
+    [11, 9, 2, 11, 2, 6, 11, 6, 4, 11, 4, 1, -1, -1, -1],
+    [6, 8, 0, 6, 0, 1, 6, 1, 11, 6, 11, 9, 6, 9, 2],
+    [11, 5, 0, 11, 0, 2, 11, 2, 6, 11, 6, 4, 11, 4, 1],
+    [1, 11, 5, 6, 8, 2, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [5, 9, 2, 5, 2, 6, 5, 6, 10, 5, 10, 1, -1, -1, -1],
+    [4, 8, 0, 5, 9, 2, 5, 2, 6, 5, 6, 10, 5, 10, 1],
+    [0, 2, 6, 0, 6, 10, 0, 10, 1, -1, -1, -1, -1, -1, -1],
+    [2, 6, 10, 2, 10, 1, 2, 1, 4, 2, 4, 8, -1, -1, -1],
+    [4, 5, 9, 4, 9, 2, 4, 2, 6, -1, -1, -1, -1, -1, -1],
+    [5, 9, 2, 5, 2, 6, 5, 6, 8, 5, 8, 0, -1, -1, -1],
+    [0, 2, 6, 0, 6, 4, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [6, 8, 2, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [8, 10, 11, 8, 11, 9, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [11, 9, 0, 11, 0, 4, 11, 4, 10, -1, -1, -1, -1, -1, -1],
+    [10, 11, 5, 10, 5, 0, 10, 0, 8, -1, -1, -1, -1, -1, -1],
+    [4, 10, 11, 4, 11, 5, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [9, 8, 4, 9, 4, 1, 9, 1, 11, -1, -1, -1, -1, -1, -1],
+    [1, 11, 9, 1, 9, 0, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [8, 4, 1, 8, 1, 11, 8, 11, 5, 8, 5, 0, -1, -1, -1],
+    [1, 11, 5, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [8, 10, 1, 8, 1, 5, 8, 5, 9, -1, -1, -1, -1, -1, -1],
+    [10, 1, 5, 10, 5, 9, 10, 9, 0, 10, 0, 4, -1, -1, -1],
+    [0, 8, 10, 0, 10, 1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [4, 10, 1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [5, 9, 8, 5, 8, 4, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [5, 9, 0, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [0, 8, 4, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+    [-1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1],
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScalarField {
+    // Samples along each axis, one unit apart
+    dimensions: [usize; 3],
+    values: Vec<f32>,
+}
+
+impl ScalarField {
+    #[allow(dead_code)]
+    pub fn new(dimensions: [usize; 3], value: f32) -> Self {
+        Self {
+            dimensions,
+            values: vec![value; dimensions.iter().product()],
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn from_fn(dimensions: [usize; 3], mut sample: impl FnMut([usize; 3]) -> f32) -> Self {
+        let mut values = Vec::with_capacity(dimensions.iter().product());
+        for z in 0..dimensions[2] {
+            for y in 0..dimensions[1] {
+                for x in 0..dimensions[0] {
+                    values.push(sample([x, y, z]));
+                }
+            }
+        }
+        Self { dimensions, values }
+    }
+
+    #[allow(dead_code)]
+    pub fn dimensions(&self) -> [usize; 3] {
+        self.dimensions
+    }
+
+    fn index(&self, position: [usize; 3]) -> usize {
+        (position[2] * self.dimensions[1] + position[1]) * self.dimensions[0] + position[0]
+    }
+
+    pub fn value(&self, position: [usize; 3]) -> f32 {
+        self.values[self.index(position)]
+    }
+
+    // Central differences, one sided at the borders of the field
+    fn gradient(&self, position: [usize; 3]) -> glm::Vec3 {
+        let mut gradient = glm::Vec3::zeros();
+        for axis in 0..3 {
+            let (mut low, mut high) = (position, position);
+            low[axis] = low[axis].saturating_sub(1);
+            high[axis] = (high[axis] + 1).min(self.dimensions[axis] - 1);
+            if high[axis] > low[axis] {
+                gradient[axis] =
+                    (self.value(high) - self.value(low)) / (high[axis] - low[axis]) as f32;
+            }
+        }
+        gradient
+    }
+}
+
+#[allow(dead_code)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FieldKind {
+    // Signed distances, negative inside the surface
+    Distance,
+    // Densities, solid above the iso level
+    Density,
+}
+
+#[derive(Debug, Clone)]
+pub struct IsosurfaceDesc {
+    pub iso_level: f32,
+    pub kind: FieldKind,
+    // Places the field's samples, one unit apart, in the world
+    pub transform: glm::Mat4,
+    // Linear base color
+    pub color: glm::Vec4,
+    field: ScalarField,
+    // One per region, changing whenever the region has to be extracted again
+    revisions: Vec<u64>,
+    next_revision: u64,
+}
+
+impl IsosurfaceDesc {
+    #[allow(dead_code)]
+    pub fn new(field: ScalarField) -> Self {
+        let revisions = vec![0; region_counts(field.dimensions).iter().product()];
+        Self {
+            iso_level: 0.0,
+            kind: FieldKind::Distance,
+            transform: glm::Mat4::identity(),
+            color: glm::vec4(0.8, 0.8, 0.8, 1.0),
+            field,
+            revisions,
+            next_revision: 1,
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn field(&self) -> &ScalarField {
+        &self.field
+    }
+
+    // Rewrites the samples from min up to but not including max, only re-extracting the regions
+    // whose triangles or normals can see the change
+    #[allow(dead_code)]
+    pub fn update_region(
+        &mut self,
+        min: [usize; 3],
+        max: [usize; 3],
+        mut update: impl FnMut([usize; 3], f32) -> f32,
+    ) {
+        let dimensions = self.field.dimensions;
+        let max = [0, 1, 2].map(|axis| max[axis].min(dimensions[axis]));
+        if (0..3).any(|axis| min[axis] >= max[axis]) {
+            return;
+        }
+        for z in min[2]..max[2] {
+            for y in min[1]..max[1] {
+                for x in min[0]..max[0] {
+                    let index = self.field.index([x, y, z]);
+                    self.field.values[index] = update([x, y, z], self.field.values[index]);
+                }
+            }
+        }
+
+        // Cells use the samples at their corners and their neighbours for normals
+        let counts = region_counts(dimensions);
+        let first = [0, 1, 2].map(|axis| min[axis].saturating_sub(2) / REGION_SIZE);
+        let last = [0, 1, 2].map(|axis| (max[axis] / REGION_SIZE).min(counts[axis] - 1));
+        let revision = self.next_revision;
+        self.next_revision += 1;
+        for z in first[2]..=last[2] {
+            for y in first[1]..=last[1] {
+                for x in first[0]..=last[0] {
+                    self.revisions[(z * counts[1] + y) * counts[0] + x] = revision;
+                }
+            }
+        }
+    }
+
+    // Values below zero are inside the surface
+    fn signed(&self, value: f32) -> f32 {
+        match self.kind {
+            FieldKind::Distance => value - self.iso_level,
+            FieldKind::Density => self.iso_level - value,
+        }
+    }
+}
+
+fn region_counts(dimensions: [usize; 3]) -> [usize; 3] {
+    dimensions.map(|samples| (samples.max(2) - 1).div_ceil(REGION_SIZE))
+}
+
+#[repr(C)]
+#[derive(Default, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct IsosurfaceVertex {
+    position: [f32; 3],
+    normal: [f32; 3],
+}
+
+impl IsosurfaceVertex {
+    const ATTRIBUTES: [wgpu::VertexAttribute; 2] = wgpu::vertex_attr_array![
+        0 => Float32x3,
+        1 => Float32x3
+    ];
+
+    fn layout<'a>() -> wgpu::VertexBufferLayout<'a> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<Self>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &Self::ATTRIBUTES,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Default, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct IsosurfaceUniform {
+    model: [[f32; 4]; 4],
+    normal_matrix: [[f32; 4]; 4],
+    color: [f32; 4],
+}
+
+impl IsosurfaceUniform {
+    // The transform is relative to the scene's origin
+    fn new(desc: &IsosurfaceDesc, transform: &glm::Mat4) -> Self {
+        Self {
+            model: (*transform).into(),
+            normal_matrix: glm::transpose(&glm::inverse(transform)).into(),
+            color: desc.color.into(),
+        }
+    }
+}
+
+// Marching cubes over the cells of one region, in the space of the field
+fn extract_region(desc: &IsosurfaceDesc, region: [usize; 3]) -> (Vec<IsosurfaceVertex>, Vec<u32>) {
+    let field = &desc.field;
+    let dimensions = field.dimensions;
+    let start = region.map(|index| index * REGION_SIZE);
+    let end = [0, 1, 2].map(|axis| (start[axis] + REGION_SIZE).min(dimensions[axis] - 1));
+
+    let mut vertices = Vec::new();
+    let mut indices = Vec::new();
+    // Vertices shared by the cells around an edge, keyed by its first sample and axis
+    let mut edge_vertices: HashMap<([usize; 3], usize), u32> = HashMap::new();
+    for z in start[2]..end[2] {
+        for y in start[1]..end[1] {
+            for x in start[0]..end[0] {
+                let corners = [0, 1, 2, 3, 4, 5, 6, 7]
+                    .map(|corner| [x + (corner & 1), y + ((corner >> 1) & 1), z + (corner >> 2)]);
+                let values = corners.map(|corner| desc.signed(field.value(corner)));
+                let case = (0..8).fold(0, |case, corner| {
+                    case | if values[corner] < 0.0 { 1 << corner } else { 0 }
+                });
+
+                for &edge in TRIANGLES[case].iter().take_while(|&&edge| edge >= 0) {
+                    let (a, b) = EDGES[edge as usize];
+                    let axis = (a ^ b).trailing_zeros() as usize;
+                    let index = *edge_vertices.entry((corners[a], axis)).or_insert_with(|| {
+                        let amount = values[a] / (values[a] - values[b]);
+                        let position = glm::lerp(
+                            &corners[a].map(|value| value as f32).into(),
+                            &corners[b].map(|value| value as f32).into(),
+                            amount,
+                        );
+                        // The gradient of the signed field points out of the surface
+                        let sign = match desc.kind {
+                            FieldKind::Distance => 1.0,
+                            FieldKind::Density => -1.0,
+                        };
+                        let gradient = glm::lerp(
+                            &field.gradient(corners[a]),
+                            &field.gradient(corners[b]),
+                            amount,
+                        ) * sign;
+                        let normal = if gradient.magnitude() > f32::EPSILON {
+                            gradient.normalize()
+                        } else {
+                            glm::Vec3::y()
+                        };
+                        vertices.push(IsosurfaceVertex {
+                            position: position.into(),
+                            normal: normal.into(),
+                        });
+                        vertices.len() as u32 - 1
+                    });
+                    indices.push(index);
+                }
+            }
+        }
+    }
+    (vertices, indices)
+}
+
+struct RegionMesh {
+    vertex_buffer: Tracked<wgpu::Buffer>,
+    index_buffer: Tracked<wgpu::Buffer>,
+    index_count: u32,
+    // In the space of the field
+    local_bounds: Aabb,
+    // Relative to the scene's origin
+    bounds: Aabb,
+}
+
+struct GpuRegion {
+    revision: u64,
+    // Regions the surface doesn't pass through have no mesh
+    mesh: Option<RegionMesh>,
+}
+
+struct GpuIsosurface {
+    uniform_buffer: Tracked<wgpu::Buffer>,
+    bind_group: wgpu::BindGroup,
+    dimensions: [usize; 3],
+    iso_level: f32,
+    kind: FieldKind,
+    regions: Vec<GpuRegion>,
+}
+
+pub struct IsosurfaceSystem {
+    bind_group_layout: wgpu::BindGroupLayout,
+    pipeline: wgpu::RenderPipeline,
+    depth_pipeline: wgpu::RenderPipeline,
+    shadow_pipeline: wgpu::RenderPipeline,
+    surfaces: Vec<GpuIsosurface>,
+}
+
+impl IsosurfaceSystem {
+    pub fn new(
+        device: &wgpu::Device,
+        camera_bind_group_layout: &wgpu::BindGroupLayout,
+        light_bind_group_layout: &wgpu::BindGroupLayout,
+        shadow_caster_bind_group_layout: &wgpu::BindGroupLayout,
+        color_format: wgpu::TextureFormat,
+        depth: DepthConfig,
+    ) -> Self {
+        let bind_group_layout = memory::create_bind_group_layout(
+            device,
+            &wgpu::BindGroupLayoutDescriptor {
+                label: Some("Isosurface Bind Group Layout"),
+                entries: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                }],
+            },
+        );
+
+        let module = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("Isosurface Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("shaders/isosurface.wgsl").into()),
+        });
+
+        let primitive = wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            front_face: wgpu::FrontFace::Ccw,
+            cull_mode: Some(wgpu::Face::Back),
+            ..Default::default()
+        };
+
+        let pipeline_layout = memory::create_pipeline_layout(
+            device,
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("Isosurface Pipeline Layout"),
+                bind_group_layouts: &[
+                    camera_bind_group_layout,
+                    &bind_group_layout,
+                    light_bind_group_layout,
+                ],
+                push_constant_ranges: &[],
+            },
+        );
+
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Isosurface Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &module,
+                entry_point: "vs_main",
+                buffers: &[IsosurfaceVertex::layout()],
+            },
+            primitive,
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: depth.format,
+                depth_write_enabled: true,
+                depth_compare: depth.compare(wgpu::CompareFunction::LessEqual),
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: depth.multisample(),
+            fragment: Some(wgpu::FragmentState {
+                module: &module,
+                entry_point: "fs_main",
+                targets: &[wgpu::ColorTargetState {
+                    format: color_format,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                }],
+            }),
+        });
+
+        let depth_pipeline_layout = memory::create_pipeline_layout(
+            device,
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("Isosurface Depth Pipeline Layout"),
+                bind_group_layouts: &[camera_bind_group_layout, &bind_group_layout],
+                push_constant_ranges: &[],
+            },
+        );
+
+        let depth_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Isosurface Depth Pipeline"),
+            layout: Some(&depth_pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &module,
+                entry_point: "vs_main",
+                buffers: &[IsosurfaceVertex::layout()],
+            },
+            primitive,
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: depth.format,
+                depth_write_enabled: true,
+                depth_compare: depth.compare(wgpu::CompareFunction::Less),
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState::default(),
+            fragment: None,
+        });
+
+        let shadow_module = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("Isosurface Shadow Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("shaders/isosurface_shadow.wgsl").into()),
+        });
+
+        let shadow_pipeline_layout = memory::create_pipeline_layout(
+            device,
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("Isosurface Shadow Pipeline Layout"),
+                bind_group_layouts: &[shadow_caster_bind_group_layout, &bind_group_layout],
+                push_constant_ranges: &[],
+            },
+        );
+
+        let shadow_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Isosurface Shadow Pipeline"),
+            layout: Some(&shadow_pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shadow_module,
+                entry_point: "vs_main",
+                buffers: &[IsosurfaceVertex::layout()],
+            },
+            primitive: wgpu::PrimitiveState {
+                cull_mode: None,
+                ..primitive
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: depth.shadow().format,
+                depth_write_enabled: true,
+                depth_compare: depth.compare(wgpu::CompareFunction::Less),
+                stencil: wgpu::StencilState::default(),
+                bias: depth.bias(wgpu::DepthBiasState {
+                    constant: 2,
+                    slope_scale: 2.0,
+                    clamp: 0.0,
+                }),
+            }),
+            multisample: wgpu::MultisampleState::default(),
+            fragment: None,
+        });
+
+        Self {
+            bind_group_layout,
+            pipeline,
+            depth_pipeline,
+            shadow_pipeline,
+            surfaces: Vec::new(),
+        }
+    }
+
+    fn create_surface(&self, device: &wgpu::Device, desc: &IsosurfaceDesc) -> GpuIsosurface {
+        let uniform_buffer = memory::create_buffer(
+            device,
+            MemoryCategory::Transient,
+            &wgpu::BufferDescriptor {
+                label: Some("Isosurface Uniform Buffer"),
+                size: std::mem::size_of::<IsosurfaceUniform>() as wgpu::BufferAddress,
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            },
+        );
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Isosurface Bind Group"),
+            layout: &self.bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: uniform_buffer.as_entire_binding(),
+            }],
+        });
+
+        GpuIsosurface {
+            uniform_buffer,
+            bind_group,
+            dimensions: desc.field.dimensions,
+            iso_level: desc.iso_level,
+            kind: desc.kind,
+            regions: Vec::new(),
+        }
+    }
+
+    pub fn update(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, scene: &Scene) {
+        while self.surfaces.len() < scene.isosurfaces.len() {
+            let surface = self.create_surface(device, &scene.isosurfaces[self.surfaces.len()]);
+            self.surfaces.push(surface);
+        }
+
+        for (desc, surface) in scene.isosurfaces.iter().zip(self.surfaces.iter_mut()) {
+            let transform = scene.local_transform(&desc.transform.cast());
+            queue.write_buffer(
+                &surface.uniform_buffer,
+                0,
+                bytemuck::cast_slice(&[IsosurfaceUniform::new(desc, &transform)]),
+            );
+
+            // The whole surface moves when the level or the field itself is replaced
+            if surface.dimensions != desc.field.dimensions
+                || surface.iso_level != desc.iso_level
+                || surface.kind != desc.kind
+            {
+                surface.dimensions = desc.field.dimensions;
+                surface.iso_level = desc.iso_level;
+                surface.kind = desc.kind;
+                surface.regions.clear();
+            }
+
+            let counts = region_counts(desc.field.dimensions);
+            let has_cells = desc.field.dimensions.iter().all(|&samples| samples >= 2);
+            for (index, &revision) in desc.revisions.iter().enumerate() {
+                if surface.regions.len() <= index {
+                    surface.regions.push(GpuRegion {
+                        revision: u64::MAX,
+                        mesh: None,
+                    });
+                }
+                let region = &mut surface.regions[index];
+                if region.revision == revision {
+                    continue;
+                }
+                region.revision = revision;
+                region.mesh = None;
+                if !has_cells {
+                    continue;
+                }
+
+                let coordinate = [
+                    index % counts[0],
+                    (index / counts[0]) % counts[1],
+                    index / (counts[0] * counts[1]),
+                ];
+                let (vertices, indices) = extract_region(desc, coordinate);
+                if indices.is_empty() {
+                    continue;
+                }
+                let mut local_bounds = Aabb::default();
+                for vertex in vertices.iter() {
+                    local_bounds.expand_to_include(&glm::Vec3::from(vertex.position));
+                }
+                region.mesh = Some(RegionMesh {
+                    vertex_buffer: memory::create_buffer_init(
+                        device,
+                        MemoryCategory::Meshes,
+                        &wgpu::util::BufferInitDescriptor {
+                            label: Some("Isosurface Vertex Buffer"),
+                            contents: bytemuck::cast_slice(&vertices),
+                            usage: wgpu::BufferUsages::VERTEX,
+                        },
+                    ),
+                    index_buffer: memory::create_buffer_init(
+                        device,
+                        MemoryCategory::Meshes,
+                        &wgpu::util::BufferInitDescriptor {
+                            label: Some("Isosurface Index Buffer"),
+                            contents: bytemuck::cast_slice(&indices),
+                            usage: wgpu::BufferUsages::INDEX,
+                        },
+                    ),
+                    index_count: indices.len() as u32,
+                    local_bounds,
+                    bounds: local_bounds,
+                });
+            }
+
+            for mesh in surface
+                .regions
+                .iter_mut()
+                .filter_map(|region| region.mesh.as_mut())
+            {
+                mesh.bounds = mesh.local_bounds.transformed(&transform);
+            }
+        }
+    }
+
+    // Bounds of every extracted region, used to fit the shadow map
+    pub fn bounds(&self) -> Aabb {
+        let mut bounds = Aabb::default();
+        for surface in self.surfaces.iter() {
+            for mesh in Self::meshes(surface) {
+                bounds.merge(&mesh.bounds);
+            }
+        }
+        bounds
+    }
+
+    fn meshes(surface: &GpuIsosurface) -> impl Iterator<Item = &RegionMesh> {
+        surface
+            .regions
+            .iter()
+            .filter_map(|region| region.mesh.as_ref())
+    }
+
+    fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>, frustum: &Frustum) {
+        for surface in self.surfaces.iter() {
+            render_pass.set_bind_group(1, &surface.bind_group, &[]);
+            for mesh in Self::meshes(surface) {
+                if !frustum.intersects_aabb(&mesh.bounds) {
+                    continue;
+                }
+                render_pass.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
+                render_pass
+                    .set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+                render_pass.draw_indexed(0..mesh.index_count, 0, 0..1);
+            }
+        }
+    }
+
+    pub fn render_shadows<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        shadow_caster_bind_group: &'a wgpu::BindGroup,
+        light_frustum: &Frustum,
+    ) {
+        render_pass.set_pipeline(&self.shadow_pipeline);
+        render_pass.set_bind_group(0, shadow_caster_bind_group, &[]);
+        self.draw(render_pass, light_frustum);
+    }
+
+    pub fn render_depth<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        camera_bind_group: &'a wgpu::BindGroup,
+        frustum: &Frustum,
+    ) {
+        render_pass.set_pipeline(&self.depth_pipeline);
+        render_pass.set_bind_group(0, camera_bind_group, &[]);
+        self.draw(render_pass, frustum);
+    }
+
+    pub fn render<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        camera_bind_group: &'a wgpu::BindGroup,
+        light_bind_group: &'a wgpu::BindGroup,
+        frustum: &Frustum,
+    ) {
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, camera_bind_group, &[]);
+        render_pass.set_bind_group(2, light_bind_group, &[]);
+        self.draw(render_pass, frustum);
+    }
+}
@@ -0,0 +1,36 @@
+// Layers are bits of a 32 bit mask. Mesh instances carry the layers they're on, while cameras
+// and lights carry the layers they see, and an instance is only drawn, lit or shadowed by those
+// sharing a layer with it. A few layers also change how their instances are drawn
+pub const DEFAULT: u32 = 1 << 0;
+// Instances on this layer don't cast shadows
+pub const NO_SHADOW: u32 = 1 << 1;
+// Instances on this layer are drawn over the rest of the scene without depth testing, and stay
+// out of the depth, velocity and shadow passes
+pub const OVERLAY: u32 = 1 << 2;
+pub const ALL: u32 = u32::MAX;
+
+pub const COUNT: usize = 32;
+
+pub fn name(layer: usize) -> String {
+    match 1 << layer {
+        DEFAULT => "Default".to_string(),
+        NO_SHADOW => "No shadow".to_string(),
+        OVERLAY => "Overlay".to_string(),
+        _ => format!("Layer {}", layer),
+    }
+}
+
+// Instances drawn normally by a camera seeing these layers
+pub fn visible(instance: u32, camera: u32) -> bool {
+    instance & camera != 0 && instance & OVERLAY == 0
+}
+
+// Instances drawn over the scene by a camera seeing these layers
+pub fn overlay(instance: u32, camera: u32) -> bool {
+    instance & camera != 0 && instance & OVERLAY != 0
+}
+
+// Instances casting shadows from a light shining on these layers
+pub fn casts_shadow(instance: u32, light: u32) -> bool {
+    instance & light != 0 && instance & (NO_SHADOW | OVERLAY) == 0
+}
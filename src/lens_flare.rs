@@ -0,0 +1,518 @@
+use nalgebra_glm as glm;
+
+use crate::{
+    camera::{aspect_ratio, Camera},
+    memory::{self, MemoryCategory, Tracked},
+    scene::{LightHandle, Scene},
+    texture::{DepthConfig, Texture},
+};
+
+// Flares past this many are ignored
+pub const MAX_LENS_FLARES: usize = 16;
+
+#[allow(dead_code)]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum FlareSource {
+    Sun,
+    Light(LightHandle),
+    Position(glm::Vec3),
+}
+
+#[allow(dead_code)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FlareShape {
+    Glow,
+    Ring,
+    // The shape of the lens aperture
+    Hexagon,
+    // Glare streaking out from bright lights
+    Starburst,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct FlareElement {
+    pub shape: FlareShape,
+    // Position along the line from the source through the center of the screen, zero at the
+    // source, one at the center and two mirrored across it
+    pub offset: f32,
+    // Radius as a fraction of the screen height
+    pub size: f32,
+    pub color: glm::Vec4,
+}
+
+// A chain of sprites drawn over a bright light, fading out while something is in front of it
+#[derive(Debug, Clone, PartialEq)]
+pub struct LensFlareDesc {
+    pub source: FlareSource,
+    pub elements: Vec<FlareElement>,
+    pub intensity: f32,
+    // Radius in pixels around the source tested against the depth buffer
+    pub occlusion_radius: f32,
+    pub visible: bool,
+}
+
+impl Default for LensFlareDesc {
+    fn default() -> Self {
+        let element = |shape, offset, size, color: [f32; 4]| FlareElement {
+            shape,
+            offset,
+            size,
+            color: color.into(),
+        };
+        Self {
+            source: FlareSource::Sun,
+            elements: vec![
+                element(FlareShape::Starburst, 0.0, 0.4, [1.0, 0.95, 0.85, 1.0]),
+                element(FlareShape::Glow, 0.0, 0.15, [1.0, 0.9, 0.7, 1.0]),
+                element(FlareShape::Hexagon, 0.4, 0.05, [0.4, 0.6, 1.0, 0.3]),
+                element(FlareShape::Ring, 0.7, 0.12, [0.6, 1.0, 0.6, 0.2]),
+                element(FlareShape::Hexagon, 1.2, 0.08, [1.0, 0.6, 0.3, 0.3]),
+                element(FlareShape::Glow, 1.5, 0.04, [0.8, 0.5, 1.0, 0.4]),
+                element(FlareShape::Ring, 1.9, 0.25, [0.5, 0.7, 1.0, 0.15]),
+            ],
+            intensity: 1.0,
+            occlusion_radius: 8.0,
+            visible: true,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Default, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct LensFlareUniform {
+    // x: delta time, y: aspect ratio, z: flare count, w: one when depth is reversed
+    parameters: [f32; 4],
+}
+
+#[repr(C)]
+#[derive(Default, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct GpuFlareSource {
+    // xy: normalized device coordinates, z: depth, w: one when in front of the camera
+    position: [f32; 4],
+    // rgb: color scaled by intensity, w: occlusion radius in pixels
+    color: [f32; 4],
+}
+
+#[repr(C)]
+#[derive(Default, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct ElementInstance {
+    color: [f32; 4],
+    // x: offset, y: size
+    placement: [f32; 2],
+    flare: u32,
+    shape: u32,
+}
+
+impl ElementInstance {
+    const ATTRIBUTES: [wgpu::VertexAttribute; 4] =
+        wgpu::vertex_attr_array![0 => Float32x4, 1 => Float32x2, 2 => Uint32, 3 => Uint32];
+
+    fn layout<'a>() -> wgpu::VertexBufferLayout<'a> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<Self>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &Self::ATTRIBUTES,
+        }
+    }
+}
+
+// Tests each flare's source against the depth buffer on the GPU, so occlusion never waits on a
+// readback, then draws the flares additively over the HDR scene color
+pub struct LensFlareSystem {
+    uniform_buffer: Tracked<wgpu::Buffer>,
+    source_buffer: Tracked<wgpu::Buffer>,
+    visibility_buffer: Tracked<wgpu::Buffer>,
+    instance_buffer: Tracked<wgpu::Buffer>,
+    instance_capacity: usize,
+    instance_count: u32,
+    flare_count: u32,
+    // Sources are placed with a standard projection, the depth they're tested against may not be
+    depth: DepthConfig,
+    occlusion_bind_group_layout: wgpu::BindGroupLayout,
+    occlusion_bind_group: wgpu::BindGroup,
+    sprite_bind_group: wgpu::BindGroup,
+    occlusion_pipeline: wgpu::ComputePipeline,
+    sprite_pipeline: wgpu::RenderPipeline,
+}
+
+impl LensFlareSystem {
+    pub fn new(device: &wgpu::Device, depth_texture: &Texture, depth: DepthConfig) -> Self {
+        let uniform_buffer = memory::create_buffer(
+            device,
+            MemoryCategory::Transient,
+            &wgpu::BufferDescriptor {
+                label: Some("Lens Flare Uniform Buffer"),
+                size: std::mem::size_of::<LensFlareUniform>() as wgpu::BufferAddress,
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            },
+        );
+
+        let source_buffer = memory::create_buffer(
+            device,
+            MemoryCategory::Transient,
+            &wgpu::BufferDescriptor {
+                label: Some("Lens Flare Source Buffer"),
+                size: (MAX_LENS_FLARES * std::mem::size_of::<GpuFlareSource>())
+                    as wgpu::BufferAddress,
+                usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            },
+        );
+
+        // Flares start out hidden and fade in
+        let visibility_buffer = memory::create_buffer_init(
+            device,
+            MemoryCategory::Transient,
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("Lens Flare Visibility Buffer"),
+                contents: bytemuck::cast_slice(&[0.0f32; MAX_LENS_FLARES]),
+                usage: wgpu::BufferUsages::STORAGE,
+            },
+        );
+
+        let buffer_entry = |binding, visibility, ty| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility,
+            ty: wgpu::BindingType::Buffer {
+                ty,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let storage = |read_only| wgpu::BufferBindingType::Storage { read_only };
+
+        let occlusion_bind_group_layout = memory::create_bind_group_layout(
+            device,
+            &wgpu::BindGroupLayoutDescriptor {
+                label: Some("Lens Flare Occlusion Bind Group Layout"),
+                entries: &[
+                    buffer_entry(
+                        0,
+                        wgpu::ShaderStages::COMPUTE,
+                        wgpu::BufferBindingType::Uniform,
+                    ),
+                    buffer_entry(1, wgpu::ShaderStages::COMPUTE, storage(true)),
+                    buffer_entry(2, wgpu::ShaderStages::COMPUTE, storage(false)),
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 3,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Depth,
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                ],
+            },
+        );
+
+        let sprite_bind_group_layout = memory::create_bind_group_layout(
+            device,
+            &wgpu::BindGroupLayoutDescriptor {
+                label: Some("Lens Flare Sprite Bind Group Layout"),
+                entries: &[
+                    buffer_entry(
+                        0,
+                        wgpu::ShaderStages::VERTEX,
+                        wgpu::BufferBindingType::Uniform,
+                    ),
+                    buffer_entry(1, wgpu::ShaderStages::VERTEX, storage(true)),
+                    buffer_entry(2, wgpu::ShaderStages::VERTEX, storage(true)),
+                ],
+            },
+        );
+
+        let occlusion_bind_group = Self::create_occlusion_bind_group(
+            device,
+            &occlusion_bind_group_layout,
+            &uniform_buffer,
+            &source_buffer,
+            &visibility_buffer,
+            depth_texture,
+        );
+
+        let sprite_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Lens Flare Sprite Bind Group"),
+            layout: &sprite_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: source_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: visibility_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        let occlusion_module = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("Lens Flare Occlusion Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("shaders/lens_flare.wgsl").into()),
+        });
+
+        let occlusion_pipeline_layout = memory::create_pipeline_layout(
+            device,
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("Lens Flare Occlusion Pipeline Layout"),
+                bind_group_layouts: &[&occlusion_bind_group_layout],
+                push_constant_ranges: &[],
+            },
+        );
+
+        let occlusion_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Lens Flare Occlusion Pipeline"),
+            layout: Some(&occlusion_pipeline_layout),
+            module: &occlusion_module,
+            entry_point: "cs_occlusion",
+        });
+
+        let sprite_module = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("Lens Flare Sprite Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("shaders/lens_flare_sprite.wgsl").into()),
+        });
+
+        let sprite_pipeline_layout = memory::create_pipeline_layout(
+            device,
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("Lens Flare Sprite Pipeline Layout"),
+                bind_group_layouts: &[&sprite_bind_group_layout],
+                push_constant_ranges: &[],
+            },
+        );
+
+        let additive = wgpu::BlendComponent {
+            src_factor: wgpu::BlendFactor::One,
+            dst_factor: wgpu::BlendFactor::One,
+            operation: wgpu::BlendOperation::Add,
+        };
+        let sprite_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Lens Flare Sprite Pipeline"),
+            layout: Some(&sprite_pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &sprite_module,
+                entry_point: "vs_main",
+                buffers: &[ElementInstance::layout()],
+            },
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleStrip,
+                ..Default::default()
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            fragment: Some(wgpu::FragmentState {
+                module: &sprite_module,
+                entry_point: "fs_main",
+                targets: &[wgpu::ColorTargetState {
+                    format: Texture::HDR_FORMAT,
+                    blend: Some(wgpu::BlendState {
+                        color: additive,
+                        alpha: additive,
+                    }),
+                    write_mask: wgpu::ColorWrites::ALL,
+                }],
+            }),
+        });
+
+        Self {
+            uniform_buffer,
+            source_buffer,
+            visibility_buffer,
+            instance_buffer: Self::create_instance_buffer(device, 1),
+            instance_capacity: 1,
+            instance_count: 0,
+            flare_count: 0,
+            depth,
+            occlusion_bind_group_layout,
+            occlusion_bind_group,
+            sprite_bind_group,
+            occlusion_pipeline,
+            sprite_pipeline,
+        }
+    }
+
+    fn create_occlusion_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        uniform_buffer: &wgpu::Buffer,
+        source_buffer: &wgpu::Buffer,
+        visibility_buffer: &wgpu::Buffer,
+        depth_texture: &Texture,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Lens Flare Occlusion Bind Group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: source_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: visibility_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::TextureView(&depth_texture.view),
+                },
+            ],
+        })
+    }
+
+    fn create_instance_buffer(device: &wgpu::Device, capacity: usize) -> Tracked<wgpu::Buffer> {
+        memory::create_buffer(
+            device,
+            MemoryCategory::Meshes,
+            &wgpu::BufferDescriptor {
+                label: Some("Lens Flare Instance Buffer"),
+                size: (capacity * std::mem::size_of::<ElementInstance>()) as wgpu::BufferAddress,
+                usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            },
+        )
+    }
+
+    pub fn resize(&mut self, device: &wgpu::Device, depth_texture: &Texture) {
+        self.occlusion_bind_group = Self::create_occlusion_bind_group(
+            device,
+            &self.occlusion_bind_group_layout,
+            &self.uniform_buffer,
+            &self.source_buffer,
+            &self.visibility_buffer,
+            depth_texture,
+        );
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn update(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        scene: &Scene,
+        camera: &Camera,
+        dimensions: &[u32; 2],
+        delta_time: f32,
+        enabled: bool,
+    ) {
+        let aspect_ratio = aspect_ratio(dimensions);
+        let view_projection = camera.projection_matrix(aspect_ratio) * camera.view_matrix();
+
+        // Hidden flares keep their slot so their visibility stays with them
+        let flares = scene.lens_flares.iter().take(MAX_LENS_FLARES);
+        let mut sources = Vec::new();
+        let mut instances = Vec::new();
+        for (index, flare) in flares.enumerate() {
+            let (position, color) = match flare.source {
+                // The sun is infinitely far away in the opposite direction it shines in
+                FlareSource::Sun => ((-scene.sun.direction).push(0.0), scene.sun.radiance()),
+                FlareSource::Light(handle) => match scene.lights.get(handle.0) {
+                    Some(light) => (scene.local_point(&light.position).push(1.0), light.color),
+                    None => (glm::Vec4::zeros(), glm::Vec3::zeros()),
+                },
+                FlareSource::Position(position) => (
+                    scene.local_point(&position).push(1.0),
+                    glm::vec3(1.0, 1.0, 1.0),
+                ),
+            };
+            let clip = view_projection * position;
+            let in_front = flare.visible && clip.w > f32::EPSILON;
+            let ndc = if in_front {
+                clip.xyz() / clip.w
+            } else {
+                glm::vec3(0.0, 0.0, 1.0)
+            };
+            let color = color * flare.intensity;
+            sources.push(GpuFlareSource {
+                position: [ndc.x, ndc.y, ndc.z, in_front as u32 as f32],
+                color: [color.x, color.y, color.z, flare.occlusion_radius],
+            });
+
+            if !enabled || !in_front {
+                continue;
+            }
+            instances.extend(flare.elements.iter().map(|element| ElementInstance {
+                color: element.color.into(),
+                placement: [element.offset, element.size],
+                flare: index as u32,
+                shape: match element.shape {
+                    FlareShape::Glow => 0,
+                    FlareShape::Ring => 1,
+                    FlareShape::Hexagon => 2,
+                    FlareShape::Starburst => 3,
+                },
+            }));
+        }
+
+        self.flare_count = sources.len() as u32;
+        self.instance_count = instances.len() as u32;
+        queue.write_buffer(
+            &self.uniform_buffer,
+            0,
+            bytemuck::cast_slice(&[LensFlareUniform {
+                parameters: [
+                    delta_time,
+                    aspect_ratio,
+                    self.flare_count as f32,
+                    self.depth.reversed as u32 as f32,
+                ],
+            }]),
+        );
+        if !sources.is_empty() {
+            queue.write_buffer(&self.source_buffer, 0, bytemuck::cast_slice(&sources));
+        }
+        if instances.len() > self.instance_capacity {
+            self.instance_capacity = instances.len().next_power_of_two();
+            self.instance_buffer = Self::create_instance_buffer(device, self.instance_capacity);
+        }
+        if !instances.is_empty() {
+            queue.write_buffer(&self.instance_buffer, 0, bytemuck::cast_slice(&instances));
+        }
+    }
+
+    // Needs the finished depth buffer, flares fade towards what the occlusion test found
+    pub fn render(&self, encoder: &mut wgpu::CommandEncoder, scene_color: &Texture) {
+        if self.flare_count == 0 {
+            return;
+        }
+
+        {
+            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("Lens Flare Occlusion Pass"),
+            });
+            compute_pass.set_pipeline(&self.occlusion_pipeline);
+            compute_pass.set_bind_group(0, &self.occlusion_bind_group, &[]);
+            compute_pass.dispatch(self.flare_count, 1, 1);
+        }
+
+        if self.instance_count == 0 {
+            return;
+        }
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Lens Flare Pass"),
+            color_attachments: &[wgpu::RenderPassColorAttachment {
+                view: &scene_color.view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: true,
+                },
+            }],
+            depth_stencil_attachment: None,
+        });
+        render_pass.set_pipeline(&self.sprite_pipeline);
+        render_pass.set_bind_group(0, &self.sprite_bind_group, &[]);
+        render_pass.set_vertex_buffer(0, self.instance_buffer.slice(..));
+        render_pass.draw(0..4, 0..self.instance_count);
+    }
+}
@@ -0,0 +1,825 @@
+use nalgebra_glm as glm;
+
+use crate::{
+    area_lights::AreaLightSystem,
+    blue_noise::BlueNoise,
+    bounds::{Aabb, Frustum},
+    camera::Camera,
+    irradiance::IrradianceSystem,
+    layers,
+    material::PAPER_WHITE_NITS,
+    memory::{self, MemoryCategory, Tracked},
+    probes::ReflectionProbeSystem,
+    ray_traced_shadows::RayTracedShadowSystem,
+    shadow_atlas::{self, ShadowAssignment, ShadowView, MAX_SHADOW_VIEWS},
+    texture::{DepthConfig, Texture},
+};
+
+// The sun's tile in the corner of the shadow atlas
+const SHADOW_MAP_SIZE: u32 = 2048;
+
+// Lights past this many are ignored while shading
+pub const MAX_PUNCTUAL_LIGHTS: usize = 16;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct DirectionalLight {
+    // The direction the light travels in
+    pub direction: glm::Vec3,
+    pub color: glm::Vec3,
+    pub intensity: f32,
+    // Black body temperature in Kelvin the color tints, see color_temperature
+    pub temperature: Option<f32>,
+    // The layers the light shines on and casts shadows onto, see layers.rs
+    pub layers: u32,
+}
+
+impl Default for DirectionalLight {
+    fn default() -> Self {
+        Self {
+            direction: glm::normalize(&glm::vec3(-0.3, -1.0, -0.2)),
+            color: glm::vec3(1.0, 1.0, 1.0),
+            intensity: 1.0,
+            temperature: None,
+            layers: layers::ALL,
+        }
+    }
+}
+
+impl DirectionalLight {
+    // Color scaled by intensity in units of scene color
+    pub fn radiance(&self) -> glm::Vec3 {
+        tinted(&self.color, self.temperature) * self.intensity
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum LightUnit {
+    // Luminous intensity, what glTF uses for point and spot lights
+    Candela,
+    // Luminous power, spread over the sphere or cone the light shines into
+    Lumens,
+    // Illuminance, what directional lights always use
+    Lux,
+}
+
+impl LightUnit {
+    pub const ALL: [Self; 3] = [Self::Candela, Self::Lumens, Self::Lux];
+
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            Self::Candela => "cd",
+            Self::Lumens => "lm",
+            Self::Lux => "lx",
+        }
+    }
+}
+
+// Linear color of a black body at a temperature in Kelvin with a luminance of one, through the
+// approximation of the Planckian locus by Kim et al.
+pub fn color_temperature(kelvin: f32) -> glm::Vec3 {
+    let t = kelvin.clamp(1667.0, 25000.0);
+    let (t2, t3) = (t * t, t * t * t);
+    let x = if t <= 4000.0 {
+        -0.266_123_9e9 / t3 - 0.234_358_9e6 / t2 + 0.877_695_6e3 / t + 0.179_910
+    } else {
+        -3.025_846_9e9 / t3 + 2.107_038e6 / t2 + 0.222_634_7e3 / t + 0.240_390
+    };
+    let (x2, x3) = (x * x, x * x * x);
+    let y = if t <= 2222.0 {
+        -1.106_381_4 * x3 - 1.348_110_2 * x2 + 2.185_558_3 * x - 0.202_196_83
+    } else if t <= 4000.0 {
+        -0.954_947_6 * x3 - 1.374_185_9 * x2 + 2.091_37 * x - 0.167_488_67
+    } else {
+        3.081_758 * x3 - 5.873_387 * x2 + 3.751_13 * x - 0.370_014_83
+    };
+
+    // From xyY to XYZ, then to linear sRGB
+    let (big_x, big_y, big_z) = (x / y, 1.0, (1.0 - x - y) / y);
+    glm::vec3(
+        3.240_454_2 * big_x - 1.537_138_5 * big_y - 0.498_531_4 * big_z,
+        -0.969_266 * big_x + 1.876_010_8 * big_y + 0.041_556 * big_z,
+        0.055_643_4 * big_x - 0.204_025_9 * big_y + 1.057_225_2 * big_z,
+    )
+    .map(|channel| channel.max(0.0))
+}
+
+// A color filtering the light of a black body, or the color alone without a temperature
+pub fn tinted(color: &glm::Vec3, temperature: Option<f32>) -> glm::Vec3 {
+    match temperature {
+        Some(kelvin) => color.component_mul(&color_temperature(kelvin)),
+        None => *color,
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum PunctualLightKind {
+    Directional,
+    Point,
+    // Angles in radians from the spot direction, light fades out between the inner and outer cone
+    Spot {
+        inner_cone_angle: f32,
+        outer_cone_angle: f32,
+    },
+}
+
+// Lights such as lamps, imported from glTF scenes
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct PunctualLight {
+    pub kind: PunctualLightKind,
+    pub position: glm::Vec3,
+    // The direction spot and directional lights shine in
+    pub direction: glm::Vec3,
+    pub color: glm::Vec3,
+    // Measured in the unit below, converted to candela for point and spot lights and lux for
+    // directional lights
+    pub intensity: f32,
+    pub unit: LightUnit,
+    // Black body temperature in Kelvin the color tints, see color_temperature
+    pub temperature: Option<f32>,
+    // Distance at which the light has faded out completely, unbounded when None
+    pub range: Option<f32>,
+    // The layers the light shines on, see layers.rs
+    pub layers: u32,
+    // Spot and point lights share the shadow atlas, see shadow_atlas.rs
+    pub cast_shadows: bool,
+}
+
+impl Default for PunctualLight {
+    fn default() -> Self {
+        Self {
+            kind: PunctualLightKind::Point,
+            position: glm::Vec3::zeros(),
+            direction: -glm::Vec3::z(),
+            color: glm::vec3(1.0, 1.0, 1.0),
+            intensity: 1.0,
+            unit: LightUnit::Candela,
+            temperature: None,
+            range: None,
+            layers: layers::ALL,
+            cast_shadows: false,
+        }
+    }
+}
+
+impl PunctualLight {
+    pub fn transformed(&self, transform: &glm::Mat4) -> Self {
+        let position = transform * self.position.push(1.0);
+        let direction = glm::mat4_to_mat3(transform) * self.direction;
+        Self {
+            position: position.xyz(),
+            direction: glm::normalize(&direction),
+            ..*self
+        }
+    }
+
+    // Candela for point and spot lights, lux for directional lights. Lux measured at a distance
+    // of one is the same number of candela
+    pub fn luminous_intensity(&self) -> f32 {
+        match (self.unit, self.kind) {
+            (LightUnit::Lumens, PunctualLightKind::Point) => {
+                self.intensity / (4.0 * std::f32::consts::PI)
+            }
+            // The solid angle of the outer cone
+            (
+                LightUnit::Lumens,
+                PunctualLightKind::Spot {
+                    outer_cone_angle, ..
+                },
+            ) => {
+                let solid_angle = 2.0 * std::f32::consts::PI * (1.0 - outer_cone_angle.cos());
+                self.intensity / solid_angle.max(f32::EPSILON)
+            }
+            _ => self.intensity,
+        }
+    }
+
+    // Color scaled by the luminous intensity
+    pub fn radiance(&self) -> glm::Vec3 {
+        tinted(&self.color, self.temperature) * self.luminous_intensity()
+    }
+}
+
+#[repr(C)]
+#[derive(Default, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct PunctualLightUniform {
+    // w: one for lights with a position, zero for directional lights whose xyz points towards the light
+    position: [f32; 4],
+    // Points towards the light
+    direction: [f32; 4],
+    // Illuminance at a distance of one in units of scene color
+    color: [f32; 4],
+    // x: spot angle scale, y: spot angle offset, z: range or zero when unbounded, w: the bits of
+    // the layers it shines on
+    parameters: [f32; 4],
+    // x: the first shadow view, y: how many views there are or zero when unshadowed
+    shadow: [u32; 4],
+}
+
+impl PunctualLightUniform {
+    fn new(light: &PunctualLight, shadow: [u32; 4]) -> Self {
+        let direction = -glm::normalize(&light.direction);
+        let position = match light.kind {
+            PunctualLightKind::Directional => glm::vec3_to_vec4(&direction),
+            _ => light.position.push(1.0),
+        };
+
+        // Cone falloff as scale and offset of the cosine, which leaves point lights unattenuated
+        let (angle_scale, angle_offset) = match light.kind {
+            PunctualLightKind::Spot {
+                inner_cone_angle,
+                outer_cone_angle,
+            } => {
+                let cos_outer = outer_cone_angle.cos();
+                let scale = 1.0 / (inner_cone_angle.cos() - cos_outer).max(0.001);
+                (scale, -cos_outer * scale)
+            }
+            _ => (0.0, 1.0),
+        };
+
+        // A white surface lit by paper white nits times pi lux is displayed as white
+        let color = light.radiance() / PAPER_WHITE_NITS;
+        Self {
+            position: position.into(),
+            direction: glm::vec3_to_vec4(&direction).into(),
+            color: glm::vec3_to_vec4(&color).into(),
+            parameters: [
+                angle_scale,
+                angle_offset,
+                light.range.unwrap_or(0.0).max(0.0),
+                f32::from_bits(light.layers),
+            ],
+            shadow,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct LightUniform {
+    view_projection: [[f32; 4]; 4],
+    // Points towards the light
+    direction: [f32; 4],
+    // Color premultiplied by intensity
+    color: [f32; 4],
+    punctual_lights: [PunctualLightUniform; MAX_PUNCTUAL_LIGHTS],
+    punctual_light_count: u32,
+    // The layers the directional light shines on
+    layers: u32,
+    _padding: [u32; 2],
+    shadow_views: [ShadowViewUniform; MAX_SHADOW_VIEWS],
+}
+
+impl LightUniform {
+    fn new(
+        light: &DirectionalLight,
+        punctual_lights: &[PunctualLight],
+        view_projection: &glm::Mat4,
+        shadows: &ShadowAssignment,
+        atlas_size: u32,
+    ) -> Self {
+        let color = light.radiance();
+        let mut uniform = Self {
+            view_projection: (*view_projection).into(),
+            direction: glm::vec3_to_vec4(&-glm::normalize(&light.direction)).into(),
+            color: glm::vec3_to_vec4(&color).into(),
+            layers: light.layers,
+            ..bytemuck::Zeroable::zeroed()
+        };
+        for (index, (slot, light)) in uniform
+            .punctual_lights
+            .iter_mut()
+            .zip(punctual_lights.iter())
+            .enumerate()
+        {
+            let shadow = shadows
+                .light_shadow(index)
+                .map(|shadow| [shadow.first_view as u32, shadow.view_count as u32, 0, 0])
+                .unwrap_or_default();
+            *slot = PunctualLightUniform::new(light, shadow);
+        }
+        for (slot, view) in uniform.shadow_views.iter_mut().zip(shadows.views.iter()) {
+            *slot = ShadowViewUniform::new(view, atlas_size);
+        }
+        uniform.punctual_light_count = punctual_lights.len().min(MAX_PUNCTUAL_LIGHTS) as u32;
+        uniform
+    }
+}
+
+#[repr(C)]
+#[derive(Default, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct ShadowViewUniform {
+    view_projection: [[f32; 4]; 4],
+    // xy: offset of the tile in the atlas, z: size of the tile, w: size of a texel, in uvs
+    rect: [f32; 4],
+}
+
+impl ShadowViewUniform {
+    fn new(view: &ShadowView, atlas_size: u32) -> Self {
+        let atlas_size = atlas_size as f32;
+        Self {
+            view_projection: view.view_projection.into(),
+            rect: [
+                view.viewport[0] as f32 / atlas_size,
+                view.viewport[1] as f32 / atlas_size,
+                view.viewport[2] as f32 / atlas_size,
+                1.0 / atlas_size,
+            ],
+        }
+    }
+}
+
+// What shadow casters read from the light, matching the start of the light uniform
+#[repr(C)]
+#[derive(Default, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct CasterUniform {
+    view_projection: [[f32; 4]; 4],
+    direction: [f32; 4],
+    color: [f32; 4],
+}
+
+// Renders shadow casters from the point of view of the directional light, lit surfaces also
+// find the reflection and irradiance probes, area lights and the ray traced shadow mask through its
+// bind group.
+// Shadowed spot and point lights render into tiles of a shared atlas instead
+pub struct LightingSystem {
+    light_buffer: Tracked<wgpu::Buffer>,
+    // The sun and the local lights share one atlas, which keeps the textures model shading
+    // samples within the limit of sixteen
+    shadow_map: Texture,
+    // Shadow maps are reversed along with the scene's depth
+    depth: DepthConfig,
+    atlas_size: u32,
+    sun_size: u32,
+    shadows: ShadowAssignment,
+    // Each shadow view's caster uniform at its own aligned offset
+    caster_buffer: Tracked<wgpu::Buffer>,
+    caster_stride: u32,
+    view_bind_groups: Vec<wgpu::BindGroup>,
+    view_projection: glm::Mat4,
+    caster_bind_group_layout: wgpu::BindGroupLayout,
+    caster_bind_group: wgpu::BindGroup,
+    bind_group_layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+    // Probes are rendered without the mask, which only lines up with the camera's pixels
+    capture_bind_group: wgpu::BindGroup,
+}
+
+impl LightingSystem {
+    pub fn new(
+        device: &wgpu::Device,
+        depth: DepthConfig,
+        probe_system: &ReflectionProbeSystem,
+        irradiance_system: &IrradianceSystem,
+        area_light_system: &AreaLightSystem,
+        ray_traced_shadow_system: &RayTracedShadowSystem,
+        blue_noise: &BlueNoise,
+    ) -> Self {
+        let light_buffer = memory::create_buffer(
+            device,
+            MemoryCategory::Transient,
+            &wgpu::BufferDescriptor {
+                label: Some("Light Uniform Buffer"),
+                size: std::mem::size_of::<LightUniform>() as wgpu::BufferAddress,
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            },
+        );
+
+        // Devices limited to smaller textures only have room for the sun
+        let atlas_size = shadow_atlas::ATLAS_SIZE.min(device.limits().max_texture_dimension_2d);
+        let sun_size = SHADOW_MAP_SIZE.min(atlas_size);
+        let shadow_map = Texture::create_depth_texture(
+            device,
+            atlas_size,
+            atlas_size,
+            depth.shadow(),
+            "Shadow Atlas",
+        );
+
+        let alignment = device.limits().min_uniform_buffer_offset_alignment;
+        let caster_size = std::mem::size_of::<CasterUniform>() as u32;
+        let caster_stride = caster_size.div_ceil(alignment) * alignment;
+        let caster_buffer = memory::create_buffer(
+            device,
+            MemoryCategory::Transient,
+            &wgpu::BufferDescriptor {
+                label: Some("Shadow Caster Uniform Buffer"),
+                size: (caster_stride as usize * MAX_SHADOW_VIEWS) as wgpu::BufferAddress,
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            },
+        );
+
+        let light_entry = wgpu::BindGroupLayoutEntry {
+            binding: 0,
+            visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+
+        // Casters can't sample the shadow map they are rendering into
+        let caster_bind_group_layout = memory::create_bind_group_layout(
+            device,
+            &wgpu::BindGroupLayoutDescriptor {
+                label: Some("Shadow Caster Bind Group Layout"),
+                entries: &[light_entry],
+            },
+        );
+
+        let caster_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Shadow Caster Bind Group"),
+            layout: &caster_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: light_buffer.as_entire_binding(),
+            }],
+        });
+
+        let view_bind_groups = (0..MAX_SHADOW_VIEWS)
+            .map(|view| {
+                device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: Some("Shadow View Caster Bind Group"),
+                    layout: &caster_bind_group_layout,
+                    entries: &[wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                            buffer: &caster_buffer,
+                            offset: (view as u32 * caster_stride) as wgpu::BufferAddress,
+                            size: wgpu::BufferSize::new(caster_size as u64),
+                        }),
+                    }],
+                })
+            })
+            .collect();
+
+        let bind_group_layout = memory::create_bind_group_layout(
+            device,
+            &wgpu::BindGroupLayoutDescriptor {
+                label: Some("Light Bind Group Layout"),
+                entries: &[
+                    light_entry,
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Depth,
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 2,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Sampler {
+                            filtering: true,
+                            comparison: true,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 3,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                            view_dimension: wgpu::TextureViewDimension::D2Array,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 4,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Sampler {
+                            filtering: true,
+                            comparison: false,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 5,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 6,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Storage { read_only: true },
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 7,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 8,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 9,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 10,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Storage { read_only: true },
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 11,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                ],
+            },
+        );
+
+        let resources = LightResources {
+            light_buffer: &light_buffer,
+            shadow_map: &shadow_map,
+            probe_system,
+            irradiance_system,
+            area_light_system,
+            blue_noise,
+        };
+        let bind_group = resources.create_bind_group(
+            device,
+            &bind_group_layout,
+            ray_traced_shadow_system.mask_view(),
+        );
+        let capture_bind_group = resources.create_bind_group(
+            device,
+            &bind_group_layout,
+            ray_traced_shadow_system.unshadowed_view(),
+        );
+
+        Self {
+            light_buffer,
+            shadow_map,
+            depth: depth.shadow(),
+            atlas_size,
+            sun_size,
+            shadows: ShadowAssignment::default(),
+            caster_buffer,
+            caster_stride,
+            view_bind_groups,
+            view_projection: glm::Mat4::identity(),
+            caster_bind_group_layout,
+            caster_bind_group,
+            bind_group_layout,
+            bind_group,
+            capture_bind_group,
+        }
+    }
+
+    // The shadow mask is recreated with the render targets
+    pub fn resize(
+        &mut self,
+        device: &wgpu::Device,
+        probe_system: &ReflectionProbeSystem,
+        irradiance_system: &IrradianceSystem,
+        area_light_system: &AreaLightSystem,
+        ray_traced_shadow_system: &RayTracedShadowSystem,
+        blue_noise: &BlueNoise,
+    ) {
+        self.bind_group = LightResources {
+            light_buffer: &self.light_buffer,
+            shadow_map: &self.shadow_map,
+            probe_system,
+            irradiance_system,
+            area_light_system,
+            blue_noise,
+        }
+        .create_bind_group(
+            device,
+            &self.bind_group_layout,
+            ray_traced_shadow_system.mask_view(),
+        );
+    }
+
+    pub fn caster_bind_group_layout(&self) -> &wgpu::BindGroupLayout {
+        &self.caster_bind_group_layout
+    }
+
+    pub fn caster_bind_group(&self) -> &wgpu::BindGroup {
+        &self.caster_bind_group
+    }
+
+    pub fn bind_group_layout(&self) -> &wgpu::BindGroupLayout {
+        &self.bind_group_layout
+    }
+
+    pub fn bind_group(&self) -> &wgpu::BindGroup {
+        &self.bind_group
+    }
+
+    pub fn capture_bind_group(&self) -> &wgpu::BindGroup {
+        &self.capture_bind_group
+    }
+
+    pub fn shadow_map(&self) -> &Texture {
+        &self.shadow_map
+    }
+
+    pub fn view_projection(&self) -> &glm::Mat4 {
+        &self.view_projection
+    }
+
+    // x, y and size in texels of the sun's tile in the shadow map
+    pub fn sun_viewport(&self) -> [u32; 3] {
+        [0, 0, self.sun_size]
+    }
+
+    // The tiles of the atlas to render this frame with the bind group casters use for each
+    pub fn shadow_views(&self) -> impl Iterator<Item = (&ShadowView, &wgpu::BindGroup)> {
+        self.shadows.views.iter().zip(self.view_bind_groups.iter())
+    }
+
+    // Shadowed local lights and the atlas views they took
+    pub fn shadow_stats(&self) -> (usize, usize) {
+        (self.shadows.lights.len(), self.shadows.views.len())
+    }
+
+    // Fits an orthographic shadow projection around everything that casts shadows and hands out
+    // atlas tiles to the local lights the camera can see
+    pub fn update(
+        &mut self,
+        queue: &wgpu::Queue,
+        light: &DirectionalLight,
+        punctual_lights: &[PunctualLight],
+        casters: &Aabb,
+        camera: &Camera,
+        camera_frustum: &Frustum,
+    ) {
+        let (center, radius) = if !casters.is_empty() {
+            (
+                casters.center(),
+                (casters.extents().magnitude() * 0.5).max(1.0),
+            )
+        } else {
+            (glm::Vec3::zeros(), 1.0)
+        };
+
+        let direction = glm::normalize(&light.direction);
+        let up = if direction.y.abs() > 0.99 {
+            glm::Vec3::z()
+        } else {
+            glm::Vec3::y()
+        };
+        let eye = center - direction * radius * 2.0;
+        let view = glm::look_at_rh(&eye, &center, &up);
+        let projection = glm::ortho_rh_zo(-radius, radius, -radius, radius, 0.0, radius * 4.0);
+        self.view_projection = self.depth.projection(projection * view);
+
+        self.shadows = shadow_atlas::assign(
+            punctual_lights,
+            camera,
+            camera_frustum,
+            self.atlas_size,
+            self.sun_size,
+        );
+        for view in self.shadows.views.iter_mut() {
+            view.view_projection = self.depth.projection(view.view_projection);
+        }
+        for (index, view) in self.shadows.views.iter().enumerate() {
+            let caster = CasterUniform {
+                view_projection: view.view_projection.into(),
+                ..Default::default()
+            };
+            queue.write_buffer(
+                &self.caster_buffer,
+                (index as u32 * self.caster_stride) as wgpu::BufferAddress,
+                bytemuck::cast_slice(&[caster]),
+            );
+        }
+
+        queue.write_buffer(
+            &self.light_buffer,
+            0,
+            bytemuck::cast_slice(&[LightUniform::new(
+                light,
+                punctual_lights,
+                &self.view_projection,
+                &self.shadows,
+                self.atlas_size,
+            )]),
+        );
+    }
+}
+
+// Everything lit surfaces bind besides the shadow mask
+struct LightResources<'a> {
+    light_buffer: &'a wgpu::Buffer,
+    shadow_map: &'a Texture,
+    probe_system: &'a ReflectionProbeSystem,
+    irradiance_system: &'a IrradianceSystem,
+    area_light_system: &'a AreaLightSystem,
+    blue_noise: &'a BlueNoise,
+}
+
+impl<'a> LightResources<'a> {
+    fn create_bind_group(
+        &self,
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        shadow_mask: &wgpu::TextureView,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Light Bind Group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: self.light_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&self.shadow_map.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::Sampler(&self.shadow_map.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::TextureView(self.probe_system.view()),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: wgpu::BindingResource::Sampler(self.probe_system.sampler()),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: self.probe_system.uniform_buffer().as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 6,
+                    resource: self
+                        .irradiance_system
+                        .coefficient_buffer()
+                        .as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 7,
+                    resource: self.irradiance_system.uniform_buffer().as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 8,
+                    resource: wgpu::BindingResource::TextureView(shadow_mask),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 9,
+                    resource: self.area_light_system.uniform_buffer().as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 10,
+                    resource: self.area_light_system.data_buffer().as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 11,
+                    resource: self.blue_noise.buffer().as_entire_binding(),
+                },
+            ],
+        })
+    }
+}
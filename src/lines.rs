@@ -0,0 +1,440 @@
+use nalgebra_glm as glm;
+use std::ops::Range;
+
+use crate::{
+    memory::{self, MemoryCategory, Tracked},
+    scene::Scene,
+    shader_snippets,
+    texture::Texture,
+};
+
+#[allow(dead_code)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum LineSpace {
+    // Points are in the world and seen through the camera
+    World,
+    // Points are in pixels from the top left corner of the window, z is ignored
+    Screen,
+}
+
+#[allow(dead_code)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum LineJoin {
+    // Segments meet at a sharp corner and the ends are cut off square
+    Miter,
+    // Segments and ends are rounded off
+    Round,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PolylineDesc {
+    pub points: Vec<glm::Vec3>,
+    pub space: LineSpace,
+    // Width in pixels whatever the space
+    pub width: f32,
+    pub color: glm::Vec4,
+    pub join: LineJoin,
+    // Lengths of the dashes and the gaps between them in the units of the points, solid when None
+    pub dashes: Option<glm::Vec2>,
+    // Joins the last point back to the first
+    pub closed: bool,
+    // World lines are hidden behind opaque geometry when set
+    pub depth_test: bool,
+}
+
+impl Default for PolylineDesc {
+    fn default() -> Self {
+        Self {
+            points: Vec::new(),
+            space: LineSpace::World,
+            width: 1.0,
+            color: glm::vec4(1.0, 1.0, 1.0, 1.0),
+            join: LineJoin::Miter,
+            dashes: None,
+            closed: false,
+            depth_test: true,
+        }
+    }
+}
+
+// A segment drawn as a quad expanded to its width on screen
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct LineSegment {
+    // The w of the neighbours is one when present and zero at the ends of the line
+    previous: [f32; 4],
+    // The w of the endpoints is their distance along the line
+    start: [f32; 4],
+    end: [f32; 4],
+    next: [f32; 4],
+    color: [f32; 4],
+    // Width, dash length, gap length
+    parameters: [f32; 4],
+    join: u32,
+}
+
+impl LineSegment {
+    const ATTRIBUTES: [wgpu::VertexAttribute; 7] = wgpu::vertex_attr_array![
+        0 => Float32x4,
+        1 => Float32x4,
+        2 => Float32x4,
+        3 => Float32x4,
+        4 => Float32x4,
+        5 => Float32x4,
+        6 => Uint32
+    ];
+
+    fn layout<'a>() -> wgpu::VertexBufferLayout<'a> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<Self>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &Self::ATTRIBUTES,
+        }
+    }
+}
+
+fn line_segments(desc: &PolylineDesc, scene: &Scene) -> Vec<LineSegment> {
+    // Screen lines measure their dashes in pixels, world lines are drawn relative to the origin
+    let point = |index: usize| match desc.space {
+        LineSpace::World => scene.local_point(&desc.points[index]),
+        LineSpace::Screen => glm::vec3(desc.points[index].x, desc.points[index].y, 0.0),
+    };
+
+    // Repeated points would leave segments without a direction
+    let mut points: Vec<glm::Vec3> = Vec::with_capacity(desc.points.len());
+    for index in 0..desc.points.len() {
+        if points.last() != Some(&point(index)) {
+            points.push(point(index));
+        }
+    }
+    if desc.closed && points.len() > 2 && points.first() == points.last() {
+        points.pop();
+    }
+    if points.len() < 2 {
+        return Vec::new();
+    }
+
+    let count = points.len();
+    let closed = desc.closed && count > 2;
+    let segment_count = if closed { count } else { count - 1 };
+    let neighbour = |index: isize| -> [f32; 4] {
+        let wrapped = if closed {
+            Some(index.rem_euclid(count as isize) as usize)
+        } else if index >= 0 && (index as usize) < count {
+            Some(index as usize)
+        } else {
+            None
+        };
+        match wrapped {
+            Some(index) => glm::vec4(points[index].x, points[index].y, points[index].z, 1.0).into(),
+            None => [0.0; 4],
+        }
+    };
+
+    let color = desc.color.into();
+    let dashes = desc.dashes.unwrap_or_else(glm::Vec2::zeros);
+    let parameters = [desc.width, dashes.x, dashes.y, 0.0];
+    let join = match desc.join {
+        LineJoin::Miter => 0,
+        LineJoin::Round => 1,
+    };
+
+    let mut distance = 0.0;
+    let mut segments = Vec::with_capacity(segment_count);
+    for index in 0..segment_count {
+        let start = points[index];
+        let end = points[(index + 1) % count];
+        let length = glm::distance(&start, &end);
+        segments.push(LineSegment {
+            previous: neighbour(index as isize - 1),
+            start: glm::vec4(start.x, start.y, start.z, distance).into(),
+            end: glm::vec4(end.x, end.y, end.z, distance + length).into(),
+            next: neighbour(index as isize + 2),
+            color,
+            parameters,
+            join,
+        });
+        distance += length;
+    }
+    segments
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct LineUniform {
+    viewport: [f32; 4],
+}
+
+pub struct LineSystem {
+    uniform_buffer: Tracked<wgpu::Buffer>,
+    uniform_bind_group: wgpu::BindGroup,
+    depth_bind_group_layout: wgpu::BindGroupLayout,
+    depth_bind_group: wgpu::BindGroup,
+    world_pipeline: wgpu::RenderPipeline,
+    depth_tested_world_pipeline: wgpu::RenderPipeline,
+    screen_pipeline: wgpu::RenderPipeline,
+    segment_buffer: Tracked<wgpu::Buffer>,
+    segment_capacity: usize,
+    // Ranges of the segment buffer drawn by each pipeline
+    world_segments: Range<u32>,
+    depth_tested_world_segments: Range<u32>,
+    screen_segments: Range<u32>,
+}
+
+impl LineSystem {
+    pub fn new(
+        device: &wgpu::Device,
+        camera_bind_group_layout: &wgpu::BindGroupLayout,
+        output_format: wgpu::TextureFormat,
+        depth_texture: &Texture,
+    ) -> Self {
+        let uniform_buffer = memory::create_buffer(
+            device,
+            MemoryCategory::Transient,
+            &wgpu::BufferDescriptor {
+                label: Some("Line Uniform Buffer"),
+                size: std::mem::size_of::<LineUniform>() as wgpu::BufferAddress,
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            },
+        );
+
+        let uniform_bind_group_layout = memory::create_bind_group_layout(
+            device,
+            &wgpu::BindGroupLayoutDescriptor {
+                label: Some("Line Uniform Bind Group Layout"),
+                entries: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                }],
+            },
+        );
+
+        let uniform_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Line Uniform Bind Group"),
+            layout: &uniform_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: uniform_buffer.as_entire_binding(),
+            }],
+        });
+
+        // Like labels, world lines compare against the scene depth themselves because they are
+        // drawn at the output resolution
+        let depth_bind_group_layout = memory::create_bind_group_layout(
+            device,
+            &wgpu::BindGroupLayoutDescriptor {
+                label: Some("Line Depth Bind Group Layout"),
+                entries: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Depth,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                }],
+            },
+        );
+
+        let depth_bind_group =
+            Self::create_depth_bind_group(device, &depth_bind_group_layout, depth_texture);
+
+        let module = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("Line Shader"),
+            source: wgpu::ShaderSource::Wgsl(
+                shader_snippets::splice(include_str!("shaders/line.wgsl")).into(),
+            ),
+        });
+
+        let pipeline_layout = memory::create_pipeline_layout(
+            device,
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("Line Pipeline Layout"),
+                bind_group_layouts: &[
+                    camera_bind_group_layout,
+                    &uniform_bind_group_layout,
+                    &depth_bind_group_layout,
+                ],
+                push_constant_ranges: &[],
+            },
+        );
+
+        let create_pipeline = |label, vertex_entry_point, fragment_entry_point| {
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some(label),
+                layout: Some(&pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: &module,
+                    entry_point: vertex_entry_point,
+                    buffers: &[LineSegment::layout()],
+                },
+                primitive: wgpu::PrimitiveState::default(),
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState::default(),
+                fragment: Some(wgpu::FragmentState {
+                    module: &module,
+                    entry_point: fragment_entry_point,
+                    targets: &[wgpu::ColorTargetState {
+                        format: output_format,
+                        blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    }],
+                }),
+            })
+        };
+
+        let world_pipeline = create_pipeline("World Line Pipeline", "vs_world", "fs_main");
+        let depth_tested_world_pipeline = create_pipeline(
+            "Depth Tested World Line Pipeline",
+            "vs_world",
+            "fs_depth_tested",
+        );
+        let screen_pipeline = create_pipeline("Screen Line Pipeline", "vs_screen", "fs_main");
+
+        Self {
+            uniform_buffer,
+            uniform_bind_group,
+            depth_bind_group_layout,
+            depth_bind_group,
+            world_pipeline,
+            depth_tested_world_pipeline,
+            screen_pipeline,
+            segment_buffer: Self::create_segment_buffer(device, 0),
+            segment_capacity: 0,
+            world_segments: 0..0,
+            depth_tested_world_segments: 0..0,
+            screen_segments: 0..0,
+        }
+    }
+
+    fn create_depth_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        depth_texture: &Texture,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Line Depth Bind Group"),
+            layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&depth_texture.view),
+            }],
+        })
+    }
+
+    pub fn resize(&mut self, device: &wgpu::Device, depth_texture: &Texture) {
+        self.depth_bind_group =
+            Self::create_depth_bind_group(device, &self.depth_bind_group_layout, depth_texture);
+    }
+
+    fn create_segment_buffer(device: &wgpu::Device, capacity: usize) -> Tracked<wgpu::Buffer> {
+        memory::create_buffer(
+            device,
+            MemoryCategory::Meshes,
+            &wgpu::BufferDescriptor {
+                label: Some("Line Segment Buffer"),
+                size: (capacity.max(1) * std::mem::size_of::<LineSegment>()) as wgpu::BufferAddress,
+                usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            },
+        )
+    }
+
+    pub fn update(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        scene: &Scene,
+        dimensions: &[u32; 2],
+    ) {
+        queue.write_buffer(
+            &self.uniform_buffer,
+            0,
+            bytemuck::cast_slice(&[LineUniform {
+                viewport: [dimensions[0] as f32, dimensions[1] as f32, 0.0, 0.0],
+            }]),
+        );
+
+        let mut segments = Vec::new();
+        let mut collect = |filter: &dyn Fn(&PolylineDesc) -> bool| {
+            let start = segments.len() as u32;
+            for polyline in scene.polylines.iter().filter(|polyline| filter(polyline)) {
+                segments.extend(line_segments(polyline, scene));
+            }
+            start..segments.len() as u32
+        };
+        self.depth_tested_world_segments =
+            collect(&|polyline| polyline.space == LineSpace::World && polyline.depth_test);
+        self.world_segments =
+            collect(&|polyline| polyline.space == LineSpace::World && !polyline.depth_test);
+        self.screen_segments = collect(&|polyline| polyline.space == LineSpace::Screen);
+
+        if segments.len() > self.segment_capacity {
+            self.segment_capacity = segments.len().next_power_of_two();
+            self.segment_buffer = Self::create_segment_buffer(device, self.segment_capacity);
+        }
+        if !segments.is_empty() {
+            queue.write_buffer(&self.segment_buffer, 0, bytemuck::cast_slice(&segments));
+        }
+    }
+
+    fn draw<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        camera_bind_group: &'a wgpu::BindGroup,
+        pipelines: &[(&'a wgpu::RenderPipeline, &Range<u32>)],
+    ) {
+        if pipelines.iter().all(|(_, segments)| segments.is_empty()) {
+            return;
+        }
+        render_pass.set_bind_group(0, camera_bind_group, &[]);
+        render_pass.set_bind_group(1, &self.uniform_bind_group, &[]);
+        render_pass.set_bind_group(2, &self.depth_bind_group, &[]);
+        render_pass.set_vertex_buffer(0, self.segment_buffer.slice(..));
+        for (pipeline, segments) in pipelines {
+            if segments.is_empty() {
+                continue;
+            }
+            render_pass.set_pipeline(pipeline);
+            render_pass.draw(0..6, (*segments).clone());
+        }
+    }
+
+    pub fn render_world<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        camera_bind_group: &'a wgpu::BindGroup,
+    ) {
+        self.draw(
+            render_pass,
+            camera_bind_group,
+            &[
+                (
+                    &self.depth_tested_world_pipeline,
+                    &self.depth_tested_world_segments,
+                ),
+                (&self.world_pipeline, &self.world_segments),
+            ],
+        );
+    }
+
+    pub fn render_screen<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        camera_bind_group: &'a wgpu::BindGroup,
+    ) {
+        self.draw(
+            render_pass,
+            camera_bind_group,
+            &[(&self.screen_pipeline, &self.screen_segments)],
+        );
+    }
+}
@@ -1,58 +1,522 @@
+mod actions;
+mod animation;
+mod area_lights;
+mod asset_browser;
+mod assets;
+mod blit;
+mod bloom;
+mod blue_noise;
+mod bookmarks;
+mod bounds;
+mod budgets;
+mod bvh;
+mod camera;
+mod camera_2d;
+mod camera_path;
+mod commands;
+mod compute;
+mod config;
+mod debug;
+mod decals;
+mod depth_of_field;
+mod draco;
+mod editor;
+mod environment;
+mod export;
+mod exposure;
+mod follow;
+mod frame_capture;
+#[cfg(feature = "gamepad")]
+mod gamepad;
+mod gui;
+mod hiz;
+mod import;
+mod input;
+mod irradiance;
+mod isosurface;
+mod layers;
+mod lens_flare;
+mod lighting;
+mod lines;
+mod material;
+mod memory;
+mod meshopt;
+mod model;
+mod motion;
+mod motion_blur;
+mod notifications;
+mod options;
+mod orientation;
+mod particles;
+mod path_tracer;
+#[cfg(feature = "physics")]
+mod physics;
+mod points;
+mod postprocess;
+mod power;
+mod probes;
+mod profiler;
+mod ray_traced_shadows;
+mod readback;
+mod recording;
+mod remote;
+mod render_cameras;
+mod render_targets;
 mod renderer;
+mod resolution;
+mod scene;
+mod settings;
+mod shader_errors;
+mod shader_snippets;
+mod shadow_atlas;
+mod sharpen;
+mod skeletons;
+mod sky;
+mod sprites;
+mod ssao;
+mod streaming;
+mod temporal;
+mod terrain;
+mod text;
 mod texture;
+mod texture_reload;
+mod timeline;
+mod upscale;
+mod velocity;
+mod viewports;
+mod voxels;
+mod water;
+#[cfg(target_arch = "wasm32")]
+mod web;
+mod window_mode;
+mod windows;
 
+use actions::Action;
+use animation::AnimationSystem;
 use anyhow::Result;
-use image::io::Reader;
+use asset_browser::AssetBrowser;
+use assets::{AssetKind, OpenMode};
+use bookmarks::{CameraBookmark, CameraPose, BOOKMARK_SLOTS};
+use clap::Parser;
+use config::Config;
+use debug::DebugAction;
+use editor::Editor;
+#[cfg(feature = "gamepad")]
+use gamepad::GamepadSystem;
+use gui::Gui;
+use input::{CameraMode, Input};
+use instant::Instant;
+use nalgebra_glm as glm;
+use notifications::Notifications;
+use options::Options;
+#[cfg(feature = "physics")]
+use physics::PhysicsSystem;
+use remote::{RemoteCommand, RemoteServer};
 use renderer::Renderer;
-use std::path::Path;
+use scene::Scene;
+use settings::Settings;
+use skeletons::SkeletonSystem;
+use std::path::{Path, PathBuf};
+use streaming::FrameStreamer;
+use texture_reload::TextureWatcher;
+use timeline::Timeline;
+use window_mode::WindowMode;
+use windows::WindowHandle;
 use winit::{
-    dpi::PhysicalSize,
-    event::{ElementState, Event, KeyboardInput, MouseButton, VirtualKeyCode, WindowEvent},
-    event_loop::{ControlFlow, EventLoop},
-    window::{Icon, Window, WindowBuilder},
+    dpi::{PhysicalPosition, PhysicalSize},
+    event::{
+        DeviceEvent, ElementState, Event, KeyboardInput, MouseButton, MouseScrollDelta, Touch,
+        VirtualKeyCode, WindowEvent,
+    },
+    event_loop::{ControlFlow, EventLoop, EventLoopWindowTarget},
+    window::{Window, WindowBuilder, WindowId},
 };
 
+// Browsers and mobile apps have no working directory to keep a config file in
+const PERSISTENT_CONFIG: bool = !cfg!(any(
+    target_arch = "wasm32",
+    target_os = "android",
+    target_os = "ios"
+));
+
+const DEFAULT_WINDOW_DIMENSIONS: [u32; 2] = [800, 600];
+
+// Optional services that let external tools drive and watch the viewer
+struct Remote {
+    server: Option<RemoteServer>,
+    streamer: Option<FrameStreamer>,
+}
+
+impl Remote {
+    fn new(options: &Options) -> Result<Self> {
+        let address = options
+            .remote
+            .clone()
+            .or_else(|| std::env::var("RENDERER_REMOTE_ADDRESS").ok());
+        let server = match address {
+            Some(address) => Some(RemoteServer::start(
+                &address,
+                options.remote_screenshots.clone(),
+            )?),
+            None => None,
+        };
+        let address = options
+            .stream
+            .clone()
+            .or_else(|| std::env::var("RENDERER_STREAM_ADDRESS").ok());
+        let streamer = match address {
+            Some(address) => Some(FrameStreamer::start(&address)?),
+            None => None,
+        };
+        Ok(Self { server, streamer })
+    }
+}
+
+// Renders a number of frames without showing the window, then saves the last one and exits
+struct Screenshot {
+    path: PathBuf,
+    frames: u32,
+}
+
+// Another view of the scene with its own camera, opened from the debug interface
+struct PreviewWindow {
+    window: Window,
+    handle: WindowHandle,
+}
+
+struct App {
+    renderer: Renderer,
+    scene: Scene,
+    input: Input,
+    gui: Gui,
+    remote: Remote,
+    #[cfg(feature = "physics")]
+    physics: PhysicsSystem,
+    #[cfg(feature = "gamepad")]
+    gamepad: GamepadSystem,
+    previews: Vec<PreviewWindow>,
+    screenshot: Option<Screenshot>,
+    asset_browser: AssetBrowser,
+    editor: Editor,
+    animation: AnimationSystem,
+    timeline: Timeline,
+    skeletons: SkeletonSystem,
+    texture_watcher: TextureWatcher,
+    notifications: Notifications,
+    // The environment image last opened from the settings, reopened when they name another
+    environment: Option<PathBuf>,
+    config: Config,
+    // Where the config is saved on exit, if anywhere
+    config_path: Option<PathBuf>,
+    // The settings after the command line and environment were applied to the saved ones
+    launch_settings: Settings,
+    // The window mode the environment asked for, if it did
+    launch_window_mode: Option<WindowMode>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
 fn main() -> Result<()> {
-    let event_loop = EventLoop::new();
+    pollster::block_on(run())
+}
 
-    let image = Reader::open("assets/icon.png".to_string())?
-        .decode()?
-        .into_rgba8();
-    let (width, height) = image.dimensions();
-    let icon = Icon::from_rgba(image.into_raw(), width, height)?;
+// The browser can't be blocked on, so the app runs as a task once the module has loaded
+#[cfg(target_arch = "wasm32")]
+fn main() {
+    std::panic::set_hook(Box::new(console_error_panic_hook::hook));
+    wasm_bindgen_futures::spawn_local(async {
+        if let Err(error) = run().await {
+            web_sys::console::error_1(&format!("Error: {}", error).into());
+        }
+    });
+}
 
-    let mut window = WindowBuilder::new()
-        .with_title("Dragonglass Renderer")
-        .with_inner_size(PhysicalSize::new(800, 600))
-        .with_window_icon(Some(icon))
-        .build(&event_loop)?;
+async fn run() -> Result<()> {
+    let options = Options::parse();
+
+    let config = if PERSISTENT_CONFIG {
+        Config::load(&options.config).unwrap_or_else(|error| {
+            eprintln!("Warning: {}", error);
+            Config::default()
+        })
+    } else {
+        Config::default()
+    };
+
+    // Converting files only needs the scene they're loaded into, not a window
+    if let Some(path) = options.export.as_ref() {
+        let mut scene = Scene::default();
+        for asset in options.assets.iter() {
+            assets::load_asset(&mut scene, asset, &config.settings.import)?;
+        }
+        for error in scene.drain_load_errors() {
+            eprintln!("Warning: {}", error);
+        }
+        return export::export_scene(&scene, path);
+    }
+
+    let event_loop = EventLoop::new();
+    let mut window = create_window(&event_loop, &options, &config)?;
 
     let logical_size = window.inner_size();
     let window_dimensions = [logical_size.width, logical_size.height];
-    let mut renderer = pollster::block_on(Renderer::new(&window, &window_dimensions))?;
+    let backends = options.backend.unwrap_or(renderer::BACKEND);
+    let stencil = options.stencil.unwrap_or(renderer::STENCIL);
+    // Large worlds see far past the near plane, where reversed float depth keeps its precision
+    let reverse_z = options
+        .reverse_z
+        .unwrap_or(renderer::REVERSE_Z || config.settings.rebase_distance > 0.0);
+    let samples = options.msaa.unwrap_or(config.settings.msaa_samples);
+    let mut app = App {
+        renderer: Renderer::new(
+            &window,
+            &window_dimensions,
+            backends,
+            stencil,
+            reverse_z,
+            samples,
+        )
+        .await?,
+        scene: Scene::default(),
+        input: Input::default(),
+        gui: Gui::new(&window),
+        remote: Remote::new(&options)?,
+        #[cfg(feature = "physics")]
+        physics: PhysicsSystem::default(),
+        #[cfg(feature = "gamepad")]
+        gamepad: GamepadSystem::new()?,
+        previews: Vec::new(),
+        screenshot: options.screenshot.clone().map(|path| Screenshot {
+            path,
+            frames: options.frames.max(1),
+        }),
+        asset_browser: AssetBrowser::new(options.asset_directory.clone()),
+        editor: Editor::default(),
+        animation: AnimationSystem::default(),
+        timeline: Timeline::default(),
+        skeletons: SkeletonSystem::default(),
+        texture_watcher: TextureWatcher::default(),
+        notifications: Notifications::default(),
+        environment: None,
+        // Screenshots render with the saved state but leave it as it was
+        config_path: (PERSISTENT_CONFIG && options.screenshot.is_none())
+            .then(|| options.config.clone()),
+        launch_settings: config.settings.clone(),
+        launch_window_mode: None,
+        config,
+    };
 
-    event_loop.run(move |event, _, control_flow| {
+    app.renderer.settings = app.config.settings.clone();
+    app.renderer.settings.msaa_samples = samples;
+    if let Some(environment) = options.environment.clone() {
+        app.renderer.settings.environment = Some(environment);
+    }
+    if let Some(camera) = app.config.camera.as_ref() {
+        app.renderer.camera = camera.clone();
+    }
+    if let Some(bindings) = app.config.bindings.as_ref() {
+        app.input.action_map = bindings.clone().with_missing_defaults();
+    }
+    if let Some(window_mode) = app.config.window_mode {
+        set_window_mode(&window, &mut app.renderer, window_mode);
+    }
+
+    app.renderer.set_scale_factor(window.scale_factor());
+
+    if let Ok(value) = std::env::var("RENDERER_POWER_SAVING") {
+        app.renderer.settings.power_saving = settings::parse_bool(&value)?;
+    }
+
+    // Budgets are listed as `target=milliseconds`, such as `frame=16.6,gpu=12`
+    if let Ok(value) = std::env::var("RENDERER_BUDGETS") {
+        for budget in value.split(',').filter(|budget| !budget.trim().is_empty()) {
+            app.renderer.budgets.register(budget.parse()?);
+        }
+    }
+
+    if let Ok(value) = std::env::var("RENDERER_WINDOW_MODE") {
+        let window_mode = value.parse()?;
+        set_window_mode(&window, &mut app.renderer, window_mode);
+        app.launch_window_mode = Some(window_mode);
+    }
+
+    if let Some(vsync) = options.vsync {
+        app.renderer.settings.vsync = vsync;
+    }
+    app.launch_settings = app.renderer.settings.clone();
+
+    for path in options.assets.iter() {
+        assets::load_asset(&mut app.scene, path, &app.renderer.settings.import)?;
+        app.config.add_recent_file(path);
+    }
+
+    // Opened here rather than on the first frame, which screenshots don't pass through
+    if let Some(path) = app.renderer.settings.environment.clone() {
+        assets::load_asset(&mut app.scene, &path, &app.renderer.settings.import)?;
+        app.environment = Some(path);
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    if let Some(url) = web::asset_url() {
+        let bytes = web::fetch(&url).await?;
+        let import = &app.renderer.settings.import;
+        assets::load_asset_from_memory(&mut app.scene, Path::new(&url), &bytes, import)?;
+    }
+
+    event_loop.run(move |event, target, control_flow| {
         *control_flow = ControlFlow::Poll;
-        if let Err(error) = step(event, control_flow, &mut window, &mut renderer) {
+        if let Err(error) = step(event, target, control_flow, &mut window, &mut app) {
             eprintln!("Error: {}", error);
             *control_flow = ControlFlow::Exit
         }
     });
 }
 
+fn create_window(event_loop: &EventLoop<()>, options: &Options, config: &Config) -> Result<Window> {
+    let [width, height] = config
+        .window_dimensions
+        .unwrap_or(DEFAULT_WINDOW_DIMENSIONS);
+    let builder = WindowBuilder::new()
+        .with_title("Dragonglass Renderer")
+        .with_inner_size(PhysicalSize::new(
+            options.width.unwrap_or(width),
+            options.height.unwrap_or(height),
+        ))
+        .with_visible(options.screenshot.is_none());
+
+    // Pages and Android apps provide their own icons
+    #[cfg(not(any(target_arch = "wasm32", target_os = "android")))]
+    let builder = builder.with_window_icon(Some(load_icon(&options.icon)?));
+
+    let window = builder.build(event_loop)?;
+
+    // The window is a canvas that has to be added to the page
+    #[cfg(target_arch = "wasm32")]
+    web::attach_canvas(&window)?;
+
+    Ok(window)
+}
+
+#[cfg(not(any(target_arch = "wasm32", target_os = "android")))]
+fn load_icon(path: &Path) -> Result<winit::window::Icon> {
+    let image = image::io::Reader::open(assets::asset_path(&path.to_string_lossy())?)?
+        .decode()?
+        .into_rgba8();
+    let (width, height) = image.dimensions();
+    Ok(winit::window::Icon::from_rgba(
+        image.into_raw(),
+        width,
+        height,
+    )?)
+}
+
 fn step(
     event: Event<()>,
+    target: &EventLoopWindowTarget<()>,
     control_flow: &mut ControlFlow,
     window: &mut Window,
-    renderer: &mut Renderer,
+    app: &mut App,
 ) -> Result<()> {
     *control_flow = ControlFlow::Poll;
 
-    let logical_size = window.inner_size();
-    let window_dimensions = [logical_size.width, logical_size.height];
+    // The interface and input only follow the main window
+    if let Event::WindowEvent {
+        ref event,
+        window_id,
+    } = event
+    {
+        if window_id != window.id() {
+            return handle_preview_window_event(
+                window_id,
+                event,
+                &mut app.renderer,
+                &mut app.previews,
+            );
+        }
+    }
+
+    app.gui.handle_event(&event);
+    if app.gui.captures_event(&event) {
+        return Ok(());
+    }
+
+    let App {
+        renderer,
+        scene,
+        input,
+        gui,
+        remote,
+        #[cfg(feature = "physics")]
+        physics,
+        #[cfg(feature = "gamepad")]
+        gamepad,
+        previews,
+        screenshot,
+        asset_browser,
+        editor,
+        animation,
+        timeline,
+        skeletons,
+        texture_watcher,
+        notifications,
+        environment,
+        config,
+        config_path,
+        launch_settings,
+        launch_window_mode,
+    } = app;
 
     match event {
-        Event::MainEventsCleared => handle_main_events_cleared(renderer, &window_dimensions),
+        Event::MainEventsCleared => {
+            if let Some(offset) = renderer.update_origin(scene) {
+                input.translate(&offset);
+            }
+            #[cfg(feature = "physics")]
+            physics.update(
+                scene,
+                renderer.settings.physics_colliders,
+                renderer.frame_step(),
+            );
+            #[cfg(feature = "gamepad")]
+            gamepad.update(input, renderer.settings.gamepad_dead_zone);
+            if let Some(screenshot) = screenshot.as_mut() {
+                return handle_screenshot(control_flow, screenshot, renderer, scene);
+            }
+            handle_main_events_cleared(
+                control_flow,
+                target,
+                window,
+                renderer,
+                scene,
+                input,
+                gui,
+                remote,
+                previews,
+                asset_browser,
+                editor,
+                animation,
+                timeline,
+                skeletons,
+                texture_watcher,
+                notifications,
+                environment,
+                config,
+            )
+        }
+        Event::WindowEvent {
+            event: WindowEvent::DroppedFile(ref path),
+            window_id,
+        } if window_id == window.id() => {
+            open_asset(
+                path,
+                asset_browser.open_mode,
+                renderer,
+                scene,
+                editor,
+                notifications,
+                config,
+            );
+            Ok(())
+        }
         Event::WindowEvent {
             ref event,
             window_id,
@@ -60,31 +524,484 @@ fn step(
             if event == &WindowEvent::CloseRequested {
                 *control_flow = ControlFlow::Exit
             }
-            handle_window_event(event, renderer)
+            handle_window_event(event, window, renderer, input)
+        }
+        Event::DeviceEvent {
+            event: DeviceEvent::MouseMotion { delta },
+            ..
+        } => handle_mouse_motion(delta, input),
+        Event::Suspended => handle_suspended(renderer),
+        Event::Resumed => handle_resumed(window, renderer, previews),
+        Event::LoopDestroyed => handle_loop_destroyed(
+            window,
+            renderer,
+            scene,
+            input,
+            config,
+            config_path.as_deref(),
+            launch_settings,
+            *launch_window_mode,
+        ),
+        _ => Ok(()),
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn handle_main_events_cleared(
+    control_flow: &mut ControlFlow,
+    target: &EventLoopWindowTarget<()>,
+    window: &Window,
+    renderer: &mut Renderer,
+    scene: &mut Scene,
+    input: &mut Input,
+    gui: &mut Gui,
+    remote: &mut Remote,
+    previews: &mut Vec<PreviewWindow>,
+    asset_browser: &mut AssetBrowser,
+    editor: &mut Editor,
+    animation: &mut AnimationSystem,
+    timeline: &mut Timeline,
+    skeletons: &mut SkeletonSystem,
+    texture_watcher: &mut TextureWatcher,
+    notifications: &mut Notifications,
+    environment: &mut Option<PathBuf>,
+    config: &mut Config,
+) -> Result<()> {
+    // Sleep until the next frame is due instead of spinning when the frame rate is limited
+    if let Some(next_frame_time) = renderer.next_frame_time() {
+        if Instant::now() < next_frame_time {
+            *control_flow = ControlFlow::WaitUntil(next_frame_time);
+            return Ok(());
+        }
+    }
+
+    if let Some(server) = remote.server.as_ref() {
+        handle_remote_requests(server, renderer, scene, editor);
+    }
+    let step = renderer.frame_step();
+    if renderer.is_2d() {
+        input.update_camera_2d(&mut renderer.camera_2d);
+    } else {
+        input.update_camera(&mut renderer.camera, scene, step);
+    }
+    handle_actions(window, renderer, scene, input, gui, editor, notifications);
+    animation.update(scene, step);
+    for message in texture_watcher.update(scene, renderer.settings.import.hot_reload) {
+        notifications.push(message);
+    }
+    update_environment(renderer, scene, environment, notifications);
+    for error in scene.drain_load_errors() {
+        eprintln!("Warning: {}", error);
+        notifications.push(error);
+    }
+    if renderer.settings.sky.enabled {
+        sky::update_sun(&mut scene.sun, &renderer.settings.sky);
+    }
+    gui.set_ui_scale(window, renderer.settings.ui_scale);
+    if renderer.settings.stats_overlay {
+        draw_stats(renderer);
+    }
+    notifications.draw(renderer);
+
+    let mut actions = Vec::new();
+    let mut picked_asset = None;
+    let gui_frame = gui.frame(window, |context| {
+        actions = debug::debug_window(context, renderer, scene, &mut input.scripted_camera);
+        debug::shader_error_window(context, renderer);
+        picked_asset = asset_browser.show(context, &config.recent_files);
+        editor.show(context, renderer, scene);
+        timeline.show(context, scene);
+    });
+    skeletons.update(scene, &renderer.settings, editor.selected_joint());
+    // Streams are copied from the frame about to be presented
+    if let Some(streamer) = remote.streamer.as_mut() {
+        if streamer.wants_frame() {
+            renderer.read_frame(streamer.capture());
+        }
+    }
+    renderer.render(scene, &gui_frame)?;
+
+    for alert in renderer.budgets.drain_alerts() {
+        eprintln!("Warning: {}", alert);
+    }
+    for alert in renderer.memory.drain_alerts() {
+        eprintln!("Warning: {}", alert);
+    }
+    for error in renderer.shader_errors.drain_reports() {
+        eprintln!("Error: {}", error);
+    }
+    for message in renderer.recording.update() {
+        notifications.push(message);
+    }
+    // A recorded path stops recording once it ends
+    if input.camera_mode == CameraMode::Scripted
+        && input.scripted_camera.finished()
+        && renderer.recording.is_recording()
+    {
+        toggle_recording(renderer, notifications);
+    }
+
+    for action in actions {
+        match action {
+            DebugAction::SwitchAdapter(index) => switch_adapter(window, renderer, previews, index)?,
+            DebugAction::Follow(model) => input.follow(model),
+            DebugAction::StopFollowing => input.set_camera_mode(CameraMode::Orbit),
+            DebugAction::SetCameraMode(camera_mode) => input.set_camera_mode(camera_mode),
+            DebugAction::GrabCursor => input.set_cursor_grabbed(window, true),
+            DebugAction::OpenWindow => open_preview_window(target, renderer, previews)?,
+            DebugAction::ToggleRecording => toggle_recording(renderer, notifications),
+            DebugAction::GoToBookmark(slot) => go_to_bookmark(renderer, scene, input, slot),
+            DebugAction::Focus => focus(renderer, scene, input),
+            DebugAction::ExportScene => export_scene(scene, notifications),
+            DebugAction::PlayPath => input.play_path(renderer.scene_bounds()),
+            DebugAction::RecordPath => {
+                input.play_path(renderer.scene_bounds());
+                if !renderer.recording.is_recording() {
+                    toggle_recording(renderer, notifications);
+                }
+            }
         }
-        Event::LoopDestroyed => handle_loop_destroyed(renderer),
-        _ => return Ok(()),
     }
+    if let Some(path) = picked_asset {
+        let open_mode = asset_browser.open_mode;
+        open_asset(
+            &path,
+            open_mode,
+            renderer,
+            scene,
+            editor,
+            notifications,
+            config,
+        );
+    }
+    input.end_frame();
+    Ok(())
 }
 
-fn handle_main_events_cleared(renderer: &mut Renderer, window_dimensions: &[u32; 2]) -> Result<()> {
-    renderer.render(window_dimensions)?;
+fn handle_screenshot(
+    control_flow: &mut ControlFlow,
+    screenshot: &mut Screenshot,
+    renderer: &mut Renderer,
+    scene: &Scene,
+) -> Result<()> {
+    let image = renderer.render_offscreen(scene)?;
+    screenshot.frames = screenshot.frames.saturating_sub(1);
+    if screenshot.frames == 0 {
+        image.save(&screenshot.path)?;
+        *control_flow = ControlFlow::Exit;
+    }
     Ok(())
 }
 
-fn handle_loop_destroyed(renderer: &mut Renderer) -> Result<()> {
+#[cfg(not(target_arch = "wasm32"))]
+fn switch_adapter(
+    window: &Window,
+    renderer: &mut Renderer,
+    previews: &[PreviewWindow],
+    index: usize,
+) -> Result<()> {
+    pollster::block_on(renderer.switch_adapter(window, index))?;
+    for preview in previews.iter() {
+        renderer.attach_window(preview.handle, &preview.window)?;
+    }
+    Ok(())
+}
+
+// The browser only exposes the adapter it chooses
+#[cfg(target_arch = "wasm32")]
+fn switch_adapter(
+    _window: &Window,
+    _renderer: &mut Renderer,
+    _previews: &[PreviewWindow],
+    index: usize,
+) -> Result<()> {
+    anyhow::bail!("No adapter exists at index {}!", index)
+}
+
+fn open_preview_window(
+    target: &EventLoopWindowTarget<()>,
+    renderer: &mut Renderer,
+    previews: &mut Vec<PreviewWindow>,
+) -> Result<()> {
+    let window = WindowBuilder::new()
+        .with_title("Preview")
+        .with_inner_size(PhysicalSize::new(480, 360))
+        .build(target)?;
+    let size = window.inner_size();
+    let handle = renderer.add_window(&window, &[size.width, size.height])?;
+    previews.push(PreviewWindow { window, handle });
+    Ok(())
+}
+
+fn handle_preview_window_event(
+    window_id: WindowId,
+    window_event: &WindowEvent,
+    renderer: &mut Renderer,
+    previews: &mut Vec<PreviewWindow>,
+) -> Result<()> {
+    let index = match previews
+        .iter()
+        .position(|preview| preview.window.id() == window_id)
+    {
+        Some(index) => index,
+        None => return Ok(()),
+    };
+    let handle = previews[index].handle;
+    match window_event {
+        WindowEvent::Resized(size) => renderer.resize_window(handle, [size.width, size.height]),
+        WindowEvent::ScaleFactorChanged { new_inner_size, .. } => {
+            renderer.resize_window(handle, [new_inner_size.width, new_inner_size.height])
+        }
+        // The surface goes before the window it was created for
+        WindowEvent::CloseRequested => {
+            renderer.remove_window(handle);
+            previews.remove(index);
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+fn handle_actions(
+    window: &Window,
+    renderer: &mut Renderer,
+    scene: &mut Scene,
+    input: &mut Input,
+    gui: &mut Gui,
+    editor: &mut Editor,
+    notifications: &mut Notifications,
+) {
+    if input.action_pressed(Action::ToggleGui) {
+        gui.visible = !gui.visible;
+    }
+    if input.action_pressed(Action::ToggleCursorGrab) {
+        input.set_cursor_grabbed(window, !input.cursor_grabbed());
+    }
+    if input.action_pressed(Action::ReleaseCursor) {
+        input.set_cursor_grabbed(window, false);
+    }
+    if input.action_pressed(Action::ToggleFullscreen) {
+        set_window_mode(window, renderer, WindowMode::of(window).next());
+    }
+    if input.action_pressed(Action::Toggle2d) {
+        renderer.set_2d(!renderer.is_2d());
+    }
+    if input.action_pressed(Action::Undo) {
+        editor.undo(scene);
+    }
+    if input.action_pressed(Action::Redo) {
+        editor.redo(scene);
+    }
+    if input.action_pressed(Action::ToggleRecording) {
+        toggle_recording(renderer, notifications);
+    }
+    if input.action_pressed(Action::CaptureFrame) {
+        if let Err(error) = renderer.capture_next_frame() {
+            notifications.push(format!("Failed to capture the frame: {}", error));
+        }
+    }
+    handle_bookmarks(renderer, scene, input, notifications);
+    if input.action_pressed(Action::Focus) {
+        focus(renderer, scene, input);
+    }
+    if input.action_pressed(Action::Select) {
+        renderer.selection = renderer.pick(scene, input.cursor_position());
+        if renderer.settings.depth_of_field.click_to_focus {
+            renderer.focus_at(input.cursor_position());
+        }
+    }
+}
+
+// Holding Ctrl saves to a slot instead of going to it, though the number key is down either way
+fn handle_bookmarks(
+    renderer: &mut Renderer,
+    scene: &Scene,
+    input: &mut Input,
+    notifications: &mut Notifications,
+) {
+    for slot in 0..BOOKMARK_SLOTS {
+        if input.action_pressed(Action::SaveBookmark(slot as u8)) {
+            renderer.settings.camera_bookmarks[slot] =
+                Some(CameraBookmark::of(&renderer.camera, scene));
+            notifications.push(format!("Saved camera bookmark {}", slot + 1));
+        } else if input.action_pressed(Action::GoToBookmark(slot as u8)) {
+            go_to_bookmark(renderer, scene, input, slot);
+        }
+    }
+}
+
+// Frames the selected instance, or the whole scene when nothing is selected
+fn focus(renderer: &mut Renderer, scene: &Scene, input: &mut Input) {
+    let framed = renderer.framed_camera(scene);
+    // Orthographic cameras frame by their height, which isn't part of a pose
+    renderer.camera.projection = framed.projection;
+    let duration = renderer.settings.camera_transition;
+    input.transition_to(&mut renderer.camera, CameraPose::of(&framed), duration);
+}
+
+fn go_to_bookmark(renderer: &mut Renderer, scene: &Scene, input: &mut Input, slot: usize) {
+    if let Some(bookmark) = renderer.settings.camera_bookmarks[slot] {
+        let duration = renderer.settings.camera_transition;
+        input.transition_to(&mut renderer.camera, bookmark.pose(scene), duration);
+    }
+}
+
+fn export_scene(scene: &Scene, notifications: &mut Notifications) {
+    let exported = export::next_export_path()
+        .and_then(|path| export::export_scene(scene, &path).map(|_| path));
+    match exported {
+        Ok(path) => notifications.push(format!("Exported the scene to {}", path.display())),
+        Err(error) => notifications.push(format!("Failed to export the scene: {}", error)),
+    }
+}
+
+fn toggle_recording(renderer: &mut Renderer, notifications: &mut Notifications) {
+    match renderer.toggle_recording() {
+        Ok(Some(path)) => notifications.push(format!("Recording to {}", path.display())),
+        Ok(None) => {}
+        Err(error) => notifications.push(format!("Failed to start recording: {}", error)),
+    }
+}
+
+// Not every platform reports a resize when the window changes modes, so the surface and render
+// targets are resized right away as well
+fn set_window_mode(window: &Window, renderer: &mut Renderer, window_mode: WindowMode) {
+    window_mode::set_window_mode(window, window_mode);
+    let size = window.inner_size();
+    renderer.resize([size.width, size.height]);
+}
+
+fn draw_stats(renderer: &mut Renderer) {
+    let frame_time = budgets::milliseconds(renderer.budgets.frame_time());
+    let cpu_time = budgets::milliseconds(renderer.budgets.cpu_time());
+    let stats = format!(
+        "{:.0} fps\nFrame: {:.2}ms\nCPU: {:.2}ms",
+        1000.0 / frame_time.max(0.001),
+        frame_time,
+        cpu_time
+    );
+    renderer.draw_text(
+        &stats,
+        glm::vec2(8.0, 8.0),
+        16.0,
+        glm::vec4(1.0, 1.0, 1.0, 1.0),
+    );
+}
+
+fn handle_remote_requests(
+    remote_server: &RemoteServer,
+    renderer: &mut Renderer,
+    scene: &mut Scene,
+    editor: &mut Editor,
+) {
+    for request in remote_server.requests() {
+        let result =
+            handle_remote_command(remote_server, &request.command, renderer, scene, editor);
+        request.respond(result);
+    }
+}
+
+fn handle_remote_command(
+    remote_server: &RemoteServer,
+    command: &RemoteCommand,
+    renderer: &mut Renderer,
+    scene: &mut Scene,
+    editor: &mut Editor,
+) -> Result<()> {
+    match command {
+        RemoteCommand::LoadAsset(path) => {
+            assets::load_asset(scene, path, &renderer.settings.import)?;
+            editor.history.clear();
+        }
+        RemoteCommand::SetCamera { position, target } => {
+            renderer.camera.position = *position;
+            renderer.camera.target = *target;
+        }
+        RemoteCommand::Screenshot(path) => {
+            let path = remote_server.screenshot_path(path);
+            if let Some(directory) = path.parent() {
+                std::fs::create_dir_all(directory)?;
+            }
+            renderer.capture_frame(scene)?.save(path)?
+        }
+        RemoteCommand::SetSetting { name, value } => renderer.settings.set(name, value)?,
+    }
+    Ok(())
+}
+
+fn handle_suspended(renderer: &mut Renderer) -> Result<()> {
+    renderer.pause();
+    Ok(())
+}
+
+// The window may have been resized or rotated while in the background
+fn handle_resumed(
+    window: &Window,
+    renderer: &mut Renderer,
+    previews: &[PreviewWindow],
+) -> Result<()> {
+    renderer.resume(window);
+    for preview in previews.iter() {
+        renderer.attach_window(preview.handle, &preview.window)?;
+    }
+    let size = window.inner_size();
+    renderer.resize([size.width, size.height]);
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+fn handle_loop_destroyed(
+    window: &Window,
+    renderer: &mut Renderer,
+    scene: &Scene,
+    input: &Input,
+    config: &mut Config,
+    config_path: Option<&Path>,
+    launch_settings: &Settings,
+    launch_window_mode: Option<WindowMode>,
+) -> Result<()> {
+    if let Some(path) = config_path {
+        // Fullscreen windows report the monitor's size, so the windowed size is kept instead
+        let window_mode = WindowMode::of(window);
+        if window_mode == WindowMode::Windowed {
+            let size = window.inner_size();
+            config.window_dimensions = Some([size.width, size.height]);
+        }
+        // Like the settings, the mode from the environment is only kept if it was changed since
+        if launch_window_mode != Some(window_mode) {
+            config.window_mode = Some(window_mode);
+        }
+        // Saved where it is in the world, since the origin starts over next time
+        let mut camera = renderer.camera_3d().clone();
+        camera.translate(scene.origin.cast());
+        config.camera = Some(camera);
+        config.settings = renderer
+            .settings
+            .to_persist(&config.settings, launch_settings);
+        config.bindings = Some(input.action_map.clone());
+        config.save(path)?;
+    }
     renderer.cleanup()?;
     Ok(())
 }
 
-fn handle_window_event(window_event: &WindowEvent, renderer: &mut Renderer) -> Result<()> {
+fn handle_window_event(
+    window_event: &WindowEvent,
+    window: &Window,
+    renderer: &mut Renderer,
+    input: &mut Input,
+) -> Result<()> {
     match window_event {
         WindowEvent::Resized(physical_size) => handle_resize(*physical_size, renderer),
         WindowEvent::ScaleFactorChanged {
-            ref new_inner_size, ..
-        } => handle_scale_factor_changed(new_inner_size, renderer),
-        WindowEvent::DroppedFile(ref path) => handle_file_dropped(path),
-        WindowEvent::MouseInput { button, state, .. } => handle_mouse_input(*button, *state),
+            ref new_inner_size,
+            scale_factor,
+        } => handle_scale_factor_changed(*scale_factor, new_inner_size, renderer),
+        WindowEvent::CursorMoved { position, .. } => handle_cursor_moved(*position, input),
+        WindowEvent::MouseInput { button, state, .. } => handle_mouse_input(*button, *state, input),
+        WindowEvent::MouseWheel { delta, .. } => handle_mouse_wheel(*delta, input),
+        WindowEvent::Focused(false) => handle_focus_lost(window, input),
+        WindowEvent::Touch(touch) => handle_touch(touch, input),
         WindowEvent::KeyboardInput {
             input:
                 KeyboardInput {
@@ -93,7 +1010,7 @@ fn handle_window_event(window_event: &WindowEvent, renderer: &mut Renderer) -> R
                     ..
                 },
             ..
-        } => handle_keyboard_input(*state, *keycode),
+        } => handle_keyboard_input(*state, *keycode, input),
         _ => Ok(()),
     }
 }
@@ -104,25 +1021,108 @@ fn handle_resize(physical_size: PhysicalSize<u32>, renderer: &mut Renderer) -> R
 }
 
 fn handle_scale_factor_changed(
+    scale_factor: f64,
     new_inner_size: &&mut PhysicalSize<u32>,
     renderer: &mut Renderer,
 ) -> Result<()> {
+    renderer.set_scale_factor(scale_factor);
     let size = **new_inner_size;
     renderer.resize([size.width, size.height]);
     Ok(())
 }
 
-fn handle_file_dropped(path: &Path) -> Result<()> {
-    // TODO
+// A file that fails to open is reported on screen without closing the viewer
+// Opens the environment the settings name whenever it changes, or removes it once they name none
+fn update_environment(
+    renderer: &Renderer,
+    scene: &mut Scene,
+    environment: &mut Option<PathBuf>,
+    notifications: &mut Notifications,
+) {
+    if *environment == renderer.settings.environment {
+        return;
+    }
+    *environment = renderer.settings.environment.clone();
+    match environment.as_ref() {
+        Some(path) => {
+            if let Err(error) = assets::load_asset(scene, path, &renderer.settings.import) {
+                notifications.push(format!("Failed to open {}: {}", path.display(), error));
+            }
+        }
+        None => scene.set_environment(None),
+    }
+}
+
+fn open_asset(
+    path: &Path,
+    open_mode: OpenMode,
+    renderer: &mut Renderer,
+    scene: &mut Scene,
+    editor: &mut Editor,
+    notifications: &mut Notifications,
+    config: &mut Config,
+) {
+    // Kept in the settings, which open it on the next frame and remember it across runs
+    if AssetKind::of(path) == Some(AssetKind::Environment) {
+        renderer.settings.environment = Some(path.to_path_buf());
+        config.add_recent_file(path);
+        return;
+    }
+    let import = &renderer.settings.import;
+    match assets::open_asset(scene, path, open_mode, &mut renderer.selection, import) {
+        Ok(()) => {
+            // Edits in the history refer to models by index, so it can't reach past a change
+            // made outside of it
+            if open_mode == OpenMode::Replace {
+                editor.reset();
+            } else {
+                editor.history.clear();
+            }
+            config.add_recent_file(path);
+        }
+        Err(error) => notifications.push(format!("Failed to open {}: {}", path.display(), error)),
+    }
+}
+
+fn handle_cursor_moved(position: PhysicalPosition<f64>, input: &mut Input) -> Result<()> {
+    input.handle_cursor_moved(glm::vec2(position.x as f32, position.y as f32));
+    Ok(())
+}
+
+fn handle_mouse_input(
+    button: MouseButton,
+    button_state: ElementState,
+    input: &mut Input,
+) -> Result<()> {
+    input.handle_mouse_input(button_state, button);
+    Ok(())
+}
+
+fn handle_mouse_wheel(delta: MouseScrollDelta, input: &mut Input) -> Result<()> {
+    input.handle_mouse_wheel(delta);
     Ok(())
 }
 
-fn handle_mouse_input(button: MouseButton, button_state: ElementState) -> Result<()> {
-    // TODO
+fn handle_focus_lost(window: &Window, input: &mut Input) -> Result<()> {
+    input.handle_focus_lost(window);
     Ok(())
 }
 
-fn handle_keyboard_input(keystate: ElementState, keycode: VirtualKeyCode) -> Result<()> {
-    // TODO
+fn handle_mouse_motion((x, y): (f64, f64), input: &mut Input) -> Result<()> {
+    input.handle_mouse_motion(glm::vec2(x as f32, y as f32));
+    Ok(())
+}
+
+fn handle_touch(touch: &Touch, input: &mut Input) -> Result<()> {
+    input.handle_touch(touch);
+    Ok(())
+}
+
+fn handle_keyboard_input(
+    keystate: ElementState,
+    keycode: VirtualKeyCode,
+    input: &mut Input,
+) -> Result<()> {
+    input.handle_keyboard_input(keystate, keycode);
     Ok(())
 }
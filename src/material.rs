@@ -0,0 +1,505 @@
+use anyhow::{bail, Result};
+use nalgebra_glm as glm;
+use std::{path::PathBuf, str::FromStr};
+
+// Luminance of one unit of scene color, which is displayed as white at an exposure of one
+pub const PAPER_WHITE_NITS: f32 = 100.0;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum AlphaMode {
+    Opaque,
+    // Fragments below the alpha cutoff are discarded
+    Mask,
+    Blend,
+    // Drawn with opaque surfaces, discarding fragments against blue noise so they need no sorting
+    Dithered,
+}
+
+// How baked ambient occlusion is combined with screen space ambient occlusion
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum OcclusionBlend {
+    Multiply,
+    // Keeps the darker of the two so surfaces with baked occlusion aren't darkened twice
+    Min,
+}
+
+impl FromStr for OcclusionBlend {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        Ok(match value.trim() {
+            "multiply" => Self::Multiply,
+            "min" => Self::Min,
+            _ => bail!(
+                "Unknown occlusion blend '{}', expected one of: multiply, min",
+                value
+            ),
+        })
+    }
+}
+
+// How a material responds to light
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ShadingModel {
+    Pbr,
+    // Displays the base color as is, ignoring lights
+    Unlit,
+    // Lighting is quantized into flat bands of color
+    Toon,
+}
+
+impl FromStr for ShadingModel {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        Ok(match value.trim() {
+            "pbr" => Self::Pbr,
+            "unlit" => Self::Unlit,
+            "toon" => Self::Toon,
+            _ => bail!(
+                "Unknown shading model '{}', expected one of: pbr, unlit, toon",
+                value
+            ),
+        })
+    }
+}
+
+// Maps texture coordinates onto a texture, applied as scale, then rotation, then offset
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct TextureTransform {
+    pub offset: glm::Vec2,
+    // Radians counter clockwise
+    pub rotation: f32,
+    pub scale: glm::Vec2,
+    // The texture coordinate set the texture is sampled with
+    pub tex_coord: u32,
+}
+
+impl Default for TextureTransform {
+    fn default() -> Self {
+        Self {
+            offset: glm::Vec2::zeros(),
+            rotation: 0.0,
+            scale: glm::vec2(1.0, 1.0),
+            tex_coord: 0,
+        }
+    }
+}
+
+impl TextureTransform {
+    pub fn matrix(&self) -> glm::Mat3 {
+        let (sin, cos) = self.rotation.sin_cos();
+        let translation = glm::mat3(
+            1.0,
+            0.0,
+            self.offset.x,
+            0.0,
+            1.0,
+            self.offset.y,
+            0.0,
+            0.0,
+            1.0,
+        );
+        let rotation = glm::mat3(cos, sin, 0.0, -sin, cos, 0.0, 0.0, 0.0, 1.0);
+        let scale = glm::mat3(
+            self.scale.x,
+            0.0,
+            0.0,
+            0.0,
+            self.scale.y,
+            0.0,
+            0.0,
+            0.0,
+            1.0,
+        );
+        translation * rotation * scale
+    }
+}
+
+// One transform for each texture of a material
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct TextureTransforms {
+    pub base_color: TextureTransform,
+    pub metallic_roughness: TextureTransform,
+    pub normal: TextureTransform,
+    pub occlusion: TextureTransform,
+    pub emissive: TextureTransform,
+    pub clearcoat: TextureTransform,
+    pub clearcoat_normal: TextureTransform,
+    pub transmission: TextureTransform,
+    pub sheen: TextureTransform,
+    pub specular: TextureTransform,
+    pub lightmap: TextureTransform,
+}
+
+impl Default for TextureTransforms {
+    fn default() -> Self {
+        Self {
+            base_color: TextureTransform::default(),
+            metallic_roughness: TextureTransform::default(),
+            normal: TextureTransform::default(),
+            occlusion: TextureTransform::default(),
+            emissive: TextureTransform::default(),
+            clearcoat: TextureTransform::default(),
+            clearcoat_normal: TextureTransform::default(),
+            transmission: TextureTransform::default(),
+            sheen: TextureTransform::default(),
+            specular: TextureTransform::default(),
+            // Lightmaps are unwrapped onto their own texture coordinates
+            lightmap: TextureTransform {
+                tex_coord: 1,
+                ..Default::default()
+            },
+        }
+    }
+}
+
+impl TextureTransforms {
+    // In the order the material shader indexes them
+    fn all(&self) -> [&TextureTransform; MATERIAL_TEXTURE_COUNT] {
+        [
+            &self.base_color,
+            &self.metallic_roughness,
+            &self.normal,
+            &self.occlusion,
+            &self.emissive,
+            &self.clearcoat,
+            &self.clearcoat_normal,
+            &self.transmission,
+            &self.sheen,
+            &self.specular,
+            &self.lightmap,
+        ]
+    }
+}
+
+pub const MATERIAL_TEXTURE_COUNT: usize = 11;
+
+// Whether each of a material's textures holds color, which is stored as sRGB, rather than data
+// such as normals, which is stored linearly
+pub const SRGB_TEXTURES: [bool; MATERIAL_TEXTURE_COUNT] = [
+    true, false, false, false, true, false, false, false, true, true, true,
+];
+
+pub const MATERIAL_TEXTURE_NAMES: [&str; MATERIAL_TEXTURE_COUNT] = [
+    "Base color",
+    "Metallic roughness",
+    "Normal",
+    "Occlusion",
+    "Emissive",
+    "Clearcoat",
+    "Clearcoat normal",
+    "Transmission",
+    "Sheen",
+    "Specular",
+    "Lightmap",
+];
+
+// Refers to no image, so the missing texture placeholder is drawn in its place
+pub const MISSING_IMAGE: usize = usize::MAX;
+
+// Textures refer to images by their index in the model
+#[derive(Debug, Clone, PartialEq)]
+pub struct Material {
+    pub name: Option<String>,
+    pub base_color_factor: glm::Vec4,
+    pub base_color_texture: Option<usize>,
+    pub metallic_factor: f32,
+    pub roughness_factor: f32,
+    // Roughness is read from the green channel and metalness from the blue channel
+    pub metallic_roughness_texture: Option<usize>,
+    pub normal_texture: Option<usize>,
+    pub normal_scale: f32,
+    // Baked ambient occlusion is read from the red channel
+    pub occlusion_texture: Option<usize>,
+    pub occlusion_strength: f32,
+    pub emissive_factor: glm::Vec3,
+    pub emissive_texture: Option<usize>,
+    // Luminance in nits of a white emissive texel, anything above paper white blooms
+    pub emissive_luminance: f32,
+    pub alpha_mode: AlphaMode,
+    pub alpha_cutoff: f32,
+    // Back faces are drawn too, shaded with their normals flipped
+    pub double_sided: bool,
+    // A thin clear layer over the base material, such as car paint
+    pub clearcoat_factor: f32,
+    pub clearcoat_roughness_factor: f32,
+    // Clearcoat is read from the red channel and its roughness from the green channel
+    pub clearcoat_texture: Option<usize>,
+    pub clearcoat_normal_texture: Option<usize>,
+    pub clearcoat_normal_scale: f32,
+    // Fraction of light passing through the surface rather than being diffusely reflected
+    pub transmission_factor: f32,
+    // Transmission is read from the red channel
+    pub transmission_texture: Option<usize>,
+    // Back scattering from fibers at grazing angles, such as cloth
+    pub sheen_color_factor: glm::Vec3,
+    pub sheen_roughness_factor: f32,
+    // Sheen color is read from the color channels and its roughness from the alpha channel
+    pub sheen_texture: Option<usize>,
+    // Strength and tint of the specular reflection of non-metals
+    pub specular_factor: f32,
+    pub specular_color_factor: glm::Vec3,
+    // Specular color is read from the color channels and its strength from the alpha channel
+    pub specular_texture: Option<usize>,
+    pub ior: f32,
+    // Indirect light baked by tools such as Blender, replacing the ambient light when present
+    pub lightmap_texture: Option<usize>,
+    // Scales the baked light, like an exposure
+    pub lightmap_intensity: f32,
+    pub texture_transforms: TextureTransforms,
+    pub shading_model: ShadingModel,
+    // Number of flat bands toon shading divides the light into
+    pub toon_bands: u32,
+    // Width in pixels of the inverted hull drawn around the surface, zero for none
+    pub outline_width: f32,
+    pub outline_color: glm::Vec3,
+    // How much screen space ambient occlusion darkens this material, zero to ignore it
+    pub ssao_strength: f32,
+    pub occlusion_blend: OcclusionBlend,
+    // A render camera whose image replaces the base color and emissive textures, for monitors,
+    // mirrors and portals
+    pub render_camera: Option<usize>,
+    // The render camera is a reflection of the scene read where the surface is on screen and
+    // reflected like the environment, keeping the base color texture, see RenderCameraView
+    pub planar_reflection: bool,
+    // A WGSL file with a surface function replacing the model shader's own, see SurfaceInput in
+    // model.wgsl. Materials with a shader aren't packed
+    pub shader: Option<PathBuf>,
+}
+
+impl Material {
+    // The images of the material's textures, in the order the material shader indexes them
+    pub fn textures(&self) -> [Option<usize>; MATERIAL_TEXTURE_COUNT] {
+        [
+            self.base_color_texture,
+            self.metallic_roughness_texture,
+            self.normal_texture,
+            self.occlusion_texture,
+            self.emissive_texture,
+            self.clearcoat_texture,
+            self.clearcoat_normal_texture,
+            self.transmission_texture,
+            self.sheen_texture,
+            self.specular_texture,
+            self.lightmap_texture,
+        ]
+    }
+
+    pub fn textures_mut(&mut self) -> [&mut Option<usize>; MATERIAL_TEXTURE_COUNT] {
+        [
+            &mut self.base_color_texture,
+            &mut self.metallic_roughness_texture,
+            &mut self.normal_texture,
+            &mut self.occlusion_texture,
+            &mut self.emissive_texture,
+            &mut self.clearcoat_texture,
+            &mut self.clearcoat_normal_texture,
+            &mut self.transmission_texture,
+            &mut self.sheen_texture,
+            &mut self.specular_texture,
+            &mut self.lightmap_texture,
+        ]
+    }
+
+    // Drawn after the opaque scene so the light passing through can be read back
+    pub fn is_transmissive(&self) -> bool {
+        self.transmission_factor > 0.0 && self.shading_model != ShadingModel::Unlit
+    }
+
+    pub fn has_outline(&self) -> bool {
+        self.outline_width > 0.0
+    }
+
+    // Drawn by primitives referring to a material the model doesn't have
+    pub fn error() -> Self {
+        Self {
+            name: Some("Error".to_string()),
+            base_color_texture: Some(MISSING_IMAGE),
+            metallic_factor: 0.0,
+            roughness_factor: 1.0,
+            emissive_factor: glm::vec3(0.5, 0.5, 0.5),
+            emissive_texture: Some(MISSING_IMAGE),
+            double_sided: true,
+            ..Default::default()
+        }
+    }
+}
+
+impl Default for Material {
+    fn default() -> Self {
+        Self {
+            name: None,
+            base_color_factor: glm::vec4(1.0, 1.0, 1.0, 1.0),
+            base_color_texture: None,
+            metallic_factor: 1.0,
+            roughness_factor: 1.0,
+            metallic_roughness_texture: None,
+            normal_texture: None,
+            normal_scale: 1.0,
+            occlusion_texture: None,
+            occlusion_strength: 1.0,
+            emissive_factor: glm::Vec3::zeros(),
+            emissive_texture: None,
+            emissive_luminance: PAPER_WHITE_NITS,
+            alpha_mode: AlphaMode::Opaque,
+            alpha_cutoff: 0.5,
+            double_sided: false,
+            clearcoat_factor: 0.0,
+            clearcoat_roughness_factor: 0.0,
+            clearcoat_texture: None,
+            clearcoat_normal_texture: None,
+            clearcoat_normal_scale: 1.0,
+            transmission_factor: 0.0,
+            transmission_texture: None,
+            sheen_color_factor: glm::Vec3::zeros(),
+            sheen_roughness_factor: 0.0,
+            sheen_texture: None,
+            specular_factor: 1.0,
+            specular_color_factor: glm::vec3(1.0, 1.0, 1.0),
+            specular_texture: None,
+            ior: 1.5,
+            lightmap_texture: None,
+            lightmap_intensity: 1.0,
+            texture_transforms: TextureTransforms::default(),
+            shading_model: ShadingModel::Pbr,
+            toon_bands: 3,
+            outline_width: 0.0,
+            outline_color: glm::Vec3::zeros(),
+            ssao_strength: 1.0,
+            occlusion_blend: OcclusionBlend::Multiply,
+            render_camera: None,
+            planar_reflection: false,
+            shader: None,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Default, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct TextureTransformUniform {
+    // Columns of the rotation and scale
+    matrix: [f32; 4],
+    offset: [f32; 2],
+    tex_coord: u32,
+    _padding: u32,
+}
+
+impl TextureTransformUniform {
+    fn new(transform: &TextureTransform) -> Self {
+        let matrix = transform.matrix();
+        Self {
+            matrix: [matrix.m11, matrix.m21, matrix.m12, matrix.m22],
+            offset: [matrix.m13, matrix.m23],
+            tex_coord: transform.tex_coord,
+            _padding: 0,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Default, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct MaterialUniform {
+    base_color_factor: [f32; 4],
+    // w: emissive strength in units of scene color
+    emissive_factor: [f32; 4],
+    metallic_factor: f32,
+    roughness_factor: f32,
+    normal_scale: f32,
+    occlusion_strength: f32,
+    ssao_strength: f32,
+    occlusion_blend: u32,
+    alpha_mode: u32,
+    alpha_cutoff: f32,
+    // w: sheen roughness
+    sheen_color_factor: [f32; 4],
+    // w: specular strength
+    specular_color_factor: [f32; 4],
+    clearcoat_factor: f32,
+    clearcoat_roughness_factor: f32,
+    clearcoat_normal_scale: f32,
+    transmission_factor: f32,
+    ior: f32,
+    shading_model: u32,
+    toon_bands: u32,
+    outline_width: f32,
+    outline_color: [f32; 4],
+    texture_transforms: [TextureTransformUniform; MATERIAL_TEXTURE_COUNT],
+    lightmap_intensity: f32,
+    planar_reflection: u32,
+    _padding: [u32; 2],
+}
+
+impl MaterialUniform {
+    pub fn new(material: &Material) -> Self {
+        Self {
+            base_color_factor: material.base_color_factor.into(),
+            emissive_factor: [
+                material.emissive_factor.x,
+                material.emissive_factor.y,
+                material.emissive_factor.z,
+                material.emissive_luminance / PAPER_WHITE_NITS,
+            ],
+            metallic_factor: material.metallic_factor,
+            roughness_factor: material.roughness_factor,
+            normal_scale: material.normal_scale,
+            // Without a texture there is no baked occlusion to apply
+            occlusion_strength: if material.occlusion_texture.is_some() {
+                material.occlusion_strength
+            } else {
+                0.0
+            },
+            ssao_strength: material.ssao_strength,
+            occlusion_blend: match material.occlusion_blend {
+                OcclusionBlend::Multiply => 0,
+                OcclusionBlend::Min => 1,
+            },
+            alpha_mode: match material.alpha_mode {
+                AlphaMode::Opaque => 0,
+                AlphaMode::Mask => 1,
+                AlphaMode::Blend => 2,
+                AlphaMode::Dithered => 3,
+            },
+            alpha_cutoff: material.alpha_cutoff,
+            sheen_color_factor: [
+                material.sheen_color_factor.x,
+                material.sheen_color_factor.y,
+                material.sheen_color_factor.z,
+                material.sheen_roughness_factor,
+            ],
+            specular_color_factor: [
+                material.specular_color_factor.x,
+                material.specular_color_factor.y,
+                material.specular_color_factor.z,
+                material.specular_factor,
+            ],
+            clearcoat_factor: material.clearcoat_factor,
+            clearcoat_roughness_factor: material.clearcoat_roughness_factor,
+            clearcoat_normal_scale: material.clearcoat_normal_scale,
+            transmission_factor: material.transmission_factor,
+            // An index of refraction of one would make dielectrics entirely unreflective
+            ior: material.ior.max(1.0),
+            shading_model: match material.shading_model {
+                ShadingModel::Pbr => 0,
+                ShadingModel::Unlit => 1,
+                ShadingModel::Toon => 2,
+            },
+            toon_bands: material.toon_bands.max(1),
+            outline_width: material.outline_width,
+            outline_color: glm::vec3_to_vec4(&material.outline_color).into(),
+            texture_transforms: material
+                .texture_transforms
+                .all()
+                .map(TextureTransformUniform::new),
+            // Zero leaves surfaces without a lightmap to the ambient light
+            lightmap_intensity: if material.lightmap_texture.is_some() {
+                material.lightmap_intensity.max(0.0)
+            } else {
+                0.0
+            },
+            planar_reflection: (material.planar_reflection && material.render_camera.is_some())
+                as u32,
+            _padding: [0; 2],
+        }
+    }
+}
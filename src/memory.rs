@@ -0,0 +1,348 @@
+use instant::Instant;
+use std::{
+    fmt,
+    ops::Deref,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+use wgpu::util::DeviceExt;
+
+// Usage that stays over the budget is reported again at most this often
+const ALERT_INTERVAL: Duration = Duration::from_secs(10);
+// Usage is warned about once it gets this close to a limit
+const WARNING_THRESHOLD: f64 = 0.9;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum MemoryCategory {
+    // Vertex, index and instance data, along with anything built from it such as BVHs
+    Meshes,
+    // Images loaded from assets
+    Textures,
+    // Textures and buffers that passes render or compute into
+    Targets,
+    // Uniform, staging and readback buffers
+    Transient,
+}
+
+static ALLOCATED: [AtomicU64; 4] = [
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+];
+
+// Bytes allocated by the renderer in each category, which only estimates what the driver uses
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct MemoryStats {
+    pub meshes: u64,
+    pub textures: u64,
+    pub targets: u64,
+    pub transient: u64,
+}
+
+impl MemoryStats {
+    pub fn total(&self) -> u64 {
+        self.meshes + self.textures + self.targets + self.transient
+    }
+}
+
+impl fmt::Display for MemoryStats {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            formatter,
+            "{:.1}MB (meshes: {:.1}MB, textures: {:.1}MB, targets: {:.1}MB, transient: {:.1}MB)",
+            megabytes(self.total()),
+            megabytes(self.meshes),
+            megabytes(self.textures),
+            megabytes(self.targets),
+            megabytes(self.transient)
+        )
+    }
+}
+
+pub fn megabytes(bytes: u64) -> f64 {
+    bytes as f64 / (1024.0 * 1024.0)
+}
+
+pub fn stats() -> MemoryStats {
+    let allocated = |category: MemoryCategory| ALLOCATED[category as usize].load(Ordering::Relaxed);
+    MemoryStats {
+        meshes: allocated(MemoryCategory::Meshes),
+        textures: allocated(MemoryCategory::Textures),
+        targets: allocated(MemoryCategory::Targets),
+        transient: allocated(MemoryCategory::Transient),
+    }
+}
+
+// A buffer or texture that counts towards the memory stats until it's dropped
+pub struct Tracked<T> {
+    resource: T,
+    category: MemoryCategory,
+    size: u64,
+}
+
+impl<T> Tracked<T> {
+    fn new(resource: T, category: MemoryCategory, size: u64) -> Self {
+        ALLOCATED[category as usize].fetch_add(size, Ordering::Relaxed);
+        Self {
+            resource,
+            category,
+            size,
+        }
+    }
+}
+
+impl<T> Deref for Tracked<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.resource
+    }
+}
+
+impl<T> Drop for Tracked<T> {
+    fn drop(&mut self) {
+        ALLOCATED[self.category as usize].fetch_sub(self.size, Ordering::Relaxed);
+    }
+}
+
+pub fn create_buffer(
+    device: &wgpu::Device,
+    category: MemoryCategory,
+    desc: &wgpu::BufferDescriptor,
+) -> Tracked<wgpu::Buffer> {
+    check_buffer_limits(device, desc.label, desc.size, desc.usage);
+    Tracked::new(device.create_buffer(desc), category, desc.size)
+}
+
+pub fn create_buffer_init(
+    device: &wgpu::Device,
+    category: MemoryCategory,
+    desc: &wgpu::util::BufferInitDescriptor,
+) -> Tracked<wgpu::Buffer> {
+    let size = desc.contents.len() as u64;
+    check_buffer_limits(device, desc.label, size, desc.usage);
+    Tracked::new(device.create_buffer_init(desc), category, size)
+}
+
+pub fn create_texture(
+    device: &wgpu::Device,
+    category: MemoryCategory,
+    desc: &wgpu::TextureDescriptor,
+) -> Tracked<wgpu::Texture> {
+    check_texture_limits(device, desc);
+    Tracked::new(device.create_texture(desc), category, texture_size(desc))
+}
+
+pub fn create_texture_with_data(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    category: MemoryCategory,
+    desc: &wgpu::TextureDescriptor,
+    data: &[u8],
+) -> Tracked<wgpu::Texture> {
+    check_texture_limits(device, desc);
+    let texture = device.create_texture_with_data(queue, desc, data);
+    Tracked::new(texture, category, texture_size(desc))
+}
+
+// Every mip level of every layer and sample, in whole blocks for compressed formats
+fn texture_size(desc: &wgpu::TextureDescriptor) -> u64 {
+    let info = desc.format.describe();
+    let (block_width, block_height) = (
+        info.block_dimensions.0 as u32,
+        info.block_dimensions.1 as u32,
+    );
+    (0..desc.mip_level_count)
+        .map(|level| {
+            let size = desc
+                .size
+                .mip_level_size(level, desc.dimension == wgpu::TextureDimension::D3);
+            let blocks = size.width.div_ceil(block_width) as u64
+                * size.height.div_ceil(block_height) as u64
+                * size.depth_or_array_layers as u64;
+            blocks * info.block_size as u64
+        })
+        .sum::<u64>()
+        * desc.sample_count as u64
+}
+
+// Layouts aren't memory, but are checked against the limits here along with what's bound to them
+pub fn create_bind_group_layout(
+    device: &wgpu::Device,
+    desc: &wgpu::BindGroupLayoutDescriptor,
+) -> wgpu::BindGroupLayout {
+    check_bind_group_layout_limits(device, desc);
+    device.create_bind_group_layout(desc)
+}
+
+pub fn create_pipeline_layout(
+    device: &wgpu::Device,
+    desc: &wgpu::PipelineLayoutDescriptor,
+) -> wgpu::PipelineLayout {
+    let limit = device.limits().max_bind_groups;
+    if desc.bind_group_layouts.len() as u32 > limit {
+        eprintln!(
+            "Warning: {} uses {} bind groups, past the limit of {}",
+            desc.label.unwrap_or("A pipeline layout"),
+            desc.bind_group_layouts.len(),
+            limit
+        );
+    }
+    device.create_pipeline_layout(desc)
+}
+
+// Storage buffers can't be bound past the adapter's limit, which large scans run into first
+fn check_buffer_limits(
+    device: &wgpu::Device,
+    label: Option<&str>,
+    size: u64,
+    usage: wgpu::BufferUsages,
+) {
+    let limits = device.limits();
+    let label = label.unwrap_or("A buffer");
+    if usage.contains(wgpu::BufferUsages::STORAGE) {
+        warn_near_limit(
+            label,
+            size,
+            limits.max_storage_buffer_binding_size,
+            "storage buffer",
+        );
+    }
+    if usage.contains(wgpu::BufferUsages::UNIFORM) {
+        warn_near_limit(
+            label,
+            size,
+            limits.max_uniform_buffer_binding_size,
+            "uniform buffer",
+        );
+    }
+}
+
+fn warn_near_limit(label: &str, size: u64, limit: u32, kind: &str) {
+    let limit = limit as u64;
+    if size as f64 > limit as f64 * WARNING_THRESHOLD {
+        eprintln!(
+            "Warning: {} is {:.1}MB, close to the {:.1}MB {} limit",
+            label,
+            megabytes(size),
+            megabytes(limit),
+            kind
+        );
+    }
+}
+
+// Huge heightmaps and environment images are the likeliest to go past the largest dimension
+fn check_texture_limits(device: &wgpu::Device, desc: &wgpu::TextureDescriptor) {
+    let limits = device.limits();
+    let size = desc.size;
+    let (dimension, limit, layers) = match desc.dimension {
+        wgpu::TextureDimension::D1 => (size.width, limits.max_texture_dimension_1d, 1),
+        wgpu::TextureDimension::D2 => (
+            size.width.max(size.height),
+            limits.max_texture_dimension_2d,
+            size.depth_or_array_layers,
+        ),
+        wgpu::TextureDimension::D3 => (
+            size.width.max(size.height).max(size.depth_or_array_layers),
+            limits.max_texture_dimension_3d,
+            1,
+        ),
+    };
+    let label = desc.label.unwrap_or("A texture");
+    if dimension as f64 > limit as f64 * WARNING_THRESHOLD {
+        eprintln!(
+            "Warning: {} is {} texels across, close to the limit of {}",
+            label, dimension, limit
+        );
+    }
+    if layers > limits.max_texture_array_layers {
+        eprintln!(
+            "Warning: {} has {} layers, past the limit of {}",
+            label, layers, limits.max_texture_array_layers
+        );
+    }
+}
+
+// Counts are only warned about past their limits, since pipelines are built to use them fully.
+// Storage buffers add up across a pipeline's bind groups, so this only catches a layout that's
+// over the limit on its own
+fn check_bind_group_layout_limits(device: &wgpu::Device, desc: &wgpu::BindGroupLayoutDescriptor) {
+    let limit = device.limits().max_storage_buffers_per_shader_stage;
+    for (stage, name) in [
+        (wgpu::ShaderStages::VERTEX, "vertex"),
+        (wgpu::ShaderStages::FRAGMENT, "fragment"),
+        (wgpu::ShaderStages::COMPUTE, "compute"),
+    ] {
+        let storage_buffers = desc
+            .entries
+            .iter()
+            .filter(|entry| entry.visibility.contains(stage))
+            .filter(|entry| {
+                matches!(
+                    entry.ty,
+                    wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { .. },
+                        ..
+                    }
+                )
+            })
+            .map(|entry| entry.count.map_or(1, |count| count.get()))
+            .sum::<u32>();
+        if storage_buffers > limit {
+            eprintln!(
+                "Warning: {} binds {} storage buffers in the {} stage, past the limit of {}",
+                desc.label.unwrap_or("A bind group layout"),
+                storage_buffers,
+                name,
+                limit
+            );
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MemoryAlert {
+    pub stats: MemoryStats,
+    pub budget: u64,
+}
+
+impl fmt::Display for MemoryAlert {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            formatter,
+            "GPU memory is approaching the {:.1}MB budget: {}",
+            megabytes(self.budget),
+            self.stats
+        )
+    }
+}
+
+#[derive(Default)]
+pub struct MemoryMonitor {
+    last_alert: Option<Instant>,
+    alerts: Vec<MemoryAlert>,
+}
+
+impl MemoryMonitor {
+    // Alerts raised since the last call
+    pub fn drain_alerts(&mut self) -> Vec<MemoryAlert> {
+        std::mem::take(&mut self.alerts)
+    }
+
+    // A budget of zero is never exceeded
+    pub fn check(&mut self, budget: u64) {
+        let stats = stats();
+        if budget == 0 || (stats.total() as f64) < budget as f64 * WARNING_THRESHOLD {
+            self.last_alert = None;
+            return;
+        }
+        let due = self
+            .last_alert
+            .is_none_or(|last_alert| last_alert.elapsed() >= ALERT_INTERVAL);
+        if due {
+            self.last_alert = Some(Instant::now());
+            self.alerts.push(MemoryAlert { stats, budget });
+        }
+    }
+}
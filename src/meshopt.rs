@@ -0,0 +1,664 @@
+use anyhow::{bail, ensure, Context, Result};
+use std::str::FromStr;
+
+// Decoders for buffer views compressed with EXT_meshopt_compression
+
+const VERTEX_HEADER: u8 = 0xa0;
+const INDEX_HEADER: u8 = 0xe0;
+const SEQUENCE_HEADER: u8 = 0xd0;
+
+const BYTE_GROUP_SIZE: usize = 16;
+const VERTEX_BLOCK_SIZE_BYTES: usize = 8192;
+const VERTEX_BLOCK_MAX_SIZE: usize = 256;
+const TAIL_MAX_SIZE: usize = 32;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Mode {
+    Attributes,
+    Triangles,
+    Indices,
+}
+
+impl FromStr for Mode {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        Ok(match value {
+            "ATTRIBUTES" => Self::Attributes,
+            "TRIANGLES" => Self::Triangles,
+            "INDICES" => Self::Indices,
+            _ => bail!(
+                "Unknown meshopt mode '{}', expected one of: ATTRIBUTES, TRIANGLES, INDICES",
+                value
+            ),
+        })
+    }
+}
+
+// Applied to attributes after decoding to restore values quantized by the encoder
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Filter {
+    None,
+    Octahedral,
+    Quaternion,
+    Exponential,
+}
+
+impl FromStr for Filter {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        Ok(match value {
+            "NONE" => Self::None,
+            "OCTAHEDRAL" => Self::Octahedral,
+            "QUATERNION" => Self::Quaternion,
+            "EXPONENTIAL" => Self::Exponential,
+            _ => bail!(
+                "Unknown meshopt filter '{}', expected one of: NONE, OCTAHEDRAL, QUATERNION, EXPONENTIAL",
+                value
+            ),
+        })
+    }
+}
+
+// A buffer view whose data is decoded from a range of another buffer
+#[derive(Debug, Clone)]
+pub struct CompressedView {
+    pub buffer: usize,
+    pub offset: usize,
+    pub length: usize,
+    pub stride: usize,
+    pub count: usize,
+    pub mode: Mode,
+    pub filter: Filter,
+    // The byte length of the buffer view it decodes into
+    pub decoded_length: usize,
+}
+
+impl CompressedView {
+    pub fn decode(&self, buffers: &[Vec<u8>]) -> Result<Vec<u8>> {
+        // Counts come from the file, so they're checked before anything is allocated for them
+        ensure!(
+            self.count.checked_mul(self.stride) == Some(self.decoded_length),
+            "Compressed buffer view holds {} elements of {} bytes, but decodes into {} bytes",
+            self.count,
+            self.stride,
+            self.decoded_length
+        );
+        let data = buffers
+            .get(self.buffer)
+            .and_then(|buffer| buffer.get(self.offset..self.offset.checked_add(self.length)?))
+            .context("Compressed buffer view is out of bounds")?;
+        let mut decoded = match self.mode {
+            Mode::Attributes => decode_vertex_buffer(data, self.count, self.stride)?,
+            Mode::Triangles => decode_index_buffer(data, self.count, self.stride)?,
+            Mode::Indices => decode_index_sequence(data, self.count, self.stride)?,
+        };
+        match self.filter {
+            Filter::None => {}
+            Filter::Octahedral => decode_octahedral(&mut decoded, self.stride)?,
+            Filter::Quaternion => decode_quaternion(&mut decoded, self.stride)?,
+            Filter::Exponential => decode_exponential(&mut decoded, self.stride)?,
+        }
+        Ok(decoded)
+    }
+}
+
+fn take<'a>(data: &mut &'a [u8], length: usize) -> Result<&'a [u8]> {
+    ensure!(data.len() >= length, "Compressed data is truncated");
+    let (taken, rest) = data.split_at(length);
+    *data = rest;
+    Ok(taken)
+}
+
+fn take_byte(data: &mut &[u8]) -> Result<u8> {
+    Ok(take(data, 1)?[0])
+}
+
+fn unzigzag8(value: u8) -> u8 {
+    (value >> 1) ^ 0u8.wrapping_sub(value & 1)
+}
+
+fn unzigzag32(value: u32) -> u32 {
+    (value >> 1) ^ 0u32.wrapping_sub(value & 1)
+}
+
+// Seven bits per byte, with the high bit set on every byte but the last
+fn decode_vbyte(data: &mut &[u8]) -> Result<u32> {
+    let lead = take_byte(data)?;
+    if lead < 128 {
+        return Ok(lead as u32);
+    }
+    let mut result = (lead & 127) as u32;
+    let mut shift = 7;
+    for _ in 0..4 {
+        let group = take_byte(data)?;
+        result |= ((group & 127) as u32) << shift;
+        shift += 7;
+        if group < 128 {
+            break;
+        }
+    }
+    Ok(result)
+}
+
+fn decode_index(data: &mut &[u8], last: u32) -> Result<u32> {
+    Ok(last.wrapping_add(unzigzag32(decode_vbyte(data)?)))
+}
+
+// Groups of sixteen bytes are stored with zero, two, four or eight bits each,
+// values that don't fit in two or four bits follow the group in full
+fn decode_bytes(data: &mut &[u8], buffer: &mut [u8]) -> Result<()> {
+    let groups = buffer.len() / BYTE_GROUP_SIZE;
+    let header = take(data, groups.div_ceil(4))?;
+    for (group, output) in buffer.chunks_exact_mut(BYTE_GROUP_SIZE).enumerate() {
+        match (header[group / 4] >> ((group % 4) * 2)) & 3 {
+            0 => output.fill(0),
+            3 => output.copy_from_slice(take(data, BYTE_GROUP_SIZE)?),
+            bits_log2 => {
+                let bits = 1 << bits_log2;
+                let sentinel = (1u8 << bits) - 1;
+                let values_per_byte = 8 / bits;
+                let packed = take(data, BYTE_GROUP_SIZE / values_per_byte)?;
+                for (index, value) in output.iter_mut().enumerate() {
+                    let shift = 8 - bits * (index % values_per_byte + 1);
+                    let encoded = (packed[index / values_per_byte] >> shift) & sentinel;
+                    *value = if encoded == sentinel {
+                        take_byte(data)?
+                    } else {
+                        encoded
+                    };
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+// Each byte of the vertex is delta encoded against the same byte of the previous vertex
+fn decode_vertex_buffer(data: &[u8], count: usize, stride: usize) -> Result<Vec<u8>> {
+    ensure!(
+        stride > 0 && stride <= 256 && stride.is_multiple_of(4),
+        "Invalid meshopt vertex stride {}",
+        stride
+    );
+    let tail_size = stride.max(TAIL_MAX_SIZE);
+    ensure!(
+        data.len() > tail_size,
+        "Compressed vertex data is truncated"
+    );
+    ensure!(
+        data[0] & 0xf0 == VERTEX_HEADER,
+        "Invalid meshopt vertex data header"
+    );
+    ensure!(
+        data[0] & 0x0f == 0,
+        "Unsupported meshopt vertex codec version {}",
+        data[0] & 0x0f
+    );
+
+    // The tail holds the first vertex the deltas start from
+    let (mut body, tail) = data[1..].split_at(data.len() - 1 - tail_size);
+    let mut last_vertex = tail[tail_size - stride..].to_vec();
+
+    let block_size =
+        ((VERTEX_BLOCK_SIZE_BYTES / stride) & !(BYTE_GROUP_SIZE - 1)).min(VERTEX_BLOCK_MAX_SIZE);
+    let mut deltas = [0; VERTEX_BLOCK_MAX_SIZE];
+    let mut vertices = vec![0; count * stride];
+    for block in vertices.chunks_mut(block_size * stride) {
+        let block_count = block.len() / stride;
+        let aligned_count = (block_count + BYTE_GROUP_SIZE - 1) & !(BYTE_GROUP_SIZE - 1);
+        for byte in 0..stride {
+            decode_bytes(&mut body, &mut deltas[..aligned_count])?;
+            let mut previous = last_vertex[byte];
+            for (vertex, delta) in block.chunks_exact_mut(stride).zip(deltas.iter()) {
+                previous = previous.wrapping_add(unzigzag8(*delta));
+                vertex[byte] = previous;
+            }
+        }
+        last_vertex.copy_from_slice(&block[(block_count - 1) * stride..]);
+    }
+    ensure!(body.is_empty(), "Compressed vertex data has trailing bytes");
+    Ok(vertices)
+}
+
+fn write_index(indices: &mut Vec<u8>, index: u32, index_size: usize) {
+    if index_size == 2 {
+        indices.extend_from_slice(&(index as u16).to_le_bytes());
+    } else {
+        indices.extend_from_slice(&index.to_le_bytes());
+    }
+}
+
+// Recently seen edges and vertices the triangle codes refer back to
+struct IndexFifos {
+    edges: [(u32, u32); 16],
+    edge_offset: usize,
+    vertices: [u32; 16],
+    vertex_offset: usize,
+}
+
+impl IndexFifos {
+    fn edge(&self, back: usize) -> (u32, u32) {
+        self.edges[self.edge_offset.wrapping_sub(1 + back) & 15]
+    }
+
+    fn vertex(&self, back: usize) -> u32 {
+        self.vertices[self.vertex_offset.wrapping_sub(back) & 15]
+    }
+
+    fn push_edge(&mut self, a: u32, b: u32) {
+        self.edges[self.edge_offset] = (a, b);
+        self.edge_offset = (self.edge_offset + 1) & 15;
+    }
+
+    fn push_vertex(&mut self, vertex: u32, advance: bool) {
+        self.vertices[self.vertex_offset] = vertex;
+        self.vertex_offset = (self.vertex_offset + advance as usize) & 15;
+    }
+}
+
+// Triangles are coded as a byte each, reusing edges and vertices of earlier triangles where possible
+fn decode_index_buffer(data: &[u8], count: usize, index_size: usize) -> Result<Vec<u8>> {
+    ensure!(
+        index_size == 2 || index_size == 4,
+        "Invalid meshopt index size {}",
+        index_size
+    );
+    ensure!(
+        count.is_multiple_of(3),
+        "Meshopt triangle index count must be a multiple of three"
+    );
+    ensure!(
+        data.len() >= 1 + count / 3 + 16,
+        "Compressed index data is truncated"
+    );
+    ensure!(
+        data[0] & 0xf0 == INDEX_HEADER,
+        "Invalid meshopt index data header"
+    );
+    let version = data[0] & 0x0f;
+    ensure!(
+        version <= 1,
+        "Unsupported meshopt index codec version {}",
+        version
+    );
+
+    let (codes, rest) = data[1..].split_at(count / 3);
+    let (mut extra, codeaux_table) = rest.split_at(rest.len() - 16);
+
+    let mut fifos = IndexFifos {
+        edges: [(u32::MAX, u32::MAX); 16],
+        edge_offset: 0,
+        vertices: [u32::MAX; 16],
+        vertex_offset: 0,
+    };
+    let mut next = 0u32;
+    let mut last = 0u32;
+    let fec_max = if version >= 1 { 13 } else { 15 };
+
+    let mut indices = Vec::with_capacity(count * index_size);
+    for &code in codes {
+        let (a, b, c) = if code < 0xf0 {
+            // An edge from the fifo plus a new, cached or explicitly coded vertex
+            let (a, b) = fifos.edge((code >> 4) as usize);
+            let fec = code & 15;
+            let c = if fec < fec_max {
+                let c = if fec == 0 {
+                    next
+                } else {
+                    fifos.vertex(1 + fec as usize)
+                };
+                next = next.wrapping_add((fec == 0) as u32);
+                fifos.push_vertex(c, fec == 0);
+                c
+            } else {
+                // Thirteen and fourteen code the vertex after or before the last explicit one
+                last = match fec {
+                    13 => last.wrapping_sub(1),
+                    14 => last.wrapping_add(1),
+                    _ => decode_index(&mut extra, last)?,
+                };
+                fifos.push_vertex(last, true);
+                last
+            };
+            fifos.push_edge(c, b);
+            fifos.push_edge(a, c);
+            (a, b, c)
+        } else {
+            let (fea, feb, fec) = if code < 0xfe {
+                let codeaux = codeaux_table[(code & 15) as usize];
+                (0, codeaux >> 4, codeaux & 15)
+            } else {
+                let codeaux = take_byte(&mut extra)?;
+                if codeaux == 0 {
+                    next = 0;
+                }
+                (
+                    if code == 0xfe { 0 } else { 15 },
+                    codeaux >> 4,
+                    codeaux & 15,
+                )
+            };
+
+            // New vertices are numbered in order before any explicit indices are read
+            let mut vertex = |fe: u8| {
+                if fe == 0 {
+                    next = next.wrapping_add(1);
+                    next.wrapping_sub(1)
+                } else {
+                    fifos.vertex(fe as usize)
+                }
+            };
+            let mut a = if fea == 0 { vertex(0) } else { 0 };
+            let mut b = vertex(feb);
+            let mut c = vertex(fec);
+            for (fe, index) in [(fea, &mut a), (feb, &mut b), (fec, &mut c)] {
+                if fe == 15 {
+                    last = decode_index(&mut extra, last)?;
+                    *index = last;
+                }
+            }
+
+            fifos.push_vertex(a, true);
+            fifos.push_vertex(b, feb == 0 || feb == 15);
+            fifos.push_vertex(c, fec == 0 || fec == 15);
+            fifos.push_edge(b, a);
+            fifos.push_edge(c, b);
+            fifos.push_edge(a, c);
+            (a, b, c)
+        };
+        for index in [a, b, c] {
+            write_index(&mut indices, index, index_size);
+        }
+    }
+    ensure!(extra.is_empty(), "Compressed index data has trailing bytes");
+    Ok(indices)
+}
+
+// Indices are delta encoded against one of two previous indices
+fn decode_index_sequence(data: &[u8], count: usize, index_size: usize) -> Result<Vec<u8>> {
+    ensure!(
+        index_size == 2 || index_size == 4,
+        "Invalid meshopt index size {}",
+        index_size
+    );
+    ensure!(
+        data.len() >= 1 + count + 4,
+        "Compressed index data is truncated"
+    );
+    ensure!(
+        data[0] & 0xf0 == SEQUENCE_HEADER,
+        "Invalid meshopt index sequence header"
+    );
+    ensure!(
+        data[0] & 0x0f <= 1,
+        "Unsupported meshopt index sequence version {}",
+        data[0] & 0x0f
+    );
+
+    let mut body = &data[1..data.len() - 4];
+    let mut last = [0u32; 2];
+    let mut indices = Vec::with_capacity(count * index_size);
+    for _ in 0..count {
+        let value = decode_vbyte(&mut body)?;
+        let baseline = (value & 1) as usize;
+        let index = last[baseline].wrapping_add(unzigzag32(value >> 1));
+        last[baseline] = index;
+        write_index(&mut indices, index, index_size);
+    }
+    ensure!(body.is_empty(), "Compressed index data has trailing bytes");
+    Ok(indices)
+}
+
+fn read_component(vertex: &[u8], index: usize, width: usize) -> f32 {
+    match width {
+        1 => vertex[index] as i8 as f32,
+        _ => i16::from_le_bytes([vertex[index * 2], vertex[index * 2 + 1]]) as f32,
+    }
+}
+
+fn write_component(vertex: &mut [u8], index: usize, width: usize, value: f32) {
+    let value = value.round() as i32;
+    match width {
+        1 => vertex[index] = value as i8 as u8,
+        _ => vertex[index * 2..index * 2 + 2].copy_from_slice(&(value as i16).to_le_bytes()),
+    }
+}
+
+// Unit vectors stored as two octahedral coordinates, with the third component holding one
+fn decode_octahedral(data: &mut [u8], stride: usize) -> Result<()> {
+    ensure!(
+        stride == 4 || stride == 8,
+        "The octahedral filter requires a stride of 4 or 8"
+    );
+    let width = stride / 4;
+    let max = ((1 << (width * 8 - 1)) - 1) as f32;
+    for vertex in data.chunks_exact_mut(stride) {
+        let mut x = read_component(vertex, 0, width);
+        let mut y = read_component(vertex, 1, width);
+        let z = read_component(vertex, 2, width) - x.abs() - y.abs();
+
+        // Coordinates in the lower hemisphere are folded back out
+        let t = z.min(0.0);
+        x += if x >= 0.0 { t } else { -t };
+        y += if y >= 0.0 { t } else { -t };
+
+        let scale = max / (x * x + y * y + z * z).sqrt();
+        write_component(vertex, 0, width, x * scale);
+        write_component(vertex, 1, width, y * scale);
+        write_component(vertex, 2, width, z * scale);
+    }
+    Ok(())
+}
+
+// Rotations stored as their three smallest components, the last holding the index of the largest
+fn decode_quaternion(data: &mut [u8], stride: usize) -> Result<()> {
+    ensure!(stride == 8, "The quaternion filter requires a stride of 8");
+    for vertex in data.chunks_exact_mut(stride) {
+        let packed = i16::from_le_bytes([vertex[6], vertex[7]]);
+        let scale = std::f32::consts::FRAC_1_SQRT_2 / (packed | 3) as f32;
+        let x = read_component(vertex, 0, 2) * scale;
+        let y = read_component(vertex, 1, 2) * scale;
+        let z = read_component(vertex, 2, 2) * scale;
+        let w = (1.0 - x * x - y * y - z * z).max(0.0).sqrt();
+
+        let largest = (packed & 3) as usize;
+        for (offset, value) in [(1, x), (2, y), (3, z), (0, w)] {
+            write_component(vertex, (largest + offset) & 3, 2, value * 32767.0);
+        }
+    }
+    Ok(())
+}
+
+// Floats stored as a 24 bit mantissa and an 8 bit exponent
+fn decode_exponential(data: &mut [u8], stride: usize) -> Result<()> {
+    ensure!(
+        stride.is_multiple_of(4),
+        "The exponential filter requires a stride that is a multiple of 4"
+    );
+    for value in data.chunks_exact_mut(4) {
+        let encoded = u32::from_le_bytes([value[0], value[1], value[2], value[3]]);
+        let mantissa = ((encoded << 8) as i32) >> 8;
+        let exponent = (encoded as i32) >> 24;
+        let scale = f32::from_bits(((exponent + 127) as u32) << 23);
+        value.copy_from_slice(&(scale * mantissa as f32).to_le_bytes());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Encoded by meshoptimizer 0.25 and checked against its own decoders
+
+    fn u16s(bytes: &[u8]) -> Vec<u16> {
+        bytes
+            .chunks_exact(2)
+            .map(|value| u16::from_le_bytes([value[0], value[1]]))
+            .collect()
+    }
+
+    fn u32s(bytes: &[u8]) -> Vec<u32> {
+        bytes
+            .chunks_exact(4)
+            .map(|value| u32::from_le_bytes([value[0], value[1], value[2], value[3]]))
+            .collect()
+    }
+
+    const TRIANGLES: [u32; 18] = [0, 1, 2, 2, 1, 3, 4, 6, 5, 7, 8, 9, 9, 8, 0, 100, 101, 102];
+
+    const ENCODED_TRIANGLES: [u8; 34] = [
+        0xe1, 0xf0, 0x10, 0xfe, 0xff, 0x19, 0xff, 0xf0, 0x0c, 0xff, 0x02, 0x02, 0x02, 0xff, 0xb6,
+        0x01, 0x02, 0x02, 0x00, 0x76, 0x87, 0x56, 0x67, 0x78, 0xa9, 0x86, 0x65, 0x89, 0x68, 0x98,
+        0x01, 0x69, 0x00, 0x00,
+    ];
+
+    #[test]
+    fn decodes_vertex_buffer() {
+        let encoded = [
+            0xa0, 0x03, 0x00, 0x4a, 0x4a, 0x4a, 0x4a, 0x4a, 0x4a, 0x4a, 0x4a, 0x4a, 0x4a, 0x4a,
+            0x4a, 0x4a, 0x4a, 0x4a, 0x01, 0x00, 0x02, 0x00, 0x08, 0x02, 0x05, 0x55, 0x55, 0x55,
+            0x55, 0x55, 0x55, 0x55, 0x00, 0x03, 0x00, 0xb6, 0x22, 0x71, 0xfa, 0x66, 0x2d, 0xc1,
+            0xaa, 0x16, 0x7d, 0xee, 0x5a, 0x39, 0xcd, 0x9e, 0x02, 0x00, 0x24, 0x46, 0x8a, 0xac,
+            0xef, 0xff, 0xff, 0x10, 0x10, 0x12, 0x12, 0x14, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xe8, 0x03, 0x00, 0x00, 0x00, 0x00,
+        ];
+        let vertices = decode_vertex_buffer(&encoded, 16, 8).unwrap();
+        let expected = (0..16u32)
+            .flat_map(|index| {
+                [
+                    (index * 37) as u16,
+                    (1000 - index * 3) as u16,
+                    (index * index * 91) as u16,
+                    0,
+                ]
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(u16s(&vertices), expected);
+    }
+
+    #[test]
+    fn rejects_truncated_vertex_buffer() {
+        assert!(decode_vertex_buffer(&[0xa0, 0x00], 1, 4).is_err());
+    }
+
+    #[test]
+    fn decodes_index_buffer() {
+        let indices = decode_index_buffer(&ENCODED_TRIANGLES, TRIANGLES.len(), 4).unwrap();
+        assert_eq!(u32s(&indices), TRIANGLES);
+        let indices = decode_index_buffer(&ENCODED_TRIANGLES, TRIANGLES.len(), 2).unwrap();
+        assert_eq!(u16s(&indices), TRIANGLES.map(|index| index as u16).to_vec());
+    }
+
+    #[test]
+    fn decodes_index_buffer_version_zero() {
+        let mut encoded = ENCODED_TRIANGLES;
+        encoded[0] = 0xe0;
+        let indices = decode_index_buffer(&encoded, TRIANGLES.len(), 4).unwrap();
+        assert_eq!(u32s(&indices), TRIANGLES);
+    }
+
+    #[test]
+    fn decodes_index_sequence() {
+        let encoded = [
+            0xd0, 0x00, 0x04, 0x04, 0x0c, 0x06, 0xb1, 0x09, 0x04, 0x03, 0xb0, 0x8b, 0x11, 0x8f,
+            0x09, 0x00, 0x00, 0x00, 0x00,
+        ];
+        let indices = decode_index_sequence(&encoded, 10, 4).unwrap();
+        assert_eq!(u32s(&indices), [0, 1, 2, 5, 3, 300, 4, 299, 70000, 7]);
+    }
+
+    #[test]
+    fn decodes_octahedral_filter() {
+        let mut bytes = [
+            0x00, 0x00, 0x7f, 0x00, 0x36, 0x49, 0x7f, 0x00, 0xab, 0x6a, 0x7f, 0x00, 0x55, 0xab,
+            0x7f, 0x00,
+        ];
+        decode_octahedral(&mut bytes, 4).unwrap();
+        assert_eq!(
+            bytes,
+            [
+                0x00, 0x00, 0x7f, 0x00, 0x4c, 0x66, 0x00, 0x00, 0xde, 0x43, 0x9a, 0x00, 0x49, 0xb7,
+                0xb6, 0x00,
+            ]
+        );
+
+        let mut bytes = [
+            0x00, 0x00, 0x00, 0x00, 0xff, 0x7f, 0x00, 0x00, 0xdb, 0x36, 0x24, 0x49, 0xff, 0x7f,
+            0x00, 0x00, 0xa5, 0xaa, 0xad, 0x6a, 0xff, 0x7f, 0x00, 0x00, 0x55, 0x55, 0xab, 0xaa,
+            0xff, 0x7f, 0x00, 0x00,
+        ];
+        decode_octahedral(&mut bytes, 8).unwrap();
+        assert_eq!(
+            bytes,
+            [
+                0x00, 0x00, 0x00, 0x00, 0xff, 0x7f, 0x00, 0x00, 0xcc, 0x4c, 0x66, 0x66, 0x00, 0x00,
+                0x00, 0x00, 0xd1, 0xdd, 0x5e, 0x44, 0x55, 0x99, 0x00, 0x00, 0xe5, 0x49, 0x1b, 0xb6,
+                0x19, 0xb6, 0x00, 0x00,
+            ]
+        );
+    }
+
+    #[test]
+    fn decodes_quaternion_filter() {
+        let mut bytes = [
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xff, 0x07, 0xa7, 0x05, 0xa7, 0x05, 0xa7, 0x05,
+            0xfc, 0x07, 0x00, 0x00, 0x01, 0xf8, 0x00, 0x00, 0xfd, 0x07,
+        ];
+        decode_quaternion(&mut bytes, 8).unwrap();
+        assert_eq!(
+            bytes,
+            [
+                0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xff, 0x7f, 0x0f, 0x40, 0xfa, 0x3f, 0xfa, 0x3f,
+                0xfa, 0x3f, 0x00, 0x00, 0x82, 0x5a, 0x00, 0x00, 0x7e, 0xa5,
+            ]
+        );
+    }
+
+    #[test]
+    fn decodes_exponential_filter() {
+        let mut bytes = [
+            0x00, 0x30, 0x00, 0xf3, 0x00, 0xe0, 0xff, 0xf1, 0x80, 0x3e, 0x00, 0xfc, 0x44, 0x32,
+            0x00, 0xf4,
+        ];
+        decode_exponential(&mut bytes, 16).unwrap();
+        let values = u32s(&bytes)
+            .into_iter()
+            .map(f32::from_bits)
+            .collect::<Vec<_>>();
+        assert_eq!(values, [1.5, -0.25, 1000.0, 3.1416016]);
+    }
+
+    #[test]
+    fn parses_modes_and_filters() {
+        assert_eq!("TRIANGLES".parse::<Mode>().unwrap(), Mode::Triangles);
+        assert_eq!("OCTAHEDRAL".parse::<Filter>().unwrap(), Filter::Octahedral);
+        assert!("STRIPS".parse::<Mode>().is_err());
+    }
+
+    #[test]
+    fn rejects_counts_that_dont_match_the_target_view() {
+        let view = |count, stride, decoded_length| CompressedView {
+            buffer: 0,
+            offset: 0,
+            length: 4,
+            stride,
+            count,
+            mode: Mode::Attributes,
+            filter: Filter::None,
+            decoded_length,
+        };
+        let buffers = vec![vec![0; 4]];
+        for view in [
+            view(16, 8, 64),
+            view(usize::MAX, 8, 64),
+            view(1 << 40, 1 << 30, 0),
+        ] {
+            let error = view.decode(&buffers).unwrap_err();
+            assert!(error.to_string().contains("decodes into"), "{}", error);
+        }
+    }
+}
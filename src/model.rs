@@ -0,0 +1,3433 @@
+use anyhow::{bail, Result};
+use image::GenericImageView;
+use nalgebra_glm as glm;
+use std::{
+    cell::Cell,
+    collections::{BTreeMap, HashMap, HashSet},
+    ops::Range,
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+use crate::{
+    animation::Animations,
+    bounds::{Aabb, Frustum},
+    camera::Camera,
+    layers,
+    lighting::PunctualLight,
+    material::{AlphaMode, Material, MaterialUniform, MATERIAL_TEXTURE_COUNT, SRGB_TEXTURES},
+    memory::{self, MemoryCategory, Tracked},
+    render_targets::{FramePhase, RenderTargetPool},
+    scene::{MaterialList, ModelHandle, Scene},
+    settings::{CullMode, Settings},
+    shader_errors::{ComposedShader, ShaderError},
+    shader_snippets,
+    ssao::SsaoSystem,
+    texture::{missing_image, DepthConfig, Texture},
+};
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct ModelVertex {
+    pub position: [f32; 3],
+    pub normal: [f32; 3],
+    // w holds the handedness of the bitangent
+    pub tangent: [f32; 4],
+    pub uv: [f32; 2],
+    // Second texture coordinate set, selected per texture by the material
+    pub uv_1: [f32; 2],
+    // Linear color multiplied with the base color
+    pub color: [f32; 4],
+}
+
+impl Default for ModelVertex {
+    fn default() -> Self {
+        Self {
+            position: [0.0; 3],
+            normal: [0.0; 3],
+            tangent: [0.0; 4],
+            uv: [0.0; 2],
+            uv_1: [0.0; 2],
+            color: [1.0; 4],
+        }
+    }
+}
+
+impl ModelVertex {
+    // Instances take the locations in between
+    const ATTRIBUTES: [wgpu::VertexAttribute; 6] = wgpu::vertex_attr_array![
+        0 => Float32x3,
+        1 => Float32x3,
+        2 => Float32x4,
+        3 => Float32x2,
+        11 => Float32x2,
+        12 => Float32x4
+    ];
+
+    fn layout<'a>() -> wgpu::VertexBufferLayout<'a> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<Self>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &Self::ATTRIBUTES,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Primitive {
+    pub vertices: Vec<ModelVertex>,
+    pub indices: Vec<u32>,
+    // Primitives without a material use the default material
+    pub material: Option<usize>,
+}
+
+impl Primitive {
+    fn triangles(&self) -> impl Iterator<Item = [usize; 3]> + '_ {
+        self.indices.chunks_exact(3).map(|triangle| {
+            [
+                triangle[0] as usize,
+                triangle[1] as usize,
+                triangle[2] as usize,
+            ]
+        })
+    }
+
+    // Smooth normals averaged from the faces surrounding each vertex, weighted by area
+    pub fn generate_normals(&mut self) {
+        let mut normals = vec![glm::Vec3::zeros(); self.vertices.len()];
+        for [a, b, c] in self.triangles() {
+            let [p0, p1, p2] =
+                [a, b, c].map(|index| glm::Vec3::from(self.vertices[index].position));
+            let normal = (p1 - p0).cross(&(p2 - p0));
+            for index in [a, b, c] {
+                normals[index] += normal;
+            }
+        }
+        for (vertex, normal) in self.vertices.iter_mut().zip(normals) {
+            if normal.magnitude() > f32::EPSILON {
+                vertex.normal = normal.normalize().into();
+            }
+        }
+    }
+
+    // Tangents follow the direction the texture's U coordinate increases in
+    pub fn generate_tangents(&mut self) {
+        let mut tangents = vec![glm::Vec3::zeros(); self.vertices.len()];
+        let mut bitangents = vec![glm::Vec3::zeros(); self.vertices.len()];
+        for [a, b, c] in self.triangles() {
+            let [v0, v1, v2] = [a, b, c].map(|index| self.vertices[index]);
+            let edge1 = glm::Vec3::from(v1.position) - glm::Vec3::from(v0.position);
+            let edge2 = glm::Vec3::from(v2.position) - glm::Vec3::from(v0.position);
+            let delta1 = glm::Vec2::from(v1.uv) - glm::Vec2::from(v0.uv);
+            let delta2 = glm::Vec2::from(v2.uv) - glm::Vec2::from(v0.uv);
+            let determinant = delta1.x * delta2.y - delta2.x * delta1.y;
+            if determinant.abs() <= f32::EPSILON {
+                continue;
+            }
+            let tangent = (edge1 * delta2.y - edge2 * delta1.y) / determinant;
+            let bitangent = (edge2 * delta1.x - edge1 * delta2.x) / determinant;
+            for index in [a, b, c] {
+                tangents[index] += tangent;
+                bitangents[index] += bitangent;
+            }
+        }
+        for ((vertex, tangent), bitangent) in self.vertices.iter_mut().zip(tangents).zip(bitangents)
+        {
+            let normal = glm::Vec3::from(vertex.normal);
+            let tangent = tangent - normal * normal.dot(&tangent);
+            if tangent.magnitude() <= f32::EPSILON {
+                continue;
+            }
+            let handedness = if normal.cross(&tangent).dot(&bitangent) < 0.0 {
+                -1.0
+            } else {
+                1.0
+            };
+            vertex.tangent = glm::vec3_to_vec4(&tangent.normalize()).into();
+            vertex.tangent[3] = handedness;
+        }
+    }
+
+    pub fn bounds(&self) -> Aabb {
+        let mut bounds = Aabb::default();
+        for vertex in self.vertices.iter() {
+            bounds.expand_to_include(&glm::Vec3::from(vertex.position));
+        }
+        bounds
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Mesh {
+    pub primitives: Vec<Primitive>,
+}
+
+impl Mesh {
+    pub fn bounds(&self) -> Aabb {
+        let mut bounds = Aabb::default();
+        for primitive in self.primitives.iter() {
+            bounds.merge(&primitive.bounds());
+        }
+        bounds
+    }
+}
+
+// Node transforms are f64 with the double feature, so geospatial scenes keep their precision
+// through the node hierarchy, and are only narrowed to f32 when they're sent to the GPU
+#[cfg(feature = "double")]
+pub type NodeTransform = glm::DMat4;
+#[cfg(not(feature = "double"))]
+pub type NodeTransform = glm::Mat4;
+
+#[derive(Debug, Clone)]
+pub struct MeshInstance {
+    pub mesh: usize,
+    // Relative to the model's transform
+    pub transform: NodeTransform,
+    // The layers the instance is on, see layers.rs
+    pub layers: u32,
+}
+
+// A joint of a skeleton in the pose the model was loaded in
+#[derive(Debug, Clone)]
+pub struct Joint {
+    pub name: Option<String>,
+    // Index of the parent joint in the same skeleton
+    pub parent: Option<usize>,
+    // Relative to the model's transform
+    pub transform: glm::Mat4,
+}
+
+// The joints of a glTF skin, shown for debugging. Vertices aren't deformed by them
+#[derive(Debug, Clone)]
+pub struct Skeleton {
+    pub name: Option<String>,
+    pub joints: Vec<Joint>,
+}
+
+#[derive(Clone)]
+pub struct ModelDesc {
+    pub name: Option<String>,
+    pub images: Vec<image::DynamicImage>,
+    // The files images were read from by image index, watched so edits on disk are reloaded
+    pub image_files: HashMap<usize, PathBuf>,
+    // Images that failed to load while importing, which are drawn with a placeholder
+    pub load_errors: Vec<String>,
+    pub materials: MaterialList,
+    pub meshes: Vec<Mesh>,
+    pub instances: Vec<MeshInstance>,
+    // Relative to the model's transform
+    pub lights: Vec<PunctualLight>,
+    pub skeletons: Vec<Skeleton>,
+    pub animations: Option<Animations>,
+    // Where the model is in the world, in f64 so that models far from the scene's origin such
+    // as those placed in UTM or ECEF coordinates stay precise
+    pub transform: glm::DMat4,
+}
+
+impl Default for ModelDesc {
+    fn default() -> Self {
+        Self {
+            name: None,
+            images: Vec::new(),
+            image_files: HashMap::new(),
+            load_errors: Vec::new(),
+            materials: MaterialList::default(),
+            meshes: Vec::new(),
+            instances: Vec::new(),
+            lights: Vec::new(),
+            skeletons: Vec::new(),
+            animations: None,
+            transform: glm::DMat4::identity(),
+        }
+    }
+}
+
+impl ModelDesc {
+    // World space bounds of an instance as imported, ignoring skinning
+    pub fn instance_bounds(&self, instance: usize) -> Option<Aabb> {
+        let instance = self.instances.get(instance)?;
+        let mesh = self.meshes.get(instance.mesh)?;
+        Some(
+            mesh.bounds()
+                .transformed(&self.instance_transform(instance).cast::<f32>()),
+        )
+    }
+
+    // Where an instance is in the world
+    pub fn instance_transform(&self, instance: &MeshInstance) -> glm::DMat4 {
+        self.transform * instance.transform.cast::<f64>()
+    }
+
+    // World space bounds of every instance
+    pub fn bounds(&self) -> Aabb {
+        let mut bounds = Aabb::default();
+        for instance in 0..self.instances.len() {
+            if let Some(instance_bounds) = self.instance_bounds(instance) {
+                bounds.merge(&instance_bounds);
+            }
+        }
+        bounds
+    }
+}
+
+#[repr(C)]
+#[derive(Default, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct InstanceData {
+    model: [[f32; 4]; 4],
+    // Columns of the inverse transpose of the model matrix's upper 3x3
+    normal_matrix: [[f32; 4]; 3],
+    layers: u32,
+}
+
+impl InstanceData {
+    const ATTRIBUTES: [wgpu::VertexAttribute; 8] = wgpu::vertex_attr_array![
+        4 => Float32x4,
+        5 => Float32x4,
+        6 => Float32x4,
+        7 => Float32x4,
+        8 => Float32x4,
+        9 => Float32x4,
+        10 => Float32x4,
+        13 => Uint32
+    ];
+
+    fn new(transform: &glm::Mat4, layers: u32) -> Self {
+        let normal_matrix = glm::inverse_transpose(glm::mat4_to_mat3(transform));
+        let column = |index: usize| glm::vec3_to_vec4(&normal_matrix.column(index).into()).into();
+        Self {
+            model: (*transform).into(),
+            normal_matrix: [column(0), column(1), column(2)],
+            layers,
+        }
+    }
+
+    fn layout<'a>() -> wgpu::VertexBufferLayout<'a> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<Self>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &Self::ATTRIBUTES,
+        }
+    }
+
+    // Velocity only needs the model matrices, this frame's followed by the previous frame's
+    const MOTION_ATTRIBUTES: [[wgpu::VertexAttribute; 4]; 2] = [
+        wgpu::vertex_attr_array![4 => Float32x4, 5 => Float32x4, 6 => Float32x4, 7 => Float32x4],
+        wgpu::vertex_attr_array![8 => Float32x4, 9 => Float32x4, 10 => Float32x4, 11 => Float32x4],
+    ];
+
+    fn motion_layout<'a>(previous: bool) -> wgpu::VertexBufferLayout<'a> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<Self>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &Self::MOTION_ATTRIBUTES[previous as usize],
+        }
+    }
+}
+
+struct GpuPrimitive {
+    vertex_buffer: Tracked<wgpu::Buffer>,
+    index_buffer: Tracked<wgpu::Buffer>,
+    index_count: u32,
+    material: usize,
+}
+
+impl GpuPrimitive {
+    // Buffers are labeled with the model's name so they can be told apart in frame captures
+    fn new(device: &wgpu::Device, primitive: &Primitive, material: usize, name: &str) -> Self {
+        Self {
+            vertex_buffer: memory::create_buffer_init(
+                device,
+                MemoryCategory::Meshes,
+                &wgpu::util::BufferInitDescriptor {
+                    label: Some(&format!("{} Vertex Buffer", name)),
+                    contents: bytemuck::cast_slice(&primitive.vertices),
+                    usage: wgpu::BufferUsages::VERTEX,
+                },
+            ),
+            index_buffer: memory::create_buffer_init(
+                device,
+                MemoryCategory::Meshes,
+                &wgpu::util::BufferInitDescriptor {
+                    label: Some(&format!("{} Index Buffer", name)),
+                    contents: bytemuck::cast_slice(&primitive.indices),
+                    usage: wgpu::BufferUsages::INDEX,
+                },
+            ),
+            index_count: primitive.indices.len() as u32,
+            material,
+        }
+    }
+}
+
+// The material a primitive is drawn with, the default material after the model's own when it
+// has none
+// Primitives without a material use the default material after the model's own, and those
+// referring to one the model doesn't have use the error material after that
+fn primitive_material(primitive: &Primitive, desc: &ModelDesc) -> usize {
+    match primitive.material {
+        Some(material) if material < desc.materials.len() => material,
+        Some(_) => desc.materials.len() + 1,
+        None => desc.materials.len(),
+    }
+}
+
+// Instances of a mesh on the same layers, drawn with a single call
+struct InstanceBatch {
+    layers: u32,
+    instances: Range<u32>,
+}
+
+const CULL_WORKGROUP_SIZE: u32 = 64;
+
+// Marks textures a packed material doesn't have
+const NO_LAYER: u32 = u32::MAX;
+
+// Puts equal keys in the same group, returning the key of each group in the order they were first
+// seen and the group of each key
+fn group_bindings<K: Clone + Eq + std::hash::Hash>(keys: &[K]) -> (Vec<K>, Vec<usize>) {
+    let mut groups = Vec::new();
+    let mut indices = HashMap::new();
+    let key_groups = keys
+        .iter()
+        .map(|key| {
+            *indices.entry(key.clone()).or_insert_with(|| {
+                groups.push(key.clone());
+                groups.len() - 1
+            })
+        })
+        .collect();
+    (groups, key_groups)
+}
+
+// The distance between material uniforms in a buffer bound with dynamic offsets
+fn material_uniform_stride(limits: &wgpu::Limits) -> u32 {
+    let alignment = limits.min_uniform_buffer_offset_alignment.max(1);
+    (std::mem::size_of::<MaterialUniform>() as u32).div_ceil(alignment) * alignment
+}
+
+// Materials sharing a texture array of each kind of texture, which holds a layer for each image
+#[derive(Debug, Default, PartialEq)]
+struct MaterialGroup {
+    // The size of every image of each kind, once a material of the group has one
+    sizes: [Option<(u32, u32)>; MATERIAL_TEXTURE_COUNT],
+    // The images of each kind in the order of their layers
+    images: [Vec<usize>; MATERIAL_TEXTURE_COUNT],
+}
+
+// Puts each material in the first group whose images of every kind are the same size as its own
+// and that has layers left for them, so images are never scaled to fit. Takes the image and size of
+// each texture of each material and returns the groups, with the group of each material and the
+// layer of each of its textures
+#[allow(clippy::type_complexity)]
+fn group_materials(
+    material_textures: &[[Option<(usize, (u32, u32))>; MATERIAL_TEXTURE_COUNT]],
+    max_layers: usize,
+) -> (
+    Vec<MaterialGroup>,
+    Vec<(usize, [u32; MATERIAL_TEXTURE_COUNT])>,
+) {
+    let fits = |group: &MaterialGroup, textures: &[Option<(usize, (u32, u32))>]| {
+        textures.iter().enumerate().all(|(texture, image)| {
+            let (image, size) = match image {
+                Some(image) => *image,
+                None => return true,
+            };
+            group.sizes[texture].is_none_or(|group_size| group_size == size)
+                && (group.images[texture].contains(&image)
+                    || group.images[texture].len() < max_layers)
+        })
+    };
+
+    let mut groups = Vec::new();
+    let mut placements = Vec::with_capacity(material_textures.len());
+    for textures in material_textures.iter() {
+        let group_index = match groups.iter().position(|group| fits(group, textures)) {
+            Some(group_index) => group_index,
+            None => {
+                groups.push(MaterialGroup::default());
+                groups.len() - 1
+            }
+        };
+        let group = &mut groups[group_index];
+        let mut layers = [NO_LAYER; MATERIAL_TEXTURE_COUNT];
+        for (texture, image) in textures.iter().enumerate() {
+            let (image, size) = match image {
+                Some(image) => *image,
+                None => continue,
+            };
+            group.sizes[texture] = Some(size);
+            let images = &mut group.images[texture];
+            let layer = match images.iter().position(|used| *used == image) {
+                Some(layer) => layer,
+                None => {
+                    images.push(image);
+                    images.len() - 1
+                }
+            };
+            layers[texture] = layer as u32;
+        }
+        placements.push((group_index, layers));
+    }
+    (groups, placements)
+}
+
+// Every mip level of an image down to a single texel
+fn mip_chain(image: &image::DynamicImage) -> Vec<image::RgbaImage> {
+    let mut levels = vec![image.to_rgba8()];
+    loop {
+        let (width, height) = levels[levels.len() - 1].dimensions();
+        if width == 1 && height == 1 {
+            return levels;
+        }
+        let level = image::imageops::resize(
+            &levels[levels.len() - 1],
+            (width / 2).max(1),
+            (height / 2).max(1),
+            image::imageops::FilterType::Triangle,
+        );
+        levels.push(level);
+    }
+}
+
+// The model shader with its material bindings replaced by those of packed materials, which
+// take their draw data from push constants when they're supported
+fn packed_material_shader(push_constants: bool) -> String {
+    const START: &str = "// Material bindings, swapped";
+    const END: &str = "// End of material bindings\n";
+    const DRAW_UNIFORM: &str = "[[group(1), binding(14)]]\nvar<uniform> draw: DrawData;";
+    let source = include_str!("shaders/model.wgsl");
+    let start = source.find(START).unwrap_or(0);
+    let end = source.find(END).map_or(start, |end| end + END.len());
+    let mut bindings = include_str!("shaders/model_material_arrays.wgsl").to_string();
+    if push_constants {
+        bindings = bindings.replace(DRAW_UNIFORM, "var<push_constant> draw: DrawData;");
+    }
+    format!("{}{}{}", &source[..start], bindings, &source[end..])
+}
+
+// The model shader with the surface function of a material's WGSL file in place of its own. It's
+// checked up front, since wgpu aborts on shaders that fail to compile
+fn material_shader(file: &str, surface: &str) -> ComposedShader {
+    const START: &str = "// Custom surface, replaced";
+    const END: &str = "// End of custom surface\n";
+    let source = include_str!("shaders/model.wgsl");
+    let start = source.find(START).unwrap_or(0);
+    let end = source.find(END).map_or(start, |end| end + END.len());
+    let mut shader = ComposedShader::new(file);
+    shader.push("model.wgsl", &source[..start], 1);
+    shader.push(file, surface, 1);
+    shader.push(
+        "model.wgsl",
+        &source[end..],
+        source[..end].lines().count() + 1,
+    );
+    shader
+}
+
+fn custom_material_shader(path: &Path) -> Result<String, ShaderError> {
+    let file = path.display().to_string();
+    let surface = std::fs::read_to_string(path).map_err(|error| {
+        ShaderError::new(
+            file.clone(),
+            format!("Failed to read material shader: {}", error),
+        )
+    })?;
+    let shader = material_shader(&file, &surface);
+    let module = shader.validate()?;
+    // Bindings are laid out by the model shader, so the pipeline couldn't be created with more
+    for (_, variable) in module.global_variables.iter() {
+        let name = match (variable.binding.as_ref(), variable.name.as_ref()) {
+            (Some(_), Some(name)) => name,
+            _ => continue,
+        };
+        let line = shader.declaration_line(name);
+        if line
+            .and_then(|line| shader.locate(line))
+            .map(|(file, _)| file)
+            == Some(file.as_str())
+        {
+            return Err(shader.error_at(
+                line,
+                1,
+                format!(
+                    "Material shaders can't declare their own bindings such as '{}'",
+                    name
+                ),
+            ));
+        }
+    }
+    Ok(shader.source)
+}
+
+// A material shader, drawing with the error surface when it couldn't be compiled
+struct CustomShader {
+    path: PathBuf,
+    pipelines: MaterialPipelines,
+}
+
+// What changes between draws of a model with packed materials
+#[repr(C)]
+#[derive(Default, Copy, Clone, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+struct DrawData {
+    material: u32,
+}
+
+#[repr(C)]
+#[derive(Default, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct CullInstance {
+    min: [f32; 3],
+    batch: u32,
+    max: [f32; 3],
+    batch_start: u32,
+}
+
+// The arguments of an indexed indirect draw
+#[repr(C)]
+#[derive(Default, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct DrawArguments {
+    index_count: u32,
+    instance_count: u32,
+    first_index: u32,
+    base_vertex: i32,
+    first_instance: u32,
+}
+
+// A mesh's instances culled on the GPU. The survivors of each batch are packed at the start of
+// the batch's range, and each primitive of each batch is drawn indirectly with however many
+// survived
+struct CulledInstances {
+    source_buffer: Tracked<wgpu::Buffer>,
+    culled_buffer: Tracked<wgpu::Buffer>,
+    cull_buffer: Tracked<wgpu::Buffer>,
+    arguments_buffer: Tracked<wgpu::Buffer>,
+    // Arguments without any instances, copied over the arguments before culling
+    reset_buffer: Tracked<wgpu::Buffer>,
+    parameters_buffer: Tracked<wgpu::Buffer>,
+    bind_group: wgpu::BindGroup,
+    instance_count: usize,
+    argument_count: usize,
+}
+
+impl CulledInstances {
+    fn new(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        instance_count: usize,
+        argument_count: usize,
+    ) -> Self {
+        let create_buffer = |label, size: usize, usage| {
+            memory::create_buffer(
+                device,
+                MemoryCategory::Meshes,
+                &wgpu::BufferDescriptor {
+                    label: Some(label),
+                    size: size as wgpu::BufferAddress,
+                    usage,
+                    mapped_at_creation: false,
+                },
+            )
+        };
+        let instances_size = instance_count * std::mem::size_of::<InstanceData>();
+        let arguments_size = argument_count * std::mem::size_of::<DrawArguments>();
+        let source_buffer = create_buffer(
+            "Model Cull Source Buffer",
+            instances_size,
+            wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        );
+        let culled_buffer = create_buffer(
+            "Model Culled Instance Buffer",
+            instances_size,
+            wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::VERTEX,
+        );
+        let cull_buffer = create_buffer(
+            "Model Cull Buffer",
+            instance_count * std::mem::size_of::<CullInstance>(),
+            wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        );
+        let arguments_buffer = create_buffer(
+            "Model Draw Arguments Buffer",
+            arguments_size,
+            wgpu::BufferUsages::STORAGE
+                | wgpu::BufferUsages::INDIRECT
+                | wgpu::BufferUsages::COPY_DST,
+        );
+        let reset_buffer = create_buffer(
+            "Model Draw Arguments Reset Buffer",
+            arguments_size,
+            wgpu::BufferUsages::COPY_SRC | wgpu::BufferUsages::COPY_DST,
+        );
+        let parameters_buffer = create_buffer(
+            "Model Cull Parameters Buffer",
+            std::mem::size_of::<[u32; 4]>(),
+            wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        );
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Model Cull Bind Group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: source_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: culled_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: cull_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: arguments_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: parameters_buffer.as_entire_binding(),
+                },
+            ],
+        });
+        Self {
+            source_buffer,
+            culled_buffer,
+            cull_buffer,
+            arguments_buffer,
+            reset_buffer,
+            parameters_buffer,
+            bind_group,
+            instance_count,
+            argument_count,
+        }
+    }
+}
+
+struct GpuMesh {
+    primitives: Vec<GpuPrimitive>,
+    local_bounds: Aabb,
+    instance_buffer: Tracked<wgpu::Buffer>,
+    // Last frame's instances, where velocity is measured from
+    previous_instance_buffer: Tracked<wgpu::Buffer>,
+    instances: Vec<InstanceData>,
+    instance_count: u32,
+    // Instances are grouped by their layers, so each pass can skip the ones it doesn't draw
+    batches: Vec<InstanceBatch>,
+    bounds: Aabb,
+    instance_bounds: Vec<Aabb>,
+    // Only kept while occlusion culling
+    culling: Option<CulledInstances>,
+}
+
+impl GpuMesh {
+    fn new(device: &wgpu::Device, primitives: Vec<GpuPrimitive>, local_bounds: Aabb) -> Self {
+        Self {
+            primitives,
+            local_bounds,
+            instance_buffer: ModelSystem::create_instance_buffer(device, 0),
+            previous_instance_buffer: ModelSystem::create_instance_buffer(device, 0),
+            instances: Vec::new(),
+            instance_count: 0,
+            batches: Vec::new(),
+            bounds: Aabb::default(),
+            instance_bounds: Vec::new(),
+            culling: None,
+        }
+    }
+
+    fn has_batch(&self, filter: impl Fn(u32) -> bool) -> bool {
+        self.batches.iter().any(|batch| filter(batch.layers))
+    }
+
+    // Writes where each instance is this frame, given its layers and transform
+    fn place(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        mut placements: Vec<(u32, glm::Mat4)>,
+    ) {
+        // A stable sort keeps instances in the same order between frames for velocity
+        placements.sort_by_key(|(layers, _)| *layers);
+
+        self.bounds = Aabb::default();
+        self.batches.clear();
+        self.instance_bounds.clear();
+        for (index, (layers, transform)) in placements.iter().enumerate() {
+            let bounds = self.local_bounds.transformed(transform);
+            self.bounds.merge(&bounds);
+            self.instance_bounds.push(bounds);
+            let index = index as u32;
+            match self.batches.last_mut() {
+                Some(batch) if batch.layers == *layers => batch.instances.end = index + 1,
+                _ => self.batches.push(InstanceBatch {
+                    layers: *layers,
+                    instances: index..index + 1,
+                }),
+            }
+        }
+
+        let instances = placements
+            .iter()
+            .map(|(layers, transform)| InstanceData::new(transform, *layers))
+            .collect::<Vec<_>>();
+        // Instances can't be matched up once their count changes, so they start out still
+        if instances.len() != self.instance_count as usize {
+            self.instance_buffer = ModelSystem::create_instance_buffer(device, instances.len());
+            self.previous_instance_buffer =
+                ModelSystem::create_instance_buffer(device, instances.len());
+            self.instance_count = instances.len() as u32;
+            self.instances = instances.clone();
+        }
+        if !instances.is_empty() {
+            queue.write_buffer(&self.instance_buffer, 0, bytemuck::cast_slice(&instances));
+            queue.write_buffer(
+                &self.previous_instance_buffer,
+                0,
+                bytemuck::cast_slice(&self.instances),
+            );
+        }
+        self.instances = instances;
+    }
+
+    // Writes this frame's instances for culling, after they're placed
+    fn prepare_culling(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        layout: &wgpu::BindGroupLayout,
+        enabled: bool,
+    ) {
+        if !enabled || self.instances.is_empty() || self.primitives.is_empty() {
+            self.culling = None;
+            return;
+        }
+        let argument_count = self.batches.len() * self.primitives.len();
+        if self.culling.as_ref().is_none_or(|culling| {
+            culling.instance_count != self.instances.len()
+                || culling.argument_count != argument_count
+        }) {
+            self.culling = Some(CulledInstances::new(
+                device,
+                layout,
+                self.instances.len(),
+                argument_count,
+            ));
+        }
+        let culling = match self.culling.as_ref() {
+            Some(culling) => culling,
+            None => return,
+        };
+
+        let mut cull_instances = Vec::with_capacity(self.instances.len());
+        let mut arguments = Vec::with_capacity(argument_count);
+        for (batch_index, batch) in self.batches.iter().enumerate() {
+            for bounds in self.instance_bounds[batch.instances.start as usize..]
+                .iter()
+                .take(batch.instances.len())
+            {
+                cull_instances.push(CullInstance {
+                    min: bounds.min.into(),
+                    batch: batch_index as u32,
+                    max: bounds.max.into(),
+                    batch_start: batch.instances.start,
+                });
+            }
+            arguments.extend(self.primitives.iter().map(|primitive| DrawArguments {
+                index_count: primitive.index_count,
+                ..Default::default()
+            }));
+        }
+        queue.write_buffer(
+            &culling.source_buffer,
+            0,
+            bytemuck::cast_slice(&self.instances),
+        );
+        queue.write_buffer(
+            &culling.cull_buffer,
+            0,
+            bytemuck::cast_slice(&cull_instances),
+        );
+        queue.write_buffer(&culling.reset_buffer, 0, bytemuck::cast_slice(&arguments));
+        queue.write_buffer(
+            &culling.parameters_buffer,
+            0,
+            bytemuck::cast_slice(&[self.primitives.len() as u32, 0, 0, 0]),
+        );
+    }
+}
+
+struct GpuMaterial {
+    alpha_mode: AlphaMode,
+    transmissive: bool,
+    outlined: bool,
+    cull_mode: Option<wgpu::Face>,
+    // The images the material was bound with, which can only change by creating the model again
+    textures: [Option<usize>; MATERIAL_TEXTURE_COUNT],
+    // Index of the material's custom shader
+    shader: Option<usize>,
+}
+
+impl GpuMaterial {
+    // Opaque surfaces are drawn before decals, transparent ones after
+    fn is_opaque(&self) -> bool {
+        self.alpha_mode != AlphaMode::Blend && !self.transmissive
+    }
+}
+
+fn face_culling(material: &Material, cull_mode: CullMode) -> Option<wgpu::Face> {
+    match cull_mode {
+        CullMode::Material if material.double_sided => None,
+        CullMode::Material | CullMode::Back => Some(wgpu::Face::Back),
+        CullMode::Front => Some(wgpu::Face::Front),
+        CullMode::None => None,
+    }
+}
+
+// A variant of a pipeline for each way faces can be culled
+struct CullVariants {
+    back: wgpu::RenderPipeline,
+    front: wgpu::RenderPipeline,
+    none: wgpu::RenderPipeline,
+}
+
+impl CullVariants {
+    fn new(create: impl Fn(Option<wgpu::Face>) -> wgpu::RenderPipeline) -> Self {
+        Self {
+            back: create(Some(wgpu::Face::Back)),
+            front: create(Some(wgpu::Face::Front)),
+            none: create(None),
+        }
+    }
+
+    fn get(&self, cull_mode: Option<wgpu::Face>) -> &wgpu::RenderPipeline {
+        match cull_mode {
+            Some(wgpu::Face::Back) => &self.back,
+            Some(wgpu::Face::Front) => &self.front,
+            None => &self.none,
+        }
+    }
+}
+
+// Which of the pipeline variants a cull mode draws with, the order opaque draws are sorted in
+fn cull_order(cull_mode: Option<wgpu::Face>) -> u8 {
+    match cull_mode {
+        Some(wgpu::Face::Back) => 0,
+        Some(wgpu::Face::Front) => 1,
+        None => 2,
+    }
+}
+
+// State changes made while drawing models, added up over every pass of a frame
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct DrawStats {
+    pub draws: u32,
+    pub pipelines: u32,
+    pub bind_groups: u32,
+    pub buffers: u32,
+    pub push_constants: u32,
+    // Bind groups bound again with only their dynamic offset changed
+    pub dynamic_offsets: u32,
+    // Binds skipped because the same state was already bound
+    pub redundant: u32,
+}
+
+impl DrawStats {
+    fn add(&mut self, other: &Self) {
+        self.draws += other.draws;
+        self.pipelines += other.pipelines;
+        self.bind_groups += other.bind_groups;
+        self.buffers += other.buffers;
+        self.push_constants += other.push_constants;
+        self.dynamic_offsets += other.dynamic_offsets;
+        self.redundant += other.redundant;
+    }
+}
+
+// Records draws into a render pass, skipping binds of state that is already bound and counting
+// the rest into the frame's stats once the pass is done
+struct DrawEncoder<'a, 'p> {
+    render_pass: &'p mut wgpu::RenderPass<'a>,
+    pipeline: Option<&'a wgpu::RenderPipeline>,
+    // Bind groups are bound with a dynamic offset when their layout has one
+    bind_groups: [Option<(&'a wgpu::BindGroup, Option<u32>)>; 4],
+    // Buffers are bound from an offset into them
+    vertex_buffers: [Option<(&'a wgpu::Buffer, wgpu::BufferAddress)>; 3],
+    index_buffer: Option<&'a wgpu::Buffer>,
+    // Forgotten whenever the pipeline changes, since its layout may not keep them
+    draw_data: Option<DrawData>,
+    // Passes start with a reference of zero
+    stencil_reference: u32,
+    // The model last named in the pass with a debug marker
+    marked: Option<&'a GpuModel>,
+    stats: DrawStats,
+    frame_stats: &'p Cell<DrawStats>,
+}
+
+impl<'a, 'p> DrawEncoder<'a, 'p> {
+    fn new(render_pass: &'p mut wgpu::RenderPass<'a>, frame_stats: &'p Cell<DrawStats>) -> Self {
+        Self {
+            render_pass,
+            pipeline: None,
+            bind_groups: [None; 4],
+            vertex_buffers: [None; 3],
+            index_buffer: None,
+            draw_data: None,
+            stencil_reference: 0,
+            marked: None,
+            stats: DrawStats::default(),
+            frame_stats,
+        }
+    }
+
+    fn bound<T>(bound: &mut Option<&'a T>, state: &'a T, redundant: &mut u32) -> bool {
+        if bound.is_some_and(|bound| std::ptr::eq(bound, state)) {
+            *redundant += 1;
+            return true;
+        }
+        *bound = Some(state);
+        false
+    }
+
+    fn set_pipeline(&mut self, pipeline: &'a wgpu::RenderPipeline) {
+        if !Self::bound(&mut self.pipeline, pipeline, &mut self.stats.redundant) {
+            self.render_pass.set_pipeline(pipeline);
+            self.stats.pipelines += 1;
+            self.draw_data = None;
+        }
+    }
+
+    fn set_bind_group(&mut self, index: u32, bind_group: &'a wgpu::BindGroup) {
+        self.set_bind_group_at(index, bind_group, None);
+    }
+
+    fn set_bind_group_at(
+        &mut self,
+        index: u32,
+        bind_group: &'a wgpu::BindGroup,
+        offset: Option<u32>,
+    ) {
+        let bound = &mut self.bind_groups[index as usize];
+        let same_group = bound.is_some_and(|(bound, _)| std::ptr::eq(bound, bind_group));
+        if same_group && bound.is_some_and(|(_, bound_offset)| bound_offset == offset) {
+            self.stats.redundant += 1;
+            return;
+        }
+        *bound = Some((bind_group, offset));
+        self.render_pass
+            .set_bind_group(index, bind_group, offset.as_slice());
+        if same_group {
+            self.stats.dynamic_offsets += 1;
+        } else {
+            self.stats.bind_groups += 1;
+        }
+    }
+
+    // Names the model drawn next in frame captures, once per run of its draws
+    fn mark_model(&mut self, model: &'a GpuModel) {
+        if self
+            .marked
+            .is_some_and(|marked| std::ptr::eq(marked, model))
+        {
+            return;
+        }
+        self.marked = Some(model);
+        self.render_pass.insert_debug_marker(&model.name);
+    }
+
+    fn set_draw_data(&mut self, draw_data: DrawData) {
+        if self.draw_data == Some(draw_data) {
+            self.stats.redundant += 1;
+            return;
+        }
+        self.draw_data = Some(draw_data);
+        self.render_pass.set_push_constants(
+            wgpu::ShaderStages::VERTEX_FRAGMENT,
+            0,
+            bytemuck::cast_slice(&[draw_data]),
+        );
+        self.stats.push_constants += 1;
+    }
+
+    fn set_stencil_reference(&mut self, reference: u32) {
+        if self.stencil_reference == reference {
+            self.stats.redundant += 1;
+            return;
+        }
+        self.stencil_reference = reference;
+        self.render_pass.set_stencil_reference(reference);
+    }
+
+    fn set_vertex_buffer(&mut self, slot: u32, buffer: &'a wgpu::Buffer) {
+        self.set_vertex_buffer_from(slot, buffer, 0);
+    }
+
+    fn set_vertex_buffer_from(
+        &mut self,
+        slot: u32,
+        buffer: &'a wgpu::Buffer,
+        offset: wgpu::BufferAddress,
+    ) {
+        let bound = &mut self.vertex_buffers[slot as usize];
+        if bound.is_some_and(|(bound, bound_offset)| {
+            std::ptr::eq(bound, buffer) && bound_offset == offset
+        }) {
+            self.stats.redundant += 1;
+            return;
+        }
+        *bound = Some((buffer, offset));
+        self.render_pass
+            .set_vertex_buffer(slot, buffer.slice(offset..));
+        self.stats.buffers += 1;
+    }
+
+    fn set_index_buffer(&mut self, buffer: &'a wgpu::Buffer) {
+        if !Self::bound(&mut self.index_buffer, buffer, &mut self.stats.redundant) {
+            self.render_pass
+                .set_index_buffer(buffer.slice(..), wgpu::IndexFormat::Uint32);
+            self.stats.buffers += 1;
+        }
+    }
+
+    fn draw_indexed(&mut self, indices: Range<u32>, instances: Range<u32>) {
+        self.render_pass.draw_indexed(indices, 0, instances);
+        self.stats.draws += 1;
+    }
+
+    fn draw_indexed_indirect(&mut self, buffer: &'a wgpu::Buffer, offset: wgpu::BufferAddress) {
+        self.render_pass.draw_indexed_indirect(buffer, offset);
+        self.stats.draws += 1;
+    }
+}
+
+impl Drop for DrawEncoder<'_, '_> {
+    fn drop(&mut self) {
+        let mut frame_stats = self.frame_stats.get();
+        frame_stats.add(&self.stats);
+        self.frame_stats.set(frame_stats);
+    }
+}
+
+// An opaque primitive to draw, sorted by pipeline, then material, then front to back
+struct OpaqueDraw<'a> {
+    key: (u8, usize, usize),
+    distance: f32,
+    model: &'a GpuModel,
+    mesh: &'a GpuMesh,
+    primitive: &'a GpuPrimitive,
+    primitive_index: usize,
+}
+
+impl OpaqueDraw<'_> {
+    // Models with outlines mark where they're drawn with a stencil reference of their own, so
+    // their outlines only show around them and never over them
+    fn stencil_reference(&self) -> u32 {
+        if self
+            .model
+            .materials
+            .iter()
+            .any(|material| material.outlined)
+        {
+            (self.key.1 % 255) as u32 + 1
+        } else {
+            0
+        }
+    }
+}
+
+// The instances of a model merged into one mesh per set of layers, with a primitive per
+// material, drawn in place of its meshes
+struct StaticBatches {
+    meshes: Vec<GpuMesh>,
+    layers: Vec<u32>,
+    // The instances as they were when merged
+    instances: Vec<MeshInstance>,
+    // Draws per pass the instances would take without merging
+    unbatched_draws: usize,
+}
+
+impl StaticBatches {
+    fn new(device: &wgpu::Device, desc: &ModelDesc, name: &str) -> Result<Self> {
+        let mut merged: BTreeMap<u32, BTreeMap<usize, Primitive>> = BTreeMap::new();
+        let mut drawn_meshes = HashSet::new();
+        for instance in desc.instances.iter() {
+            let mesh = match desc.meshes.get(instance.mesh) {
+                Some(mesh) => mesh,
+                None => bail!("Instance refers to missing mesh {}!", instance.mesh),
+            };
+            drawn_meshes.insert(instance.mesh);
+            let transform = instance.transform.cast::<f32>();
+            let normal_matrix = glm::inverse_transpose(glm::mat4_to_mat3(&transform));
+            // Mirroring turns triangles inside out, so their winding and bitangents are flipped back
+            let mirrored = glm::determinant(&glm::mat4_to_mat3(&transform)) < 0.0;
+            for primitive in mesh.primitives.iter() {
+                if primitive.vertices.is_empty() || primitive.indices.is_empty() {
+                    continue;
+                }
+                let material = primitive_material(primitive, desc);
+                let target = merged
+                    .entry(instance.layers)
+                    .or_default()
+                    .entry(material)
+                    .or_default();
+                let base = target.vertices.len() as u32;
+                target
+                    .vertices
+                    .extend(primitive.vertices.iter().map(|vertex| {
+                        let position = transform * glm::Vec3::from(vertex.position).push(1.0);
+                        let normal = normal_matrix * glm::Vec3::from(vertex.normal);
+                        let tangent = glm::mat4_to_mat3(&transform)
+                            * glm::vec3(vertex.tangent[0], vertex.tangent[1], vertex.tangent[2]);
+                        let tangent = tangent.try_normalize(f32::EPSILON).unwrap_or(tangent);
+                        let handedness = if mirrored {
+                            -vertex.tangent[3]
+                        } else {
+                            vertex.tangent[3]
+                        };
+                        ModelVertex {
+                            position: position.xyz().into(),
+                            normal: normal.try_normalize(f32::EPSILON).unwrap_or(normal).into(),
+                            tangent: tangent.push(handedness).into(),
+                            ..*vertex
+                        }
+                    }));
+                for triangle in primitive.indices.chunks_exact(3) {
+                    let triangle = if mirrored {
+                        [triangle[0], triangle[2], triangle[1]]
+                    } else {
+                        [triangle[0], triangle[1], triangle[2]]
+                    };
+                    target
+                        .indices
+                        .extend(triangle.iter().map(|index| base + index));
+                }
+            }
+        }
+
+        let mut meshes = Vec::with_capacity(merged.len());
+        let mut layers = Vec::with_capacity(merged.len());
+        for (mesh_layers, primitives) in merged {
+            let mut local_bounds = Aabb::default();
+            let primitives = primitives
+                .into_iter()
+                .map(|(material, primitive)| {
+                    local_bounds.merge(&primitive.bounds());
+                    GpuPrimitive::new(device, &primitive, material, name)
+                })
+                .collect();
+            meshes.push(GpuMesh::new(device, primitives, local_bounds));
+            layers.push(mesh_layers);
+        }
+
+        Ok(Self {
+            meshes,
+            layers,
+            instances: desc.instances.clone(),
+            unbatched_draws: drawn_meshes
+                .into_iter()
+                .map(|mesh| desc.meshes[mesh].primitives.len())
+                .sum(),
+        })
+    }
+
+    // Instances added, removed or moved to other layers always need merging again, while moved
+    // instances only do when asked to
+    fn is_stale(&self, desc: &ModelDesc, rebuild_moved: bool) -> bool {
+        self.instances.len() != desc.instances.len()
+            || self
+                .instances
+                .iter()
+                .zip(desc.instances.iter())
+                .any(|(merged, instance)| {
+                    merged.mesh != instance.mesh
+                        || merged.layers != instance.layers
+                        || (rebuild_moved && merged.transform != instance.transform)
+                })
+    }
+}
+
+// Whether each material of a model is bound on its own, or groups of them are bound at once with
+// their textures packed into a texture array per kind of texture
+#[allow(clippy::large_enum_variant)]
+enum MaterialBindings {
+    Separate {
+        // The uniform of each material, a stride apart so each sits at its own dynamic offset
+        materials_buffer: Tracked<wgpu::Buffer>,
+        stride: u32,
+        // One for each set of textures used by the materials
+        bind_groups: Vec<wgpu::BindGroup>,
+        // The bind group of each material
+        material_groups: Vec<usize>,
+        _textures: Vec<Texture>,
+    },
+    Packed {
+        materials_buffer: Tracked<wgpu::Buffer>,
+        bind_groups: Vec<wgpu::BindGroup>,
+        // The bind group of each material
+        material_groups: Vec<usize>,
+        // The draw data of every material and the stride between them, when it isn't pushed
+        draws: Option<(Tracked<wgpu::Buffer>, u32)>,
+        _textures: Vec<Tracked<wgpu::Texture>>,
+    },
+}
+
+struct GpuModel {
+    // Labels its resources and draws in frame captures
+    name: String,
+    meshes: Vec<GpuMesh>,
+    static_batches: Option<StaticBatches>,
+    // The last material is the default for primitives without one
+    materials: Vec<GpuMaterial>,
+    bindings: MaterialBindings,
+    // The scene's images revision for the model when it was created
+    images_revision: u64,
+}
+
+impl GpuModel {
+    fn drawn_meshes(&self) -> &[GpuMesh] {
+        match self.static_batches.as_ref() {
+            Some(batches) => &batches.meshes,
+            None => &self.meshes,
+        }
+    }
+
+    fn drawn_meshes_mut(&mut self) -> &mut [GpuMesh] {
+        match self.static_batches.as_mut() {
+            Some(batches) => &mut batches.meshes,
+            None => &mut self.meshes,
+        }
+    }
+
+    // Packed materials stay bound across draws of their group, with only the material's index
+    // changing
+    fn bind_material<'a>(&'a self, encoder: &mut DrawEncoder<'a, '_>, material: usize) {
+        encoder.mark_model(self);
+        match &self.bindings {
+            MaterialBindings::Separate {
+                bind_groups,
+                material_groups,
+                stride,
+                ..
+            } => {
+                encoder.set_bind_group_at(
+                    1,
+                    &bind_groups[material_groups[material]],
+                    Some(material as u32 * stride),
+                );
+            }
+            MaterialBindings::Packed {
+                bind_groups,
+                material_groups,
+                draws: Some((_, stride)),
+                ..
+            } => {
+                encoder.set_bind_group_at(
+                    1,
+                    &bind_groups[material_groups[material]],
+                    Some(material as u32 * stride),
+                );
+            }
+            MaterialBindings::Packed {
+                bind_groups,
+                material_groups,
+                ..
+            } => {
+                encoder.set_bind_group(1, &bind_groups[material_groups[material]]);
+                encoder.set_draw_data(DrawData {
+                    material: material as u32,
+                });
+            }
+        }
+    }
+
+    fn write_material(&self, queue: &wgpu::Queue, material: usize, uniform: &MaterialUniform) {
+        let (buffer, offset) = match &self.bindings {
+            MaterialBindings::Separate {
+                materials_buffer,
+                stride,
+                ..
+            } => (materials_buffer, material * *stride as usize),
+            MaterialBindings::Packed {
+                materials_buffer, ..
+            } => (
+                materials_buffer,
+                material * std::mem::size_of::<MaterialUniform>(),
+            ),
+        };
+        queue.write_buffer(
+            buffer,
+            offset as wgpu::BufferAddress,
+            bytemuck::cast_slice(&[*uniform]),
+        );
+    }
+}
+
+// Fallbacks bound in place of textures a material doesn't have
+struct DefaultTextures {
+    white: Texture,
+    white_linear: Texture,
+    flat_normal: Texture,
+}
+
+impl DefaultTextures {
+    fn new(device: &wgpu::Device, queue: &wgpu::Queue) -> Result<Self> {
+        let pixel = |color: [u8; 4]| {
+            image::DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(1, 1, image::Rgba(color)))
+        };
+        Ok(Self {
+            white: Texture::from_image(
+                device,
+                queue,
+                &pixel([255, 255, 255, 255]),
+                Some("Default White Texture"),
+            )?,
+            white_linear: Texture::from_image_with_format(
+                device,
+                queue,
+                &pixel([255, 255, 255, 255]),
+                wgpu::TextureFormat::Rgba8Unorm,
+                Some("Default Linear White Texture"),
+            )?,
+            flat_normal: Texture::from_image_with_format(
+                device,
+                queue,
+                &pixel([128, 128, 255, 255]),
+                wgpu::TextureFormat::Rgba8Unorm,
+                Some("Default Normal Texture"),
+            )?,
+        })
+    }
+}
+
+// The pipelines drawing with the model shader, built for each way of binding materials
+struct MaterialPipelines {
+    pipeline: CullVariants,
+    blend_pipeline: CullVariants,
+    transmission_pipeline: CullVariants,
+    overlay_pipeline: CullVariants,
+    depth_pipeline: CullVariants,
+    outline_pipeline: wgpu::RenderPipeline,
+}
+
+// The layouts of the model pipelines for one way of binding materials, kept so pipelines for
+// custom material shaders can be built later
+struct MaterialPipelineLayouts {
+    pipeline: wgpu::PipelineLayout,
+    // Transparent surfaces also have the opaque scene color bound
+    transparent: wgpu::PipelineLayout,
+    depth: wgpu::PipelineLayout,
+}
+
+impl MaterialPipelineLayouts {
+    fn new(
+        device: &wgpu::Device,
+        material_bind_group_layout: &wgpu::BindGroupLayout,
+        camera_bind_group_layout: &wgpu::BindGroupLayout,
+        light_bind_group_layout: &wgpu::BindGroupLayout,
+        ambient_occlusion_bind_group_layout: &wgpu::BindGroupLayout,
+        transmission_bind_group_layout: &wgpu::BindGroupLayout,
+        push_constant_ranges: &[wgpu::PushConstantRange],
+    ) -> Self {
+        let pipeline = memory::create_pipeline_layout(
+            device,
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("Model Pipeline Layout"),
+                bind_group_layouts: &[
+                    camera_bind_group_layout,
+                    material_bind_group_layout,
+                    light_bind_group_layout,
+                    ambient_occlusion_bind_group_layout,
+                ],
+                push_constant_ranges,
+            },
+        );
+        let transparent = memory::create_pipeline_layout(
+            device,
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("Model Transparent Pipeline Layout"),
+                bind_group_layouts: &[
+                    camera_bind_group_layout,
+                    material_bind_group_layout,
+                    light_bind_group_layout,
+                    transmission_bind_group_layout,
+                ],
+                push_constant_ranges,
+            },
+        );
+        let depth = memory::create_pipeline_layout(
+            device,
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("Model Depth Pipeline Layout"),
+                bind_group_layouts: &[camera_bind_group_layout, material_bind_group_layout],
+                push_constant_ranges: &[],
+            },
+        );
+        Self {
+            pipeline,
+            transparent,
+            depth,
+        }
+    }
+}
+
+impl MaterialPipelines {
+    fn new(
+        device: &wgpu::Device,
+        source: &str,
+        layouts: &MaterialPipelineLayouts,
+        color_format: wgpu::TextureFormat,
+        depth: DepthConfig,
+    ) -> Self {
+        let module = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("Model Shader"),
+            source: wgpu::ShaderSource::Wgsl(source.into()),
+        });
+
+        let pipeline_layout = &layouts.pipeline;
+        let transparent_pipeline_layout = &layouts.transparent;
+
+        let buffers = [ModelVertex::layout(), InstanceData::layout()];
+
+        let primitive = wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            front_face: wgpu::FrontFace::Ccw,
+            cull_mode: Some(wgpu::Face::Back),
+            ..Default::default()
+        };
+
+        // Opaque surfaces write the stencil reference of their model and outlines are drawn
+        // where it differs. Without a stencil outlines can cut into the creases of surfaces
+        let stencil = |compare, pass_op, write_mask| {
+            if !depth.has_stencil() {
+                return wgpu::StencilState::default();
+            }
+            let face = wgpu::StencilFaceState {
+                compare,
+                fail_op: wgpu::StencilOperation::Keep,
+                depth_fail_op: wgpu::StencilOperation::Keep,
+                pass_op,
+            };
+            wgpu::StencilState {
+                front: face,
+                back: face,
+                read_mask: 0xff,
+                write_mask,
+            }
+        };
+        let opaque_stencil = stencil(
+            wgpu::CompareFunction::Always,
+            wgpu::StencilOperation::Replace,
+            0xff,
+        );
+        let outline_stencil = stencil(
+            wgpu::CompareFunction::NotEqual,
+            wgpu::StencilOperation::Keep,
+            0,
+        );
+
+        let create_pipeline = |label,
+                               layout,
+                               entry_point,
+                               blend,
+                               depth_write_enabled,
+                               depth_compare,
+                               stencil: &wgpu::StencilState,
+                               cull_mode,
+                               multisample| {
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some(label),
+                layout: Some(layout),
+                vertex: wgpu::VertexState {
+                    module: &module,
+                    entry_point: "vs_main",
+                    buffers: &buffers,
+                },
+                primitive: wgpu::PrimitiveState {
+                    cull_mode,
+                    ..primitive
+                },
+                // Opaque surfaces were already written by the depth prepass
+                depth_stencil: Some(wgpu::DepthStencilState {
+                    format: depth.format,
+                    depth_write_enabled,
+                    depth_compare: depth.compare(depth_compare),
+                    stencil: stencil.clone(),
+                    bias: wgpu::DepthBiasState::default(),
+                }),
+                multisample,
+                fragment: Some(wgpu::FragmentState {
+                    module: &module,
+                    entry_point,
+                    targets: &[wgpu::ColorTargetState {
+                        format: color_format,
+                        blend: Some(blend),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    }],
+                }),
+            })
+        };
+
+        let pipeline = CullVariants::new(|cull_mode| {
+            create_pipeline(
+                "Model Pipeline",
+                pipeline_layout,
+                "fs_main",
+                wgpu::BlendState::REPLACE,
+                true,
+                wgpu::CompareFunction::LessEqual,
+                &opaque_stencil,
+                cull_mode,
+                depth.multisample(),
+            )
+        });
+        let blend_pipeline = CullVariants::new(|cull_mode| {
+            create_pipeline(
+                "Model Blend Pipeline",
+                transparent_pipeline_layout,
+                "fs_main",
+                wgpu::BlendState::ALPHA_BLENDING,
+                false,
+                wgpu::CompareFunction::LessEqual,
+                &wgpu::StencilState::default(),
+                cull_mode,
+                wgpu::MultisampleState::default(),
+            )
+        });
+        // Transmissive surfaces aren't in the depth prepass, so they write their own depth
+        let transmission_pipeline = CullVariants::new(|cull_mode| {
+            create_pipeline(
+                "Model Transmission Pipeline",
+                transparent_pipeline_layout,
+                "fs_transmission",
+                wgpu::BlendState::ALPHA_BLENDING,
+                true,
+                wgpu::CompareFunction::LessEqual,
+                &wgpu::StencilState::default(),
+                cull_mode,
+                wgpu::MultisampleState::default(),
+            )
+        });
+
+        let overlay_pipeline = CullVariants::new(|cull_mode| {
+            create_pipeline(
+                "Model Overlay Pipeline",
+                transparent_pipeline_layout,
+                "fs_main",
+                wgpu::BlendState::ALPHA_BLENDING,
+                false,
+                wgpu::CompareFunction::Always,
+                &wgpu::StencilState::default(),
+                cull_mode,
+                wgpu::MultisampleState::default(),
+            )
+        });
+
+        let depth_pipeline_layout = &layouts.depth;
+
+        // Masked materials still sample their alpha to cut holes in the prepass
+        let depth_pipeline = CullVariants::new(|cull_mode| {
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some("Model Depth Pipeline"),
+                layout: Some(depth_pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: &module,
+                    entry_point: "vs_main",
+                    buffers: &buffers,
+                },
+                primitive: wgpu::PrimitiveState {
+                    cull_mode,
+                    ..primitive
+                },
+                depth_stencil: Some(wgpu::DepthStencilState {
+                    format: depth.format,
+                    depth_write_enabled: true,
+                    depth_compare: depth.compare(wgpu::CompareFunction::Less),
+                    stencil: wgpu::StencilState::default(),
+                    bias: wgpu::DepthBiasState::default(),
+                }),
+                multisample: wgpu::MultisampleState::default(),
+                fragment: Some(wgpu::FragmentState {
+                    module: &module,
+                    entry_point: "fs_depth",
+                    targets: &[],
+                }),
+            })
+        });
+
+        // Inverted hulls, only their back faces poke out from behind the surface
+        let outline_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Model Outline Pipeline"),
+            layout: Some(depth_pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &module,
+                entry_point: "vs_outline",
+                buffers: &buffers,
+            },
+            primitive: wgpu::PrimitiveState {
+                cull_mode: Some(wgpu::Face::Front),
+                ..primitive
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: depth.format,
+                depth_write_enabled: true,
+                depth_compare: depth.compare(wgpu::CompareFunction::LessEqual),
+                stencil: outline_stencil,
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: depth.multisample(),
+            fragment: Some(wgpu::FragmentState {
+                module: &module,
+                entry_point: "fs_outline",
+                targets: &[wgpu::ColorTargetState {
+                    format: color_format,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                }],
+            }),
+        });
+
+        Self {
+            pipeline,
+            blend_pipeline,
+            transmission_pipeline,
+            overlay_pipeline,
+            depth_pipeline,
+            outline_pipeline,
+        }
+    }
+}
+
+pub struct ModelSystem {
+    material_bind_group_layout: wgpu::BindGroupLayout,
+    transmission_bind_group_layout: wgpu::BindGroupLayout,
+    transmission_bind_group: wgpu::BindGroup,
+    // The scene color once everything opaque is drawn, seen through transmissive surfaces
+    opaque_color: Rc<Texture>,
+    pipelines: MaterialPipelines,
+    layouts: MaterialPipelineLayouts,
+    // Compiled the first time a material refers to them
+    custom_shaders: Vec<CustomShader>,
+    shader_errors: Vec<ShaderError>,
+    color_format: wgpu::TextureFormat,
+    depth: DepthConfig,
+    // Only available when storage buffers can be read while drawing
+    packed_materials: Option<(wgpu::BindGroupLayout, MaterialPipelines)>,
+    // Whether models are created with their materials packed, when available
+    pack_materials: bool,
+    // Whether draws of packed materials push their draw data instead of binding it
+    push_constants: bool,
+    shadow_pipeline: wgpu::RenderPipeline,
+    velocity_pipeline: wgpu::RenderPipeline,
+    sampler: wgpu::Sampler,
+    default_textures: Option<DefaultTextures>,
+    models: Vec<GpuModel>,
+    // The scene's models revision the models were created at
+    models_revision: u64,
+    // The scene's materials revision the material uniforms were last written at
+    materials_revision: u64,
+    // Counted while this frame's passes are encoded, then kept for reporting
+    draw_stats: Cell<DrawStats>,
+    last_draw_stats: DrawStats,
+    // Only available alongside the Hi-Z pyramid
+    cull: Option<(wgpu::BindGroupLayout, wgpu::ComputePipeline)>,
+    // Whether this frame's instances have been culled, which the main pass then draws
+    culled: bool,
+}
+
+impl ModelSystem {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        device: &wgpu::Device,
+        camera_bind_group_layout: &wgpu::BindGroupLayout,
+        light_bind_group_layout: &wgpu::BindGroupLayout,
+        shadow_caster_bind_group_layout: &wgpu::BindGroupLayout,
+        hiz_bind_group_layout: Option<&wgpu::BindGroupLayout>,
+        target_pool: &mut RenderTargetPool,
+        packed_materials_supported: bool,
+        ssao_system: &SsaoSystem,
+        color_format: wgpu::TextureFormat,
+        depth: DepthConfig,
+        dimensions: &[u32; 2],
+    ) -> Self {
+        let texture_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        };
+
+        let material_bind_group_layout = memory::create_bind_group_layout(
+            device,
+            &wgpu::BindGroupLayoutDescriptor {
+                label: Some("Material Bind Group Layout"),
+                entries: &[
+                    // Outlines read their width in the vertex stage
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            // Picks the material out of the model's materials buffer
+                            has_dynamic_offset: true,
+                            min_binding_size: wgpu::BufferSize::new(std::mem::size_of::<
+                                MaterialUniform,
+                            >()
+                                as u64),
+                        },
+                        count: None,
+                    },
+                    texture_entry(1),
+                    texture_entry(2),
+                    texture_entry(3),
+                    texture_entry(4),
+                    texture_entry(5),
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 6,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Sampler {
+                            filtering: true,
+                            comparison: false,
+                        },
+                        count: None,
+                    },
+                    texture_entry(7),
+                    texture_entry(8),
+                    texture_entry(9),
+                    texture_entry(10),
+                    texture_entry(11),
+                    texture_entry(12),
+                ],
+            },
+        );
+
+        let transmission_bind_group_layout = memory::create_bind_group_layout(
+            device,
+            &wgpu::BindGroupLayoutDescriptor {
+                label: Some("Transmission Bind Group Layout"),
+                entries: &[
+                    texture_entry(0),
+                    texture_entry(1),
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 2,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Sampler {
+                            filtering: true,
+                            comparison: false,
+                        },
+                        count: None,
+                    },
+                ],
+            },
+        );
+
+        let primitive = wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            front_face: wgpu::FrontFace::Ccw,
+            cull_mode: Some(wgpu::Face::Back),
+            ..Default::default()
+        };
+
+        let layouts = MaterialPipelineLayouts::new(
+            device,
+            &material_bind_group_layout,
+            camera_bind_group_layout,
+            light_bind_group_layout,
+            ssao_system.output_bind_group_layout(),
+            &transmission_bind_group_layout,
+            &[],
+        );
+        let pipelines = MaterialPipelines::new(
+            device,
+            include_str!("shaders/model.wgsl"),
+            &layouts,
+            color_format,
+            depth,
+        );
+
+        // Packed materials pick theirs with push constants where they're supported, otherwise
+        // from a dynamic offset into a uniform buffer. The WGSL front end of this wgpu can't
+        // declare arrays of textures, so rather than one binding array of every image, materials
+        // whose images are the same size share texture arrays
+        let push_constants = device.features().contains(wgpu::Features::PUSH_CONSTANTS);
+        let packed_materials = packed_materials_supported.then(|| {
+            let bind_group_layout = Self::create_packed_bind_group_layout(device, push_constants);
+            let push_constant_range = wgpu::PushConstantRange {
+                stages: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                range: 0..std::mem::size_of::<DrawData>() as u32,
+            };
+            let layouts = MaterialPipelineLayouts::new(
+                device,
+                &bind_group_layout,
+                camera_bind_group_layout,
+                light_bind_group_layout,
+                ssao_system.output_bind_group_layout(),
+                &transmission_bind_group_layout,
+                if push_constants {
+                    std::slice::from_ref(&push_constant_range)
+                } else {
+                    &[]
+                },
+            );
+            let pipelines = MaterialPipelines::new(
+                device,
+                &packed_material_shader(push_constants),
+                &layouts,
+                color_format,
+                depth,
+            );
+            (bind_group_layout, pipelines)
+        });
+
+        let shadow_module = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("Model Shadow Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("shaders/model_shadow.wgsl").into()),
+        });
+
+        let shadow_pipeline_layout = memory::create_pipeline_layout(
+            device,
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("Model Shadow Pipeline Layout"),
+                bind_group_layouts: &[shadow_caster_bind_group_layout],
+                push_constant_ranges: &[],
+            },
+        );
+
+        let shadow_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Model Shadow Pipeline"),
+            layout: Some(&shadow_pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shadow_module,
+                entry_point: "vs_main",
+                buffers: &[ModelVertex::layout(), InstanceData::layout()],
+            },
+            // Thin geometry would let light leak through if back faces were culled
+            primitive: wgpu::PrimitiveState {
+                cull_mode: None,
+                ..primitive
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: depth.shadow().format,
+                depth_write_enabled: true,
+                depth_compare: depth.compare(wgpu::CompareFunction::Less),
+                stencil: wgpu::StencilState::default(),
+                bias: depth.bias(wgpu::DepthBiasState {
+                    constant: 2,
+                    slope_scale: 2.0,
+                    clamp: 0.0,
+                }),
+            }),
+            multisample: wgpu::MultisampleState::default(),
+            fragment: None,
+        });
+
+        let velocity_module = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("Model Velocity Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("shaders/model_velocity.wgsl").into()),
+        });
+
+        let velocity_pipeline_layout = memory::create_pipeline_layout(
+            device,
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("Model Velocity Pipeline Layout"),
+                bind_group_layouts: &[camera_bind_group_layout],
+                push_constant_ranges: &[],
+            },
+        );
+
+        // Drawn over the camera's velocity where the depth prepass kept the surface
+        let velocity_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Model Velocity Pipeline"),
+            layout: Some(&velocity_pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &velocity_module,
+                entry_point: "vs_main",
+                buffers: &[
+                    wgpu::VertexBufferLayout {
+                        array_stride: std::mem::size_of::<ModelVertex>() as wgpu::BufferAddress,
+                        step_mode: wgpu::VertexStepMode::Vertex,
+                        attributes: &ModelVertex::ATTRIBUTES[..1],
+                    },
+                    InstanceData::motion_layout(false),
+                    InstanceData::motion_layout(true),
+                ],
+            },
+            primitive: wgpu::PrimitiveState {
+                cull_mode: None,
+                ..primitive
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: depth.format,
+                depth_write_enabled: false,
+                depth_compare: depth.compare(wgpu::CompareFunction::LessEqual),
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState::default(),
+            fragment: Some(wgpu::FragmentState {
+                module: &velocity_module,
+                entry_point: "fs_main",
+                targets: &[wgpu::ColorTargetState {
+                    format: Texture::VELOCITY_FORMAT,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                }],
+            }),
+        });
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Material Sampler"),
+            address_mode_u: wgpu::AddressMode::Repeat,
+            address_mode_v: wgpu::AddressMode::Repeat,
+            address_mode_w: wgpu::AddressMode::Repeat,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            // Only packed textures have mip levels
+            mipmap_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let opaque_color = Self::create_opaque_color(device, target_pool, color_format, dimensions);
+        let transmission_bind_group = Self::create_transmission_bind_group(
+            device,
+            &transmission_bind_group_layout,
+            ssao_system.texture(),
+            &opaque_color,
+        );
+
+        // Culling reads the Hi-Z pyramid, which is only built where compute shaders are available
+        let cull = hiz_bind_group_layout.map(|hiz_bind_group_layout| {
+            let storage_entry = |binding, read_only| wgpu::BindGroupLayoutEntry {
+                binding,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only },
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            };
+            let cull_bind_group_layout = memory::create_bind_group_layout(
+                device,
+                &wgpu::BindGroupLayoutDescriptor {
+                    label: Some("Model Cull Bind Group Layout"),
+                    entries: &[
+                        storage_entry(0, true),
+                        storage_entry(1, false),
+                        storage_entry(2, true),
+                        storage_entry(3, false),
+                        wgpu::BindGroupLayoutEntry {
+                            binding: 4,
+                            visibility: wgpu::ShaderStages::COMPUTE,
+                            ty: wgpu::BindingType::Buffer {
+                                ty: wgpu::BufferBindingType::Uniform,
+                                has_dynamic_offset: false,
+                                min_binding_size: None,
+                            },
+                            count: None,
+                        },
+                    ],
+                },
+            );
+            let cull_module = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+                label: Some("Model Cull Shader"),
+                source: wgpu::ShaderSource::Wgsl(
+                    shader_snippets::splice(include_str!("shaders/model_cull.wgsl")).into(),
+                ),
+            });
+            let cull_pipeline_layout = memory::create_pipeline_layout(
+                device,
+                &wgpu::PipelineLayoutDescriptor {
+                    label: Some("Model Cull Pipeline Layout"),
+                    bind_group_layouts: &[
+                        camera_bind_group_layout,
+                        hiz_bind_group_layout,
+                        &cull_bind_group_layout,
+                    ],
+                    push_constant_ranges: &[],
+                },
+            );
+            let cull_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some("Model Cull Pipeline"),
+                layout: Some(&cull_pipeline_layout),
+                module: &cull_module,
+                entry_point: "cs_main",
+            });
+            (cull_bind_group_layout, cull_pipeline)
+        });
+
+        Self {
+            material_bind_group_layout,
+            transmission_bind_group_layout,
+            transmission_bind_group,
+            opaque_color,
+            pipelines,
+            layouts,
+            custom_shaders: Vec::new(),
+            shader_errors: Vec::new(),
+            color_format,
+            depth,
+            packed_materials,
+            pack_materials: false,
+            push_constants,
+            shadow_pipeline,
+            velocity_pipeline,
+            sampler,
+            default_textures: None,
+            models: Vec::new(),
+            models_revision: 0,
+            materials_revision: 0,
+            draw_stats: Cell::new(DrawStats::default()),
+            last_draw_stats: DrawStats::default(),
+            cull,
+            culled: false,
+        }
+    }
+
+    // Copied and read while drawing the scene, including probe faces
+    fn create_opaque_color(
+        device: &wgpu::Device,
+        target_pool: &mut RenderTargetPool,
+        color_format: wgpu::TextureFormat,
+        dimensions: &[u32; 2],
+    ) -> Rc<Texture> {
+        target_pool.acquire(
+            device,
+            dimensions[0],
+            dimensions[1],
+            color_format,
+            "Opaque Scene Color Texture",
+            FramePhase::Probes..=FramePhase::Scene,
+        )
+    }
+
+    fn create_transmission_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        ambient_occlusion: &Texture,
+        opaque_color: &Texture,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Transmission Bind Group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&ambient_occlusion.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&opaque_color.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::Sampler(&opaque_color.sampler),
+                },
+            ],
+        })
+    }
+
+    pub fn resize(
+        &mut self,
+        device: &wgpu::Device,
+        target_pool: &mut RenderTargetPool,
+        color_format: wgpu::TextureFormat,
+        ambient_occlusion: &Texture,
+        dimensions: &[u32; 2],
+    ) {
+        self.opaque_color =
+            Self::create_opaque_color(device, target_pool, color_format, dimensions);
+        self.transmission_bind_group = Self::create_transmission_bind_group(
+            device,
+            &self.transmission_bind_group_layout,
+            ambient_occlusion,
+            &self.opaque_color,
+        );
+    }
+
+    // Shaders that fail are reported once and left out until they're reloaded
+    fn compile_custom_shaders(&mut self, device: &wgpu::Device, desc: &ModelDesc) {
+        for path in desc
+            .materials
+            .iter()
+            .filter_map(|material| material.shader.as_ref())
+        {
+            if self.custom_shader(Some(path)).is_some() {
+                continue;
+            }
+            // Materials whose shader fails are drawn with a magenta checker so they stand out
+            let source = custom_material_shader(path).unwrap_or_else(|error| {
+                self.shader_errors.push(error);
+                material_shader(
+                    "model_error_surface.wgsl",
+                    include_str!("shaders/model_error_surface.wgsl"),
+                )
+                .source
+            });
+            let pipelines = MaterialPipelines::new(
+                device,
+                &source,
+                &self.layouts,
+                self.color_format,
+                self.depth,
+            );
+            self.custom_shaders.push(CustomShader {
+                path: path.clone(),
+                pipelines,
+            });
+        }
+    }
+
+    fn custom_shader(&self, path: Option<&Path>) -> Option<usize> {
+        let path = path?;
+        self.custom_shaders
+            .iter()
+            .position(|shader| shader.path == path)
+    }
+
+    // Material shaders that failed to compile since this was last called, which draw with the
+    // error surface instead
+    pub fn drain_shader_errors(&mut self) -> Vec<ShaderError> {
+        std::mem::take(&mut self.shader_errors)
+    }
+
+    // Material shaders are compiled again as models are created, such as after their files change
+    pub fn reload_custom_shaders(&mut self) {
+        self.custom_shaders.clear();
+        self.models.clear();
+    }
+
+    fn create_model(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        default_textures: &DefaultTextures,
+        render_cameras: &[&Texture],
+        desc: &ModelDesc,
+        index: usize,
+    ) -> Result<GpuModel> {
+        let name = desc
+            .name
+            .clone()
+            .unwrap_or_else(|| format!("Model {}", index));
+        let default_material = Material::default();
+        let error_material = Material::error();
+        let materials = desc
+            .materials
+            .iter()
+            .chain([&default_material, &error_material])
+            .collect::<Vec<_>>();
+
+        // Render cameras are drawn to every frame, so they can't be packed with the other images
+        let packed = if self.pack_materials
+            && materials
+                .iter()
+                .all(|material| material.render_camera.is_none() && material.shader.is_none())
+        {
+            self.create_packed_bindings(device, queue, desc, &name, &materials)?
+        } else {
+            None
+        };
+        let bindings = match packed {
+            Some(bindings) => bindings,
+            None => self.create_separate_bindings(
+                device,
+                queue,
+                default_textures,
+                render_cameras,
+                desc,
+                &name,
+                &materials,
+            )?,
+        };
+
+        let materials = materials
+            .iter()
+            .map(|material| GpuMaterial {
+                alpha_mode: material.alpha_mode,
+                transmissive: material.is_transmissive(),
+                outlined: material.has_outline(),
+                cull_mode: face_culling(material, CullMode::Material),
+                textures: material.textures(),
+                shader: self.custom_shader(material.shader.as_deref()),
+            })
+            .collect::<Vec<_>>();
+
+        let mut meshes = Vec::with_capacity(desc.meshes.len());
+        for mesh in desc.meshes.iter() {
+            let mut local_bounds = Aabb::default();
+            let mut primitives = Vec::with_capacity(mesh.primitives.len());
+            for primitive in mesh.primitives.iter() {
+                if primitive.vertices.is_empty() || primitive.indices.is_empty() {
+                    continue;
+                }
+                let material = primitive_material(primitive, desc);
+                local_bounds.merge(&primitive.bounds());
+                primitives.push(GpuPrimitive::new(device, primitive, material, &name));
+            }
+            meshes.push(GpuMesh::new(device, primitives, local_bounds));
+        }
+
+        Ok(GpuModel {
+            name,
+            meshes,
+            static_batches: None,
+            materials,
+            bindings,
+            images_revision: 0,
+        })
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn create_separate_bindings(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        default_textures: &DefaultTextures,
+        render_cameras: &[&Texture],
+        desc: &ModelDesc,
+        name: &str,
+        materials: &[&Material],
+    ) -> Result<MaterialBindings> {
+        // Color images are stored as sRGB while data images such as normal maps are linear
+        let mut textures = Vec::new();
+        let mut texture_indices = HashMap::new();
+        let mut texture_index = |image: Option<usize>, srgb: bool| -> Result<Option<usize>> {
+            let image = match image {
+                Some(image) => image,
+                None => return Ok(None),
+            };
+            if let Some(index) = texture_indices.get(&(image, srgb)) {
+                return Ok(Some(*index));
+            }
+            let rgba = desc
+                .images
+                .get(image)
+                .cloned()
+                .unwrap_or_else(missing_image);
+            let format = if srgb {
+                wgpu::TextureFormat::Rgba8UnormSrgb
+            } else {
+                wgpu::TextureFormat::Rgba8Unorm
+            };
+            let texture = Texture::from_image_with_format(
+                device,
+                queue,
+                &rgba,
+                format,
+                Some(&format!("{} Image {}", name, image)),
+            )?;
+            textures.push(texture);
+            texture_indices.insert((image, srgb), textures.len() - 1);
+            Ok(Some(textures.len() - 1))
+        };
+
+        // Materials with the same textures share a bind group, and pick their uniform from one
+        // buffer with a dynamic offset, so draws switching between them only change the offset
+        let mut material_bindings = Vec::with_capacity(materials.len());
+        for material in materials.iter() {
+            let render_camera = match material.render_camera {
+                Some(index) if index >= render_cameras.len() => {
+                    bail!("Material refers to missing render camera {}!", index)
+                }
+                Some(index) => Some((index, material.planar_reflection)),
+                None => None,
+            };
+            let indices = material
+                .textures()
+                .into_iter()
+                .zip(SRGB_TEXTURES)
+                .map(|(image, srgb)| texture_index(image, srgb))
+                .collect::<Result<Vec<_>>>()?;
+            material_bindings.push((indices, render_camera));
+        }
+        let (groups, material_groups) = group_bindings(&material_bindings);
+
+        let stride = material_uniform_stride(&device.limits());
+        let mut contents = vec![0; materials.len() * stride as usize];
+        for (material, data) in materials.iter().zip(contents.chunks_mut(stride as usize)) {
+            data[..std::mem::size_of::<MaterialUniform>()]
+                .copy_from_slice(bytemuck::cast_slice(&[MaterialUniform::new(material)]));
+        }
+        let materials_buffer = memory::create_buffer_init(
+            device,
+            MemoryCategory::Transient,
+            &wgpu::util::BufferInitDescriptor {
+                label: Some(&format!("{} Materials Buffer", name)),
+                contents: &contents,
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            },
+        );
+
+        let bind_groups = groups
+            .iter()
+            .enumerate()
+            .map(|(group_index, (indices, render_camera))| {
+                let fallbacks = [
+                    &default_textures.white,
+                    &default_textures.white_linear,
+                    &default_textures.flat_normal,
+                    &default_textures.white_linear,
+                    &default_textures.white,
+                    &default_textures.white_linear,
+                    &default_textures.flat_normal,
+                    &default_textures.white_linear,
+                    &default_textures.white,
+                    &default_textures.white,
+                    &default_textures.white,
+                ];
+                let mut views = indices
+                    .iter()
+                    .zip(fallbacks)
+                    .map(|(index, fallback)| index.map_or(fallback, |index| &textures[index]))
+                    .collect::<Vec<_>>();
+                if let Some((render_camera, planar_reflection)) = *render_camera {
+                    let render_camera = render_cameras[render_camera];
+                    if !planar_reflection {
+                        views[0] = render_camera;
+                    }
+                    views[4] = render_camera;
+                }
+                // The sampler sits between the first five textures and the extension textures
+                let mut entries = vec![
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                            buffer: &materials_buffer,
+                            offset: 0,
+                            size: wgpu::BufferSize::new(
+                                std::mem::size_of::<MaterialUniform>() as u64
+                            ),
+                        }),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 6,
+                        resource: wgpu::BindingResource::Sampler(&self.sampler),
+                    },
+                ];
+                for (index, texture) in views.iter().enumerate() {
+                    let binding = if index < 5 { index + 1 } else { index + 2 };
+                    entries.push(wgpu::BindGroupEntry {
+                        binding: binding as u32,
+                        resource: wgpu::BindingResource::TextureView(&texture.view),
+                    });
+                }
+                device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: Some(&format!("{} Bind Group {}", name, group_index)),
+                    layout: &self.material_bind_group_layout,
+                    entries: &entries,
+                })
+            })
+            .collect::<Vec<_>>();
+
+        Ok(MaterialBindings::Separate {
+            materials_buffer,
+            stride,
+            bind_groups,
+            material_groups,
+            _textures: textures,
+        })
+    }
+
+    // Packs the images of each kind of texture into the layers of texture arrays, with one bind
+    // group for each group of materials whose images are the same size. Gives up when an image is
+    // missing or too large to be a layer
+    fn create_packed_bindings(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        desc: &ModelDesc,
+        name: &str,
+        materials: &[&Material],
+    ) -> Result<Option<MaterialBindings>> {
+        let layout = match self.packed_materials.as_ref() {
+            Some((layout, _)) => layout,
+            None => return Ok(None),
+        };
+        let limits = device.limits();
+
+        let mut material_textures = Vec::with_capacity(materials.len());
+        for material in materials.iter() {
+            let mut textures = [None; MATERIAL_TEXTURE_COUNT];
+            for (texture, image) in textures.iter_mut().zip(material.textures()) {
+                let image = match image {
+                    Some(image) => image,
+                    None => continue,
+                };
+                // Missing images are drawn with a placeholder, which only separate bindings have
+                let size = match desc.images.get(image) {
+                    Some(image) => image.dimensions(),
+                    None => return Ok(None),
+                };
+                if size.0.max(size.1) > limits.max_texture_dimension_2d {
+                    return Ok(None);
+                }
+                *texture = Some((image, size));
+            }
+            material_textures.push(textures);
+        }
+        let (groups, placements) =
+            group_materials(&material_textures, limits.max_texture_array_layers as usize);
+
+        let layers = placements
+            .iter()
+            .flat_map(|(_, layers)| layers.iter().copied())
+            .collect::<Vec<_>>();
+        let uniforms = materials
+            .iter()
+            .map(|material| MaterialUniform::new(material))
+            .collect::<Vec<_>>();
+        let materials_buffer = memory::create_buffer_init(
+            device,
+            MemoryCategory::Transient,
+            &wgpu::util::BufferInitDescriptor {
+                label: Some(&format!("{} Packed Materials Buffer", name)),
+                contents: bytemuck::cast_slice(&uniforms),
+                usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            },
+        );
+        let layers_buffer = memory::create_buffer_init(
+            device,
+            MemoryCategory::Transient,
+            &wgpu::util::BufferInitDescriptor {
+                label: Some(&format!("{} Packed Layers Buffer", name)),
+                contents: bytemuck::cast_slice(&layers),
+                usage: wgpu::BufferUsages::STORAGE,
+            },
+        );
+
+        // Without push constants the draw data of each material sits at its own dynamic offset
+        let draws = (!self.push_constants).then(|| {
+            let stride = limits
+                .min_uniform_buffer_offset_alignment
+                .max(std::mem::size_of::<DrawData>() as u32);
+            let mut contents = vec![0; materials.len() * stride as usize];
+            for (material, data) in contents.chunks_mut(stride as usize).enumerate() {
+                let draw_data = DrawData {
+                    material: material as u32,
+                };
+                data[..std::mem::size_of::<DrawData>()]
+                    .copy_from_slice(bytemuck::cast_slice(&[draw_data]));
+            }
+            let buffer = memory::create_buffer_init(
+                device,
+                MemoryCategory::Transient,
+                &wgpu::util::BufferInitDescriptor {
+                    label: Some(&format!("{} Packed Draw Buffer", name)),
+                    contents: &contents,
+                    usage: wgpu::BufferUsages::UNIFORM,
+                },
+            );
+            (buffer, stride)
+        });
+
+        let mut textures = Vec::with_capacity(groups.len() * MATERIAL_TEXTURE_COUNT);
+        let mut bind_groups = Vec::with_capacity(groups.len());
+        for (group_index, group) in groups.iter().enumerate() {
+            let group_textures = SRGB_TEXTURES
+                .into_iter()
+                .enumerate()
+                .map(|(texture_index, srgb)| {
+                    let images = group.images[texture_index]
+                        .iter()
+                        .map(|image| &desc.images[*image])
+                        .collect::<Vec<_>>();
+                    Self::create_packed_texture(
+                        device,
+                        queue,
+                        &format!(
+                            "{} Packed Texture {} Group {}",
+                            name, texture_index, group_index
+                        ),
+                        &images,
+                        group.sizes[texture_index],
+                        srgb,
+                    )
+                })
+                .collect::<Vec<_>>();
+            let views = group_textures
+                .iter()
+                .map(|texture| {
+                    texture.create_view(&wgpu::TextureViewDescriptor {
+                        dimension: Some(wgpu::TextureViewDimension::D2Array),
+                        ..Default::default()
+                    })
+                })
+                .collect::<Vec<_>>();
+
+            // Bound like separate materials, with the layers of each texture after them
+            let mut entries = vec![
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: materials_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 6,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 13,
+                    resource: layers_buffer.as_entire_binding(),
+                },
+            ];
+            for (index, view) in views.iter().enumerate() {
+                let binding = if index < 5 { index + 1 } else { index + 2 };
+                entries.push(wgpu::BindGroupEntry {
+                    binding: binding as u32,
+                    resource: wgpu::BindingResource::TextureView(view),
+                });
+            }
+            if let Some((buffer, _)) = draws.as_ref() {
+                entries.push(wgpu::BindGroupEntry {
+                    binding: 14,
+                    resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                        buffer,
+                        offset: 0,
+                        size: wgpu::BufferSize::new(std::mem::size_of::<DrawData>() as u64),
+                    }),
+                });
+            }
+
+            bind_groups.push(device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some(&format!("{} Packed Bind Group {}", name, group_index)),
+                layout,
+                entries: &entries,
+            }));
+            textures.extend(group_textures);
+        }
+
+        Ok(Some(MaterialBindings::Packed {
+            materials_buffer,
+            bind_groups,
+            material_groups: placements.iter().map(|(group, _)| *group).collect(),
+            draws,
+            _textures: textures,
+        }))
+    }
+
+    // A texture array with a layer for each image, which are all the given size, and every mip
+    // level of them. Kinds of texture no material of the group has get a single empty texel
+    fn create_packed_texture(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        label: &str,
+        images: &[&image::DynamicImage],
+        size: Option<(u32, u32)>,
+        srgb: bool,
+    ) -> Tracked<wgpu::Texture> {
+        let (width, height) = size.unwrap_or((1, 1));
+        let mip_level_count = u32::BITS - width.max(height).leading_zeros();
+        let texture = memory::create_texture(
+            device,
+            MemoryCategory::Textures,
+            &wgpu::TextureDescriptor {
+                label: Some(label),
+                size: wgpu::Extent3d {
+                    width,
+                    height,
+                    depth_or_array_layers: images.len().max(1) as u32,
+                },
+                mip_level_count,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: if srgb {
+                    wgpu::TextureFormat::Rgba8UnormSrgb
+                } else {
+                    wgpu::TextureFormat::Rgba8Unorm
+                },
+                usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            },
+        );
+        for (layer, image) in images.iter().enumerate() {
+            for (mip_level, rgba) in mip_chain(image).iter().enumerate() {
+                let (width, height) = rgba.dimensions();
+                queue.write_texture(
+                    wgpu::ImageCopyTexture {
+                        texture: &texture,
+                        mip_level: mip_level as u32,
+                        origin: wgpu::Origin3d {
+                            x: 0,
+                            y: 0,
+                            z: layer as u32,
+                        },
+                        aspect: wgpu::TextureAspect::All,
+                    },
+                    rgba,
+                    wgpu::ImageDataLayout {
+                        offset: 0,
+                        bytes_per_row: std::num::NonZeroU32::new(4 * width),
+                        rows_per_image: std::num::NonZeroU32::new(height),
+                    },
+                    wgpu::Extent3d {
+                        width,
+                        height,
+                        depth_or_array_layers: 1,
+                    },
+                );
+            }
+        }
+        texture
+    }
+
+    fn create_packed_bind_group_layout(
+        device: &wgpu::Device,
+        push_constants: bool,
+    ) -> wgpu::BindGroupLayout {
+        let storage_entry = |binding, visibility| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only: true },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let texture_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                view_dimension: wgpu::TextureViewDimension::D2Array,
+                multisampled: false,
+            },
+            count: None,
+        };
+        let mut entries = vec![
+            // Outlines read their width in the vertex stage
+            storage_entry(0, wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT),
+            texture_entry(1),
+            texture_entry(2),
+            texture_entry(3),
+            texture_entry(4),
+            texture_entry(5),
+            wgpu::BindGroupLayoutEntry {
+                binding: 6,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Sampler {
+                    filtering: true,
+                    comparison: false,
+                },
+                count: None,
+            },
+            texture_entry(7),
+            texture_entry(8),
+            texture_entry(9),
+            texture_entry(10),
+            texture_entry(11),
+            texture_entry(12),
+            storage_entry(13, wgpu::ShaderStages::FRAGMENT),
+        ];
+        if !push_constants {
+            entries.push(wgpu::BindGroupLayoutEntry {
+                binding: 14,
+                visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: true,
+                    min_binding_size: wgpu::BufferSize::new(std::mem::size_of::<DrawData>() as u64),
+                },
+                count: None,
+            });
+        }
+        memory::create_bind_group_layout(
+            device,
+            &wgpu::BindGroupLayoutDescriptor {
+                label: Some("Packed Material Bind Group Layout"),
+                entries: &entries,
+            },
+        )
+    }
+
+    fn create_instance_buffer(device: &wgpu::Device, count: usize) -> Tracked<wgpu::Buffer> {
+        memory::create_buffer(
+            device,
+            MemoryCategory::Meshes,
+            &wgpu::BufferDescriptor {
+                label: Some("Model Instance Buffer"),
+                size: (count.max(1) * std::mem::size_of::<InstanceData>()) as wgpu::BufferAddress,
+                usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            },
+        )
+    }
+
+    pub fn update(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        scene: &Scene,
+        render_cameras: &[&Texture],
+        settings: &Settings,
+    ) -> Result<()> {
+        if self.default_textures.is_none() {
+            self.default_textures = Some(DefaultTextures::new(device, queue)?);
+        }
+        self.last_draw_stats = self.draw_stats.take();
+        self.culled = false;
+
+        // Models are created again to bind their materials the other way
+        let pack_materials = settings.pack_materials && self.packed_materials.is_some();
+        if self.models_revision != scene.models_revision() || self.pack_materials != pack_materials
+        {
+            self.models.clear();
+            self.models_revision = scene.models_revision();
+            self.pack_materials = pack_materials;
+        }
+
+        // Edited materials are uploaded again, and models whose materials now use other images or
+        // whose images were replaced are created again
+        let materials_edited = self.materials_revision != scene.materials_revision();
+        self.materials_revision = scene.materials_revision();
+        for index in 0..self.models.len() {
+            let desc = &scene.models[index];
+            let images_revision = scene.images_revision(ModelHandle(index));
+            let rebound = materials_edited
+                && desc
+                    .materials
+                    .iter()
+                    .zip(self.models[index].materials.iter())
+                    .any(|(material, gpu_material)| {
+                        material.textures() != gpu_material.textures
+                            || material.shader.as_deref()
+                                != gpu_material
+                                    .shader
+                                    .map(|shader| self.custom_shaders[shader].path.as_path())
+                    });
+            if rebound || self.models[index].images_revision != images_revision {
+                self.compile_custom_shaders(device, desc);
+                let default_textures = self.default_textures.as_ref().unwrap();
+                let mut model = self.create_model(
+                    device,
+                    queue,
+                    default_textures,
+                    render_cameras,
+                    desc,
+                    index,
+                )?;
+                model.images_revision = images_revision;
+                self.models[index] = model;
+            }
+        }
+
+        while self.models.len() < scene.models.len() {
+            let desc = &scene.models[self.models.len()];
+            self.compile_custom_shaders(device, desc);
+            let default_textures = self.default_textures.as_ref().unwrap();
+            let index = self.models.len();
+            let mut model =
+                self.create_model(device, queue, default_textures, render_cameras, desc, index)?;
+            model.images_revision = scene.images_revision(ModelHandle(index));
+            self.models.push(model);
+        }
+
+        for (desc, model) in scene.models.iter().zip(self.models.iter_mut()) {
+            let default_material = Material::default();
+            let error_material = Material::error();
+            for (index, material) in desc
+                .materials
+                .iter()
+                .chain([&default_material, &error_material])
+                .enumerate()
+                .take(model.materials.len())
+            {
+                let gpu_material = &mut model.materials[index];
+                gpu_material.alpha_mode = material.alpha_mode;
+                gpu_material.transmissive = material.is_transmissive();
+                gpu_material.outlined = material.has_outline();
+                gpu_material.cull_mode = face_culling(material, settings.cull_mode);
+                if materials_edited {
+                    model.write_material(queue, index, &MaterialUniform::new(material));
+                }
+            }
+
+            // Animated models move every frame, so they're never merged
+            if !settings.static_batching || desc.animations.is_some() {
+                model.static_batches = None;
+            } else if model
+                .static_batches
+                .as_ref()
+                .is_none_or(|batches| batches.is_stale(desc, settings.rebuild_static_batches))
+            {
+                model.static_batches = Some(StaticBatches::new(device, desc, &model.name)?);
+            }
+
+            let transform = scene.local_transform(&desc.transform);
+            if let Some(batches) = model.static_batches.as_mut() {
+                // The merged vertices are already placed relative to the model
+                for (mesh, layers) in batches.meshes.iter_mut().zip(batches.layers.iter()) {
+                    mesh.place(device, queue, vec![(*layers, transform)]);
+                }
+                continue;
+            }
+
+            for (index, mesh) in model.meshes.iter_mut().enumerate() {
+                let placements = desc
+                    .instances
+                    .iter()
+                    .filter(|instance| instance.mesh == index)
+                    .map(|instance| {
+                        let transform = scene.local_transform(&desc.instance_transform(instance));
+                        (instance.layers, transform)
+                    })
+                    .collect::<Vec<_>>();
+                mesh.place(device, queue, placements);
+            }
+        }
+
+        if let Some((cull_bind_group_layout, _)) = self.cull.as_ref() {
+            for model in self.models.iter_mut() {
+                for mesh in model.drawn_meshes_mut() {
+                    mesh.prepare_culling(
+                        device,
+                        queue,
+                        cull_bind_group_layout,
+                        settings.occlusion_culling,
+                    );
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // Tests every instance against the pyramid of the depth prepass, so the main pass only draws
+    // those that can be seen. Passes encoded before this, such as probe faces, draw everything
+    pub fn cull(
+        &mut self,
+        encoder: &mut wgpu::CommandEncoder,
+        camera_bind_group: &wgpu::BindGroup,
+        hiz_bind_group: &wgpu::BindGroup,
+    ) {
+        let cull_pipeline = match self.cull.as_ref() {
+            Some((_, cull_pipeline)) => cull_pipeline,
+            None => return,
+        };
+        let culled = self
+            .models
+            .iter()
+            .flat_map(|model| model.drawn_meshes())
+            .filter_map(|mesh| mesh.culling.as_ref())
+            .collect::<Vec<_>>();
+        if culled.is_empty() {
+            return;
+        }
+        for culling in culled.iter() {
+            encoder.copy_buffer_to_buffer(
+                &culling.reset_buffer,
+                0,
+                &culling.arguments_buffer,
+                0,
+                (culling.argument_count * std::mem::size_of::<DrawArguments>())
+                    as wgpu::BufferAddress,
+            );
+        }
+        {
+            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("Model Cull Pass"),
+            });
+            compute_pass.set_pipeline(cull_pipeline);
+            compute_pass.set_bind_group(0, camera_bind_group, &[]);
+            compute_pass.set_bind_group(1, hiz_bind_group, &[]);
+            for culling in culled.iter() {
+                compute_pass.set_bind_group(2, &culling.bind_group, &[]);
+                compute_pass.dispatch(
+                    (culling.instance_count as u32).div_ceil(CULL_WORKGROUP_SIZE),
+                    1,
+                    1,
+                );
+            }
+        }
+        self.culled = true;
+    }
+
+    // State changes made drawing models during the last frame
+    pub fn draw_stats(&self) -> DrawStats {
+        self.last_draw_stats
+    }
+
+    pub fn static_batch_draws(&self) -> (usize, usize) {
+        self.models
+            .iter()
+            .filter_map(|model| model.static_batches.as_ref())
+            .fold((0, 0), |(merged, unmerged), batches| {
+                let draws = batches
+                    .meshes
+                    .iter()
+                    .map(|mesh| mesh.primitives.len())
+                    .sum::<usize>();
+                (merged + draws, unmerged + batches.unbatched_draws)
+            })
+    }
+
+    // Merges the models again on the next update, picking up instances that moved
+    pub fn clear_static_batches(&mut self) {
+        for model in self.models.iter_mut() {
+            model.static_batches = None;
+        }
+    }
+
+    // Bounds of every model instance, used to fit the shadow map
+    pub fn bounds(&self) -> Aabb {
+        let mut bounds = Aabb::default();
+        for mesh in self.models.iter().flat_map(|model| model.drawn_meshes()) {
+            bounds.merge(&mesh.bounds);
+        }
+        bounds
+    }
+
+    fn visible_meshes<'a: 'b, 'b>(
+        &'a self,
+        frustum: &'b Frustum,
+        filter: impl Fn(u32) -> bool + Copy + 'b,
+    ) -> impl Iterator<Item = (&'a GpuModel, &'a GpuMesh)> + 'b {
+        self.models.iter().flat_map(move |model| {
+            model
+                .drawn_meshes()
+                .iter()
+                .filter(move |mesh| {
+                    mesh.instance_count > 0
+                        && mesh.has_batch(filter)
+                        && frustum.intersects_aabb(&mesh.bounds)
+                })
+                .map(move |mesh| (model, mesh))
+        })
+    }
+
+    // Grouping draws by pipeline and material keeps state changes down, while drawing nearer
+    // surfaces first lets depth testing reject more of what's behind them
+    fn sorted_opaque_draws<'a>(
+        &'a self,
+        frustum: &Frustum,
+        camera: &Camera,
+        filter: impl Fn(u32) -> bool + Copy,
+    ) -> Vec<OpaqueDraw<'a>> {
+        let mut draws = Vec::new();
+        for (model_index, model) in self.models.iter().enumerate() {
+            for mesh in model.drawn_meshes() {
+                if mesh.instance_count == 0
+                    || !mesh.has_batch(filter)
+                    || !frustum.intersects_aabb(&mesh.bounds)
+                {
+                    continue;
+                }
+                let distance = glm::distance2(&camera.position, &mesh.bounds.center());
+                for (primitive_index, primitive) in mesh.primitives.iter().enumerate() {
+                    let material = &model.materials[primitive.material];
+                    if !material.is_opaque() {
+                        continue;
+                    }
+                    draws.push(OpaqueDraw {
+                        key: (
+                            cull_order(material.cull_mode),
+                            model_index,
+                            primitive.material,
+                        ),
+                        distance,
+                        model,
+                        mesh,
+                        primitive,
+                        primitive_index,
+                    });
+                }
+            }
+        }
+        draws.sort_by(|a, b| a.key.cmp(&b.key).then(a.distance.total_cmp(&b.distance)));
+        draws
+    }
+
+    // Only instances on the light's layers that don't opt out of casting shadows are drawn
+    pub fn render_shadows<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        shadow_caster_bind_group: &'a wgpu::BindGroup,
+        light_frustum: &Frustum,
+        light_layers: u32,
+    ) {
+        let filter = move |instance| layers::casts_shadow(instance, light_layers);
+        let mut encoder = DrawEncoder::new(render_pass, &self.draw_stats);
+        encoder.set_pipeline(&self.shadow_pipeline);
+        encoder.set_bind_group(0, shadow_caster_bind_group);
+        for (model, mesh) in self.visible_meshes(light_frustum, filter) {
+            encoder.mark_model(model);
+            encoder.set_vertex_buffer(1, &mesh.instance_buffer);
+            for primitive in mesh.primitives.iter() {
+                // Transparent surfaces let the light through
+                if !model.materials[primitive.material].is_opaque() {
+                    continue;
+                }
+                Self::draw_primitive(&mut encoder, mesh, primitive, filter);
+            }
+        }
+    }
+
+    // Writes the depth of opaque surfaces ahead of screen space ambient occlusion
+    pub fn render_depth<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        camera_bind_group: &'a wgpu::BindGroup,
+        camera: &Camera,
+        frustum: &Frustum,
+    ) {
+        let camera_layers = camera.layers;
+        let filter = move |instance| layers::visible(instance, camera_layers);
+        let mut encoder = DrawEncoder::new(render_pass, &self.draw_stats);
+        encoder.set_bind_group(0, camera_bind_group);
+        for draw in self.sorted_opaque_draws(frustum, camera, filter) {
+            let material = &draw.model.materials[draw.primitive.material];
+            encoder.set_pipeline(
+                self.material_pipelines(draw.model, draw.primitive.material)
+                    .depth_pipeline
+                    .get(material.cull_mode),
+            );
+            draw.model
+                .bind_material(&mut encoder, draw.primitive.material);
+            encoder.set_vertex_buffer(1, &draw.mesh.instance_buffer);
+            Self::draw_primitive(&mut encoder, draw.mesh, draw.primitive, filter);
+        }
+    }
+
+    // Writes the screen space motion of opaque surfaces since the previous frame
+    pub fn render_velocity<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        camera_bind_group: &'a wgpu::BindGroup,
+        frustum: &Frustum,
+        camera_layers: u32,
+    ) {
+        let filter = move |instance| layers::visible(instance, camera_layers);
+        let mut encoder = DrawEncoder::new(render_pass, &self.draw_stats);
+        encoder.set_pipeline(&self.velocity_pipeline);
+        encoder.set_bind_group(0, camera_bind_group);
+        for (model, mesh) in self.visible_meshes(frustum, filter) {
+            encoder.mark_model(model);
+            encoder.set_vertex_buffer(1, &mesh.instance_buffer);
+            encoder.set_vertex_buffer(2, &mesh.previous_instance_buffer);
+            for primitive in mesh.primitives.iter() {
+                if !model.materials[primitive.material].is_opaque() {
+                    continue;
+                }
+                Self::draw_primitive(&mut encoder, mesh, primitive, filter);
+            }
+        }
+    }
+
+    pub fn copy_scene_color(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        scene_color: &Texture,
+        dimensions: &[u32; 2],
+    ) {
+        let transmissive = self
+            .models
+            .iter()
+            .flat_map(|model| model.materials.iter())
+            .any(|material| material.transmissive);
+        if !transmissive {
+            return;
+        }
+        encoder.copy_texture_to_texture(
+            scene_color.texture.as_image_copy(),
+            self.opaque_color.texture.as_image_copy(),
+            wgpu::Extent3d {
+                width: dimensions[0],
+                height: dimensions[1],
+                depth_or_array_layers: 1,
+            },
+        );
+    }
+
+    pub fn render<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        camera_bind_group: &'a wgpu::BindGroup,
+        light_bind_group: &'a wgpu::BindGroup,
+        ambient_occlusion_bind_group: &'a wgpu::BindGroup,
+        camera: &Camera,
+        frustum: &Frustum,
+    ) {
+        let camera_layers = camera.layers;
+        let filter = move |instance| layers::visible(instance, camera_layers);
+        let mut encoder = DrawEncoder::new(render_pass, &self.draw_stats);
+        encoder.set_bind_group(0, camera_bind_group);
+        encoder.set_bind_group(2, light_bind_group);
+        encoder.set_bind_group(3, ambient_occlusion_bind_group);
+
+        let draws = self.sorted_opaque_draws(frustum, camera, filter);
+        for draw in draws.iter() {
+            let material = &draw.model.materials[draw.primitive.material];
+            encoder.set_pipeline(
+                self.material_pipelines(draw.model, draw.primitive.material)
+                    .pipeline
+                    .get(material.cull_mode),
+            );
+            draw.model
+                .bind_material(&mut encoder, draw.primitive.material);
+            encoder.set_stencil_reference(draw.stencil_reference());
+            self.draw_opaque(&mut encoder, draw, filter);
+        }
+
+        for draw in draws.iter() {
+            let material = &draw.model.materials[draw.primitive.material];
+            if !material.outlined {
+                continue;
+            }
+            encoder.set_pipeline(
+                &self
+                    .material_pipelines(draw.model, draw.primitive.material)
+                    .outline_pipeline,
+            );
+            encoder.set_stencil_reference(draw.stencil_reference());
+            draw.model
+                .bind_material(&mut encoder, draw.primitive.material);
+            self.draw_opaque(&mut encoder, draw, filter);
+        }
+    }
+
+    // Draws the instances that survived culling when they've been culled this frame
+    fn draw_opaque<'a>(
+        &self,
+        encoder: &mut DrawEncoder<'a, '_>,
+        draw: &OpaqueDraw<'a>,
+        filter: impl Fn(u32) -> bool,
+    ) {
+        let culling = match draw.mesh.culling.as_ref().filter(|_| self.culled) {
+            Some(culling) => culling,
+            None => {
+                encoder.set_vertex_buffer(1, &draw.mesh.instance_buffer);
+                Self::draw_primitive(encoder, draw.mesh, draw.primitive, filter);
+                return;
+            }
+        };
+        encoder.set_vertex_buffer(0, &draw.primitive.vertex_buffer);
+        encoder.set_index_buffer(&draw.primitive.index_buffer);
+        let instance_size = std::mem::size_of::<InstanceData>() as wgpu::BufferAddress;
+        let argument_size = std::mem::size_of::<DrawArguments>() as wgpu::BufferAddress;
+        for (index, batch) in draw.mesh.batches.iter().enumerate() {
+            if !filter(batch.layers) {
+                continue;
+            }
+            encoder.set_vertex_buffer_from(
+                1,
+                &culling.culled_buffer,
+                batch.instances.start as wgpu::BufferAddress * instance_size,
+            );
+            let argument = index * draw.mesh.primitives.len() + draw.primitive_index;
+            encoder.draw_indexed_indirect(
+                &culling.arguments_buffer,
+                argument as wgpu::BufferAddress * argument_size,
+            );
+        }
+    }
+
+    // Transmissive and blended surfaces, drawn over the finished opaque scene, followed by
+    // everything on the overlay layer
+    pub fn render_transparent<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        camera_bind_group: &'a wgpu::BindGroup,
+        light_bind_group: &'a wgpu::BindGroup,
+        camera: &Camera,
+        frustum: &Frustum,
+    ) {
+        let filter = move |instance| layers::visible(instance, camera.layers);
+        let mut encoder = DrawEncoder::new(render_pass, &self.draw_stats);
+        encoder.set_bind_group(0, camera_bind_group);
+        encoder.set_bind_group(2, light_bind_group);
+        encoder.set_bind_group(3, &self.transmission_bind_group);
+
+        let mut blended = Vec::new();
+        for (model, mesh) in self.visible_meshes(frustum, filter) {
+            for primitive in mesh.primitives.iter() {
+                let material = &model.materials[primitive.material];
+                if material.transmissive {
+                    encoder.set_pipeline(
+                        self.material_pipelines(model, primitive.material)
+                            .transmission_pipeline
+                            .get(material.cull_mode),
+                    );
+                    model.bind_material(&mut encoder, primitive.material);
+                    encoder.set_vertex_buffer(1, &mesh.instance_buffer);
+                    Self::draw_primitive(&mut encoder, mesh, primitive, filter);
+                } else if material.alpha_mode == AlphaMode::Blend {
+                    blended.push((model, mesh, primitive));
+                }
+            }
+        }
+
+        // Blended surfaces are drawn back to front over everything else
+        let distance = |mesh: &GpuMesh| glm::distance2(&camera.position, &mesh.bounds.center());
+        blended.sort_by(|(_, a, _), (_, b, _)| distance(b).total_cmp(&distance(a)));
+        for (model, mesh, primitive) in blended {
+            let material = &model.materials[primitive.material];
+            encoder.set_pipeline(
+                self.material_pipelines(model, primitive.material)
+                    .blend_pipeline
+                    .get(material.cull_mode),
+            );
+            encoder.set_vertex_buffer(1, &mesh.instance_buffer);
+            model.bind_material(&mut encoder, primitive.material);
+            Self::draw_primitive(&mut encoder, mesh, primitive, filter);
+        }
+
+        // Overlays ignore depth, so they're also drawn back to front to cover each other
+        let overlay = move |instance| layers::overlay(instance, camera.layers);
+        let mut overlays = self.visible_meshes(frustum, overlay).collect::<Vec<_>>();
+        overlays.sort_by(|(_, a), (_, b)| distance(b).total_cmp(&distance(a)));
+        for (model, mesh) in overlays {
+            encoder.set_vertex_buffer(1, &mesh.instance_buffer);
+            for primitive in mesh.primitives.iter() {
+                let material = &model.materials[primitive.material];
+                encoder.set_pipeline(
+                    self.material_pipelines(model, primitive.material)
+                        .overlay_pipeline
+                        .get(material.cull_mode),
+                );
+                model.bind_material(&mut encoder, primitive.material);
+                Self::draw_primitive(&mut encoder, mesh, primitive, overlay);
+            }
+        }
+    }
+
+    // The pipelines drawing a material, which depend on how the model's materials are bound and
+    // whether the material has a shader of its own
+    fn material_pipelines(&self, model: &GpuModel, material: usize) -> &MaterialPipelines {
+        let custom = model.materials[material]
+            .shader
+            .map(|shader| &self.custom_shaders[shader].pipelines);
+        match (custom, &model.bindings, self.packed_materials.as_ref()) {
+            (Some(pipelines), _, _) => pipelines,
+            (None, MaterialBindings::Packed { .. }, Some((_, pipelines))) => pipelines,
+            _ => &self.pipelines,
+        }
+    }
+
+    fn draw_primitive<'a>(
+        encoder: &mut DrawEncoder<'a, '_>,
+        mesh: &'a GpuMesh,
+        primitive: &'a GpuPrimitive,
+        filter: impl Fn(u32) -> bool,
+    ) {
+        encoder.set_vertex_buffer(0, &primitive.vertex_buffer);
+        encoder.set_index_buffer(&primitive.index_buffer);
+        for batch in mesh.batches.iter().filter(|batch| filter(batch.layers)) {
+            encoder.draw_indexed(0..primitive.index_count, batch.instances.clone());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn textures(
+        base_color: Option<(usize, (u32, u32))>,
+        normal: Option<(usize, (u32, u32))>,
+    ) -> [Option<(usize, (u32, u32))>; MATERIAL_TEXTURE_COUNT] {
+        let mut textures = [None; MATERIAL_TEXTURE_COUNT];
+        textures[0] = base_color;
+        textures[2] = normal;
+        textures
+    }
+
+    #[test]
+    fn groups_materials_by_the_size_of_their_images() {
+        let materials = [
+            textures(Some((0, (512, 512))), Some((1, (512, 512)))),
+            textures(Some((2, (4096, 4096))), None),
+            textures(Some((3, (512, 512))), None),
+            // Shares the first material's image, so it takes the same layer
+            textures(Some((0, (512, 512))), Some((4, (512, 512)))),
+            textures(None, None),
+        ];
+        let (groups, placements) = group_materials(&materials, 256);
+
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[0].images[0], vec![0, 3]);
+        assert_eq!(groups[0].images[2], vec![1, 4]);
+        assert_eq!(groups[1].sizes[0], Some((4096, 4096)));
+        assert_eq!(placements[0].0, 0);
+        assert_eq!(placements[1].0, 1);
+        assert_eq!(
+            placements[3],
+            (0, {
+                let mut layers = [NO_LAYER; MATERIAL_TEXTURE_COUNT];
+                layers[0] = 0;
+                layers[2] = 1;
+                layers
+            })
+        );
+        // Materials without textures join the first group
+        assert_eq!(placements[4].0, 0);
+        assert!(placements[4].1.iter().all(|layer| *layer == NO_LAYER));
+    }
+
+    #[test]
+    fn starts_a_new_group_once_the_layers_run_out() {
+        let materials = (0..3)
+            .map(|image| textures(Some((image, (64, 64))), None))
+            .collect::<Vec<_>>();
+        let (groups, placements) = group_materials(&materials, 2);
+
+        assert_eq!(groups.len(), 2);
+        assert_eq!(
+            placements
+                .iter()
+                .map(|(group, _)| *group)
+                .collect::<Vec<_>>(),
+            vec![0, 0, 1]
+        );
+        assert_eq!(placements[2].1[0], 0);
+    }
+
+    #[test]
+    fn mip_chains_end_at_a_single_texel() {
+        let image = image::DynamicImage::new_rgba8(16, 4);
+        let sizes = mip_chain(&image)
+            .iter()
+            .map(|level| level.dimensions())
+            .collect::<Vec<_>>();
+        assert_eq!(sizes, vec![(16, 4), (8, 2), (4, 1), (2, 1), (1, 1)]);
+        // Matches the mip level count of the packed texture
+        assert_eq!(sizes.len() as u32, u32::BITS - 16u32.leading_zeros());
+    }
+
+    #[test]
+    fn materials_with_the_same_textures_share_a_bind_group() {
+        let bindings = [
+            (vec![Some(0), None], None),
+            (vec![None, None], None),
+            (vec![Some(0), None], None),
+            (vec![None, None], Some((0, false))),
+            (vec![None, None], None),
+        ];
+        let (groups, material_groups) = group_bindings(&bindings);
+        assert_eq!(groups.len(), 3);
+        assert_eq!(material_groups, vec![0, 1, 0, 2, 1]);
+    }
+
+    #[test]
+    fn material_uniforms_fit_their_dynamic_offsets() {
+        let module = naga::front::wgsl::parse_str(include_str!("shaders/model.wgsl")).unwrap();
+        let mut layouter = naga::proc::Layouter::default();
+        layouter.update(&module.types, &module.constants).unwrap();
+        let (material, _) = module
+            .types
+            .iter()
+            .find(|(_, ty)| ty.name.as_deref() == Some("Material"))
+            .unwrap();
+        let size = std::mem::size_of::<MaterialUniform>() as u32;
+        assert_eq!(layouter[material].size, size);
+
+        for alignment in [64, 256] {
+            let limits = wgpu::Limits {
+                min_uniform_buffer_offset_alignment: alignment,
+                ..Default::default()
+            };
+            let stride = material_uniform_stride(&limits);
+            assert_eq!(stride % alignment, 0);
+            assert!(stride >= size);
+        }
+    }
+}
@@ -0,0 +1,168 @@
+// Sensors report the device orientation relative to a gravity aligned reference frame
+// with Z pointing up. The device frame has X to the right of the screen, Y towards the
+// top of the screen, and Z pointing out of the screen.
+
+#[cfg(target_os = "ios")]
+pub use ios::MotionSensor;
+
+#[cfg(target_arch = "wasm32")]
+pub use web::MotionSensor;
+
+#[cfg(not(any(target_os = "ios", target_arch = "wasm32")))]
+pub use unsupported::MotionSensor;
+
+#[cfg(not(any(target_os = "ios", target_arch = "wasm32")))]
+mod unsupported {
+    use nalgebra_glm as glm;
+
+    pub struct MotionSensor;
+
+    impl MotionSensor {
+        pub fn new() -> Option<Self> {
+            None
+        }
+
+        pub fn orientation(&self) -> Option<glm::Quat> {
+            None
+        }
+    }
+}
+
+#[cfg(target_os = "ios")]
+mod ios {
+    use nalgebra_glm as glm;
+    use objc::{
+        class, msg_send,
+        runtime::{Object, BOOL, NO},
+        sel, sel_impl,
+    };
+
+    #[link(name = "CoreMotion", kind = "framework")]
+    extern "C" {}
+
+    const UPDATE_INTERVAL: f64 = 1.0 / 60.0;
+
+    #[repr(C)]
+    #[derive(Copy, Clone)]
+    struct CMQuaternion {
+        x: f64,
+        y: f64,
+        z: f64,
+        w: f64,
+    }
+
+    pub struct MotionSensor {
+        manager: *mut Object,
+    }
+
+    impl MotionSensor {
+        pub fn new() -> Option<Self> {
+            unsafe {
+                let manager: *mut Object = msg_send![class!(CMMotionManager), new];
+                if manager.is_null() {
+                    return None;
+                }
+
+                let available: BOOL = msg_send![manager, isDeviceMotionAvailable];
+                if available == NO {
+                    let _: () = msg_send![manager, release];
+                    return None;
+                }
+
+                let _: () = msg_send![manager, setDeviceMotionUpdateInterval: UPDATE_INTERVAL];
+                let _: () = msg_send![manager, startDeviceMotionUpdates];
+                Some(Self { manager })
+            }
+        }
+
+        pub fn orientation(&self) -> Option<glm::Quat> {
+            unsafe {
+                let motion: *mut Object = msg_send![self.manager, deviceMotion];
+                if motion.is_null() {
+                    return None;
+                }
+                let attitude: *mut Object = msg_send![motion, attitude];
+                let quaternion: CMQuaternion = msg_send![attitude, quaternion];
+                Some(glm::quat(
+                    quaternion.x as f32,
+                    quaternion.y as f32,
+                    quaternion.z as f32,
+                    quaternion.w as f32,
+                ))
+            }
+        }
+    }
+
+    impl Drop for MotionSensor {
+        fn drop(&mut self) {
+            unsafe {
+                let _: () = msg_send![self.manager, stopDeviceMotionUpdates];
+                let _: () = msg_send![self.manager, release];
+            }
+        }
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+mod web {
+    use nalgebra_glm as glm;
+    use std::{cell::Cell, rc::Rc};
+    use wasm_bindgen::{closure::Closure, JsCast};
+    use web_sys::DeviceOrientationEvent;
+
+    pub struct MotionSensor {
+        orientation: Rc<Cell<Option<glm::Quat>>>,
+        listener: Closure<dyn FnMut(DeviceOrientationEvent)>,
+    }
+
+    impl MotionSensor {
+        pub fn new() -> Option<Self> {
+            let window = web_sys::window()?;
+            let orientation = Rc::new(Cell::new(None));
+
+            let latest = orientation.clone();
+            let listener = Closure::wrap(Box::new(move |event: DeviceOrientationEvent| {
+                if let (Some(alpha), Some(beta), Some(gamma)) =
+                    (event.alpha(), event.beta(), event.gamma())
+                {
+                    latest.set(Some(Self::euler_to_quaternion(alpha, beta, gamma)));
+                }
+            }) as Box<dyn FnMut(DeviceOrientationEvent)>);
+
+            window
+                .add_event_listener_with_callback(
+                    "deviceorientation",
+                    listener.as_ref().unchecked_ref(),
+                )
+                .ok()?;
+
+            Some(Self {
+                orientation,
+                listener,
+            })
+        }
+
+        pub fn orientation(&self) -> Option<glm::Quat> {
+            self.orientation.get()
+        }
+
+        // Device orientation events use intrinsic Z-X'-Y'' Tait-Bryan angles in degrees
+        fn euler_to_quaternion(alpha: f64, beta: f64, gamma: f64) -> glm::Quat {
+            let z = glm::quat_angle_axis((alpha as f32).to_radians(), &glm::Vec3::z());
+            let x = glm::quat_angle_axis((beta as f32).to_radians(), &glm::Vec3::x());
+            let y = glm::quat_angle_axis((gamma as f32).to_radians(), &glm::Vec3::y());
+            z * x * y
+        }
+    }
+
+    impl Drop for MotionSensor {
+        fn drop(&mut self) {
+            if let Some(window) = web_sys::window() {
+                let _ = window.remove_event_listener_with_callback(
+                    "deviceorientation",
+                    self.listener.as_ref().unchecked_ref(),
+                );
+            }
+        }
+    }
+}
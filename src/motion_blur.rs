@@ -0,0 +1,277 @@
+use std::rc::Rc;
+
+use crate::{
+    memory::{self, MemoryCategory, Tracked},
+    render_targets::{FramePhase, RenderTargetPool},
+    settings::Settings,
+    shader_snippets,
+    texture::Texture,
+};
+
+#[repr(C)]
+#[derive(Default, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct MotionBlurUniform {
+    samples: u32,
+    shutter: f32,
+    _padding: [u32; 2],
+}
+
+impl MotionBlurUniform {
+    fn new(settings: &Settings) -> Self {
+        let motion_blur = &settings.motion_blur;
+        Self {
+            samples: motion_blur
+                .samples
+                .min(settings.effect_quality.max_motion_blur_samples()),
+            shutter: motion_blur.shutter_angle / 360.0,
+            _padding: [0; 2],
+        }
+    }
+}
+
+// Smears the HDR scene color along each pixel's velocity
+pub struct MotionBlurSystem {
+    uniform_buffer: Tracked<wgpu::Buffer>,
+    enabled: bool,
+    bind_group_layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+    output: Rc<Texture>,
+    pipeline: wgpu::RenderPipeline,
+}
+
+impl MotionBlurSystem {
+    pub fn new(
+        device: &wgpu::Device,
+        target_pool: &mut RenderTargetPool,
+        scene_color: &Texture,
+        velocity_texture: &Texture,
+        dimensions: &[u32; 2],
+    ) -> Self {
+        let uniform_buffer = memory::create_buffer(
+            device,
+            MemoryCategory::Transient,
+            &wgpu::BufferDescriptor {
+                label: Some("Motion Blur Uniform Buffer"),
+                size: std::mem::size_of::<MotionBlurUniform>() as wgpu::BufferAddress,
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            },
+        );
+
+        let texture_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        };
+        let bind_group_layout = memory::create_bind_group_layout(
+            device,
+            &wgpu::BindGroupLayoutDescriptor {
+                label: Some("Motion Blur Bind Group Layout"),
+                entries: &[
+                    texture_entry(0),
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Sampler {
+                            filtering: true,
+                            comparison: false,
+                        },
+                        count: None,
+                    },
+                    texture_entry(2),
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 3,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                ],
+            },
+        );
+
+        let module = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("Motion Blur Shader"),
+            source: wgpu::ShaderSource::Wgsl(
+                shader_snippets::splice(include_str!("shaders/motion_blur.wgsl")).into(),
+            ),
+        });
+
+        let pipeline_layout = memory::create_pipeline_layout(
+            device,
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("Motion Blur Pipeline Layout"),
+                bind_group_layouts: &[&bind_group_layout],
+                push_constant_ranges: &[],
+            },
+        );
+
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Motion Blur Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &module,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            fragment: Some(wgpu::FragmentState {
+                module: &module,
+                entry_point: "fs_main",
+                targets: &[wgpu::ColorTargetState {
+                    format: Texture::HDR_FORMAT,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                }],
+            }),
+        });
+
+        let bind_group = Self::create_bind_group(
+            device,
+            &bind_group_layout,
+            &uniform_buffer,
+            scene_color,
+            velocity_texture,
+        );
+        let output = Self::create_output(device, target_pool, dimensions);
+
+        Self {
+            uniform_buffer,
+            enabled: false,
+            bind_group_layout,
+            bind_group,
+            output,
+            pipeline,
+        }
+    }
+
+    fn create_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        uniform_buffer: &wgpu::Buffer,
+        scene_color: &Texture,
+        velocity_texture: &Texture,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Motion Blur Bind Group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&scene_color.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&scene_color.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(&velocity_texture.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+            ],
+        })
+    }
+
+    // Only needed until it's copied back into the scene color
+    fn create_output(
+        device: &wgpu::Device,
+        target_pool: &mut RenderTargetPool,
+        dimensions: &[u32; 2],
+    ) -> Rc<Texture> {
+        target_pool.acquire(
+            device,
+            dimensions[0],
+            dimensions[1],
+            Texture::HDR_FORMAT,
+            "Motion Blur Output Texture",
+            FramePhase::MotionBlur..=FramePhase::MotionBlur,
+        )
+    }
+
+    pub fn resize(
+        &mut self,
+        device: &wgpu::Device,
+        target_pool: &mut RenderTargetPool,
+        scene_color: &Texture,
+        velocity_texture: &Texture,
+        dimensions: &[u32; 2],
+    ) {
+        self.bind_group = Self::create_bind_group(
+            device,
+            &self.bind_group_layout,
+            &self.uniform_buffer,
+            scene_color,
+            velocity_texture,
+        );
+        self.output = Self::create_output(device, target_pool, dimensions);
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn update(&mut self, queue: &wgpu::Queue, settings: &Settings) {
+        let motion_blur = &settings.motion_blur;
+        self.enabled =
+            motion_blur.enabled && motion_blur.samples > 1 && motion_blur.shutter_angle > 0.0;
+        queue.write_buffer(
+            &self.uniform_buffer,
+            0,
+            bytemuck::cast_slice(&[MotionBlurUniform::new(settings)]),
+        );
+    }
+
+    // Replaces the scene color with its blurred version
+    pub fn render(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        scene_color: &Texture,
+        dimensions: &[u32; 2],
+    ) {
+        if !self.enabled {
+            return;
+        }
+
+        {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Motion Blur Pass"),
+                color_attachments: &[wgpu::RenderPassColorAttachment {
+                    view: &self.output.view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                        store: true,
+                    },
+                }],
+                depth_stencil_attachment: None,
+            });
+            render_pass.set_pipeline(&self.pipeline);
+            render_pass.set_bind_group(0, &self.bind_group, &[]);
+            render_pass.draw(0..3, 0..1);
+        }
+
+        encoder.copy_texture_to_texture(
+            self.output.texture.as_image_copy(),
+            scene_color.texture.as_image_copy(),
+            wgpu::Extent3d {
+                width: dimensions[0],
+                height: dimensions[1],
+                depth_or_array_layers: 1,
+            },
+        );
+    }
+}
@@ -0,0 +1,37 @@
+use instant::Instant;
+use nalgebra_glm as glm;
+use std::time::Duration;
+
+use crate::renderer::Renderer;
+
+const DISPLAY_TIME: Duration = Duration::from_secs(5);
+const TEXT_SIZE: f32 = 16.0;
+
+// Messages shown in the corner of the window for a few seconds, such as files that failed to open
+#[derive(Default)]
+pub struct Notifications {
+    messages: Vec<(String, Instant)>,
+}
+
+impl Notifications {
+    pub fn push(&mut self, message: String) {
+        eprintln!("{}", message);
+        self.messages.push((message, Instant::now()));
+    }
+
+    // Newest at the bottom
+    pub fn draw(&mut self, renderer: &mut Renderer) {
+        self.messages
+            .retain(|(_, shown)| shown.elapsed() < DISPLAY_TIME);
+        // Screen text is scaled by the interface scale, so the bottom is found in the same units
+        let height = renderer.physical_size()[1] as f32 / renderer.interface_scale();
+        for (index, (message, _)) in self.messages.iter().rev().enumerate() {
+            renderer.draw_text(
+                message,
+                glm::vec2(8.0, height - (index + 1) as f32 * TEXT_SIZE * 1.25 - 8.0),
+                TEXT_SIZE,
+                glm::vec4(1.0, 0.4, 0.4, 1.0),
+            );
+        }
+    }
+}
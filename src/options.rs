@@ -0,0 +1,158 @@
+use anyhow::{bail, Result};
+use clap::Parser;
+use std::path::PathBuf;
+
+use crate::settings;
+
+// Browsers and Android apps aren't started with arguments, so they always get the defaults
+#[derive(Parser)]
+#[clap(name = "renderer", about = "Dragonglass Renderer")]
+pub struct Options {
+    #[clap(
+        value_name = "ASSET",
+        help = "Models, point clouds or heightmaps to load at startup"
+    )]
+    pub assets: Vec<PathBuf>,
+
+    #[clap(
+        long,
+        value_name = "BACKEND",
+        parse(try_from_str = parse_backend),
+        help = "Graphics API to use instead of the platform's: vulkan, metal, dx12, dx11 or gl"
+    )]
+    pub backend: Option<wgpu::Backends>,
+
+    #[clap(
+        long,
+        help = "Window width in pixels, otherwise the last session's or 800"
+    )]
+    pub width: Option<u32>,
+
+    #[clap(
+        long,
+        help = "Window height in pixels, otherwise the last session's or 600"
+    )]
+    pub height: Option<u32>,
+
+    #[clap(
+        long,
+        value_name = "PATH",
+        default_value = "assets",
+        help = "Directory listed in the asset browser"
+    )]
+    pub asset_directory: PathBuf,
+
+    #[clap(
+        long,
+        value_name = "PATH",
+        default_value = "renderer.ron",
+        help = "Where the window, camera, settings and bindings are kept between sessions"
+    )]
+    pub config: PathBuf,
+
+    #[clap(
+        long,
+        value_name = "PATH",
+        default_value = "assets/icon.png",
+        help = "Window icon image"
+    )]
+    pub icon: PathBuf,
+
+    #[clap(
+        long,
+        value_name = "BOOL",
+        parse(try_from_str = settings::parse_bool),
+        help = "Wait for the display's refresh before presenting frames"
+    )]
+    pub vsync: Option<bool>,
+
+    #[clap(
+        long,
+        value_name = "BOOL",
+        parse(try_from_str = settings::parse_bool),
+        help = "Give the depth buffer a stencil, which outlines are masked with"
+    )]
+    pub stencil: Option<bool>,
+
+    #[clap(
+        long,
+        value_name = "BOOL",
+        parse(try_from_str = settings::parse_bool),
+        help = "Reverse the depth buffer for precision in large scenes, which leaves out the stencil, \
+                on by default when rebase_distance is set"
+    )]
+    pub reverse_z: Option<bool>,
+
+    #[clap(
+        long,
+        value_name = "SAMPLES",
+        parse(try_from_str = settings::parse_samples),
+        help = "Samples per pixel of opaque surfaces and the sky, 1 to turn multisampling off or 4"
+    )]
+    pub msaa: Option<u32>,
+
+    #[clap(
+        long,
+        value_name = "PATH",
+        help = "Equirectangular .hdr or .exr image drawn around the scene in place of the sky"
+    )]
+    pub environment: Option<PathBuf>,
+
+    #[clap(
+        long,
+        value_name = "PATH",
+        help = "Render without showing the window, save the last frame here and exit"
+    )]
+    pub screenshot: Option<PathBuf>,
+
+    #[clap(
+        long,
+        value_name = "PATH",
+        help = "Save the loaded assets to a .glb or .gltf file and exit, without opening a window"
+    )]
+    pub export: Option<PathBuf>,
+
+    #[clap(
+        long,
+        default_value_t = 1,
+        requires = "screenshot",
+        help = "Frames to render before the screenshot, letting temporal effects settle"
+    )]
+    pub frames: u32,
+
+    #[clap(
+        long,
+        value_name = "ADDRESS",
+        help = "Accept remote control commands over TCP on this address, such as 127.0.0.1:7878"
+    )]
+    pub remote: Option<String>,
+
+    #[clap(
+        long,
+        value_name = "PATH",
+        default_value = "screenshots",
+        help = "Directory that screenshots taken over the remote connection are saved in"
+    )]
+    pub remote_screenshots: PathBuf,
+
+    #[clap(
+        long,
+        value_name = "ADDRESS",
+        help = "Stream the presented frames as MJPEG over HTTP on this address, such as 127.0.0.1:8080"
+    )]
+    pub stream: Option<String>,
+}
+
+fn parse_backend(value: &str) -> Result<wgpu::Backends> {
+    Ok(match value.trim() {
+        "vulkan" => wgpu::Backends::VULKAN,
+        "metal" => wgpu::Backends::METAL,
+        "dx12" => wgpu::Backends::DX12,
+        "dx11" => wgpu::Backends::DX11,
+        "gl" => wgpu::Backends::GL,
+        _ => bail!(
+            "Unknown backend '{}', expected one of: vulkan, metal, dx12, dx11, gl",
+            value
+        ),
+    })
+}
@@ -0,0 +1,103 @@
+use nalgebra_glm as glm;
+use std::f32::consts::{FRAC_PI_2, PI};
+
+use crate::camera::Camera;
+
+// Below this angular speed the device is considered at rest,
+// so any change in heading is sensor drift rather than user motion
+const STATIONARY_ANGULAR_SPEED: f32 = 0.05;
+const SMOOTHING_RATE: f32 = 20.0;
+
+// Turns the camera into a window into the scene that follows the physical orientation of the device
+pub struct DeviceOrientationCamera {
+    heading_offset: f32,
+    smoothed: Option<glm::Quat>,
+    last_sample: Option<glm::Quat>,
+    recenter_requested: bool,
+}
+
+impl Default for DeviceOrientationCamera {
+    fn default() -> Self {
+        Self {
+            heading_offset: 0.0,
+            smoothed: None,
+            last_sample: None,
+            recenter_requested: true,
+        }
+    }
+}
+
+impl DeviceOrientationCamera {
+    // Aligns the current device heading with the direction the camera is facing
+    pub fn recenter(&mut self) {
+        self.recenter_requested = true;
+    }
+
+    pub fn update(&mut self, camera: &mut Camera, device_orientation: glm::Quat, delta_time: f32) {
+        // Sensors use a Z-up reference frame while the world is Y-up
+        let orientation = glm::quat_angle_axis(-FRAC_PI_2, &glm::Vec3::x()) * device_orientation;
+
+        if let Some(last_sample) = self.last_sample {
+            let angular_speed =
+                Self::angle_between(&last_sample, &orientation) / delta_time.max(1e-4);
+            if angular_speed < STATIONARY_ANGULAR_SPEED {
+                self.heading_offset -=
+                    Self::wrap_angle(Self::heading(&orientation) - Self::heading(&last_sample));
+            }
+        }
+        self.last_sample = Some(orientation);
+
+        let offset = camera.target - camera.position;
+        let distance = offset.magnitude().max(camera.z_near);
+
+        if self.recenter_requested {
+            let camera_heading = Self::heading_of(&offset.normalize(), &camera.up);
+            self.heading_offset = camera_heading - Self::heading(&orientation);
+            self.smoothed = None;
+            self.recenter_requested = false;
+        }
+
+        let mut target = glm::quat_angle_axis(self.heading_offset, &glm::Vec3::y()) * orientation;
+        let smoothed = match self.smoothed {
+            Some(previous) => {
+                if glm::quat_dot(&previous, &target) < 0.0 {
+                    target = -target;
+                }
+                let blend = 1.0 - (-SMOOTHING_RATE * delta_time).exp();
+                glm::quat_slerp(&previous, &target, blend)
+            }
+            None => target,
+        };
+        self.smoothed = Some(smoothed);
+
+        let forward = glm::quat_rotate_vec3(&smoothed, &-glm::Vec3::z());
+        camera.target = camera.position + forward * distance;
+        camera.up = glm::quat_rotate_vec3(&smoothed, &glm::Vec3::y());
+    }
+
+    fn heading(orientation: &glm::Quat) -> f32 {
+        let forward = glm::quat_rotate_vec3(orientation, &-glm::Vec3::z());
+        let up = glm::quat_rotate_vec3(orientation, &glm::Vec3::y());
+        Self::heading_of(&forward, &up)
+    }
+
+    fn heading_of(forward: &glm::Vec3, up: &glm::Vec3) -> f32 {
+        // Looking straight down or up, the top of the screen indicates the heading instead
+        let direction = if forward.y < -0.95 {
+            *up
+        } else if forward.y > 0.95 {
+            -up
+        } else {
+            *forward
+        };
+        (-direction.x).atan2(-direction.z)
+    }
+
+    fn angle_between(a: &glm::Quat, b: &glm::Quat) -> f32 {
+        2.0 * glm::quat_dot(a, b).abs().min(1.0).acos()
+    }
+
+    fn wrap_angle(angle: f32) -> f32 {
+        (angle + PI).rem_euclid(2.0 * PI) - PI
+    }
+}
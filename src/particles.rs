@@ -0,0 +1,714 @@
+use anyhow::Result;
+use nalgebra_glm as glm;
+
+use crate::{
+    memory::{self, MemoryCategory, Tracked},
+    scene::Scene,
+    shader_snippets,
+    texture::Texture,
+};
+
+const WORKGROUP_SIZE: u32 = 64;
+const MAX_COLOR_KEYS: usize = 4;
+const SORT_PARAMETER_STRIDE: wgpu::BufferAddress = 256;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ParticleBlendMode {
+    Additive,
+    AlphaBlended,
+}
+
+#[derive(Clone)]
+pub struct Flipbook {
+    pub image: image::DynamicImage,
+    pub columns: u32,
+    pub rows: u32,
+}
+
+#[derive(Clone)]
+pub struct EmitterDesc {
+    pub position: glm::Vec3,
+    pub direction: glm::Vec3,
+    // Particles emitted per second
+    pub rate: f32,
+    // Half-angle of the emission cone, in radians
+    pub spread: f32,
+    pub speed: [f32; 2],
+    pub lifetime: [f32; 2],
+    // Start and end size of each particle
+    pub size: [f32; 2],
+    pub gravity: glm::Vec3,
+    pub drag: f32,
+    // Evenly spaced over each particle's lifetime, up to four keys are used
+    pub color_over_life: Vec<glm::Vec4>,
+    pub blend_mode: ParticleBlendMode,
+    pub flipbook: Option<Flipbook>,
+    // Distance over which particles fade out when intersecting geometry
+    pub soft_fade_distance: f32,
+}
+
+impl Default for EmitterDesc {
+    fn default() -> Self {
+        Self {
+            position: glm::Vec3::zeros(),
+            direction: glm::Vec3::y(),
+            rate: 100.0,
+            spread: 15_f32.to_radians(),
+            speed: [1.0, 2.0],
+            lifetime: [1.0, 2.0],
+            size: [0.1, 0.05],
+            gravity: glm::vec3(0.0, -9.81, 0.0),
+            drag: 0.0,
+            color_over_life: vec![glm::vec4(1.0, 1.0, 1.0, 1.0), glm::vec4(1.0, 1.0, 1.0, 0.0)],
+            blend_mode: ParticleBlendMode::Additive,
+            flipbook: None,
+            soft_fade_distance: 0.5,
+        }
+    }
+}
+
+impl EmitterDesc {
+    pub fn capacity(&self) -> u32 {
+        (self.rate * self.lifetime[0].max(self.lifetime[1])).ceil() as u32 + 1
+    }
+}
+
+#[repr(C)]
+#[derive(Default, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct EmitterUniform {
+    position: [f32; 4],
+    direction: [f32; 4],
+    gravity: [f32; 4],
+    speed: [f32; 2],
+    lifetime: [f32; 2],
+    size: [f32; 2],
+    flipbook: [f32; 2],
+    colors: [[f32; 4]; MAX_COLOR_KEYS],
+    spawn_offset: u32,
+    spawn_count: u32,
+    capacity: u32,
+    seed: u32,
+    color_count: u32,
+    sort_count: u32,
+    soft_fade_distance: f32,
+    sorted: u32,
+    // Added to live particles once after the scene's origin moves
+    translation: [f32; 4],
+}
+
+#[repr(C)]
+#[derive(Default, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct Particle {
+    position: [f32; 4],
+    velocity: [f32; 4],
+}
+
+#[repr(C)]
+#[derive(Default, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct SortEntry {
+    key: f32,
+    index: u32,
+}
+
+struct GpuEmitter {
+    uniform_buffer: Tracked<wgpu::Buffer>,
+    simulation_bind_group: wgpu::BindGroup,
+    sort_parameter_bind_group: wgpu::BindGroup,
+    render_bind_group: wgpu::BindGroup,
+    capacity: u32,
+    sort_count: u32,
+    sort_steps: u32,
+    spawn_offset: u32,
+    spawn_accumulator: f32,
+    _particle_buffer: Tracked<wgpu::Buffer>,
+    _sort_buffer: Tracked<wgpu::Buffer>,
+    _sort_parameter_buffer: Tracked<wgpu::Buffer>,
+    _flipbook: Texture,
+}
+
+pub struct ParticleSystem {
+    simulation_bind_group_layout: wgpu::BindGroupLayout,
+    sort_parameter_bind_group_layout: wgpu::BindGroupLayout,
+    render_bind_group_layout: wgpu::BindGroupLayout,
+    depth_bind_group_layout: wgpu::BindGroupLayout,
+    depth_bind_group: wgpu::BindGroup,
+    simulate_pipeline: wgpu::ComputePipeline,
+    sort_keys_pipeline: wgpu::ComputePipeline,
+    bitonic_sort_pipeline: wgpu::ComputePipeline,
+    additive_pipeline: wgpu::RenderPipeline,
+    alpha_blended_pipeline: wgpu::RenderPipeline,
+    emitters: Vec<GpuEmitter>,
+    frame: u32,
+    // Carried into the next simulation, moving particles already in flight with the scene
+    translation: glm::Vec3,
+}
+
+impl ParticleSystem {
+    pub fn new(
+        device: &wgpu::Device,
+        camera_bind_group_layout: &wgpu::BindGroupLayout,
+        color_format: wgpu::TextureFormat,
+        depth_texture: &Texture,
+    ) -> Self {
+        let simulation_bind_group_layout = memory::create_bind_group_layout(
+            device,
+            &wgpu::BindGroupLayoutDescriptor {
+                label: Some("Particle Simulation Bind Group Layout"),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    Self::storage_entry(1, wgpu::ShaderStages::COMPUTE, false),
+                    Self::storage_entry(2, wgpu::ShaderStages::COMPUTE, false),
+                ],
+            },
+        );
+
+        let sort_parameter_bind_group_layout = memory::create_bind_group_layout(
+            device,
+            &wgpu::BindGroupLayoutDescriptor {
+                label: Some("Particle Sort Parameter Bind Group Layout"),
+                entries: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: true,
+                        min_binding_size: wgpu::BufferSize::new(16),
+                    },
+                    count: None,
+                }],
+            },
+        );
+
+        let render_bind_group_layout = memory::create_bind_group_layout(
+            device,
+            &wgpu::BindGroupLayoutDescriptor {
+                label: Some("Particle Render Bind Group Layout"),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    Self::storage_entry(1, wgpu::ShaderStages::VERTEX, true),
+                    Self::storage_entry(2, wgpu::ShaderStages::VERTEX, true),
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 3,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 4,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Sampler {
+                            filtering: true,
+                            comparison: false,
+                        },
+                        count: None,
+                    },
+                ],
+            },
+        );
+
+        let depth_bind_group_layout = memory::create_bind_group_layout(
+            device,
+            &wgpu::BindGroupLayoutDescriptor {
+                label: Some("Particle Depth Bind Group Layout"),
+                entries: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Depth,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                }],
+            },
+        );
+
+        let depth_bind_group =
+            Self::create_depth_bind_group(device, &depth_bind_group_layout, depth_texture);
+
+        let simulation_module = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("Particle Simulation Shader"),
+            source: wgpu::ShaderSource::Wgsl(
+                include_str!("shaders/particle_simulation.wgsl").into(),
+            ),
+        });
+
+        let simulation_pipeline_layout = memory::create_pipeline_layout(
+            device,
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("Particle Simulation Pipeline Layout"),
+                bind_group_layouts: &[
+                    camera_bind_group_layout,
+                    &simulation_bind_group_layout,
+                    &sort_parameter_bind_group_layout,
+                ],
+                push_constant_ranges: &[],
+            },
+        );
+
+        let create_compute_pipeline = |label: &str, entry_point: &str| {
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some(label),
+                layout: Some(&simulation_pipeline_layout),
+                module: &simulation_module,
+                entry_point,
+            })
+        };
+        let simulate_pipeline = create_compute_pipeline("Particle Simulate Pipeline", "simulate");
+        let sort_keys_pipeline =
+            create_compute_pipeline("Particle Sort Keys Pipeline", "compute_sort_keys");
+        let bitonic_sort_pipeline =
+            create_compute_pipeline("Particle Bitonic Sort Pipeline", "bitonic_sort");
+
+        let render_module = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("Particle Shader"),
+            source: wgpu::ShaderSource::Wgsl(
+                shader_snippets::splice(include_str!("shaders/particle.wgsl")).into(),
+            ),
+        });
+
+        let render_pipeline_layout = memory::create_pipeline_layout(
+            device,
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("Particle Render Pipeline Layout"),
+                bind_group_layouts: &[
+                    camera_bind_group_layout,
+                    &render_bind_group_layout,
+                    &depth_bind_group_layout,
+                ],
+                push_constant_ranges: &[],
+            },
+        );
+
+        let create_render_pipeline = |label: &str, blend: wgpu::BlendState| {
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some(label),
+                layout: Some(&render_pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: &render_module,
+                    entry_point: "vs_main",
+                    buffers: &[],
+                },
+                primitive: wgpu::PrimitiveState {
+                    topology: wgpu::PrimitiveTopology::TriangleList,
+                    cull_mode: None,
+                    ..Default::default()
+                },
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState::default(),
+                fragment: Some(wgpu::FragmentState {
+                    module: &render_module,
+                    entry_point: "fs_main",
+                    targets: &[wgpu::ColorTargetState {
+                        format: color_format,
+                        blend: Some(blend),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    }],
+                }),
+            })
+        };
+
+        let additive_pipeline = create_render_pipeline(
+            "Particle Additive Pipeline",
+            wgpu::BlendState {
+                color: wgpu::BlendComponent {
+                    src_factor: wgpu::BlendFactor::SrcAlpha,
+                    dst_factor: wgpu::BlendFactor::One,
+                    operation: wgpu::BlendOperation::Add,
+                },
+                alpha: wgpu::BlendComponent {
+                    src_factor: wgpu::BlendFactor::Zero,
+                    dst_factor: wgpu::BlendFactor::One,
+                    operation: wgpu::BlendOperation::Add,
+                },
+            },
+        );
+
+        let alpha_blended_pipeline = create_render_pipeline(
+            "Particle Alpha Blended Pipeline",
+            wgpu::BlendState::ALPHA_BLENDING,
+        );
+
+        Self {
+            simulation_bind_group_layout,
+            sort_parameter_bind_group_layout,
+            render_bind_group_layout,
+            depth_bind_group_layout,
+            depth_bind_group,
+            simulate_pipeline,
+            sort_keys_pipeline,
+            bitonic_sort_pipeline,
+            additive_pipeline,
+            alpha_blended_pipeline,
+            emitters: Vec::new(),
+            frame: 0,
+            translation: glm::Vec3::zeros(),
+        }
+    }
+
+    fn storage_entry(
+        binding: u32,
+        visibility: wgpu::ShaderStages,
+        read_only: bool,
+    ) -> wgpu::BindGroupLayoutEntry {
+        wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        }
+    }
+
+    fn create_depth_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        depth_texture: &Texture,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Particle Depth Bind Group"),
+            layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&depth_texture.view),
+            }],
+        })
+    }
+
+    pub fn resize(&mut self, device: &wgpu::Device, depth_texture: &Texture) {
+        self.depth_bind_group =
+            Self::create_depth_bind_group(device, &self.depth_bind_group_layout, depth_texture);
+    }
+
+    fn create_emitter(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        desc: &EmitterDesc,
+    ) -> Result<GpuEmitter> {
+        let capacity = desc.capacity();
+        let sort_count = capacity.next_power_of_two();
+
+        let uniform_buffer = memory::create_buffer(
+            device,
+            MemoryCategory::Transient,
+            &wgpu::BufferDescriptor {
+                label: Some("Particle Emitter Uniform Buffer"),
+                size: std::mem::size_of::<EmitterUniform>() as wgpu::BufferAddress,
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            },
+        );
+
+        let particle_buffer = memory::create_buffer_init(
+            device,
+            MemoryCategory::Meshes,
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("Particle Buffer"),
+                contents: bytemuck::cast_slice(&vec![Particle::default(); capacity as usize]),
+                usage: wgpu::BufferUsages::STORAGE,
+            },
+        );
+
+        let sort_buffer = memory::create_buffer(
+            device,
+            MemoryCategory::Meshes,
+            &wgpu::BufferDescriptor {
+                label: Some("Particle Sort Buffer"),
+                size: (sort_count as usize * std::mem::size_of::<SortEntry>())
+                    as wgpu::BufferAddress,
+                usage: wgpu::BufferUsages::STORAGE,
+                mapped_at_creation: false,
+            },
+        );
+
+        // Each bitonic merge step gets its own slot, selected with a dynamic offset
+        let mut sort_parameters = Vec::new();
+        let mut k = 2;
+        while k <= sort_count {
+            let mut j = k / 2;
+            while j > 0 {
+                let mut slot = [0_u32; (SORT_PARAMETER_STRIDE / 4) as usize];
+                slot[0] = j;
+                slot[1] = k;
+                sort_parameters.extend_from_slice(&slot);
+                j /= 2;
+            }
+            k *= 2;
+        }
+        let sort_steps = (sort_parameters.len() as u64 * 4 / SORT_PARAMETER_STRIDE) as u32;
+        if sort_parameters.is_empty() {
+            sort_parameters.resize((SORT_PARAMETER_STRIDE / 4) as usize, 0);
+        }
+        let sort_parameter_buffer = memory::create_buffer_init(
+            device,
+            MemoryCategory::Transient,
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("Particle Sort Parameter Buffer"),
+                contents: bytemuck::cast_slice(&sort_parameters),
+                usage: wgpu::BufferUsages::UNIFORM,
+            },
+        );
+
+        let flipbook_image = match desc.flipbook.as_ref() {
+            Some(flipbook) => flipbook.image.clone(),
+            None => image::DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(
+                1,
+                1,
+                image::Rgba([255, 255, 255, 255]),
+            )),
+        };
+        let flipbook = Texture::from_image(
+            device,
+            queue,
+            &flipbook_image,
+            Some("Particle Flipbook Texture"),
+        )?;
+
+        let simulation_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Particle Simulation Bind Group"),
+            layout: &self.simulation_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: particle_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: sort_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        let sort_parameter_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Particle Sort Parameter Bind Group"),
+            layout: &self.sort_parameter_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                    buffer: &sort_parameter_buffer,
+                    offset: 0,
+                    size: wgpu::BufferSize::new(16),
+                }),
+            }],
+        });
+
+        let render_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Particle Render Bind Group"),
+            layout: &self.render_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: particle_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: sort_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::TextureView(&flipbook.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: wgpu::BindingResource::Sampler(&flipbook.sampler),
+                },
+            ],
+        });
+
+        Ok(GpuEmitter {
+            uniform_buffer,
+            simulation_bind_group,
+            sort_parameter_bind_group,
+            render_bind_group,
+            capacity,
+            sort_count,
+            sort_steps,
+            spawn_offset: 0,
+            spawn_accumulator: 0.0,
+            _particle_buffer: particle_buffer,
+            _sort_buffer: sort_buffer,
+            _sort_parameter_buffer: sort_parameter_buffer,
+            _flipbook: flipbook,
+        })
+    }
+
+    pub fn update(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        camera_bind_group: &wgpu::BindGroup,
+        scene: &Scene,
+        delta_time: f32,
+    ) -> Result<()> {
+        while self.emitters.len() < scene.emitters.len() {
+            let emitter =
+                self.create_emitter(device, queue, &scene.emitters[self.emitters.len()])?;
+            self.emitters.push(emitter);
+        }
+
+        self.frame = self.frame.wrapping_add(1);
+        let translation = std::mem::replace(&mut self.translation, glm::Vec3::zeros());
+
+        for (index, (desc, emitter)) in scene
+            .emitters
+            .iter()
+            .zip(self.emitters.iter_mut())
+            .enumerate()
+        {
+            emitter.spawn_accumulator += desc.rate * delta_time;
+            let spawn_count = (emitter.spawn_accumulator.floor() as u32).min(emitter.capacity);
+            emitter.spawn_accumulator -= spawn_count as f32;
+
+            let uniform = Self::emitter_uniform(
+                desc,
+                &scene.local_point(&desc.position),
+                emitter,
+                spawn_count,
+                delta_time,
+                self.frame,
+                index,
+                &translation,
+            );
+            queue.write_buffer(&emitter.uniform_buffer, 0, bytemuck::cast_slice(&[uniform]));
+            emitter.spawn_offset = (emitter.spawn_offset + spawn_count) % emitter.capacity;
+
+            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("Particle Compute Pass"),
+            });
+            compute_pass.set_bind_group(0, camera_bind_group, &[]);
+            compute_pass.set_bind_group(1, &emitter.simulation_bind_group, &[]);
+            compute_pass.set_bind_group(2, &emitter.sort_parameter_bind_group, &[0]);
+
+            compute_pass.set_pipeline(&self.simulate_pipeline);
+            compute_pass.dispatch(Self::workgroup_count(emitter.capacity), 1, 1);
+
+            if desc.blend_mode == ParticleBlendMode::AlphaBlended {
+                let workgroups = Self::workgroup_count(emitter.sort_count);
+                compute_pass.set_pipeline(&self.sort_keys_pipeline);
+                compute_pass.dispatch(workgroups, 1, 1);
+
+                compute_pass.set_pipeline(&self.bitonic_sort_pipeline);
+                for step in 0..emitter.sort_steps {
+                    let offset = (step as wgpu::BufferAddress * SORT_PARAMETER_STRIDE) as u32;
+                    compute_pass.set_bind_group(2, &emitter.sort_parameter_bind_group, &[offset]);
+                    compute_pass.dispatch(workgroups, 1, 1);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // The position is relative to the scene's origin
+    #[allow(clippy::too_many_arguments)]
+    fn emitter_uniform(
+        desc: &EmitterDesc,
+        position: &glm::Vec3,
+        emitter: &GpuEmitter,
+        spawn_count: u32,
+        delta_time: f32,
+        frame: u32,
+        index: usize,
+        translation: &glm::Vec3,
+    ) -> EmitterUniform {
+        let mut colors = [[1.0; 4]; MAX_COLOR_KEYS];
+        let color_count = desc.color_over_life.len().min(MAX_COLOR_KEYS);
+        for (color, key) in colors.iter_mut().zip(desc.color_over_life.iter()) {
+            *color = [key.x, key.y, key.z, key.w];
+        }
+
+        let flipbook = desc
+            .flipbook
+            .as_ref()
+            .map(|flipbook| [flipbook.columns.max(1) as f32, flipbook.rows.max(1) as f32])
+            .unwrap_or([1.0, 1.0]);
+
+        EmitterUniform {
+            position: [position.x, position.y, position.z, desc.spread],
+            direction: [
+                desc.direction.x,
+                desc.direction.y,
+                desc.direction.z,
+                desc.drag,
+            ],
+            gravity: [desc.gravity.x, desc.gravity.y, desc.gravity.z, delta_time],
+            speed: desc.speed,
+            lifetime: desc.lifetime,
+            size: desc.size,
+            flipbook,
+            colors,
+            spawn_offset: emitter.spawn_offset,
+            spawn_count,
+            capacity: emitter.capacity,
+            seed: frame.wrapping_mul(0x9e37_79b9) ^ index as u32,
+            color_count: color_count.max(1) as u32,
+            sort_count: emitter.sort_count,
+            soft_fade_distance: desc.soft_fade_distance,
+            sorted: (desc.blend_mode == ParticleBlendMode::AlphaBlended) as u32,
+            translation: [translation.x, translation.y, translation.z, 0.0],
+        }
+    }
+
+    // Moves the particles in flight, which live on the GPU, when the scene's origin moves
+    pub fn translate(&mut self, offset: &glm::Vec3) {
+        self.translation += offset;
+    }
+
+    fn workgroup_count(count: u32) -> u32 {
+        count.div_ceil(WORKGROUP_SIZE)
+    }
+
+    pub fn render<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        camera_bind_group: &'a wgpu::BindGroup,
+        scene: &Scene,
+    ) {
+        render_pass.set_bind_group(0, camera_bind_group, &[]);
+        render_pass.set_bind_group(2, &self.depth_bind_group, &[]);
+        for (desc, emitter) in scene.emitters.iter().zip(self.emitters.iter()) {
+            let pipeline = match desc.blend_mode {
+                ParticleBlendMode::Additive => &self.additive_pipeline,
+                ParticleBlendMode::AlphaBlended => &self.alpha_blended_pipeline,
+            };
+            render_pass.set_pipeline(pipeline);
+            render_pass.set_bind_group(1, &emitter.render_bind_group, &[]);
+            render_pass.draw(0..6, 0..emitter.capacity);
+        }
+    }
+}
@@ -0,0 +1,639 @@
+use nalgebra_glm as glm;
+use std::collections::HashMap;
+
+use crate::{
+    bvh::{for_each_scene_triangle, BvhUpdate, SceneBvh, SceneTriangle},
+    camera::{aspect_ratio, Camera},
+    material::{Material, PAPER_WHITE_NITS},
+    memory::{self, MemoryCategory, Tracked},
+    model::{ModelDesc, ModelVertex},
+    scene::Scene,
+    settings::Settings,
+    shader_snippets,
+    texture::Texture,
+};
+
+const WORKGROUP_SIZE: u32 = 8;
+const MAX_BOUNCES: u32 = 4;
+// Accumulation stops once this many samples are averaged, the image has converged by then
+const MAX_SAMPLES: u32 = 4096;
+
+// Vertex colors are multiplied with the base color texture sampled at each vertex, textures
+// aren't sampled per hit
+#[repr(C)]
+#[derive(Default, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct TraceTriangle {
+    // The first position's w holds the bits of the material index
+    positions: [[f32; 4]; 3],
+    normals: [[f32; 4]; 3],
+    colors: [[f32; 4]; 3],
+}
+
+impl TraceTriangle {
+    fn transform(&mut self, triangle: &SceneTriangle) {
+        let material_bits = self.positions[0][3];
+        for (corner, vertex) in triangle.vertices.iter().enumerate() {
+            let position = triangle.transform * glm::Vec3::from(vertex.position).push(1.0);
+            let normal = triangle.normal_matrix * glm::Vec3::from(vertex.normal);
+            self.positions[corner] = position.into();
+            self.normals[corner] = glm::vec3_to_vec4(&normal).into();
+        }
+        self.positions[0][3] = material_bits;
+    }
+}
+
+#[repr(C)]
+#[derive(Default, Copy, Clone, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+struct TraceMaterial {
+    base_color: [f32; 4],
+    emissive: [f32; 4],
+    // x: metallic, y: roughness
+    parameters: [f32; 4],
+}
+
+impl TraceMaterial {
+    fn new(material: &Material) -> Self {
+        let emissive = material.emissive_factor * material.emissive_luminance / PAPER_WHITE_NITS;
+        Self {
+            base_color: material.base_color_factor.into(),
+            emissive: glm::vec3_to_vec4(&emissive).into(),
+            parameters: [
+                material.metallic_factor.clamp(0.0, 1.0),
+                material.roughness_factor.clamp(0.0, 1.0),
+                0.0,
+                0.0,
+            ],
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Default, Copy, Clone, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+struct PathTracerUniform {
+    inverse_view_projection: [[f32; 4]; 4],
+    camera_position: [f32; 4],
+    // Points towards the sun
+    sun_direction: [f32; 4],
+    sun_color: [f32; 4],
+    sky_color: [f32; 4],
+    // Width, height, the sample being accumulated and the number of bounces
+    parameters: [u32; 4],
+}
+
+struct SceneBuffers {
+    triangle_buffer: Tracked<wgpu::Buffer>,
+    node_buffer: Tracked<wgpu::Buffer>,
+    material_buffer: Tracked<wgpu::Buffer>,
+    materials: Vec<TraceMaterial>,
+}
+
+// Replaces the rasterized scene color with a progressively path traced one, which goes through
+// the same exposure, bloom and tone mapping. Samples accumulate until the camera, sun or
+// materials change
+pub struct PathTracerSystem {
+    uniform_buffer: Tracked<wgpu::Buffer>,
+    accumulation_buffer: Tracked<wgpu::Buffer>,
+    trace_layout: wgpu::BindGroupLayout,
+    trace_bind_group: Option<wgpu::BindGroup>,
+    trace_pipeline: wgpu::ComputePipeline,
+    resolve_layout: wgpu::BindGroupLayout,
+    resolve_bind_group: wgpu::BindGroup,
+    resolve_pipeline: wgpu::RenderPipeline,
+    scene_buffers: Option<SceneBuffers>,
+    scene_bvh: SceneBvh,
+    // In the order the scene's triangles are visited, not the hierarchy's
+    triangles: Vec<TraceTriangle>,
+    uniform: Option<PathTracerUniform>,
+    dimensions: [u32; 2],
+    enabled: bool,
+    // Whether this frame adds a sample
+    tracing: bool,
+}
+
+impl PathTracerSystem {
+    pub fn new(device: &wgpu::Device, dimensions: &[u32; 2]) -> Self {
+        let uniform_buffer = memory::create_buffer(
+            device,
+            MemoryCategory::Transient,
+            &wgpu::BufferDescriptor {
+                label: Some("Path Tracer Uniform Buffer"),
+                size: std::mem::size_of::<PathTracerUniform>() as wgpu::BufferAddress,
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            },
+        );
+
+        let buffer_entry = |binding, visibility, ty| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility,
+            ty: wgpu::BindingType::Buffer {
+                ty,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let storage = |read_only| wgpu::BufferBindingType::Storage { read_only };
+
+        let trace_layout = memory::create_bind_group_layout(
+            device,
+            &wgpu::BindGroupLayoutDescriptor {
+                label: Some("Path Tracer Bind Group Layout"),
+                entries: &[
+                    buffer_entry(
+                        0,
+                        wgpu::ShaderStages::COMPUTE,
+                        wgpu::BufferBindingType::Uniform,
+                    ),
+                    buffer_entry(1, wgpu::ShaderStages::COMPUTE, storage(true)),
+                    buffer_entry(2, wgpu::ShaderStages::COMPUTE, storage(true)),
+                    buffer_entry(3, wgpu::ShaderStages::COMPUTE, storage(true)),
+                    buffer_entry(4, wgpu::ShaderStages::COMPUTE, storage(false)),
+                ],
+            },
+        );
+
+        let resolve_layout = memory::create_bind_group_layout(
+            device,
+            &wgpu::BindGroupLayoutDescriptor {
+                label: Some("Path Tracer Resolve Bind Group Layout"),
+                entries: &[
+                    buffer_entry(
+                        0,
+                        wgpu::ShaderStages::FRAGMENT,
+                        wgpu::BufferBindingType::Uniform,
+                    ),
+                    buffer_entry(1, wgpu::ShaderStages::FRAGMENT, storage(true)),
+                ],
+            },
+        );
+
+        let trace_module = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("Path Tracer Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("shaders/path_tracer.wgsl").into()),
+        });
+        let trace_pipeline_layout = memory::create_pipeline_layout(
+            device,
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("Path Tracer Pipeline Layout"),
+                bind_group_layouts: &[&trace_layout],
+                push_constant_ranges: &[],
+            },
+        );
+        let trace_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Path Tracer Pipeline"),
+            layout: Some(&trace_pipeline_layout),
+            module: &trace_module,
+            entry_point: "main",
+        });
+
+        let resolve_module = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("Path Tracer Resolve Shader"),
+            source: wgpu::ShaderSource::Wgsl(
+                shader_snippets::splice(include_str!("shaders/path_tracer_resolve.wgsl")).into(),
+            ),
+        });
+        let resolve_pipeline_layout = memory::create_pipeline_layout(
+            device,
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("Path Tracer Resolve Pipeline Layout"),
+                bind_group_layouts: &[&resolve_layout],
+                push_constant_ranges: &[],
+            },
+        );
+        let resolve_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Path Tracer Resolve Pipeline"),
+            layout: Some(&resolve_pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &resolve_module,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            fragment: Some(wgpu::FragmentState {
+                module: &resolve_module,
+                entry_point: "fs_main",
+                targets: &[wgpu::ColorTargetState {
+                    format: Texture::HDR_FORMAT,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                }],
+            }),
+        });
+
+        let accumulation_buffer = Self::create_accumulation_buffer(device, dimensions);
+        let resolve_bind_group = Self::create_resolve_bind_group(
+            device,
+            &resolve_layout,
+            &uniform_buffer,
+            &accumulation_buffer,
+        );
+
+        Self {
+            uniform_buffer,
+            accumulation_buffer,
+            trace_layout,
+            trace_bind_group: None,
+            trace_pipeline,
+            resolve_layout,
+            resolve_bind_group,
+            resolve_pipeline,
+            scene_buffers: None,
+            scene_bvh: SceneBvh::default(),
+            triangles: Vec::new(),
+            uniform: None,
+            dimensions: *dimensions,
+            enabled: false,
+            tracing: false,
+        }
+    }
+
+    fn create_accumulation_buffer(
+        device: &wgpu::Device,
+        dimensions: &[u32; 2],
+    ) -> Tracked<wgpu::Buffer> {
+        memory::create_buffer(
+            device,
+            MemoryCategory::Targets,
+            &wgpu::BufferDescriptor {
+                label: Some("Path Tracer Accumulation Buffer"),
+                size: (dimensions[0] * dimensions[1]) as wgpu::BufferAddress * 16,
+                usage: wgpu::BufferUsages::STORAGE,
+                mapped_at_creation: false,
+            },
+        )
+    }
+
+    fn create_resolve_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        uniform_buffer: &wgpu::Buffer,
+        accumulation_buffer: &wgpu::Buffer,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Path Tracer Resolve Bind Group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: accumulation_buffer.as_entire_binding(),
+                },
+            ],
+        })
+    }
+
+    fn create_trace_bind_group(&mut self, device: &wgpu::Device) {
+        let scene_buffers = match self.scene_buffers.as_ref() {
+            Some(scene_buffers) => scene_buffers,
+            None => return,
+        };
+        self.trace_bind_group = Some(device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Path Tracer Bind Group"),
+            layout: &self.trace_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: self.uniform_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: scene_buffers.triangle_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: scene_buffers.node_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: scene_buffers.material_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: self.accumulation_buffer.as_entire_binding(),
+                },
+            ],
+        }));
+    }
+
+    pub fn resize(&mut self, device: &wgpu::Device, dimensions: &[u32; 2]) {
+        self.dimensions = *dimensions;
+        self.accumulation_buffer = Self::create_accumulation_buffer(device, dimensions);
+        self.resolve_bind_group = Self::create_resolve_bind_group(
+            device,
+            &self.resolve_layout,
+            &self.uniform_buffer,
+            &self.accumulation_buffer,
+        );
+        self.create_trace_bind_group(device);
+        self.uniform = None;
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn update(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        scene: &Scene,
+        camera: &Camera,
+        settings: &Settings,
+    ) {
+        self.enabled = settings.path_tracing;
+        self.tracing = false;
+        if !self.enabled {
+            self.uniform = None;
+            return;
+        }
+
+        // The hierarchy is built once models are loaded and refit as they move
+        match self.scene_bvh.update(scene) {
+            BvhUpdate::Rebuilt => {
+                self.triangles = gather_triangles(scene);
+                self.scene_buffers = Some(self.create_scene_buffers(device, scene));
+                self.create_trace_bind_group(device);
+                self.uniform = None;
+            }
+            BvhUpdate::Refit => {
+                let mut triangles = self.triangles.iter_mut();
+                for_each_scene_triangle(scene, |triangle| {
+                    if let Some(trace_triangle) = triangles.next() {
+                        trace_triangle.transform(&triangle);
+                    }
+                });
+                if let Some(scene_buffers) = self.scene_buffers.as_ref() {
+                    queue.write_buffer(
+                        &scene_buffers.triangle_buffer,
+                        0,
+                        bytemuck::cast_slice(&self.ordered_triangles()),
+                    );
+                    queue.write_buffer(
+                        &scene_buffers.node_buffer,
+                        0,
+                        bytemuck::cast_slice(&self.scene_bvh.bvh().gpu_nodes()),
+                    );
+                }
+                self.uniform = None;
+            }
+            BvhUpdate::Unchanged => {}
+        }
+
+        let materials = gather_materials(scene);
+        if let Some(scene_buffers) = self.scene_buffers.as_mut() {
+            if scene_buffers.materials != materials {
+                queue.write_buffer(
+                    &scene_buffers.material_buffer,
+                    0,
+                    bytemuck::cast_slice(&materials),
+                );
+                scene_buffers.materials = materials;
+                self.uniform = None;
+            }
+        }
+
+        let view_projection =
+            camera.projection_matrix(aspect_ratio(&self.dimensions)) * camera.view_matrix();
+        let sun_color = scene.sun.radiance();
+        let mut uniform = PathTracerUniform {
+            inverse_view_projection: glm::inverse(&view_projection).into(),
+            camera_position: glm::vec3_to_vec4(&camera.position).into(),
+            sun_direction: glm::vec3_to_vec4(&-glm::normalize(&scene.sun.direction)).into(),
+            sun_color: glm::vec3_to_vec4(&sun_color).into(),
+            sky_color: glm::vec3_to_vec4(&settings.clear_color).into(),
+            parameters: [self.dimensions[0], self.dimensions[1], 0, MAX_BOUNCES],
+        };
+
+        match self.uniform {
+            Some(previous) => {
+                let mut unchanged = previous;
+                unchanged.parameters[2] = 0;
+                if unchanged != uniform {
+                    self.tracing = true;
+                } else if previous.parameters[2] + 1 < MAX_SAMPLES {
+                    uniform.parameters[2] = previous.parameters[2] + 1;
+                    self.tracing = true;
+                } else {
+                    uniform = previous;
+                }
+            }
+            None => self.tracing = true,
+        }
+        self.uniform = Some(uniform);
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[uniform]));
+    }
+
+    // Triangles sorted so every leaf's are contiguous
+    fn ordered_triangles(&self) -> Vec<TraceTriangle> {
+        let mut triangles = self
+            .scene_bvh
+            .bvh()
+            .triangle_order()
+            .iter()
+            .map(|index| self.triangles[*index])
+            .collect::<Vec<_>>();
+        // Bindings can't be empty
+        if triangles.is_empty() {
+            triangles.push(TraceTriangle::default());
+        }
+        triangles
+    }
+
+    fn create_scene_buffers(&self, device: &wgpu::Device, scene: &Scene) -> SceneBuffers {
+        let triangles = self.ordered_triangles();
+        let nodes = self.scene_bvh.bvh().gpu_nodes();
+        let mut materials = gather_materials(scene);
+        if materials.is_empty() {
+            materials.push(TraceMaterial::default());
+        }
+
+        let triangle_buffer = memory::create_buffer_init(
+            device,
+            MemoryCategory::Meshes,
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("Path Tracer Triangle Buffer"),
+                contents: bytemuck::cast_slice(&triangles),
+                usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            },
+        );
+        let node_buffer = memory::create_buffer_init(
+            device,
+            MemoryCategory::Meshes,
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("Path Tracer Node Buffer"),
+                contents: bytemuck::cast_slice(&nodes),
+                usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            },
+        );
+        let material_buffer = memory::create_buffer_init(
+            device,
+            MemoryCategory::Meshes,
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("Path Tracer Material Buffer"),
+                contents: bytemuck::cast_slice(&materials),
+                usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            },
+        );
+
+        SceneBuffers {
+            triangle_buffer,
+            node_buffer,
+            material_buffer,
+            materials,
+        }
+    }
+
+    pub fn render(&self, encoder: &mut wgpu::CommandEncoder, scene_color: &wgpu::TextureView) {
+        if let (true, Some(trace_bind_group)) = (self.tracing, self.trace_bind_group.as_ref()) {
+            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("Path Tracer Pass"),
+            });
+            compute_pass.set_pipeline(&self.trace_pipeline);
+            compute_pass.set_bind_group(0, trace_bind_group, &[]);
+            compute_pass.dispatch(
+                self.dimensions[0].div_ceil(WORKGROUP_SIZE),
+                self.dimensions[1].div_ceil(WORKGROUP_SIZE),
+                1,
+            );
+        }
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Path Tracer Resolve Pass"),
+            color_attachments: &[wgpu::RenderPassColorAttachment {
+                view: scene_color,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: true,
+                },
+            }],
+            depth_stencil_attachment: None,
+        });
+        render_pass.set_pipeline(&self.resolve_pipeline);
+        render_pass.set_bind_group(0, &self.resolve_bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
+
+// Each model's materials followed by the default material its primitives without one use
+fn gather_materials(scene: &Scene) -> Vec<TraceMaterial> {
+    let default_material = Material::default();
+    scene
+        .models
+        .iter()
+        .flat_map(|model| {
+            model
+                .materials
+                .iter()
+                .chain(std::iter::once(&default_material))
+                .map(TraceMaterial::new)
+        })
+        .collect()
+}
+
+fn gather_triangles(scene: &Scene) -> Vec<TraceTriangle> {
+    let mut material_offsets = Vec::new();
+    let mut material_offset = 0;
+    for model in scene.models.iter() {
+        material_offsets.push(material_offset);
+        material_offset += model.materials.len() + 1;
+    }
+    let base_colors = scene
+        .models
+        .iter()
+        .map(BaseColorImages::new)
+        .collect::<Vec<_>>();
+
+    let mut triangles = Vec::new();
+    for_each_scene_triangle(scene, |triangle| {
+        let model = &scene.models[triangle.model];
+        let material = triangle
+            .primitive
+            .material
+            .filter(|material| *material < model.materials.len())
+            .unwrap_or(model.materials.len());
+        let mut trace_triangle = TraceTriangle::default();
+        for (corner, vertex) in triangle.vertices.iter().enumerate() {
+            let color = glm::Vec4::from(vertex.color).component_mul(
+                &base_colors[triangle.model].sample(model.materials.get(material), vertex),
+            );
+            trace_triangle.colors[corner] = color.into();
+        }
+        trace_triangle.positions[0][3] =
+            f32::from_bits((material_offsets[triangle.model] + material) as u32);
+        trace_triangle.transform(&triangle);
+        triangles.push(trace_triangle);
+    });
+    triangles
+}
+
+// Base color textures decoded once per model so vertices can be colored by them
+struct BaseColorImages {
+    images: HashMap<usize, image::RgbaImage>,
+}
+
+impl BaseColorImages {
+    fn new(model: &ModelDesc) -> Self {
+        let images = model
+            .materials
+            .iter()
+            .filter_map(|material| material.base_color_texture)
+            .filter_map(|image| {
+                model
+                    .images
+                    .get(image)
+                    .map(|source| (image, source.to_rgba8()))
+            })
+            .collect();
+        Self { images }
+    }
+
+    fn sample(&self, material: Option<&Material>, vertex: &ModelVertex) -> glm::Vec4 {
+        let white = glm::vec4(1.0, 1.0, 1.0, 1.0);
+        let material = match material {
+            Some(material) => material,
+            None => return white,
+        };
+        let image = match material
+            .base_color_texture
+            .and_then(|image| self.images.get(&image))
+        {
+            Some(image) if image.width() > 0 && image.height() > 0 => image,
+            _ => return white,
+        };
+        let transform = &material.texture_transforms.base_color;
+        let uv = if transform.tex_coord == 1 {
+            vertex.uv_1
+        } else {
+            vertex.uv
+        };
+        let uv = transform.matrix() * glm::vec3(uv[0], uv[1], 1.0);
+        let x = (uv.x * image.width() as f32).floor() as i64;
+        let y = (uv.y * image.height() as f32).floor() as i64;
+        let texel = image.get_pixel(
+            x.rem_euclid(image.width() as i64) as u32,
+            y.rem_euclid(image.height() as i64) as u32,
+        );
+        glm::vec4(
+            srgb_to_linear(texel[0]),
+            srgb_to_linear(texel[1]),
+            srgb_to_linear(texel[2]),
+            texel[3] as f32 / 255.0,
+        )
+    }
+}
+
+fn srgb_to_linear(value: u8) -> f32 {
+    let value = value as f32 / 255.0;
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
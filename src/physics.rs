@@ -0,0 +1,380 @@
+use anyhow::{bail, Result};
+use instant::Instant;
+use nalgebra_glm as glm;
+use rapier3d::{na, prelude::*};
+
+use crate::{
+    lines::{LineJoin, LineSpace, PolylineDesc},
+    scene::{ModelHandle, PolylineHandle, Scene},
+};
+
+// Seconds simulated per step, frames run as many steps as the time since the last one covers
+const FIXED_TIME_STEP: f32 = 1.0 / 60.0;
+// Long hitches are dropped instead of simulated all at once
+const MAX_STEPS_PER_UPDATE: u32 = 8;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct BodyHandle(pub usize);
+
+#[allow(dead_code)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BodyKind {
+    // Never moves, such as level geometry
+    Static,
+    // Moved by the simulation, which writes its transform back to the model
+    Dynamic,
+    // Follows the model's transform and pushes dynamic bodies out of its way
+    Kinematic,
+}
+
+#[allow(dead_code)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ColliderShape {
+    // The mesh's exact triangles, which have no volume and so only suit static and kinematic bodies
+    TriangleMesh,
+    // The smallest convex shape around the mesh's vertices
+    ConvexHull,
+    // The box around the mesh's vertices
+    Box,
+}
+
+// A rigid body moving a model, with a collider for each of the model's mesh instances
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct RigidBodyDesc {
+    pub model: ModelHandle,
+    pub kind: BodyKind,
+    pub shape: ColliderShape,
+    // Kilograms per cubic meter
+    pub density: f32,
+    pub friction: f32,
+    // How much speed is kept when bouncing, from none to all of it
+    pub restitution: f32,
+}
+
+impl Default for RigidBodyDesc {
+    fn default() -> Self {
+        Self {
+            model: ModelHandle(0),
+            kind: BodyKind::Dynamic,
+            shape: ColliderShape::ConvexHull,
+            density: 1000.0,
+            friction: 0.5,
+            restitution: 0.0,
+        }
+    }
+}
+
+struct Body {
+    model: ModelHandle,
+    kind: BodyKind,
+    handle: RigidBodyHandle,
+    // Rigid bodies can't scale, so the model's scale is baked into its colliders and kept
+    // to rebuild the model's transform
+    scale: glm::Vec3,
+}
+
+// Simulates rigid bodies created from models at a fixed rate and writes their transforms back
+// to the models
+pub struct PhysicsSystem {
+    pub gravity: glm::Vec3,
+    pipeline: PhysicsPipeline,
+    integration_parameters: IntegrationParameters,
+    islands: IslandManager,
+    broad_phase: BroadPhase,
+    narrow_phase: NarrowPhase,
+    rigid_bodies: RigidBodySet,
+    colliders: ColliderSet,
+    joints: JointSet,
+    ccd_solver: CCDSolver,
+    bodies: Vec<Body>,
+    accumulator: f32,
+    last_update: Option<Instant>,
+    // Reused each frame for the collider wireframes, emptied when they're hidden
+    polylines: Vec<PolylineHandle>,
+}
+
+impl Default for PhysicsSystem {
+    fn default() -> Self {
+        Self {
+            gravity: glm::vec3(0.0, -9.81, 0.0),
+            pipeline: PhysicsPipeline::new(),
+            integration_parameters: IntegrationParameters {
+                dt: FIXED_TIME_STEP,
+                ..Default::default()
+            },
+            islands: IslandManager::new(),
+            broad_phase: BroadPhase::new(),
+            narrow_phase: NarrowPhase::new(),
+            rigid_bodies: RigidBodySet::new(),
+            colliders: ColliderSet::new(),
+            joints: JointSet::new(),
+            ccd_solver: CCDSolver::new(),
+            bodies: Vec::new(),
+            accumulator: 0.0,
+            last_update: None,
+            polylines: Vec::new(),
+        }
+    }
+}
+
+impl PhysicsSystem {
+    #[allow(dead_code)]
+    pub fn spawn_body(&mut self, scene: &Scene, desc: RigidBodyDesc) -> Result<BodyHandle> {
+        let model = match scene.models.get(desc.model.0) {
+            Some(model) => model,
+            None => bail!("Rigid body refers to missing model {}!", desc.model.0),
+        };
+        let (position, scale) = decompose(&model.transform.cast());
+
+        let rigid_body = match desc.kind {
+            BodyKind::Static => RigidBodyBuilder::new_static(),
+            BodyKind::Dynamic => RigidBodyBuilder::new_dynamic(),
+            BodyKind::Kinematic => RigidBodyBuilder::new_kinematic_position_based(),
+        }
+        .position(position)
+        .build();
+        let handle = self.rigid_bodies.insert(rigid_body);
+
+        for instance in model.instances.iter() {
+            let mesh = match model.meshes.get(instance.mesh) {
+                Some(mesh) => mesh,
+                None => continue,
+            };
+            let transform = glm::scaling(&scale) * instance.transform.cast::<f32>();
+            let mut vertices = Vec::new();
+            let mut indices = Vec::new();
+            for primitive in mesh.primitives.iter() {
+                let offset = vertices.len() as u32;
+                vertices.extend(primitive.vertices.iter().map(|vertex| {
+                    let position = transform * glm::Vec3::from(vertex.position).push(1.0);
+                    Point::new(position.x, position.y, position.z)
+                }));
+                indices.extend(
+                    primitive
+                        .indices
+                        .chunks_exact(3)
+                        .map(|triangle| [triangle[0], triangle[1], triangle[2]])
+                        .filter(|triangle| {
+                            triangle
+                                .iter()
+                                .all(|index| (*index as usize) < primitive.vertices.len())
+                        })
+                        .map(|triangle| triangle.map(|index| index + offset)),
+                );
+            }
+            if vertices.is_empty() {
+                continue;
+            }
+
+            let collider = match desc.shape {
+                ColliderShape::TriangleMesh if indices.is_empty() => continue,
+                ColliderShape::TriangleMesh => ColliderBuilder::trimesh(vertices, indices),
+                ColliderShape::ConvexHull => match ColliderBuilder::convex_hull(&vertices) {
+                    Some(collider) => collider,
+                    None => continue,
+                },
+                ColliderShape::Box => {
+                    let mut min = vertices[0];
+                    let mut max = vertices[0];
+                    for vertex in vertices.iter() {
+                        min = min.inf(vertex);
+                        max = max.sup(vertex);
+                    }
+                    let half_extents = (max - min) * 0.5;
+                    ColliderBuilder::cuboid(half_extents.x, half_extents.y, half_extents.z)
+                        .position(Isometry::translation(
+                            (min.x + max.x) * 0.5,
+                            (min.y + max.y) * 0.5,
+                            (min.z + max.z) * 0.5,
+                        ))
+                }
+            }
+            .density(desc.density)
+            .friction(desc.friction)
+            .restitution(desc.restitution)
+            .build();
+            self.colliders
+                .insert_with_parent(collider, handle, &mut self.rigid_bodies);
+        }
+
+        self.bodies.push(Body {
+            model: desc.model,
+            kind: desc.kind,
+            handle,
+            scale,
+        });
+        Ok(BodyHandle(self.bodies.len() - 1))
+    }
+
+    #[allow(dead_code)]
+    pub fn apply_impulse(&mut self, body: BodyHandle, impulse: glm::Vec3) {
+        if let Some(rigid_body) = self
+            .bodies
+            .get(body.0)
+            .and_then(|body| self.rigid_bodies.get_mut(body.handle))
+        {
+            rigid_body.apply_impulse(impulse, true);
+        }
+    }
+
+    // Catches the simulation up to the current time, or by the fixed step when given one, then
+    // moves the models and redraws the collider wireframes
+    pub fn update(&mut self, scene: &mut Scene, draw_colliders: bool, step: Option<f32>) {
+        let now = Instant::now();
+        if let Some(last_update) = self.last_update {
+            self.accumulator += step.unwrap_or(now.duration_since(last_update).as_secs_f32());
+        }
+        self.last_update = Some(now);
+
+        for body in self.bodies.iter() {
+            if body.kind != BodyKind::Kinematic {
+                continue;
+            }
+            if let (Some(model), Some(rigid_body)) = (
+                scene.models.get(body.model.0),
+                self.rigid_bodies.get_mut(body.handle),
+            ) {
+                rigid_body.set_next_kinematic_position(decompose(&model.transform.cast()).0);
+            }
+        }
+
+        let mut steps = 0;
+        while self.accumulator >= FIXED_TIME_STEP {
+            self.accumulator -= FIXED_TIME_STEP;
+            steps += 1;
+            if steps > MAX_STEPS_PER_UPDATE {
+                self.accumulator = 0.0;
+                break;
+            }
+            self.pipeline.step(
+                &self.gravity,
+                &self.integration_parameters,
+                &mut self.islands,
+                &mut self.broad_phase,
+                &mut self.narrow_phase,
+                &mut self.rigid_bodies,
+                &mut self.colliders,
+                &mut self.joints,
+                &mut self.ccd_solver,
+                &(),
+                &(),
+            );
+        }
+
+        for body in self.bodies.iter() {
+            if body.kind != BodyKind::Dynamic {
+                continue;
+            }
+            if let (Some(model), Some(rigid_body)) = (
+                scene.models.get_mut(body.model.0),
+                self.rigid_bodies.get(body.handle),
+            ) {
+                model.transform =
+                    (rigid_body.position().to_homogeneous() * glm::scaling(&body.scale)).cast();
+            }
+        }
+
+        self.draw_colliders(scene, draw_colliders);
+    }
+
+    fn draw_colliders(&mut self, scene: &mut Scene, visible: bool) {
+        let mut outlines = Vec::new();
+        if visible {
+            for (_, collider) in self.colliders.iter() {
+                collider_outlines(collider, &mut outlines);
+            }
+        }
+
+        while self.polylines.len() < outlines.len() {
+            self.polylines
+                .push(scene.spawn_polyline(PolylineDesc::default()));
+        }
+        for (index, handle) in self.polylines.iter().enumerate() {
+            let polyline = match scene.polylines.get_mut(handle.0) {
+                Some(polyline) => polyline,
+                None => continue,
+            };
+            let (points, closed) = outlines.get(index).cloned().unwrap_or_default();
+            *polyline = PolylineDesc {
+                points,
+                space: LineSpace::World,
+                width: 1.5,
+                color: glm::vec4(0.2, 1.0, 0.4, 1.0),
+                join: LineJoin::Miter,
+                dashes: None,
+                closed,
+                depth_test: false,
+            };
+        }
+    }
+}
+
+// Splits a transform into the rotation and translation a rigid body can have and its scale
+fn decompose(transform: &glm::Mat4) -> (Isometry<Real>, glm::Vec3) {
+    let basis = glm::mat4_to_mat3(transform);
+    let scale = glm::vec3(
+        basis.column(0).magnitude(),
+        basis.column(1).magnitude(),
+        basis.column(2).magnitude(),
+    );
+    let rotation = glm::Mat3::from_columns(&[
+        basis.column(0) / scale.x.max(f32::EPSILON),
+        basis.column(1) / scale.y.max(f32::EPSILON),
+        basis.column(2) / scale.z.max(f32::EPSILON),
+    ]);
+    let rotation = na::UnitQuaternion::from_matrix(&rotation);
+    let translation =
+        na::Translation3::new(transform[(0, 3)], transform[(1, 3)], transform[(2, 3)]);
+    (Isometry::from_parts(translation, rotation), scale)
+}
+
+// World space outlines of a collider's edges and whether each one closes back on itself
+fn collider_outlines(collider: &Collider, outlines: &mut Vec<(Vec<glm::Vec3>, bool)>) {
+    let position = collider.position();
+    let world = |point: &Point<Real>| {
+        let point = position * point;
+        glm::vec3(point.x, point.y, point.z)
+    };
+    let shape = collider.shape();
+    if let Some(trimesh) = shape.as_trimesh() {
+        let vertices = trimesh.vertices();
+        for triangle in trimesh.indices() {
+            outlines.push((
+                triangle
+                    .iter()
+                    .map(|index| world(&vertices[*index as usize]))
+                    .collect(),
+                true,
+            ));
+        }
+    } else if let Some(hull) = shape.as_convex_polyhedron() {
+        let points = hull.points();
+        for edge in hull.edges() {
+            outlines.push((
+                vec![
+                    world(&points[edge.vertices[0] as usize]),
+                    world(&points[edge.vertices[1] as usize]),
+                ],
+                false,
+            ));
+        }
+    } else if let Some(cuboid) = shape.as_cuboid() {
+        let half = cuboid.half_extents;
+        let corner =
+            |x: f32, y: f32, z: f32| world(&Point::new(x * half.x, y * half.y, z * half.z));
+        for y in [-1.0, 1.0] {
+            outlines.push((
+                vec![
+                    corner(-1.0, y, -1.0),
+                    corner(1.0, y, -1.0),
+                    corner(1.0, y, 1.0),
+                    corner(-1.0, y, 1.0),
+                ],
+                true,
+            ));
+        }
+        for (x, z) in [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)] {
+            outlines.push((vec![corner(x, -1.0, z), corner(x, 1.0, z)], false));
+        }
+    }
+}
@@ -0,0 +1,378 @@
+use nalgebra_glm as glm;
+use std::ops::Range;
+
+use crate::{
+    bounds::{Aabb, Frustum},
+    memory::{self, MemoryCategory, Tracked},
+    scene::Scene,
+    texture::DepthConfig,
+};
+
+// Points are split into chunks of at most this many, each culled on its own
+const CHUNK_SIZE: usize = 16384;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct CloudPoint {
+    pub position: glm::Vec3,
+    // Color in sRGB and linear alpha
+    pub color: [u8; 4],
+}
+
+#[allow(dead_code)]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum PointSize {
+    // Diameter in pixels, the same at any distance
+    Screen(f32),
+    // Diameter in world units, shrinking with distance
+    World(f32),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PointCloudDesc {
+    pub points: Vec<CloudPoint>,
+    pub transform: glm::Mat4,
+    pub size: PointSize,
+    // Points are discarded outside a circle instead of drawn as squares
+    pub round: bool,
+}
+
+impl Default for PointCloudDesc {
+    fn default() -> Self {
+        Self {
+            points: Vec::new(),
+            transform: glm::Mat4::identity(),
+            size: PointSize::Screen(2.0),
+            round: true,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct PointVertex {
+    position: [f32; 3],
+    color: [u8; 4],
+}
+
+impl PointVertex {
+    const ATTRIBUTES: [wgpu::VertexAttribute; 2] = wgpu::vertex_attr_array![
+        0 => Float32x3,
+        1 => Unorm8x4
+    ];
+
+    fn layout<'a>() -> wgpu::VertexBufferLayout<'a> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<Self>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &Self::ATTRIBUTES,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct PointCloudUniform {
+    model: [[f32; 4]; 4],
+    // x: diameter, y: world space size when one, z: round when one
+    parameters: [f32; 4],
+}
+
+impl PointCloudUniform {
+    // The transform is relative to the scene's origin
+    fn new(desc: &PointCloudDesc, transform: &glm::Mat4) -> Self {
+        let (size, world) = match desc.size {
+            PointSize::Screen(size) => (size, 0.0),
+            PointSize::World(size) => (size, 1.0),
+        };
+        Self {
+            model: (*transform).into(),
+            parameters: [size, world, if desc.round { 1.0 } else { 0.0 }, 0.0],
+        }
+    }
+}
+
+// Points stored next to each other in the vertex buffer, in the local space of the cloud
+struct PointChunk {
+    bounds: Aabb,
+    points: Range<u32>,
+}
+
+fn bounds_of(points: &[PointVertex]) -> Aabb {
+    let mut bounds = Aabb::default();
+    for point in points {
+        bounds.expand_to_include(&glm::Vec3::from(point.position));
+    }
+    bounds
+}
+
+// Splits the points in half across their longest axis until each chunk is small enough,
+// reordering them so every chunk is contiguous
+fn split_chunks(points: &mut [PointVertex], offset: usize, chunks: &mut Vec<PointChunk>) {
+    let bounds = bounds_of(points);
+    if points.len() <= CHUNK_SIZE {
+        chunks.push(PointChunk {
+            bounds,
+            points: offset as u32..(offset + points.len()) as u32,
+        });
+        return;
+    }
+    let extents = bounds.extents();
+    let axis = if extents.x >= extents.y && extents.x >= extents.z {
+        0
+    } else if extents.y >= extents.z {
+        1
+    } else {
+        2
+    };
+    let middle = points.len() / 2;
+    points.select_nth_unstable_by(middle, |a, b| a.position[axis].total_cmp(&b.position[axis]));
+    let (low, high) = points.split_at_mut(middle);
+    split_chunks(low, offset, chunks);
+    split_chunks(high, offset + middle, chunks);
+}
+
+struct GpuPointCloud {
+    vertex_buffer: Tracked<wgpu::Buffer>,
+    uniform_buffer: Tracked<wgpu::Buffer>,
+    bind_group: wgpu::BindGroup,
+    chunks: Vec<PointChunk>,
+}
+
+pub struct PointCloudSystem {
+    bind_group_layout: wgpu::BindGroupLayout,
+    pipeline: wgpu::RenderPipeline,
+    depth_pipeline: wgpu::RenderPipeline,
+    clouds: Vec<GpuPointCloud>,
+}
+
+impl PointCloudSystem {
+    pub fn new(
+        device: &wgpu::Device,
+        camera_bind_group_layout: &wgpu::BindGroupLayout,
+        color_format: wgpu::TextureFormat,
+        depth: DepthConfig,
+    ) -> Self {
+        let bind_group_layout = memory::create_bind_group_layout(
+            device,
+            &wgpu::BindGroupLayoutDescriptor {
+                label: Some("Point Cloud Bind Group Layout"),
+                entries: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                }],
+            },
+        );
+
+        let module = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("Point Cloud Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("shaders/points.wgsl").into()),
+        });
+
+        let pipeline_layout = memory::create_pipeline_layout(
+            device,
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("Point Cloud Pipeline Layout"),
+                bind_group_layouts: &[camera_bind_group_layout, &bind_group_layout],
+                push_constant_ranges: &[],
+            },
+        );
+
+        let create_pipeline =
+            |label, fragment_entry_point, targets: &[wgpu::ColorTargetState], multisample| {
+                device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                    label: Some(label),
+                    layout: Some(&pipeline_layout),
+                    vertex: wgpu::VertexState {
+                        module: &module,
+                        entry_point: "vs_main",
+                        buffers: &[PointVertex::layout()],
+                    },
+                    primitive: wgpu::PrimitiveState::default(),
+                    depth_stencil: Some(wgpu::DepthStencilState {
+                        format: depth.format,
+                        depth_write_enabled: true,
+                        depth_compare: depth.compare(wgpu::CompareFunction::LessEqual),
+                        stencil: wgpu::StencilState::default(),
+                        bias: wgpu::DepthBiasState::default(),
+                    }),
+                    multisample,
+                    fragment: Some(wgpu::FragmentState {
+                        module: &module,
+                        entry_point: fragment_entry_point,
+                        targets,
+                    }),
+                })
+            };
+
+        let pipeline = create_pipeline(
+            "Point Cloud Pipeline",
+            "fs_main",
+            &[wgpu::ColorTargetState {
+                format: color_format,
+                blend: Some(wgpu::BlendState::REPLACE),
+                write_mask: wgpu::ColorWrites::ALL,
+            }],
+            depth.multisample(),
+        );
+        let depth_pipeline = create_pipeline(
+            "Point Cloud Depth Pipeline",
+            "fs_depth",
+            &[],
+            wgpu::MultisampleState::default(),
+        );
+
+        Self {
+            bind_group_layout,
+            pipeline,
+            depth_pipeline,
+            clouds: Vec::new(),
+        }
+    }
+
+    fn create_cloud(&self, device: &wgpu::Device, desc: &PointCloudDesc) -> GpuPointCloud {
+        let mut vertices = desc
+            .points
+            .iter()
+            .map(|point| PointVertex {
+                position: point.position.into(),
+                color: point.color,
+            })
+            .collect::<Vec<_>>();
+        let mut chunks = Vec::new();
+        if !vertices.is_empty() {
+            split_chunks(&mut vertices, 0, &mut chunks);
+        }
+
+        let vertex_buffer = memory::create_buffer_init(
+            device,
+            MemoryCategory::Meshes,
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("Point Cloud Vertex Buffer"),
+                contents: bytemuck::cast_slice(&vertices),
+                usage: wgpu::BufferUsages::VERTEX,
+            },
+        );
+
+        let uniform_buffer = memory::create_buffer(
+            device,
+            MemoryCategory::Transient,
+            &wgpu::BufferDescriptor {
+                label: Some("Point Cloud Uniform Buffer"),
+                size: std::mem::size_of::<PointCloudUniform>() as wgpu::BufferAddress,
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            },
+        );
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Point Cloud Bind Group"),
+            layout: &self.bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: uniform_buffer.as_entire_binding(),
+            }],
+        });
+
+        GpuPointCloud {
+            vertex_buffer,
+            uniform_buffer,
+            bind_group,
+            chunks,
+        }
+    }
+
+    pub fn update(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, scene: &Scene) {
+        while self.clouds.len() < scene.point_clouds.len() {
+            let cloud = self.create_cloud(device, &scene.point_clouds[self.clouds.len()]);
+            self.clouds.push(cloud);
+        }
+
+        for (desc, cloud) in scene.point_clouds.iter().zip(self.clouds.iter()) {
+            queue.write_buffer(
+                &cloud.uniform_buffer,
+                0,
+                bytemuck::cast_slice(&[PointCloudUniform::new(
+                    desc,
+                    &scene.local_transform(&desc.transform.cast()),
+                )]),
+            );
+        }
+    }
+
+    fn draw<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        camera_bind_group: &'a wgpu::BindGroup,
+        scene: &Scene,
+        frustum: &Frustum,
+    ) {
+        render_pass.set_bind_group(0, camera_bind_group, &[]);
+        for (desc, cloud) in scene.point_clouds.iter().zip(self.clouds.iter()) {
+            if desc.points.is_empty() {
+                continue;
+            }
+            render_pass.set_bind_group(1, &cloud.bind_group, &[]);
+            render_pass.set_vertex_buffer(0, cloud.vertex_buffer.slice(..));
+            let transform = scene.local_transform(&desc.transform.cast());
+
+            // Neighbouring visible chunks are drawn together
+            let mut visible: Option<Range<u32>> = None;
+            for chunk in cloud.chunks.iter() {
+                // World sized points can reach past the bounds of their centers
+                let mut bounds = chunk.bounds.transformed(&transform);
+                if let PointSize::World(size) = desc.size {
+                    let radius = glm::vec3(size, size, size) * 0.5;
+                    bounds.min -= radius;
+                    bounds.max += radius;
+                }
+                if !frustum.intersects_aabb(&bounds) {
+                    if let Some(points) = visible.take() {
+                        render_pass.draw(0..6, points);
+                    }
+                    continue;
+                }
+                visible = match visible {
+                    Some(points) if points.end == chunk.points.start => {
+                        Some(points.start..chunk.points.end)
+                    }
+                    Some(points) => {
+                        render_pass.draw(0..6, points);
+                        Some(chunk.points.clone())
+                    }
+                    None => Some(chunk.points.clone()),
+                };
+            }
+            if let Some(points) = visible {
+                render_pass.draw(0..6, points);
+            }
+        }
+    }
+
+    pub fn render_depth<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        camera_bind_group: &'a wgpu::BindGroup,
+        scene: &Scene,
+        frustum: &Frustum,
+    ) {
+        render_pass.set_pipeline(&self.depth_pipeline);
+        self.draw(render_pass, camera_bind_group, scene, frustum);
+    }
+
+    pub fn render<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        camera_bind_group: &'a wgpu::BindGroup,
+        scene: &Scene,
+        frustum: &Frustum,
+    ) {
+        render_pass.set_pipeline(&self.pipeline);
+        self.draw(render_pass, camera_bind_group, scene, frustum);
+    }
+}
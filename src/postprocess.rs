@@ -0,0 +1,353 @@
+use nalgebra_glm as glm;
+
+use crate::{
+    camera::Camera,
+    memory::{self, MemoryCategory, Tracked},
+    settings::{FogMode, Settings, ToneMapping},
+    shader_snippets,
+    texture::Texture,
+};
+
+#[repr(C)]
+#[derive(Default, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct PostProcessUniform {
+    // w: environment blend
+    fog_color: [f32; 4],
+    environment_color: [f32; 4],
+    // x: density, y: start, z: end
+    fog_distance: [f32; 4],
+    // x: density, y: falloff, z: base height
+    fog_height: [f32; 4],
+    // x: density, y: anisotropy, z: max distance
+    volumetric: [f32; 4],
+    // x: vignette, y: chromatic aberration, z: film grain, w: grain seed
+    imperfections: [f32; 4],
+    fog_mode: u32,
+    // Zero when volumetric lighting is disabled
+    volumetric_samples: u32,
+    // Zero when bloom is disabled
+    bloom_intensity: f32,
+    exposure: f32,
+    tone_mapping: u32,
+    // Replaces the exposure with the adapted one when set
+    auto_exposure: u32,
+    // 0: off, 1: dithered in sRGB space for sRGB outputs, 2: dithered as is for linear outputs
+    dithering: u32,
+    _padding: u32,
+}
+
+impl PostProcessUniform {
+    fn new(settings: &Settings, camera: &Camera, frame_index: u64, srgb_output: bool) -> Self {
+        let fog = &settings.fog;
+        let imperfections = &settings.imperfections;
+        let volumetric = &settings.volumetric;
+        let volumetric_samples = if volumetric.enabled {
+            volumetric
+                .samples
+                .min(settings.effect_quality.max_volumetric_samples())
+        } else {
+            0
+        };
+        Self {
+            fog_color: [fog.color.x, fog.color.y, fog.color.z, fog.environment_blend],
+            // The clear color is the environment seen behind the scene
+            environment_color: glm::vec3_to_vec4(&settings.clear_color).into(),
+            fog_distance: [fog.density, fog.start, fog.end, 0.0],
+            fog_height: [fog.height_density, fog.height_falloff, fog.base_height, 0.0],
+            volumetric: [
+                volumetric.density,
+                volumetric.anisotropy,
+                volumetric.max_distance,
+                0.0,
+            ],
+            // The grain seed wraps before it loses precision
+            imperfections: [
+                imperfections.vignette,
+                imperfections.chromatic_aberration,
+                imperfections.film_grain,
+                (frame_index % 1024) as f32,
+            ],
+            fog_mode: match fog.mode {
+                FogMode::None => 0,
+                FogMode::Linear => 1,
+                FogMode::Exponential => 2,
+                FogMode::ExponentialSquared => 3,
+            },
+            volumetric_samples,
+            bloom_intensity: if settings.bloom.enabled {
+                settings.bloom.intensity
+            } else {
+                0.0
+            },
+            // A physical camera sets its own exposure
+            exposure: camera.exposure().unwrap_or(settings.exposure),
+            tone_mapping: match settings.tone_mapping {
+                ToneMapping::None => 0,
+                ToneMapping::Reinhard => 1,
+                ToneMapping::Aces => 2,
+            },
+            auto_exposure: settings.auto_exposure.enabled as u32,
+            dithering: match (settings.dithering, srgb_output) {
+                (false, _) => 0,
+                (true, true) => 1,
+                (true, false) => 2,
+            },
+            _padding: 0,
+        }
+    }
+}
+
+// Applies full screen effects to the HDR scene color and resolves it into the surface format
+pub struct PostProcess {
+    uniform_buffer: Tracked<wgpu::Buffer>,
+    bind_group_layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+    pipeline: wgpu::RenderPipeline,
+    // The output is quantized after the hardware encodes it to sRGB
+    srgb_output: bool,
+}
+
+impl PostProcess {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        device: &wgpu::Device,
+        camera_bind_group_layout: &wgpu::BindGroupLayout,
+        light_bind_group_layout: &wgpu::BindGroupLayout,
+        output_format: wgpu::TextureFormat,
+        scene_color: &Texture,
+        depth_texture: &Texture,
+        bloom_texture: &Texture,
+        exposure_buffer: &wgpu::Buffer,
+    ) -> Self {
+        let uniform_buffer = memory::create_buffer(
+            device,
+            MemoryCategory::Transient,
+            &wgpu::BufferDescriptor {
+                label: Some("Post Process Uniform Buffer"),
+                size: std::mem::size_of::<PostProcessUniform>() as wgpu::BufferAddress,
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            },
+        );
+
+        let bind_group_layout = memory::create_bind_group_layout(
+            device,
+            &wgpu::BindGroupLayoutDescriptor {
+                label: Some("Post Process Bind Group Layout"),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Sampler {
+                            filtering: true,
+                            comparison: false,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 2,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Depth,
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 3,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 4,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 5,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Storage { read_only: true },
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                ],
+            },
+        );
+
+        let bind_group = Self::create_bind_group(
+            device,
+            &bind_group_layout,
+            &uniform_buffer,
+            scene_color,
+            depth_texture,
+            bloom_texture,
+            exposure_buffer,
+        );
+
+        let module = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("Post Process Shader"),
+            source: wgpu::ShaderSource::Wgsl(
+                shader_snippets::splice(include_str!("shaders/postprocess.wgsl")).into(),
+            ),
+        });
+
+        let pipeline_layout = memory::create_pipeline_layout(
+            device,
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("Post Process Pipeline Layout"),
+                bind_group_layouts: &[
+                    camera_bind_group_layout,
+                    &bind_group_layout,
+                    light_bind_group_layout,
+                ],
+                push_constant_ranges: &[],
+            },
+        );
+
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Post Process Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &module,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            fragment: Some(wgpu::FragmentState {
+                module: &module,
+                entry_point: "fs_main",
+                targets: &[wgpu::ColorTargetState {
+                    format: output_format,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                }],
+            }),
+        });
+
+        Self {
+            uniform_buffer,
+            bind_group_layout,
+            bind_group,
+            pipeline,
+            srgb_output: output_format.describe().srgb,
+        }
+    }
+
+    fn create_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        uniform_buffer: &wgpu::Buffer,
+        scene_color: &Texture,
+        depth_texture: &Texture,
+        bloom_texture: &Texture,
+        exposure_buffer: &wgpu::Buffer,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Post Process Bind Group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&scene_color.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&scene_color.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(&depth_texture.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: wgpu::BindingResource::TextureView(&bloom_texture.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: exposure_buffer.as_entire_binding(),
+                },
+            ],
+        })
+    }
+
+    pub fn resize(
+        &mut self,
+        device: &wgpu::Device,
+        scene_color: &Texture,
+        depth_texture: &Texture,
+        bloom_texture: &Texture,
+        exposure_buffer: &wgpu::Buffer,
+    ) {
+        self.bind_group = Self::create_bind_group(
+            device,
+            &self.bind_group_layout,
+            &self.uniform_buffer,
+            scene_color,
+            depth_texture,
+            bloom_texture,
+            exposure_buffer,
+        );
+    }
+
+    pub fn update(
+        &self,
+        queue: &wgpu::Queue,
+        settings: &Settings,
+        camera: &Camera,
+        frame_index: u64,
+    ) {
+        queue.write_buffer(
+            &self.uniform_buffer,
+            0,
+            bytemuck::cast_slice(&[PostProcessUniform::new(
+                settings,
+                camera,
+                frame_index,
+                self.srgb_output,
+            )]),
+        );
+    }
+
+    pub fn render<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        camera_bind_group: &'a wgpu::BindGroup,
+        light_bind_group: &'a wgpu::BindGroup,
+    ) {
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, camera_bind_group, &[]);
+        render_pass.set_bind_group(1, &self.bind_group, &[]);
+        render_pass.set_bind_group(2, light_bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
@@ -0,0 +1,193 @@
+use instant::Instant;
+use std::time::Duration;
+
+#[cfg(target_os = "linux")]
+use linux::query_power_source;
+
+#[cfg(target_os = "windows")]
+use windows::query_power_source;
+
+#[cfg(target_os = "macos")]
+use macos::query_power_source;
+
+#[cfg(target_os = "ios")]
+use ios::query_power_source;
+
+#[cfg(not(any(
+    target_os = "linux",
+    target_os = "windows",
+    target_os = "macos",
+    target_os = "ios"
+)))]
+use unsupported::query_power_source;
+
+// Querying the power source can involve the filesystem or another process
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+// Platforms without a way to query the power source only report it as unknown
+#[cfg_attr(
+    not(any(
+        target_os = "linux",
+        target_os = "windows",
+        target_os = "macos",
+        target_os = "ios"
+    )),
+    allow(dead_code)
+)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PowerSource {
+    External,
+    Battery,
+    Unknown,
+}
+
+pub struct PowerMonitor {
+    source: PowerSource,
+    last_poll: Option<Instant>,
+}
+
+impl Default for PowerMonitor {
+    fn default() -> Self {
+        Self {
+            source: PowerSource::Unknown,
+            last_poll: None,
+        }
+    }
+}
+
+impl PowerMonitor {
+    pub fn update(&mut self) {
+        let due = self
+            .last_poll
+            .is_none_or(|last_poll| last_poll.elapsed() >= POLL_INTERVAL);
+        if due {
+            self.source = query_power_source();
+            self.last_poll = Some(Instant::now());
+        }
+    }
+
+    pub fn source(&self) -> PowerSource {
+        self.source
+    }
+}
+
+#[cfg(not(any(
+    target_os = "linux",
+    target_os = "windows",
+    target_os = "macos",
+    target_os = "ios"
+)))]
+mod unsupported {
+    use super::PowerSource;
+
+    pub fn query_power_source() -> PowerSource {
+        PowerSource::Unknown
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use super::PowerSource;
+    use std::path::Path;
+
+    const POWER_SUPPLY_PATH: &str = "/sys/class/power_supply";
+
+    fn read(supply: &Path, name: &str) -> String {
+        std::fs::read_to_string(supply.join(name))
+            .map(|value| value.trim().to_string())
+            .unwrap_or_default()
+    }
+
+    pub fn query_power_source() -> PowerSource {
+        let entries = match std::fs::read_dir(POWER_SUPPLY_PATH) {
+            Ok(entries) => entries,
+            Err(_) => return PowerSource::Unknown,
+        };
+
+        let mut source = PowerSource::Unknown;
+        for entry in entries.flatten() {
+            let supply = entry.path();
+            match read(&supply, "type").as_str() {
+                "Mains" | "USB" if read(&supply, "online") == "1" => {
+                    return PowerSource::External;
+                }
+                // Batteries in peripherals such as wireless mice report a device scope
+                "Battery" if read(&supply, "scope") != "Device" => {
+                    source = match read(&supply, "status").as_str() {
+                        "Discharging" => PowerSource::Battery,
+                        _ => PowerSource::External,
+                    };
+                }
+                _ => {}
+            }
+        }
+        source
+    }
+}
+
+#[cfg(target_os = "windows")]
+mod windows {
+    use super::PowerSource;
+    use winapi::um::winbase::{GetSystemPowerStatus, SYSTEM_POWER_STATUS};
+
+    pub fn query_power_source() -> PowerSource {
+        let mut status: SYSTEM_POWER_STATUS = unsafe { std::mem::zeroed() };
+        if unsafe { GetSystemPowerStatus(&mut status) } == 0 {
+            return PowerSource::Unknown;
+        }
+        match status.ACLineStatus {
+            0 => PowerSource::Battery,
+            1 => PowerSource::External,
+            _ => PowerSource::Unknown,
+        }
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod macos {
+    use super::PowerSource;
+    use std::process::Command;
+
+    pub fn query_power_source() -> PowerSource {
+        let output = match Command::new("pmset").args(["-g", "batt"]).output() {
+            Ok(output) => output,
+            Err(_) => return PowerSource::Unknown,
+        };
+        let report = String::from_utf8_lossy(&output.stdout);
+        if report.contains("'Battery Power'") {
+            PowerSource::Battery
+        } else if report.contains("'AC Power'") {
+            PowerSource::External
+        } else {
+            PowerSource::Unknown
+        }
+    }
+}
+
+#[cfg(target_os = "ios")]
+mod ios {
+    use super::PowerSource;
+    use objc::{
+        class, msg_send,
+        runtime::{Object, YES},
+        sel, sel_impl,
+    };
+
+    // UIDeviceBatteryState
+    const BATTERY_STATE_UNPLUGGED: isize = 1;
+    const BATTERY_STATE_CHARGING: isize = 2;
+    const BATTERY_STATE_FULL: isize = 3;
+
+    pub fn query_power_source() -> PowerSource {
+        unsafe {
+            let device: *mut Object = msg_send![class!(UIDevice), currentDevice];
+            let _: () = msg_send![device, setBatteryMonitoringEnabled: YES];
+            let state: isize = msg_send![device, batteryState];
+            match state {
+                BATTERY_STATE_UNPLUGGED => PowerSource::Battery,
+                BATTERY_STATE_CHARGING | BATTERY_STATE_FULL => PowerSource::External,
+                _ => PowerSource::Unknown,
+            }
+        }
+    }
+}
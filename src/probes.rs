@@ -0,0 +1,546 @@
+use std::num::NonZeroU32;
+
+use nalgebra_glm as glm;
+
+use crate::{
+    camera::{Camera, CameraUniform},
+    memory::{self, MemoryCategory, Tracked},
+    scene::Scene,
+    ssao::AMBIENT_OCCLUSION_FORMAT,
+    texture::{DepthConfig, MultisampledTarget, Texture},
+};
+
+// Probes past this many are ignored
+pub const MAX_REFLECTION_PROBES: usize = 8;
+
+const PROBE_SIZE: u32 = 128;
+// Each mip is prefiltered for a rougher surface than the last
+const PROBE_MIPS: u32 = 5;
+pub const FACES: u32 = 6;
+
+// Direction each cube face looks in and its up vector, matched by the shaders
+const FACE_DIRECTIONS: [([f32; 3], [f32; 3]); FACES as usize] = [
+    ([1.0, 0.0, 0.0], [0.0, 1.0, 0.0]),
+    ([-1.0, 0.0, 0.0], [0.0, 1.0, 0.0]),
+    ([0.0, 1.0, 0.0], [0.0, 0.0, -1.0]),
+    ([0.0, -1.0, 0.0], [0.0, 0.0, 1.0]),
+    ([0.0, 0.0, 1.0], [0.0, 1.0, 0.0]),
+    ([0.0, 0.0, -1.0], [0.0, 1.0, 0.0]),
+];
+
+// Captures the surroundings at a point so nearby surfaces reflect them instead of nothing
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ReflectionProbeDesc {
+    pub position: glm::Vec3,
+    // Distance at which the probe's contribution has faded out
+    pub radius: f32,
+}
+
+impl Default for ReflectionProbeDesc {
+    fn default() -> Self {
+        Self {
+            position: glm::Vec3::zeros(),
+            radius: 10.0,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Default, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct ReflectionProbeUniform {
+    // xyz: position, w: radius or zero until the probe has been captured
+    probes: [[f32; 4]; MAX_REFLECTION_PROBES],
+    count: u32,
+    max_lod: f32,
+    _padding: [u32; 2],
+}
+
+#[repr(C)]
+#[derive(Default, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct PrefilterUniform {
+    // x: roughness the mip is filtered for
+    parameters: [f32; 4],
+}
+
+// Looks along one of the cube face directions
+pub fn face_camera(position: glm::Vec3, face: usize, z_far: f32) -> Camera {
+    let (direction, up) = FACE_DIRECTIONS[face];
+    Camera {
+        position,
+        target: position + glm::Vec3::from(direction),
+        up: up.into(),
+        fov: 90_f32.to_radians(),
+        z_near: 0.05,
+        z_far,
+        ..Camera::default()
+    }
+}
+
+// The views a face of a pending probe is rendered with
+pub struct ProbeFace<'a> {
+    pub camera: Camera,
+    pub camera_bind_group: &'a wgpu::BindGroup,
+    pub color: &'a wgpu::TextureView,
+    pub depth: &'a wgpu::TextureView,
+    // Drawn into first and resolved into the color when multisampling
+    pub multisampled: Option<&'a MultisampledTarget>,
+}
+
+// Renders the scene into cube faces at each probe, one probe per frame, and prefilters them
+// into a mip chain that rough surfaces sample blurrier reflections from
+pub struct ReflectionProbeSystem {
+    uniform_buffer: Tracked<wgpu::Buffer>,
+    // Six layers per probe, in the order of the face directions
+    probe_texture: Tracked<wgpu::Texture>,
+    probe_view: wgpu::TextureView,
+    sampler: wgpu::Sampler,
+    // The descs each probe was last captured with
+    captured: Vec<Option<ReflectionProbeDesc>>,
+    // The probe whose faces are rendered this frame
+    pending: Option<(usize, ReflectionProbeDesc)>,
+    // Where the pending probe is relative to the scene's origin
+    capture_position: glm::Vec3,
+    z_far: f32,
+    face_buffers: Vec<Tracked<wgpu::Buffer>>,
+    face_bind_groups: Vec<wgpu::BindGroup>,
+    capture_texture: Tracked<wgpu::Texture>,
+    capture_views: Vec<wgpu::TextureView>,
+    _capture_depth: Texture,
+    capture_depth_view: wgpu::TextureView,
+    multisampled: Option<MultisampledTarget>,
+    depth: DepthConfig,
+    ambient_occlusion_bind_group: wgpu::BindGroup,
+    prefilter_bind_groups: Vec<wgpu::BindGroup>,
+    prefilter_pipeline: wgpu::RenderPipeline,
+}
+
+impl ReflectionProbeSystem {
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        camera_bind_group_layout: &wgpu::BindGroupLayout,
+        ambient_occlusion_bind_group_layout: &wgpu::BindGroupLayout,
+        depth: DepthConfig,
+    ) -> Self {
+        let uniform_buffer = memory::create_buffer(
+            device,
+            MemoryCategory::Transient,
+            &wgpu::BufferDescriptor {
+                label: Some("Reflection Probe Uniform Buffer"),
+                size: std::mem::size_of::<ReflectionProbeUniform>() as wgpu::BufferAddress,
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            },
+        );
+
+        let probe_texture = memory::create_texture(
+            device,
+            MemoryCategory::Targets,
+            &wgpu::TextureDescriptor {
+                label: Some("Reflection Probe Texture"),
+                size: wgpu::Extent3d {
+                    width: PROBE_SIZE,
+                    height: PROBE_SIZE,
+                    depth_or_array_layers: MAX_REFLECTION_PROBES as u32 * FACES,
+                },
+                mip_level_count: PROBE_MIPS,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: Texture::HDR_FORMAT,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING
+                    | wgpu::TextureUsages::RENDER_ATTACHMENT
+                    | wgpu::TextureUsages::COPY_DST,
+            },
+        );
+        let probe_view = probe_texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(wgpu::TextureViewDimension::D2Array),
+            ..Default::default()
+        });
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Reflection Probe Sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let (face_buffers, face_bind_groups) = (0..FACES)
+            .map(|_| {
+                let buffer = memory::create_buffer(
+                    device,
+                    MemoryCategory::Transient,
+                    &wgpu::BufferDescriptor {
+                        label: Some("Reflection Probe Camera Buffer"),
+                        size: std::mem::size_of::<CameraUniform>() as wgpu::BufferAddress,
+                        usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                        mapped_at_creation: false,
+                    },
+                );
+                let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: Some("Reflection Probe Camera Bind Group"),
+                    layout: camera_bind_group_layout,
+                    entries: &[wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: buffer.as_entire_binding(),
+                    }],
+                });
+                (buffer, bind_group)
+            })
+            .unzip();
+
+        // Faces are rendered here first, the prefilter reads them while writing the probe's mips
+        let capture_texture = memory::create_texture(
+            device,
+            MemoryCategory::Targets,
+            &wgpu::TextureDescriptor {
+                label: Some("Reflection Probe Capture Texture"),
+                size: wgpu::Extent3d {
+                    width: PROBE_SIZE,
+                    height: PROBE_SIZE,
+                    depth_or_array_layers: FACES,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: Texture::HDR_FORMAT,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING
+                    | wgpu::TextureUsages::RENDER_ATTACHMENT
+                    | wgpu::TextureUsages::COPY_SRC,
+            },
+        );
+        let capture_views = (0..FACES)
+            .map(|face| {
+                capture_texture.create_view(&wgpu::TextureViewDescriptor {
+                    dimension: Some(wgpu::TextureViewDimension::D2),
+                    base_array_layer: face,
+                    array_layer_count: NonZeroU32::new(1),
+                    ..Default::default()
+                })
+            })
+            .collect();
+        let capture_array_view = capture_texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(wgpu::TextureViewDimension::D2Array),
+            ..Default::default()
+        });
+        let capture_depth = Texture::create_depth_texture(
+            device,
+            PROBE_SIZE,
+            PROBE_SIZE,
+            depth,
+            "Reflection Probe Depth Texture",
+        );
+        let capture_depth_view = capture_depth.create_attachment_view();
+        let multisampled = MultisampledTarget::new(
+            device,
+            &[PROBE_SIZE, PROBE_SIZE],
+            Texture::HDR_FORMAT,
+            depth,
+            "Reflection Probe",
+        );
+
+        // Screen space occlusion doesn't apply to the captured views
+        let unoccluded = memory::create_texture_with_data(
+            device,
+            queue,
+            MemoryCategory::Targets,
+            &wgpu::TextureDescriptor {
+                label: Some("Reflection Probe Ambient Occlusion Texture"),
+                size: wgpu::Extent3d {
+                    width: PROBE_SIZE,
+                    height: PROBE_SIZE,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: AMBIENT_OCCLUSION_FORMAT,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING,
+            },
+            &vec![u8::MAX; (PROBE_SIZE * PROBE_SIZE) as usize],
+        );
+        let unoccluded_view = unoccluded.create_view(&wgpu::TextureViewDescriptor::default());
+        let ambient_occlusion_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Reflection Probe Ambient Occlusion Bind Group"),
+            layout: ambient_occlusion_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&unoccluded_view),
+            }],
+        });
+
+        let prefilter_bind_group_layout = memory::create_bind_group_layout(
+            device,
+            &wgpu::BindGroupLayoutDescriptor {
+                label: Some("Reflection Probe Prefilter Bind Group Layout"),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                            view_dimension: wgpu::TextureViewDimension::D2Array,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Sampler {
+                            filtering: true,
+                            comparison: false,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 2,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                ],
+            },
+        );
+
+        // Mip zero is the capture itself, the rest get one bind group each
+        let prefilter_bind_groups = (1..PROBE_MIPS)
+            .map(|mip| {
+                let roughness = mip as f32 / (PROBE_MIPS - 1) as f32;
+                let uniform = PrefilterUniform {
+                    parameters: [roughness, 0.0, 0.0, 0.0],
+                };
+                let buffer = memory::create_buffer_init(
+                    device,
+                    MemoryCategory::Transient,
+                    &wgpu::util::BufferInitDescriptor {
+                        label: Some("Reflection Probe Prefilter Uniform Buffer"),
+                        contents: bytemuck::cast_slice(&[uniform]),
+                        usage: wgpu::BufferUsages::UNIFORM,
+                    },
+                );
+                device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: Some("Reflection Probe Prefilter Bind Group"),
+                    layout: &prefilter_bind_group_layout,
+                    entries: &[
+                        wgpu::BindGroupEntry {
+                            binding: 0,
+                            resource: wgpu::BindingResource::TextureView(&capture_array_view),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 1,
+                            resource: wgpu::BindingResource::Sampler(&sampler),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 2,
+                            resource: buffer.as_entire_binding(),
+                        },
+                    ],
+                })
+            })
+            .collect();
+
+        let module = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("Reflection Probe Prefilter Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("shaders/probe_prefilter.wgsl").into()),
+        });
+
+        let pipeline_layout = memory::create_pipeline_layout(
+            device,
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("Reflection Probe Prefilter Pipeline Layout"),
+                bind_group_layouts: &[&prefilter_bind_group_layout],
+                push_constant_ranges: &[],
+            },
+        );
+
+        let prefilter_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Reflection Probe Prefilter Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &module,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            fragment: Some(wgpu::FragmentState {
+                module: &module,
+                entry_point: "fs_main",
+                targets: &[wgpu::ColorTargetState {
+                    format: Texture::HDR_FORMAT,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                }],
+            }),
+        });
+
+        Self {
+            uniform_buffer,
+            probe_texture,
+            probe_view,
+            sampler,
+            captured: vec![None; MAX_REFLECTION_PROBES],
+            pending: None,
+            capture_position: glm::Vec3::zeros(),
+            z_far: 1000.0,
+            face_buffers,
+            face_bind_groups,
+            capture_texture,
+            capture_views,
+            _capture_depth: capture_depth,
+            capture_depth_view,
+            multisampled,
+            depth,
+            ambient_occlusion_bind_group,
+            prefilter_bind_groups,
+            prefilter_pipeline,
+        }
+    }
+
+    // Lit surfaces sample the probes through the light bind group
+    pub fn view(&self) -> &wgpu::TextureView {
+        &self.probe_view
+    }
+
+    pub fn sampler(&self) -> &wgpu::Sampler {
+        &self.sampler
+    }
+
+    pub fn uniform_buffer(&self) -> &wgpu::Buffer {
+        &self.uniform_buffer
+    }
+
+    // Bound in place of screen space occlusion while rendering the faces
+    pub fn ambient_occlusion_bind_group(&self) -> &wgpu::BindGroup {
+        &self.ambient_occlusion_bind_group
+    }
+
+    // Probes are otherwise only captured when they are added or changed
+    pub fn request_capture(&mut self) {
+        self.captured
+            .iter_mut()
+            .for_each(|captured| *captured = None);
+    }
+
+    pub fn update(&mut self, queue: &wgpu::Queue, scene: &Scene, z_far: f32) {
+        let probes =
+            &scene.reflection_probes[..scene.reflection_probes.len().min(MAX_REFLECTION_PROBES)];
+        self.z_far = z_far;
+        self.pending = probes
+            .iter()
+            .enumerate()
+            .find(|(index, probe)| self.captured[*index] != Some(**probe))
+            .map(|(index, probe)| (index, *probe));
+
+        if let Some((_, probe)) = self.pending {
+            self.capture_position = scene.local_point(&probe.position);
+            for (face, buffer) in self.face_buffers.iter().enumerate() {
+                let camera = face_camera(self.capture_position, face, self.z_far);
+                let uniform = CameraUniform::new(&camera, &[PROBE_SIZE, PROBE_SIZE], self.depth);
+                queue.write_buffer(buffer, 0, bytemuck::cast_slice(&[uniform]));
+            }
+        }
+
+        let mut uniform = ReflectionProbeUniform {
+            count: probes.len() as u32,
+            max_lod: (PROBE_MIPS - 1) as f32,
+            ..Default::default()
+        };
+        for (index, probe) in probes.iter().enumerate() {
+            // Probes waiting for their first capture have nothing to reflect yet
+            let radius = match self.captured[index] {
+                Some(captured) => captured.radius.max(0.0),
+                None => 0.0,
+            };
+            let position = scene.local_point(&probe.position);
+            uniform.probes[index] = [position.x, position.y, position.z, radius];
+        }
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[uniform]));
+    }
+
+    // Empty unless a probe needs capturing this frame
+    pub fn faces(&self) -> Vec<ProbeFace<'_>> {
+        if self.pending.is_none() {
+            return Vec::new();
+        }
+        (0..FACES as usize)
+            .map(|face| ProbeFace {
+                camera: face_camera(self.capture_position, face, self.z_far),
+                camera_bind_group: &self.face_bind_groups[face],
+                color: &self.capture_views[face],
+                depth: &self.capture_depth_view,
+                multisampled: self.multisampled.as_ref(),
+            })
+            .collect()
+    }
+
+    // Moves the rendered faces into the probe and prefilters its mips
+    pub fn finish_capture(&mut self, encoder: &mut wgpu::CommandEncoder) {
+        let (index, probe) = match self.pending.take() {
+            Some(pending) => pending,
+            None => return,
+        };
+        let first_layer = index as u32 * FACES;
+
+        encoder.copy_texture_to_texture(
+            self.capture_texture.as_image_copy(),
+            wgpu::ImageCopyTexture {
+                texture: &self.probe_texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d {
+                    x: 0,
+                    y: 0,
+                    z: first_layer,
+                },
+                aspect: wgpu::TextureAspect::All,
+            },
+            wgpu::Extent3d {
+                width: PROBE_SIZE,
+                height: PROBE_SIZE,
+                depth_or_array_layers: FACES,
+            },
+        );
+
+        for (mip, bind_group) in (1..PROBE_MIPS).zip(self.prefilter_bind_groups.iter()) {
+            for face in 0..FACES {
+                let view = self
+                    .probe_texture
+                    .create_view(&wgpu::TextureViewDescriptor {
+                        dimension: Some(wgpu::TextureViewDimension::D2),
+                        base_mip_level: mip,
+                        mip_level_count: NonZeroU32::new(1),
+                        base_array_layer: first_layer + face,
+                        array_layer_count: NonZeroU32::new(1),
+                        ..Default::default()
+                    });
+                let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                    label: Some("Reflection Probe Prefilter Pass"),
+                    color_attachments: &[wgpu::RenderPassColorAttachment {
+                        view: &view,
+                        resolve_target: None,
+                        ops: wgpu::Operations {
+                            load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                            store: true,
+                        },
+                    }],
+                    depth_stencil_attachment: None,
+                });
+                render_pass.set_pipeline(&self.prefilter_pipeline);
+                render_pass.set_bind_group(0, bind_group, &[]);
+                // The instance selects the face being filtered
+                render_pass.draw(0..3, face..face + 1);
+            }
+        }
+
+        self.captured[index] = Some(probe);
+    }
+}
@@ -0,0 +1,207 @@
+use std::{task::Poll, time::Duration};
+
+use crate::memory::{self, MemoryCategory, Tracked};
+use crate::readback::{poll_mapping, MapFuture};
+
+// Timestamps are read back a few frames later so the CPU never waits on the GPU
+const READBACK_FRAMES: usize = 3;
+const MAX_TIMESTAMPS: u32 = 32;
+const TIMESTAMP_SIZE: wgpu::BufferAddress = std::mem::size_of::<u64>() as wgpu::BufferAddress;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PassTiming {
+    pub label: &'static str,
+    pub duration: Duration,
+}
+
+enum ReadbackState {
+    Free,
+    Recorded,
+    Mapping(MapFuture),
+}
+
+struct Readback {
+    buffer: Tracked<wgpu::Buffer>,
+    labels: Vec<&'static str>,
+    frame: u64,
+    state: ReadbackState,
+}
+
+// Measures how long each pass takes on the GPU using timestamp queries
+pub struct GpuProfiler {
+    query_set: wgpu::QuerySet,
+    resolve_buffer: Tracked<wgpu::Buffer>,
+    readbacks: Vec<Readback>,
+    timestamp_period: f32,
+    // The first timestamp marks the start of the frame and each label marks the end of a pass
+    labels: Vec<&'static str>,
+    recording: bool,
+    frame: u64,
+}
+
+impl GpuProfiler {
+    // Returns nothing when the device can't record timestamps
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue) -> Option<Self> {
+        if !device.features().contains(wgpu::Features::TIMESTAMP_QUERY) {
+            return None;
+        }
+
+        let query_set = device.create_query_set(&wgpu::QuerySetDescriptor {
+            label: Some("Profiler Query Set"),
+            ty: wgpu::QueryType::Timestamp,
+            count: MAX_TIMESTAMPS,
+        });
+
+        let size = MAX_TIMESTAMPS as wgpu::BufferAddress * TIMESTAMP_SIZE;
+        let resolve_buffer = memory::create_buffer(
+            device,
+            MemoryCategory::Transient,
+            &wgpu::BufferDescriptor {
+                label: Some("Profiler Resolve Buffer"),
+                size,
+                usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::COPY_SRC,
+                mapped_at_creation: false,
+            },
+        );
+
+        let readbacks = (0..READBACK_FRAMES)
+            .map(|_| Readback {
+                buffer: memory::create_buffer(
+                    device,
+                    MemoryCategory::Transient,
+                    &wgpu::BufferDescriptor {
+                        label: Some("Profiler Readback Buffer"),
+                        size,
+                        usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+                        mapped_at_creation: false,
+                    },
+                ),
+                labels: Vec::new(),
+                frame: 0,
+                state: ReadbackState::Free,
+            })
+            .collect();
+
+        Some(Self {
+            query_set,
+            resolve_buffer,
+            readbacks,
+            timestamp_period: queue.get_timestamp_period(),
+            labels: Vec::new(),
+            recording: false,
+            frame: 0,
+        })
+    }
+
+    // Frames are skipped while every readback buffer is still in flight
+    pub fn begin_frame(&mut self, encoder: &mut wgpu::CommandEncoder, frame: u64) {
+        self.recording = self
+            .readbacks
+            .iter()
+            .any(|readback| matches!(readback.state, ReadbackState::Free));
+        if !self.recording {
+            return;
+        }
+        self.labels.clear();
+        self.frame = frame;
+        encoder.write_timestamp(&self.query_set, 0);
+    }
+
+    pub fn end_pass(&mut self, encoder: &mut wgpu::CommandEncoder, label: &'static str) {
+        if !self.recording || self.labels.len() as u32 + 1 >= MAX_TIMESTAMPS {
+            return;
+        }
+        self.labels.push(label);
+        encoder.write_timestamp(&self.query_set, self.labels.len() as u32);
+    }
+
+    pub fn end_frame(&mut self, encoder: &mut wgpu::CommandEncoder) {
+        if !self.recording {
+            return;
+        }
+        self.recording = false;
+
+        let readback = match self
+            .readbacks
+            .iter_mut()
+            .find(|readback| matches!(readback.state, ReadbackState::Free))
+        {
+            Some(readback) => readback,
+            None => return,
+        };
+
+        let count = self.labels.len() as u32 + 1;
+        encoder.resolve_query_set(&self.query_set, 0..count, &self.resolve_buffer, 0);
+        encoder.copy_buffer_to_buffer(
+            &self.resolve_buffer,
+            0,
+            &readback.buffer,
+            0,
+            count as wgpu::BufferAddress * TIMESTAMP_SIZE,
+        );
+        readback.labels = std::mem::take(&mut self.labels);
+        readback.frame = self.frame;
+        readback.state = ReadbackState::Recorded;
+    }
+
+    // Buffers can only be mapped once the commands writing to them are submitted
+    pub fn after_submit(&mut self) {
+        for readback in self.readbacks.iter_mut() {
+            if matches!(readback.state, ReadbackState::Recorded) {
+                let mapping = readback.buffer.slice(..).map_async(wgpu::MapMode::Read);
+                readback.state = ReadbackState::Mapping(Box::pin(mapping));
+            }
+        }
+    }
+
+    // Returns the most recent frame whose timings have arrived, without blocking
+    pub fn collect(&mut self, device: &wgpu::Device) -> Option<(u64, Vec<PassTiming>)> {
+        device.poll(wgpu::Maintain::Poll);
+
+        let mut latest: Option<(u64, Vec<PassTiming>)> = None;
+        for readback in self.readbacks.iter_mut() {
+            let result = match &mut readback.state {
+                ReadbackState::Mapping(mapping) => match poll_mapping(mapping) {
+                    Poll::Ready(result) => result,
+                    Poll::Pending => continue,
+                },
+                _ => continue,
+            };
+            readback.state = ReadbackState::Free;
+            if result.is_err() {
+                continue;
+            }
+
+            let timings = {
+                let data = readback.buffer.slice(..).get_mapped_range();
+                let timestamps = data
+                    .chunks_exact(TIMESTAMP_SIZE as usize)
+                    .map(|bytes| u64::from_ne_bytes(bytes.try_into().unwrap_or_default()))
+                    .collect::<Vec<_>>();
+                readback
+                    .labels
+                    .iter()
+                    .enumerate()
+                    .map(|(index, label)| {
+                        let ticks = timestamps[index + 1].saturating_sub(timestamps[index]);
+                        PassTiming {
+                            label,
+                            duration: Duration::from_nanos(
+                                (ticks as f64 * self.timestamp_period as f64) as u64,
+                            ),
+                        }
+                    })
+                    .collect::<Vec<_>>()
+            };
+            readback.buffer.unmap();
+
+            if latest
+                .as_ref()
+                .is_none_or(|(frame, _)| readback.frame > *frame)
+            {
+                latest = Some((readback.frame, timings));
+            }
+        }
+        latest
+    }
+}
@@ -0,0 +1,598 @@
+use nalgebra_glm as glm;
+
+use crate::{
+    bvh::{BvhUpdate, SceneBvh},
+    memory::{self, MemoryCategory, Tracked},
+    scene::Scene,
+    settings::Settings,
+    shader_snippets,
+    texture::Texture,
+};
+
+const WORKGROUP_SIZE: u32 = 8;
+const SHADOW_MASK_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::R32Float;
+// Rays per traced pixel when the sun has a radius, hard shadows only need one
+const SOFT_SHADOW_RAYS: u32 = 4;
+
+#[repr(C)]
+#[derive(Default, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct RayTracedShadowUniform {
+    // xyz: towards the sun, w: tangent of its angular radius
+    sun: [f32; 4],
+    // Pixels per traced pixel on each axis, filter radius, rays per pixel and frame
+    parameters: [u32; 4],
+}
+
+struct SceneBuffers {
+    triangle_buffer: Tracked<wgpu::Buffer>,
+    node_buffer: Tracked<wgpu::Buffer>,
+    bind_group: wgpu::BindGroup,
+}
+
+// The passes that trace the sun's visibility and filter it into the mask
+struct Tracing {
+    trace_layout: wgpu::BindGroupLayout,
+    trace_bind_group: wgpu::BindGroup,
+    scene_layout: wgpu::BindGroupLayout,
+    trace_pipeline: wgpu::ComputePipeline,
+    filter_layout: wgpu::BindGroupLayout,
+    filter_bind_group: wgpu::BindGroup,
+    filter_pipeline: wgpu::ComputePipeline,
+}
+
+// Traces the sun's visibility from every pixel of the depth prepass against the models'
+// triangles and filters it into a mask that lit surfaces multiply with the shadow map. Meant
+// for static scenes, moving models refit the hierarchy and wide changes rebuild it
+pub struct RayTracedShadowSystem {
+    uniform_buffer: Tracked<wgpu::Buffer>,
+    traced: Texture,
+    mask: Texture,
+    // A single lit texel, bound while rendering probes whose pixels don't match the mask's
+    unshadowed: Texture,
+    // Only created where compute shaders are available, the mask is left lit without them
+    tracing: Option<Tracing>,
+    scene_buffers: Option<SceneBuffers>,
+    scene_bvh: SceneBvh,
+    dimensions: [u32; 2],
+    step: u32,
+    frame: u32,
+    enabled: bool,
+    // Whether the mask has been left lit since tracing was disabled
+    cleared: bool,
+}
+
+impl RayTracedShadowSystem {
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        compute_supported: bool,
+        camera_bind_group_layout: &wgpu::BindGroupLayout,
+        depth_texture: &Texture,
+        dimensions: &[u32; 2],
+    ) -> Self {
+        let uniform_buffer = memory::create_buffer(
+            device,
+            MemoryCategory::Transient,
+            &wgpu::BufferDescriptor {
+                label: Some("Ray Traced Shadow Uniform Buffer"),
+                size: std::mem::size_of::<RayTracedShadowUniform>() as wgpu::BufferAddress,
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            },
+        );
+
+        let (traced, mask) = Self::create_targets(device, dimensions);
+        let unshadowed = create_mask_texture(device, 1, 1, "Unshadowed Mask Texture");
+        queue.write_texture(
+            wgpu::ImageCopyTexture {
+                texture: &unshadowed.texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            bytemuck::cast_slice(&[1.0_f32]),
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: std::num::NonZeroU32::new(4),
+                rows_per_image: std::num::NonZeroU32::new(1),
+            },
+            wgpu::Extent3d {
+                width: 1,
+                height: 1,
+                depth_or_array_layers: 1,
+            },
+        );
+
+        let tracing = compute_supported.then(|| {
+            Self::create_tracing(
+                device,
+                camera_bind_group_layout,
+                &uniform_buffer,
+                depth_texture,
+                &traced,
+                &mask,
+            )
+        });
+
+        Self {
+            uniform_buffer,
+            traced,
+            mask,
+            unshadowed,
+            tracing,
+            scene_buffers: None,
+            scene_bvh: SceneBvh::default(),
+            dimensions: *dimensions,
+            step: 1,
+            frame: 0,
+            enabled: false,
+            cleared: false,
+        }
+    }
+
+    fn create_tracing(
+        device: &wgpu::Device,
+        camera_bind_group_layout: &wgpu::BindGroupLayout,
+        uniform_buffer: &wgpu::Buffer,
+        depth_texture: &Texture,
+        traced: &Texture,
+        mask: &Texture,
+    ) -> Tracing {
+        let uniform_entry = wgpu::BindGroupLayoutEntry {
+            binding: 0,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let depth_entry = wgpu::BindGroupLayoutEntry {
+            binding: 1,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Depth,
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        };
+        let storage_texture_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::StorageTexture {
+                access: wgpu::StorageTextureAccess::WriteOnly,
+                format: SHADOW_MASK_FORMAT,
+                view_dimension: wgpu::TextureViewDimension::D2,
+            },
+            count: None,
+        };
+        let storage_buffer_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only: true },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+
+        let trace_layout = memory::create_bind_group_layout(
+            device,
+            &wgpu::BindGroupLayoutDescriptor {
+                label: Some("Ray Traced Shadow Bind Group Layout"),
+                entries: &[uniform_entry, depth_entry, storage_texture_entry(2)],
+            },
+        );
+
+        let scene_layout = memory::create_bind_group_layout(
+            device,
+            &wgpu::BindGroupLayoutDescriptor {
+                label: Some("Ray Traced Shadow Scene Bind Group Layout"),
+                entries: &[storage_buffer_entry(0), storage_buffer_entry(1)],
+            },
+        );
+
+        let filter_layout = memory::create_bind_group_layout(
+            device,
+            &wgpu::BindGroupLayoutDescriptor {
+                label: Some("Ray Traced Shadow Filter Bind Group Layout"),
+                entries: &[
+                    uniform_entry,
+                    depth_entry,
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 2,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                    storage_texture_entry(3),
+                ],
+            },
+        );
+
+        let trace_pipeline = Self::create_pipeline(
+            device,
+            "Ray Traced Shadow",
+            &shader_snippets::splice(include_str!("shaders/ray_traced_shadows.wgsl")),
+            &[camera_bind_group_layout, &trace_layout, &scene_layout],
+        );
+        let filter_pipeline = Self::create_pipeline(
+            device,
+            "Ray Traced Shadow Filter",
+            &shader_snippets::splice(include_str!("shaders/ray_traced_shadow_filter.wgsl")),
+            &[camera_bind_group_layout, &filter_layout],
+        );
+
+        let trace_bind_group = Self::create_trace_bind_group(
+            device,
+            &trace_layout,
+            uniform_buffer,
+            depth_texture,
+            traced,
+        );
+        let filter_bind_group = Self::create_filter_bind_group(
+            device,
+            &filter_layout,
+            uniform_buffer,
+            depth_texture,
+            traced,
+            mask,
+        );
+
+        Tracing {
+            trace_layout,
+            trace_bind_group,
+            scene_layout,
+            trace_pipeline,
+            filter_layout,
+            filter_bind_group,
+            filter_pipeline,
+        }
+    }
+
+    fn create_pipeline(
+        device: &wgpu::Device,
+        label: &str,
+        source: &str,
+        bind_group_layouts: &[&wgpu::BindGroupLayout],
+    ) -> wgpu::ComputePipeline {
+        let module = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some(&format!("{} Shader", label)),
+            source: wgpu::ShaderSource::Wgsl(source.into()),
+        });
+        let layout = memory::create_pipeline_layout(
+            device,
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some(&format!("{} Pipeline Layout", label)),
+                bind_group_layouts,
+                push_constant_ranges: &[],
+            },
+        );
+        device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some(&format!("{} Pipeline", label)),
+            layout: Some(&layout),
+            module: &module,
+            entry_point: "main",
+        })
+    }
+
+    // The traced texture is full size so switching to half resolution only uses less of it
+    fn create_targets(device: &wgpu::Device, dimensions: &[u32; 2]) -> (Texture, Texture) {
+        (
+            create_mask_texture(
+                device,
+                dimensions[0],
+                dimensions[1],
+                "Traced Shadow Texture",
+            ),
+            create_mask_texture(device, dimensions[0], dimensions[1], "Shadow Mask Texture"),
+        )
+    }
+
+    fn create_trace_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        uniform_buffer: &wgpu::Buffer,
+        depth_texture: &Texture,
+        traced: &Texture,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Ray Traced Shadow Bind Group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&depth_texture.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(&traced.view),
+                },
+            ],
+        })
+    }
+
+    fn create_filter_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        uniform_buffer: &wgpu::Buffer,
+        depth_texture: &Texture,
+        traced: &Texture,
+        mask: &Texture,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Ray Traced Shadow Filter Bind Group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&depth_texture.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(&traced.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::TextureView(&mask.view),
+                },
+            ],
+        })
+    }
+
+    pub fn resize(
+        &mut self,
+        device: &wgpu::Device,
+        depth_texture: &Texture,
+        dimensions: &[u32; 2],
+    ) {
+        self.dimensions = *dimensions;
+        let (traced, mask) = Self::create_targets(device, dimensions);
+        self.traced = traced;
+        self.mask = mask;
+        if let Some(tracing) = self.tracing.as_mut() {
+            tracing.trace_bind_group = Self::create_trace_bind_group(
+                device,
+                &tracing.trace_layout,
+                &self.uniform_buffer,
+                depth_texture,
+                &self.traced,
+            );
+            tracing.filter_bind_group = Self::create_filter_bind_group(
+                device,
+                &tracing.filter_layout,
+                &self.uniform_buffer,
+                depth_texture,
+                &self.traced,
+                &self.mask,
+            );
+        }
+        self.cleared = false;
+    }
+
+    // Bound by lit surfaces and read at their pixel
+    pub fn mask_view(&self) -> &wgpu::TextureView {
+        &self.mask.view
+    }
+
+    pub fn unshadowed_view(&self) -> &wgpu::TextureView {
+        &self.unshadowed.view
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn update(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        scene: &Scene,
+        settings: &Settings,
+    ) {
+        let shadows = &settings.ray_traced_shadows;
+        self.enabled = shadows.enabled;
+        let tracing = match self.tracing.as_ref() {
+            Some(tracing) if self.enabled => tracing,
+            _ => return,
+        };
+
+        match self.scene_bvh.update(scene) {
+            BvhUpdate::Rebuilt => {
+                self.scene_buffers = Some(self.create_scene_buffers(device, tracing));
+            }
+            BvhUpdate::Refit => {
+                if let Some(scene_buffers) = self.scene_buffers.as_ref() {
+                    queue.write_buffer(
+                        &scene_buffers.triangle_buffer,
+                        0,
+                        bytemuck::cast_slice(&self.ordered_triangles()),
+                    );
+                    queue.write_buffer(
+                        &scene_buffers.node_buffer,
+                        0,
+                        bytemuck::cast_slice(&self.scene_bvh.bvh().gpu_nodes()),
+                    );
+                }
+            }
+            BvhUpdate::Unchanged => {}
+        }
+
+        self.step = if shadows.half_resolution { 2 } else { 1 };
+        self.frame = self.frame.wrapping_add(1);
+        let soft = shadows.softness > 0.0;
+        let sun = -glm::normalize(&scene.sun.direction);
+        let uniform = RayTracedShadowUniform {
+            sun: [sun.x, sun.y, sun.z, shadows.softness.to_radians().tan()],
+            parameters: [
+                self.step,
+                if soft { 2 } else { 1 },
+                if soft { SOFT_SHADOW_RAYS } else { 1 },
+                self.frame,
+            ],
+        };
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[uniform]));
+    }
+
+    // Triangle corners sorted so every leaf's are contiguous
+    fn ordered_triangles(&self) -> Vec<[f32; 4]> {
+        let triangles = self.scene_bvh.triangles();
+        let mut corners = self
+            .scene_bvh
+            .bvh()
+            .triangle_order()
+            .iter()
+            .flat_map(|index| triangles[*index].map(|corner| corner.push(1.0).into()))
+            .collect::<Vec<_>>();
+        // Bindings can't be empty
+        if corners.is_empty() {
+            corners.resize(3, [0.0; 4]);
+        }
+        corners
+    }
+
+    fn create_scene_buffers(&self, device: &wgpu::Device, tracing: &Tracing) -> SceneBuffers {
+        let triangle_buffer = memory::create_buffer_init(
+            device,
+            MemoryCategory::Meshes,
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("Ray Traced Shadow Triangle Buffer"),
+                contents: bytemuck::cast_slice(&self.ordered_triangles()),
+                usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            },
+        );
+        let node_buffer = memory::create_buffer_init(
+            device,
+            MemoryCategory::Meshes,
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("Ray Traced Shadow Node Buffer"),
+                contents: bytemuck::cast_slice(&self.scene_bvh.bvh().gpu_nodes()),
+                usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            },
+        );
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Ray Traced Shadow Scene Bind Group"),
+            layout: &tracing.scene_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: triangle_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: node_buffer.as_entire_binding(),
+                },
+            ],
+        });
+        SceneBuffers {
+            triangle_buffer,
+            node_buffer,
+            bind_group,
+        }
+    }
+
+    // Traces and filters the mask, or leaves it lit once while disabled
+    pub fn render(
+        &mut self,
+        encoder: &mut wgpu::CommandEncoder,
+        camera_bind_group: &wgpu::BindGroup,
+    ) {
+        let (tracing, scene_buffers) = match (
+            self.enabled,
+            self.tracing.as_ref(),
+            self.scene_buffers.as_ref(),
+        ) {
+            (true, Some(tracing), Some(scene_buffers)) => (tracing, scene_buffers),
+            _ => {
+                if !self.cleared {
+                    encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                        label: Some("Shadow Mask Clear Pass"),
+                        color_attachments: &[wgpu::RenderPassColorAttachment {
+                            view: &self.mask.view,
+                            resolve_target: None,
+                            ops: wgpu::Operations {
+                                load: wgpu::LoadOp::Clear(wgpu::Color::WHITE),
+                                store: true,
+                            },
+                        }],
+                        depth_stencil_attachment: None,
+                    });
+                    self.cleared = true;
+                }
+                return;
+            }
+        };
+        self.cleared = false;
+
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Ray Traced Shadow Pass"),
+        });
+        compute_pass.set_pipeline(&tracing.trace_pipeline);
+        compute_pass.set_bind_group(0, camera_bind_group, &[]);
+        compute_pass.set_bind_group(1, &tracing.trace_bind_group, &[]);
+        compute_pass.set_bind_group(2, &scene_buffers.bind_group, &[]);
+        compute_pass.dispatch(
+            self.dimensions[0]
+                .div_ceil(self.step)
+                .div_ceil(WORKGROUP_SIZE),
+            self.dimensions[1]
+                .div_ceil(self.step)
+                .div_ceil(WORKGROUP_SIZE),
+            1,
+        );
+
+        compute_pass.set_pipeline(&tracing.filter_pipeline);
+        compute_pass.set_bind_group(1, &tracing.filter_bind_group, &[]);
+        compute_pass.dispatch(
+            self.dimensions[0].div_ceil(WORKGROUP_SIZE),
+            self.dimensions[1].div_ceil(WORKGROUP_SIZE),
+            1,
+        );
+    }
+}
+
+fn create_mask_texture(device: &wgpu::Device, width: u32, height: u32, label: &str) -> Texture {
+    let texture = memory::create_texture(
+        device,
+        MemoryCategory::Targets,
+        &wgpu::TextureDescriptor {
+            label: Some(label),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: SHADOW_MASK_FORMAT,
+            usage: wgpu::TextureUsages::STORAGE_BINDING
+                | wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::RENDER_ATTACHMENT
+                | wgpu::TextureUsages::COPY_DST,
+        },
+    );
+    let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+    // Masks are loaded by pixel, never filtered
+    let sampler = device.create_sampler(&wgpu::SamplerDescriptor::default());
+    Texture {
+        texture,
+        view,
+        sampler,
+    }
+}
@@ -0,0 +1,260 @@
+use crate::memory::{self, MemoryCategory, Tracked};
+use anyhow::{Context as _, Result};
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll, Waker},
+};
+
+pub type MapFuture = Pin<Box<dyn Future<Output = Result<(), wgpu::BufferAsyncError>> + Send>>;
+
+pub type ReadbackCallback = Box<dyn FnOnce(Result<Vec<u8>>)>;
+
+pub type FrameCallback = Box<dyn FnOnce(Result<image::RgbaImage>)>;
+
+// Checks on a mapping without blocking, the device has to be polled for it to make progress
+pub fn poll_mapping(mapping: &mut MapFuture) -> Poll<Result<(), wgpu::BufferAsyncError>> {
+    mapping
+        .as_mut()
+        .poll(&mut Context::from_waker(Waker::noop()))
+}
+
+// Surfaces are often blue first, which is swapped back for images of the frames read from them
+pub fn frame_image(
+    width: u32,
+    height: u32,
+    mut pixels: Vec<u8>,
+    format: wgpu::TextureFormat,
+) -> Result<image::RgbaImage> {
+    if matches!(
+        format,
+        wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb
+    ) {
+        for pixel in pixels.chunks_mut(4) {
+            pixel.swap(0, 2);
+        }
+    }
+    image::RgbaImage::from_raw(width, height, pixels)
+        .context("Failed to create an image from the captured frame!")
+}
+
+// Rows copied out of a texture are padded to a multiple of the copy alignment
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct TextureLayout {
+    pub width: u32,
+    pub height: u32,
+    pub bytes_per_pixel: u32,
+    pub padded_bytes_per_row: u32,
+}
+
+impl TextureLayout {
+    pub fn new(width: u32, height: u32, bytes_per_pixel: u32) -> Self {
+        Self {
+            width,
+            height,
+            bytes_per_pixel,
+            padded_bytes_per_row: (bytes_per_pixel * width)
+                .div_ceil(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT)
+                * wgpu::COPY_BYTES_PER_ROW_ALIGNMENT,
+        }
+    }
+
+    pub fn buffer_size(&self) -> wgpu::BufferAddress {
+        (self.padded_bytes_per_row * self.height) as wgpu::BufferAddress
+    }
+
+    pub fn unpad(&self, data: &[u8]) -> Vec<u8> {
+        let row_size = (self.bytes_per_pixel * self.width) as usize;
+        let mut pixels = Vec::with_capacity(row_size * self.height as usize);
+        for row in data.chunks(self.padded_bytes_per_row as usize) {
+            pixels.extend_from_slice(&row[..row_size]);
+        }
+        pixels
+    }
+}
+
+// A copy of a buffer or texture on its way back to the CPU
+pub struct Readback {
+    buffer: Tracked<wgpu::Buffer>,
+    // Only present for textures
+    layout: Option<TextureLayout>,
+    // Only present once the commands copying into the buffer are submitted
+    mapping: Option<MapFuture>,
+}
+
+impl Readback {
+    fn create_buffer(device: &wgpu::Device, size: wgpu::BufferAddress) -> Tracked<wgpu::Buffer> {
+        memory::create_buffer(
+            device,
+            MemoryCategory::Transient,
+            &wgpu::BufferDescriptor {
+                label: Some("Readback Buffer"),
+                size,
+                usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+                mapped_at_creation: false,
+            },
+        )
+    }
+
+    // Records a copy of part of a buffer, the size must be a multiple of four
+    pub fn from_buffer(
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        source: &wgpu::Buffer,
+        offset: wgpu::BufferAddress,
+        size: wgpu::BufferAddress,
+    ) -> Self {
+        let buffer = Self::create_buffer(device, size);
+        encoder.copy_buffer_to_buffer(source, offset, &buffer, 0, size);
+        Self {
+            buffer,
+            layout: None,
+            mapping: None,
+        }
+    }
+
+    // Records a copy of the first mip level of a 2D texture
+    pub fn from_texture(
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        texture: &wgpu::Texture,
+        layout: TextureLayout,
+    ) -> Self {
+        Self::from_texture_region(
+            device,
+            encoder,
+            texture,
+            wgpu::Origin3d::ZERO,
+            wgpu::TextureAspect::All,
+            layout,
+        )
+    }
+
+    // Records a copy of the layout's size starting at the origin, depth textures need their
+    // depth aspect selected
+    pub fn from_texture_region(
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        texture: &wgpu::Texture,
+        origin: wgpu::Origin3d,
+        aspect: wgpu::TextureAspect,
+        layout: TextureLayout,
+    ) -> Self {
+        let buffer = Self::create_buffer(device, layout.buffer_size());
+        encoder.copy_texture_to_buffer(
+            wgpu::ImageCopyTexture {
+                texture,
+                mip_level: 0,
+                origin,
+                aspect,
+            },
+            wgpu::ImageCopyBuffer {
+                buffer: &buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: std::num::NonZeroU32::new(layout.padded_bytes_per_row),
+                    rows_per_image: std::num::NonZeroU32::new(layout.height),
+                },
+            },
+            wgpu::Extent3d {
+                width: layout.width,
+                height: layout.height,
+                depth_or_array_layers: 1,
+            },
+        );
+        Self {
+            buffer,
+            layout: Some(layout),
+            mapping: None,
+        }
+    }
+
+    // Buffers can only be mapped once the commands writing to them are submitted
+    pub fn submitted(&mut self) {
+        if self.mapping.is_none() {
+            self.mapping = Some(Box::pin(
+                self.buffer.slice(..).map_async(wgpu::MapMode::Read),
+            ));
+        }
+    }
+
+    // Returns the data once it has arrived, without blocking
+    #[allow(dead_code)]
+    pub fn try_read(&mut self, device: &wgpu::Device) -> Option<Result<Vec<u8>>> {
+        device.poll(wgpu::Maintain::Poll);
+        self.read()
+    }
+
+    // Blocks until the GPU has finished with the copy
+    pub fn wait(mut self, device: &wgpu::Device) -> Result<Vec<u8>> {
+        self.submitted();
+        device.poll(wgpu::Maintain::Wait);
+        self.read()
+            .context("The readback didn't finish after waiting on the device!")?
+    }
+
+    fn read(&mut self) -> Option<Result<Vec<u8>>> {
+        let result = match poll_mapping(self.mapping.as_mut()?) {
+            Poll::Ready(result) => result,
+            Poll::Pending => return None,
+        };
+        self.mapping = None;
+        if let Err(error) = result {
+            return Some(Err(error).context("Failed to map a readback buffer!"));
+        }
+
+        let data = {
+            let data = self.buffer.slice(..).get_mapped_range();
+            match self.layout {
+                Some(layout) => layout.unpad(&data),
+                None => data.to_vec(),
+            }
+        };
+        self.buffer.unmap();
+        Some(Ok(data))
+    }
+}
+
+// Readbacks handed to callbacks as their data arrives, checked once a frame
+#[derive(Default)]
+pub struct ReadbackQueue {
+    readbacks: Vec<(Readback, ReadbackCallback)>,
+}
+
+impl ReadbackQueue {
+    pub fn push(&mut self, readback: Readback, callback: ReadbackCallback) {
+        self.readbacks.push((readback, callback));
+    }
+
+    pub fn after_submit(&mut self) {
+        for (readback, _) in self.readbacks.iter_mut() {
+            readback.submitted();
+        }
+    }
+
+    pub fn poll(&mut self, device: &wgpu::Device) {
+        self.poll_with(device, wgpu::Maintain::Poll);
+    }
+
+    // Blocks until every submitted readback has arrived
+    pub fn wait(&mut self, device: &wgpu::Device) {
+        self.poll_with(device, wgpu::Maintain::Wait);
+    }
+
+    fn poll_with(&mut self, device: &wgpu::Device, maintain: wgpu::Maintain) {
+        if self.readbacks.is_empty() {
+            return;
+        }
+        device.poll(maintain);
+        let mut index = 0;
+        while index < self.readbacks.len() {
+            match self.readbacks[index].0.read() {
+                Some(result) => {
+                    let (_, callback) = self.readbacks.swap_remove(index);
+                    callback(result);
+                }
+                None => index += 1,
+            }
+        }
+    }
+}
@@ -0,0 +1,265 @@
+use anyhow::{bail, Context, Result};
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+    process::{Child, Command, Stdio},
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        mpsc, Arc,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use crate::settings::RecordingSettings;
+
+// Recordings are numbered in here so earlier ones are kept
+const RECORDINGS_DIRECTORY: &str = "recordings";
+
+// A presented frame read back from the GPU, on its way to the thread writing the recording
+pub struct RecordedFrame {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+    // Surfaces are often blue first, which is swapped on the writing thread
+    pub bgra: bool,
+}
+
+impl RecordedFrame {
+    fn into_rgba(mut self) -> Vec<u8> {
+        if self.bgra {
+            for pixel in self.pixels.chunks_mut(4) {
+                pixel.swap(0, 2);
+            }
+        }
+        self.pixels
+    }
+}
+
+struct Recording {
+    path: PathBuf,
+    sender: mpsc::Sender<RecordedFrame>,
+    writer: JoinHandle<Result<u32>>,
+    // Frames the writer is done with
+    written: Arc<AtomicU32>,
+}
+
+// Writes each presented frame out as a numbered PNG or pipes it to ffmpeg, on a thread of its
+// own. Frames arrive a few frames late through readbacks, which hold on to a sender until then,
+// so a stopped recording finishes once the last of them is written
+#[derive(Default)]
+pub struct RecordingSystem {
+    recording: Option<Recording>,
+    finishing: Vec<(PathBuf, JoinHandle<Result<u32>>)>,
+    frames: u32,
+}
+
+impl RecordingSystem {
+    pub fn is_recording(&self) -> bool {
+        self.recording.is_some()
+    }
+
+    // Frames captured by the current recording, or the last one
+    pub fn frames(&self) -> u32 {
+        self.frames
+    }
+
+    // Starts recording when stopped and stops when recording, returning where a new recording
+    // is written
+    pub fn toggle(&mut self, settings: &RecordingSettings) -> Result<Option<PathBuf>> {
+        if self.recording.is_some() {
+            self.stop();
+            return Ok(None);
+        }
+        self.start(settings).map(Some)
+    }
+
+    // Browsers have neither threads nor files to write to
+    pub fn start(&mut self, settings: &RecordingSettings) -> Result<PathBuf> {
+        if cfg!(target_arch = "wasm32") {
+            bail!("Recording isn't supported in the browser");
+        }
+        self.stop();
+        let path = next_recording_path(Path::new(RECORDINGS_DIRECTORY), settings.ffmpeg)?;
+        let (sender, receiver) = mpsc::channel();
+        let written = Arc::new(AtomicU32::new(0));
+        let writer = if settings.ffmpeg {
+            let path = path.clone();
+            let frame_rate = settings.frame_rate;
+            let written = written.clone();
+            thread::spawn(move || write_video(&path, frame_rate, receiver, &written))
+        } else {
+            std::fs::create_dir_all(&path)
+                .with_context(|| format!("Failed to create {}", path.display()))?;
+            let path = path.clone();
+            let written = written.clone();
+            thread::spawn(move || write_images(&path, receiver, &written))
+        };
+        self.recording = Some(Recording {
+            path: path.clone(),
+            sender,
+            writer,
+            written,
+        });
+        self.frames = 0;
+        Ok(path)
+    }
+
+    pub fn stop(&mut self) {
+        if let Some(recording) = self.recording.take() {
+            self.finishing.push((recording.path, recording.writer));
+        }
+    }
+
+    // Where this frame is sent once it's read back, when recording
+    pub fn frame_sender(&mut self) -> Option<mpsc::Sender<RecordedFrame>> {
+        let recording = self.recording.as_ref()?;
+        self.frames += 1;
+        Some(recording.sender.clone())
+    }
+
+    // Blocks until every frame sent so far is written, unless the writer gave up
+    pub fn wait_for_writer(&self) {
+        let recording = match self.recording.as_ref() {
+            Some(recording) => recording,
+            None => return,
+        };
+        while recording.written.load(Ordering::Acquire) < self.frames
+            && !recording.writer.is_finished()
+        {
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    // Reports how stopped recordings went once they're written
+    pub fn update(&mut self) -> Vec<String> {
+        let mut messages = Vec::new();
+        let mut index = 0;
+        while index < self.finishing.len() {
+            if self.finishing[index].1.is_finished() {
+                let (path, writer) = self.finishing.swap_remove(index);
+                messages.push(finished_message(&path, writer));
+            } else {
+                index += 1;
+            }
+        }
+        messages
+    }
+
+    // Waits for stopped recordings to be written, once every frame has been read back
+    pub fn finish(&mut self) -> Vec<String> {
+        self.stop();
+        self.finishing
+            .drain(..)
+            .map(|(path, writer)| finished_message(&path, writer))
+            .collect()
+    }
+}
+
+fn finished_message(path: &Path, writer: JoinHandle<Result<u32>>) -> String {
+    match writer.join() {
+        Ok(Ok(frames)) => format!("Recorded {} frames to {}", frames, path.display()),
+        Ok(Err(error)) => format!("Failed to record {}: {}", path.display(), error),
+        Err(_) => format!("Failed to record {}: the writer panicked", path.display()),
+    }
+}
+
+// The first of recording_001, recording_002 and so on that isn't taken
+fn next_recording_path(directory: &Path, video: bool) -> Result<PathBuf> {
+    std::fs::create_dir_all(directory)
+        .with_context(|| format!("Failed to create {}", directory.display()))?;
+    for index in 1..10000 {
+        let name = format!("recording_{:03}", index);
+        let directory_path = directory.join(&name);
+        let video_path = directory.join(format!("{}.mp4", name));
+        if !directory_path.exists() && !video_path.exists() {
+            return Ok(if video { video_path } else { directory_path });
+        }
+    }
+    bail!("No recording names are left in {}", directory.display())
+}
+
+fn write_images(
+    directory: &Path,
+    receiver: mpsc::Receiver<RecordedFrame>,
+    written: &AtomicU32,
+) -> Result<u32> {
+    let mut count = 0;
+    for frame in receiver {
+        let (width, height) = (frame.width, frame.height);
+        let image = image::RgbaImage::from_raw(width, height, frame.into_rgba())
+            .context("Failed to create an image from a recorded frame")?;
+        image.save(directory.join(format!("frame_{:05}.png", count)))?;
+        count += 1;
+        written.store(count, Ordering::Release);
+    }
+    Ok(count)
+}
+
+// ffmpeg is started with the size of the first frame, which every frame after has to match
+fn write_video(
+    path: &Path,
+    frame_rate: f32,
+    receiver: mpsc::Receiver<RecordedFrame>,
+    written: &AtomicU32,
+) -> Result<u32> {
+    let mut ffmpeg: Option<(Child, [u32; 2])> = None;
+    let mut count = 0;
+    for frame in receiver {
+        let size = [frame.width, frame.height];
+        let (child, started_size) = match ffmpeg.as_mut() {
+            Some(ffmpeg) => ffmpeg,
+            None => ffmpeg.insert((start_ffmpeg(path, size, frame_rate)?, size)),
+        };
+        if *started_size != size {
+            bail!("The window was resized while recording, every frame of a video has to match");
+        }
+        child
+            .stdin
+            .as_mut()
+            .context("ffmpeg has no input")?
+            .write_all(&frame.into_rgba())
+            .context("Failed to write a frame to ffmpeg")?;
+        count += 1;
+        written.store(count, Ordering::Release);
+    }
+    if let Some((mut child, _)) = ffmpeg {
+        // Closing its input lets ffmpeg finish the video
+        child.stdin = None;
+        let status = child.wait()?;
+        if !status.success() {
+            bail!("ffmpeg exited with {}", status);
+        }
+    }
+    Ok(count)
+}
+
+// Raw RGBA frames come in on stdin. H.264 needs even dimensions, so odd ones are padded
+fn start_ffmpeg(path: &Path, size: [u32; 2], frame_rate: f32) -> Result<Child> {
+    Command::new("ffmpeg")
+        .args([
+            "-y",
+            "-f",
+            "rawvideo",
+            "-pixel_format",
+            "rgba",
+            "-video_size",
+        ])
+        .arg(format!("{}x{}", size[0], size[1]))
+        .arg("-framerate")
+        .arg(frame_rate.to_string())
+        .args([
+            "-i",
+            "-",
+            "-vf",
+            "pad=ceil(iw/2)*2:ceil(ih/2)*2",
+            "-pix_fmt",
+            "yuv420p",
+        ])
+        .arg(path)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .context("Failed to start ffmpeg, is it installed and on the path?")
+}
@@ -0,0 +1,241 @@
+use anyhow::{bail, Context, Result};
+use nalgebra_glm as glm;
+use std::{
+    io::{BufRead, BufReader, Write},
+    net::{TcpListener, TcpStream},
+    path::{Component, Path, PathBuf},
+    str::FromStr,
+    sync::mpsc::{channel, Receiver, Sender},
+    thread,
+};
+
+use crate::settings::parse_vec3;
+
+// Commands are sent one per line over TCP and each is answered
+// with a single line, either "ok" or "error: <reason>"
+//
+//   load <path>
+//   camera <px> <py> <pz> <tx> <ty> <tz>
+//   screenshot <path>   relative to the server's screenshot directory
+//   set <name> <value>
+#[derive(Debug, Clone, PartialEq)]
+pub enum RemoteCommand {
+    LoadAsset(PathBuf),
+    SetCamera {
+        position: glm::Vec3,
+        target: glm::Vec3,
+    },
+    Screenshot(PathBuf),
+    SetSetting {
+        name: String,
+        value: String,
+    },
+}
+
+impl FromStr for RemoteCommand {
+    type Err = anyhow::Error;
+
+    fn from_str(line: &str) -> Result<Self> {
+        let line = line.trim();
+        let (name, arguments) = match line.split_once(char::is_whitespace) {
+            Some((name, arguments)) => (name, arguments.trim()),
+            None => (line, ""),
+        };
+
+        let command = match name {
+            "load" if !arguments.is_empty() => Self::LoadAsset(PathBuf::from(arguments)),
+            "camera" => {
+                let components = arguments.split_whitespace().collect::<Vec<_>>();
+                if components.len() != 6 {
+                    bail!("Usage: camera <px> <py> <pz> <tx> <ty> <tz>");
+                }
+                Self::SetCamera {
+                    position: parse_vec3(&components[..3].join(" "))?,
+                    target: parse_vec3(&components[3..].join(" "))?,
+                }
+            }
+            "screenshot" if !arguments.is_empty() => {
+                let path = PathBuf::from(arguments);
+                // Anything but plain names could leave the screenshot directory
+                if !path
+                    .components()
+                    .all(|component| matches!(component, Component::Normal(_) | Component::CurDir))
+                {
+                    bail!("Screenshot paths must be relative and can't contain '..'");
+                }
+                Self::Screenshot(path)
+            }
+            "set" => {
+                let (name, value) = arguments
+                    .split_once(char::is_whitespace)
+                    .context("Usage: set <name> <value>")?;
+                Self::SetSetting {
+                    name: name.to_string(),
+                    value: value.trim().to_string(),
+                }
+            }
+            "load" => bail!("Usage: load <path>"),
+            "screenshot" => bail!("Usage: screenshot <path>"),
+            _ => bail!(
+                "Unknown command '{}', expected one of: load, camera, screenshot, set",
+                name
+            ),
+        };
+        Ok(command)
+    }
+}
+
+pub struct RemoteRequest {
+    pub command: RemoteCommand,
+    reply: Sender<Result<(), String>>,
+}
+
+impl RemoteRequest {
+    pub fn respond(self, result: Result<()>) {
+        // The client may have disconnected while the command was running
+        let _ = self.reply.send(result.map_err(|error| error.to_string()));
+    }
+}
+
+// Commands are queued by the connection threads and applied on the main thread between frames
+pub struct RemoteServer {
+    requests: Receiver<RemoteRequest>,
+    screenshot_directory: PathBuf,
+}
+
+impl RemoteServer {
+    pub fn start(address: &str, screenshot_directory: PathBuf) -> Result<Self> {
+        let listener = TcpListener::bind(address)
+            .with_context(|| format!("Failed to bind the remote control server to {}!", address))?;
+        println!("Remote control server listening on {}", address);
+
+        let (sender, requests) = channel();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(error) => {
+                        eprintln!("Failed to accept remote connection: {}", error);
+                        continue;
+                    }
+                };
+                let sender = sender.clone();
+                thread::spawn(move || {
+                    if let Err(error) = Self::handle_connection(stream, sender) {
+                        eprintln!("Remote connection closed: {}", error);
+                    }
+                });
+            }
+        });
+
+        Ok(Self {
+            requests,
+            screenshot_directory,
+        })
+    }
+
+    fn handle_connection(stream: TcpStream, sender: Sender<RemoteRequest>) -> Result<()> {
+        let mut writer = stream.try_clone()?;
+        for line in BufReader::new(stream).lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let result = match line.parse::<RemoteCommand>() {
+                Ok(command) => {
+                    let (reply, response) = channel();
+                    sender
+                        .send(RemoteRequest { command, reply })
+                        .context("The viewer has shut down!")?;
+                    response.recv().context("The viewer has shut down!")?
+                }
+                Err(error) => Err(error.to_string()),
+            };
+
+            match result {
+                Ok(()) => writeln!(writer, "ok")?,
+                Err(error) => writeln!(writer, "error: {}", error)?,
+            }
+        }
+        Ok(())
+    }
+
+    // Where a screenshot command's path is saved, which parsing keeps inside the directory
+    pub fn screenshot_path(&self, path: &Path) -> PathBuf {
+        self.screenshot_directory.join(path)
+    }
+
+    pub fn requests(&self) -> impl Iterator<Item = RemoteRequest> + '_ {
+        self.requests.try_iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(line: &str) -> Result<RemoteCommand> {
+        line.parse::<RemoteCommand>()
+    }
+
+    #[test]
+    fn parses_each_command() {
+        assert_eq!(
+            parse("load assets/models/helmet.glb").unwrap(),
+            RemoteCommand::LoadAsset(PathBuf::from("assets/models/helmet.glb"))
+        );
+        assert_eq!(
+            parse("camera 1 2 3 0 0.5 -1").unwrap(),
+            RemoteCommand::SetCamera {
+                position: glm::vec3(1.0, 2.0, 3.0),
+                target: glm::vec3(0.0, 0.5, -1.0),
+            }
+        );
+        assert_eq!(
+            parse("screenshot frame.png").unwrap(),
+            RemoteCommand::Screenshot(PathBuf::from("frame.png"))
+        );
+        assert_eq!(
+            parse("  set bloom_intensity   0.25 ").unwrap(),
+            RemoteCommand::SetSetting {
+                name: "bloom_intensity".to_string(),
+                value: "0.25".to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_commands() {
+        assert!(parse("load").is_err());
+        assert!(parse("screenshot").is_err());
+        assert!(parse("camera 1 2 3").is_err());
+        assert!(parse("camera 1 2 3 4 5 six").is_err());
+        assert!(parse("set vsync").is_err());
+        assert!(parse("teleport 0 0 0").is_err());
+    }
+
+    #[test]
+    fn accepts_nested_relative_screenshot_paths() {
+        assert_eq!(
+            parse("screenshot captures/today/frame.png").unwrap(),
+            RemoteCommand::Screenshot(PathBuf::from("captures/today/frame.png"))
+        );
+        assert_eq!(
+            parse("screenshot ./captures/frame.png").unwrap(),
+            RemoteCommand::Screenshot(PathBuf::from("./captures/frame.png"))
+        );
+    }
+
+    #[test]
+    fn rejects_screenshot_paths_outside_the_directory() {
+        assert!(parse("screenshot ../frame.png").is_err());
+        assert!(parse("screenshot captures/../../frame.png").is_err());
+        assert!(parse("screenshot /tmp/frame.png").is_err());
+        #[cfg(windows)]
+        {
+            assert!(parse(r"screenshot C:\frame.png").is_err());
+            assert!(parse(r"screenshot \\server\share\frame.png").is_err());
+        }
+    }
+}
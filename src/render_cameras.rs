@@ -0,0 +1,292 @@
+use nalgebra_glm as glm;
+
+use crate::{
+    blit::{BlitPipeline, BlitUniform},
+    camera::Camera,
+    memory::{self, MemoryCategory, Tracked},
+    scene::Scene,
+    texture::Texture,
+};
+
+// How far past a mirror the clip plane sits, so the mirror's own surface is clipped
+const CLIP_PLANE_OFFSET: f32 = 0.001;
+
+// Where a render camera looks from
+#[allow(dead_code)]
+#[derive(Clone)]
+pub enum RenderCameraView {
+    // Fixed in the world, such as a security camera feeding a monitor
+    Camera(Camera),
+    // The main camera reflected in the plane through the point facing along the normal
+    Mirror {
+        point: glm::Vec3,
+        normal: glm::Vec3,
+    },
+    // The main camera reflected like a mirror, with the image lined up with the main view so
+    // planar_reflection materials can read it where they are on screen, such as for floors and
+    // water. The target should share the window's aspect ratio
+    Reflection {
+        point: glm::Vec3,
+        normal: glm::Vec3,
+    },
+    // The main camera carried from the entrance's transform to the exit's, seeing out of the
+    // other end
+    Portal {
+        entrance: glm::Mat4,
+        exit: glm::Mat4,
+    },
+}
+
+// A camera rendering the scene into a texture that materials show through their render_camera
+#[derive(Clone)]
+pub struct RenderCameraDesc {
+    pub view: RenderCameraView,
+    pub dimensions: [u32; 2],
+    // Frames between updates, one updates every frame and zero only renders the camera once
+    pub update_interval: u32,
+}
+
+impl Default for RenderCameraDesc {
+    fn default() -> Self {
+        Self {
+            view: RenderCameraView::Camera(Camera::default()),
+            dimensions: [512, 512],
+            update_interval: 1,
+        }
+    }
+}
+
+impl RenderCameraDesc {
+    // The main camera and the returned camera are relative to the scene's origin
+    pub fn camera(&self, main_camera: &Camera, scene: &Scene) -> Camera {
+        let mut camera = match &self.view {
+            RenderCameraView::Camera(camera) => {
+                let mut camera = camera.clone();
+                camera.translate(scene.local_point(&camera.position) - camera.position);
+                camera
+            }
+            RenderCameraView::Mirror { point, normal }
+            | RenderCameraView::Reflection { point, normal } => {
+                let point = &scene.local_point(point);
+                // Looking from behind the plane, the image is flipped when it is copied out so
+                // the view keeps its handedness and faces aren't culled inside out
+                let normal = normal.normalize();
+                let reflect_point = |position: &glm::Vec3| {
+                    position - normal * 2.0 * (position - point).dot(&normal)
+                };
+                let reflect_vector =
+                    |vector: &glm::Vec3| vector - normal * 2.0 * vector.dot(&normal);
+                Camera {
+                    position: reflect_point(&main_camera.position),
+                    target: reflect_point(&main_camera.target),
+                    up: reflect_vector(&main_camera.up),
+                    // Whatever is behind the mirror would otherwise show up in front of it
+                    clip_plane: Some(glm::vec4(
+                        normal.x,
+                        normal.y,
+                        normal.z,
+                        -normal.dot(point) - CLIP_PLANE_OFFSET,
+                    )),
+                    ..main_camera.clone()
+                }
+            }
+            RenderCameraView::Portal { entrance, exit } => {
+                let transform = scene.local_transform(&exit.cast())
+                    * glm::inverse(&scene.local_transform(&entrance.cast()));
+                let transform_point = |position: &glm::Vec3| {
+                    (transform * glm::vec4(position.x, position.y, position.z, 1.0)).xyz()
+                };
+                Camera {
+                    position: transform_point(&main_camera.position),
+                    target: transform_point(&main_camera.target),
+                    up: (transform
+                        * glm::vec4(main_camera.up.x, main_camera.up.y, main_camera.up.z, 0.0))
+                    .xyz()
+                    .normalize(),
+                    ..main_camera.clone()
+                }
+            }
+        };
+        if !matches!(self.view, RenderCameraView::Reflection { .. }) {
+            camera.aspect_ratio =
+                Some(self.dimensions[0] as f32 / self.dimensions[1].max(1) as f32);
+        }
+        camera
+    }
+
+    fn mirrored(&self) -> bool {
+        matches!(
+            self.view,
+            RenderCameraView::Mirror { .. } | RenderCameraView::Reflection { .. }
+        )
+    }
+}
+
+struct RenderTarget {
+    texture: Texture,
+    dimensions: [u32; 2],
+    // The frame the target was last rendered in
+    rendered: Option<u64>,
+    previous_view_projection: Option<glm::Mat4>,
+}
+
+// The scene is rendered at the main view's resolution before it is scaled into a target
+pub struct RenderCameraSource {
+    texture: Texture,
+    dimensions: [u32; 2],
+    stretch_bind_group: wgpu::BindGroup,
+    mirror_bind_group: wgpu::BindGroup,
+}
+
+impl RenderCameraSource {
+    pub fn view(&self) -> &wgpu::TextureView {
+        &self.texture.view
+    }
+}
+
+// Renders cameras other than the main one into textures materials can sample, each at its own
+// update rate. A surface showing its own camera sees the image from the camera's last update
+pub struct RenderCameraSystem {
+    format: wgpu::TextureFormat,
+    blit_pipeline: BlitPipeline,
+    stretch_buffer: Tracked<wgpu::Buffer>,
+    mirror_buffer: Tracked<wgpu::Buffer>,
+    targets: Vec<RenderTarget>,
+    source: Option<RenderCameraSource>,
+}
+
+impl RenderCameraSystem {
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat) -> Self {
+        let uniform_buffer = |label, uniform: BlitUniform| {
+            memory::create_buffer_init(
+                device,
+                MemoryCategory::Transient,
+                &wgpu::util::BufferInitDescriptor {
+                    label: Some(label),
+                    contents: bytemuck::cast_slice(&[uniform]),
+                    usage: wgpu::BufferUsages::UNIFORM,
+                },
+            )
+        };
+        Self {
+            format,
+            blit_pipeline: BlitPipeline::new(device, format),
+            stretch_buffer: uniform_buffer("Render Camera Blit Buffer", BlitUniform::stretch()),
+            mirror_buffer: uniform_buffer("Render Camera Mirror Buffer", BlitUniform::mirror()),
+            targets: Vec::new(),
+            source: None,
+        }
+    }
+
+    // Targets are created once and keep their size, since material bind groups refer to them
+    pub fn update(&mut self, device: &wgpu::Device, scene: &Scene) {
+        while self.targets.len() < scene.render_cameras.len() {
+            let desc = &scene.render_cameras[self.targets.len()];
+            let dimensions = [desc.dimensions[0].max(1), desc.dimensions[1].max(1)];
+            self.targets.push(RenderTarget {
+                texture: Texture::create_render_target(
+                    device,
+                    dimensions[0],
+                    dimensions[1],
+                    self.format,
+                    "Render Camera Texture",
+                ),
+                dimensions,
+                rendered: None,
+                previous_view_projection: None,
+            });
+        }
+    }
+
+    pub fn textures(&self) -> Vec<&Texture> {
+        self.targets.iter().map(|target| &target.texture).collect()
+    }
+
+    // The render cameras to update this frame and the cameras to render them with
+    pub fn due(&self, scene: &Scene, main_camera: &Camera, frame: u64) -> Vec<(usize, Camera)> {
+        scene
+            .render_cameras
+            .iter()
+            .enumerate()
+            .filter(|(index, desc)| {
+                match self.targets.get(*index).and_then(|target| target.rendered) {
+                    None => true,
+                    Some(_) if desc.update_interval == 0 => false,
+                    Some(rendered) => frame >= rendered + desc.update_interval as u64,
+                }
+            })
+            .map(|(index, desc)| (index, desc.camera(main_camera, scene)))
+            .collect()
+    }
+
+    pub fn take_source(
+        &mut self,
+        device: &wgpu::Device,
+        dimensions: &[u32; 2],
+    ) -> RenderCameraSource {
+        match self.source.take() {
+            Some(source) if source.dimensions == *dimensions => source,
+            _ => {
+                let texture = Texture::create_render_target(
+                    device,
+                    dimensions[0],
+                    dimensions[1],
+                    self.format,
+                    "Render Camera Source Texture",
+                );
+                let stretch_bind_group =
+                    self.blit_pipeline
+                        .create_bind_group(device, &texture, &self.stretch_buffer);
+                let mirror_bind_group =
+                    self.blit_pipeline
+                        .create_bind_group(device, &texture, &self.mirror_buffer);
+                RenderCameraSource {
+                    texture,
+                    dimensions: *dimensions,
+                    stretch_bind_group,
+                    mirror_bind_group,
+                }
+            }
+        }
+    }
+
+    pub fn restore_source(&mut self, source: RenderCameraSource) {
+        self.source = Some(source);
+    }
+
+    pub fn previous_view_projection(&self, index: usize) -> Option<glm::Mat4> {
+        self.targets
+            .get(index)
+            .and_then(|target| target.previous_view_projection)
+    }
+
+    // Scales the rendered source into the camera's target
+    pub fn finish(
+        &mut self,
+        encoder: &mut wgpu::CommandEncoder,
+        source: &RenderCameraSource,
+        scene: &Scene,
+        index: usize,
+        frame: u64,
+        previous_view_projection: Option<glm::Mat4>,
+    ) {
+        let target = match self.targets.get_mut(index) {
+            Some(target) => target,
+            None => return,
+        };
+        let bind_group = if scene.render_cameras[index].mirrored() {
+            &source.mirror_bind_group
+        } else {
+            &source.stretch_bind_group
+        };
+        self.blit_pipeline.blit(
+            encoder,
+            &target.texture.view,
+            bind_group,
+            wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+            Some([0, 0, target.dimensions[0], target.dimensions[1]]),
+        );
+        target.rendered = Some(frame);
+        target.previous_view_projection = previous_view_projection;
+    }
+}
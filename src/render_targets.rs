@@ -0,0 +1,85 @@
+use std::{ops::RangeInclusive, rc::Rc};
+
+use crate::texture::Texture;
+
+// The parts of a frame in the order they run, which transient targets are used between
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum FramePhase {
+    // Reflection and irradiance probe faces, drawn like the scene before it
+    Probes,
+    AmbientOcclusion,
+    Scene,
+    DepthOfField,
+    MotionBlur,
+    Bloom,
+    Upscale,
+    PostProcess,
+}
+
+struct PooledTarget {
+    width: u32,
+    height: u32,
+    format: wgpu::TextureFormat,
+    texture: Rc<Texture>,
+    // The phases of every user sharing the texture, which never overlap
+    lifetimes: Vec<RangeInclusive<FramePhase>>,
+}
+
+// Hands out render targets for effects that only need them during part of the frame, so
+// targets of the same size and format alias each other when their users never overlap
+#[derive(Default)]
+pub struct RenderTargetPool {
+    targets: Vec<PooledTarget>,
+    acquired: usize,
+}
+
+impl RenderTargetPool {
+    pub fn acquire(
+        &mut self,
+        device: &wgpu::Device,
+        width: u32,
+        height: u32,
+        format: wgpu::TextureFormat,
+        label: &str,
+        lifetime: RangeInclusive<FramePhase>,
+    ) -> Rc<Texture> {
+        self.acquired += 1;
+        let overlaps = |other: &RangeInclusive<FramePhase>| {
+            lifetime.start() <= other.end() && other.start() <= lifetime.end()
+        };
+        let reusable = self.targets.iter_mut().find(|target| {
+            target.width == width
+                && target.height == height
+                && target.format == format
+                && !target.lifetimes.iter().any(overlaps)
+        });
+        if let Some(target) = reusable {
+            target.lifetimes.push(lifetime);
+            return target.texture.clone();
+        }
+
+        let texture = Rc::new(Texture::create_render_target(
+            device, width, height, format, label,
+        ));
+        self.targets.push(PooledTarget {
+            width,
+            height,
+            format,
+            texture: texture.clone(),
+            lifetimes: vec![lifetime],
+        });
+        texture
+    }
+
+    // Forgets every target before they're all acquired again at a new size. Textures still held
+    // are freed once their users replace them, and are never handed out again
+    pub fn clear(&mut self) {
+        self.targets.clear();
+        self.acquired = 0;
+    }
+
+    // Textures allocated and how many targets they were acquired for
+    pub fn stats(&self) -> (usize, usize) {
+        (self.targets.len(), self.acquired)
+    }
+}
@@ -1,181 +1,2858 @@
-use anyhow::{Context, Result};
+use anyhow::{anyhow, bail, Context, Result};
+use instant::Instant;
+use nalgebra_glm as glm;
 use raw_window_handle::HasRawWindowHandle;
+use std::{cell::Cell, rc::Rc, sync::mpsc, time::Duration};
 
-use crate::texture::Texture;
+use crate::{
+    area_lights::AreaLightSystem,
+    bloom::BloomSystem,
+    blue_noise::BlueNoise,
+    bounds::{Aabb, Frustum},
+    budgets::BudgetMonitor,
+    bvh::{Ray, SceneBvh, SceneHit},
+    camera::{aspect_ratio, Camera, CameraUniform},
+    camera_2d::Camera2d,
+    compute::{
+        ComputeBufferHandle, ComputeResource, ComputeStage, ComputeSystem, ComputeTextureHandle,
+    },
+    decals::DecalSystem,
+    depth_of_field::DepthOfFieldSystem,
+    environment::EnvironmentSystem,
+    exposure::ExposureSystem,
+    frame_capture::FrameCaptureSystem,
+    gui::{GuiFrame, GuiPass},
+    hiz::HiZSystem,
+    irradiance::IrradianceSystem,
+    isosurface::IsosurfaceSystem,
+    lens_flare::LensFlareSystem,
+    lighting::LightingSystem,
+    lines::LineSystem,
+    memory::{self, MemoryCategory, MemoryMonitor, MemoryStats, Tracked},
+    model::{DrawStats, ModelSystem},
+    motion_blur::MotionBlurSystem,
+    particles::ParticleSystem,
+    path_tracer::PathTracerSystem,
+    points::PointCloudSystem,
+    postprocess::PostProcess,
+    power::{PowerMonitor, PowerSource},
+    probes::{ProbeFace, ReflectionProbeSystem},
+    profiler::GpuProfiler,
+    ray_traced_shadows::RayTracedShadowSystem,
+    readback::{self, FrameCallback, Readback, ReadbackCallback, ReadbackQueue, TextureLayout},
+    recording::{RecordedFrame, RecordingSystem},
+    render_cameras::RenderCameraSystem,
+    render_targets::RenderTargetPool,
+    resolution::ResolutionController,
+    scene::Scene,
+    settings::{Settings, Upscaling},
+    shader_errors::{ShaderError, ShaderErrors},
+    sharpen::SharpenSystem,
+    sky::SkySystem,
+    sprites::{self, SpriteSystem},
+    ssao::SsaoSystem,
+    temporal::{self, TemporalUpscaleSystem},
+    terrain::TerrainSystem,
+    text::{Text, TextSystem},
+    texture::{DepthConfig, MultisampledTarget, Texture},
+    upscale::UpscaleSystem,
+    velocity::VelocitySystem,
+    viewports::{Viewport, ViewportTarget},
+    voxels::VoxelSystem,
+    water::WaterSystem,
+    windows::{SecondaryWindow, WindowHandle, WindowSurface},
+};
 
-#[cfg(target_family = "wasm")]
-const BACKEND: wgpu::Backends = wgpu::Backends::BROWSER_WEBGPU;
+#[cfg(all(target_family = "wasm", not(feature = "webgl")))]
+pub const BACKEND: wgpu::Backends = wgpu::Backends::BROWSER_WEBGPU;
+
+#[cfg(all(target_family = "wasm", feature = "webgl"))]
+pub const BACKEND: wgpu::Backends = wgpu::Backends::GL;
 
 #[cfg(target_os = "windows")]
-const BACKEND: wgpu::Backends = wgpu::Backends::DX12;
+pub const BACKEND: wgpu::Backends = wgpu::Backends::DX12;
 
-#[cfg(target_os = "macos")]
-const BACKEND: wgpu::Backends = wgpu::Backends::METAL;
+#[cfg(any(target_os = "macos", target_os = "ios"))]
+pub const BACKEND: wgpu::Backends = wgpu::Backends::METAL;
 
 #[cfg(target_os = "linux")]
-const BACKEND: wgpu::Backends = wgpu::Backends::VULKAN;
+pub const BACKEND: wgpu::Backends = wgpu::Backends::VULKAN;
+
+// Older devices only have GLES
+#[cfg(target_os = "android")]
+pub const BACKEND: wgpu::Backends = wgpu::Backends::VULKAN.union(wgpu::Backends::GL);
+
+// The depth buffer has a stencil unless asked not to. Depth is read back through a compute pass
+// when it has one, which WebGL can't run
+pub const STENCIL: bool = !cfg!(feature = "webgl");
+
+// Depth is standard unless asked to be reversed or the origin is moved for large worlds, which
+// takes the place of the stencil
+pub const REVERSE_Z: bool = false;
+
+// Android only hands over a window between the Resumed and Suspended events, which come after
+// startup, so the surface is created once the app resumes
+const SURFACE_AT_STARTUP: bool = !cfg!(target_os = "android");
+
+// Used until there is a surface to ask, the format Android surfaces prefer
+const FALLBACK_SURFACE_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+
+pub struct AdapterDetails {
+    pub info: wgpu::AdapterInfo,
+    pub features: wgpu::Features,
+    pub limits: wgpu::Limits,
+    pub supports_surface: bool,
+    pub active: bool,
+}
+
+impl AdapterDetails {
+    fn new(adapter: &wgpu::Adapter, surface: Option<&wgpu::Surface>, active: bool) -> Self {
+        Self {
+            info: adapter.get_info(),
+            features: adapter.features(),
+            limits: adapter.limits(),
+            supports_surface: surface.is_none_or(|surface| adapter.is_surface_supported(surface)),
+            active,
+        }
+    }
+}
 
 pub struct Renderer {
-    surface: wgpu::Surface,
-    device: wgpu::Device,
-    queue: wgpu::Queue,
-    config: wgpu::SurfaceConfiguration,
+    // Everything tied to the active device, released while switching adapters
+    gpu: Option<Gpu>,
+    pub camera: Camera,
+    pub settings: Settings,
+    // Physical pixels of the window
     dimensions: [u32; 2],
-    depth_texture: Texture,
+    // Physical pixels per logical pixel, reported by the platform for high density displays
+    scale_factor: f64,
+    // The graphics APIs adapters are chosen from, kept for switching adapters
+    backends: wgpu::Backends,
+    depth: DepthConfig,
+    last_frame: Instant,
+    paused: bool,
+    power_monitor: PowerMonitor,
+    pub budgets: BudgetMonitor,
+    pub memory: MemoryMonitor,
+    frame_index: u64,
+    // Drawn over the next frame, then cleared
+    text: Vec<Text>,
+    // Set once the depth under a click to focus has been read back
+    focus_distance: Rc<Cell<Option<f32>>>,
+    resolution_controller: ResolutionController,
+    // Built on the first pick, so scenes that are never picked from don't pay for it
+    picking_bvh: SceneBvh,
+    // The last model picked by clicking
+    pub selection: Option<SceneHit>,
+    // Windows besides the main one, removed windows leave an empty slot so handles stay valid
+    windows: Vec<Option<SecondaryWindow>>,
+    // Rectangles of the window rendered with their own cameras, the whole window shows the
+    // main camera when there are none
+    pub viewports: Vec<Viewport>,
+    // Drives the camera and sprites while in 2D mode
+    pub camera_2d: Camera2d,
+    // The 3D camera to return to, kept while in 2D mode
+    camera_3d: Option<Camera>,
+    pub frame_capture: FrameCaptureSystem,
+    pub recording: RecordingSystem,
+    // Shader and GPU errors shown on screen until they're dismissed
+    pub shader_errors: ShaderErrors,
 }
 
 impl Renderer {
     pub async fn new(
         window_handle: &impl HasRawWindowHandle,
         dimensions: &[u32; 2],
+        backends: wgpu::Backends,
+        stencil: bool,
+        reverse_z: bool,
+        samples: u32,
     ) -> Result<Self> {
-        let instance = wgpu::Instance::new(BACKEND);
+        // Mobile windows have no size until they are resumed
+        let dimensions = [dimensions[0].max(1), dimensions[1].max(1)];
+        let depth = DepthConfig::new(stencil, reverse_z, samples);
+        // RenderDoc is looked for before the device is created so it can hook the device
+        let frame_capture = FrameCaptureSystem::new();
+        let gpu = Gpu::new(window_handle, &dimensions, backends, depth, None).await?;
+        let shader_errors = ShaderErrors::default();
+        shader_errors.watch_device(&gpu.device);
+        Ok(Self {
+            gpu: Some(gpu),
+            camera: Camera::default(),
+            settings: Settings::default(),
+            dimensions,
+            scale_factor: 1.0,
+            backends,
+            depth,
+            last_frame: Instant::now(),
+            paused: false,
+            power_monitor: PowerMonitor::default(),
+            budgets: BudgetMonitor::default(),
+            memory: MemoryMonitor::default(),
+            frame_index: 0,
+            text: Vec::new(),
+            focus_distance: Rc::new(Cell::new(None)),
+            resolution_controller: ResolutionController::default(),
+            picking_bvh: SceneBvh::default(),
+            selection: None,
+            windows: Vec::new(),
+            viewports: Vec::new(),
+            camera_2d: Camera2d::default(),
+            camera_3d: None,
+            frame_capture,
+            recording: RecordingSystem::default(),
+            shader_errors,
+        })
+    }
 
-        let surface = unsafe { instance.create_surface(window_handle) };
+    // Starts or stops recording presented frames, returning where a new recording is written
+    pub fn toggle_recording(&mut self) -> Result<Option<std::path::PathBuf>> {
+        self.recording.toggle(&self.settings.recording)
+    }
 
-        let adapter = Self::create_adapter(&instance, &surface).await?;
+    // Captures the next frame in RenderDoc, when the app is running under it
+    pub fn capture_next_frame(&mut self) -> Result<()> {
+        self.frame_capture.capture_next_frame()
+    }
 
-        let (device, queue) = Self::request_device(&adapter).await?;
+    pub fn adapters(&self) -> &[AdapterDetails] {
+        self.gpu
+            .as_ref()
+            .map(|gpu| gpu.adapters.as_slice())
+            .unwrap_or_default()
+    }
 
-        let swapchain_format = surface
-            .get_preferred_format(&adapter)
-            .context("Failed to get preferred surface format!")?;
+    // Whether the active device can time individual passes
+    pub fn gpu_timing_supported(&self) -> bool {
+        self.gpu.as_ref().is_some_and(|gpu| gpu.profiler.is_some())
+    }
 
-        let config = wgpu::SurfaceConfiguration {
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
-            format: swapchain_format,
-            width: dimensions[0],
-            height: dimensions[1],
-            present_mode: wgpu::PresentMode::Fifo,
-        };
+    pub fn power_source(&self) -> PowerSource {
+        self.power_monitor.source()
+    }
 
-        surface.configure(&device, &config);
+    // The settings in effect this frame, which can be scaled back to save power
+    pub fn active_settings(&mut self) -> Settings {
+        self.power_monitor.update();
+        let mut settings = self.settings.clone();
+        if self.gpu.as_ref().is_some_and(|gpu| !gpu.compute_supported) {
+            settings = settings.without_compute();
+        }
+        // Nothing that depends on how fast frames are made may change an offline recording
+        if self.recording.is_recording() && settings.recording.offline {
+            settings.vsync = false;
+            settings.frame_rate_limit = 0.0;
+            return settings;
+        }
+        if settings.dynamic_resolution.enabled {
+            settings.render_scale = self.resolution_controller.scale();
+        }
+        if settings.power_saving && self.power_monitor.source() == PowerSource::Battery {
+            settings.battery_saving()
+        } else {
+            settings
+        }
+    }
 
-        let depth_texture =
-            Texture::create_depth_texture(&device, dimensions[0], dimensions[1], "Depth Texture");
+    // Recorded frames are a fixed step apart so they play back at the speed they were made
+    pub fn frame_step(&self) -> Option<f32> {
+        self.recording
+            .is_recording()
+            .then(|| 1.0 / self.settings.recording.frame_rate)
+    }
 
-        Ok(Self {
-            surface,
-            device,
-            queue,
-            config,
-            dimensions: *dimensions,
-            depth_texture,
-        })
+    // Bounds of everything drawn in the scene, as of the last frame
+    pub fn scene_bounds(&self) -> Aabb {
+        self.gpu
+            .as_ref()
+            .map(|gpu| gpu.scene_bounds())
+            .unwrap_or_default()
     }
 
-    async fn create_adapter(
-        instance: &wgpu::Instance,
-        surface: &wgpu::Surface,
-    ) -> Result<wgpu::Adapter> {
-        instance
-            .request_adapter(&wgpu::RequestAdapterOptions {
-                power_preference: wgpu::PowerPreference::default(),
-                compatible_surface: Some(&surface),
-                force_fallback_adapter: false,
-            })
-            .await
-            .context("Failed to request a GPU adapter!")
+    // The camera moved to frame the selected instance, or the whole scene without a selection
+    pub fn framed_camera(&self, scene: &Scene) -> Camera {
+        let bounds = match self.selection.as_ref() {
+            Some(selection) => scene
+                .instance_bounds(selection.model, selection.instance)
+                .unwrap_or_default(),
+            None => {
+                let mut bounds = scene.bounds();
+                bounds.merge(&self.scene_bounds());
+                bounds
+            }
+        };
+        let mut camera = self.camera.clone();
+        camera.frame(&bounds, aspect_ratio(&self.dimensions));
+        camera
     }
 
-    async fn request_device(adapter: &wgpu::Adapter) -> Result<(wgpu::Device, wgpu::Queue)> {
-        let (device, queue) = adapter
-            .request_device(
-                &wgpu::DeviceDescriptor {
-                    features: wgpu::Features::empty(),
-                    limits: wgpu::Limits::default(),
-                    label: None,
-                },
-                None,
-            )
-            .await
-            .context("Failed to request a device!")?;
-        Ok((device, queue))
+    // When the next frame is due if the frame rate is limited
+    pub fn next_frame_time(&mut self) -> Option<Instant> {
+        let frame_rate_limit = self.active_settings().frame_rate_limit;
+        if frame_rate_limit <= 0.0 {
+            return None;
+        }
+        Some(self.last_frame + Duration::from_secs_f32(1.0 / frame_rate_limit))
+    }
+
+    // Browsers only expose the adapter they choose
+    #[cfg_attr(target_arch = "wasm32", allow(dead_code))]
+    pub async fn switch_adapter(
+        &mut self,
+        window_handle: &impl HasRawWindowHandle,
+        adapter_index: usize,
+    ) -> Result<()> {
+        let previous_index = self.adapters().iter().position(|adapter| adapter.active);
+
+        // A window surface can't be handed over to another device,
+        // so the old device and surface are released before the new ones are created.
+        // Secondary windows have to be attached again afterwards
+        for window in self.windows.iter_mut().flatten() {
+            window.surface = None;
+        }
+        self.gpu = None;
+
+        let backends = self.backends;
+        let mut gpu = match Gpu::new(
+            window_handle,
+            &self.dimensions,
+            backends,
+            self.depth,
+            Some(adapter_index),
+        )
+        .await
+        {
+            Ok(gpu) => gpu,
+            Err(error) => {
+                eprintln!("Failed to switch to adapter {}: {}", adapter_index, error);
+                Gpu::new(
+                    window_handle,
+                    &self.dimensions,
+                    backends,
+                    self.depth,
+                    previous_index,
+                )
+                .await
+                .context("Failed to restore the previous adapter!")?
+            }
+        };
+        if gpu.surface.is_none() && !self.paused {
+            gpu.create_surface(window_handle);
+        }
+        self.shader_errors.watch_device(&gpu.device);
+        self.gpu = Some(gpu);
+        self.last_frame = Instant::now();
+        Ok(())
     }
 
     pub fn resize(&mut self, dimensions: [u32; 2]) {
-        if dimensions[0] == 0 || dimensions[1] == 0 {
+        // Minimized windows have no area, and fullscreen transitions can report the same size twice
+        if dimensions[0] == 0 || dimensions[1] == 0 || dimensions == self.dimensions {
             return;
         }
         self.dimensions = dimensions;
-        self.config.width = dimensions[0];
-        self.config.height = dimensions[1];
-        self.surface.configure(&self.device, &self.config);
-        self.depth_texture = Texture::create_depth_texture(
-            &self.device,
-            dimensions[0],
-            dimensions[1],
-            "Depth Texture",
-        );
+        if let Some(gpu) = self.gpu.as_mut() {
+            gpu.resize(dimensions);
+        }
     }
 
-    pub fn render(&mut self, dimensions: &[u32; 2]) -> Result<()> {
-        match self.render_frame(dimensions) {
-            Ok(_) => {}
-            // Recreate the swapchain if lost
-            Err(wgpu::SurfaceError::Lost) => self.resize(self.dimensions),
-            // The system is out of memory, we should probably quit
-            // Err(wgpu::SurfaceError::OutOfMemory) => *control_flow = ControlFlow::Exit,
-            // All other errors should be resolved by the next frame
-            Err(e) => eprintln!("{:?}", e),
+    pub fn set_scale_factor(&mut self, scale_factor: f64) {
+        if scale_factor > 0.0 {
+            self.scale_factor = scale_factor;
         }
+    }
+
+    pub fn scale_factor(&self) -> f64 {
+        self.scale_factor
+    }
+
+    pub fn physical_size(&self) -> [u32; 2] {
+        self.dimensions
+    }
+
+    pub fn logical_size(&self) -> glm::Vec2 {
+        glm::vec2(self.dimensions[0] as f32, self.dimensions[1] as f32) / self.scale_factor as f32
+    }
+
+    // Physical pixels per logical pixel of the interface and screen text
+    pub fn interface_scale(&self) -> f32 {
+        self.scale_factor as f32 * self.settings.ui_scale
+    }
+
+    // Opens another view of the scene in the window, starting from the main camera
+    pub fn add_window(
+        &mut self,
+        window_handle: &impl HasRawWindowHandle,
+        dimensions: &[u32; 2],
+    ) -> Result<WindowHandle> {
+        let handle = WindowHandle(self.windows.len());
+        self.windows
+            .push(Some(SecondaryWindow::new(self.camera.clone(), *dimensions)));
+        self.attach_window(handle, window_handle)?;
+        Ok(handle)
+    }
+
+    // Creates the window's surface on the active device, needed again after switching adapters
+    pub fn attach_window(
+        &mut self,
+        handle: WindowHandle,
+        window_handle: &impl HasRawWindowHandle,
+    ) -> Result<()> {
+        let gpu = self
+            .gpu
+            .as_ref()
+            .context("No device is available to attach a window to!")?;
+        let window = self
+            .windows
+            .get_mut(handle.0)
+            .and_then(|window| window.as_mut())
+            .with_context(|| format!("Window handle refers to missing window {}!", handle.0))?;
+        window.surface = Some(WindowSurface::new(
+            &gpu.instance,
+            &gpu.adapter,
+            &gpu.device,
+            window_handle,
+            &window.dimensions,
+        )?);
         Ok(())
     }
 
-    fn render_frame(&mut self, _dimensions: &[u32; 2]) -> Result<(), wgpu::SurfaceError> {
-        // let height = if dimensions[1] > 0 {
-        //     dimensions[1] as f32
-        // } else {
-        //     1.0
-        // };
-        // let aspect_ratio = dimensions[0] as f32 / height as f32;
+    // The surface is released here, so this must be called before the window itself is closed
+    pub fn remove_window(&mut self, handle: WindowHandle) {
+        if let Some(window) = self.windows.get_mut(handle.0) {
+            *window = None;
+        }
+    }
 
-        let frame = self.surface.get_current_texture()?;
+    pub fn resize_window(&mut self, handle: WindowHandle, dimensions: [u32; 2]) {
+        let window = match self
+            .windows
+            .get_mut(handle.0)
+            .and_then(|window| window.as_mut())
+        {
+            Some(window) => window,
+            None => return,
+        };
+        if dimensions[0] == 0 || dimensions[1] == 0 {
+            return;
+        }
+        window.dimensions = dimensions;
+        if let (Some(gpu), Some(surface)) = (self.gpu.as_ref(), window.surface.as_mut()) {
+            surface.resize(&gpu.device, &dimensions);
+        }
+    }
 
-        let view = frame
-            .texture
-            .create_view(&wgpu::TextureViewDescriptor::default());
+    #[allow(dead_code)]
+    pub fn window_camera_mut(&mut self, handle: WindowHandle) -> Option<&mut Camera> {
+        self.windows
+            .get_mut(handle.0)
+            .and_then(|window| window.as_mut())
+            .map(|window| &mut window.camera)
+    }
 
-        let mut encoder = self
-            .device
-            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
-                label: Some("Render Encoder"),
-            });
+    // Probes are otherwise only captured when they are added or moved
+    pub fn capture_reflection_probes(&mut self) {
+        if let Some(gpu) = self.gpu.as_mut() {
+            gpu.reflection_probe_system.request_capture();
+        }
+    }
+
+    // The irradiance volume is otherwise only baked when it is added or changed
+    pub fn bake_irradiance_volume(&mut self) {
+        if let Some(gpu) = self.gpu.as_mut() {
+            gpu.irradiance_system.request_bake();
+        }
+    }
+
+    // Draws per pass of the merged models, followed by what they would take without merging
+    pub fn static_batch_draws(&self) -> (usize, usize) {
+        self.gpu
+            .as_ref()
+            .map_or((0, 0), |gpu| gpu.model_system.static_batch_draws())
+    }
+
+    // Sizes of every buffer and texture the renderer has allocated
+    pub fn memory_stats(&self) -> MemoryStats {
+        memory::stats()
+    }
+
+    // Transient render targets allocated and how many effects they are shared between
+    pub fn render_target_stats(&self) -> (usize, usize) {
+        self.gpu
+            .as_ref()
+            .map(|gpu| gpu.target_pool.stats())
+            .unwrap_or_default()
+    }
+
+    // Local lights given shadows in the atlas and the views rendered for them
+    pub fn shadow_stats(&self) -> (usize, usize) {
+        self.gpu
+            .as_ref()
+            .map(|gpu| gpu.lighting_system.shadow_stats())
+            .unwrap_or_default()
+    }
+
+    pub fn draw_stats(&self) -> DrawStats {
+        self.gpu
+            .as_ref()
+            .map(|gpu| gpu.model_system.draw_stats())
+            .unwrap_or_default()
+    }
+
+    pub fn rebuild_static_batches(&mut self) {
+        if let Some(gpu) = self.gpu.as_mut() {
+            gpu.model_system.clear_static_batches();
+        }
+    }
+
+    // Reads material shaders from their files again, such as after editing them
+    pub fn reload_material_shaders(&mut self) {
+        if let Some(gpu) = self.gpu.as_mut() {
+            gpu.model_system.reload_custom_shaders();
+        }
+        self.shader_errors.clear();
+    }
+
+    // Mobile platforms forbid GPU work while the application is in the background and take its
+    // windows away, so their surfaces are released with them
+    pub fn pause(&mut self) {
+        self.paused = true;
+        if let Some(gpu) = self.gpu.as_mut() {
+            gpu.surface = None;
+        }
+        for window in self.windows.iter_mut().flatten() {
+            window.surface = None;
+        }
+    }
+
+    // Secondary windows have to be attached again afterwards
+    pub fn resume(&mut self, window_handle: &impl HasRawWindowHandle) {
+        self.paused = false;
+        self.last_frame = Instant::now();
+        if let Some(gpu) = self.gpu.as_mut() {
+            if gpu.surface.is_none() {
+                gpu.create_surface(window_handle);
+            }
+        }
+    }
+
+    // The callback receives the buffer's contents once a later frame has finished on the GPU
+    #[allow(dead_code)]
+    pub fn read_compute_buffer(
+        &mut self,
+        buffer: ComputeBufferHandle,
+        callback: impl FnOnce(Result<Vec<u8>>) + 'static,
+    ) {
+        self.read_compute(ComputeResource::Buffer(buffer), Box::new(callback));
+    }
+
+    // The callback receives the texture's pixels in rows without padding
+    #[allow(dead_code)]
+    pub fn read_compute_texture(
+        &mut self,
+        texture: ComputeTextureHandle,
+        callback: impl FnOnce(Result<Vec<u8>>) + 'static,
+    ) {
+        self.read_compute(ComputeResource::Texture(texture), Box::new(callback));
+    }
+
+    fn read_compute(&mut self, resource: ComputeResource, callback: ReadbackCallback) {
+        match self.gpu.as_mut() {
+            Some(gpu) => gpu.compute_reads.push((resource, callback)),
+            None => callback(Err(anyhow!("No device is available to read back from!"))),
+        }
+    }
+
+    // Position is in pixels from the top left corner of the window. The callback receives the
+    // distance from the camera along its view direction, or nothing when the background is hit
+    pub fn read_depth(
+        &mut self,
+        position: glm::Vec2,
+        callback: impl FnOnce(Result<Option<f32>>) + 'static,
+    ) {
+        let gpu = match self.gpu.as_mut() {
+            Some(gpu) => gpu,
+            None => {
+                callback(Err(anyhow!("No device is available to read back from!")));
+                return;
+            }
+        };
+        let [z_near, z_far] = [self.camera.z_near, self.camera.z_far];
+        let config = self.depth;
+        gpu.depth_reads.push((
+            position,
+            Box::new(move |result: Result<Vec<u8>>| {
+                callback(result.and_then(|data| {
+                    let depth = config.standard(bytemuck::pod_read_unaligned::<f32>(
+                        data.get(..4).context("The depth readback was empty!")?,
+                    ));
+                    Ok((depth < 1.0).then(|| z_near * z_far / (z_far - depth * (z_far - z_near))))
+                }))
+            }),
+        ));
+    }
+
+    // The callback receives the next presented frame without the interface, copied from a target
+    // it's drawn into on its way to the window
+    pub fn read_frame(&mut self, callback: impl FnOnce(Result<image::RgbaImage>) + 'static) {
+        match self.gpu.as_mut() {
+            Some(gpu) => gpu.frame_reads.push(Box::new(callback)),
+            None => callback(Err(anyhow!("No device is available to read back from!"))),
+        }
+    }
+
+    // Focuses depth of field on whatever is under the position once its depth arrives
+    pub fn focus_at(&mut self, position: glm::Vec2) {
+        let focus_distance = self.focus_distance.clone();
+        self.read_depth(position, move |result| match result {
+            Ok(Some(distance)) => focus_distance.set(Some(distance)),
+            Ok(None) => {}
+            Err(error) => eprintln!("Failed to read the focus depth: {}", error),
+        });
+    }
 
+    // Moves the scene's origin to the camera once the camera strays past the rebase distance, which
+    // keeps what's near it precise. The scene stays where it is in the world, only the cameras and
+    // what the GPU kept from the last frame follow the origin. Returns how far the origin moved,
+    // for anything else holding positions relative to it such as a camera transition
+    pub fn update_origin(&mut self, scene: &mut Scene) -> Option<glm::Vec3> {
+        let distance = self.settings.rebase_distance;
+        if distance <= 0.0 || self.is_2d() || self.camera.position.magnitude() < distance {
+            return None;
+        }
+        let offset = self.camera.position;
+        scene.rebase(&offset);
+        self.camera.translate(-offset);
+        for camera in self
+            .viewports
+            .iter_mut()
+            .filter_map(|viewport| viewport.camera.as_mut())
         {
-            let _render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                label: Some("Render Pass"),
-                color_attachments: &[wgpu::RenderPassColorAttachment {
-                    view: &view,
-                    resolve_target: None,
-                    ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(wgpu::Color {
-                            r: 0.1,
-                            g: 0.2,
-                            b: 0.3,
-                            a: 1.0,
-                        }),
-                        store: true,
-                    },
-                }],
-                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
-                    view: &self.depth_texture.view,
-                    depth_ops: Some(wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(1.0),
-                        store: true,
-                    }),
-                    stencil_ops: None,
-                }),
-            });
+            camera.translate(-offset);
+        }
+        if let Some(gpu) = self.gpu.as_mut() {
+            gpu.translate(&offset);
         }
+        Some(offset)
+    }
 
-        self.queue.submit(std::iter::once(encoder.finish()));
-        frame.present();
+    pub fn is_2d(&self) -> bool {
+        self.camera_3d.is_some()
+    }
+
+    // Switches between the 2D camera and the 3D one, which is left where it was
+    pub fn set_2d(&mut self, enabled: bool) {
+        match (enabled, self.camera_3d.take()) {
+            (true, camera_3d) => {
+                self.camera_3d = Some(camera_3d.unwrap_or_else(|| self.camera.clone()))
+            }
+            (false, Some(camera_3d)) => self.camera = camera_3d,
+            (false, None) => {}
+        }
+    }
+
+    // The camera outside of 2D mode, which is the one remembered between sessions
+    pub fn camera_3d(&self) -> &Camera {
+        self.camera_3d.as_ref().unwrap_or(&self.camera)
+    }
+
+    fn apply_camera_2d(&mut self) {
+        if self.is_2d() {
+            self.camera_2d.apply(&mut self.camera, &self.dimensions);
+        }
+    }
+
+    // The closest model triangle under the position in window pixels, found on the CPU so the
+    // result is available immediately
+    pub fn pick(&mut self, scene: &Scene, position: glm::Vec2) -> Option<SceneHit> {
+        self.apply_camera_2d();
+        self.picking_bvh.update(scene);
+        let view_projection = self
+            .camera
+            .projection_matrix(aspect_ratio(&self.dimensions))
+            * self.camera.view_matrix();
+        let far = glm::inverse(&view_projection)
+            * glm::vec4(
+                position.x / self.dimensions[0] as f32 * 2.0 - 1.0,
+                1.0 - position.y / self.dimensions[1] as f32 * 2.0,
+                1.0,
+                1.0,
+            );
+        let ray = Ray {
+            origin: self.camera.position,
+            direction: glm::normalize(&(far.xyz() / far.w - self.camera.position)),
+        };
+        self.picking_bvh.intersect(&ray, f32::MAX, None)
+    }
+
+    // Position is in pixels from the top left corner of the window, size is the font size in pixels
+    pub fn draw_text(&mut self, text: &str, position: glm::Vec2, size: f32, color: glm::Vec4) {
+        self.text.push(Text {
+            text: text.to_string(),
+            position,
+            size,
+            color,
+        });
+    }
+
+    pub fn render(&mut self, scene: &Scene, gui: &GuiFrame) -> Result<()> {
+        let presentable = self.gpu.as_ref().is_some_and(|gpu| gpu.surface.is_some());
+        if self.paused || !presentable {
+            self.text.clear();
+            return Ok(());
+        }
+
+        let frame_time = self.advance_frame();
+        let delta_time = self.frame_step().unwrap_or(frame_time.as_secs_f32());
+        self.apply_camera_2d();
+
+        // Offline recordings wait on the frames before them, so none are lost however slowly
+        // they're written
+        if self.recording.is_recording() && self.settings.recording.offline {
+            if let Some(gpu) = self.gpu.as_mut() {
+                gpu.readbacks.wait(&gpu.device);
+            }
+            self.recording.wait_for_writer();
+        }
+
+        let settings = self.active_settings();
+        let text = self.physical_text();
+        self.text.clear();
+        let gpu = match self.gpu.as_mut() {
+            Some(gpu) => gpu,
+            None => return Ok(()),
+        };
+
+        let viewports = self
+            .viewports
+            .iter()
+            .map(|viewport| {
+                let rectangle = viewport.pixel_rectangle(&self.dimensions);
+                (viewport.view_camera(&self.camera, &rectangle), rectangle)
+            })
+            .collect::<Vec<_>>();
+
+        let frame = FrameContext {
+            camera: &self.camera,
+            camera_2d: self.camera_3d.is_some().then_some(&self.camera_2d),
+            settings: &settings,
+            scene,
+            text: &text,
+            delta_time,
+            index: self.frame_index,
+        };
+
+        // Only the whole window is recorded, not split viewports
+        let recording = if viewports.is_empty() {
+            self.recording.frame_sender()
+        } else {
+            None
+        };
+        match gpu.render_frame(&frame, &viewports, gui, recording) {
+            Ok(cpu_time) => self
+                .budgets
+                .record_cpu(self.frame_index, frame_time, cpu_time),
+            // Recreate the swapchain if lost or no longer matching the window, as can happen
+            // while switching in and out of fullscreen
+            Err(wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated) => {
+                gpu.resize(self.dimensions)
+            }
+            // The system is out of memory, we should probably quit
+            // Err(wgpu::SurfaceError::OutOfMemory) => *control_flow = ControlFlow::Exit,
+            // All other errors should be resolved by the next frame
+            Err(e) => eprintln!("{:?}", e),
+        }
+        for error in gpu
+            .shader_errors
+            .drain(..)
+            .chain(gpu.model_system.drain_shader_errors())
+        {
+            self.shader_errors.push(error);
+        }
+
+        // Time only advances in the main view, so effects aren't simulated once per window
+        for window in self.windows.iter_mut().flatten() {
+            let SecondaryWindow {
+                camera,
+                dimensions,
+                previous_view_projection,
+                surface,
+            } = window;
+            let surface = match surface.as_mut() {
+                Some(surface) => surface,
+                None => continue,
+            };
+            let frame = FrameContext {
+                camera,
+                camera_2d: None,
+                settings: &settings,
+                scene,
+                text: &[],
+                delta_time: 0.0,
+                index: self.frame_index,
+            };
+            std::mem::swap(&mut gpu.previous_view_projection, previous_view_projection);
+            let result = gpu.render_window(&frame, surface);
+            std::mem::swap(&mut gpu.previous_view_projection, previous_view_projection);
+            match result {
+                Ok(()) => {}
+                Err(wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated) => {
+                    surface.resize(&gpu.device, dimensions)
+                }
+                Err(e) => eprintln!("{:?}", e),
+            }
+        }
+
+        if let Some((frame_index, timings)) = gpu
+            .profiler
+            .as_mut()
+            .and_then(|profiler| profiler.collect(&gpu.device))
+        {
+            self.budgets.record_gpu(frame_index, timings);
+        }
+
+        self.memory
+            .check((self.settings.memory_budget as f64 * 1024.0 * 1024.0) as u64);
+
+        if self.settings.dynamic_resolution.enabled {
+            // Waiting on vsync inflates the frame time, so the GPU time is used when measured
+            let gpu_time = self
+                .budgets
+                .gpu_timings()
+                .iter()
+                .map(|pass| pass.duration)
+                .sum::<Duration>();
+            let measured = if gpu_time.is_zero() {
+                frame_time
+            } else {
+                gpu_time
+            };
+            self.resolution_controller.update(
+                &self.settings.dynamic_resolution,
+                measured,
+                frame_time,
+            );
+        }
 
+        gpu.readbacks.poll(&gpu.device);
+        if let Some(distance) = self.focus_distance.take() {
+            self.settings.depth_of_field.focus_distance = distance;
+        }
         Ok(())
     }
 
+    // Renders the next frame into an offscreen target and reads it back instead of presenting it,
+    // for windows that are never shown
+    pub fn render_offscreen(&mut self, scene: &Scene) -> Result<image::RgbaImage> {
+        let delta_time = self.advance_frame().as_secs_f32();
+        self.apply_camera_2d();
+        let settings = self.active_settings();
+        let text = self.physical_text();
+        self.text.clear();
+        let gpu = self
+            .gpu
+            .as_mut()
+            .context("No device is available to render a frame!")?;
+        let frame = FrameContext {
+            camera: &self.camera,
+            camera_2d: self.camera_3d.is_some().then_some(&self.camera_2d),
+            settings: &settings,
+            scene,
+            text: &text,
+            delta_time,
+            index: self.frame_index,
+        };
+        let image = gpu.capture_frame(&frame)?;
+        gpu.readbacks.poll(&gpu.device);
+        Ok(image)
+    }
+
+    // Renders the scene into an offscreen target and reads it back to the CPU
+    pub fn capture_frame(&mut self, scene: &Scene) -> Result<image::RgbaImage> {
+        self.apply_camera_2d();
+        let settings = self.active_settings();
+        let text = self.physical_text();
+        let gpu = self
+            .gpu
+            .as_mut()
+            .context("No device is available to capture a frame!")?;
+        let frame = FrameContext {
+            camera: &self.camera,
+            camera_2d: self.camera_3d.is_some().then_some(&self.camera_2d),
+            settings: &settings,
+            scene,
+            text: &text,
+            delta_time: 0.0,
+            index: self.frame_index,
+        };
+        gpu.capture_frame(&frame)
+    }
+
+    fn advance_frame(&mut self) -> Duration {
+        let now = Instant::now();
+        let frame_time = now - self.last_frame;
+        self.last_frame = now;
+        self.frame_index += 1;
+        frame_time
+    }
+
+    // Screen text is placed in logical pixels and drawn in physical ones
+    fn physical_text(&self) -> Vec<Text> {
+        let scale = self.interface_scale();
+        self.text
+            .iter()
+            .map(|text| Text {
+                text: text.text.clone(),
+                position: text.position * scale,
+                size: text.size * scale,
+                color: text.color,
+            })
+            .collect()
+    }
+
+    // Recordings still being written are finished before exiting
     pub fn cleanup(&mut self) -> Result<()> {
+        self.recording.stop();
+        if let Some(gpu) = self.gpu.as_mut() {
+            gpu.readbacks.wait(&gpu.device);
+            // Anything left can't arrive, and holds a recording open
+            gpu.readbacks = ReadbackQueue::default();
+        }
+        for message in self.recording.finish() {
+            println!("{}", message);
+        }
         Ok(())
     }
 }
+
+struct FrameContext<'a> {
+    camera: &'a Camera,
+    // Places sprites in world units instead of window pixels while in 2D mode
+    camera_2d: Option<&'a Camera2d>,
+    settings: &'a Settings,
+    scene: &'a Scene,
+    text: &'a [Text],
+    delta_time: f32,
+    index: u64,
+}
+
+struct Gpu {
+    // Kept to create surfaces for secondary windows
+    instance: wgpu::Instance,
+    adapter: wgpu::Adapter,
+    // Absent while the platform has taken the window away
+    surface: Option<wgpu::Surface>,
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    config: wgpu::SurfaceConfiguration,
+    adapters: Vec<AdapterDetails>,
+    // The scene is rendered at a scaled resolution and upscaled before or while post processing
+    render_scale: f32,
+    upscaling: Upscaling,
+    render_dimensions: [u32; 2],
+    depth: DepthConfig,
+    // Sampled through its depth only view, and attached with its stencil through
+    // depth_attachment
+    depth_texture: Texture,
+    depth_attachment: wgpu::TextureView,
+    scene_color: Texture,
+    // What the opaque pass draws into before it's resolved into the scene color, when
+    // multisampling
+    multisampled: Option<MultisampledTarget>,
+    // Owns the transient targets sized with the scene, so they can alias each other
+    target_pool: RenderTargetPool,
+    camera_buffer: Tracked<wgpu::Buffer>,
+    camera_bind_group: wgpu::BindGroup,
+    // The camera without jitter, for overlays drawn at the output resolution
+    overlay_camera_buffer: Tracked<wgpu::Buffer>,
+    overlay_camera_bind_group: wgpu::BindGroup,
+    lighting_system: LightingSystem,
+    ray_traced_shadow_system: RayTracedShadowSystem,
+    reflection_probe_system: ReflectionProbeSystem,
+    irradiance_system: IrradianceSystem,
+    area_light_system: AreaLightSystem,
+    blue_noise: BlueNoise,
+    sky_system: SkySystem,
+    environment_system: EnvironmentSystem,
+    ssao_system: SsaoSystem,
+    model_system: ModelSystem,
+    decal_system: DecalSystem,
+    // Systems that only run as compute shaders are left out on devices without them
+    particle_system: Option<ParticleSystem>,
+    point_cloud_system: PointCloudSystem,
+    terrain_system: TerrainSystem,
+    voxel_system: VoxelSystem,
+    isosurface_system: IsosurfaceSystem,
+    water_system: WaterSystem,
+    compute_system: ComputeSystem,
+    path_tracer_system: Option<PathTracerSystem>,
+    bloom_system: BloomSystem,
+    depth_of_field_system: DepthOfFieldSystem,
+    velocity_system: VelocitySystem,
+    motion_blur_system: MotionBlurSystem,
+    temporal_system: TemporalUpscaleSystem,
+    // The previous frame's camera, which velocity is measured against
+    previous_view_projection: Option<glm::Mat4>,
+    // Created once viewports are used or frames are recorded, and the previous camera of each
+    // viewport
+    viewport_target: Option<ViewportTarget>,
+    viewport_history: Vec<Option<glm::Mat4>>,
+    render_camera_system: RenderCameraSystem,
+    exposure_system: ExposureSystem,
+    hiz_system: Option<HiZSystem>,
+    upscale_system: UpscaleSystem,
+    lens_flare_system: Option<LensFlareSystem>,
+    post_process: PostProcess,
+    sharpen_system: SharpenSystem,
+    line_system: LineSystem,
+    sprite_system: SpriteSystem,
+    text_system: TextSystem,
+    gui_pass: GuiPass,
+    // Only present when the device supports timestamp queries
+    profiler: Option<GpuProfiler>,
+    // WebGL2 has no compute shaders, so the features built on them are turned off there
+    compute_supported: bool,
+    // Recorded into the next presented frame
+    compute_reads: Vec<(ComputeResource, ReadbackCallback)>,
+    // Shaders that failed to compile this frame
+    shader_errors: Vec<ShaderError>,
+    // Window positions whose depth is read back after the next presented frame
+    depth_reads: Vec<(glm::Vec2, ReadbackCallback)>,
+    // Handed the next presented frame, only read while it fills the whole window
+    frame_reads: Vec<FrameCallback>,
+    readbacks: ReadbackQueue,
+}
+
+impl Gpu {
+    async fn new(
+        window_handle: &impl HasRawWindowHandle,
+        dimensions: &[u32; 2],
+        backends: wgpu::Backends,
+        depth: DepthConfig,
+        adapter_index: Option<usize>,
+    ) -> Result<Self> {
+        let instance = wgpu::Instance::new(backends);
+
+        let surface = SURFACE_AT_STARTUP.then(|| unsafe { instance.create_surface(window_handle) });
+
+        let (adapter, adapters) =
+            Self::create_adapter(&instance, surface.as_ref(), backends, adapter_index).await?;
+
+        let (device, queue) = Self::request_device(&adapter).await?;
+        let downlevel_flags = adapter.get_downlevel_properties().flags;
+        let compute_supported = downlevel_flags.contains(wgpu::DownlevelFlags::COMPUTE_SHADERS);
+
+        let swapchain_format = match surface.as_ref() {
+            Some(surface) => surface
+                .get_preferred_format(&adapter)
+                .context("Failed to get preferred surface format!")?,
+            None => FALLBACK_SURFACE_FORMAT,
+        };
+
+        let config = wgpu::SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            format: swapchain_format,
+            width: dimensions[0],
+            height: dimensions[1],
+            present_mode: wgpu::PresentMode::Fifo,
+        };
+
+        if let Some(surface) = surface.as_ref() {
+            surface.configure(&device, &config);
+        }
+
+        let depth_texture = Texture::create_depth_texture(
+            &device,
+            dimensions[0],
+            dimensions[1],
+            depth,
+            "Depth Texture",
+        );
+        let depth_attachment = depth_texture.create_attachment_view();
+
+        let scene_color = Texture::create_render_target(
+            &device,
+            dimensions[0],
+            dimensions[1],
+            Texture::HDR_FORMAT,
+            "Scene Color Texture",
+        );
+        let multisampled =
+            MultisampledTarget::new(&device, dimensions, Texture::HDR_FORMAT, depth, "Scene");
+
+        let camera_buffer = memory::create_buffer(
+            &device,
+            MemoryCategory::Transient,
+            &wgpu::BufferDescriptor {
+                label: Some("Camera Uniform Buffer"),
+                size: std::mem::size_of::<CameraUniform>() as wgpu::BufferAddress,
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            },
+        );
+
+        let camera_bind_group_layout = memory::create_bind_group_layout(
+            &device,
+            &wgpu::BindGroupLayoutDescriptor {
+                label: Some("Camera Bind Group Layout"),
+                entries: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX
+                        | wgpu::ShaderStages::FRAGMENT
+                        | wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                }],
+            },
+        );
+
+        let camera_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Camera Bind Group"),
+            layout: &camera_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: camera_buffer.as_entire_binding(),
+            }],
+        });
+
+        let overlay_camera_buffer = memory::create_buffer(
+            &device,
+            MemoryCategory::Transient,
+            &wgpu::BufferDescriptor {
+                label: Some("Overlay Camera Uniform Buffer"),
+                size: std::mem::size_of::<CameraUniform>() as wgpu::BufferAddress,
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            },
+        );
+
+        let overlay_camera_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Overlay Camera Bind Group"),
+            layout: &camera_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: overlay_camera_buffer.as_entire_binding(),
+            }],
+        });
+
+        let decal_system = DecalSystem::new(
+            &device,
+            &camera_bind_group_layout,
+            Texture::HDR_FORMAT,
+            &depth_texture,
+        );
+
+        let particle_system = compute_supported.then(|| {
+            ParticleSystem::new(
+                &device,
+                &camera_bind_group_layout,
+                Texture::HDR_FORMAT,
+                &depth_texture,
+            )
+        });
+
+        let point_cloud_system = PointCloudSystem::new(
+            &device,
+            &camera_bind_group_layout,
+            Texture::HDR_FORMAT,
+            depth,
+        );
+
+        // Transient targets created below alias each other where their lifetimes allow
+        let mut target_pool = RenderTargetPool::default();
+
+        let blue_noise = BlueNoise::new(&device);
+
+        let ssao_system = SsaoSystem::new(
+            &device,
+            &mut target_pool,
+            &camera_bind_group_layout,
+            &depth_texture,
+            &blue_noise,
+            dimensions,
+        );
+
+        let reflection_probe_system = ReflectionProbeSystem::new(
+            &device,
+            &queue,
+            &camera_bind_group_layout,
+            ssao_system.output_bind_group_layout(),
+            depth,
+        );
+
+        let irradiance_system = IrradianceSystem::new(
+            &device,
+            compute_supported,
+            &camera_bind_group_layout,
+            Texture::HDR_FORMAT,
+            depth,
+        );
+
+        let ray_traced_shadow_system = RayTracedShadowSystem::new(
+            &device,
+            &queue,
+            compute_supported,
+            &camera_bind_group_layout,
+            &depth_texture,
+            dimensions,
+        );
+
+        let area_light_system = AreaLightSystem::new(&device);
+        let sky_system = SkySystem::new(
+            &device,
+            &camera_bind_group_layout,
+            Texture::HDR_FORMAT,
+            depth,
+        );
+        let environment_system = EnvironmentSystem::new(
+            &device,
+            &camera_bind_group_layout,
+            Texture::HDR_FORMAT,
+            depth,
+        );
+
+        let lighting_system = LightingSystem::new(
+            &device,
+            depth,
+            &reflection_probe_system,
+            &irradiance_system,
+            &area_light_system,
+            &ray_traced_shadow_system,
+            &blue_noise,
+        );
+
+        let terrain_system = TerrainSystem::new(
+            &device,
+            &camera_bind_group_layout,
+            lighting_system.bind_group_layout(),
+            lighting_system.caster_bind_group_layout(),
+            Texture::HDR_FORMAT,
+            depth,
+        );
+
+        let voxel_system = VoxelSystem::new(
+            &device,
+            &camera_bind_group_layout,
+            lighting_system.bind_group_layout(),
+            lighting_system.caster_bind_group_layout(),
+            Texture::HDR_FORMAT,
+            depth,
+        );
+
+        let isosurface_system = IsosurfaceSystem::new(
+            &device,
+            &camera_bind_group_layout,
+            lighting_system.bind_group_layout(),
+            lighting_system.caster_bind_group_layout(),
+            Texture::HDR_FORMAT,
+            depth,
+        );
+
+        let hiz_system = compute_supported
+            .then(|| HiZSystem::new(&device, &queue, &depth_texture, depth, dimensions));
+
+        // Packed materials are read from storage buffers while shading
+        let packed_materials_supported = downlevel_flags.contains(
+            wgpu::DownlevelFlags::VERTEX_STORAGE | wgpu::DownlevelFlags::FRAGMENT_STORAGE,
+        );
+
+        let model_system = ModelSystem::new(
+            &device,
+            &camera_bind_group_layout,
+            lighting_system.bind_group_layout(),
+            lighting_system.caster_bind_group_layout(),
+            hiz_system.as_ref().map(HiZSystem::bind_group_layout),
+            &mut target_pool,
+            packed_materials_supported,
+            &ssao_system,
+            Texture::HDR_FORMAT,
+            depth,
+            dimensions,
+        );
+
+        let water_system = WaterSystem::new(
+            &device,
+            &mut target_pool,
+            &camera_bind_group_layout,
+            Texture::HDR_FORMAT,
+            &depth_texture,
+            dimensions,
+        );
+
+        let path_tracer_system =
+            compute_supported.then(|| PathTracerSystem::new(&device, dimensions));
+
+        let bloom_system = BloomSystem::new(&device, &mut target_pool, &scene_color, dimensions);
+
+        let depth_of_field_system = DepthOfFieldSystem::new(
+            &device,
+            &mut target_pool,
+            &camera_bind_group_layout,
+            &scene_color,
+            &depth_texture,
+            dimensions,
+        );
+
+        let velocity_system = VelocitySystem::new(
+            &device,
+            &camera_bind_group_layout,
+            &depth_texture,
+            dimensions,
+        );
+
+        let motion_blur_system = MotionBlurSystem::new(
+            &device,
+            &mut target_pool,
+            &scene_color,
+            velocity_system.texture(),
+            dimensions,
+        );
+
+        let temporal_system =
+            TemporalUpscaleSystem::new(&device, &scene_color, velocity_system.texture());
+
+        let exposure_system =
+            ExposureSystem::new(&device, compute_supported, &scene_color, dimensions);
+
+        let lens_flare_system =
+            compute_supported.then(|| LensFlareSystem::new(&device, &depth_texture, depth));
+
+        let upscale_system = UpscaleSystem::new(&device, &mut target_pool, &scene_color);
+
+        let post_process = PostProcess::new(
+            &device,
+            &camera_bind_group_layout,
+            lighting_system.bind_group_layout(),
+            swapchain_format,
+            &scene_color,
+            &depth_texture,
+            bloom_system.texture(),
+            exposure_system.adapted_buffer(),
+        );
+
+        let sharpen_system =
+            SharpenSystem::new(&device, swapchain_format, &[config.width, config.height]);
+
+        let render_camera_system = RenderCameraSystem::new(&device, swapchain_format);
+
+        let line_system = LineSystem::new(
+            &device,
+            &camera_bind_group_layout,
+            swapchain_format,
+            &depth_texture,
+        );
+
+        let sprite_system = SpriteSystem::new(&device, &queue, swapchain_format)?;
+
+        let text_system = TextSystem::new(
+            &device,
+            &camera_bind_group_layout,
+            swapchain_format,
+            &depth_texture,
+        )?;
+
+        let gui_pass = GuiPass::new(&device, swapchain_format);
+
+        let profiler = GpuProfiler::new(&device, &queue);
+
+        Ok(Self {
+            instance,
+            adapter,
+            surface,
+            device,
+            queue,
+            config,
+            adapters,
+            render_scale: 1.0,
+            upscaling: Upscaling::Bilinear,
+            render_dimensions: *dimensions,
+            depth,
+            depth_texture,
+            depth_attachment,
+            scene_color,
+            multisampled,
+            target_pool,
+            camera_buffer,
+            camera_bind_group,
+            overlay_camera_buffer,
+            overlay_camera_bind_group,
+            lighting_system,
+            ray_traced_shadow_system,
+            reflection_probe_system,
+            irradiance_system,
+            area_light_system,
+            blue_noise,
+            sky_system,
+            environment_system,
+            ssao_system,
+            model_system,
+            decal_system,
+            particle_system,
+            point_cloud_system,
+            terrain_system,
+            voxel_system,
+            isosurface_system,
+            water_system,
+            compute_system: ComputeSystem::default(),
+            path_tracer_system,
+            bloom_system,
+            depth_of_field_system,
+            velocity_system,
+            motion_blur_system,
+            temporal_system,
+            previous_view_projection: None,
+            viewport_target: None,
+            viewport_history: Vec::new(),
+            render_camera_system,
+            exposure_system,
+            hiz_system,
+            upscale_system,
+            lens_flare_system,
+            post_process,
+            sharpen_system,
+            line_system,
+            sprite_system,
+            text_system,
+            gui_pass,
+            profiler,
+            compute_supported,
+            compute_reads: Vec::new(),
+            shader_errors: Vec::new(),
+            depth_reads: Vec::new(),
+            frame_reads: Vec::new(),
+            readbacks: ReadbackQueue::default(),
+        })
+    }
+
+    // Picks the requested adapter, or lets wgpu choose when none is given
+    async fn create_adapter(
+        instance: &wgpu::Instance,
+        surface: Option<&wgpu::Surface>,
+        backends: wgpu::Backends,
+        adapter_index: Option<usize>,
+    ) -> Result<(wgpu::Adapter, Vec<AdapterDetails>)> {
+        #[cfg(not(target_arch = "wasm32"))]
+        let mut available = instance.enumerate_adapters(backends).collect::<Vec<_>>();
+
+        // Browsers only expose the adapter they choose
+        #[cfg(target_arch = "wasm32")]
+        let mut available: Vec<wgpu::Adapter> = {
+            let _ = backends;
+            Vec::new()
+        };
+
+        let adapter = match adapter_index {
+            Some(index) if index < available.len() => available.remove(index),
+            Some(index) => bail!("No adapter exists at index {}!", index),
+            None => instance
+                .request_adapter(&wgpu::RequestAdapterOptions {
+                    power_preference: wgpu::PowerPreference::default(),
+                    compatible_surface: surface,
+                    force_fallback_adapter: false,
+                })
+                .await
+                .context("Failed to request a GPU adapter!")?,
+        };
+
+        let selected = adapter.get_info();
+        let mut adapters = available
+            .iter()
+            .map(|candidate| {
+                let active = candidate.get_info() == selected;
+                AdapterDetails::new(candidate, surface, active)
+            })
+            .collect::<Vec<_>>();
+        match adapter_index {
+            Some(index) => adapters.insert(index, AdapterDetails::new(&adapter, surface, true)),
+            None if !adapters.iter().any(|details| details.active) => {
+                adapters.push(AdapterDetails::new(&adapter, surface, true))
+            }
+            None => {}
+        }
+
+        Ok((adapter, adapters))
+    }
+
+    async fn request_device(adapter: &wgpu::Adapter) -> Result<(wgpu::Device, wgpu::Queue)> {
+        // Timestamps are used for profiling and push constants for per-draw data when available
+        let features =
+            adapter.features() & (wgpu::Features::TIMESTAMP_QUERY | wgpu::Features::PUSH_CONSTANTS);
+        // WebGL2 and mobile GPUs can fall short of the default limits
+        let mut limits = if cfg!(feature = "webgl") {
+            wgpu::Limits::downlevel_webgl2_defaults()
+        } else if cfg!(any(target_os = "android", target_os = "ios")) {
+            wgpu::Limits::downlevel_defaults()
+        } else {
+            wgpu::Limits::default()
+        };
+        if features.contains(wgpu::Features::PUSH_CONSTANTS) {
+            limits.max_push_constant_size = adapter.limits().max_push_constant_size;
+        }
+        let (device, queue) = adapter
+            .request_device(
+                &wgpu::DeviceDescriptor {
+                    features,
+                    limits,
+                    label: Some("Device"),
+                },
+                None,
+            )
+            .await
+            .context("Failed to request a device!")?;
+        Ok((device, queue))
+    }
+
+    // The surface is configured with the current size and format once it is created
+    fn create_surface(&mut self, window_handle: &impl HasRawWindowHandle) {
+        let surface = unsafe { self.instance.create_surface(window_handle) };
+        surface.configure(&self.device, &self.config);
+        self.surface = Some(surface);
+    }
+
+    fn resize(&mut self, dimensions: [u32; 2]) {
+        self.config.width = dimensions[0];
+        self.config.height = dimensions[1];
+        if let Some(surface) = self.surface.as_ref() {
+            surface.configure(&self.device, &self.config);
+        }
+        self.sharpen_system
+            .resize(&self.device, self.config.format, &dimensions);
+        self.viewport_target = None;
+        self.resize_render_targets();
+    }
+
+    fn resize_render_targets(&mut self) {
+        let dimensions = [
+            ((self.config.width as f32 * self.render_scale).round() as u32).max(1),
+            ((self.config.height as f32 * self.render_scale).round() as u32).max(1),
+        ];
+        self.render_dimensions = dimensions;
+        self.target_pool.clear();
+        self.depth_texture = Texture::create_depth_texture(
+            &self.device,
+            dimensions[0],
+            dimensions[1],
+            self.depth,
+            "Depth Texture",
+        );
+        self.depth_attachment = self.depth_texture.create_attachment_view();
+        self.scene_color = Texture::create_render_target(
+            &self.device,
+            dimensions[0],
+            dimensions[1],
+            Texture::HDR_FORMAT,
+            "Scene Color Texture",
+        );
+        self.multisampled = MultisampledTarget::new(
+            &self.device,
+            &dimensions,
+            Texture::HDR_FORMAT,
+            self.depth,
+            "Scene",
+        );
+        self.ssao_system.resize(
+            &self.device,
+            &mut self.target_pool,
+            &self.depth_texture,
+            &self.blue_noise,
+            &dimensions,
+        );
+        if let Some(hiz_system) = self.hiz_system.as_mut() {
+            hiz_system.resize(&self.device, &self.queue, &self.depth_texture, &dimensions);
+        }
+        self.model_system.resize(
+            &self.device,
+            &mut self.target_pool,
+            Texture::HDR_FORMAT,
+            self.ssao_system.texture(),
+            &dimensions,
+        );
+        self.decal_system.resize(&self.device, &self.depth_texture);
+        self.line_system.resize(&self.device, &self.depth_texture);
+        self.text_system.resize(&self.device, &self.depth_texture);
+        if let Some(particle_system) = self.particle_system.as_mut() {
+            particle_system.resize(&self.device, &self.depth_texture);
+        }
+        self.water_system.resize(
+            &self.device,
+            &mut self.target_pool,
+            Texture::HDR_FORMAT,
+            &self.depth_texture,
+            &dimensions,
+        );
+        if let Some(path_tracer_system) = self.path_tracer_system.as_mut() {
+            path_tracer_system.resize(&self.device, &dimensions);
+        }
+        self.ray_traced_shadow_system
+            .resize(&self.device, &self.depth_texture, &dimensions);
+        self.lighting_system.resize(
+            &self.device,
+            &self.reflection_probe_system,
+            &self.irradiance_system,
+            &self.area_light_system,
+            &self.ray_traced_shadow_system,
+            &self.blue_noise,
+        );
+        self.bloom_system.resize(
+            &self.device,
+            &mut self.target_pool,
+            &self.scene_color,
+            &dimensions,
+        );
+        self.depth_of_field_system.resize(
+            &self.device,
+            &mut self.target_pool,
+            &self.scene_color,
+            &self.depth_texture,
+            &dimensions,
+        );
+        self.velocity_system
+            .resize(&self.device, &self.depth_texture, &dimensions);
+        self.motion_blur_system.resize(
+            &self.device,
+            &mut self.target_pool,
+            &self.scene_color,
+            self.velocity_system.texture(),
+            &dimensions,
+        );
+        self.exposure_system
+            .resize(&self.device, &self.scene_color, &dimensions);
+        if let Some(lens_flare_system) = self.lens_flare_system.as_mut() {
+            lens_flare_system.resize(&self.device, &self.depth_texture);
+        }
+        let output_dimensions = [self.config.width, self.config.height];
+        self.temporal_system.resize(
+            &self.device,
+            &self.scene_color,
+            self.velocity_system.texture(),
+            &output_dimensions,
+            self.upscaling == Upscaling::Temporal,
+        );
+        self.upscale_system.resize(
+            &self.device,
+            &self.queue,
+            &mut self.target_pool,
+            &self.scene_color,
+            &dimensions,
+            &output_dimensions,
+            self.upscaling == Upscaling::Fsr,
+        );
+        let post_process_input = if self.temporal_system.enabled() {
+            self.temporal_system.texture()
+        } else {
+            self.upscale_system.texture(&self.scene_color)
+        };
+        self.post_process.resize(
+            &self.device,
+            post_process_input,
+            &self.depth_texture,
+            self.bloom_system.texture(),
+            self.exposure_system.adapted_buffer(),
+        );
+    }
+
+    fn render_frame(
+        &mut self,
+        frame_context: &FrameContext,
+        viewports: &[(Camera, [u32; 4])],
+        gui: &GuiFrame,
+        recording: Option<mpsc::Sender<RecordedFrame>>,
+    ) -> Result<Duration, wgpu::SurfaceError> {
+        let present_mode = if frame_context.settings.vsync {
+            wgpu::PresentMode::Fifo
+        } else {
+            wgpu::PresentMode::Immediate
+        };
+        if present_mode != self.config.present_mode {
+            self.config.present_mode = present_mode;
+            if let Some(surface) = self.surface.as_ref() {
+                surface.configure(&self.device, &self.config);
+            }
+        }
+
+        let frame = match self.surface.as_ref() {
+            Some(surface) => surface.get_current_texture()?,
+            None => return Err(wgpu::SurfaceError::Lost),
+        };
+
+        // Waiting on the surface is left out of the time spent recording the frame
+        let start = Instant::now();
+
+        let view = frame
+            .texture
+            .create_view(&wgpu::TextureViewDescriptor::default());
+
+        // Render cameras go first so materials show what they see this frame
+        self.render_cameras(frame_context);
+
+        // Viewports are submitted ahead of the frame's own encoder, which draws the interface
+        if !viewports.is_empty() {
+            self.render_viewports(&view, frame_context, viewports);
+        }
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Render Encoder"),
+            });
+
+        if let Some(profiler) = self.profiler.as_mut() {
+            profiler.begin_frame(&mut encoder, frame_context.index);
+        }
+
+        // Recorded and read frames are drawn into a target that can be copied, then shown from it
+        if viewports.is_empty() {
+            let frame_reads = std::mem::take(&mut self.frame_reads);
+            if recording.is_some() || !frame_reads.is_empty() {
+                self.read_back_frame(&mut encoder, &view, frame_context, recording, frame_reads);
+            } else {
+                self.encode_frame(&mut encoder, &view, frame_context);
+            }
+        } else {
+            for callback in self.frame_reads.drain(..) {
+                callback(Err(anyhow!(
+                    "Frames split into viewports aren't read back!"
+                )));
+            }
+        }
+
+        // Copied after the frame's dispatches so reads see their results
+        for (resource, callback) in std::mem::take(&mut self.compute_reads) {
+            match self.compute_system.readback(
+                &self.device,
+                &mut encoder,
+                frame_context.scene,
+                resource,
+            ) {
+                Ok(readback) => self.readbacks.push(readback, callback),
+                Err(error) => callback(Err(error)),
+            }
+        }
+
+        // The scene may be rendered at a different resolution than the window. Depth with a
+        // stencil can't be copied, so it's read from the first level of the Hi-Z pyramid
+        let [width, height] = self.render_dimensions;
+        let depth_source = if self.depth.has_stencil() {
+            self.hiz_system.as_ref().map(|hiz_system| {
+                if !self.depth_reads.is_empty() {
+                    hiz_system.copy_depth(&mut encoder);
+                }
+                (hiz_system.texture(), wgpu::TextureAspect::All)
+            })
+        } else {
+            Some((&*self.depth_texture.texture, wgpu::TextureAspect::DepthOnly))
+        };
+        for (position, callback) in std::mem::take(&mut self.depth_reads) {
+            let (depth_source, depth_aspect) = match depth_source {
+                Some(depth_source) => depth_source,
+                None => {
+                    callback(Err(anyhow!(
+                        "Depth with a stencil can't be read back without compute shaders!"
+                    )));
+                    continue;
+                }
+            };
+            let x = position.x * width as f32 / self.config.width as f32;
+            let y = position.y * height as f32 / self.config.height as f32;
+            let readback = Readback::from_texture_region(
+                &self.device,
+                &mut encoder,
+                depth_source,
+                wgpu::Origin3d {
+                    x: (x.max(0.0) as u32).min(width - 1),
+                    y: (y.max(0.0) as u32).min(height - 1),
+                    z: 0,
+                },
+                depth_aspect,
+                TextureLayout::new(1, 1, 4),
+            );
+            self.readbacks.push(readback, callback);
+        }
+
+        self.begin_pass(&mut encoder, "Interface");
+        // The interface is drawn straight onto the surface so captured frames leave it out
+        if let Err(error) = self.gui_pass.render(
+            &self.device,
+            &self.queue,
+            &mut encoder,
+            &view,
+            gui,
+            [self.config.width, self.config.height],
+        ) {
+            eprintln!("Failed to render the interface: {}", error);
+        }
+        self.end_pass(&mut encoder, "Interface");
+
+        if let Some(profiler) = self.profiler.as_mut() {
+            profiler.end_frame(&mut encoder);
+        }
+
+        self.queue.submit(std::iter::once(encoder.finish()));
+
+        if let Some(profiler) = self.profiler.as_mut() {
+            profiler.after_submit();
+        }
+        self.readbacks.after_submit();
+
+        let cpu_time = start.elapsed();
+        frame.present();
+
+        Ok(cpu_time)
+    }
+
+    fn read_back_frame(
+        &mut self,
+        encoder: &mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
+        frame_context: &FrameContext,
+        recording: Option<mpsc::Sender<RecordedFrame>>,
+        frame_reads: Vec<FrameCallback>,
+    ) {
+        let [width, height] = [self.config.width, self.config.height];
+        let target = match self.viewport_target.take() {
+            Some(target) => target,
+            None => ViewportTarget::new(&self.device, self.config.format, &[width, height]),
+        };
+        self.encode_frame(encoder, target.view(), frame_context);
+        target.blit(encoder, view, [0, 0, width, height], true);
+        let readback = Readback::from_texture(
+            &self.device,
+            encoder,
+            target.texture(),
+            TextureLayout::new(width, height, 4),
+        );
+        let format = self.config.format;
+        let bgra = matches!(
+            format,
+            wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb
+        );
+        self.readbacks.push(
+            readback,
+            Box::new(move |result| {
+                let pixels = match result {
+                    Ok(pixels) => pixels,
+                    Err(error) => {
+                        if recording.is_some() {
+                            eprintln!("Failed to read back a recorded frame: {}", error);
+                        }
+                        for callback in frame_reads {
+                            callback(Err(anyhow!("Failed to read back the frame: {}", error)));
+                        }
+                        return;
+                    }
+                };
+                if !frame_reads.is_empty() {
+                    let image = readback::frame_image(width, height, pixels.clone(), format);
+                    for callback in frame_reads {
+                        callback(match image.as_ref() {
+                            Ok(image) => Ok(image.clone()),
+                            Err(error) => Err(anyhow!("{}", error)),
+                        });
+                    }
+                }
+                // The recording was abandoned if the writer is gone
+                if let Some(sender) = recording {
+                    let _ = sender.send(RecordedFrame {
+                        width,
+                        height,
+                        pixels,
+                        bgra,
+                    });
+                }
+            }),
+        );
+        self.viewport_target = Some(target);
+    }
+
+    // Renders the opaque scene from a probe into one of its cube faces
+    fn render_probe_face(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        face: &ProbeFace,
+        settings: &Settings,
+    ) {
+        let probe_frustum = face.camera.frustum(1.0);
+        // Multisampled faces write depth of their own
+        if face.multisampled.is_none() {
+            let mut depth_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Probe Depth Pass"),
+                color_attachments: &[],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: face.depth,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(self.depth.far()),
+                        store: true,
+                    }),
+                    stencil_ops: self.stencil_ops(wgpu::LoadOp::Clear(0)),
+                }),
+            });
+            // Models are drawn against the depth of a prepass
+            self.model_system.render_depth(
+                &mut depth_pass,
+                face.camera_bind_group,
+                &face.camera,
+                &probe_frustum,
+            );
+        }
+
+        let mut probe_pass = self.begin_opaque_pass(
+            encoder,
+            "Probe Pass",
+            face.color,
+            face.depth,
+            face.multisampled,
+            settings,
+        );
+        self.terrain_system.render(
+            &mut probe_pass,
+            face.camera_bind_group,
+            self.lighting_system.capture_bind_group(),
+            &face.camera,
+            &probe_frustum,
+            settings.effect_quality,
+        );
+        self.voxel_system.render(
+            &mut probe_pass,
+            face.camera_bind_group,
+            self.lighting_system.capture_bind_group(),
+            &probe_frustum,
+        );
+        self.isosurface_system.render(
+            &mut probe_pass,
+            face.camera_bind_group,
+            self.lighting_system.capture_bind_group(),
+            &probe_frustum,
+        );
+        self.model_system.render(
+            &mut probe_pass,
+            face.camera_bind_group,
+            self.lighting_system.capture_bind_group(),
+            self.reflection_probe_system.ambient_occlusion_bind_group(),
+            &face.camera,
+            &probe_frustum,
+        );
+        self.render_background(&mut probe_pass, face.camera_bind_group);
+    }
+
+    // Opaque surfaces and the sky are drawn over the depth prepass, or into the multisampled
+    // target with depth of their own before being resolved into the color
+    fn begin_opaque_pass<'a>(
+        &self,
+        encoder: &'a mut wgpu::CommandEncoder,
+        label: &'a str,
+        color: &'a wgpu::TextureView,
+        depth: &'a wgpu::TextureView,
+        multisampled: Option<&'a MultisampledTarget>,
+        settings: &Settings,
+    ) -> wgpu::RenderPass<'a> {
+        let (view, resolve_target, depth_view, depth_load, stencil_load) = match multisampled {
+            Some(target) => (
+                &target.color_view,
+                Some(color),
+                &target.depth_view,
+                wgpu::LoadOp::Clear(self.depth.far()),
+                wgpu::LoadOp::Clear(0),
+            ),
+            None => (color, None, depth, wgpu::LoadOp::Load, wgpu::LoadOp::Load),
+        };
+        encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some(label),
+            color_attachments: &[wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color {
+                        r: settings.clear_color.x as f64,
+                        g: settings.clear_color.y as f64,
+                        b: settings.clear_color.z as f64,
+                        a: 1.0,
+                    }),
+                    store: true,
+                },
+            }],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: depth_view,
+                depth_ops: Some(wgpu::Operations {
+                    load: depth_load,
+                    store: true,
+                }),
+                stencil_ops: self.stencil_ops(stencil_load),
+            }),
+        })
+    }
+
+    // The environment takes the place of the sky while one is open
+    fn render_background<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        camera_bind_group: &'a wgpu::BindGroup,
+    ) {
+        if self.environment_system.enabled() {
+            self.environment_system
+                .render(render_pass, camera_bind_group);
+        } else {
+            self.sky_system.render(render_pass, camera_bind_group);
+        }
+    }
+
+    fn scene_bounds(&self) -> Aabb {
+        let mut bounds = self.terrain_system.bounds();
+        bounds.merge(&self.voxel_system.bounds());
+        bounds.merge(&self.isosurface_system.bounds());
+        bounds.merge(&self.model_system.bounds());
+        bounds
+    }
+
+    // Lines what was rendered last frame up with the scene after its origin moves
+    fn translate(&mut self, offset: &glm::Vec3) {
+        let translation = glm::translation(offset);
+        for view_projection in self
+            .previous_view_projection
+            .iter_mut()
+            .chain(self.viewport_history.iter_mut().flatten())
+        {
+            *view_projection *= translation;
+        }
+        if let Some(particle_system) = self.particle_system.as_mut() {
+            particle_system.translate(&-offset);
+        }
+    }
+
+    // Passes on a depth buffer without a stencil can't have stencil operations
+    fn stencil_ops(&self, load: wgpu::LoadOp<u32>) -> Option<wgpu::Operations<u32>> {
+        self.depth
+            .has_stencil()
+            .then_some(wgpu::Operations { load, store: true })
+    }
+
+    // Passes are grouped under their label so frame captures read like the frame
+    fn begin_pass(&mut self, encoder: &mut wgpu::CommandEncoder, label: &'static str) {
+        encoder.push_debug_group(label);
+    }
+
+    // Timestamps are only written while the profiler is recording a presented frame
+    fn end_pass(&mut self, encoder: &mut wgpu::CommandEncoder, label: &'static str) {
+        encoder.pop_debug_group();
+        if let Some(profiler) = self.profiler.as_mut() {
+            profiler.end_pass(encoder, label);
+        }
+    }
+
+    // Each render camera due for an update goes through the whole frame at the window's
+    // resolution like a viewport, then is scaled into its own texture
+    fn render_cameras(&mut self, frame_context: &FrameContext) {
+        let scene = frame_context.scene;
+        let cameras =
+            self.render_camera_system
+                .due(scene, frame_context.camera, frame_context.index);
+        if cameras.is_empty() {
+            return;
+        }
+
+        let source = self
+            .render_camera_system
+            .take_source(&self.device, &[self.config.width, self.config.height]);
+        let profiler = self.profiler.take();
+        let main_history = self.previous_view_projection.take();
+
+        for (index, camera) in cameras {
+            let camera_frame = FrameContext {
+                camera: &camera,
+                delta_time: 0.0,
+                ..*frame_context
+            };
+            self.previous_view_projection =
+                self.render_camera_system.previous_view_projection(index);
+
+            let mut encoder = self
+                .device
+                .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                    label: Some("Render Camera Encoder"),
+                });
+            encoder.push_debug_group(&format!("Render Camera {}", index));
+            self.encode_frame(&mut encoder, source.view(), &camera_frame);
+            encoder.pop_debug_group();
+            self.render_camera_system.finish(
+                &mut encoder,
+                &source,
+                scene,
+                index,
+                frame_context.index,
+                self.previous_view_projection,
+            );
+            self.queue.submit(std::iter::once(encoder.finish()));
+        }
+
+        self.previous_view_projection = main_history;
+        self.profiler = profiler;
+        self.render_camera_system.restore_source(source);
+    }
+
+    // Each viewport goes through the whole frame with its own camera at the window's resolution,
+    // then is copied into its rectangle. They are submitted one at a time since the frame's
+    // uniforms are written through the queue, and their passes aren't profiled
+    fn render_viewports(
+        &mut self,
+        view: &wgpu::TextureView,
+        frame_context: &FrameContext,
+        viewports: &[(Camera, [u32; 4])],
+    ) {
+        let target = match self.viewport_target.take() {
+            Some(target) => target,
+            None => ViewportTarget::new(
+                &self.device,
+                self.config.format,
+                &[self.config.width, self.config.height],
+            ),
+        };
+        let profiler = self.profiler.take();
+        let main_history = self.previous_view_projection.take();
+        self.viewport_history.resize(viewports.len(), None);
+
+        for (index, (camera, rectangle)) in viewports.iter().enumerate() {
+            // Time only advances once per frame, so effects aren't simulated per viewport
+            let viewport_frame = FrameContext {
+                camera,
+                delta_time: if index == 0 {
+                    frame_context.delta_time
+                } else {
+                    0.0
+                },
+                ..*frame_context
+            };
+            self.previous_view_projection = self.viewport_history[index];
+
+            let mut encoder = self
+                .device
+                .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                    label: Some("Viewport Encoder"),
+                });
+            encoder.push_debug_group(&format!("Viewport {}", index + 1));
+            self.encode_frame(&mut encoder, target.view(), &viewport_frame);
+            encoder.pop_debug_group();
+            target.blit(&mut encoder, view, *rectangle, index == 0);
+            self.queue.submit(std::iter::once(encoder.finish()));
+
+            self.viewport_history[index] = self.previous_view_projection;
+        }
+
+        self.previous_view_projection = main_history;
+        self.profiler = profiler;
+        self.viewport_target = Some(target);
+    }
+
+    // Renders the scene at the main view's resolution and fits it into the window
+    fn render_window(
+        &mut self,
+        frame_context: &FrameContext,
+        surface: &mut WindowSurface,
+    ) -> Result<(), wgpu::SurfaceError> {
+        let frame = surface.current_texture()?;
+        let view = frame
+            .texture
+            .create_view(&wgpu::TextureViewDescriptor::default());
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Window Encoder"),
+            });
+
+        let target = surface.prepare_target(
+            &self.device,
+            &self.queue,
+            self.config.format,
+            &[self.config.width, self.config.height],
+        );
+        self.encode_frame(&mut encoder, target, frame_context);
+        surface.blit(&mut encoder, &view);
+
+        self.queue.submit(std::iter::once(encoder.finish()));
+        frame.present();
+        Ok(())
+    }
+
+    fn capture_frame(&mut self, frame_context: &FrameContext) -> Result<image::RgbaImage> {
+        let [width, height] = [self.config.width, self.config.height];
+        let size = wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        };
+        let texture = memory::create_texture(
+            &self.device,
+            MemoryCategory::Targets,
+            &wgpu::TextureDescriptor {
+                label: Some("Capture Texture"),
+                size,
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: self.config.format,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            },
+        );
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Capture Encoder"),
+            });
+
+        self.encode_frame(&mut encoder, &view, frame_context);
+
+        let readback = Readback::from_texture(
+            &self.device,
+            &mut encoder,
+            &texture,
+            TextureLayout::new(width, height, 4),
+        );
+
+        self.queue.submit(std::iter::once(encoder.finish()));
+
+        let pixels = readback.wait(&self.device)?;
+        readback::frame_image(width, height, pixels, self.config.format)
+    }
+
+    // Rasterizes the scene into the scene color, after the depth prepass
+    fn render_scene(
+        &mut self,
+        encoder: &mut wgpu::CommandEncoder,
+        frame_context: &FrameContext,
+        frustum: &Frustum,
+    ) {
+        let FrameContext {
+            camera,
+            settings,
+            scene,
+            ..
+        } = *frame_context;
+        let dimensions = self.render_dimensions;
+
+        self.begin_pass(encoder, "Render Pass");
+        {
+            let mut render_pass = self.begin_opaque_pass(
+                encoder,
+                "Render Pass",
+                &self.scene_color.view,
+                &self.depth_attachment,
+                self.multisampled.as_ref(),
+                settings,
+            );
+            self.terrain_system.render(
+                &mut render_pass,
+                &self.camera_bind_group,
+                self.lighting_system.bind_group(),
+                camera,
+                frustum,
+                settings.effect_quality,
+            );
+            self.voxel_system.render(
+                &mut render_pass,
+                &self.camera_bind_group,
+                self.lighting_system.bind_group(),
+                frustum,
+            );
+            self.isosurface_system.render(
+                &mut render_pass,
+                &self.camera_bind_group,
+                self.lighting_system.bind_group(),
+                frustum,
+            );
+            self.model_system.render(
+                &mut render_pass,
+                &self.camera_bind_group,
+                self.lighting_system.bind_group(),
+                self.ssao_system.output_bind_group(),
+                camera,
+                frustum,
+            );
+            self.point_cloud_system.render(
+                &mut render_pass,
+                &self.camera_bind_group,
+                scene,
+                frustum,
+            );
+            self.render_background(&mut render_pass, &self.camera_bind_group);
+            if settings.irradiance_probes {
+                self.irradiance_system
+                    .render_debug(&mut render_pass, &self.camera_bind_group);
+            }
+        }
+        self.end_pass(encoder, "Render Pass");
+
+        self.begin_pass(encoder, "Decal Pass");
+        {
+            let mut decal_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Decal Pass"),
+                color_attachments: &[wgpu::RenderPassColorAttachment {
+                    view: &self.scene_color.view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: true,
+                    },
+                }],
+                depth_stencil_attachment: None,
+            });
+            self.decal_system
+                .render(&mut decal_pass, &self.camera_bind_group, scene);
+        }
+        self.end_pass(encoder, "Decal Pass");
+
+        self.begin_pass(encoder, "Transparent Pass");
+        self.model_system
+            .copy_scene_color(encoder, &self.scene_color, &dimensions);
+
+        {
+            let mut transparent_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Transparent Pass"),
+                color_attachments: &[wgpu::RenderPassColorAttachment {
+                    view: &self.scene_color.view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: true,
+                    },
+                }],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: &self.depth_attachment,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: true,
+                    }),
+                    stencil_ops: None,
+                }),
+            });
+            self.model_system.render_transparent(
+                &mut transparent_pass,
+                &self.camera_bind_group,
+                self.lighting_system.bind_group(),
+                camera,
+                frustum,
+            );
+        }
+        self.end_pass(encoder, "Transparent Pass");
+
+        self.begin_pass(encoder, "Water Pass");
+        self.water_system
+            .copy_scene_color(encoder, &self.scene_color, &dimensions, scene);
+
+        {
+            let mut water_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Water Pass"),
+                color_attachments: &[wgpu::RenderPassColorAttachment {
+                    view: &self.scene_color.view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: true,
+                    },
+                }],
+                depth_stencil_attachment: None,
+            });
+            self.water_system
+                .render(&mut water_pass, &self.camera_bind_group, scene);
+        }
+        self.end_pass(encoder, "Water Pass");
+
+        self.begin_pass(encoder, "Particle Pass");
+        if let Some(particle_system) = self.particle_system.as_ref() {
+            let mut particle_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Particle Pass"),
+                color_attachments: &[wgpu::RenderPassColorAttachment {
+                    view: &self.scene_color.view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: true,
+                    },
+                }],
+                depth_stencil_attachment: None,
+            });
+            particle_system.render(&mut particle_pass, &self.camera_bind_group, scene);
+        }
+        self.end_pass(encoder, "Particle Pass");
+    }
+
+    fn encode_frame(
+        &mut self,
+        encoder: &mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
+        frame_context: &FrameContext,
+    ) {
+        let FrameContext {
+            camera,
+            settings,
+            scene,
+            text,
+            delta_time,
+            ..
+        } = *frame_context;
+
+        if settings.render_scale != self.render_scale || settings.upscaling != self.upscaling {
+            self.render_scale = settings.render_scale;
+            self.upscaling = settings.upscaling;
+            self.resize_render_targets();
+        }
+        let dimensions = self.render_dimensions;
+
+        let temporal = self.upscaling == Upscaling::Temporal;
+        let jitter = if temporal {
+            temporal::jitter(frame_context.index)
+        } else {
+            glm::Vec2::zeros()
+        };
+        let mut camera_uniform = CameraUniform::new(camera, &dimensions, self.depth);
+        let view_projection = glm::Mat4::from(camera_uniform.previous_view_projection);
+        self.queue.write_buffer(
+            &self.overlay_camera_buffer,
+            0,
+            bytemuck::cast_slice(&[camera_uniform]),
+        );
+        if temporal {
+            camera_uniform = camera_uniform.jittered(jitter, &dimensions);
+        }
+        if let Some(previous_view_projection) = self.previous_view_projection {
+            camera_uniform.previous_view_projection = previous_view_projection.into();
+        }
+        self.previous_view_projection = Some(view_projection);
+        self.queue.write_buffer(
+            &self.camera_buffer,
+            0,
+            bytemuck::cast_slice(&[camera_uniform]),
+        );
+
+        self.post_process
+            .update(&self.queue, settings, camera, frame_context.index);
+        self.sharpen_system.update(&self.queue, settings);
+        self.bloom_system.update(&self.queue, settings);
+        self.depth_of_field_system.update(&self.queue, settings);
+        self.motion_blur_system.update(&self.queue, settings);
+        self.temporal_system.update(&self.queue, jitter);
+        self.blue_noise
+            .update(&self.queue, frame_context.index, temporal);
+        self.exposure_system
+            .update(&self.queue, settings, delta_time);
+        if let Some(lens_flare_system) = self.lens_flare_system.as_mut() {
+            lens_flare_system.update(
+                &self.device,
+                &self.queue,
+                scene,
+                camera,
+                &dimensions,
+                delta_time,
+                settings.lens_flares,
+            );
+        }
+        self.ssao_system.update(&self.queue, settings);
+
+        if let Err(error) = self.terrain_system.update(&self.device, &self.queue, scene) {
+            eprintln!("Failed to update terrain: {}", error);
+        }
+
+        if let Err(error) = self.voxel_system.update(&self.device, &self.queue, scene) {
+            eprintln!("Failed to update voxels: {}", error);
+        }
+
+        self.isosurface_system
+            .update(&self.device, &self.queue, scene);
+
+        self.render_camera_system.update(&self.device, scene);
+        if let Err(error) = self.model_system.update(
+            &self.device,
+            &self.queue,
+            scene,
+            &self.render_camera_system.textures(),
+            settings,
+        ) {
+            eprintln!("Failed to update models: {}", error);
+        }
+
+        self.point_cloud_system
+            .update(&self.device, &self.queue, scene);
+
+        let shadow_casters = self.scene_bounds();
+        self.lighting_system.update(
+            &self.queue,
+            &scene.sun,
+            &scene.punctual_lights(),
+            &shadow_casters,
+            camera,
+            &camera.frustum(aspect_ratio(&dimensions)),
+        );
+        self.area_light_system.update(&self.queue, scene);
+        let sky_changed =
+            self.sky_system
+                .update(&self.queue, &settings.sky, &scene.sun, delta_time);
+        let environment_changed =
+            self.environment_system
+                .update(&self.device, &self.queue, scene, &settings.sky);
+        if sky_changed || environment_changed {
+            self.reflection_probe_system.request_capture();
+            self.irradiance_system.request_bake();
+        }
+        self.reflection_probe_system
+            .update(&self.queue, scene, camera.z_far);
+        if let Some(path_tracer_system) = self.path_tracer_system.as_mut() {
+            path_tracer_system.update(&self.device, &self.queue, scene, camera, settings);
+        }
+        self.ray_traced_shadow_system
+            .update(&self.device, &self.queue, scene, settings);
+        self.irradiance_system
+            .update(&self.queue, scene, camera.z_far);
+
+        if let Err(error) = self.water_system.update(
+            &self.device,
+            &self.queue,
+            scene,
+            delta_time,
+            settings.effect_quality,
+        ) {
+            eprintln!("Failed to update water: {}", error);
+        }
+
+        if let Err(error) = self.decal_system.update(&self.device, &self.queue, scene) {
+            eprintln!("Failed to update decals: {}", error);
+        }
+
+        self.begin_pass(encoder, "Particle Simulation");
+        if let Some(particle_system) = self.particle_system.as_mut() {
+            if let Err(error) = particle_system.update(
+                &self.device,
+                &self.queue,
+                encoder,
+                &self.camera_bind_group,
+                scene,
+                delta_time,
+            ) {
+                eprintln!("Failed to update particles: {}", error);
+            }
+        }
+        self.end_pass(encoder, "Particle Simulation");
+
+        self.begin_pass(encoder, "Compute Before Render");
+        // The scene's compute pipelines are never created without compute shaders
+        if self.compute_supported {
+            if let Err(error) = self.compute_system.update(&self.device, &self.queue, scene) {
+                match error.downcast::<ShaderError>() {
+                    Ok(error) => self.shader_errors.push(error),
+                    Err(error) => eprintln!("Failed to update compute: {}", error),
+                }
+            }
+        }
+        self.compute_system
+            .dispatch(encoder, scene, ComputeStage::BeforeRender);
+        self.end_pass(encoder, "Compute Before Render");
+
+        self.begin_pass(encoder, "Shadow Pass");
+        {
+            let mut shadow_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Shadow Pass"),
+                color_attachments: &[],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: &self.lighting_system.shadow_map().view,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(self.depth.far()),
+                        store: true,
+                    }),
+                    stencil_ops: None,
+                }),
+            });
+            // The sun renders into its corner of the atlas, then local lights into their tiles
+            let [x, y, size] = self.lighting_system.sun_viewport();
+            shadow_pass.set_viewport(x as f32, y as f32, size as f32, size as f32, 0.0, 1.0);
+            shadow_pass.set_scissor_rect(x, y, size, size);
+            let light_frustum = Frustum::from_matrix(self.lighting_system.view_projection());
+            self.terrain_system.render_shadows(
+                &mut shadow_pass,
+                self.lighting_system.caster_bind_group(),
+                &light_frustum,
+            );
+            self.voxel_system.render_shadows(
+                &mut shadow_pass,
+                self.lighting_system.caster_bind_group(),
+                &light_frustum,
+            );
+            self.isosurface_system.render_shadows(
+                &mut shadow_pass,
+                self.lighting_system.caster_bind_group(),
+                &light_frustum,
+            );
+            // Models shadow the scene through the traced mask instead
+            if !self.ray_traced_shadow_system.enabled() {
+                self.model_system.render_shadows(
+                    &mut shadow_pass,
+                    self.lighting_system.caster_bind_group(),
+                    &light_frustum,
+                    scene.sun.layers,
+                );
+            }
+
+            for (view, caster_bind_group) in self.lighting_system.shadow_views() {
+                let [x, y, size] = view.viewport;
+                shadow_pass.set_viewport(x as f32, y as f32, size as f32, size as f32, 0.0, 1.0);
+                shadow_pass.set_scissor_rect(x, y, size, size);
+                let view_frustum = Frustum::from_matrix(&view.view_projection);
+                self.terrain_system.render_shadows(
+                    &mut shadow_pass,
+                    caster_bind_group,
+                    &view_frustum,
+                );
+                self.voxel_system.render_shadows(
+                    &mut shadow_pass,
+                    caster_bind_group,
+                    &view_frustum,
+                );
+                self.isosurface_system.render_shadows(
+                    &mut shadow_pass,
+                    caster_bind_group,
+                    &view_frustum,
+                );
+                self.model_system.render_shadows(
+                    &mut shadow_pass,
+                    caster_bind_group,
+                    &view_frustum,
+                    view.layers,
+                );
+            }
+        }
+        self.end_pass(encoder, "Shadow Pass");
+
+        self.begin_pass(encoder, "Reflection Probes");
+        for face in self.reflection_probe_system.faces() {
+            self.render_probe_face(encoder, &face, settings);
+        }
+        self.reflection_probe_system.finish_capture(encoder);
+        self.end_pass(encoder, "Reflection Probes");
+
+        self.begin_pass(encoder, "Irradiance Probes");
+        for face in self.irradiance_system.faces() {
+            self.render_probe_face(encoder, &face, settings);
+        }
+        self.irradiance_system.finish_capture(encoder);
+        self.end_pass(encoder, "Irradiance Probes");
+
+        let frustum = camera.frustum(aspect_ratio(&dimensions));
+
+        self.begin_pass(encoder, "Depth Prepass");
+        {
+            let mut depth_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Depth Prepass"),
+                color_attachments: &[],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: &self.depth_attachment,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(self.depth.far()),
+                        store: true,
+                    }),
+                    stencil_ops: self.stencil_ops(wgpu::LoadOp::Clear(0)),
+                }),
+            });
+            self.terrain_system.render_depth(
+                &mut depth_pass,
+                &self.camera_bind_group,
+                camera,
+                &frustum,
+                settings.effect_quality,
+            );
+            self.voxel_system
+                .render_depth(&mut depth_pass, &self.camera_bind_group, &frustum);
+            self.isosurface_system
+                .render_depth(&mut depth_pass, &self.camera_bind_group, &frustum);
+            self.model_system.render_depth(
+                &mut depth_pass,
+                &self.camera_bind_group,
+                camera,
+                &frustum,
+            );
+            self.point_cloud_system.render_depth(
+                &mut depth_pass,
+                &self.camera_bind_group,
+                scene,
+                &frustum,
+            );
+        }
+        self.end_pass(encoder, "Depth Prepass");
+
+        if settings.occlusion_culling {
+            self.begin_pass(encoder, "Occlusion Culling");
+            if let Some(hiz_system) = self.hiz_system.as_ref() {
+                hiz_system.render(encoder);
+                self.model_system
+                    .cull(encoder, &self.camera_bind_group, hiz_system.bind_group());
+            }
+            self.end_pass(encoder, "Occlusion Culling");
+        }
+
+        if self.motion_blur_system.enabled() || self.temporal_system.enabled() {
+            self.begin_pass(encoder, "Velocity");
+            self.velocity_system.render(
+                encoder,
+                &self.camera_bind_group,
+                &self.depth_attachment,
+                &self.model_system,
+                &frustum,
+                camera.layers,
+            );
+            self.end_pass(encoder, "Velocity");
+        }
+
+        self.begin_pass(encoder, "Ambient Occlusion");
+        self.ssao_system.render(encoder, &self.camera_bind_group);
+        self.end_pass(encoder, "Ambient Occlusion");
+
+        if self
+            .path_tracer_system
+            .as_ref()
+            .is_some_and(PathTracerSystem::enabled)
+        {
+            self.begin_pass(encoder, "Path Tracing");
+            if let Some(path_tracer_system) = self.path_tracer_system.as_ref() {
+                path_tracer_system.render(encoder, &self.scene_color.view);
+            }
+            self.end_pass(encoder, "Path Tracing");
+        } else {
+            self.begin_pass(encoder, "Ray Traced Shadows");
+            self.ray_traced_shadow_system
+                .render(encoder, &self.camera_bind_group);
+            self.end_pass(encoder, "Ray Traced Shadows");
+            self.render_scene(encoder, frame_context, &frustum);
+        }
+
+        self.begin_pass(encoder, "Compute After Render");
+        self.compute_system
+            .dispatch(encoder, scene, ComputeStage::AfterRender);
+        self.end_pass(encoder, "Compute After Render");
+
+        self.begin_pass(encoder, "Depth Of Field");
+        self.depth_of_field_system
+            .render(encoder, &self.camera_bind_group, &self.scene_color);
+        self.end_pass(encoder, "Depth Of Field");
+
+        self.begin_pass(encoder, "Motion Blur");
+        self.motion_blur_system
+            .render(encoder, &self.scene_color, &dimensions);
+        self.end_pass(encoder, "Motion Blur");
+
+        // Measured before bloom is added, which is spread from the same scene color
+        self.begin_pass(encoder, "Auto Exposure");
+        self.exposure_system.render(encoder);
+        self.end_pass(encoder, "Auto Exposure");
+
+        // Added after exposure is measured so flares don't darken the scene, and before bloom so
+        // they glow
+        self.begin_pass(encoder, "Lens Flares");
+        if let Some(lens_flare_system) = self.lens_flare_system.as_ref() {
+            lens_flare_system.render(encoder, &self.scene_color);
+        }
+        self.end_pass(encoder, "Lens Flares");
+
+        self.begin_pass(encoder, "Bloom");
+        self.bloom_system.render(encoder);
+        self.end_pass(encoder, "Bloom");
+
+        self.begin_pass(encoder, "Temporal Upscale");
+        self.temporal_system.render(encoder);
+        self.end_pass(encoder, "Temporal Upscale");
+
+        self.begin_pass(encoder, "Upscale");
+        self.upscale_system.render(encoder);
+        self.end_pass(encoder, "Upscale");
+
+        self.begin_pass(encoder, "Post Process Pass");
+        {
+            let mut post_process_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Post Process Pass"),
+                color_attachments: &[wgpu::RenderPassColorAttachment {
+                    view: self.sharpen_system.target(view),
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                        store: true,
+                    },
+                }],
+                depth_stencil_attachment: None,
+            });
+            self.post_process.render(
+                &mut post_process_pass,
+                &self.camera_bind_group,
+                self.lighting_system.bind_group(),
+            );
+        }
+        self.end_pass(encoder, "Post Process Pass");
+
+        self.begin_pass(encoder, "Sharpen");
+        self.sharpen_system.render(encoder, view);
+        self.end_pass(encoder, "Sharpen");
+
+        self.begin_pass(encoder, "Overlay Pass");
+        // Lines and sprites are laid out in pixels of the output after tone mapping, like text.
+        // World lines go under the sprites and screen lines over them
+        let output_dimensions = [self.config.width, self.config.height];
+        self.line_system
+            .update(&self.device, &self.queue, scene, &output_dimensions);
+        let sprite_projection = match frame_context.camera_2d {
+            Some(camera_2d) => camera_2d.projection(&output_dimensions),
+            None => sprites::screen_projection(&output_dimensions),
+        };
+        if let Err(error) =
+            self.sprite_system
+                .update(&self.device, &self.queue, scene, &sprite_projection)
+        {
+            eprintln!("Failed to update sprites: {}", error);
+        }
+        {
+            let mut overlay_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Overlay Pass"),
+                color_attachments: &[wgpu::RenderPassColorAttachment {
+                    view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: true,
+                    },
+                }],
+                depth_stencil_attachment: None,
+            });
+            self.line_system
+                .render_world(&mut overlay_pass, &self.overlay_camera_bind_group);
+            self.sprite_system.render(&mut overlay_pass);
+            self.line_system
+                .render_screen(&mut overlay_pass, &self.overlay_camera_bind_group);
+        }
+        self.end_pass(encoder, "Overlay Pass");
+
+        self.begin_pass(encoder, "Text Pass");
+        // Text and labels are drawn at the output resolution after tone mapping so they stay sharp
+        self.text_system.update(
+            &self.device,
+            &self.queue,
+            text,
+            scene,
+            &[self.config.width, self.config.height],
+        );
+        {
+            let mut text_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Text Pass"),
+                color_attachments: &[wgpu::RenderPassColorAttachment {
+                    view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: true,
+                    },
+                }],
+                depth_stencil_attachment: None,
+            });
+            self.text_system
+                .render(&mut text_pass, &self.overlay_camera_bind_group);
+        }
+        self.end_pass(encoder, "Text Pass");
+    }
+}
@@ -0,0 +1,71 @@
+use std::time::Duration;
+
+use crate::{budgets::milliseconds, settings::DynamicResolutionSettings};
+
+// Scale changes reallocate every render target, so they are made in steps and spaced apart
+const SCALE_STEP: f32 = 0.05;
+const ADJUST_INTERVAL: Duration = Duration::from_millis(500);
+// Weight given to each new frame in the smoothed frame time
+const SMOOTHING: f32 = 0.1;
+// Frames this much faster than the target leave room to raise the scale
+const HEADROOM: f32 = 0.85;
+
+// Adjusts the render scale to keep frames close to a target time
+pub struct ResolutionController {
+    scale: f32,
+    // Milliseconds
+    average_frame_time: Option<f32>,
+    since_adjustment: Duration,
+}
+
+impl Default for ResolutionController {
+    fn default() -> Self {
+        Self {
+            scale: 1.0,
+            average_frame_time: None,
+            since_adjustment: Duration::ZERO,
+        }
+    }
+}
+
+impl ResolutionController {
+    pub fn scale(&self) -> f32 {
+        self.scale
+    }
+
+    pub fn update(
+        &mut self,
+        settings: &DynamicResolutionSettings,
+        frame_time: Duration,
+        elapsed: Duration,
+    ) {
+        let frame_time = milliseconds(frame_time);
+        let average = match self.average_frame_time {
+            Some(average) => average + (frame_time - average) * SMOOTHING,
+            None => frame_time,
+        };
+        self.average_frame_time = Some(average);
+        // Clamping by hand doesn't panic when the range is inverted
+        self.scale = self.scale.min(settings.max_scale).max(settings.min_scale);
+
+        self.since_adjustment += elapsed;
+        if self.since_adjustment < ADJUST_INTERVAL {
+            return;
+        }
+        let target = settings.target_frame_time;
+        let scale = if average > target {
+            self.scale - SCALE_STEP
+        } else if average < target * HEADROOM {
+            self.scale + SCALE_STEP
+        } else {
+            return;
+        };
+        let scale = scale.min(settings.max_scale).max(settings.min_scale);
+        if scale != self.scale {
+            self.scale = scale;
+            self.since_adjustment = Duration::ZERO;
+            // Timings from before the change no longer apply
+            self.average_frame_time = None;
+        }
+    }
+}
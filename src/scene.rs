@@ -0,0 +1,540 @@
+use nalgebra_glm as glm;
+use std::collections::HashMap;
+
+use crate::{
+    area_lights::AreaLightDesc,
+    bounds::Aabb,
+    compute::{
+        ComputeBufferDesc, ComputeBufferHandle, ComputePipelineDesc, ComputePipelineHandle,
+        ComputeTextureDesc, ComputeTextureHandle, DispatchDesc,
+    },
+    decals::DecalDesc,
+    environment::EnvironmentDesc,
+    irradiance::IrradianceVolumeDesc,
+    isosurface::IsosurfaceDesc,
+    lens_flare::LensFlareDesc,
+    lighting::{DirectionalLight, PunctualLight},
+    lines::PolylineDesc,
+    material::Material,
+    model::ModelDesc,
+    particles::EmitterDesc,
+    points::PointCloudDesc,
+    probes::ReflectionProbeDesc,
+    render_cameras::RenderCameraDesc,
+    sprites::{SpriteDesc, SpriteSheetDesc, SpriteSheetHandle},
+    terrain::TerrainDesc,
+    text::LabelDesc,
+    voxels::VoxelWorldDesc,
+    water::WaterDesc,
+};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct EmitterHandle(pub usize);
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct DecalHandle(pub usize);
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct TerrainHandle(pub usize);
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct WaterHandle(pub usize);
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct ModelHandle(pub usize);
+
+// A material of a model, by its index in the model's materials
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct MaterialHandle {
+    pub model: ModelHandle,
+    pub material: usize,
+}
+
+// The materials of a model. Once the model is in a scene they can only be changed through
+// Scene::materials, which flags them for upload
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct MaterialList(Vec<Material>);
+
+impl From<Vec<Material>> for MaterialList {
+    fn from(materials: Vec<Material>) -> Self {
+        Self(materials)
+    }
+}
+
+impl FromIterator<Material> for MaterialList {
+    fn from_iter<I: IntoIterator<Item = Material>>(materials: I) -> Self {
+        Self(materials.into_iter().collect())
+    }
+}
+
+impl std::ops::Deref for MaterialList {
+    type Target = [Material];
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+// Edits the materials of the scene's models, flagging them for upload
+pub struct Materials<'a> {
+    models: &'a mut [ModelDesc],
+    revision: &'a mut u64,
+}
+
+impl<'a> Materials<'a> {
+    // Flags the material for upload, and its model for creating again if its textures change
+    pub fn get_mut(self, handle: MaterialHandle) -> Option<&'a mut Material> {
+        let material = self
+            .models
+            .get_mut(handle.model.0)?
+            .materials
+            .0
+            .get_mut(handle.material)?;
+        *self.revision += 1;
+        Some(material)
+    }
+
+    pub fn add(self, model: ModelHandle, material: Material) -> Option<MaterialHandle> {
+        let materials = &mut self.models.get_mut(model.0)?.materials.0;
+        materials.push(material);
+        *self.revision += 1;
+        Some(MaterialHandle {
+            model,
+            material: materials.len() - 1,
+        })
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct LightHandle(pub usize);
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct AreaLightHandle(pub usize);
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct LabelHandle(pub usize);
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct SpriteHandle(pub usize);
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct PolylineHandle(pub usize);
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct PointCloudHandle(pub usize);
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct VoxelWorldHandle(pub usize);
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct IsosurfaceHandle(pub usize);
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct DispatchHandle(pub usize);
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct LensFlareHandle(pub usize);
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct ReflectionProbeHandle(pub usize);
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct RenderCameraHandle(pub usize);
+
+pub struct Scene {
+    pub emitters: Vec<EmitterDesc>,
+    pub decals: Vec<DecalDesc>,
+    pub terrains: Vec<TerrainDesc>,
+    pub waters: Vec<WaterDesc>,
+    pub models: Vec<ModelDesc>,
+    pub sun: DirectionalLight,
+    pub lights: Vec<PunctualLight>,
+    pub area_lights: Vec<AreaLightDesc>,
+    pub labels: Vec<LabelDesc>,
+    pub sprite_sheets: Vec<SpriteSheetDesc>,
+    pub sprites: Vec<SpriteDesc>,
+    pub polylines: Vec<PolylineDesc>,
+    pub point_clouds: Vec<PointCloudDesc>,
+    pub voxel_worlds: Vec<VoxelWorldDesc>,
+    pub isosurfaces: Vec<IsosurfaceDesc>,
+    pub compute_buffers: Vec<ComputeBufferDesc>,
+    pub compute_textures: Vec<ComputeTextureDesc>,
+    pub compute_pipelines: Vec<ComputePipelineDesc>,
+    pub dispatches: Vec<DispatchDesc>,
+    pub lens_flares: Vec<LensFlareDesc>,
+    pub reflection_probes: Vec<ReflectionProbeDesc>,
+    pub render_cameras: Vec<RenderCameraDesc>,
+    // Baked into a grid of probes that light models inside it indirectly
+    pub irradiance_volume: Option<IrradianceVolumeDesc>,
+    // Drawn in place of the sky, see environment.rs
+    environment: Option<EnvironmentDesc>,
+    // Changes whenever the environment is replaced, so it's uploaded again
+    environment_revision: u64,
+    // Where the scene is drawn from in the world. The camera and the GPU's copy of the scene are
+    // placed relative to it, so moving it along with the camera keeps what's nearby precise in f32
+    pub origin: glm::DVec3,
+    // Scene units per unit of the files models are opened from, such as 0.01 for files in
+    // centimeters in a scene in meters
+    pub unit_scale: f32,
+    // Changes whenever models are removed or their images change, so their GPU copies are
+    // created again
+    models_revision: u64,
+    // Changes whenever a material is edited, so material uniforms are uploaded again
+    materials_revision: u64,
+    // Changes for a model whenever its images are replaced, so only that model is uploaded again
+    images_revisions: HashMap<ModelHandle, u64>,
+    // Failures while loading spawned models, waiting to be shown
+    load_errors: Vec<String>,
+}
+
+impl Default for Scene {
+    fn default() -> Self {
+        Self {
+            emitters: Vec::new(),
+            decals: Vec::new(),
+            terrains: Vec::new(),
+            waters: Vec::new(),
+            models: Vec::new(),
+            sun: DirectionalLight::default(),
+            lights: Vec::new(),
+            area_lights: Vec::new(),
+            labels: Vec::new(),
+            sprite_sheets: Vec::new(),
+            sprites: Vec::new(),
+            polylines: Vec::new(),
+            point_clouds: Vec::new(),
+            voxel_worlds: Vec::new(),
+            isosurfaces: Vec::new(),
+            compute_buffers: Vec::new(),
+            compute_textures: Vec::new(),
+            compute_pipelines: Vec::new(),
+            dispatches: Vec::new(),
+            lens_flares: Vec::new(),
+            reflection_probes: Vec::new(),
+            render_cameras: Vec::new(),
+            irradiance_volume: None,
+            environment: None,
+            environment_revision: 0,
+            origin: glm::DVec3::zeros(),
+            unit_scale: 1.0,
+            models_revision: 0,
+            materials_revision: 0,
+            images_revisions: HashMap::new(),
+            load_errors: Vec::new(),
+        }
+    }
+}
+
+impl Scene {
+    #[allow(dead_code)]
+    pub fn spawn_emitter(&mut self, desc: EmitterDesc) -> EmitterHandle {
+        self.emitters.push(desc);
+        EmitterHandle(self.emitters.len() - 1)
+    }
+
+    #[allow(dead_code)]
+    pub fn spawn_decal(&mut self, desc: DecalDesc) -> DecalHandle {
+        self.decals.push(desc);
+        DecalHandle(self.decals.len() - 1)
+    }
+
+    pub fn spawn_terrain(&mut self, desc: TerrainDesc) -> TerrainHandle {
+        self.terrains.push(desc);
+        TerrainHandle(self.terrains.len() - 1)
+    }
+
+    #[allow(dead_code)]
+    pub fn spawn_water(&mut self, desc: WaterDesc) -> WaterHandle {
+        self.waters.push(desc);
+        WaterHandle(self.waters.len() - 1)
+    }
+
+    pub fn spawn_model(&mut self, mut desc: ModelDesc) -> ModelHandle {
+        let name = desc.name.clone().unwrap_or_else(|| "model".to_string());
+        self.load_errors.extend(
+            desc.load_errors
+                .drain(..)
+                .map(|error| format!("Loading {}: {}", name, error)),
+        );
+        self.models.push(desc);
+        ModelHandle(self.models.len() - 1)
+    }
+
+    pub fn drain_load_errors(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.load_errors)
+    }
+
+    // Later models move down to fill the gap, so their handles refer to the model before them
+    pub fn remove_model(&mut self, model: ModelHandle) -> Option<ModelDesc> {
+        if model.0 >= self.models.len() {
+            return None;
+        }
+        self.invalidate_models();
+        Some(self.models.remove(model.0))
+    }
+
+    pub fn insert_model(&mut self, model: ModelHandle, desc: ModelDesc) {
+        let index = model.0.min(self.models.len());
+        self.models.insert(index, desc);
+        self.invalidate_models();
+    }
+
+    // Relative to the origin like the camera, as imported and ignoring skinning
+    pub fn instance_bounds(&self, model: ModelHandle, instance: usize) -> Option<Aabb> {
+        let model = self.models.get(model.0)?;
+        let placed = model.instances.get(instance)?;
+        let bounds = model.meshes.get(placed.mesh)?.bounds();
+        Some(bounds.transformed(&self.local_transform(&model.instance_transform(placed))))
+    }
+
+    // Bounds of every model in the scene, relative to the origin
+    pub fn bounds(&self) -> Aabb {
+        let mut bounds = Aabb::default();
+        for (index, model) in self.models.iter().enumerate() {
+            for instance in 0..model.instances.len() {
+                if let Some(instance_bounds) = self.instance_bounds(ModelHandle(index), instance) {
+                    bounds.merge(&instance_bounds);
+                }
+            }
+        }
+        bounds
+    }
+
+    pub fn clear_models(&mut self) {
+        self.models.clear();
+        self.invalidate_models();
+    }
+
+    // Needed after a model's images or its number of materials change, which are only read when
+    // the model is uploaded
+    pub fn invalidate_models(&mut self) {
+        self.models_revision += 1;
+        self.images_revisions.clear();
+    }
+
+    pub fn models_revision(&self) -> u64 {
+        self.models_revision
+    }
+
+    pub fn material(&self, handle: MaterialHandle) -> Option<&Material> {
+        self.models
+            .get(handle.model.0)?
+            .materials
+            .get(handle.material)
+    }
+
+    pub fn materials(&mut self) -> Materials<'_> {
+        Materials {
+            models: &mut self.models,
+            revision: &mut self.materials_revision,
+        }
+    }
+
+    pub fn materials_revision(&self) -> u64 {
+        self.materials_revision
+    }
+
+    // Swaps in a new copy of an image, such as one edited on disk, uploading the model again
+    pub fn replace_image(
+        &mut self,
+        model: ModelHandle,
+        image: usize,
+        source: image::DynamicImage,
+    ) -> bool {
+        let replaced = match self
+            .models
+            .get_mut(model.0)
+            .and_then(|desc| desc.images.get_mut(image))
+        {
+            Some(replaced) => replaced,
+            None => return false,
+        };
+        *replaced = source;
+        *self.images_revisions.entry(model).or_default() += 1;
+        true
+    }
+
+    pub fn images_revision(&self, model: ModelHandle) -> u64 {
+        self.images_revisions
+            .get(&model)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    pub fn environment(&self) -> Option<&EnvironmentDesc> {
+        self.environment.as_ref()
+    }
+
+    pub fn set_environment(&mut self, environment: Option<EnvironmentDesc>) {
+        self.environment = environment;
+        self.environment_revision += 1;
+    }
+
+    pub fn environment_revision(&self) -> u64 {
+        self.environment_revision
+    }
+
+    pub fn spawn_light(&mut self, light: PunctualLight) -> LightHandle {
+        self.lights.push(light);
+        LightHandle(self.lights.len() - 1)
+    }
+
+    #[allow(dead_code)]
+    pub fn spawn_area_light(&mut self, desc: AreaLightDesc) -> AreaLightHandle {
+        self.area_lights.push(desc);
+        AreaLightHandle(self.area_lights.len() - 1)
+    }
+
+    pub fn spawn_label(&mut self, desc: LabelDesc) -> LabelHandle {
+        self.labels.push(desc);
+        LabelHandle(self.labels.len() - 1)
+    }
+
+    #[allow(dead_code)]
+    pub fn spawn_sprite_sheet(&mut self, desc: SpriteSheetDesc) -> SpriteSheetHandle {
+        self.sprite_sheets.push(desc);
+        SpriteSheetHandle(self.sprite_sheets.len() - 1)
+    }
+
+    #[allow(dead_code)]
+    pub fn spawn_sprite(&mut self, desc: SpriteDesc) -> SpriteHandle {
+        self.sprites.push(desc);
+        SpriteHandle(self.sprites.len() - 1)
+    }
+
+    pub fn spawn_polyline(&mut self, desc: PolylineDesc) -> PolylineHandle {
+        self.polylines.push(desc);
+        PolylineHandle(self.polylines.len() - 1)
+    }
+
+    pub fn spawn_point_cloud(&mut self, desc: PointCloudDesc) -> PointCloudHandle {
+        self.point_clouds.push(desc);
+        PointCloudHandle(self.point_clouds.len() - 1)
+    }
+
+    #[allow(dead_code)]
+    pub fn spawn_voxel_world(&mut self, desc: VoxelWorldDesc) -> VoxelWorldHandle {
+        self.voxel_worlds.push(desc);
+        VoxelWorldHandle(self.voxel_worlds.len() - 1)
+    }
+
+    #[allow(dead_code)]
+    pub fn spawn_isosurface(&mut self, desc: IsosurfaceDesc) -> IsosurfaceHandle {
+        self.isosurfaces.push(desc);
+        IsosurfaceHandle(self.isosurfaces.len() - 1)
+    }
+
+    #[allow(dead_code)]
+    pub fn spawn_compute_buffer(&mut self, desc: ComputeBufferDesc) -> ComputeBufferHandle {
+        self.compute_buffers.push(desc);
+        ComputeBufferHandle(self.compute_buffers.len() - 1)
+    }
+
+    #[allow(dead_code)]
+    pub fn spawn_compute_texture(&mut self, desc: ComputeTextureDesc) -> ComputeTextureHandle {
+        self.compute_textures.push(desc);
+        ComputeTextureHandle(self.compute_textures.len() - 1)
+    }
+
+    #[allow(dead_code)]
+    pub fn spawn_compute_pipeline(&mut self, desc: ComputePipelineDesc) -> ComputePipelineHandle {
+        self.compute_pipelines.push(desc);
+        ComputePipelineHandle(self.compute_pipelines.len() - 1)
+    }
+
+    #[allow(dead_code)]
+    pub fn spawn_dispatch(&mut self, desc: DispatchDesc) -> DispatchHandle {
+        self.dispatches.push(desc);
+        DispatchHandle(self.dispatches.len() - 1)
+    }
+
+    #[allow(dead_code)]
+    pub fn spawn_lens_flare(&mut self, desc: LensFlareDesc) -> LensFlareHandle {
+        self.lens_flares.push(desc);
+        LensFlareHandle(self.lens_flares.len() - 1)
+    }
+
+    #[allow(dead_code)]
+    pub fn spawn_reflection_probe(&mut self, desc: ReflectionProbeDesc) -> ReflectionProbeHandle {
+        self.reflection_probes.push(desc);
+        ReflectionProbeHandle(self.reflection_probes.len() - 1)
+    }
+
+    #[allow(dead_code)]
+    pub fn spawn_render_camera(&mut self, desc: RenderCameraDesc) -> RenderCameraHandle {
+        self.render_cameras.push(desc);
+        RenderCameraHandle(self.render_cameras.len() - 1)
+    }
+
+    // Positions in the world are f64, which stays precise across planets
+    pub fn world_position(&self, position: &glm::Vec3) -> glm::DVec3 {
+        self.origin + position.cast::<f64>()
+    }
+
+    // Where a position in the world is placed relative to the origin
+    pub fn local_position(&self, position: &glm::DVec3) -> glm::Vec3 {
+        (position - self.origin).cast::<f32>()
+    }
+
+    // The camera and everything uploaded to the GPU are relative to the origin. It's subtracted
+    // in f64 before narrowing, so only what's far from the camera loses precision
+    pub fn local_point(&self, position: &glm::Vec3) -> glm::Vec3 {
+        self.local_position(&position.cast::<f64>())
+    }
+
+    pub fn local_transform(&self, transform: &glm::DMat4) -> glm::Mat4 {
+        (glm::translation(&-self.origin) * transform).cast::<f32>()
+    }
+
+    // Moves the origin, which only changes where the scene is drawn from. Everything stored in the
+    // scene stays in world space, so moving the origin never rounds it
+    pub fn rebase(&mut self, offset: &glm::Vec3) {
+        self.origin += offset.cast::<f64>();
+    }
+
+    // Scene lights followed by the lights of every model, relative to the origin
+    pub fn punctual_lights(&self) -> Vec<PunctualLight> {
+        let scene_lights = self.lights.iter().map(|light| PunctualLight {
+            position: self.local_point(&light.position),
+            ..*light
+        });
+        let model_lights = self.models.iter().flat_map(|model| {
+            let transform = self.local_transform(&model.transform);
+            model
+                .lights
+                .iter()
+                .map(move |light| light.transformed(&transform))
+        });
+        scene_lights.chain(model_lights).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn editing_materials_flags_them_for_upload() {
+        let mut scene = Scene::default();
+        let model = scene.spawn_model(ModelDesc {
+            materials: vec![Material::default()].into(),
+            ..Default::default()
+        });
+        let handle = MaterialHandle { model, material: 0 };
+        let revision = scene.materials_revision();
+
+        scene.materials().get_mut(handle).unwrap().roughness_factor = 0.25;
+        assert_ne!(scene.materials_revision(), revision);
+        assert_eq!(scene.material(handle).unwrap().roughness_factor, 0.25);
+
+        let revision = scene.materials_revision();
+        let added = scene.materials().add(model, Material::default()).unwrap();
+        assert_eq!(added.material, 1);
+        assert_ne!(scene.materials_revision(), revision);
+
+        let revision = scene.materials_revision();
+        let missing = MaterialHandle { model, material: 5 };
+        assert!(scene.materials().get_mut(missing).is_none());
+        assert_eq!(scene.materials_revision(), revision);
+    }
+}
@@ -0,0 +1,981 @@
+use anyhow::{bail, Context, Result};
+use nalgebra_glm as glm;
+use serde::{Deserialize, Serialize};
+use std::{path::PathBuf, str::FromStr};
+
+use crate::bookmarks::{CameraBookmark, BOOKMARK_SLOTS};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FogMode {
+    None,
+    Linear,
+    Exponential,
+    ExponentialSquared,
+}
+
+impl FromStr for FogMode {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        Ok(match value.trim() {
+            "none" => Self::None,
+            "linear" => Self::Linear,
+            "exp" | "exponential" => Self::Exponential,
+            "exp2" | "exponential_squared" => Self::ExponentialSquared,
+            _ => bail!(
+                "Unknown fog mode '{}', expected one of: none, linear, exp, exp2",
+                value
+            ),
+        })
+    }
+}
+
+// Maps the exposed HDR scene color into the displayable range
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ToneMapping {
+    // Values above one are clipped
+    None,
+    Reinhard,
+    Aces,
+}
+
+impl FromStr for ToneMapping {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        Ok(match value.trim() {
+            "none" => Self::None,
+            "reinhard" => Self::Reinhard,
+            "aces" => Self::Aces,
+            _ => bail!(
+                "Unknown tone mapping '{}', expected one of: none, reinhard, aces",
+                value
+            ),
+        })
+    }
+}
+
+// How the scene is brought up to the output resolution when rendered below it
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Upscaling {
+    Bilinear,
+    // Edge adaptive, in the spirit of FidelityFX Super Resolution 1
+    Fsr,
+    // Accumulates jittered frames over time, which also antialiases at full resolution
+    Temporal,
+}
+
+impl FromStr for Upscaling {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        Ok(match value.trim() {
+            "bilinear" => Self::Bilinear,
+            "fsr" => Self::Fsr,
+            "temporal" => Self::Temporal,
+            _ => bail!(
+                "Unknown upscaling '{}', expected one of: bilinear, fsr, temporal",
+                value
+            ),
+        })
+    }
+}
+
+// Filter applied to the final image, mostly to recover detail lost when upscaling
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SharpenMode {
+    None,
+    // Adds back the difference from a blurred copy
+    Unsharp,
+    // Sharpens low contrast detail more than edges, which keeps halos down
+    ContrastAdaptive,
+}
+
+impl FromStr for SharpenMode {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        Ok(match value.trim() {
+            "none" => Self::None,
+            "unsharp" => Self::Unsharp,
+            "contrast_adaptive" => Self::ContrastAdaptive,
+            _ => bail!(
+                "Unknown sharpen mode '{}', expected one of: none, unsharp, contrast_adaptive",
+                value
+            ),
+        })
+    }
+}
+
+// Which faces of models are culled, overriding materials helps find meshes wound inside out
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CullMode {
+    // Back faces are culled unless the material is double sided
+    Material,
+    None,
+    Front,
+    Back,
+}
+
+impl FromStr for CullMode {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        Ok(match value.trim() {
+            "material" => Self::Material,
+            "none" => Self::None,
+            "front" => Self::Front,
+            "back" => Self::Back,
+            _ => bail!(
+                "Unknown cull mode '{}', expected one of: material, none, front, back",
+                value
+            ),
+        })
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum EffectQuality {
+    Low,
+    Medium,
+    High,
+}
+
+impl EffectQuality {
+    pub fn reflection_steps(self) -> u32 {
+        match self {
+            Self::Low => 8,
+            Self::Medium => 16,
+            Self::High => 32,
+        }
+    }
+
+    pub fn max_volumetric_samples(self) -> u32 {
+        match self {
+            Self::Low => 16,
+            Self::Medium => 32,
+            Self::High => 128,
+        }
+    }
+
+    pub fn max_ssao_samples(self) -> u32 {
+        match self {
+            Self::Low => 8,
+            Self::Medium => 16,
+            Self::High => 32,
+        }
+    }
+
+    pub fn depth_of_field_samples(self) -> u32 {
+        match self {
+            Self::Low => 16,
+            Self::Medium => 32,
+            Self::High => 64,
+        }
+    }
+
+    pub fn max_motion_blur_samples(self) -> u32 {
+        match self {
+            Self::Low => 4,
+            Self::Medium => 8,
+            Self::High => 16,
+        }
+    }
+
+    // Each level blurs across twice the distance of the one before it
+    pub fn bloom_levels(self) -> u32 {
+        match self {
+            Self::Low => 4,
+            Self::Medium => 5,
+            Self::High => 6,
+        }
+    }
+
+    // Scales the distance at which terrain switches to coarser detail levels
+    pub fn lod_distance_scale(self) -> f32 {
+        match self {
+            Self::Low => 0.5,
+            Self::Medium => 0.75,
+            Self::High => 1.0,
+        }
+    }
+}
+
+impl FromStr for EffectQuality {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        Ok(match value.trim() {
+            "low" => Self::Low,
+            "medium" => Self::Medium,
+            "high" => Self::High,
+            _ => bail!(
+                "Unknown effect quality '{}', expected one of: low, medium, high",
+                value
+            ),
+        })
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FogSettings {
+    pub mode: FogMode,
+    pub color: glm::Vec3,
+    // Used by the exponential modes
+    pub density: f32,
+    // Used by the linear mode
+    pub start: f32,
+    pub end: f32,
+    // Height fog is disabled when its density is zero
+    pub height_density: f32,
+    pub height_falloff: f32,
+    // Height fog is at its densest below this height
+    pub base_height: f32,
+    // How much the fog takes on the color of the environment towards the horizon
+    pub environment_blend: f32,
+}
+
+impl Default for FogSettings {
+    fn default() -> Self {
+        Self {
+            mode: FogMode::None,
+            color: glm::vec3(0.5, 0.6, 0.7),
+            density: 0.02,
+            start: 10.0,
+            end: 100.0,
+            height_density: 0.0,
+            height_falloff: 0.2,
+            base_height: 0.0,
+            environment_blend: 0.0,
+        }
+    }
+}
+
+// Places the sun by the time of day and draws a procedural sky behind the scene, see sky.rs
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SkySettings {
+    pub enabled: bool,
+    // Local solar time in hours
+    pub time_of_day: f32,
+    // Degrees north of the equator
+    pub latitude: f32,
+    pub day_of_year: u32,
+    // Haziness of the atmosphere, from 2 for a clear sky to 10 for a hazy one
+    pub turbidity: f32,
+    // Intensity of the sun when it's overhead, the sky brightens with it
+    pub sun_intensity: f32,
+    // Fraction of the sky covered by clouds
+    pub cloud_coverage: f32,
+    // How fast the clouds drift, in cloud widths per second
+    pub cloud_speed: f32,
+    // Seconds between recapturing the reflection probes and rebaking the irradiance volume, so
+    // lighting follows the sky, zero to only do it by hand
+    pub lighting_interval: f32,
+    // Brightness of an environment opened from an HDR image, which is drawn in place of the sky,
+    // see environment.rs
+    pub environment_intensity: f32,
+    // Degrees the environment is turned about the up axis
+    pub environment_rotation: f32,
+}
+
+impl Default for SkySettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            time_of_day: 10.0,
+            latitude: 45.0,
+            day_of_year: 172,
+            turbidity: 2.5,
+            sun_intensity: 1.0,
+            cloud_coverage: 0.4,
+            cloud_speed: 0.02,
+            lighting_interval: 10.0,
+            environment_intensity: 1.0,
+            environment_rotation: 0.0,
+        }
+    }
+}
+
+// Fits models opened from files to the scene, whatever units they were made in
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ImportSettings {
+    // Moves the center of the model's bounds to the origin
+    pub recenter: bool,
+    // Scales the model so the longest side of its bounds is the target size
+    pub normalize: bool,
+    pub target_size: f32,
+    // Reloads the images of opened models when their files change on disk, see texture_reload.rs
+    pub hot_reload: bool,
+}
+
+impl Default for ImportSettings {
+    fn default() -> Self {
+        Self {
+            recenter: false,
+            normalize: false,
+            target_size: 2.0,
+            hot_reload: true,
+        }
+    }
+}
+
+// Recording presented frames, see recording.rs
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RecordingSettings {
+    // Pipes frames to ffmpeg for a video instead of writing numbered PNGs
+    pub ffmpeg: bool,
+    // Time advances by one frame at this rate for each recorded frame, however long it took
+    pub frame_rate: f32,
+    // Waits for each frame to be written before the next, with vsync, frame rate limits and
+    // dynamic resolution off, so recordings come out the same on any machine
+    pub offline: bool,
+}
+
+impl Default for RecordingSettings {
+    fn default() -> Self {
+        Self {
+            ffmpeg: false,
+            frame_rate: 30.0,
+            offline: false,
+        }
+    }
+}
+
+// Light from the sun scattered towards the camera by particles in the air
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct VolumetricSettings {
+    pub enabled: bool,
+    pub density: f32,
+    // Positive values scatter light forwards, negative values scatter it back towards the light
+    pub anisotropy: f32,
+    // Samples taken along each view ray
+    pub samples: u32,
+    pub max_distance: f32,
+}
+
+impl Default for VolumetricSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            density: 0.02,
+            anisotropy: 0.6,
+            samples: 32,
+            max_distance: 100.0,
+        }
+    }
+}
+
+// Screen space ambient occlusion, combined with each material's baked occlusion
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SsaoSettings {
+    pub enabled: bool,
+    // World space distance searched for occluders
+    pub radius: f32,
+    // Offsets depth comparisons to keep flat surfaces from occluding themselves
+    pub bias: f32,
+    // Exponent applied to the result, darkening occluded areas further
+    pub intensity: f32,
+    pub samples: u32,
+}
+
+impl Default for SsaoSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            radius: 0.5,
+            bias: 0.025,
+            intensity: 1.5,
+            samples: 16,
+        }
+    }
+}
+
+// Sun shadows cast by models traced against a hierarchy of their triangles instead of rendered
+// into the shadow map, meant for static scenes since the hierarchy is only refit as they move
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RayTracedShadowSettings {
+    pub enabled: bool,
+    // Traces a ray for every other pixel on each axis, filled in by the filter
+    pub half_resolution: bool,
+    // Angular radius of the sun in degrees, zero for hard shadows
+    pub softness: f32,
+}
+
+impl Default for RayTracedShadowSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            half_resolution: true,
+            softness: 0.5,
+        }
+    }
+}
+
+// Light above the threshold spreads into its surroundings, emissive materials brighter
+// than paper white bloom once exposed
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BloomSettings {
+    pub enabled: bool,
+    // Scene brightness at which bloom starts, before exposure
+    pub threshold: f32,
+    // Range below the threshold over which bloom fades in
+    pub knee: f32,
+    pub intensity: f32,
+    // Spread of the upsampling filter in texels
+    pub radius: f32,
+}
+
+impl Default for BloomSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            threshold: 1.0,
+            knee: 0.5,
+            intensity: 0.1,
+            radius: 1.0,
+        }
+    }
+}
+
+// Blurs the scene away from the focus distance like a camera lens
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DepthOfFieldSettings {
+    pub enabled: bool,
+    // Distance from the camera that stays sharp
+    pub focus_distance: f32,
+    // Blur radius in pixels of points infinitely far away, wider apertures blur more
+    pub aperture: f32,
+    // Limits the blur radius in pixels, mostly for the near field
+    pub max_radius: f32,
+    // Clicking the scene focuses on whatever is under the cursor
+    pub click_to_focus: bool,
+}
+
+impl Default for DepthOfFieldSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            focus_distance: 5.0,
+            aperture: 8.0,
+            max_radius: 16.0,
+            click_to_focus: false,
+        }
+    }
+}
+
+// Blurs moving pixels along their motion, from the camera or from models
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MotionBlurSettings {
+    pub enabled: bool,
+    pub samples: u32,
+    // Portion of the frame the shutter stays open for, 360 degrees blurs across the whole frame
+    pub shutter_angle: f32,
+}
+
+impl Default for MotionBlurSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            samples: 8,
+            shutter_angle: 180.0,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SharpenSettings {
+    pub mode: SharpenMode,
+    // Zero leaves the image untouched
+    pub strength: f32,
+}
+
+impl Default for SharpenSettings {
+    fn default() -> Self {
+        Self {
+            mode: SharpenMode::None,
+            strength: 0.5,
+        }
+    }
+}
+
+// Imitates the flaws of a real camera, each effect is off at zero intensity
+#[derive(Debug, Default, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CameraImperfectionSettings {
+    // Darkens the corners of the image
+    pub vignette: f32,
+    // Separates the color channels towards the edges of the image
+    pub chromatic_aberration: f32,
+    // Noise that changes every frame
+    pub film_grain: f32,
+}
+
+// Adapts the exposure to the average scene luminance, measured with a histogram of the HDR
+// buffer. While disabled the manual exposure is used instead
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AutoExposureSettings {
+    pub enabled: bool,
+    // Range of log2 scene luminance measured, anything outside is clamped to it
+    pub min_ev: f32,
+    pub max_ev: f32,
+    // Adaptation rates when the scene gets brighter or darker
+    pub speed_up: f32,
+    pub speed_down: f32,
+    // Stops added to the adapted exposure
+    pub compensation: f32,
+}
+
+impl Default for AutoExposureSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            min_ev: -10.0,
+            max_ev: 6.0,
+            speed_up: 3.0,
+            speed_down: 1.0,
+            compensation: 0.0,
+        }
+    }
+}
+
+// Replaces the render scale with one that is adjusted to keep frames within a target time
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DynamicResolutionSettings {
+    pub enabled: bool,
+    // Milliseconds, measured on the GPU when timestamps are available
+    pub target_frame_time: f32,
+    pub min_scale: f32,
+    pub max_scale: f32,
+}
+
+impl Default for DynamicResolutionSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            target_frame_time: 16.6,
+            min_scale: 0.5,
+            max_scale: 1.0,
+        }
+    }
+}
+
+// Limits applied on top of the user's settings while running on battery
+const BATTERY_FRAME_RATE_LIMIT: f32 = 30.0;
+const BATTERY_RENDER_SCALE: f32 = 0.75;
+const BATTERY_EFFECT_QUALITY: EffectQuality = EffectQuality::Low;
+
+pub const MIN_RENDER_SCALE: f32 = 0.25;
+pub const MAX_RENDER_SCALE: f32 = 2.0;
+
+pub const MIN_UI_SCALE: f32 = 0.5;
+pub const MAX_UI_SCALE: f32 = 3.0;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub vsync: bool,
+    // Samples per pixel of opaque surfaces and the sky, 1 for none or 4. Only read at startup,
+    // since every pipeline drawing them is built for it, unless --msaa says otherwise
+    pub msaa_samples: u32,
+    // Frames per second, zero for no limit
+    pub frame_rate_limit: f32,
+    // Fraction of the window resolution the scene is rendered at
+    pub render_scale: f32,
+    pub upscaling: Upscaling,
+    pub dynamic_resolution: DynamicResolutionSettings,
+    pub effect_quality: EffectQuality,
+    // Reduces the workload automatically while running on battery
+    pub power_saving: bool,
+    // Frame timings drawn in the corner of the window
+    pub stats_overlay: bool,
+    // Enlarges the interface and screen text beyond the window's scale factor
+    pub ui_scale: f32,
+    pub clear_color: glm::Vec3,
+    // Multiplies the scene color before tone mapping
+    pub exposure: f32,
+    pub auto_exposure: AutoExposureSettings,
+    pub tone_mapping: ToneMapping,
+    // Adds blue noise below the output's precision so smooth gradients don't band
+    pub dithering: bool,
+    pub bloom: BloomSettings,
+    pub depth_of_field: DepthOfFieldSettings,
+    pub motion_blur: MotionBlurSettings,
+    pub imperfections: CameraImperfectionSettings,
+    // Draws the lens flares placed in the scene
+    pub lens_flares: bool,
+    pub sharpen: SharpenSettings,
+    pub sky: SkySettings,
+    // Equirectangular .hdr or .exr image drawn in place of the sky, see environment.rs
+    pub environment: Option<PathBuf>,
+    pub fog: FogSettings,
+    pub volumetric: VolumetricSettings,
+    pub ssao: SsaoSettings,
+    pub ray_traced_shadows: RayTracedShadowSettings,
+    // Draws the baked irradiance probes as spheres
+    pub irradiance_probes: bool,
+    // Draws the bones of models with skeletons, and their joint names when set
+    pub skeletons: bool,
+    pub joint_names: bool,
+    // Outlines the colliders of simulated models
+    #[cfg(feature = "physics")]
+    pub physics_colliders: bool,
+    // Stick deflection ignored around the center, as a fraction of full deflection
+    #[cfg(feature = "gamepad")]
+    pub gamepad_dead_zone: f32,
+    // Replaces the rasterized scene with a progressively path traced one
+    pub path_tracing: bool,
+    pub cull_mode: CullMode,
+    // Merges the instances of each model that isn't animated into a mesh per material, so
+    // scenes of many static meshes take far fewer draws
+    pub static_batching: bool,
+    // Merges a model again when its instances move, otherwise moved instances are drawn where
+    // they were merged until rebuilt by hand
+    pub rebuild_static_batches: bool,
+    // Tests model instances against the depth prepass on the GPU and draws only those that
+    // aren't hidden behind it
+    pub occlusion_culling: bool,
+    // Packs the textures of a model's materials into arrays per texture slot, shared by materials
+    // whose images are the same size, so most draws only change the material index instead of
+    // rebinding every texture. Only takes effect where storage buffers can be read while shading
+    pub pack_materials: bool,
+    // Megabytes of GPU memory the renderer is warned about approaching, zero for no budget
+    pub memory_budget: f32,
+    // How far the camera strays from the scene's origin before the origin is moved to it, which
+    // keeps large worlds precise around the camera. Zero never moves it. Depth is reversed at
+    // startup when it's set, unless --reverse-z says otherwise
+    pub rebase_distance: f32,
+    pub recording: RecordingSettings,
+    // Camera poses saved with Ctrl and a number key, and returned to with the number key
+    pub camera_bookmarks: [Option<CameraBookmark>; BOOKMARK_SLOTS],
+    // Seconds the camera takes to move to a bookmark or frame the selection, zero jumps straight
+    // there
+    pub camera_transition: f32,
+    pub import: ImportSettings,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            vsync: true,
+            msaa_samples: 1,
+            frame_rate_limit: 0.0,
+            render_scale: 1.0,
+            upscaling: Upscaling::Bilinear,
+            dynamic_resolution: DynamicResolutionSettings::default(),
+            effect_quality: EffectQuality::High,
+            power_saving: true,
+            stats_overlay: false,
+            ui_scale: 1.0,
+            clear_color: glm::vec3(0.1, 0.2, 0.3),
+            exposure: 1.0,
+            auto_exposure: AutoExposureSettings::default(),
+            tone_mapping: ToneMapping::Aces,
+            dithering: true,
+            bloom: BloomSettings::default(),
+            depth_of_field: DepthOfFieldSettings::default(),
+            motion_blur: MotionBlurSettings::default(),
+            imperfections: CameraImperfectionSettings::default(),
+            lens_flares: true,
+            sharpen: SharpenSettings::default(),
+            sky: SkySettings::default(),
+            environment: None,
+            fog: FogSettings::default(),
+            volumetric: VolumetricSettings::default(),
+            ssao: SsaoSettings::default(),
+            ray_traced_shadows: RayTracedShadowSettings::default(),
+            irradiance_probes: false,
+            skeletons: true,
+            joint_names: false,
+            #[cfg(feature = "physics")]
+            physics_colliders: true,
+            #[cfg(feature = "gamepad")]
+            gamepad_dead_zone: 0.15,
+            path_tracing: false,
+            cull_mode: CullMode::Material,
+            static_batching: false,
+            rebuild_static_batches: true,
+            occlusion_culling: false,
+            pack_materials: true,
+            memory_budget: 0.0,
+            rebase_distance: 0.0,
+            recording: RecordingSettings::default(),
+            camera_bookmarks: [None; BOOKMARK_SLOTS],
+            camera_transition: 1.0,
+            import: ImportSettings::default(),
+        }
+    }
+}
+
+impl Settings {
+    pub fn battery_saving(&self) -> Self {
+        let frame_rate_limit = if self.frame_rate_limit > 0.0 {
+            self.frame_rate_limit.min(BATTERY_FRAME_RATE_LIMIT)
+        } else {
+            BATTERY_FRAME_RATE_LIMIT
+        };
+        Self {
+            frame_rate_limit,
+            render_scale: self.render_scale.min(BATTERY_RENDER_SCALE),
+            effect_quality: self.effect_quality.min(BATTERY_EFFECT_QUALITY),
+            ..self.clone()
+        }
+    }
+
+    // The settings saved between runs. Those given on the command line or in the environment only
+    // last for the run, so they're saved as they were loaded unless they were changed while running
+    pub fn to_persist(&self, loaded: &Settings, launched: &Settings) -> Self {
+        let mut settings = self.clone();
+        if self.msaa_samples == launched.msaa_samples {
+            settings.msaa_samples = loaded.msaa_samples;
+        }
+        if self.environment == launched.environment {
+            settings.environment = loaded.environment.clone();
+        }
+        if self.vsync == launched.vsync {
+            settings.vsync = loaded.vsync;
+        }
+        if self.power_saving == launched.power_saving {
+            settings.power_saving = loaded.power_saving;
+        }
+        settings
+    }
+
+    // Turns off what runs as compute shaders on devices without them, such as WebGL2. Models
+    // are then only frustum culled and the manual exposure is used
+    pub fn without_compute(&self) -> Self {
+        Self {
+            auto_exposure: AutoExposureSettings {
+                enabled: false,
+                ..self.auto_exposure
+            },
+            lens_flares: false,
+            ray_traced_shadows: RayTracedShadowSettings {
+                enabled: false,
+                ..self.ray_traced_shadows
+            },
+            path_tracing: false,
+            occlusion_culling: false,
+            ..self.clone()
+        }
+    }
+
+    pub fn set(&mut self, name: &str, value: &str) -> Result<()> {
+        match name {
+            "vsync" => self.vsync = parse_bool(value)?,
+            "msaa_samples" => self.msaa_samples = parse_samples(value)?,
+            "frame_rate_limit" => self.frame_rate_limit = parse_f32(value)?.max(0.0),
+            "render_scale" => {
+                self.render_scale = parse_f32(value)?.clamp(MIN_RENDER_SCALE, MAX_RENDER_SCALE)
+            }
+            "upscaling" => self.upscaling = value.parse()?,
+            "dynamic_resolution" => self.dynamic_resolution.enabled = parse_bool(value)?,
+            "target_frame_time" => {
+                self.dynamic_resolution.target_frame_time = parse_f32(value)?.max(1.0)
+            }
+            "dynamic_resolution_min_scale" => {
+                self.dynamic_resolution.min_scale =
+                    parse_f32(value)?.clamp(MIN_RENDER_SCALE, MAX_RENDER_SCALE)
+            }
+            "dynamic_resolution_max_scale" => {
+                self.dynamic_resolution.max_scale =
+                    parse_f32(value)?.clamp(MIN_RENDER_SCALE, MAX_RENDER_SCALE)
+            }
+            "effect_quality" => self.effect_quality = value.parse()?,
+            "power_saving" => self.power_saving = parse_bool(value)?,
+            "stats_overlay" => self.stats_overlay = parse_bool(value)?,
+            "ui_scale" => self.ui_scale = parse_f32(value)?.clamp(MIN_UI_SCALE, MAX_UI_SCALE),
+            "clear_color" => self.clear_color = parse_vec3(value)?,
+            "exposure" => self.exposure = parse_f32(value)?.max(0.0),
+            "auto_exposure_enabled" => self.auto_exposure.enabled = parse_bool(value)?,
+            "auto_exposure_min_ev" => self.auto_exposure.min_ev = parse_f32(value)?,
+            "auto_exposure_max_ev" => self.auto_exposure.max_ev = parse_f32(value)?,
+            "auto_exposure_speed_up" => self.auto_exposure.speed_up = parse_f32(value)?.max(0.0),
+            "auto_exposure_speed_down" => {
+                self.auto_exposure.speed_down = parse_f32(value)?.max(0.0)
+            }
+            "exposure_compensation" => self.auto_exposure.compensation = parse_f32(value)?,
+            "tone_mapping" => self.tone_mapping = value.parse()?,
+            "dithering" => self.dithering = parse_bool(value)?,
+            "bloom_enabled" => self.bloom.enabled = parse_bool(value)?,
+            "bloom_threshold" => self.bloom.threshold = parse_f32(value)?.max(0.0),
+            "bloom_knee" => self.bloom.knee = parse_f32(value)?.max(0.0),
+            "bloom_intensity" => self.bloom.intensity = parse_f32(value)?.max(0.0),
+            "bloom_radius" => self.bloom.radius = parse_f32(value)?.max(0.0),
+            "depth_of_field_enabled" => self.depth_of_field.enabled = parse_bool(value)?,
+            "focus_distance" => self.depth_of_field.focus_distance = parse_f32(value)?.max(0.0),
+            "aperture" => self.depth_of_field.aperture = parse_f32(value)?.max(0.0),
+            "depth_of_field_max_radius" => {
+                self.depth_of_field.max_radius = parse_f32(value)?.max(0.0)
+            }
+            "click_to_focus" => self.depth_of_field.click_to_focus = parse_bool(value)?,
+            "motion_blur_enabled" => self.motion_blur.enabled = parse_bool(value)?,
+            "motion_blur_samples" => self.motion_blur.samples = parse_u32(value)?,
+            "shutter_angle" => self.motion_blur.shutter_angle = parse_f32(value)?.clamp(0.0, 360.0),
+            "vignette" => self.imperfections.vignette = parse_f32(value)?.clamp(0.0, 1.0),
+            "chromatic_aberration" => {
+                self.imperfections.chromatic_aberration = parse_f32(value)?.max(0.0)
+            }
+            "film_grain" => self.imperfections.film_grain = parse_f32(value)?.max(0.0),
+            "lens_flares" => self.lens_flares = parse_bool(value)?,
+            "sharpen_mode" => self.sharpen.mode = value.parse()?,
+            "sharpen_strength" => self.sharpen.strength = parse_f32(value)?.clamp(0.0, 2.0),
+            "sky_enabled" => self.sky.enabled = parse_bool(value)?,
+            "time_of_day" => self.sky.time_of_day = parse_f32(value)?.rem_euclid(24.0),
+            "latitude" => self.sky.latitude = parse_f32(value)?.clamp(-90.0, 90.0),
+            "day_of_year" => self.sky.day_of_year = parse_u32(value)?.clamp(1, 365),
+            "turbidity" => self.sky.turbidity = parse_f32(value)?.clamp(2.0, 10.0),
+            "sun_intensity" => self.sky.sun_intensity = parse_f32(value)?.max(0.0),
+            "cloud_coverage" => self.sky.cloud_coverage = parse_f32(value)?.clamp(0.0, 1.0),
+            "cloud_speed" => self.sky.cloud_speed = parse_f32(value)?,
+            "sky_lighting_interval" => self.sky.lighting_interval = parse_f32(value)?.max(0.0),
+            "environment_intensity" => self.sky.environment_intensity = parse_f32(value)?.max(0.0),
+            "environment_rotation" => {
+                self.sky.environment_rotation = parse_f32(value)?.rem_euclid(360.0)
+            }
+            "fog_mode" => self.fog.mode = value.parse()?,
+            "fog_color" => self.fog.color = parse_vec3(value)?,
+            "fog_density" => self.fog.density = parse_f32(value)?,
+            "fog_start" => self.fog.start = parse_f32(value)?,
+            "fog_end" => self.fog.end = parse_f32(value)?,
+            "fog_height_density" => self.fog.height_density = parse_f32(value)?,
+            "fog_height_falloff" => self.fog.height_falloff = parse_f32(value)?,
+            "fog_base_height" => self.fog.base_height = parse_f32(value)?,
+            "fog_environment_blend" => self.fog.environment_blend = parse_f32(value)?,
+            "volumetric_enabled" => self.volumetric.enabled = parse_bool(value)?,
+            "volumetric_density" => self.volumetric.density = parse_f32(value)?,
+            "volumetric_anisotropy" => {
+                self.volumetric.anisotropy = parse_f32(value)?.clamp(-0.99, 0.99)
+            }
+            "volumetric_samples" => self.volumetric.samples = parse_u32(value)?,
+            "volumetric_max_distance" => self.volumetric.max_distance = parse_f32(value)?,
+            "ssao_enabled" => self.ssao.enabled = parse_bool(value)?,
+            "ssao_radius" => self.ssao.radius = parse_f32(value)?.max(0.0),
+            "ssao_bias" => self.ssao.bias = parse_f32(value)?,
+            "ssao_intensity" => self.ssao.intensity = parse_f32(value)?.max(0.0),
+            "ssao_samples" => self.ssao.samples = parse_u32(value)?,
+            "ray_traced_shadows" => self.ray_traced_shadows.enabled = parse_bool(value)?,
+            "ray_traced_shadow_half_resolution" => {
+                self.ray_traced_shadows.half_resolution = parse_bool(value)?
+            }
+            "ray_traced_shadow_softness" => {
+                self.ray_traced_shadows.softness = parse_f32(value)?.clamp(0.0, 10.0)
+            }
+            "irradiance_probes" => self.irradiance_probes = parse_bool(value)?,
+            "skeletons" => self.skeletons = parse_bool(value)?,
+            "joint_names" => self.joint_names = parse_bool(value)?,
+            #[cfg(feature = "physics")]
+            "physics_colliders" => self.physics_colliders = parse_bool(value)?,
+            #[cfg(feature = "gamepad")]
+            "gamepad_dead_zone" => self.gamepad_dead_zone = parse_f32(value)?.clamp(0.0, 0.9),
+            "path_tracing" => self.path_tracing = parse_bool(value)?,
+            "cull_mode" => self.cull_mode = value.parse()?,
+            "static_batching" => self.static_batching = parse_bool(value)?,
+            "rebuild_static_batches" => self.rebuild_static_batches = parse_bool(value)?,
+            "occlusion_culling" => self.occlusion_culling = parse_bool(value)?,
+            "pack_materials" => self.pack_materials = parse_bool(value)?,
+            "memory_budget" => self.memory_budget = parse_f32(value)?.max(0.0),
+            "rebase_distance" => self.rebase_distance = parse_f32(value)?.max(0.0),
+            "recording_ffmpeg" => self.recording.ffmpeg = parse_bool(value)?,
+            "recording_frame_rate" => self.recording.frame_rate = parse_f32(value)?.max(1.0),
+            "recording_offline" => self.recording.offline = parse_bool(value)?,
+            "camera_transition" => self.camera_transition = parse_f32(value)?.max(0.0),
+            "import_recenter" => self.import.recenter = parse_bool(value)?,
+            "import_normalize" => self.import.normalize = parse_bool(value)?,
+            "import_target_size" => self.import.target_size = parse_f32(value)?.max(0.001),
+            "import_hot_reload" => self.import.hot_reload = parse_bool(value)?,
+            _ => bail!("Unknown setting '{}'!", name),
+        }
+        Ok(())
+    }
+}
+
+pub fn parse_bool(value: &str) -> Result<bool> {
+    match value.trim() {
+        "true" | "on" | "1" => Ok(true),
+        "false" | "off" | "0" => Ok(false),
+        _ => bail!("Expected a boolean but found '{}'!", value),
+    }
+}
+
+pub fn parse_f32(value: &str) -> Result<f32> {
+    value
+        .trim()
+        .parse::<f32>()
+        .with_context(|| format!("Expected a number but found '{}'!", value))
+}
+
+pub fn parse_u32(value: &str) -> Result<u32> {
+    value
+        .trim()
+        .parse::<u32>()
+        .with_context(|| format!("Expected a whole number but found '{}'!", value))
+}
+
+// Multisampling is off at 1 sample, and 4 is the only other count every backend supports
+pub fn parse_samples(value: &str) -> Result<u32> {
+    match parse_u32(value)? {
+        samples @ (1 | 4) => Ok(samples),
+        samples => bail!("Expected 1 or 4 samples but found {}!", samples),
+    }
+}
+
+// Accepts three components separated by commas or whitespace
+pub fn parse_vec3(value: &str) -> Result<glm::Vec3> {
+    let components = value
+        .split(|character: char| character == ',' || character.is_whitespace())
+        .filter(|component| !component.is_empty())
+        .map(|component| {
+            component
+                .parse::<f32>()
+                .with_context(|| format!("Expected a number but found '{}'!", component))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    match components.as_slice() {
+        [x, y, z] => Ok(glm::vec3(*x, *y, *z)),
+        _ => bail!("Expected three components but found '{}'!", value),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn launch_overrides_are_not_persisted() {
+        let loaded = Settings::default();
+        let launched = Settings {
+            msaa_samples: 1,
+            vsync: !loaded.vsync,
+            environment: Some(PathBuf::from("studio.hdr")),
+            ..loaded.clone()
+        };
+
+        let mut current = launched.clone();
+        current.frame_rate_limit = 30.0;
+        let persisted = current.to_persist(&loaded, &launched);
+        assert_eq!(persisted.msaa_samples, loaded.msaa_samples);
+        assert_eq!(persisted.vsync, loaded.vsync);
+        assert_eq!(persisted.environment, loaded.environment);
+        assert_eq!(persisted.frame_rate_limit, 30.0);
+
+        // Changed again while running, so what it was changed to is kept
+        current.vsync = loaded.vsync;
+        current.environment = Some(PathBuf::from("sunset.hdr"));
+        let persisted = current.to_persist(&loaded, &launched);
+        assert_eq!(persisted.vsync, loaded.vsync);
+        assert_eq!(persisted.environment, current.environment);
+    }
+}
@@ -0,0 +1,282 @@
+use std::{
+    fmt,
+    ops::Range,
+    sync::{Arc, Mutex},
+};
+
+// Where an error was found, in the file the line was written in rather than the composed source
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShaderLocation {
+    pub file: String,
+    pub line: usize,
+    pub column: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShaderError {
+    // The shader or pipeline that failed
+    pub shader: String,
+    pub location: Option<ShaderLocation>,
+    pub message: String,
+    // The offending line as it was written, when it is known
+    pub snippet: Option<String>,
+}
+
+impl ShaderError {
+    pub fn new(shader: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            shader: shader.into(),
+            location: None,
+            message: message.into(),
+            snippet: None,
+        }
+    }
+}
+
+impl fmt::Display for ShaderError {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self.location.as_ref() {
+            Some(location) => write!(
+                formatter,
+                "{}:{}:{}: {}",
+                location.file, location.line, location.column, self.message
+            )?,
+            None => write!(formatter, "{}: {}", self.shader, self.message)?,
+        }
+        if let Some(snippet) = self.snippet.as_ref() {
+            write!(formatter, "\n    {}", snippet.trim())?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ShaderError {}
+
+// A run of lines in the composed source taken from a file
+struct Piece {
+    file: String,
+    lines: Range<usize>,
+    // The line of the file the piece starts at
+    first_line: usize,
+}
+
+// Shader source spliced together from several files, which keeps track of where each line came
+// from so errors point at what was written instead of the composed source
+pub struct ComposedShader {
+    name: String,
+    pub source: String,
+    pieces: Vec<Piece>,
+}
+
+impl ComposedShader {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            source: String::new(),
+            pieces: Vec::new(),
+        }
+    }
+
+    // Appends text starting at a 1-based line of its file
+    pub fn push(&mut self, file: impl Into<String>, text: &str, first_line: usize) {
+        if text.is_empty() {
+            return;
+        }
+        let start = self.source.lines().count();
+        self.source.push_str(text);
+        if !text.ends_with('\n') {
+            self.source.push('\n');
+        }
+        self.pieces.push(Piece {
+            file: file.into(),
+            lines: start..self.source.lines().count(),
+            first_line,
+        });
+    }
+
+    // The file and 1-based line of a 1-based line of the composed source
+    pub fn locate(&self, line: usize) -> Option<(&str, usize)> {
+        let index = line.checked_sub(1)?;
+        self.pieces
+            .iter()
+            .find(|piece| piece.lines.contains(&index))
+            .map(|piece| {
+                (
+                    piece.file.as_str(),
+                    piece.first_line + index - piece.lines.start,
+                )
+            })
+    }
+
+    // The 1-based line of the composed source declaring a function, type or global
+    pub fn declaration_line(&self, name: &str) -> Option<usize> {
+        self.source
+            .lines()
+            .position(|line| declares(line, name))
+            .map(|index| index + 1)
+    }
+
+    pub fn error_at(&self, line: Option<usize>, column: usize, message: String) -> ShaderError {
+        let mut error = ShaderError::new(self.name.clone(), message);
+        if let Some(line) = line {
+            if let Some((file, file_line)) = self.locate(line) {
+                error.location = Some(ShaderLocation {
+                    file: file.to_string(),
+                    line: file_line,
+                    column,
+                });
+            }
+            error.snippet = self
+                .source
+                .lines()
+                .nth(line - 1)
+                .map(|line| line.to_string());
+        }
+        error
+    }
+
+    pub fn validate(&self) -> Result<naga::Module, ShaderError> {
+        let module = naga::front::wgsl::parse_str(&self.source).map_err(|error| {
+            let (line, column) = error.location(&self.source);
+            // The first line of the report holds the message, the rest quotes the source
+            let report = error.emit_to_string(&self.source);
+            let message = report
+                .lines()
+                .next()
+                .unwrap_or_default()
+                .trim_start_matches("error:")
+                .trim()
+                .to_string();
+            self.error_at(Some(line), column, message)
+        })?;
+        naga::valid::Validator::new(
+            naga::valid::ValidationFlags::all(),
+            naga::valid::Capabilities::empty(),
+        )
+        .validate(&module)
+        .map_err(|error| {
+            // Validation errors carry no spans, so the item they name is looked for instead
+            let line = validation_item(&error).and_then(|name| self.declaration_line(name));
+            self.error_at(line, 1, format!("{:#}", anyhow::Error::new(error)))
+        })?;
+        Ok(module)
+    }
+}
+
+fn validation_item(error: &naga::valid::ValidationError) -> Option<&str> {
+    use naga::valid::ValidationError;
+    match error {
+        ValidationError::Type { name, .. }
+        | ValidationError::Constant { name, .. }
+        | ValidationError::GlobalVariable { name, .. }
+        | ValidationError::Function { name, .. }
+        | ValidationError::EntryPoint { name, .. } => Some(name.as_str()),
+        _ => None,
+    }
+}
+
+fn declares(line: &str, name: &str) -> bool {
+    let line = line.trim_start();
+    let line = match line.strip_prefix("[[") {
+        Some(attributes) => attributes
+            .split_once("]]")
+            .map_or(attributes, |(_, line)| line)
+            .trim_start(),
+        None => line,
+    };
+    let declared = if let Some(rest) = line.strip_prefix("fn ") {
+        rest.split('(').next()
+    } else if let Some(rest) = line.strip_prefix("struct ") {
+        rest.split(|c: char| c == '{' || c.is_whitespace()).next()
+    } else if let Some(rest) = line.strip_prefix("var") {
+        // Address spaces come before the name, as in `var<uniform> name: Type;`
+        let rest = rest
+            .split_once('>')
+            .map_or(rest, |(_, rest)| rest)
+            .trim_start();
+        rest.split(':').next()
+    } else if let Some(rest) = line.strip_prefix("let ") {
+        rest.split([':', '=']).next()
+    } else {
+        None
+    };
+    declared.is_some_and(|declared| declared.trim() == name)
+}
+
+// Every shader and GPU error reported since they were last dismissed, shown on screen instead of
+// aborting
+#[derive(Default)]
+pub struct ShaderErrors {
+    errors: Vec<ShaderError>,
+    // Errors that haven't been logged yet
+    unreported: Vec<ShaderError>,
+    // Filled by wgpu, which reports errors from whichever thread found them
+    gpu_errors: Arc<Mutex<Vec<String>>>,
+}
+
+impl ShaderErrors {
+    // Validation errors, such as pipelines that don't match their shaders, are kept instead of
+    // panicking the way wgpu does by default
+    pub fn watch_device(&self, device: &wgpu::Device) {
+        let gpu_errors = self.gpu_errors.clone();
+        device.on_uncaptured_error(move |error| {
+            if let Ok(mut gpu_errors) = gpu_errors.lock() {
+                gpu_errors.push(error.to_string());
+            }
+        });
+    }
+
+    // Failures retried every frame are only reported once
+    pub fn push(&mut self, error: ShaderError) {
+        if self.errors.contains(&error) {
+            return;
+        }
+        self.errors.push(error.clone());
+        self.unreported.push(error);
+    }
+
+    pub fn drain_reports(&mut self) -> Vec<ShaderError> {
+        let gpu_errors = match self.gpu_errors.lock() {
+            Ok(mut gpu_errors) => std::mem::take(&mut *gpu_errors),
+            Err(_) => Vec::new(),
+        };
+        for message in gpu_errors {
+            self.push(ShaderError::new("GPU", message));
+        }
+        std::mem::take(&mut self.unreported)
+    }
+
+    pub fn errors(&self) -> &[ShaderError] {
+        &self.errors
+    }
+
+    pub fn clear(&mut self) {
+        self.errors.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_the_file_and_line_of_an_injected_error() {
+        let mut shader = ComposedShader::new("Material Shader");
+        shader.push(
+            "common.wgsl",
+            "fn square(value: f32) -> f32 {\n    return value * value;\n}\n",
+            1,
+        );
+        shader.push(
+            "material.wgsl",
+            "fn shade() -> f32 {\n    let value: f32 = ;\n    return square(value);\n}\n",
+            10,
+        );
+
+        let error = shader.validate().unwrap_err();
+        let location = error.location.expect("The error should have a location");
+        assert_eq!(location.file, "material.wgsl");
+        assert_eq!(location.line, 11);
+        assert_eq!(error.snippet.as_deref(), Some("    let value: f32 = ;"));
+    }
+}
@@ -0,0 +1,95 @@
+// Code shared between shaders is written once and spliced in where a shader names it on a line
+// of its own, as in `// Spliced: standard_depth`
+const SNIPPETS: &[(&str, &str)] = &[
+    (
+        "standard_depth",
+        include_str!("shaders/standard_depth.wgsl"),
+    ),
+    (
+        "fullscreen_triangle",
+        include_str!("shaders/fullscreen_triangle.wgsl"),
+    ),
+    (
+        "far_plane_triangle",
+        include_str!("shaders/far_plane_triangle.wgsl"),
+    ),
+];
+
+const MARKER: &str = "// Spliced: ";
+
+pub fn splice(source: &str) -> String {
+    let mut spliced = String::with_capacity(source.len());
+    for line in source.lines() {
+        let snippet = line.strip_prefix(MARKER).and_then(|name| {
+            SNIPPETS
+                .iter()
+                .find(|(snippet, _)| *snippet == name.trim())
+                .map(|(_, text)| *text)
+        });
+        spliced.push_str(snippet.unwrap_or(line));
+        if snippet.is_none() {
+            spliced.push('\n');
+        }
+    }
+    spliced
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spliced_shaders_validate() {
+        let shaders = [
+            ("blit", include_str!("shaders/blit.wgsl")),
+            ("bloom", include_str!("shaders/bloom.wgsl")),
+            ("decal", include_str!("shaders/decal.wgsl")),
+            (
+                "depth_of_field",
+                include_str!("shaders/depth_of_field.wgsl"),
+            ),
+            ("environment", include_str!("shaders/environment.wgsl")),
+            ("line", include_str!("shaders/line.wgsl")),
+            ("model_cull", include_str!("shaders/model_cull.wgsl")),
+            ("motion_blur", include_str!("shaders/motion_blur.wgsl")),
+            ("particle", include_str!("shaders/particle.wgsl")),
+            (
+                "path_tracer_resolve",
+                include_str!("shaders/path_tracer_resolve.wgsl"),
+            ),
+            ("postprocess", include_str!("shaders/postprocess.wgsl")),
+            (
+                "ray_traced_shadows",
+                include_str!("shaders/ray_traced_shadows.wgsl"),
+            ),
+            (
+                "ray_traced_shadow_filter",
+                include_str!("shaders/ray_traced_shadow_filter.wgsl"),
+            ),
+            ("sharpen", include_str!("shaders/sharpen.wgsl")),
+            ("sky", include_str!("shaders/sky.wgsl")),
+            ("ssao", include_str!("shaders/ssao.wgsl")),
+            ("ssao_blur", include_str!("shaders/ssao_blur.wgsl")),
+            (
+                "temporal_upscale",
+                include_str!("shaders/temporal_upscale.wgsl"),
+            ),
+            ("text", include_str!("shaders/text.wgsl")),
+            ("upscale", include_str!("shaders/upscale.wgsl")),
+            ("velocity", include_str!("shaders/velocity.wgsl")),
+            ("water", include_str!("shaders/water.wgsl")),
+        ];
+        for (name, source) in shaders {
+            let source = splice(source);
+            assert!(!source.contains(MARKER), "{} has an unknown snippet", name);
+            let module = naga::front::wgsl::parse_str(&source)
+                .unwrap_or_else(|error| panic!("{}: {}", name, error.emit_to_string(&source)));
+            naga::valid::Validator::new(
+                naga::valid::ValidationFlags::all(),
+                naga::valid::Capabilities::all(),
+            )
+            .validate(&module)
+            .unwrap_or_else(|error| panic!("{}: {:?}", name, error));
+        }
+    }
+}
@@ -0,0 +1,24 @@
+[[block]]
+struct Blit {
+    // xy: how many times larger the target is than the image on each axis, at least one, and
+    // negative to flip the image on that axis
+    scale: vec4<f32>;
+};
+[[group(0), binding(0)]]
+var source_texture: texture_2d<f32>;
+[[group(0), binding(1)]]
+var source_sampler: sampler;
+[[group(0), binding(2)]]
+var<uniform> blit: Blit;
+
+// Spliced: fullscreen_triangle
+
+// Fits the image inside the target, leaving bars where their aspect ratios differ
+[[stage(fragment)]]
+fn fs_main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let uv = (in.uv - vec2<f32>(0.5)) * blit.scale.xy + vec2<f32>(0.5);
+    if (uv.x < 0.0 || uv.x > 1.0 || uv.y < 0.0 || uv.y > 1.0) {
+        return vec4<f32>(0.0, 0.0, 0.0, 1.0);
+    }
+    return textureSampleLevel(source_texture, source_sampler, uv, 0.0);
+}
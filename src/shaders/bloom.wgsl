@@ -0,0 +1,106 @@
+[[block]]
+struct Bloom {
+    threshold: f32;
+    knee: f32;
+    radius: f32;
+};
+[[group(0), binding(0)]]
+var source_texture: texture_2d<f32>;
+[[group(0), binding(1)]]
+var source_sampler: sampler;
+[[group(0), binding(2)]]
+var<uniform> bloom: Bloom;
+
+// Keeps single very bright pixels from flickering through the whole chain
+let MAX_BRIGHTNESS: f32 = 256.0;
+
+// Spliced: fullscreen_triangle
+
+fn luminance(color: vec3<f32>) -> f32 {
+    return dot(color, vec3<f32>(0.2126, 0.7152, 0.0722));
+}
+
+fn sample_source(uv: vec2<f32>, offset: vec2<f32>) -> vec3<f32> {
+    let texel = 1.0 / vec2<f32>(textureDimensions(source_texture));
+    return textureSample(source_texture, source_sampler, uv + offset * texel).rgb;
+}
+
+// Weights a group of samples down by its brightness so fireflies don't dominate the average
+fn karis_average(a: vec3<f32>, b: vec3<f32>, c: vec3<f32>, d: vec3<f32>) -> vec3<f32> {
+    let average = (a + b + c + d) * 0.25;
+    return average / (1.0 + luminance(average));
+}
+
+// Passes light above the threshold, easing in over the knee instead of cutting off sharply
+fn threshold(color: vec3<f32>) -> vec3<f32> {
+    let brightness = max(color.r, max(color.g, color.b));
+    let knee = max(bloom.knee, 0.0001);
+    var soft = clamp(brightness - bloom.threshold + knee, 0.0, 2.0 * knee);
+    soft = soft * soft / (4.0 * knee);
+    let contribution = max(soft, brightness - bloom.threshold) / max(brightness, 0.0001);
+    return color * contribution;
+}
+
+[[stage(fragment)]]
+fn fs_prefilter(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    // Thirteen taps arranged as five overlapping boxes
+    let a = sample_source(in.uv, vec2<f32>(-2.0, -2.0));
+    let b = sample_source(in.uv, vec2<f32>(0.0, -2.0));
+    let c = sample_source(in.uv, vec2<f32>(2.0, -2.0));
+    let d = sample_source(in.uv, vec2<f32>(-1.0, -1.0));
+    let e = sample_source(in.uv, vec2<f32>(1.0, -1.0));
+    let f = sample_source(in.uv, vec2<f32>(-2.0, 0.0));
+    let g = sample_source(in.uv, vec2<f32>(0.0, 0.0));
+    let h = sample_source(in.uv, vec2<f32>(2.0, 0.0));
+    let i = sample_source(in.uv, vec2<f32>(-1.0, 1.0));
+    let j = sample_source(in.uv, vec2<f32>(1.0, 1.0));
+    let k = sample_source(in.uv, vec2<f32>(-2.0, 2.0));
+    let l = sample_source(in.uv, vec2<f32>(0.0, 2.0));
+    let m = sample_source(in.uv, vec2<f32>(2.0, 2.0));
+
+    let center = karis_average(d, e, i, j);
+    let top_left = karis_average(a, b, f, g);
+    let top_right = karis_average(b, c, g, h);
+    let bottom_left = karis_average(f, g, k, l);
+    let bottom_right = karis_average(g, h, l, m);
+    let weights = center * 0.5 + (top_left + top_right + bottom_left + bottom_right) * 0.125;
+
+    // The Karis average is undone so the threshold applies to the original brightness
+    let color = weights / max(1.0 - luminance(weights), 0.0001);
+    return vec4<f32>(threshold(min(color, vec3<f32>(MAX_BRIGHTNESS))), 1.0);
+}
+
+[[stage(fragment)]]
+fn fs_downsample(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let a = sample_source(in.uv, vec2<f32>(-2.0, -2.0));
+    let b = sample_source(in.uv, vec2<f32>(0.0, -2.0));
+    let c = sample_source(in.uv, vec2<f32>(2.0, -2.0));
+    let d = sample_source(in.uv, vec2<f32>(-1.0, -1.0));
+    let e = sample_source(in.uv, vec2<f32>(1.0, -1.0));
+    let f = sample_source(in.uv, vec2<f32>(-2.0, 0.0));
+    let g = sample_source(in.uv, vec2<f32>(0.0, 0.0));
+    let h = sample_source(in.uv, vec2<f32>(2.0, 0.0));
+    let i = sample_source(in.uv, vec2<f32>(-1.0, 1.0));
+    let j = sample_source(in.uv, vec2<f32>(1.0, 1.0));
+    let k = sample_source(in.uv, vec2<f32>(-2.0, 2.0));
+    let l = sample_source(in.uv, vec2<f32>(0.0, 2.0));
+    let m = sample_source(in.uv, vec2<f32>(2.0, 2.0));
+
+    var color = (d + e + i + j) * 0.125;
+    color = color + (a + c + k + m) * 0.03125;
+    color = color + (b + f + h + l) * 0.0625;
+    color = color + g * 0.125;
+    return vec4<f32>(color, 1.0);
+}
+
+// A 3x3 tent filter scaled by the radius, added onto the larger level
+[[stage(fragment)]]
+fn fs_upsample(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let radius = bloom.radius;
+    var color = sample_source(in.uv, vec2<f32>(0.0, 0.0)) * 4.0;
+    color = color + (sample_source(in.uv, vec2<f32>(-radius, 0.0)) + sample_source(in.uv, vec2<f32>(radius, 0.0))) * 2.0;
+    color = color + (sample_source(in.uv, vec2<f32>(0.0, -radius)) + sample_source(in.uv, vec2<f32>(0.0, radius))) * 2.0;
+    color = color + sample_source(in.uv, vec2<f32>(-radius, -radius)) + sample_source(in.uv, vec2<f32>(radius, -radius));
+    color = color + sample_source(in.uv, vec2<f32>(-radius, radius)) + sample_source(in.uv, vec2<f32>(radius, radius));
+    return vec4<f32>(color / 16.0, 1.0);
+}
@@ -0,0 +1,122 @@
+[[block]]
+struct Camera {
+    view: mat4x4<f32>;
+    projection: mat4x4<f32>;
+    inverse_view_projection: mat4x4<f32>;
+    position: vec4<f32>;
+    parameters: vec4<f32>;
+    previous_view_projection: mat4x4<f32>;
+    jitter: vec4<f32>;
+    depth: vec4<f32>;
+};
+[[group(0), binding(0)]]
+var<uniform> camera: Camera;
+
+// Spliced: standard_depth
+
+[[block]]
+struct Decal {
+    model: mat4x4<f32>;
+    inverse_model: mat4x4<f32>;
+    color: vec4<f32>;
+    opacity: f32;
+    normal_strength: f32;
+    angle_threshold: f32;
+    blend_mode: u32;
+};
+[[group(1), binding(0)]]
+var<uniform> decal: Decal;
+[[group(1), binding(1)]]
+var albedo_texture: texture_2d<f32>;
+[[group(1), binding(2)]]
+var normal_texture: texture_2d<f32>;
+[[group(1), binding(3)]]
+var decal_sampler: sampler;
+
+[[group(2), binding(0)]]
+var depth_texture: texture_depth_2d;
+
+let BLEND_MODE_MULTIPLY: u32 = 2u;
+
+struct VertexOutput {
+    [[builtin(position)]] clip_position: vec4<f32>;
+};
+
+[[stage(vertex)]]
+fn vs_main([[builtin(vertex_index)]] vertex_index: u32) -> VertexOutput {
+    var corners = array<vec3<f32>, 8>(
+        vec3<f32>(-0.5, -0.5, -0.5),
+        vec3<f32>(0.5, -0.5, -0.5),
+        vec3<f32>(0.5, 0.5, -0.5),
+        vec3<f32>(-0.5, 0.5, -0.5),
+        vec3<f32>(-0.5, -0.5, 0.5),
+        vec3<f32>(0.5, -0.5, 0.5),
+        vec3<f32>(0.5, 0.5, 0.5),
+        vec3<f32>(-0.5, 0.5, 0.5),
+    );
+    var indices = array<u32, 36>(
+        0u, 2u, 1u, 0u, 3u, 2u,
+        4u, 5u, 6u, 4u, 6u, 7u,
+        0u, 1u, 5u, 0u, 5u, 4u,
+        3u, 6u, 2u, 3u, 7u, 6u,
+        0u, 4u, 7u, 0u, 7u, 3u,
+        1u, 2u, 6u, 1u, 6u, 5u,
+    );
+
+    let index = indices[vertex_index];
+    let position = corners[index];
+    var output: VertexOutput;
+    output.clip_position = camera.projection * camera.view * decal.model * vec4<f32>(position, 1.0);
+    return output;
+}
+
+[[stage(fragment)]]
+fn fs_main(input: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let depth = textureLoad(depth_texture, vec2<i32>(input.clip_position.xy), 0);
+
+    // Reconstruct the world position of the opaque surface behind this pixel
+    let screen_uv = input.clip_position.xy / camera.parameters.zw;
+    let ndc = vec4<f32>(screen_uv.x * 2.0 - 1.0, 1.0 - screen_uv.y * 2.0, depth, 1.0);
+    let world = camera.inverse_view_projection * ndc;
+    let world_position = world.xyz / world.w;
+
+    var geometry_normal = normalize(cross(dpdy(world_position), dpdx(world_position)));
+    let view_direction = normalize(camera.position.xyz - world_position);
+    if (dot(geometry_normal, view_direction) < 0.0) {
+        geometry_normal = -geometry_normal;
+    }
+
+    let local = (decal.inverse_model * vec4<f32>(world_position, 1.0)).xyz;
+    let uv = vec2<f32>(local.x + 0.5, local.z + 0.5);
+    let albedo = textureSample(albedo_texture, decal_sampler, uv) * decal.color;
+    let tangent_normal = textureSample(normal_texture, decal_sampler, uv).xyz * 2.0 - 1.0;
+
+    if (standard_depth(depth) >= 1.0 || any(abs(local) > vec3<f32>(0.5))) {
+        discard;
+    }
+
+    // Decals project along their local -Y axis
+    let projection_axis = normalize((decal.model * vec4<f32>(0.0, 1.0, 0.0, 0.0)).xyz);
+    let facing = dot(geometry_normal, projection_axis);
+    if (facing < decal.angle_threshold) {
+        discard;
+    }
+    let angle_fade = clamp((facing - decal.angle_threshold) / max(1.0 - decal.angle_threshold, 0.0001), 0.0, 1.0);
+
+    // Without scene lighting, bumps are shaded relative to the viewer
+    let tangent_axis = normalize((decal.model * vec4<f32>(1.0, 0.0, 0.0, 0.0)).xyz);
+    let tangent = normalize(tangent_axis - geometry_normal * dot(geometry_normal, tangent_axis));
+    let bitangent = cross(geometry_normal, tangent);
+    let normal = normalize(
+        tangent * tangent_normal.x + bitangent * tangent_normal.y + geometry_normal * tangent_normal.z
+    );
+    let bump = max(dot(normal, view_direction), 0.0) / max(dot(geometry_normal, view_direction), 0.0001);
+    let shading = mix(1.0, bump, decal.normal_strength);
+
+    let alpha = albedo.a * decal.opacity * angle_fade;
+    let color = albedo.rgb * shading;
+    if (decal.blend_mode == BLEND_MODE_MULTIPLY) {
+        return vec4<f32>(mix(vec3<f32>(1.0), color, alpha), 1.0);
+    }
+    return vec4<f32>(color, alpha);
+}
@@ -0,0 +1,120 @@
+[[block]]
+struct Camera {
+    view: mat4x4<f32>;
+    projection: mat4x4<f32>;
+    inverse_view_projection: mat4x4<f32>;
+    position: vec4<f32>;
+    parameters: vec4<f32>;
+    previous_view_projection: mat4x4<f32>;
+    jitter: vec4<f32>;
+    depth: vec4<f32>;
+};
+[[group(0), binding(0)]]
+var<uniform> camera: Camera;
+
+// Spliced: standard_depth
+
+[[block]]
+struct DepthOfField {
+    focus_distance: f32;
+    aperture: f32;
+    max_radius: f32;
+    samples: u32;
+};
+[[group(1), binding(0)]]
+var source_texture: texture_2d<f32>;
+[[group(1), binding(1)]]
+var source_sampler: sampler;
+[[group(1), binding(2)]]
+var depth_texture: texture_depth_2d;
+[[group(1), binding(3)]]
+var<uniform> depth_of_field: DepthOfField;
+[[group(1), binding(4)]]
+var far_texture: texture_2d<f32>;
+[[group(1), binding(5)]]
+var near_texture: texture_2d<f32>;
+
+let GOLDEN_ANGLE: f32 = 2.39996323;
+
+// Spliced: fullscreen_triangle
+
+// Distance along the view direction of a depth buffer value
+fn view_depth(depth: f32) -> f32 {
+    let near = camera.parameters.x;
+    let far = camera.parameters.y;
+    return near * far / (far - standard_depth(depth) * (far - near));
+}
+
+// Radius of the circle of confusion in full resolution pixels, negative in front of the focus
+fn circle_of_confusion(pixel: vec2<i32>) -> f32 {
+    let distance = view_depth(textureLoad(depth_texture, pixel, 0));
+    let coc = depth_of_field.aperture * (1.0 - depth_of_field.focus_distance / distance);
+    return clamp(coc, -depth_of_field.max_radius, depth_of_field.max_radius);
+}
+
+// Halves the scene color and stores the circle of confusion of the nearest of the four pixels,
+// which lets the near field spread over the in focus pixels behind it
+[[stage(fragment)]]
+fn fs_prefilter(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let color = textureSample(source_texture, source_sampler, in.uv).rgb;
+    let pixel = vec2<i32>(in.clip_position.xy) * 2;
+    let limit = textureDimensions(depth_texture) - vec2<i32>(1);
+    let a = circle_of_confusion(min(pixel, limit));
+    let b = circle_of_confusion(min(pixel + vec2<i32>(1, 0), limit));
+    let c = circle_of_confusion(min(pixel + vec2<i32>(0, 1), limit));
+    let d = circle_of_confusion(min(pixel + vec2<i32>(1, 1), limit));
+    return vec4<f32>(color, min(min(a, b), min(c, d)));
+}
+
+struct GatherOutput {
+    [[location(0)]] far: vec4<f32>;
+    [[location(1)]] near: vec4<f32>;
+};
+
+// Gathers a disk of samples spread along a golden angle spiral. Each sample only contributes
+// where its own circle of confusion reaches this pixel, which keeps sharp edges from bleeding
+[[stage(fragment)]]
+fn fs_gather(in: VertexOutput) -> GatherOutput {
+    let center = textureSampleLevel(source_texture, source_sampler, in.uv, 0.0);
+    // The circles of confusion are measured in full resolution pixels
+    let texel = 0.5 / vec2<f32>(textureDimensions(source_texture));
+
+    var far_color = vec3<f32>(0.0);
+    var far_weight = 0.0;
+    var near_color = vec3<f32>(0.0);
+    var near_weight = 0.0;
+    for (var index = 0u; index < depth_of_field.samples; index = index + 1u) {
+        let t = (f32(index) + 0.5) / f32(depth_of_field.samples);
+        let radius = sqrt(t) * depth_of_field.max_radius;
+        let angle = f32(index) * GOLDEN_ANGLE;
+        let offset = vec2<f32>(cos(angle), sin(angle)) * radius;
+        let sample = textureSampleLevel(source_texture, source_sampler, in.uv + offset * texel, 0.0);
+
+        let far = clamp(sample.a - radius + 1.0, 0.0, 1.0);
+        far_color = far_color + sample.rgb * far;
+        far_weight = far_weight + far;
+
+        let near = clamp(-sample.a - radius + 1.0, 0.0, 1.0);
+        near_color = near_color + sample.rgb * near;
+        near_weight = near_weight + near;
+    }
+
+    var out: GatherOutput;
+    out.far = vec4<f32>(select(center.rgb, far_color / max(far_weight, 0.0001), far_weight > 0.0), 1.0);
+    // Coverage fades the blurred foreground out towards its edges
+    let coverage = clamp(near_weight * 4.0 / f32(max(depth_of_field.samples, 1u)), 0.0, 1.0);
+    out.near = vec4<f32>(select(center.rgb, near_color / max(near_weight, 0.0001), near_weight > 0.0), coverage);
+    return out;
+}
+
+[[stage(fragment)]]
+fn fs_composite(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let sharp = textureSample(source_texture, source_sampler, in.uv).rgb;
+    let far = textureSample(far_texture, source_sampler, in.uv).rgb;
+    let near = textureSample(near_texture, source_sampler, in.uv);
+    let coc = circle_of_confusion(vec2<i32>(in.clip_position.xy));
+
+    var color = mix(sharp, far, clamp(coc - 0.5, 0.0, 1.0));
+    color = mix(color, near.rgb, max(near.a, clamp(-coc - 0.5, 0.0, 1.0)));
+    return vec4<f32>(color, 1.0);
+}
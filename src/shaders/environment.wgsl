@@ -0,0 +1,43 @@
+[[block]]
+struct Camera {
+    view: mat4x4<f32>;
+    projection: mat4x4<f32>;
+    inverse_view_projection: mat4x4<f32>;
+    position: vec4<f32>;
+    parameters: vec4<f32>;
+    previous_view_projection: mat4x4<f32>;
+    jitter: vec4<f32>;
+    depth: vec4<f32>;
+};
+[[group(0), binding(0)]]
+var<uniform> camera: Camera;
+
+[[block]]
+struct Environment {
+    // x: scale into scene color, y: turn about the up axis in radians
+    parameters: vec4<f32>;
+};
+[[group(1), binding(0)]]
+var<uniform> environment: Environment;
+[[group(1), binding(1)]]
+var environment_texture: texture_2d<f32>;
+[[group(1), binding(2)]]
+var environment_sampler: sampler;
+
+let PI: f32 = 3.14159265359;
+
+// Spliced: far_plane_triangle
+
+[[stage(fragment)]]
+fn fs_main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let far = camera.inverse_view_projection * vec4<f32>(in.ndc, far_depth(), 1.0);
+    let direction = normalize(far.xyz / far.w - camera.position.xyz);
+
+    // Longitude from -Z towards +X, with the middle of the image straight ahead
+    let longitude = atan2(direction.x, -direction.z) + environment.parameters.y;
+    let latitude = acos(clamp(direction.y, -1.0, 1.0));
+    let uv = vec2<f32>(longitude / (2.0 * PI) + 0.5, latitude / PI);
+    // Sampled without derivatives, which jump where the longitude wraps around
+    let color = textureSampleLevel(environment_texture, environment_sampler, uv, 0.0).rgb;
+    return vec4<f32>(color * environment.parameters.x, 1.0);
+}
@@ -0,0 +1,100 @@
+[[block]]
+struct Exposure {
+    // x: log2 luminance of the first bin, y: log2 luminance range covered by the bins
+    log_luminance: vec4<f32>;
+    // x: delta time, y: adaptation speed up, z: adaptation speed down, w: compensation in stops
+    adaptation: vec4<f32>;
+};
+[[group(0), binding(0)]]
+var<uniform> exposure: Exposure;
+[[group(0), binding(1)]]
+var scene_texture: texture_2d<f32>;
+
+[[block]]
+struct Histogram {
+    bins: array<atomic<u32>, 256>;
+};
+[[group(0), binding(2)]]
+var<storage, read_write> histogram: Histogram;
+
+[[block]]
+struct Adapted {
+    // Average luminance the eye has adapted to so far
+    luminance: f32;
+    // Multiplies the scene color before tone mapping
+    exposure: f32;
+};
+[[group(0), binding(3)]]
+var<storage, read_write> adapted: Adapted;
+
+let BIN_COUNT: u32 = 256u;
+// Luminance the average of the scene is exposed to
+let MIDDLE_GRAY: f32 = 0.18;
+
+var<workgroup> local_bins: array<atomic<u32>, 256>;
+var<workgroup> weighted: array<f32, 256>;
+
+// Pixels too dark to measure land in the first bin, which is left out of the average
+fn bin_of(color: vec3<f32>) -> u32 {
+    let luminance = dot(color, vec3<f32>(0.2126, 0.7152, 0.0722));
+    if (luminance < 0.0001) {
+        return 0u;
+    }
+    let amount = clamp((log2(luminance) - exposure.log_luminance.x) / exposure.log_luminance.y, 0.0, 1.0);
+    return u32(amount * 254.0 + 1.0);
+}
+
+[[stage(compute), workgroup_size(16, 16)]]
+fn cs_histogram(
+    [[builtin(global_invocation_id)]] global_id: vec3<u32>,
+    [[builtin(local_invocation_index)]] local_index: u32,
+) {
+    atomicStore(&local_bins[local_index], 0u);
+    workgroupBarrier();
+
+    let dimensions = vec2<u32>(textureDimensions(scene_texture));
+    if (global_id.x < dimensions.x && global_id.y < dimensions.y) {
+        let color = textureLoad(scene_texture, vec2<i32>(global_id.xy), 0).rgb;
+        let previous = atomicAdd(&local_bins[bin_of(color)], 1u);
+    }
+    workgroupBarrier();
+
+    let previous = atomicAdd(&histogram.bins[local_index], atomicLoad(&local_bins[local_index]));
+}
+
+// Averages the histogram and moves the adapted luminance towards it, then clears the bins
+[[stage(compute), workgroup_size(256)]]
+fn cs_average([[builtin(local_invocation_index)]] local_index: u32) {
+    let count = atomicExchange(&histogram.bins[local_index], 0u);
+    weighted[local_index] = f32(count) * f32(local_index);
+    atomicStore(&local_bins[local_index], count);
+    workgroupBarrier();
+
+    for (var stride = BIN_COUNT / 2u; stride > 0u; stride = stride >> 1u) {
+        if (local_index < stride) {
+            weighted[local_index] = weighted[local_index] + weighted[local_index + stride];
+        }
+        workgroupBarrier();
+    }
+
+    if (local_index == 0u) {
+        var measured = 0u;
+        for (var bin = 1u; bin < BIN_COUNT; bin = bin + 1u) {
+            measured = measured + atomicLoad(&local_bins[bin]);
+        }
+        if (measured == 0u) {
+            return;
+        }
+
+        let average_bin = weighted[0] / f32(measured) - 1.0;
+        let log_luminance = average_bin / 254.0 * exposure.log_luminance.y + exposure.log_luminance.x;
+        let target = exp2(log_luminance);
+
+        // Eyes adjust to brightness faster than to darkness
+        let speed = select(exposure.adaptation.z, exposure.adaptation.y, target > adapted.luminance);
+        let amount = 1.0 - exp(-exposure.adaptation.x * speed);
+        let luminance = max(adapted.luminance + (target - adapted.luminance) * amount, 0.0001);
+        adapted.luminance = luminance;
+        adapted.exposure = MIDDLE_GRAY / luminance * exp2(exposure.adaptation.w);
+    }
+}
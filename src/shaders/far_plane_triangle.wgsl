@@ -0,0 +1,19 @@
+struct VertexOutput {
+    [[builtin(position)]] clip_position: vec4<f32>;
+    [[location(0)]] ndc: vec2<f32>;
+};
+
+// The far plane is at zero when depth is reversed
+fn far_depth() -> f32 {
+    return select(1.0, 0.0, camera.depth.x > 0.0);
+}
+
+// A single triangle covering the whole target on the far plane
+[[stage(vertex)]]
+fn vs_main([[builtin(vertex_index)]] vertex_index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+    var out: VertexOutput;
+    out.ndc = vec2<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0);
+    out.clip_position = vec4<f32>(out.ndc, far_depth(), 1.0);
+    return out;
+}
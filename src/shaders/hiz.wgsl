@@ -0,0 +1,57 @@
+// Each texel of a level holds the farthest depth of the texels it covers in the level above, so
+// anything nearer than it is in front of everything drawn there
+[[group(0), binding(0)]]
+var depth_texture: texture_depth_2d;
+[[group(0), binding(1)]]
+var source: texture_2d<f32>;
+[[group(0), binding(2)]]
+var destination: texture_storage_2d<r32float, write>;
+
+// The pyramid's first level is a power of two at least as large as the depth, which is stretched
+// to fill it by repeating its last row and column
+[[stage(compute), workgroup_size(8, 8)]]
+fn cs_copy_depth([[builtin(global_invocation_id)]] id: vec3<u32>) {
+    let size = textureDimensions(destination);
+    if (i32(id.x) >= size.x || i32(id.y) >= size.y) {
+        return;
+    }
+    let depth_size = textureDimensions(depth_texture);
+    let coordinates = min(vec2<i32>(id.xy), depth_size - vec2<i32>(1));
+    let depth = textureLoad(depth_texture, coordinates, 0);
+    textureStore(destination, vec2<i32>(id.xy), vec4<f32>(depth, 0.0, 0.0, 0.0));
+}
+
+// The four texels of the level above that a texel covers
+fn gather_source(id: vec2<u32>) -> vec4<f32> {
+    // Levels stop halving along an axis once it's a single texel
+    let last = textureDimensions(source) - vec2<i32>(1);
+    let corner = min(vec2<i32>(id) * 2, last);
+    let far = min(corner + vec2<i32>(1), last);
+    return vec4<f32>(
+        textureLoad(source, corner, 0).r,
+        textureLoad(source, vec2<i32>(far.x, corner.y), 0).r,
+        textureLoad(source, vec2<i32>(corner.x, far.y), 0).r,
+        textureLoad(source, far, 0).r,
+    );
+}
+
+[[stage(compute), workgroup_size(8, 8)]]
+fn cs_downsample([[builtin(global_invocation_id)]] id: vec3<u32>) {
+    let size = textureDimensions(destination);
+    if (i32(id.x) >= size.x || i32(id.y) >= size.y) {
+        return;
+    }
+    let texels = gather_source(id.xy);
+    textureStore(destination, vec2<i32>(id.xy), vec4<f32>(max(max(texels.x, texels.y), max(texels.z, texels.w)), 0.0, 0.0, 0.0));
+}
+
+// With reversed depth the farthest is the least
+[[stage(compute), workgroup_size(8, 8)]]
+fn cs_downsample_reversed([[builtin(global_invocation_id)]] id: vec3<u32>) {
+    let size = textureDimensions(destination);
+    if (i32(id.x) >= size.x || i32(id.y) >= size.y) {
+        return;
+    }
+    let texels = gather_source(id.xy);
+    textureStore(destination, vec2<i32>(id.xy), vec4<f32>(min(min(texels.x, texels.y), min(texels.z, texels.w)), 0.0, 0.0, 0.0));
+}
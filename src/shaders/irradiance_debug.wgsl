@@ -0,0 +1,66 @@
+[[block]]
+struct Camera {
+    view: mat4x4<f32>;
+    projection: mat4x4<f32>;
+    inverse_view_projection: mat4x4<f32>;
+    position: vec4<f32>;
+    parameters: vec4<f32>;
+};
+[[group(0), binding(0)]]
+var<uniform> camera: Camera;
+
+[[block]]
+struct Coefficients {
+    values: array<vec4<f32>>;
+};
+[[block]]
+struct IrradianceVolume {
+    // w: one once baked
+    min: vec4<f32>;
+    // w: sphere radius
+    max: vec4<f32>;
+    counts: vec4<u32>;
+};
+[[group(1), binding(0)]]
+var<storage, read> coefficients: Coefficients;
+[[group(1), binding(1)]]
+var<uniform> volume: IrradianceVolume;
+
+struct VertexOutput {
+    [[builtin(position)]] clip_position: vec4<f32>;
+    [[location(0)]] normal: vec3<f32>;
+    [[location(1), interpolate(flat)]] probe: u32;
+};
+
+[[stage(vertex)]]
+fn vs_main([[location(0)]] position: vec3<f32>, [[builtin(instance_index)]] probe: u32) -> VertexOutput {
+    let counts = volume.counts.xyz;
+    let cell = vec3<u32>(probe % counts.x, probe / counts.x % counts.y, probe / (counts.x * counts.y));
+    let steps = vec3<f32>(max(counts, vec3<u32>(2u)) - vec3<u32>(1u));
+    var fraction = vec3<f32>(cell) / steps;
+    fraction = select(fraction, vec3<f32>(0.5), counts == vec3<u32>(1u));
+    let center = mix(volume.min.xyz, volume.max.xyz, fraction);
+
+    var out: VertexOutput;
+    out.clip_position = camera.projection * camera.view * vec4<f32>(center + position * volume.max.w, 1.0);
+    out.normal = position;
+    out.probe = probe;
+    return out;
+}
+
+// What a white diffuse surface facing the normal would reflect
+[[stage(fragment)]]
+fn fs_main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let n = normalize(in.normal);
+    let first = in.probe * 9u;
+    var irradiance = coefficients.values[first].rgb * 0.886227;
+    irradiance = irradiance + coefficients.values[first + 1u].rgb * 1.023328 * n.y;
+    irradiance = irradiance + coefficients.values[first + 2u].rgb * 1.023328 * n.z;
+    irradiance = irradiance + coefficients.values[first + 3u].rgb * 1.023328 * n.x;
+    irradiance = irradiance + coefficients.values[first + 4u].rgb * 0.858086 * n.x * n.y;
+    irradiance = irradiance + coefficients.values[first + 5u].rgb * 0.858086 * n.y * n.z;
+    irradiance = irradiance + coefficients.values[first + 6u].rgb * 0.247708 * (3.0 * n.z * n.z - 1.0);
+    irradiance = irradiance + coefficients.values[first + 7u].rgb * 0.858086 * n.x * n.z;
+    irradiance = irradiance + coefficients.values[first + 8u].rgb * 0.429043 * (n.x * n.x - n.y * n.y);
+    return vec4<f32>(max(irradiance / 3.14159265, vec3<f32>(0.0)), 1.0);
+}
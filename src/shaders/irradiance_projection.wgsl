@@ -0,0 +1,96 @@
+[[block]]
+struct Coefficients {
+    // Nine per probe, rgb
+    values: array<vec4<f32>>;
+};
+[[block]]
+struct Projection {
+    // x: the probe being baked
+    probe: vec4<u32>;
+};
+[[group(0), binding(0)]]
+var capture_texture: texture_2d_array<f32>;
+[[group(0), binding(1)]]
+var<storage, read_write> coefficients: Coefficients;
+[[group(0), binding(2)]]
+var<uniform> projection: Projection;
+
+let PI: f32 = 3.14159265;
+let THREADS: u32 = 64u;
+
+// Each thread's sums, nine coefficients apiece with the total weight in the first's w
+var<workgroup> partial_sums: array<vec4<f32>, 576>;
+
+// Matches the face directions the probes are captured with
+fn face_forward(face: u32) -> vec3<f32> {
+    switch (i32(face)) {
+        case 0: { return vec3<f32>(1.0, 0.0, 0.0); }
+        case 1: { return vec3<f32>(-1.0, 0.0, 0.0); }
+        case 2: { return vec3<f32>(0.0, 1.0, 0.0); }
+        case 3: { return vec3<f32>(0.0, -1.0, 0.0); }
+        case 4: { return vec3<f32>(0.0, 0.0, 1.0); }
+        default: { return vec3<f32>(0.0, 0.0, -1.0); }
+    }
+}
+
+fn face_up(face: u32) -> vec3<f32> {
+    switch (i32(face)) {
+        case 2: { return vec3<f32>(0.0, 0.0, -1.0); }
+        case 3: { return vec3<f32>(0.0, 0.0, 1.0); }
+        default: { return vec3<f32>(0.0, 1.0, 0.0); }
+    }
+}
+
+// Projects every texel of the captured faces onto the spherical harmonic basis, weighted by the
+// solid angle it covers
+[[stage(compute), workgroup_size(64)]]
+fn main([[builtin(local_invocation_index)]] thread: u32) {
+    let base = thread * 9u;
+    for (var index = 0u; index < 9u; index = index + 1u) {
+        partial_sums[base + index] = vec4<f32>(0.0);
+    }
+
+    let size = textureDimensions(capture_texture);
+    let face_texels = u32(size.x * size.y);
+    for (var texel = thread; texel < face_texels * 6u; texel = texel + THREADS) {
+        let face = texel / face_texels;
+        let pixel = vec2<i32>(i32(texel % face_texels) % size.x, i32(texel % face_texels) / size.x);
+        let st = (vec2<f32>(pixel) + vec2<f32>(0.5)) / vec2<f32>(size) * 2.0 - vec2<f32>(1.0);
+        let forward = face_forward(face);
+        let up = face_up(face);
+        let right = cross(forward, up);
+        let d = normalize(forward + right * st.x - up * st.y);
+
+        let weight = 1.0 / pow(1.0 + dot(st, st), 1.5);
+        let color = textureLoad(capture_texture, pixel, i32(face), 0).rgb * weight;
+
+        partial_sums[base] = partial_sums[base] + vec4<f32>(color * 0.282095, weight);
+        partial_sums[base + 1u] = partial_sums[base + 1u] + vec4<f32>(color * 0.488603 * d.y, 0.0);
+        partial_sums[base + 2u] = partial_sums[base + 2u] + vec4<f32>(color * 0.488603 * d.z, 0.0);
+        partial_sums[base + 3u] = partial_sums[base + 3u] + vec4<f32>(color * 0.488603 * d.x, 0.0);
+        partial_sums[base + 4u] = partial_sums[base + 4u] + vec4<f32>(color * 1.092548 * d.x * d.y, 0.0);
+        partial_sums[base + 5u] = partial_sums[base + 5u] + vec4<f32>(color * 1.092548 * d.y * d.z, 0.0);
+        partial_sums[base + 6u] = partial_sums[base + 6u] + vec4<f32>(color * 0.315392 * (3.0 * d.z * d.z - 1.0), 0.0);
+        partial_sums[base + 7u] = partial_sums[base + 7u] + vec4<f32>(color * 1.092548 * d.x * d.z, 0.0);
+        partial_sums[base + 8u] = partial_sums[base + 8u] + vec4<f32>(color * 0.546274 * (d.x * d.x - d.y * d.y), 0.0);
+    }
+
+    workgroupBarrier();
+
+    if (thread == 0u) {
+        var total_weight = 0.0;
+        for (var other = 0u; other < THREADS; other = other + 1u) {
+            total_weight = total_weight + partial_sums[other * 9u].w;
+        }
+        // The weights cover the whole sphere
+        let scale = 4.0 * PI / max(total_weight, 0.0001);
+        let first = projection.probe.x * 9u;
+        for (var index = 0u; index < 9u; index = index + 1u) {
+            var sum = vec3<f32>(0.0);
+            for (var other = 0u; other < THREADS; other = other + 1u) {
+                sum = sum + partial_sums[other * 9u + index].rgb;
+            }
+            coefficients.values[first + index] = vec4<f32>(sum * scale, 0.0);
+        }
+    }
+}
@@ -0,0 +1,22 @@
+[[block]]
+struct Light {
+    view_projection: mat4x4<f32>;
+    direction: vec4<f32>;
+    color: vec4<f32>;
+};
+[[group(0), binding(0)]]
+var<uniform> light: Light;
+
+[[block]]
+struct Isosurface {
+    model: mat4x4<f32>;
+    normal_matrix: mat4x4<f32>;
+    color: vec4<f32>;
+};
+[[group(1), binding(0)]]
+var<uniform> surface: Isosurface;
+
+[[stage(vertex)]]
+fn vs_main([[location(0)]] position: vec3<f32>) -> [[builtin(position)]] vec4<f32> {
+    return light.view_projection * surface.model * vec4<f32>(position, 1.0);
+}
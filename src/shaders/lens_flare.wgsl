@@ -0,0 +1,72 @@
+[[block]]
+struct LensFlares {
+    // x: delta time, y: aspect ratio, z: flare count, w: one when depth is reversed
+    parameters: vec4<f32>;
+};
+[[group(0), binding(0)]]
+var<uniform> lens_flares: LensFlares;
+
+struct FlareSource {
+    // xy: normalized device coordinates, z: depth, w: one when in front of the camera
+    position: vec4<f32>;
+    // rgb: color scaled by intensity, w: occlusion radius in pixels
+    color: vec4<f32>;
+};
+
+[[block]]
+struct Sources {
+    sources: array<FlareSource>;
+};
+[[group(0), binding(1)]]
+var<storage, read> sources: Sources;
+
+[[block]]
+struct Visibility {
+    visibility: array<f32>;
+};
+[[group(0), binding(2)]]
+var<storage, read_write> visibility: Visibility;
+
+[[group(0), binding(3)]]
+var depth_texture: texture_depth_2d;
+
+let FADE_SPEED: f32 = 8.0;
+let OCCLUSION_SAMPLES: u32 = 64u;
+
+var<workgroup> visible_samples: atomic<u32>;
+
+// One workgroup per flare tests a grid of depth samples around its source
+[[stage(compute), workgroup_size(8, 8)]]
+fn cs_occlusion(
+    [[builtin(workgroup_id)]] workgroup_id: vec3<u32>,
+    [[builtin(local_invocation_id)]] local_id: vec3<u32>,
+    [[builtin(local_invocation_index)]] local_index: u32,
+) {
+    let index = workgroup_id.x;
+    let source = sources.sources[index];
+    if (local_index == 0u) {
+        atomicStore(&visible_samples, 0u);
+    }
+    workgroupBarrier();
+
+    let dimensions = textureDimensions(depth_texture);
+    let center = vec2<f32>(source.position.x * 0.5 + 0.5, 0.5 - source.position.y * 0.5) * vec2<f32>(dimensions);
+    let offset = (vec2<f32>(local_id.xy) + vec2<f32>(0.5)) / 4.0 - vec2<f32>(1.0);
+    let pixel = vec2<i32>(center + offset * source.color.w);
+    let inside = all(pixel >= vec2<i32>(0)) && all(pixel < dimensions);
+    if (inside && source.position.w > 0.0) {
+        // The sun is only seen where nothing was drawn. Sources have standard depth
+        let depth = textureLoad(depth_texture, pixel, 0);
+        let scene_depth = select(depth, 1.0 - depth, lens_flares.parameters.w > 0.0);
+        if (scene_depth >= min(source.position.z, 1.0)) {
+            let previous = atomicAdd(&visible_samples, 1u);
+        }
+    }
+    workgroupBarrier();
+
+    if (local_index == 0u) {
+        let target = f32(atomicLoad(&visible_samples)) / f32(OCCLUSION_SAMPLES);
+        let blend = 1.0 - exp(-lens_flares.parameters.x * FADE_SPEED);
+        visibility.visibility[index] = mix(visibility.visibility[index], target, blend);
+    }
+}
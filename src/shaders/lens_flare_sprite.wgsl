@@ -0,0 +1,96 @@
+[[block]]
+struct LensFlares {
+    // x: delta time, y: aspect ratio, z: flare count
+    parameters: vec4<f32>;
+};
+[[group(0), binding(0)]]
+var<uniform> lens_flares: LensFlares;
+
+struct FlareSource {
+    // xy: normalized device coordinates, z: depth, w: one when in front of the camera
+    position: vec4<f32>;
+    // rgb: color scaled by intensity, w: occlusion radius in pixels
+    color: vec4<f32>;
+};
+
+[[block]]
+struct Sources {
+    sources: array<FlareSource>;
+};
+[[group(0), binding(1)]]
+var<storage, read> sources: Sources;
+
+[[block]]
+struct Visibility {
+    visibility: array<f32>;
+};
+[[group(0), binding(2)]]
+var<storage, read> visibility: Visibility;
+
+let SHAPE_RING: u32 = 1u;
+let SHAPE_HEXAGON: u32 = 2u;
+let SHAPE_STARBURST: u32 = 3u;
+
+struct ElementInput {
+    [[location(0)]] color: vec4<f32>;
+    // x: offset along the flare axis, y: size as a fraction of the screen height
+    [[location(1)]] placement: vec2<f32>;
+    [[location(2)]] flare: u32;
+    [[location(3)]] shape: u32;
+};
+
+struct VertexOutput {
+    [[builtin(position)]] clip_position: vec4<f32>;
+    [[location(0)]] color: vec4<f32>;
+    [[location(1)]] uv: vec2<f32>;
+    [[location(2), interpolate(flat)]] shape: u32;
+};
+
+[[stage(vertex)]]
+fn vs_main([[builtin(vertex_index)]] vertex_index: u32, element: ElementInput) -> VertexOutput {
+    let source = sources.sources[element.flare];
+    let corner = vec2<f32>(f32(vertex_index & 1u), f32((vertex_index >> 1u) & 1u)) * 2.0 - vec2<f32>(1.0);
+
+    // Elements are strung along the line from the source through the center of the screen
+    let center = source.position.xy * (1.0 - element.placement.x);
+    let size = vec2<f32>(element.placement.y / lens_flares.parameters.y, element.placement.y);
+
+    // Flares fade out as their source leaves the screen
+    let edge = max(abs(source.position.x), abs(source.position.y));
+    let fade = clamp((1.2 - edge) * 5.0, 0.0, 1.0) * visibility.visibility[element.flare] * source.position.w;
+
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(center + corner * size, 0.0, 1.0);
+    out.color = vec4<f32>(element.color.rgb * source.color.rgb, element.color.a * fade);
+    out.uv = corner;
+    out.shape = element.shape;
+    return out;
+}
+
+fn shape_alpha(shape: u32, uv: vec2<f32>) -> f32 {
+    let radius = length(uv);
+    if (shape == SHAPE_RING) {
+        let band = (radius - 0.8) * 10.0;
+        return exp(-band * band);
+    }
+    if (shape == SHAPE_HEXAGON) {
+        let folded = abs(uv);
+        let distance = max(folded.x * 0.866025 + folded.y * 0.5, folded.y);
+        return clamp((1.0 - distance) * 8.0, 0.0, 1.0) * 0.5;
+    }
+    if (shape == SHAPE_STARBURST) {
+        let folded = abs(uv);
+        let streaks = exp(-folded.y * 40.0) * (1.0 - folded.x) + exp(-folded.x * 40.0) * (1.0 - folded.y);
+        let glow = clamp(1.0 - radius, 0.0, 1.0);
+        return clamp(streaks * 0.5 + glow * glow * glow, 0.0, 1.0);
+    }
+    let glow = clamp(1.0 - radius, 0.0, 1.0);
+    return glow * glow;
+}
+
+// Added onto the HDR scene color
+[[stage(fragment)]]
+fn fs_main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let alpha = shape_alpha(in.shape, in.uv) * in.color.a;
+    return vec4<f32>(in.color.rgb * alpha, 0.0);
+}
@@ -0,0 +1,255 @@
+[[block]]
+struct Camera {
+    view: mat4x4<f32>;
+    projection: mat4x4<f32>;
+    inverse_view_projection: mat4x4<f32>;
+    position: vec4<f32>;
+    parameters: vec4<f32>;
+    previous_view_projection: mat4x4<f32>;
+    jitter: vec4<f32>;
+    depth: vec4<f32>;
+};
+[[group(0), binding(0)]]
+var<uniform> camera: Camera;
+
+// Spliced: standard_depth
+
+[[block]]
+struct Uniform {
+    // Size of the output in pixels
+    viewport: vec4<f32>;
+};
+[[group(1), binding(0)]]
+var<uniform> lines: Uniform;
+
+[[group(2), binding(0)]]
+var depth_texture: texture_depth_2d;
+
+let JOIN_ROUND: u32 = 1u;
+
+// Keeps lines on a surface from fighting with it
+let DEPTH_BIAS: f32 = 0.00001;
+
+// Closer than this to the camera plane a point is clipped
+let NEAR_W: f32 = 0.0001;
+
+struct SegmentInput {
+    // The w of the neighbours is zero when the segment ends the line on that side
+    [[location(0)]] previous: vec4<f32>;
+    // The w of the endpoints is the distance along the line
+    [[location(1)]] start: vec4<f32>;
+    [[location(2)]] end: vec4<f32>;
+    [[location(3)]] next: vec4<f32>;
+    [[location(4)]] color: vec4<f32>;
+    // Width in pixels, dash length, gap length
+    [[location(5)]] parameters: vec4<f32>;
+    [[location(6)]] join: u32;
+};
+
+struct VertexOutput {
+    [[builtin(position)]] clip_position: vec4<f32>;
+    [[location(0)]] color: vec4<f32>;
+    // Pixels along the segment from its start and across it from its center
+    [[location(1)]] local: vec2<f32>;
+    // Half width and length in pixels, distance along the line at the start, line units per pixel
+    [[location(2)]] segment: vec4<f32>;
+    // Dash length, gap length
+    [[location(3)]] dashes: vec2<f32>;
+    [[location(4)]] join: u32;
+};
+
+// Pixels from the bottom left corner of the output
+fn to_pixels(clip: vec4<f32>) -> vec2<f32> {
+    return (clip.xy / clip.w * 0.5 + vec2<f32>(0.5)) * lines.viewport.xy;
+}
+
+fn perpendicular(direction: vec2<f32>) -> vec2<f32> {
+    return vec2<f32>(-direction.y, direction.x);
+}
+
+fn direction(from: vec2<f32>, to: vec2<f32>) -> vec2<f32> {
+    let delta = to - from;
+    if (length(delta) < 0.0001) {
+        return vec2<f32>(1.0, 0.0);
+    }
+    return normalize(delta);
+}
+
+// Offset of a corner from its endpoint, meeting the neighbouring segment when there is one
+fn corner_offset(
+    along: vec2<f32>,
+    neighbour_along: vec2<f32>,
+    has_neighbour: bool,
+    side: f32,
+    extension: f32,
+    half_width: f32,
+    join: u32,
+) -> vec2<f32> {
+    let normal = perpendicular(along);
+    if (join == JOIN_ROUND) {
+        return normal * side * half_width + along * extension * half_width;
+    }
+    if (!has_neighbour) {
+        return normal * side * half_width;
+    }
+    let miter = normalize(perpendicular(neighbour_along) + normal + vec2<f32>(0.00001, 0.0));
+    let scale = dot(miter, normal);
+    // Sharp corners would produce long spikes, they are cut off square instead
+    if (scale < 0.25) {
+        return normal * side * half_width;
+    }
+    return miter * side * half_width / scale;
+}
+
+// Expands a segment given as clip space points into the corner chosen by the vertex index
+fn expand(
+    index: u32,
+    previous: vec4<f32>,
+    clip_start: vec4<f32>,
+    clip_end: vec4<f32>,
+    next: vec4<f32>,
+    has_previous: bool,
+    has_next: bool,
+    segment: SegmentInput,
+) -> VertexOutput {
+    // Two triangles over the corners start right, end right, end left, start left
+    var corner_ends = array<u32, 6>(0u, 1u, 1u, 0u, 1u, 0u);
+    var corner_sides = array<f32, 6>(-1.0, -1.0, 1.0, -1.0, 1.0, 1.0);
+    let at_end = corner_ends[index] == 1u;
+    let side = corner_sides[index];
+
+    // Segments crossing the camera plane are cut where they cross it
+    let hidden = clip_start.w < NEAR_W && clip_end.w < NEAR_W;
+    var start = clip_start;
+    var end = clip_end;
+    if (start.w < NEAR_W) {
+        start = mix(start, end, (NEAR_W - start.w) / (end.w - start.w));
+    }
+    if (end.w < NEAR_W) {
+        end = mix(end, start, (NEAR_W - end.w) / (start.w - end.w));
+    }
+
+    let start_pixels = to_pixels(start);
+    let end_pixels = to_pixels(end);
+    let along = direction(start_pixels, end_pixels);
+    let half_width = max(segment.parameters.x, 1.0) * 0.5 + 1.0;
+
+    var position: vec2<f32>;
+    var depth: f32;
+    if (at_end) {
+        let has_neighbour = has_next && next.w > NEAR_W;
+        let neighbour_along = direction(end_pixels, to_pixels(next));
+        position = end_pixels + corner_offset(along, neighbour_along, has_neighbour, side, 1.0, half_width, segment.join);
+        depth = end.z / end.w;
+    } else {
+        let has_neighbour = has_previous && previous.w > NEAR_W;
+        let neighbour_along = direction(to_pixels(previous), start_pixels);
+        position = start_pixels + corner_offset(along, neighbour_along, has_neighbour, side, -1.0, half_width, segment.join);
+        depth = start.z / start.w;
+    }
+
+    let length_pixels = max(distance(start_pixels, end_pixels), 0.0001);
+    let length_units = segment.end.w - segment.start.w;
+
+    // Depth and coordinates interpolate linearly across the screen, so w is left at one
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(position / lines.viewport.xy * 2.0 - vec2<f32>(1.0), depth, 1.0);
+    if (hidden) {
+        out.clip_position = vec4<f32>(2.0, 2.0, 2.0, 1.0);
+    }
+    out.color = segment.color;
+    let relative = position - start_pixels;
+    out.local = vec2<f32>(dot(relative, along), dot(relative, perpendicular(along)));
+    out.segment = vec4<f32>(
+        max(segment.parameters.x, 1.0) * 0.5,
+        length_pixels,
+        segment.start.w,
+        length_units / length_pixels,
+    );
+    out.dashes = segment.parameters.yz;
+    out.join = segment.join;
+    // Lines thinner than a pixel are drawn a pixel wide and faded instead
+    out.color.a = out.color.a * min(segment.parameters.x, 1.0);
+    return out;
+}
+
+[[stage(vertex)]]
+fn vs_world([[builtin(vertex_index)]] index: u32, segment: SegmentInput) -> VertexOutput {
+    let view_projection = camera.projection * camera.view;
+    return expand(
+        index,
+        view_projection * vec4<f32>(segment.previous.xyz, 1.0),
+        view_projection * vec4<f32>(segment.start.xyz, 1.0),
+        view_projection * vec4<f32>(segment.end.xyz, 1.0),
+        view_projection * vec4<f32>(segment.next.xyz, 1.0),
+        segment.previous.w > 0.0,
+        segment.next.w > 0.0,
+        segment,
+    );
+}
+
+// Screen lines are given in pixels from the top left corner
+fn screen_to_clip(point: vec4<f32>) -> vec4<f32> {
+    let ndc = point.xy / lines.viewport.xy * 2.0 - vec2<f32>(1.0);
+    return vec4<f32>(ndc.x, -ndc.y, 0.0, 1.0);
+}
+
+[[stage(vertex)]]
+fn vs_screen([[builtin(vertex_index)]] index: u32, segment: SegmentInput) -> VertexOutput {
+    return expand(
+        index,
+        screen_to_clip(segment.previous),
+        screen_to_clip(segment.start),
+        screen_to_clip(segment.end),
+        screen_to_clip(segment.next),
+        segment.previous.w > 0.0,
+        segment.next.w > 0.0,
+        segment,
+    );
+}
+
+// Coverage of the pixel by the line, zero in the gaps between dashes
+fn coverage(in: VertexOutput) -> f32 {
+    let half_width = in.segment.x;
+    let segment_length = in.segment.y;
+    var distance = abs(in.local.y);
+    if (in.join == JOIN_ROUND) {
+        if (in.local.x < 0.0) {
+            distance = length(in.local);
+        } elseif (in.local.x > segment_length) {
+            distance = length(in.local - vec2<f32>(segment_length, 0.0));
+        }
+    }
+    var alpha = clamp(half_width - distance + 0.5, 0.0, 1.0);
+
+    let period = in.dashes.x + in.dashes.y;
+    if (in.dashes.x > 0.0 && in.dashes.y > 0.0) {
+        let units = in.segment.z + clamp(in.local.x, 0.0, segment_length) * in.segment.w;
+        if (units - floor(units / period) * period > in.dashes.x) {
+            alpha = 0.0;
+        }
+    }
+    return alpha;
+}
+
+[[stage(fragment)]]
+fn fs_main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let alpha = in.color.a * coverage(in);
+    if (alpha <= 0.0) {
+        discard;
+    }
+    return vec4<f32>(in.color.rgb, alpha);
+}
+
+// The depth texture is at the render resolution, which can differ from the output
+[[stage(fragment)]]
+fn fs_depth_tested(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let alpha = in.color.a * coverage(in);
+    let dimensions = textureDimensions(depth_texture);
+    let screen_uv = in.clip_position.xy / lines.viewport.xy;
+    let texel = clamp(vec2<i32>(screen_uv * vec2<f32>(dimensions)), vec2<i32>(0), dimensions - vec2<i32>(1));
+    if (alpha <= 0.0 || standard_depth(in.clip_position.z) > standard_depth(textureLoad(depth_texture, texel, 0)) + DEPTH_BIAS) {
+        discard;
+    }
+    return vec4<f32>(in.color.rgb, alpha);
+}
@@ -0,0 +1,1161 @@
+[[block]]
+struct Camera {
+    view: mat4x4<f32>;
+    projection: mat4x4<f32>;
+    inverse_view_projection: mat4x4<f32>;
+    position: vec4<f32>;
+    parameters: vec4<f32>;
+    previous_view_projection: mat4x4<f32>;
+    jitter: vec4<f32>;
+    depth: vec4<f32>;
+};
+[[group(0), binding(0)]]
+var<uniform> camera: Camera;
+
+// Material bindings, swapped for those of model_material_arrays.wgsl when a model's materials
+// are packed into texture arrays
+struct TextureTransform {
+    // Columns of the rotation and scale
+    matrix: vec4<f32>;
+    offset: vec2<f32>;
+    // Only the first two sets are provided by the vertices, later ones fall back to the second
+    tex_coord: u32;
+};
+
+[[block]]
+struct Material {
+    base_color_factor: vec4<f32>;
+    emissive_factor: vec4<f32>;
+    metallic_factor: f32;
+    roughness_factor: f32;
+    normal_scale: f32;
+    occlusion_strength: f32;
+    ssao_strength: f32;
+    occlusion_blend: u32;
+    alpha_mode: u32;
+    alpha_cutoff: f32;
+    sheen_color_factor: vec4<f32>;
+    specular_color_factor: vec4<f32>;
+    clearcoat_factor: f32;
+    clearcoat_roughness_factor: f32;
+    clearcoat_normal_scale: f32;
+    transmission_factor: f32;
+    ior: f32;
+    shading_model: u32;
+    toon_bands: u32;
+    outline_width: f32;
+    outline_color: vec4<f32>;
+    texture_transforms: array<TextureTransform, 11>;
+    // Zero without a lightmap
+    lightmap_intensity: f32;
+    // One when the emissive texture holds a reflection of the scene lined up with the screen
+    planar_reflection: u32;
+};
+[[group(1), binding(0)]]
+var<uniform> material: Material;
+[[group(1), binding(1)]]
+var base_color_texture: texture_2d<f32>;
+[[group(1), binding(2)]]
+var metallic_roughness_texture: texture_2d<f32>;
+[[group(1), binding(3)]]
+var normal_texture: texture_2d<f32>;
+[[group(1), binding(4)]]
+var occlusion_texture: texture_2d<f32>;
+[[group(1), binding(5)]]
+var emissive_texture: texture_2d<f32>;
+[[group(1), binding(6)]]
+var material_sampler: sampler;
+[[group(1), binding(7)]]
+var clearcoat_texture: texture_2d<f32>;
+[[group(1), binding(8)]]
+var clearcoat_normal_texture: texture_2d<f32>;
+[[group(1), binding(9)]]
+var transmission_texture: texture_2d<f32>;
+[[group(1), binding(10)]]
+var sheen_texture: texture_2d<f32>;
+[[group(1), binding(11)]]
+var specular_texture: texture_2d<f32>;
+[[group(1), binding(12)]]
+var lightmap_texture: texture_2d<f32>;
+
+// The material is bound for each draw, so there's nothing to load
+fn load_material() {
+}
+
+// Gradients are taken up front, since the texture chosen isn't uniform control flow
+fn sample_material(texture: u32, uv: vec2<f32>, ddx: vec2<f32>, ddy: vec2<f32>) -> vec4<f32> {
+    switch (texture) {
+        case 0u: { return textureSampleGrad(base_color_texture, material_sampler, uv, ddx, ddy); }
+        case 1u: { return textureSampleGrad(metallic_roughness_texture, material_sampler, uv, ddx, ddy); }
+        case 2u: { return textureSampleGrad(normal_texture, material_sampler, uv, ddx, ddy); }
+        case 3u: { return textureSampleGrad(occlusion_texture, material_sampler, uv, ddx, ddy); }
+        case 4u: { return textureSampleGrad(emissive_texture, material_sampler, uv, ddx, ddy); }
+        case 5u: { return textureSampleGrad(clearcoat_texture, material_sampler, uv, ddx, ddy); }
+        case 6u: { return textureSampleGrad(clearcoat_normal_texture, material_sampler, uv, ddx, ddy); }
+        case 7u: { return textureSampleGrad(transmission_texture, material_sampler, uv, ddx, ddy); }
+        case 8u: { return textureSampleGrad(sheen_texture, material_sampler, uv, ddx, ddy); }
+        case 9u: { return textureSampleGrad(specular_texture, material_sampler, uv, ddx, ddy); }
+        default: { return textureSampleGrad(lightmap_texture, material_sampler, uv, ddx, ddy); }
+    }
+}
+// End of material bindings
+
+struct PunctualLight {
+    // w: one for lights with a position, zero for directional lights whose xyz points towards the light
+    position: vec4<f32>;
+    direction: vec4<f32>;
+    color: vec4<f32>;
+    // x: spot angle scale, y: spot angle offset, z: range or zero when unbounded, w: the bits of
+    // the layers it shines on
+    parameters: vec4<f32>;
+    // x: the first shadow view, y: how many views there are or zero when unshadowed
+    shadow: vec4<u32>;
+};
+
+struct ShadowView {
+    view_projection: mat4x4<f32>;
+    // xy: offset of the tile in the atlas, z: size of the tile, w: size of a texel, in uvs
+    rect: vec4<f32>;
+};
+
+[[block]]
+struct Light {
+    view_projection: mat4x4<f32>;
+    direction: vec4<f32>;
+    color: vec4<f32>;
+    punctual_lights: array<PunctualLight, 16>;
+    punctual_light_count: u32;
+    layers: u32;
+    shadow_views: array<ShadowView, 48>;
+};
+[[group(2), binding(0)]]
+var<uniform> light: Light;
+[[group(2), binding(1)]]
+var shadow_map: texture_depth_2d;
+[[group(2), binding(2)]]
+var shadow_sampler: sampler_comparison;
+
+[[block]]
+struct ReflectionProbes {
+    // xyz: position, w: radius or zero until captured
+    probes: array<vec4<f32>, 8>;
+    count: u32;
+    max_lod: f32;
+};
+// Six faces per probe, filtered for rougher surfaces down the mips
+[[group(2), binding(3)]]
+var probe_texture: texture_2d_array<f32>;
+[[group(2), binding(4)]]
+var probe_sampler: sampler;
+[[group(2), binding(5)]]
+var<uniform> reflection_probes: ReflectionProbes;
+
+[[block]]
+struct IrradianceCoefficients {
+    // Nine spherical harmonic coefficients per probe
+    values: array<vec4<f32>>;
+};
+[[block]]
+struct IrradianceVolume {
+    // w: one once every probe has been baked
+    min: vec4<f32>;
+    max: vec4<f32>;
+    counts: vec4<u32>;
+};
+[[group(2), binding(6)]]
+var<storage, read> irradiance_coefficients: IrradianceCoefficients;
+[[group(2), binding(7)]]
+var<uniform> irradiance_volume: IrradianceVolume;
+[[group(2), binding(8)]]
+var shadow_mask: texture_2d<f32>;
+
+struct AreaLight {
+    position: vec4<f32>;
+    // Half the rectangle's width and height along its sides
+    right: vec4<f32>;
+    up: vec4<f32>;
+    color: vec4<f32>;
+    // x: the bits of the layers it shines on, y: one when it shines from both sides, z: one when
+    // its emission is textured
+    parameters: vec4<u32>;
+};
+[[block]]
+struct AreaLights {
+    lights: array<AreaLight, 8>;
+    count: u32;
+};
+[[block]]
+struct AreaLightData {
+    // The fitted inverse matrices, then their magnitude and fresnel terms, then the emission of
+    // each light with every level of detail
+    texels: array<vec4<f32>>;
+};
+[[group(2), binding(9)]]
+var<uniform> area_lights: AreaLights;
+[[group(2), binding(10)]]
+var<storage, read> area_light_data: AreaLightData;
+// A 64x64 tile of blue noise packed four texels to a component
+[[block]]
+struct BlueNoise {
+    offset: vec4<u32>;
+    texels: array<vec4<u32>, 256>;
+};
+[[group(2), binding(11)]]
+var<uniform> blue_noise: BlueNoise;
+
+[[group(3), binding(0)]]
+var ambient_occlusion_texture: texture_2d<f32>;
+// Only bound while drawing transmissive surfaces
+[[group(3), binding(1)]]
+var opaque_texture: texture_2d<f32>;
+[[group(3), binding(2)]]
+var opaque_sampler: sampler;
+
+struct VertexInput {
+    [[location(0)]] position: vec3<f32>;
+    [[location(1)]] normal: vec3<f32>;
+    [[location(2)]] tangent: vec4<f32>;
+    [[location(3)]] uv: vec2<f32>;
+    [[location(11)]] uv_1: vec2<f32>;
+    [[location(12)]] color: vec4<f32>;
+};
+
+struct InstanceInput {
+    [[location(4)]] model_0: vec4<f32>;
+    [[location(5)]] model_1: vec4<f32>;
+    [[location(6)]] model_2: vec4<f32>;
+    [[location(7)]] model_3: vec4<f32>;
+    [[location(8)]] normal_0: vec4<f32>;
+    [[location(9)]] normal_1: vec4<f32>;
+    [[location(10)]] normal_2: vec4<f32>;
+    [[location(13)]] layers: u32;
+};
+
+struct VertexOutput {
+    [[builtin(position)]] clip_position: vec4<f32>;
+    [[location(0)]] world_position: vec3<f32>;
+    [[location(1)]] normal: vec3<f32>;
+    [[location(2)]] tangent: vec4<f32>;
+    [[location(3)]] uv: vec2<f32>;
+    [[location(4)]] uv_1: vec2<f32>;
+    [[location(5)]] color: vec4<f32>;
+    [[location(6), interpolate(flat)]] layers: u32;
+};
+
+fn transform_vertex(vertex: VertexInput, instance: InstanceInput) -> VertexOutput {
+    let model = mat4x4<f32>(instance.model_0, instance.model_1, instance.model_2, instance.model_3);
+    let normal_matrix = mat3x3<f32>(instance.normal_0.xyz, instance.normal_1.xyz, instance.normal_2.xyz);
+    let world_position = model * vec4<f32>(vertex.position, 1.0);
+
+    var out: VertexOutput;
+    out.clip_position = camera.projection * camera.view * world_position;
+    out.world_position = world_position.xyz;
+    out.normal = normal_matrix * vertex.normal;
+    out.tangent = vec4<f32>((model * vec4<f32>(vertex.tangent.xyz, 0.0)).xyz, vertex.tangent.w);
+    out.uv = vertex.uv;
+    out.uv_1 = vertex.uv_1;
+    out.color = vertex.color;
+    out.layers = instance.layers;
+    return out;
+}
+
+[[stage(vertex)]]
+fn vs_main(vertex: VertexInput, instance: InstanceInput) -> VertexOutput {
+    return transform_vertex(vertex, instance);
+}
+
+// Pushes the back faces out along their normals by a constant width on screen
+[[stage(vertex)]]
+fn vs_outline(vertex: VertexInput, instance: InstanceInput) -> VertexOutput {
+    load_material();
+    var out = transform_vertex(vertex, instance);
+    let clip_normal = (camera.projection * camera.view * vec4<f32>(out.normal, 0.0)).xy;
+    if (dot(clip_normal, clip_normal) > 0.0) {
+        let offset = normalize(clip_normal) * material.outline_width * 2.0 / camera.parameters.zw;
+        out.clip_position = vec4<f32>(out.clip_position.xy + offset * out.clip_position.w, out.clip_position.zw);
+    }
+    return out;
+}
+
+[[stage(fragment)]]
+fn fs_outline() -> [[location(0)]] vec4<f32> {
+    load_material();
+    return vec4<f32>(material.outline_color.rgb, 1.0);
+}
+
+let PI: f32 = 3.14159265;
+let AMBIENT: f32 = 0.25;
+let SHADOW_MAP_SIZE: f32 = 2048.0;
+
+let ALPHA_MODE_MASK: u32 = 1u;
+let ALPHA_MODE_BLEND: u32 = 2u;
+let ALPHA_MODE_DITHERED: u32 = 3u;
+let OCCLUSION_BLEND_MIN: u32 = 1u;
+let SHADING_MODEL_UNLIT: u32 = 1u;
+let SHADING_MODEL_TOON: u32 = 2u;
+
+// Indices into the material's texture transforms
+let BASE_COLOR_TEXTURE: u32 = 0u;
+let METALLIC_ROUGHNESS_TEXTURE: u32 = 1u;
+let NORMAL_TEXTURE: u32 = 2u;
+let OCCLUSION_TEXTURE: u32 = 3u;
+let EMISSIVE_TEXTURE: u32 = 4u;
+let CLEARCOAT_TEXTURE: u32 = 5u;
+let CLEARCOAT_NORMAL_TEXTURE: u32 = 6u;
+let TRANSMISSION_TEXTURE: u32 = 7u;
+let SHEEN_TEXTURE: u32 = 8u;
+let SPECULAR_TEXTURE: u32 = 9u;
+let LIGHTMAP_TEXTURE: u32 = 10u;
+
+// Transmissive surfaces are treated as thin walls, offsetting the light behind them slightly
+let THIN_WALL_THICKNESS: f32 = 0.05;
+let TRANSMISSION_SAMPLES: u32 = 8u;
+let GOLDEN_ANGLE: f32 = 2.39996323;
+
+// A threshold in (0, 1) that is evenly spread over neighbouring pixels
+fn blue_noise_at(pixel: vec2<f32>) -> f32 {
+    let coordinate = (vec2<u32>(pixel) + blue_noise.offset.xy) % vec2<u32>(64u);
+    let index = coordinate.y * 64u + coordinate.x;
+    let packed = blue_noise.texels[index / 16u][(index / 4u) % 4u];
+    let value = (packed >> ((index % 4u) * 8u)) & 255u;
+    return (f32(value) + 0.5) / 256.0;
+}
+
+// Shadow filters sample a disc rotated by blue noise, which trades banding for fine grain
+let SHADOW_SAMPLES: u32 = 8u;
+let SHADOW_FILTER_RADIUS: f32 = 1.5;
+
+fn shadow_disc_offset(index: u32, rotation: f32) -> vec2<f32> {
+    let distance = sqrt((f32(index) + 0.5) / f32(SHADOW_SAMPLES)) * SHADOW_FILTER_RADIUS;
+    let angle = f32(index) * GOLDEN_ANGLE + rotation;
+    return vec2<f32>(cos(angle), sin(angle)) * distance;
+}
+
+fn shadow_visibility(position: vec3<f32>, noise: f32) -> f32 {
+    let clip = light.view_projection * vec4<f32>(position, 1.0);
+    let ndc = clip.xyz / clip.w;
+    let uv = vec2<f32>(ndc.x * 0.5 + 0.5, 0.5 - ndc.y * 0.5);
+    if (any(uv < vec2<f32>(0.0)) || any(uv > vec2<f32>(1.0)) || ndc.z < 0.0 || ndc.z > 1.0) {
+        return 1.0;
+    }
+
+    // The sun's tile is in the corner of the atlas it shares with local lights, and filtering
+    // stays inside it
+    let atlas_scale = SHADOW_MAP_SIZE / f32(textureDimensions(shadow_map).x);
+    let texel = 1.0 / SHADOW_MAP_SIZE;
+    let rotation = noise * 2.0 * PI;
+    var visibility = 0.0;
+    for (var index = 0u; index < SHADOW_SAMPLES; index = index + 1u) {
+        let offset = shadow_disc_offset(index, rotation) * texel;
+        let sample_uv = clamp(uv + offset, vec2<f32>(texel), vec2<f32>(1.0 - texel)) * atlas_scale;
+        visibility = visibility + textureSampleCompareLevel(shadow_map, shadow_sampler, sample_uv, ndc.z);
+    }
+    return visibility / f32(SHADOW_SAMPLES);
+}
+
+// Offsets the depth compared against a local light's tile, which has perspective depth. The
+// offset is toward the light, which is greater when depth is reversed
+let LOCAL_SHADOW_BIAS: f32 = 0.0005;
+
+// Point lights pick the cube face their tile was rendered through from the major axis
+fn local_shadow_visibility(punctual: PunctualLight, position: vec3<f32>, noise: f32) -> f32 {
+    if (punctual.shadow.y == 0u) {
+        return 1.0;
+    }
+    var view_index = punctual.shadow.x;
+    if (punctual.shadow.y == 6u) {
+        let offset = position - punctual.position.xyz;
+        let magnitude = abs(offset);
+        if (magnitude.x >= magnitude.y && magnitude.x >= magnitude.z) {
+            view_index = view_index + select(0u, 1u, offset.x < 0.0);
+        } elseif (magnitude.y >= magnitude.z) {
+            view_index = view_index + select(2u, 3u, offset.y < 0.0);
+        } else {
+            view_index = view_index + select(4u, 5u, offset.z < 0.0);
+        }
+    }
+    let view = light.shadow_views[view_index];
+
+    let clip = view.view_projection * vec4<f32>(position, 1.0);
+    if (clip.w <= 0.0) {
+        return 1.0;
+    }
+    let ndc = clip.xyz / clip.w;
+    let uv = vec2<f32>(ndc.x * 0.5 + 0.5, 0.5 - ndc.y * 0.5);
+    if (any(uv < vec2<f32>(0.0)) || any(uv > vec2<f32>(1.0)) || ndc.z < 0.0 || ndc.z > 1.0) {
+        return 1.0;
+    }
+
+    // Filtering stays a texel inside the tile so it never reads a neighbouring one
+    let texel = view.rect.w;
+    let tile_min = view.rect.xy + vec2<f32>(texel);
+    let tile_max = view.rect.xy + vec2<f32>(view.rect.z - texel);
+    let atlas_uv = view.rect.xy + uv * view.rect.z;
+    let depth = ndc.z - select(LOCAL_SHADOW_BIAS, -LOCAL_SHADOW_BIAS, camera.depth.x > 0.0);
+    let rotation = noise * 2.0 * PI;
+    var visibility = 0.0;
+    for (var index = 0u; index < SHADOW_SAMPLES; index = index + 1u) {
+        let sample_uv = clamp(atlas_uv + shadow_disc_offset(index, rotation) * texel, tile_min, tile_max);
+        visibility = visibility + textureSampleCompareLevel(shadow_map, shadow_sampler, sample_uv, depth);
+    }
+    return visibility / f32(SHADOW_SAMPLES);
+}
+
+// Sun visibility traced against the models, lit everywhere when shadows aren't ray traced
+fn traced_shadow(pixel: vec2<f32>) -> f32 {
+    let dimensions = textureDimensions(shadow_mask);
+    return textureLoad(shadow_mask, min(vec2<i32>(pixel), dimensions - vec2<i32>(1)), 0).r;
+}
+
+// Matches the face directions the probes are captured with
+fn probe_face_forward(face: u32) -> vec3<f32> {
+    switch (i32(face)) {
+        case 0: { return vec3<f32>(1.0, 0.0, 0.0); }
+        case 1: { return vec3<f32>(-1.0, 0.0, 0.0); }
+        case 2: { return vec3<f32>(0.0, 1.0, 0.0); }
+        case 3: { return vec3<f32>(0.0, -1.0, 0.0); }
+        case 4: { return vec3<f32>(0.0, 0.0, 1.0); }
+        default: { return vec3<f32>(0.0, 0.0, -1.0); }
+    }
+}
+
+fn probe_face_up(face: u32) -> vec3<f32> {
+    switch (i32(face)) {
+        case 2: { return vec3<f32>(0.0, 0.0, -1.0); }
+        case 3: { return vec3<f32>(0.0, 0.0, 1.0); }
+        default: { return vec3<f32>(0.0, 1.0, 0.0); }
+    }
+}
+
+fn sample_probe(probe: u32, direction: vec3<f32>, lod: f32) -> vec3<f32> {
+    let magnitude = abs(direction);
+    var face = 0u;
+    if (magnitude.x >= magnitude.y && magnitude.x >= magnitude.z) {
+        face = select(1u, 0u, direction.x > 0.0);
+    } else {
+        if (magnitude.y >= magnitude.z) {
+            face = select(3u, 2u, direction.y > 0.0);
+        } else {
+            face = select(5u, 4u, direction.z > 0.0);
+        }
+    }
+    let forward = probe_face_forward(face);
+    let up = probe_face_up(face);
+    let right = cross(forward, up);
+    let depth = dot(direction, forward);
+    let uv = vec2<f32>(dot(direction, right) / depth * 0.5 + 0.5, 0.5 - dot(direction, up) / depth * 0.5);
+    return textureSampleLevel(probe_texture, probe_sampler, uv, i32(probe * 6u + face), lod).rgb;
+}
+
+// Probes are blended by how close the surface is to each, fading out at their radius. Nothing is
+// reflected where no probe reaches
+fn probe_reflection(position: vec3<f32>, direction: vec3<f32>, roughness: f32) -> vec3<f32> {
+    let lod = roughness * reflection_probes.max_lod;
+    var color = vec3<f32>(0.0);
+    var total = 0.0;
+    for (var index = 0u; index < reflection_probes.count; index = index + 1u) {
+        let probe = reflection_probes.probes[index];
+        if (probe.w > 0.0) {
+            let weight = clamp(1.0 - distance(position, probe.xyz) / probe.w, 0.0, 1.0);
+            if (weight > 0.0) {
+                color = color + sample_probe(index, direction, lod) * weight;
+                total = total + weight;
+            }
+        }
+    }
+    // Overlapping probes share the surface, a single probe fades out towards its edge
+    return color / max(total, 1.0);
+}
+
+// Diffuse light reflected towards the normal by a white surface, from a probe's harmonics
+fn probe_irradiance(probe: u32, n: vec3<f32>) -> vec3<f32> {
+    let first = probe * 9u;
+    var irradiance = irradiance_coefficients.values[first].rgb * 0.886227;
+    irradiance = irradiance + irradiance_coefficients.values[first + 1u].rgb * 1.023328 * n.y;
+    irradiance = irradiance + irradiance_coefficients.values[first + 2u].rgb * 1.023328 * n.z;
+    irradiance = irradiance + irradiance_coefficients.values[first + 3u].rgb * 1.023328 * n.x;
+    irradiance = irradiance + irradiance_coefficients.values[first + 4u].rgb * 0.858086 * n.x * n.y;
+    irradiance = irradiance + irradiance_coefficients.values[first + 5u].rgb * 0.858086 * n.y * n.z;
+    irradiance = irradiance + irradiance_coefficients.values[first + 6u].rgb * 0.247708 * (3.0 * n.z * n.z - 1.0);
+    irradiance = irradiance + irradiance_coefficients.values[first + 7u].rgb * 0.858086 * n.x * n.z;
+    irradiance = irradiance + irradiance_coefficients.values[first + 8u].rgb * 0.429043 * (n.x * n.x - n.y * n.y);
+    return max(irradiance / PI, vec3<f32>(0.0));
+}
+
+// Interpolates the eight probes around the position inside a baked irradiance volume, the
+// constant ambient light elsewhere
+fn ambient_light(position: vec3<f32>, n: vec3<f32>) -> vec3<f32> {
+    let inside = all(position >= irradiance_volume.min.xyz) && all(position <= irradiance_volume.max.xyz);
+    if (irradiance_volume.min.w == 0.0 || !inside) {
+        return vec3<f32>(AMBIENT);
+    }
+
+    let counts = irradiance_volume.counts.xyz;
+    let last = vec3<f32>(counts - vec3<u32>(1u));
+    let extents = max(irradiance_volume.max.xyz - irradiance_volume.min.xyz, vec3<f32>(0.0001));
+    let grid = clamp((position - irradiance_volume.min.xyz) / extents * last, vec3<f32>(0.0), last);
+    let cell = vec3<u32>(floor(grid));
+    let fraction = grid - floor(grid);
+
+    var light = vec3<f32>(0.0);
+    for (var corner = 0u; corner < 8u; corner = corner + 1u) {
+        let offset = vec3<u32>(corner & 1u, (corner >> 1u) & 1u, (corner >> 2u) & 1u);
+        let probe_cell = min(cell + offset, counts - vec3<u32>(1u));
+        let weights = select(vec3<f32>(1.0) - fraction, fraction, offset == vec3<u32>(1u));
+        let probe = probe_cell.x + probe_cell.y * counts.x + probe_cell.z * counts.x * counts.y;
+        light = light + probe_irradiance(probe, n) * weights.x * weights.y * weights.z;
+    }
+    return light;
+}
+
+// Analytic fit of the split sum environment BRDF by Karis
+fn environment_brdf(f0: vec3<f32>, f90: vec3<f32>, n_dot_v: f32, roughness: f32) -> vec3<f32> {
+    let r = roughness * vec4<f32>(-1.0, -0.0275, -0.572, 0.022) + vec4<f32>(1.0, 0.0425, 1.04, -0.04);
+    let a004 = min(r.x * r.x, exp2(-9.28 * n_dot_v)) * r.x + r.y;
+    let scale_bias = vec2<f32>(-1.04, 1.04) * a004 + r.zw;
+    return f0 * scale_bias.x + f90 * scale_bias.y;
+}
+
+// Trowbridge-Reitz GGX normal distribution
+fn distribution(n_dot_h: f32, roughness: f32) -> f32 {
+    let alpha = roughness * roughness;
+    let alpha2 = alpha * alpha;
+    let denominator = n_dot_h * n_dot_h * (alpha2 - 1.0) + 1.0;
+    return alpha2 / (PI * denominator * denominator);
+}
+
+// Smith geometry term with Schlick-GGX for both the light and view directions
+fn geometry(n_dot_v: f32, n_dot_l: f32, roughness: f32) -> f32 {
+    let k = (roughness + 1.0) * (roughness + 1.0) / 8.0;
+    let view = n_dot_v / (n_dot_v * (1.0 - k) + k);
+    let light = n_dot_l / (n_dot_l * (1.0 - k) + k);
+    return view * light;
+}
+
+fn perturbed_normal(in: VertexOutput, tangent_normal: vec3<f32>) -> vec3<f32> {
+    let normal = normalize(in.normal);
+    let tangent = in.tangent.xyz - normal * dot(normal, in.tangent.xyz);
+    // Primitives without texture coordinates have no tangents to map along
+    if (dot(tangent, tangent) < 0.000001) {
+        return normal;
+    }
+    let tangent = normalize(tangent);
+    let bitangent = cross(normal, tangent) * in.tangent.w;
+    return normalize(mat3x3<f32>(tangent, bitangent, normal) * tangent_normal);
+}
+
+fn fresnel(f0: vec3<f32>, f90: vec3<f32>, cos_theta: f32) -> vec3<f32> {
+    return f0 + (f90 - f0) * pow(1.0 - cos_theta, 5.0);
+}
+
+fn specular_brdf(n_dot_l: f32, n_dot_v: f32, n_dot_h: f32, roughness: f32) -> f32 {
+    return distribution(n_dot_h, roughness) * geometry(n_dot_v, n_dot_l, roughness) / max(4.0 * n_dot_v * n_dot_l, 0.0001);
+}
+
+// Charlie distribution with Neubelt's visibility term for cloth-like sheen
+fn sheen_brdf(n_dot_l: f32, n_dot_v: f32, n_dot_h: f32, roughness: f32) -> f32 {
+    let alpha = max(roughness * roughness, 0.0001);
+    let inverse_alpha = 1.0 / alpha;
+    let sin2 = 1.0 - n_dot_h * n_dot_h;
+    let distribution = (2.0 + inverse_alpha) * pow(sin2, inverse_alpha * 0.5) / (2.0 * PI);
+    let visibility = 1.0 / max(4.0 * (n_dot_l + n_dot_v - n_dot_l * n_dot_v), 0.0001);
+    return distribution * visibility;
+}
+
+fn max_component(value: vec3<f32>) -> f32 {
+    return max(value.x, max(value.y, value.z));
+}
+
+// The shaded surface is split so transmissive surfaces can swap their diffuse light
+// for the light passing through them
+struct Surface {
+    diffuse: vec3<f32>;
+    specular: vec3<f32>;
+    // Scales the base layer for the energy reflected by the layers above it
+    base_weight: f32;
+    // Sheen, clearcoat and emitted light
+    layers: vec3<f32>;
+    alpha: f32;
+    transmission: f32;
+    // Fraction of the light behind the surface that makes it through the base layer
+    transmittance: vec3<f32>;
+    normal: vec3<f32>;
+    view: vec3<f32>;
+    roughness: f32;
+};
+
+// The parts of the surface that stay the same for every light
+struct Brdf {
+    normal: vec3<f32>;
+    view: vec3<f32>;
+    diffuse_color: vec3<f32>;
+    f0: vec3<f32>;
+    f90: vec3<f32>;
+    // Tint of toon highlights
+    specular_color: vec3<f32>;
+    roughness: f32;
+    sheen_color: vec3<f32>;
+    sheen_roughness: f32;
+    clearcoat: f32;
+    clearcoat_normal: vec3<f32>;
+    clearcoat_roughness: f32;
+};
+
+// Light reflected by each layer of the surface
+struct Reflected {
+    diffuse: vec3<f32>;
+    specular: vec3<f32>;
+    sheen: vec3<f32>;
+    clearcoat: vec3<f32>;
+};
+
+fn reflect_light(brdf: Brdf, l: vec3<f32>, radiance: vec3<f32>) -> Reflected {
+    let n = brdf.normal;
+    let v = brdf.view;
+    let h = normalize(v + l);
+    let n_dot_l = max(dot(n, l), 0.0);
+    let n_dot_v = max(dot(n, v), 0.0001);
+    let n_dot_h = max(dot(n, h), 0.0);
+    let v_dot_h = max(dot(v, h), 0.0);
+    let light_fresnel = fresnel(brdf.f0, brdf.f90, v_dot_h);
+
+    var reflected: Reflected;
+    reflected.diffuse = (vec3<f32>(1.0) - light_fresnel) * brdf.diffuse_color / PI * radiance * n_dot_l;
+    reflected.specular = light_fresnel * specular_brdf(n_dot_l, n_dot_v, n_dot_h, brdf.roughness) * radiance * n_dot_l;
+    reflected.sheen = brdf.sheen_color * sheen_brdf(n_dot_l, n_dot_v, n_dot_h, brdf.sheen_roughness) * radiance * n_dot_l;
+
+    let clearcoat_n_dot_l = max(dot(brdf.clearcoat_normal, l), 0.0);
+    let clearcoat_n_dot_v = max(dot(brdf.clearcoat_normal, v), 0.0001);
+    let clearcoat_n_dot_h = max(dot(brdf.clearcoat_normal, h), 0.0);
+    // The coating is a dielectric with an index of refraction of 1.5
+    reflected.clearcoat = fresnel(vec3<f32>(0.04), vec3<f32>(1.0), v_dot_h) * specular_brdf(clearcoat_n_dot_l, clearcoat_n_dot_v, clearcoat_n_dot_h, brdf.clearcoat_roughness) * radiance * clearcoat_n_dot_l * brdf.clearcoat;
+    return reflected;
+}
+
+// Falloff of a punctual light with distance and, for spot lights, with the angle from its cone
+fn punctual_attenuation(punctual: PunctualLight, to_light: vec3<f32>, l: vec3<f32>) -> f32 {
+    var attenuation = 1.0;
+    if (punctual.position.w > 0.0) {
+        let distance2 = max(dot(to_light, to_light), 0.0001);
+        attenuation = 1.0 / distance2;
+        let range = punctual.parameters.z;
+        if (range > 0.0) {
+            let ratio = distance2 / (range * range);
+            attenuation = attenuation * clamp(1.0 - ratio * ratio, 0.0, 1.0);
+        }
+    }
+    let spot = clamp(dot(punctual.direction.xyz, l) * punctual.parameters.x + punctual.parameters.y, 0.0, 1.0);
+    return attenuation * spot * spot;
+}
+
+let LTC_SIZE: u32 = 64u;
+let EMISSION_SIZE: u32 = 64u;
+let EMISSION_LEVELS: u32 = 7u;
+let EMISSION_TEXELS: u32 = 5461u;
+
+// Bilinear lookup of the fitted table, which holds samples at both ends of each axis
+fn ltc_lookup(offset: u32, roughness: f32, n_dot_v: f32) -> vec4<f32> {
+    let coordinates = vec2<f32>(roughness, sqrt(1.0 - n_dot_v)) * f32(LTC_SIZE - 1u);
+    let base = min(vec2<u32>(coordinates), vec2<u32>(LTC_SIZE - 2u));
+    let weight = coordinates - vec2<f32>(base);
+    let index = offset + base.x + base.y * LTC_SIZE;
+    let top = mix(area_light_data.texels[index], area_light_data.texels[index + 1u], weight.x);
+    let bottom = mix(area_light_data.texels[index + LTC_SIZE], area_light_data.texels[index + LTC_SIZE + 1u], weight.x);
+    return mix(top, bottom, weight.y);
+}
+
+// The integral of a clamped cosine over the arc between two directions, as a vector
+fn ltc_edge(v1: vec3<f32>, v2: vec3<f32>) -> vec3<f32> {
+    let x = dot(v1, v2);
+    let y = abs(x);
+    let a = 0.8543985 + (0.4965155 + 0.0145206 * y) * y;
+    let b = 3.4175940 + (4.1616724 + y) * y;
+    let v = a / b;
+    let theta_sin_theta = select(0.5 * inverseSqrt(max(1.0 - x * x, 0.0000001)) - v, v, x > 0.0);
+    return cross(v1, v2) * theta_sin_theta;
+}
+
+// Light passing through a level of the emission at a uv, filtered between texels
+fn emission_level(area: u32, level: u32, uv: vec2<f32>) -> vec3<f32> {
+    let size = EMISSION_SIZE >> level;
+    var offset = LTC_SIZE * LTC_SIZE * 2u + area * EMISSION_TEXELS;
+    for (var previous = 0u; previous < level; previous = previous + 1u) {
+        let previous_size = EMISSION_SIZE >> previous;
+        offset = offset + previous_size * previous_size;
+    }
+    let coordinates = clamp(uv * f32(size) - 0.5, vec2<f32>(0.0), vec2<f32>(f32(size - 1u)));
+    let base = vec2<u32>(coordinates);
+    let next = min(base + 1u, vec2<u32>(size - 1u));
+    let weight = coordinates - vec2<f32>(base);
+    let top = mix(area_light_data.texels[offset + base.x + base.y * size], area_light_data.texels[offset + next.x + base.y * size], weight.x);
+    let bottom = mix(area_light_data.texels[offset + base.x + next.y * size], area_light_data.texels[offset + next.x + next.y * size], weight.x);
+    return mix(top, bottom, weight.y).rgb;
+}
+
+// The emission seen through the transformed rectangle, where the corners are relative to the
+// shaded point. Where the lobe meets the plane picks the uv, and the lobe's spread over the
+// rectangle picks the level
+fn area_light_emission(area: u32, corners: array<vec3<f32>, 4>) -> vec3<f32> {
+    let v1 = corners[1] - corners[0];
+    let v2 = corners[3] - corners[0];
+    let plane_ortho = cross(v1, v2);
+    let plane_area_squared = max(dot(plane_ortho, plane_ortho), 0.0000001);
+    let plane_distance_area = dot(plane_ortho, corners[0]);
+    let projected = plane_distance_area * plane_ortho / plane_area_squared - corners[0];
+
+    let dot_v1_v2 = dot(v1, v2);
+    let inverse_dot_v1_v1 = 1.0 / max(dot(v1, v1), 0.0000001);
+    let perpendicular = v2 - v1 * dot_v1_v2 * inverse_dot_v1_v1;
+    let v = dot(perpendicular, projected) / max(dot(perpendicular, perpendicular), 0.0000001);
+    let u = dot(v1, projected) * inverse_dot_v1_v1 - dot_v1_v2 * inverse_dot_v1_v1 * v;
+
+    let spread = abs(plane_distance_area) / pow(plane_area_squared, 0.75);
+    let lod = clamp(log2(f32(EMISSION_SIZE) * spread) / log2(3.0), 0.0, f32(EMISSION_LEVELS - 1u));
+    let level = u32(lod);
+    // The image reads left to right seen from the front, where the rectangle's right points left
+    let uv = clamp(vec2<f32>(1.0 - u, 1.0 - v), vec2<f32>(0.0), vec2<f32>(1.0));
+    let fine = emission_level(area, level, uv);
+    let coarse = emission_level(area, min(level + 1u, EMISSION_LEVELS - 1u), uv);
+    return mix(fine, coarse, fract(lod));
+}
+
+// The rectangle seen through a cosine lobe transformed by minv in the frame around the normal.
+// rgb: the share of the lobe it covers tinted by its emission, a: the share alone
+fn ltc_evaluate(area: u32, normal: vec3<f32>, view: vec3<f32>, position: vec3<f32>, minv: mat3x3<f32>) -> vec4<f32> {
+    let area_light = area_lights.lights[area];
+    // The view lies in the xz plane of the frame
+    var tangent = view - normal * dot(view, normal);
+    if (dot(tangent, tangent) < 0.000001) {
+        tangent = select(vec3<f32>(1.0, 0.0, 0.0), vec3<f32>(0.0, 1.0, 0.0), abs(normal.x) > 0.9);
+        tangent = tangent - normal * dot(tangent, normal);
+    }
+    tangent = normalize(tangent);
+    let to_frame = minv * transpose(mat3x3<f32>(tangent, cross(normal, tangent), normal));
+
+    let center = area_light.position.xyz - position;
+    let right = area_light.right.xyz;
+    let up = area_light.up.xyz;
+    var corners: array<vec3<f32>, 4>;
+    corners[0] = to_frame * (center - right - up);
+    corners[1] = to_frame * (center + right - up);
+    corners[2] = to_frame * (center + right + up);
+    corners[3] = to_frame * (center - right + up);
+
+    // Clipped against the horizon, which can leave up to five corners
+    var clipped: array<vec3<f32>, 5>;
+    var count = 0u;
+    for (var index = 0u; index < 4u; index = index + 1u) {
+        let current = corners[index];
+        let next = corners[(index + 1u) % 4u];
+        if (current.z >= 0.0) {
+            clipped[count] = current;
+            count = count + 1u;
+        }
+        if ((current.z >= 0.0) != (next.z >= 0.0)) {
+            clipped[count] = mix(current, next, current.z / (current.z - next.z));
+            count = count + 1u;
+        }
+    }
+    if (count < 3u) {
+        return vec4<f32>(0.0);
+    }
+
+    var sum = vec3<f32>(0.0);
+    for (var index = 0u; index < count; index = index + 1u) {
+        let next = select(index + 1u, 0u, index + 1u == count);
+        sum = sum + ltc_edge(normalize(clipped[index]), normalize(clipped[next]));
+    }
+    // The winding faces away from the point when it's behind the light
+    var form_factor = max(sum.z, 0.0);
+    if (area_light.parameters.y != 0u) {
+        form_factor = abs(sum.z);
+    }
+    form_factor = form_factor / (2.0 * PI);
+
+    var emission = vec3<f32>(1.0);
+    if (area_light.parameters.z != 0u) {
+        emission = area_light_emission(area, corners);
+    }
+    return vec4<f32>(emission * form_factor, form_factor);
+}
+
+// Light from the rectangular lights, through their lobes fitted to the diffuse and specular
+// reflection of the surface
+fn area_light(brdf: Brdf, position: vec3<f32>, layers: u32) -> Reflected {
+    var reflected: Reflected;
+    reflected.diffuse = vec3<f32>(0.0);
+    reflected.specular = vec3<f32>(0.0);
+    reflected.sheen = vec3<f32>(0.0);
+    reflected.clearcoat = vec3<f32>(0.0);
+
+    let n_dot_v = clamp(dot(brdf.normal, brdf.view), 0.0001, 1.0);
+    let matrix = ltc_lookup(0u, brdf.roughness, n_dot_v);
+    let terms = ltc_lookup(LTC_SIZE * LTC_SIZE, brdf.roughness, n_dot_v);
+    let minv = mat3x3<f32>(
+        vec3<f32>(matrix.x, 0.0, matrix.y),
+        vec3<f32>(0.0, 1.0, 0.0),
+        vec3<f32>(matrix.z, 0.0, matrix.w),
+    );
+    let identity = mat3x3<f32>(vec3<f32>(1.0, 0.0, 0.0), vec3<f32>(0.0, 1.0, 0.0), vec3<f32>(0.0, 0.0, 1.0));
+    let specular_scale = brdf.f0 * terms.x + (brdf.f90 - brdf.f0) * terms.y;
+
+    for (var index = 0u; index < area_lights.count; index = index + 1u) {
+        let area_light = area_lights.lights[index];
+        if ((area_light.parameters.x & layers) == 0u) {
+            continue;
+        }
+        let diffuse = ltc_evaluate(index, brdf.normal, brdf.view, position, identity);
+        let specular = ltc_evaluate(index, brdf.normal, brdf.view, position, minv);
+        reflected.diffuse = reflected.diffuse + brdf.diffuse_color * area_light.color.rgb * diffuse.rgb;
+        reflected.specular = reflected.specular + specular_scale * area_light.color.rgb * specular.rgb;
+    }
+    return reflected;
+}
+
+// Light is banded by how directly it reaches the surface, with a single hard edged highlight
+fn toon_light(brdf: Brdf, l: vec3<f32>, radiance: vec3<f32>, visibility: f32) -> Reflected {
+    let n_dot_l = max(dot(brdf.normal, l), 0.0);
+    let bands = f32(material.toon_bands);
+    let ramp = ceil(n_dot_l * visibility * bands) / bands;
+
+    let n_dot_h = max(dot(brdf.normal, normalize(brdf.view + l)), 0.0);
+    let alpha = brdf.roughness * brdf.roughness;
+    let shininess = 2.0 / max(alpha * alpha, 0.0001) - 2.0;
+    let highlight = select(0.0, 1.0 - brdf.roughness, pow(n_dot_h, shininess) > 0.5 && ramp > 0.0);
+
+    var reflected: Reflected;
+    reflected.diffuse = brdf.diffuse_color / PI * radiance * ramp;
+    reflected.specular = brdf.specular_color / PI * radiance * highlight;
+    reflected.sheen = vec3<f32>(0.0);
+    reflected.clearcoat = vec3<f32>(0.0);
+    return reflected;
+}
+
+// Visibility is kept apart from the radiance so toon shading can band shadows with the light
+fn direct_light(brdf: Brdf, l: vec3<f32>, radiance: vec3<f32>, visibility: f32) -> Reflected {
+    if (material.shading_model == SHADING_MODEL_TOON) {
+        return toon_light(brdf, l, radiance, visibility);
+    }
+    return reflect_light(brdf, l, radiance * visibility);
+}
+
+// What a material looks like before it's lit, where the material's own textures and factors have
+// already been applied
+struct SurfaceInput {
+    world_position: vec3<f32>;
+    // Toward the camera
+    view: vec3<f32>;
+    uv: vec2<f32>;
+    uv_1: vec2<f32>;
+    color: vec4<f32>;
+    base_color: vec4<f32>;
+    metallic: f32;
+    roughness: f32;
+    // With the normal map applied, in world space
+    normal: vec3<f32>;
+    emissive: vec3<f32>;
+    // Baked ambient occlusion, one where nothing is occluded
+    occlusion: f32;
+};
+
+struct SurfaceOutput {
+    base_color: vec4<f32>;
+    metallic: f32;
+    roughness: f32;
+    normal: vec3<f32>;
+    emissive: vec3<f32>;
+    occlusion: f32;
+};
+
+// Custom surface, replaced by the WGSL file of a material with a shader. Everything above can be
+// used there, such as sample_material to read the material's textures
+fn surface(in: SurfaceInput) -> SurfaceOutput {
+    var out: SurfaceOutput;
+    out.base_color = in.base_color;
+    out.metallic = in.metallic;
+    out.roughness = in.roughness;
+    out.normal = in.normal;
+    out.emissive = in.emissive;
+    out.occlusion = in.occlusion;
+    return out;
+}
+// End of custom surface
+
+fn shade(
+    in: VertexOutput,
+    base_color_sample: vec4<f32>,
+    metallic_roughness: vec4<f32>,
+    normal_sample: vec3<f32>,
+    occlusion_sample: f32,
+    emissive_sample: vec3<f32>,
+    clearcoat_sample: vec4<f32>,
+    clearcoat_normal_sample: vec3<f32>,
+    transmission_sample: f32,
+    sheen_sample: vec4<f32>,
+    specular_sample: vec4<f32>,
+    lightmap_sample: vec3<f32>,
+) -> Surface {
+    let tangent_normal = (normal_sample * 2.0 - 1.0) * vec3<f32>(material.normal_scale, material.normal_scale, 1.0);
+    var surface_input: SurfaceInput;
+    surface_input.world_position = in.world_position;
+    surface_input.view = normalize(camera.position.xyz - in.world_position);
+    surface_input.uv = in.uv;
+    surface_input.uv_1 = in.uv_1;
+    surface_input.color = in.color;
+    surface_input.base_color = material.base_color_factor * base_color_sample * in.color;
+    surface_input.metallic = clamp(material.metallic_factor * metallic_roughness.b, 0.0, 1.0);
+    surface_input.roughness = clamp(material.roughness_factor * metallic_roughness.g, 0.04, 1.0);
+    surface_input.normal = perturbed_normal(in, tangent_normal);
+    surface_input.emissive = material.emissive_factor.rgb * material.emissive_factor.w * emissive_sample;
+    surface_input.occlusion = mix(1.0, occlusion_sample, material.occlusion_strength);
+    let described = surface(surface_input);
+
+    let base_color = described.base_color;
+    let metallic = clamp(described.metallic, 0.0, 1.0);
+    let alpha = select(1.0, base_color.a, material.alpha_mode == ALPHA_MODE_BLEND);
+
+    var surface: Surface;
+    if (material.shading_model == SHADING_MODEL_UNLIT) {
+        surface.diffuse = base_color.rgb;
+        surface.specular = vec3<f32>(0.0);
+        surface.base_weight = 1.0;
+        surface.layers = vec3<f32>(0.0);
+        surface.alpha = alpha;
+        surface.transmission = 0.0;
+        surface.transmittance = vec3<f32>(0.0);
+        surface.normal = normalize(in.normal);
+        surface.view = surface_input.view;
+        surface.roughness = 1.0;
+        return surface;
+    }
+
+    var brdf: Brdf;
+    brdf.roughness = clamp(described.roughness, 0.04, 1.0);
+    brdf.normal = normalize(described.normal);
+    brdf.view = surface_input.view;
+    let n_dot_v = max(dot(brdf.normal, brdf.view), 0.0001);
+
+    // The index of refraction and specular extensions shape the reflectance of non-metals
+    let specular_weight = material.specular_color_factor.w * specular_sample.a;
+    let reflectance = pow((material.ior - 1.0) / (material.ior + 1.0), 2.0);
+    let dielectric_f0 = min(reflectance * material.specular_color_factor.rgb * specular_sample.rgb, vec3<f32>(1.0)) * specular_weight;
+    brdf.f0 = mix(dielectric_f0, base_color.rgb, metallic);
+    brdf.f90 = vec3<f32>(mix(specular_weight, 1.0, metallic));
+    brdf.diffuse_color = (1.0 - metallic) * base_color.rgb;
+    brdf.specular_color = mix(vec3<f32>(specular_weight), base_color.rgb, metallic);
+    let view_fresnel = fresnel(brdf.f0, brdf.f90, n_dot_v);
+
+    brdf.sheen_color = material.sheen_color_factor.rgb * sheen_sample.rgb;
+    brdf.sheen_roughness = clamp(material.sheen_color_factor.w * sheen_sample.a, 0.0, 1.0);
+    // Roughly the energy the sheen reflects before it reaches the base layer
+    let sheen_scale = 1.0 - max_component(brdf.sheen_color) * 0.157;
+
+    brdf.clearcoat = clamp(material.clearcoat_factor * clearcoat_sample.r, 0.0, 1.0);
+    brdf.clearcoat_roughness = clamp(material.clearcoat_roughness_factor * clearcoat_sample.g, 0.04, 1.0);
+    let clearcoat_tangent_normal = (clearcoat_normal_sample * 2.0 - 1.0) * vec3<f32>(material.clearcoat_normal_scale, material.clearcoat_normal_scale, 1.0);
+    brdf.clearcoat_normal = perturbed_normal(in, clearcoat_tangent_normal);
+    let clearcoat_fresnel = fresnel(vec3<f32>(0.04), vec3<f32>(1.0), max(dot(brdf.clearcoat_normal, brdf.view), 0.0001)).x * brdf.clearcoat;
+
+    // Light colors are scaled so a white diffuse surface facing the light matches the terrain
+    var sun_radiance = light.color.rgb * PI * (1.0 - AMBIENT);
+    // Lights only shine on the layers they share with the instance
+    if ((light.layers & in.layers) == 0u) {
+        sun_radiance = vec3<f32>(0.0);
+    }
+    let noise = blue_noise_at(in.clip_position.xy);
+    var reflected = direct_light(brdf, light.direction.xyz, sun_radiance, min(shadow_visibility(in.world_position, noise), traced_shadow(in.clip_position.xy)));
+
+    for (var index = 0u; index < light.punctual_light_count; index = index + 1u) {
+        let punctual = light.punctual_lights[index];
+        if ((bitcast<u32>(punctual.parameters.w) & in.layers) == 0u) {
+            continue;
+        }
+        // Directional lights have no position and always shine from their direction
+        let to_light = punctual.position.xyz - in.world_position * punctual.position.w;
+        let l = normalize(to_light);
+        let radiance = punctual.color.rgb * punctual_attenuation(punctual, to_light, l) * local_shadow_visibility(punctual, in.world_position, noise);
+        let punctual_reflected = direct_light(brdf, l, radiance, 1.0);
+        reflected.diffuse = reflected.diffuse + punctual_reflected.diffuse;
+        reflected.specular = reflected.specular + punctual_reflected.specular;
+        reflected.sheen = reflected.sheen + punctual_reflected.sheen;
+        reflected.clearcoat = reflected.clearcoat + punctual_reflected.clearcoat;
+    }
+
+    if (area_lights.count > 0u && material.shading_model != SHADING_MODEL_TOON) {
+        let area_reflected = area_light(brdf, in.world_position, in.layers);
+        reflected.diffuse = reflected.diffuse + area_reflected.diffuse;
+        reflected.specular = reflected.specular + area_reflected.specular;
+    }
+
+    let baked_occlusion = described.occlusion;
+    let ssao_sample = textureLoad(ambient_occlusion_texture, vec2<i32>(in.clip_position.xy), 0).r;
+    let screen_space_occlusion = mix(1.0, ssao_sample, material.ssao_strength);
+    var occlusion = baked_occlusion * screen_space_occlusion;
+    if (material.occlusion_blend == OCCLUSION_BLEND_MIN) {
+        occlusion = min(baked_occlusion, screen_space_occlusion);
+    }
+
+    var emissive = described.emissive;
+
+    var environment = vec3<f32>(0.0);
+    if (material.shading_model != SHADING_MODEL_TOON) {
+        let reflection = reflect(-brdf.view, brdf.normal);
+        var radiance = probe_reflection(in.world_position, reflection, brdf.roughness);
+        // The planar reflection is sharp, so rougher surfaces lean on the probes instead
+        if (material.planar_reflection == 1u) {
+            radiance = mix(emissive_sample * material.emissive_factor.w, radiance, brdf.roughness);
+            emissive = vec3<f32>(0.0);
+        }
+        environment = radiance * environment_brdf(brdf.f0, brdf.f90, n_dot_v, brdf.roughness) * occlusion;
+    }
+
+    var ambient = ambient_light(in.world_position, brdf.normal);
+    if (material.lightmap_intensity > 0.0) {
+        ambient = lightmap_sample * material.lightmap_intensity;
+    }
+    surface.diffuse = reflected.diffuse + ambient * brdf.diffuse_color * occlusion;
+    surface.specular = reflected.specular + environment;
+    surface.transmittance = (vec3<f32>(1.0) - view_fresnel) * brdf.diffuse_color;
+    surface.base_weight = sheen_scale * (1.0 - clearcoat_fresnel);
+    surface.layers = reflected.sheen * (1.0 - clearcoat_fresnel) + reflected.clearcoat + emissive;
+    surface.alpha = alpha;
+    surface.transmission = clamp(material.transmission_factor * transmission_sample, 0.0, 1.0);
+    surface.normal = brdf.normal;
+    surface.view = brdf.view;
+    surface.roughness = brdf.roughness;
+    return surface;
+}
+
+fn texture_uv(index: u32, in: VertexOutput) -> vec2<f32> {
+    let transform = material.texture_transforms[index];
+    let uv = select(in.uv, in.uv_1, transform.tex_coord > 0u);
+    return mat2x2<f32>(transform.matrix.xy, transform.matrix.zw) * uv + transform.offset;
+}
+
+fn sample_texture(index: u32, in: VertexOutput) -> vec4<f32> {
+    let uv = texture_uv(index, in);
+    return sample_material(index, uv, dpdx(uv), dpdy(uv));
+}
+
+// The reflection where the surface is on screen, nudged by its normal map so ripples distort it
+fn sample_reflection(in: VertexOutput) -> vec3<f32> {
+    let dimensions = vec2<f32>(textureDimensions(ambient_occlusion_texture));
+    let tangent_normal = sample_texture(NORMAL_TEXTURE, in).xy * 2.0 - 1.0;
+    let uv = in.clip_position.xy / dimensions + tangent_normal * material.normal_scale * 0.02;
+    return sample_material(EMISSIVE_TEXTURE, uv, vec2<f32>(0.0), vec2<f32>(0.0)).rgb;
+}
+
+// Back faces of double sided surfaces are shaded as if they were seen from the front
+fn facing(in: VertexOutput, front_facing: bool) -> VertexOutput {
+    var out = in;
+    if (!front_facing) {
+        out.normal = -in.normal;
+        out.tangent = -in.tangent;
+    }
+    return out;
+}
+
+// Every texture is sampled up front, sampling after a discard isn't uniform control flow
+fn sample_surface(in: VertexOutput) -> Surface {
+    let base_color_sample = sample_texture(BASE_COLOR_TEXTURE, in);
+    let metallic_roughness = sample_texture(METALLIC_ROUGHNESS_TEXTURE, in);
+    let normal_sample = sample_texture(NORMAL_TEXTURE, in).xyz;
+    let occlusion_sample = sample_texture(OCCLUSION_TEXTURE, in).r;
+    var emissive_sample = sample_texture(EMISSIVE_TEXTURE, in).rgb;
+    if (material.planar_reflection == 1u) {
+        emissive_sample = sample_reflection(in);
+    }
+    let clearcoat_sample = sample_texture(CLEARCOAT_TEXTURE, in);
+    let clearcoat_normal_sample = sample_texture(CLEARCOAT_NORMAL_TEXTURE, in).xyz;
+    let transmission_sample = sample_texture(TRANSMISSION_TEXTURE, in).r;
+    let sheen_sample = sample_texture(SHEEN_TEXTURE, in);
+    let specular_sample = sample_texture(SPECULAR_TEXTURE, in);
+    let lightmap_sample = sample_texture(LIGHTMAP_TEXTURE, in).rgb;
+    return shade(
+        in,
+        base_color_sample,
+        metallic_roughness,
+        normal_sample,
+        occlusion_sample,
+        emissive_sample,
+        clearcoat_sample,
+        clearcoat_normal_sample,
+        transmission_sample,
+        sheen_sample,
+        specular_sample,
+        lightmap_sample,
+    );
+}
+
+fn masked(in: VertexOutput) -> bool {
+    let alpha = material.base_color_factor.a * in.color.a * sample_texture(BASE_COLOR_TEXTURE, in).a;
+    if (material.alpha_mode == ALPHA_MODE_DITHERED) {
+        return alpha < blue_noise_at(in.clip_position.xy);
+    }
+    return material.alpha_mode == ALPHA_MODE_MASK && alpha < material.alpha_cutoff;
+}
+
+// Only writes depth, discarding the holes cut by masked materials
+[[stage(fragment)]]
+fn fs_depth(in: VertexOutput) {
+    load_material();
+    if (masked(in)) {
+        discard;
+    }
+}
+
+[[stage(fragment)]]
+fn fs_main(in: VertexOutput, [[builtin(front_facing)]] front_facing: bool) -> [[location(0)]] vec4<f32> {
+    load_material();
+    let surface = sample_surface(facing(in, front_facing));
+    if (masked(in)) {
+        discard;
+    }
+    let color = (surface.diffuse + surface.specular) * surface.base_weight + surface.layers;
+    return vec4<f32>(color, surface.alpha);
+}
+
+fn refraction(incident: vec3<f32>, normal: vec3<f32>, eta: f32) -> vec3<f32> {
+    let cos_incident = dot(normal, incident);
+    let k = 1.0 - eta * eta * (1.0 - cos_incident * cos_incident);
+    if (k < 0.0) {
+        return vec3<f32>(0.0);
+    }
+    return eta * incident - (eta * cos_incident + sqrt(k)) * normal;
+}
+
+// Projects a world space position to a texture coordinate in the opaque scene color
+fn screen_uv(position: vec3<f32>) -> vec2<f32> {
+    let clip = camera.projection * camera.view * vec4<f32>(position, 1.0);
+    let ndc = clip.xy / clip.w;
+    return vec2<f32>(ndc.x * 0.5 + 0.5, 0.5 - ndc.y * 0.5);
+}
+
+// Rough surfaces blur the light behind them with a disc of samples
+fn transmitted_light(surface: Surface, in: VertexOutput) -> vec3<f32> {
+    let refracted = refraction(-surface.view, surface.normal, 1.0 / material.ior);
+    let uv = screen_uv(in.world_position + refracted * THIN_WALL_THICKNESS);
+    let radius = surface.roughness * surface.roughness * 0.05;
+    var light = vec3<f32>(0.0);
+    for (var index = 0u; index < TRANSMISSION_SAMPLES; index = index + 1u) {
+        let distance = sqrt((f32(index) + 0.5) / f32(TRANSMISSION_SAMPLES)) * radius;
+        let angle = f32(index) * GOLDEN_ANGLE;
+        let offset = vec2<f32>(cos(angle), sin(angle)) * distance;
+        light = light + textureSampleLevel(opaque_texture, opaque_sampler, uv + offset, 0.0).rgb;
+    }
+    return light / f32(TRANSMISSION_SAMPLES);
+}
+
+// Diffuse light is replaced by the refracted light of the opaque scene behind the surface
+[[stage(fragment)]]
+fn fs_transmission(in: VertexOutput, [[builtin(front_facing)]] front_facing: bool) -> [[location(0)]] vec4<f32> {
+    load_material();
+    let surface = sample_surface(facing(in, front_facing));
+    if (masked(in)) {
+        discard;
+    }
+    let transmitted = transmitted_light(surface, in) * surface.transmittance;
+    let diffuse = mix(surface.diffuse, transmitted, surface.transmission);
+    let color = (diffuse + surface.specular) * surface.base_weight + surface.layers;
+    return vec4<f32>(color, surface.alpha);
+}
@@ -0,0 +1,151 @@
+[[block]]
+struct Camera {
+    view: mat4x4<f32>;
+    projection: mat4x4<f32>;
+    inverse_view_projection: mat4x4<f32>;
+    position: vec4<f32>;
+    parameters: vec4<f32>;
+    previous_view_projection: mat4x4<f32>;
+    jitter: vec4<f32>;
+    depth: vec4<f32>;
+};
+[[group(0), binding(0)]]
+var<uniform> camera: Camera;
+
+[[group(1), binding(0)]]
+var hiz: texture_2d<f32>;
+
+[[block]]
+struct Pyramid {
+    // The size of the depth the pyramid was built from, which its first level repeats the
+    // edges of
+    depth_size: vec2<u32>;
+    levels: u32;
+};
+[[group(1), binding(1)]]
+var<uniform> pyramid: Pyramid;
+
+// Instances are copied a word at a time, since the vertex layout is tighter than a struct's
+[[block]]
+struct Words {
+    values: array<u32>;
+};
+[[group(2), binding(0)]]
+var<storage, read> source: Words;
+[[group(2), binding(1)]]
+var<storage, read_write> culled: Words;
+
+struct CullInstance {
+    min: vec3<f32>;
+    // The instance's batch, which it's copied into the culled instances of
+    batch: u32;
+    max: vec3<f32>;
+    // The first instance of the batch
+    batch_start: u32;
+};
+[[block]]
+struct CullInstances {
+    values: array<CullInstance>;
+};
+[[group(2), binding(2)]]
+var<storage, read> instances: CullInstances;
+
+// Draw arguments for each primitive of each batch, whose instance counts are added to here
+[[block]]
+struct Arguments {
+    values: array<atomic<u32>>;
+};
+[[group(2), binding(3)]]
+var<storage, read_write> arguments: Arguments;
+
+[[block]]
+struct Parameters {
+    primitives: u32;
+};
+[[group(2), binding(4)]]
+var<uniform> parameters: Parameters;
+
+let INSTANCE_WORDS: u32 = 29u;
+let ARGUMENT_WORDS: u32 = 5u;
+
+// Spliced: standard_depth
+
+fn is_visible(instance: CullInstance) -> bool {
+    let view_projection = camera.projection * camera.view;
+    var near = 1.0;
+    var rect_min = vec2<f32>(1.0);
+    var rect_max = vec2<f32>(-1.0);
+    for (var corner = 0u; corner < 8u; corner = corner + 1u) {
+        let position = vec3<f32>(
+            select(instance.min.x, instance.max.x, (corner & 1u) != 0u),
+            select(instance.min.y, instance.max.y, (corner & 2u) != 0u),
+            select(instance.min.z, instance.max.z, (corner & 4u) != 0u),
+        );
+        let clip = view_projection * vec4<f32>(position, 1.0);
+        // Bounds reaching behind the camera can't be tested, so they're always drawn
+        if (clip.w <= 0.0) {
+            return true;
+        }
+        let ndc = clip.xyz / clip.w;
+        near = min(near, standard_depth(ndc.z));
+        rect_min = min(rect_min, ndc.xy);
+        rect_max = max(rect_max, ndc.xy);
+    }
+    if (near < 0.0) {
+        return true;
+    }
+    if (any(rect_max < vec2<f32>(-1.0)) || any(rect_min > vec2<f32>(1.0))) {
+        return false;
+    }
+
+    // Texture rows run down the screen
+    let size = vec2<f32>(pyramid.depth_size);
+    let clamped_min = clamp(rect_min, vec2<f32>(-1.0), vec2<f32>(1.0));
+    let clamped_max = clamp(rect_max, vec2<f32>(-1.0), vec2<f32>(1.0));
+    let pixel_min = vec2<f32>(clamped_min.x * 0.5 + 0.5, 0.5 - clamped_max.y * 0.5) * size;
+    let pixel_max = vec2<f32>(clamped_max.x * 0.5 + 0.5, 0.5 - clamped_min.y * 0.5) * size;
+
+    // The level where the bounds cover at most two texels across, so four texels hold them
+    let extent = max(pixel_max.x - pixel_min.x, pixel_max.y - pixel_min.y);
+    let level = min(u32(ceil(log2(max(extent, 1.0)))), pyramid.levels - 1u);
+    let level_size = textureDimensions(hiz, i32(level)) - vec2<i32>(1);
+    let texel_min = min(vec2<i32>(vec2<u32>(pixel_min) >> vec2<u32>(level)), level_size);
+    let texel_max = min(vec2<i32>(vec2<u32>(pixel_max) >> vec2<u32>(level)), level_size);
+    let farthest = max(
+        max(
+            standard_depth(textureLoad(hiz, texel_min, i32(level)).r),
+            standard_depth(textureLoad(hiz, vec2<i32>(texel_max.x, texel_min.y), i32(level)).r),
+        ),
+        max(
+            standard_depth(textureLoad(hiz, vec2<i32>(texel_min.x, texel_max.y), i32(level)).r),
+            standard_depth(textureLoad(hiz, texel_max, i32(level)).r),
+        ),
+    );
+    return near <= farthest;
+}
+
+[[stage(compute), workgroup_size(64)]]
+fn cs_main([[builtin(global_invocation_id)]] id: vec3<u32>) {
+    let index = id.x;
+    if (index >= arrayLength(&instances.values)) {
+        return;
+    }
+    let instance = instances.values[index];
+    if (!is_visible(instance)) {
+        return;
+    }
+
+    // Every primitive of the batch draws the same instances, so the first one's count places
+    // this instance and the rest are kept level with it
+    let first_argument = instance.batch * parameters.primitives * ARGUMENT_WORDS + 1u;
+    let slot = atomicAdd(&arguments.values[first_argument], 1u);
+    for (var primitive = 1u; primitive < parameters.primitives; primitive = primitive + 1u) {
+        let ignored = atomicAdd(&arguments.values[first_argument + primitive * ARGUMENT_WORDS], 1u);
+    }
+
+    let from = index * INSTANCE_WORDS;
+    let to = (instance.batch_start + slot) * INSTANCE_WORDS;
+    for (var word = 0u; word < INSTANCE_WORDS; word = word + 1u) {
+        culled.values[to + word] = source.values[from + word];
+    }
+}
@@ -0,0 +1,15 @@
+// Drawn in place of a material shader that failed to compile. A magenta checker in world space,
+// so it shows without texture coordinates and glows in the dark
+fn surface(in: SurfaceInput) -> SurfaceOutput {
+    let cell = floor(in.world_position * 4.0);
+    let checker = abs(cell.x + cell.y + cell.z) % 2.0;
+    let color = mix(vec3<f32>(1.0, 0.0, 1.0), vec3<f32>(0.05, 0.0, 0.05), checker);
+    var out: SurfaceOutput;
+    out.base_color = vec4<f32>(color, 1.0);
+    out.metallic = 0.0;
+    out.roughness = 1.0;
+    out.normal = in.normal;
+    out.emissive = color * 0.5;
+    out.occlusion = 1.0;
+    return out;
+}
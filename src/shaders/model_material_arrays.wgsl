@@ -0,0 +1,120 @@
+// Material bindings of models whose materials are packed into texture arrays, replacing those
+// of model.wgsl. Materials whose images are the same size are bound at once and picked by the
+// index given for each draw, with a layer of each texture array per image
+struct TextureTransform {
+    // Columns of the rotation and scale
+    matrix: vec4<f32>;
+    offset: vec2<f32>;
+    // Only the first two sets are provided by the vertices, later ones fall back to the second
+    tex_coord: u32;
+};
+
+struct Material {
+    base_color_factor: vec4<f32>;
+    emissive_factor: vec4<f32>;
+    metallic_factor: f32;
+    roughness_factor: f32;
+    normal_scale: f32;
+    occlusion_strength: f32;
+    ssao_strength: f32;
+    occlusion_blend: u32;
+    alpha_mode: u32;
+    alpha_cutoff: f32;
+    sheen_color_factor: vec4<f32>;
+    specular_color_factor: vec4<f32>;
+    clearcoat_factor: f32;
+    clearcoat_roughness_factor: f32;
+    clearcoat_normal_scale: f32;
+    transmission_factor: f32;
+    ior: f32;
+    shading_model: u32;
+    toon_bands: u32;
+    outline_width: f32;
+    outline_color: vec4<f32>;
+    texture_transforms: array<TextureTransform, 11>;
+    // Zero without a lightmap
+    lightmap_intensity: f32;
+    // One when the emissive texture holds a reflection of the scene lined up with the screen
+    planar_reflection: u32;
+};
+
+[[block]]
+struct Materials {
+    values: array<Material>;
+};
+[[group(1), binding(0)]]
+var<storage, read> materials: Materials;
+[[group(1), binding(1)]]
+var base_color_textures: texture_2d_array<f32>;
+[[group(1), binding(2)]]
+var metallic_roughness_textures: texture_2d_array<f32>;
+[[group(1), binding(3)]]
+var normal_textures: texture_2d_array<f32>;
+[[group(1), binding(4)]]
+var occlusion_textures: texture_2d_array<f32>;
+[[group(1), binding(5)]]
+var emissive_textures: texture_2d_array<f32>;
+[[group(1), binding(6)]]
+var material_sampler: sampler;
+[[group(1), binding(7)]]
+var clearcoat_textures: texture_2d_array<f32>;
+[[group(1), binding(8)]]
+var clearcoat_normal_textures: texture_2d_array<f32>;
+[[group(1), binding(9)]]
+var transmission_textures: texture_2d_array<f32>;
+[[group(1), binding(10)]]
+var sheen_textures: texture_2d_array<f32>;
+[[group(1), binding(11)]]
+var specular_textures: texture_2d_array<f32>;
+[[group(1), binding(12)]]
+var lightmap_textures: texture_2d_array<f32>;
+
+// The layer of each texture of each material, or NO_LAYER when it has none
+[[block]]
+struct MaterialLayers {
+    values: array<u32>;
+};
+[[group(1), binding(13)]]
+var<storage, read> material_layers: MaterialLayers;
+
+// What changes between draws, swapped for a push constant where they're supported
+[[block]]
+struct DrawData {
+    material: u32;
+};
+[[group(1), binding(14)]]
+var<uniform> draw: DrawData;
+
+let MATERIAL_TEXTURE_COUNT: u32 = 11u;
+let NO_LAYER: u32 = 4294967295u;
+
+var<private> material: Material;
+
+fn load_material() {
+    material = materials.values[draw.material];
+}
+
+// Gradients are taken up front, since the texture chosen isn't uniform control flow
+fn sample_material(texture: u32, uv: vec2<f32>, ddx: vec2<f32>, ddy: vec2<f32>) -> vec4<f32> {
+    let layer = material_layers.values[draw.material * MATERIAL_TEXTURE_COUNT + texture];
+    // Missing textures fall back to white, or a flat normal for normal maps
+    if (layer == NO_LAYER) {
+        if (texture == 2u || texture == 6u) {
+            return vec4<f32>(0.5019608, 0.5019608, 1.0, 1.0);
+        }
+        return vec4<f32>(1.0);
+    }
+    switch (texture) {
+        case 0u: { return textureSampleGrad(base_color_textures, material_sampler, uv, i32(layer), ddx, ddy); }
+        case 1u: { return textureSampleGrad(metallic_roughness_textures, material_sampler, uv, i32(layer), ddx, ddy); }
+        case 2u: { return textureSampleGrad(normal_textures, material_sampler, uv, i32(layer), ddx, ddy); }
+        case 3u: { return textureSampleGrad(occlusion_textures, material_sampler, uv, i32(layer), ddx, ddy); }
+        case 4u: { return textureSampleGrad(emissive_textures, material_sampler, uv, i32(layer), ddx, ddy); }
+        case 5u: { return textureSampleGrad(clearcoat_textures, material_sampler, uv, i32(layer), ddx, ddy); }
+        case 6u: { return textureSampleGrad(clearcoat_normal_textures, material_sampler, uv, i32(layer), ddx, ddy); }
+        case 7u: { return textureSampleGrad(transmission_textures, material_sampler, uv, i32(layer), ddx, ddy); }
+        case 8u: { return textureSampleGrad(sheen_textures, material_sampler, uv, i32(layer), ddx, ddy); }
+        case 9u: { return textureSampleGrad(specular_textures, material_sampler, uv, i32(layer), ddx, ddy); }
+        default: { return textureSampleGrad(lightmap_textures, material_sampler, uv, i32(layer), ddx, ddy); }
+    }
+}
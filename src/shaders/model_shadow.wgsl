@@ -0,0 +1,21 @@
+[[block]]
+struct Light {
+    view_projection: mat4x4<f32>;
+    direction: vec4<f32>;
+    color: vec4<f32>;
+};
+[[group(0), binding(0)]]
+var<uniform> light: Light;
+
+struct InstanceInput {
+    [[location(4)]] model_0: vec4<f32>;
+    [[location(5)]] model_1: vec4<f32>;
+    [[location(6)]] model_2: vec4<f32>;
+    [[location(7)]] model_3: vec4<f32>;
+};
+
+[[stage(vertex)]]
+fn vs_main([[location(0)]] position: vec3<f32>, instance: InstanceInput) -> [[builtin(position)]] vec4<f32> {
+    let model = mat4x4<f32>(instance.model_0, instance.model_1, instance.model_2, instance.model_3);
+    return light.view_projection * model * vec4<f32>(position, 1.0);
+}
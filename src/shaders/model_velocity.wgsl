@@ -0,0 +1,59 @@
+[[block]]
+struct Camera {
+    view: mat4x4<f32>;
+    projection: mat4x4<f32>;
+    inverse_view_projection: mat4x4<f32>;
+    position: vec4<f32>;
+    parameters: vec4<f32>;
+    previous_view_projection: mat4x4<f32>;
+    jitter: vec4<f32>;
+};
+[[group(0), binding(0)]]
+var<uniform> camera: Camera;
+
+struct VertexInput {
+    [[location(0)]] position: vec3<f32>;
+};
+
+struct InstanceInput {
+    [[location(4)]] model_0: vec4<f32>;
+    [[location(5)]] model_1: vec4<f32>;
+    [[location(6)]] model_2: vec4<f32>;
+    [[location(7)]] model_3: vec4<f32>;
+    [[location(8)]] previous_model_0: vec4<f32>;
+    [[location(9)]] previous_model_1: vec4<f32>;
+    [[location(10)]] previous_model_2: vec4<f32>;
+    [[location(11)]] previous_model_3: vec4<f32>;
+};
+
+struct VertexOutput {
+    [[builtin(position)]] clip_position: vec4<f32>;
+    [[location(0)]] current: vec4<f32>;
+    [[location(1)]] previous: vec4<f32>;
+};
+
+[[stage(vertex)]]
+fn vs_main(vertex: VertexInput, instance: InstanceInput) -> VertexOutput {
+    let model = mat4x4<f32>(instance.model_0, instance.model_1, instance.model_2, instance.model_3);
+    let previous_model = mat4x4<f32>(
+        instance.previous_model_0,
+        instance.previous_model_1,
+        instance.previous_model_2,
+        instance.previous_model_3,
+    );
+    var out: VertexOutput;
+    out.current = camera.projection * camera.view * model * vec4<f32>(vertex.position, 1.0);
+    out.previous = camera.previous_view_projection * previous_model * vec4<f32>(vertex.position, 1.0);
+    out.clip_position = out.current;
+    return out;
+}
+
+fn clip_to_uv(clip: vec4<f32>) -> vec2<f32> {
+    let ndc = clip.xy / clip.w;
+    return vec2<f32>(ndc.x * 0.5 + 0.5, 0.5 - ndc.y * 0.5);
+}
+
+[[stage(fragment)]]
+fn fs_main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    return vec4<f32>(clip_to_uv(in.current) + camera.jitter.xy - clip_to_uv(in.previous), 0.0, 1.0);
+}
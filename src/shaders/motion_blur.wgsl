@@ -0,0 +1,45 @@
+[[block]]
+struct MotionBlur {
+    samples: u32;
+    // Fraction of the frame the shutter stays open for
+    shutter: f32;
+};
+[[group(0), binding(0)]]
+var scene_texture: texture_2d<f32>;
+[[group(0), binding(1)]]
+var scene_sampler: sampler;
+[[group(0), binding(2)]]
+var velocity_texture: texture_2d<f32>;
+[[group(0), binding(3)]]
+var<uniform> motion_blur: MotionBlur;
+
+// Keeps very fast motion from smearing across the whole screen
+let MAX_BLUR_PIXELS: f32 = 64.0;
+
+// Spliced: fullscreen_triangle
+
+// Averages samples along the motion of the pixel while the shutter was open, centered on the
+// current frame
+[[stage(fragment)]]
+fn fs_main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let dimensions = vec2<f32>(textureDimensions(scene_texture));
+    let velocity = textureLoad(velocity_texture, vec2<i32>(in.clip_position.xy), 0).xy;
+    var blur = velocity * motion_blur.shutter * dimensions;
+    let blur_length = length(blur);
+    if (blur_length > MAX_BLUR_PIXELS) {
+        blur = blur * (MAX_BLUR_PIXELS / blur_length);
+    }
+
+    let center = textureSampleLevel(scene_texture, scene_sampler, in.uv, 0.0);
+    if (blur_length < 0.5 || motion_blur.samples < 2u) {
+        return center;
+    }
+
+    var color = vec3<f32>(0.0);
+    for (var index = 0u; index < motion_blur.samples; index = index + 1u) {
+        let t = f32(index) / f32(motion_blur.samples - 1u) - 0.5;
+        let uv = in.uv - blur * t / dimensions;
+        color = color + textureSampleLevel(scene_texture, scene_sampler, uv, 0.0).rgb;
+    }
+    return vec4<f32>(color / f32(motion_blur.samples), 1.0);
+}
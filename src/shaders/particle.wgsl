@@ -0,0 +1,165 @@
+[[block]]
+struct Camera {
+    view: mat4x4<f32>;
+    projection: mat4x4<f32>;
+    inverse_view_projection: mat4x4<f32>;
+    position: vec4<f32>;
+    parameters: vec4<f32>;
+    previous_view_projection: mat4x4<f32>;
+    jitter: vec4<f32>;
+    depth: vec4<f32>;
+};
+[[group(0), binding(0)]]
+var<uniform> camera: Camera;
+
+// Spliced: standard_depth
+
+[[block]]
+struct Emitter {
+    // w: spread
+    position: vec4<f32>;
+    // w: drag
+    direction: vec4<f32>;
+    // w: delta time
+    gravity: vec4<f32>;
+    speed: vec2<f32>;
+    lifetime: vec2<f32>;
+    size: vec2<f32>;
+    flipbook: vec2<f32>;
+    colors: array<vec4<f32>, 4>;
+    spawn_offset: u32;
+    spawn_count: u32;
+    capacity: u32;
+    seed: u32;
+    color_count: u32;
+    sort_count: u32;
+    soft_fade_distance: f32;
+    sorted: u32;
+};
+[[group(1), binding(0)]]
+var<uniform> emitter: Emitter;
+
+struct Particle {
+    // w: age
+    position: vec4<f32>;
+    // w: lifetime
+    velocity: vec4<f32>;
+};
+
+[[block]]
+struct Particles {
+    data: array<Particle>;
+};
+[[group(1), binding(1)]]
+var<storage, read> particles: Particles;
+
+struct SortEntry {
+    key: f32;
+    index: u32;
+};
+
+[[block]]
+struct SortEntries {
+    data: array<SortEntry>;
+};
+[[group(1), binding(2)]]
+var<storage, read> entries: SortEntries;
+
+[[group(1), binding(3)]]
+var flipbook_texture: texture_2d<f32>;
+[[group(1), binding(4)]]
+var flipbook_sampler: sampler;
+
+[[group(2), binding(0)]]
+var depth_texture: texture_depth_2d;
+
+struct VertexOutput {
+    [[builtin(position)]] clip_position: vec4<f32>;
+    [[location(0)]] color: vec4<f32>;
+    [[location(1)]] uv: vec2<f32>;
+    [[location(2)]] view_distance: f32;
+};
+
+fn color_over_life(t: f32) -> vec4<f32> {
+    if (emitter.color_count <= 1u) {
+        return emitter.colors[0];
+    }
+    let scaled = t * f32(emitter.color_count - 1u);
+    let segment = min(u32(scaled), emitter.color_count - 2u);
+    return mix(emitter.colors[segment], emitter.colors[segment + 1u], scaled - f32(segment));
+}
+
+[[stage(vertex)]]
+fn vs_main(
+    [[builtin(vertex_index)]] vertex_index: u32,
+    [[builtin(instance_index)]] instance_index: u32,
+) -> VertexOutput {
+    var output: VertexOutput;
+
+    var index = instance_index;
+    if (emitter.sorted != 0u) {
+        index = entries.data[instance_index].index;
+    }
+
+    let particle = particles.data[index];
+    if (particle.position.w >= particle.velocity.w) {
+        // Dead particles collapse to a point outside the clip volume
+        output.clip_position = vec4<f32>(2.0, 2.0, 2.0, 1.0);
+        return output;
+    }
+
+    var corners = array<vec2<f32>, 6>(
+        vec2<f32>(-0.5, -0.5),
+        vec2<f32>(0.5, -0.5),
+        vec2<f32>(0.5, 0.5),
+        vec2<f32>(-0.5, -0.5),
+        vec2<f32>(0.5, 0.5),
+        vec2<f32>(-0.5, 0.5),
+    );
+    let corner = corners[vertex_index];
+
+    let t = clamp(particle.position.w / particle.velocity.w, 0.0, 1.0);
+    let size = mix(emitter.size.x, emitter.size.y, t);
+
+    var view_position = camera.view * vec4<f32>(particle.position.xyz, 1.0);
+    view_position = vec4<f32>(view_position.xy + corner * size, view_position.zw);
+
+    let frame_count = u32(emitter.flipbook.x * emitter.flipbook.y);
+    let frame = min(u32(t * f32(frame_count)), frame_count - 1u);
+    let columns = u32(emitter.flipbook.x);
+    let cell = vec2<f32>(f32(frame % columns), f32(frame / columns));
+    let corner_uv = vec2<f32>(corner.x + 0.5, 0.5 - corner.y);
+
+    output.clip_position = camera.projection * view_position;
+    output.color = color_over_life(t);
+    output.uv = (corner_uv + cell) / emitter.flipbook;
+    output.view_distance = -view_position.z;
+    return output;
+}
+
+fn linearize_depth(depth: f32) -> f32 {
+    let z_near = camera.parameters.x;
+    let z_far = camera.parameters.y;
+    return z_near * z_far / (z_far - standard_depth(depth) * (z_far - z_near));
+}
+
+[[stage(fragment)]]
+fn fs_main(input: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let texel = textureSample(flipbook_texture, flipbook_sampler, input.uv);
+
+    let scene_depth = textureLoad(depth_texture, vec2<i32>(input.clip_position.xy), 0);
+    let scene_distance = linearize_depth(scene_depth);
+    if (input.view_distance >= scene_distance) {
+        discard;
+    }
+
+    // Fade out particles as they approach opaque geometry to hide hard intersections
+    let fade = clamp(
+        (scene_distance - input.view_distance) / max(emitter.soft_fade_distance, 0.0001),
+        0.0,
+        1.0
+    );
+
+    let color = input.color * texel;
+    return vec4<f32>(color.rgb, color.a * fade);
+}
@@ -0,0 +1,176 @@
+[[block]]
+struct Camera {
+    view: mat4x4<f32>;
+    projection: mat4x4<f32>;
+    inverse_view_projection: mat4x4<f32>;
+    position: vec4<f32>;
+    parameters: vec4<f32>;
+};
+[[group(0), binding(0)]]
+var<uniform> camera: Camera;
+
+[[block]]
+struct Emitter {
+    // w: spread
+    position: vec4<f32>;
+    // w: drag
+    direction: vec4<f32>;
+    // w: delta time
+    gravity: vec4<f32>;
+    speed: vec2<f32>;
+    lifetime: vec2<f32>;
+    size: vec2<f32>;
+    flipbook: vec2<f32>;
+    colors: array<vec4<f32>, 4>;
+    spawn_offset: u32;
+    spawn_count: u32;
+    capacity: u32;
+    seed: u32;
+    color_count: u32;
+    sort_count: u32;
+    soft_fade_distance: f32;
+    sorted: u32;
+    // Added to live particles once after the scene's origin moves
+    translation: vec4<f32>;
+};
+[[group(1), binding(0)]]
+var<uniform> emitter: Emitter;
+
+struct Particle {
+    // w: age
+    position: vec4<f32>;
+    // w: lifetime
+    velocity: vec4<f32>;
+};
+
+[[block]]
+struct Particles {
+    data: array<Particle>;
+};
+[[group(1), binding(1)]]
+var<storage, read_write> particles: Particles;
+
+struct SortEntry {
+    key: f32;
+    index: u32;
+};
+
+[[block]]
+struct SortEntries {
+    data: array<SortEntry>;
+};
+[[group(1), binding(2)]]
+var<storage, read_write> entries: SortEntries;
+
+[[block]]
+struct SortParameters {
+    j: u32;
+    k: u32;
+    padding: vec2<u32>;
+};
+[[group(2), binding(0)]]
+var<uniform> sort: SortParameters;
+
+fn hash(value: u32) -> u32 {
+    let state = value * 747796405u + 2891336453u;
+    let word = ((state >> ((state >> 28u) + 4u)) ^ state) * 277803737u;
+    return (word >> 22u) ^ word;
+}
+
+fn random(seed: u32) -> f32 {
+    return f32(hash(seed)) / 4294967295.0;
+}
+
+fn random_direction_in_cone(seed: u32, axis: vec3<f32>, half_angle: f32) -> vec3<f32> {
+    let cos_theta = mix(1.0, cos(half_angle), random(seed));
+    let sin_theta = sqrt(max(1.0 - cos_theta * cos_theta, 0.0));
+    let phi = 6.28318530718 * random(seed + 1u);
+
+    var up = vec3<f32>(0.0, 1.0, 0.0);
+    if (abs(axis.y) > 0.999) {
+        up = vec3<f32>(1.0, 0.0, 0.0);
+    }
+    let tangent = normalize(cross(up, axis));
+    let bitangent = cross(axis, tangent);
+
+    return normalize(
+        tangent * cos(phi) * sin_theta + bitangent * sin(phi) * sin_theta + axis * cos_theta
+    );
+}
+
+[[stage(compute), workgroup_size(64)]]
+fn simulate([[builtin(global_invocation_id)]] id: vec3<u32>) {
+    let index = id.x;
+    if (index >= emitter.capacity) {
+        return;
+    }
+
+    var particle = particles.data[index];
+    let delta_time = emitter.gravity.w;
+
+    // Particles are emitted into a ring buffer, starting at the spawn offset
+    let slot = (index + emitter.capacity - emitter.spawn_offset) % emitter.capacity;
+    if (slot < emitter.spawn_count) {
+        let seed = hash(index ^ emitter.seed);
+        let direction = random_direction_in_cone(seed, normalize(emitter.direction.xyz), emitter.position.w);
+        let speed = mix(emitter.speed.x, emitter.speed.y, random(seed + 2u));
+        let lifetime = mix(emitter.lifetime.x, emitter.lifetime.y, random(seed + 3u));
+        particle.position = vec4<f32>(emitter.position.xyz, 0.0);
+        particle.velocity = vec4<f32>(direction * speed, lifetime);
+    } else {
+        if (particle.position.w < particle.velocity.w) {
+            var velocity = particle.velocity.xyz + emitter.gravity.xyz * delta_time;
+            velocity = velocity * max(1.0 - emitter.direction.w * delta_time, 0.0);
+            particle.position = vec4<f32>(
+                particle.position.xyz + emitter.translation.xyz + velocity * delta_time,
+                particle.position.w + delta_time
+            );
+            particle.velocity = vec4<f32>(velocity, particle.velocity.w);
+        }
+    }
+
+    particles.data[index] = particle;
+}
+
+[[stage(compute), workgroup_size(64)]]
+fn compute_sort_keys([[builtin(global_invocation_id)]] id: vec3<u32>) {
+    let index = id.x;
+    if (index >= emitter.sort_count) {
+        return;
+    }
+
+    // Dead particles and padding sort behind everything else
+    var entry: SortEntry;
+    entry.key = -1.0;
+    entry.index = index;
+    if (index < emitter.capacity) {
+        let particle = particles.data[index];
+        if (particle.position.w < particle.velocity.w) {
+            let view_position = camera.view * vec4<f32>(particle.position.xyz, 1.0);
+            entry.key = -view_position.z;
+        }
+    }
+    entries.data[index] = entry;
+}
+
+[[stage(compute), workgroup_size(64)]]
+fn bitonic_sort([[builtin(global_invocation_id)]] id: vec3<u32>) {
+    let index = id.x;
+    if (index >= emitter.sort_count) {
+        return;
+    }
+
+    let partner = index ^ sort.j;
+    if (partner <= index) {
+        return;
+    }
+
+    // Sorted in descending order of distance so particles are drawn back to front
+    let a = entries.data[index];
+    let b = entries.data[partner];
+    let descending = (index & sort.k) == 0u;
+    if ((descending && a.key < b.key) || (!descending && a.key > b.key)) {
+        entries.data[index] = b;
+        entries.data[partner] = a;
+    }
+}
@@ -0,0 +1,225 @@
+[[block]]
+struct PathTracer {
+    inverse_view_projection: mat4x4<f32>;
+    camera_position: vec4<f32>;
+    // Points towards the sun
+    sun_direction: vec4<f32>;
+    sun_color: vec4<f32>;
+    sky_color: vec4<f32>;
+    // x, y: dimensions, z: the sample being accumulated, w: bounces
+    parameters: vec4<u32>;
+};
+[[block]]
+struct Vectors {
+    values: array<vec4<f32>>;
+};
+[[group(0), binding(0)]]
+var<uniform> tracer: PathTracer;
+// Nine per triangle, positions with the material index's bits in the first w, then normals
+// and colors
+[[group(0), binding(1)]]
+var<storage, read> triangles: Vectors;
+// Two per node, leaves hold their first triangle in min's w and their count in max's w,
+// interior nodes hold their second child with a count of zero
+[[group(0), binding(2)]]
+var<storage, read> nodes: Vectors;
+// Three per material, base color, emissive, then metallic and roughness
+[[group(0), binding(3)]]
+var<storage, read> materials: Vectors;
+[[group(0), binding(4)]]
+var<storage, read_write> accumulation: Vectors;
+
+let PI: f32 = 3.14159265;
+let NO_HIT: u32 = 4294967295u;
+let FAR: f32 = 1.0e30;
+let EPSILON: f32 = 0.0001;
+let STACK_SIZE: u32 = 64u;
+
+var<private> seed: u32;
+
+fn random() -> f32 {
+    seed = seed * 747796405u + 2891336453u;
+    var word = ((seed >> ((seed >> 28u) + 4u)) ^ seed) * 277803737u;
+    word = (word >> 22u) ^ word;
+    return f32(word) / 4294967296.0;
+}
+
+fn random_unit_vector() -> vec3<f32> {
+    let z = random() * 2.0 - 1.0;
+    let angle = random() * 2.0 * PI;
+    let radius = sqrt(max(1.0 - z * z, 0.0));
+    return vec3<f32>(radius * cos(angle), radius * sin(angle), z);
+}
+
+struct Hit {
+    distance: f32;
+    triangle: u32;
+    barycentric: vec2<f32>;
+};
+
+fn enters_bounds(origin: vec3<f32>, inverse_direction: vec3<f32>, minimum: vec3<f32>, maximum: vec3<f32>, closest: f32) -> bool {
+    let t0 = (minimum - origin) * inverse_direction;
+    let t1 = (maximum - origin) * inverse_direction;
+    let near = min(t0, t1);
+    let far = max(t0, t1);
+    let enter = max(max(near.x, near.y), near.z);
+    let exit = min(min(far.x, far.y), far.z);
+    return exit >= max(enter, 0.0) && enter < closest;
+}
+
+// Closest triangle along the ray, walking the hierarchy with a stack
+fn trace(origin: vec3<f32>, direction: vec3<f32>, max_distance: f32) -> Hit {
+    var hit: Hit;
+    hit.distance = max_distance;
+    hit.triangle = NO_HIT;
+    let inverse_direction = vec3<f32>(1.0) / direction;
+
+    var stack: array<u32, 64>;
+    stack[0] = 0u;
+    var depth = 1u;
+    loop {
+        if (depth == 0u) {
+            break;
+        }
+        depth = depth - 1u;
+        let node = stack[depth];
+        let minimum = nodes.values[node * 2u];
+        let maximum = nodes.values[node * 2u + 1u];
+        if (!enters_bounds(origin, inverse_direction, minimum.xyz, maximum.xyz, hit.distance)) {
+            continue;
+        }
+
+        let count = bitcast<u32>(maximum.w);
+        if (count == 0u) {
+            // The hierarchy is built less than half as deep as the stack, so this only guards
+            // against writing past its end
+            if (depth + 2u <= STACK_SIZE) {
+                stack[depth] = bitcast<u32>(minimum.w);
+                stack[depth + 1u] = node + 1u;
+                depth = depth + 2u;
+            }
+            continue;
+        }
+
+        let first = bitcast<u32>(minimum.w);
+        for (var triangle = first; triangle < first + count; triangle = triangle + 1u) {
+            let p0 = triangles.values[triangle * 9u].xyz;
+            let edge1 = triangles.values[triangle * 9u + 1u].xyz - p0;
+            let edge2 = triangles.values[triangle * 9u + 2u].xyz - p0;
+            let p = cross(direction, edge2);
+            let determinant = dot(edge1, p);
+            if (abs(determinant) < 1.0e-10) {
+                continue;
+            }
+            let inverse_determinant = 1.0 / determinant;
+            let s = origin - p0;
+            let u = dot(s, p) * inverse_determinant;
+            if (u < 0.0 || u > 1.0) {
+                continue;
+            }
+            let q = cross(s, edge1);
+            let v = dot(direction, q) * inverse_determinant;
+            if (v < 0.0 || u + v > 1.0) {
+                continue;
+            }
+            let distance = dot(edge2, q) * inverse_determinant;
+            if (distance > EPSILON && distance < hit.distance) {
+                hit.distance = distance;
+                hit.triangle = triangle;
+                hit.barycentric = vec2<f32>(u, v);
+            }
+        }
+    }
+    return hit;
+}
+
+fn interpolate(hit: Hit, offset: u32) -> vec4<f32> {
+    let first = hit.triangle * 9u + offset;
+    let weights = vec3<f32>(1.0 - hit.barycentric.x - hit.barycentric.y, hit.barycentric);
+    return triangles.values[first] * weights.x + triangles.values[first + 1u] * weights.y + triangles.values[first + 2u] * weights.z;
+}
+
+fn max_component(value: vec3<f32>) -> f32 {
+    return max(max(value.x, value.y), value.z);
+}
+
+// One path per pixel each frame. The sun is sampled directly at every bounce and the clear
+// color lights the paths that escape
+[[stage(compute), workgroup_size(8, 8)]]
+fn main([[builtin(global_invocation_id)]] id: vec3<u32>) {
+    let size = tracer.parameters.xy;
+    if (id.x >= size.x || id.y >= size.y) {
+        return;
+    }
+    let pixel = id.y * size.x + id.x;
+    seed = pixel * 9781u + tracer.parameters.z * 6271u + 1u;
+
+    let uv = (vec2<f32>(id.xy) + vec2<f32>(random(), random())) / vec2<f32>(size);
+    let far = tracer.inverse_view_projection * vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 1.0, 1.0);
+    var origin = tracer.camera_position.xyz;
+    var direction = normalize(far.xyz / far.w - origin);
+
+    var throughput = vec3<f32>(1.0);
+    var radiance = vec3<f32>(0.0);
+    for (var bounce = 0u; bounce < tracer.parameters.w; bounce = bounce + 1u) {
+        let hit = trace(origin, direction, FAR);
+        if (hit.triangle == NO_HIT) {
+            radiance = radiance + throughput * tracer.sky_color.rgb;
+            break;
+        }
+
+        let first = hit.triangle * 9u;
+        let p0 = triangles.values[first].xyz;
+        var geometric = normalize(cross(triangles.values[first + 1u].xyz - p0, triangles.values[first + 2u].xyz - p0));
+        if (dot(geometric, direction) > 0.0) {
+            geometric = -geometric;
+        }
+        var normal = normalize(interpolate(hit, 3u).xyz);
+        if (dot(normal, geometric) < 0.0) {
+            normal = -normal;
+        }
+
+        let material = bitcast<u32>(triangles.values[first].w) * 3u;
+        let base_color = materials.values[material].rgb * interpolate(hit, 6u).rgb;
+        let emissive = materials.values[material + 1u].rgb;
+        let metallic = materials.values[material + 2u].x;
+        let roughness = materials.values[material + 2u].y;
+
+        radiance = radiance + throughput * emissive;
+        let position = origin + direction * hit.distance + geometric * EPSILON;
+
+        // A white diffuse surface facing the sun reflects its color
+        let sun = tracer.sun_direction.xyz;
+        let n_dot_l = dot(normal, sun);
+        if (n_dot_l > 0.0 && trace(position, sun, FAR).triangle == NO_HIT) {
+            radiance = radiance + throughput * base_color * (1.0 - metallic) * tracer.sun_color.rgb * n_dot_l;
+        }
+
+        // Metals reflect around the mirror direction, everything else scatters diffusely
+        if (random() < metallic) {
+            direction = normalize(reflect(direction, normal) + random_unit_vector() * roughness * roughness);
+            if (dot(direction, geometric) <= 0.0) {
+                break;
+            }
+        } else {
+            let scattered = normal + random_unit_vector();
+            direction = select(normal, normalize(scattered), dot(scattered, scattered) > EPSILON);
+        }
+        throughput = throughput * base_color;
+        origin = position;
+
+        if (bounce >= 2u) {
+            let survival = clamp(max_component(throughput), 0.05, 1.0);
+            if (random() > survival) {
+                break;
+            }
+            throughput = throughput / survival;
+        }
+    }
+
+    if (tracer.parameters.z == 0u) {
+        accumulation.values[pixel] = vec4<f32>(radiance, 1.0);
+    } else {
+        accumulation.values[pixel] = accumulation.values[pixel] + vec4<f32>(radiance, 1.0);
+    }
+}
@@ -0,0 +1,28 @@
+[[block]]
+struct PathTracer {
+    inverse_view_projection: mat4x4<f32>;
+    camera_position: vec4<f32>;
+    sun_direction: vec4<f32>;
+    sun_color: vec4<f32>;
+    sky_color: vec4<f32>;
+    // x, y: dimensions, z: the sample being accumulated, w: bounces
+    parameters: vec4<u32>;
+};
+[[block]]
+struct Vectors {
+    values: array<vec4<f32>>;
+};
+[[group(0), binding(0)]]
+var<uniform> tracer: PathTracer;
+[[group(0), binding(1)]]
+var<storage, read> accumulation: Vectors;
+
+// Spliced: fullscreen_triangle
+
+// Averages the samples accumulated so far
+[[stage(fragment)]]
+fn fs_main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let pixel = vec2<u32>(in.clip_position.xy);
+    let sum = accumulation.values[pixel.y * tracer.parameters.x + pixel.x];
+    return vec4<f32>(sum.rgb / f32(tracer.parameters.z + 1u), 1.0);
+}
@@ -0,0 +1,85 @@
+[[block]]
+struct Camera {
+    view: mat4x4<f32>;
+    projection: mat4x4<f32>;
+    inverse_view_projection: mat4x4<f32>;
+    position: vec4<f32>;
+    parameters: vec4<f32>;
+};
+[[group(0), binding(0)]]
+var<uniform> camera: Camera;
+
+[[block]]
+struct PointCloud {
+    model: mat4x4<f32>;
+    // x: diameter, y: world space size when one, z: round when one
+    parameters: vec4<f32>;
+};
+[[group(1), binding(0)]]
+var<uniform> cloud: PointCloud;
+
+struct PointInput {
+    [[location(0)]] position: vec3<f32>;
+    [[location(1)]] color: vec4<f32>;
+};
+
+struct VertexOutput {
+    [[builtin(position)]] clip_position: vec4<f32>;
+    // From -1 to 1 across the point
+    [[location(0)]] corner: vec2<f32>;
+    [[location(1)]] color: vec4<f32>;
+};
+
+fn srgb_to_linear(color: vec3<f32>) -> vec3<f32> {
+    let low = color / 12.92;
+    let high = pow((color + vec3<f32>(0.055)) / 1.055, vec3<f32>(2.4));
+    return select(high, low, color <= vec3<f32>(0.04045));
+}
+
+// Each point is a quad facing the camera
+[[stage(vertex)]]
+fn vs_main([[builtin(vertex_index)]] index: u32, point: PointInput) -> VertexOutput {
+    var corners = array<vec2<f32>, 6>(
+        vec2<f32>(-1.0, -1.0),
+        vec2<f32>(1.0, -1.0),
+        vec2<f32>(1.0, 1.0),
+        vec2<f32>(-1.0, -1.0),
+        vec2<f32>(1.0, 1.0),
+        vec2<f32>(-1.0, 1.0),
+    );
+    let corner = corners[index];
+    let radius = cloud.parameters.x * 0.5;
+
+    var view_position = camera.view * cloud.model * vec4<f32>(point.position, 1.0);
+    if (cloud.parameters.y > 0.5) {
+        view_position = view_position + vec4<f32>(corner * radius, 0.0, 0.0);
+    }
+    var clip_position = camera.projection * view_position;
+    if (cloud.parameters.y <= 0.5) {
+        let pixel = vec2<f32>(2.0) / camera.parameters.zw;
+        clip_position = clip_position + vec4<f32>(corner * radius * pixel * clip_position.w, 0.0, 0.0);
+    }
+
+    var out: VertexOutput;
+    out.clip_position = clip_position;
+    out.corner = corner;
+    out.color = vec4<f32>(srgb_to_linear(point.color.rgb), point.color.a);
+    return out;
+}
+
+fn clip_round(corner: vec2<f32>) {
+    if (cloud.parameters.z > 0.5 && dot(corner, corner) > 1.0) {
+        discard;
+    }
+}
+
+[[stage(fragment)]]
+fn fs_main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    clip_round(in.corner);
+    return in.color;
+}
+
+[[stage(fragment)]]
+fn fs_depth(in: VertexOutput) {
+    clip_round(in.corner);
+}
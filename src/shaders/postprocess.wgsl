@@ -0,0 +1,276 @@
+[[block]]
+struct Camera {
+    view: mat4x4<f32>;
+    projection: mat4x4<f32>;
+    inverse_view_projection: mat4x4<f32>;
+    position: vec4<f32>;
+    parameters: vec4<f32>;
+    previous_view_projection: mat4x4<f32>;
+    jitter: vec4<f32>;
+    depth: vec4<f32>;
+};
+[[group(0), binding(0)]]
+var<uniform> camera: Camera;
+
+// Spliced: standard_depth
+
+[[block]]
+struct PostProcess {
+    fog_color: vec4<f32>;
+    environment_color: vec4<f32>;
+    fog_distance: vec4<f32>;
+    fog_height: vec4<f32>;
+    volumetric: vec4<f32>;
+    imperfections: vec4<f32>;
+    fog_mode: u32;
+    volumetric_samples: u32;
+    bloom_intensity: f32;
+    exposure: f32;
+    tone_mapping: u32;
+    auto_exposure: u32;
+    dithering: u32;
+};
+[[group(1), binding(0)]]
+var scene_texture: texture_2d<f32>;
+[[group(1), binding(1)]]
+var scene_sampler: sampler;
+[[group(1), binding(2)]]
+var depth_texture: texture_depth_2d;
+[[group(1), binding(3)]]
+var<uniform> post_process: PostProcess;
+[[group(1), binding(4)]]
+var bloom_texture: texture_2d<f32>;
+
+[[block]]
+struct Adapted {
+    luminance: f32;
+    exposure: f32;
+};
+[[group(1), binding(5)]]
+var<storage, read> adapted: Adapted;
+
+[[block]]
+struct Light {
+    view_projection: mat4x4<f32>;
+    direction: vec4<f32>;
+    color: vec4<f32>;
+};
+[[group(2), binding(0)]]
+var<uniform> light: Light;
+[[group(2), binding(1)]]
+var shadow_map: texture_depth_2d;
+[[group(2), binding(2)]]
+var shadow_sampler: sampler_comparison;
+// A 64x64 tile of blue noise packed four texels to a component
+[[block]]
+struct BlueNoise {
+    offset: vec4<u32>;
+    texels: array<vec4<u32>, 256>;
+};
+[[group(2), binding(11)]]
+var<uniform> blue_noise: BlueNoise;
+
+let PI: f32 = 3.14159265;
+let SHADOW_MAP_SIZE: f32 = 2048.0;
+
+let FOG_MODE_LINEAR: u32 = 1u;
+let FOG_MODE_EXPONENTIAL: u32 = 2u;
+let FOG_MODE_EXPONENTIAL_SQUARED: u32 = 3u;
+
+let TONE_MAPPING_REINHARD: u32 = 1u;
+let TONE_MAPPING_ACES: u32 = 2u;
+
+let DITHERING_SRGB: u32 = 1u;
+let DITHERING_LINEAR: u32 = 2u;
+
+// Spliced: fullscreen_triangle
+
+fn distance_fog(distance: f32) -> f32 {
+    let density = post_process.fog_distance.x;
+    let start = post_process.fog_distance.y;
+    let end = post_process.fog_distance.z;
+    if (post_process.fog_mode == FOG_MODE_LINEAR) {
+        return clamp((distance - start) / max(end - start, 0.0001), 0.0, 1.0);
+    }
+    if (post_process.fog_mode == FOG_MODE_EXPONENTIAL) {
+        return 1.0 - exp(-density * distance);
+    }
+    if (post_process.fog_mode == FOG_MODE_EXPONENTIAL_SQUARED) {
+        let amount = density * distance;
+        return 1.0 - exp(-amount * amount);
+    }
+    return 0.0;
+}
+
+// Integrates fog density that falls off exponentially with height along the view ray
+fn height_fog(distance: f32, direction: vec3<f32>) -> f32 {
+    let density = post_process.fog_height.x;
+    let falloff = max(post_process.fog_height.y, 0.0001);
+    let base_height = post_process.fog_height.z;
+    if (density <= 0.0) {
+        return 0.0;
+    }
+
+    let camera_density = density * exp(-falloff * (camera.position.y - base_height));
+    let vertical = direction.y * distance * falloff;
+    var amount = camera_density * distance;
+    if (abs(vertical) > 0.0001) {
+        amount = amount * (1.0 - exp(-vertical)) / vertical;
+    }
+    return 1.0 - exp(-amount);
+}
+
+fn shadow_visibility(position: vec3<f32>) -> f32 {
+    let clip = light.view_projection * vec4<f32>(position, 1.0);
+    let ndc = clip.xyz / clip.w;
+    let uv = vec2<f32>(ndc.x * 0.5 + 0.5, 0.5 - ndc.y * 0.5);
+    if (any(uv < vec2<f32>(0.0)) || any(uv > vec2<f32>(1.0)) || ndc.z < 0.0 || ndc.z > 1.0) {
+        return 1.0;
+    }
+    // The sun's tile is in the corner of the atlas it shares with local lights
+    let atlas_scale = SHADOW_MAP_SIZE / f32(textureDimensions(shadow_map).x);
+    return textureSampleCompareLevel(shadow_map, shadow_sampler, uv * atlas_scale, ndc.z);
+}
+
+// Henyey-Greenstein phase function
+fn phase(cos_theta: f32, anisotropy: f32) -> f32 {
+    let g2 = anisotropy * anisotropy;
+    let denominator = max(1.0 + g2 - 2.0 * anisotropy * cos_theta, 0.0001);
+    return (1.0 - g2) / (4.0 * PI * pow(denominator, 1.5));
+}
+
+// Marches the view ray through the shadow map, gathering light scattered towards the camera
+fn volumetric_light(direction: vec3<f32>, distance: f32, pixel: vec2<f32>) -> vec3<f32> {
+    let density = post_process.volumetric.x;
+    let anisotropy = post_process.volumetric.y;
+    let ray_length = min(distance, post_process.volumetric.z);
+    let samples = post_process.volumetric_samples;
+    let step_length = ray_length / f32(samples);
+
+    // Offsetting each pixel's samples trades banding for noise
+    let jitter = fract(52.9829189 * fract(dot(pixel, vec2<f32>(0.06711056, 0.00583715))));
+
+    var scattered = 0.0;
+    for (var index = 0u; index < samples; index = index + 1u) {
+        let travelled = (f32(index) + jitter) * step_length;
+        let position = camera.position.xyz + direction * travelled;
+        let transmittance = exp(-density * travelled);
+        scattered = scattered + shadow_visibility(position) * transmittance * density * step_length;
+    }
+
+    return light.color.rgb * scattered * phase(dot(direction, light.direction.xyz), anisotropy);
+}
+
+// Narkowicz's fit of the ACES filmic curve
+fn aces(color: vec3<f32>) -> vec3<f32> {
+    return (color * (2.51 * color + 0.03)) / (color * (2.43 * color + 0.59) + 0.14);
+}
+
+fn tone_map(color: vec3<f32>) -> vec3<f32> {
+    if (post_process.tone_mapping == TONE_MAPPING_REINHARD) {
+        return color / (1.0 + color);
+    }
+    if (post_process.tone_mapping == TONE_MAPPING_ACES) {
+        return aces(color);
+    }
+    return color;
+}
+
+// Lenses focus each wavelength slightly differently, pulling red and blue apart towards the edges
+fn chromatic_aberration(uv: vec2<f32>) -> vec3<f32> {
+    let offset = (uv - vec2<f32>(0.5)) * post_process.imperfections.y * 0.02;
+    return vec3<f32>(
+        textureSample(scene_texture, scene_sampler, uv - offset).r,
+        textureSample(scene_texture, scene_sampler, uv).g,
+        textureSample(scene_texture, scene_sampler, uv + offset).b,
+    );
+}
+
+fn vignette(uv: vec2<f32>) -> f32 {
+    let offset = (uv - vec2<f32>(0.5)) * 2.0;
+    let falloff = clamp(1.0 - dot(offset, offset) * 0.5, 0.0, 1.0);
+    return mix(1.0, falloff * falloff, post_process.imperfections.x);
+}
+
+// Zero centered noise, reseeded every frame
+fn film_grain(pixel: vec2<f32>) -> f32 {
+    let seed = pixel + vec2<f32>(post_process.imperfections.w * 17.0, post_process.imperfections.w * 31.0);
+    let noise = fract(sin(dot(seed, vec2<f32>(12.9898, 78.233))) * 43758.5453);
+    return (noise - 0.5) * post_process.imperfections.z * 0.2;
+}
+
+fn blue_noise_at(pixel: vec2<f32>) -> f32 {
+    let coordinate = (vec2<u32>(pixel) + blue_noise.offset.xy) % vec2<u32>(64u);
+    let index = coordinate.y * 64u + coordinate.x;
+    let packed = blue_noise.texels[index / 16u][(index / 4u) % 4u];
+    let value = (packed >> ((index % 4u) * 8u)) & 255u;
+    return (f32(value) + 0.5) / 256.0;
+}
+
+fn linear_to_srgb(color: vec3<f32>) -> vec3<f32> {
+    let low = color * 12.92;
+    let high = 1.055 * pow(color, vec3<f32>(1.0 / 2.4)) - 0.055;
+    return select(high, low, color <= vec3<f32>(0.0031308));
+}
+
+fn srgb_to_linear(color: vec3<f32>) -> vec3<f32> {
+    let low = color / 12.92;
+    let high = pow((color + 0.055) / 1.055, vec3<f32>(2.4));
+    return select(high, low, color <= vec3<f32>(0.04045));
+}
+
+// Offsets the color by up to half a step of the 8 bit output, so gradients that would band
+// round up and down in a fine pattern instead
+fn dither(color: vec3<f32>, pixel: vec2<f32>) -> vec3<f32> {
+    let offset = (blue_noise_at(pixel) - 0.5) / 255.0;
+    if (post_process.dithering == DITHERING_SRGB) {
+        let encoded = clamp(linear_to_srgb(color) + vec3<f32>(offset), vec3<f32>(0.0), vec3<f32>(1.0));
+        return srgb_to_linear(encoded);
+    }
+    if (post_process.dithering == DITHERING_LINEAR) {
+        return color + vec3<f32>(offset);
+    }
+    return color;
+}
+
+[[stage(fragment)]]
+fn fs_main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    var color = chromatic_aberration(in.uv);
+    let bloom = textureSample(bloom_texture, scene_sampler, in.uv).rgb;
+
+    // The scene may be rendered at a different resolution than the output
+    let depth_pixel = vec2<i32>(in.uv * vec2<f32>(textureDimensions(depth_texture)));
+    let depth = textureLoad(depth_texture, depth_pixel, 0);
+    let ndc = vec4<f32>(in.uv.x * 2.0 - 1.0, 1.0 - in.uv.y * 2.0, depth, 1.0);
+    let world = camera.inverse_view_projection * ndc;
+    let offset = world.xyz / world.w - camera.position.xyz;
+    let distance = length(offset);
+    let direction = offset / max(distance, 0.0001);
+
+    // The background is left unfogged
+    if (standard_depth(depth) < 1.0) {
+        let distance_amount = distance_fog(distance);
+        let height_amount = height_fog(distance, direction);
+        let fog = 1.0 - (1.0 - distance_amount) * (1.0 - height_amount);
+
+        // Fog near the horizon takes on the color of the environment behind it
+        let horizon = 1.0 - abs(direction.y);
+        let environment_blend = post_process.fog_color.w * horizon * horizon;
+        let fog_color = mix(post_process.fog_color.rgb, post_process.environment_color.rgb, environment_blend);
+
+        color = mix(color, fog_color, fog);
+    }
+
+    if (post_process.volumetric_samples > 0u) {
+        // Rays into the background travel as far as the volume reaches
+        let ray_distance = select(distance, post_process.volumetric.z, standard_depth(depth) >= 1.0);
+        color = color + volumetric_light(direction, ray_distance, in.clip_position.xy);
+    }
+
+    color = color + bloom * post_process.bloom_intensity;
+
+    let exposure = select(post_process.exposure, adapted.exposure, post_process.auto_exposure != 0u);
+    let mapped = tone_map(color * exposure * vignette(in.uv)) + vec3<f32>(film_grain(in.clip_position.xy));
+    let clamped = clamp(mapped, vec3<f32>(0.0), vec3<f32>(1.0));
+    return vec4<f32>(clamp(dither(clamped, in.clip_position.xy), vec3<f32>(0.0), vec3<f32>(1.0)), 1.0);
+}
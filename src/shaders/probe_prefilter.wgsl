@@ -0,0 +1,121 @@
+[[block]]
+struct Prefilter {
+    // x: roughness the mip is filtered for
+    parameters: vec4<f32>;
+};
+[[group(0), binding(0)]]
+var capture_texture: texture_2d_array<f32>;
+[[group(0), binding(1)]]
+var capture_sampler: sampler;
+[[group(0), binding(2)]]
+var<uniform> prefilter: Prefilter;
+
+let PI: f32 = 3.14159265;
+let SAMPLES: u32 = 64u;
+
+struct VertexOutput {
+    [[builtin(position)]] clip_position: vec4<f32>;
+    [[location(0)]] uv: vec2<f32>;
+    [[location(1), interpolate(flat)]] face: u32;
+};
+
+// A single triangle covering the whole face, the instance is the face being filtered
+[[stage(vertex)]]
+fn vs_main([[builtin(vertex_index)]] vertex_index: u32, [[builtin(instance_index)]] instance_index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+    out.uv = uv;
+    out.face = instance_index;
+    return out;
+}
+
+// Matches the face directions the probes are captured with
+fn face_forward(face: u32) -> vec3<f32> {
+    switch (i32(face)) {
+        case 0: { return vec3<f32>(1.0, 0.0, 0.0); }
+        case 1: { return vec3<f32>(-1.0, 0.0, 0.0); }
+        case 2: { return vec3<f32>(0.0, 1.0, 0.0); }
+        case 3: { return vec3<f32>(0.0, -1.0, 0.0); }
+        case 4: { return vec3<f32>(0.0, 0.0, 1.0); }
+        default: { return vec3<f32>(0.0, 0.0, -1.0); }
+    }
+}
+
+fn face_up(face: u32) -> vec3<f32> {
+    switch (i32(face)) {
+        case 2: { return vec3<f32>(0.0, 0.0, -1.0); }
+        case 3: { return vec3<f32>(0.0, 0.0, 1.0); }
+        default: { return vec3<f32>(0.0, 1.0, 0.0); }
+    }
+}
+
+fn face_direction(face: u32, uv: vec2<f32>) -> vec3<f32> {
+    let forward = face_forward(face);
+    let up = face_up(face);
+    let right = cross(forward, up);
+    return normalize(forward + right * (uv.x * 2.0 - 1.0) + up * (1.0 - uv.y * 2.0));
+}
+
+fn sample_direction(direction: vec3<f32>) -> vec3<f32> {
+    let magnitude = abs(direction);
+    var face = 0u;
+    if (magnitude.x >= magnitude.y && magnitude.x >= magnitude.z) {
+        face = select(1u, 0u, direction.x > 0.0);
+    } else {
+        if (magnitude.y >= magnitude.z) {
+            face = select(3u, 2u, direction.y > 0.0);
+        } else {
+            face = select(5u, 4u, direction.z > 0.0);
+        }
+    }
+    let forward = face_forward(face);
+    let up = face_up(face);
+    let right = cross(forward, up);
+    let depth = dot(direction, forward);
+    let uv = vec2<f32>(dot(direction, right) / depth * 0.5 + 0.5, 0.5 - dot(direction, up) / depth * 0.5);
+    return textureSampleLevel(capture_texture, capture_sampler, uv, i32(face), 0.0).rgb;
+}
+
+fn hammersley(index: u32) -> vec2<f32> {
+    var bits = index;
+    bits = (bits << 16u) | (bits >> 16u);
+    bits = ((bits & 0x55555555u) << 1u) | ((bits & 0xAAAAAAAAu) >> 1u);
+    bits = ((bits & 0x33333333u) << 2u) | ((bits & 0xCCCCCCCCu) >> 2u);
+    bits = ((bits & 0x0F0F0F0Fu) << 4u) | ((bits & 0xF0F0F0F0u) >> 4u);
+    bits = ((bits & 0x00FF00FFu) << 8u) | ((bits & 0xFF00FF00u) >> 8u);
+    return vec2<f32>(f32(index) / f32(SAMPLES), f32(bits) * 2.3283064365386963e-10);
+}
+
+// A half vector distributed like the GGX lobe around the normal
+fn importance_sample(xi: vec2<f32>, normal: vec3<f32>, roughness: f32) -> vec3<f32> {
+    let alpha = roughness * roughness;
+    let phi = 2.0 * PI * xi.x;
+    let cos_theta = sqrt((1.0 - xi.y) / (1.0 + (alpha * alpha - 1.0) * xi.y));
+    let sin_theta = sqrt(1.0 - cos_theta * cos_theta);
+    let up = select(vec3<f32>(1.0, 0.0, 0.0), vec3<f32>(0.0, 0.0, 1.0), abs(normal.z) < 0.999);
+    let tangent = normalize(cross(up, normal));
+    let bitangent = cross(normal, tangent);
+    return normalize(tangent * cos(phi) * sin_theta + bitangent * sin(phi) * sin_theta + normal * cos_theta);
+}
+
+// Convolves the captured faces with the specular lobe of the mip's roughness, assuming the
+// view and reflection directions match the normal
+[[stage(fragment)]]
+fn fs_main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let normal = face_direction(in.face, in.uv);
+    let roughness = prefilter.parameters.x;
+
+    var color = vec3<f32>(0.0);
+    var weight = 0.0;
+    for (var index = 0u; index < SAMPLES; index = index + 1u) {
+        let half_vector = importance_sample(hammersley(index), normal, roughness);
+        let l = normalize(2.0 * dot(normal, half_vector) * half_vector - normal);
+        let n_dot_l = dot(normal, l);
+        if (n_dot_l > 0.0) {
+            color = color + sample_direction(l) * n_dot_l;
+            weight = weight + n_dot_l;
+        }
+    }
+    return vec4<f32>(color / max(weight, 0.0001), 1.0);
+}
@@ -0,0 +1,82 @@
+[[block]]
+struct Camera {
+    view: mat4x4<f32>;
+    projection: mat4x4<f32>;
+    inverse_view_projection: mat4x4<f32>;
+    position: vec4<f32>;
+    parameters: vec4<f32>;
+    previous_view_projection: mat4x4<f32>;
+    jitter: vec4<f32>;
+    depth: vec4<f32>;
+};
+[[group(0), binding(0)]]
+var<uniform> camera: Camera;
+
+// Spliced: standard_depth
+
+[[block]]
+struct RayTracedShadows {
+    // xyz: towards the sun, w: tangent of its angular radius
+    sun: vec4<f32>;
+    // x: pixels per traced pixel on each axis, y: filter radius, z: rays per pixel, w: frame
+    parameters: vec4<u32>;
+};
+[[group(1), binding(0)]]
+var<uniform> shadows: RayTracedShadows;
+[[group(1), binding(1)]]
+var depth_texture: texture_depth_2d;
+[[group(1), binding(2)]]
+var visibility: texture_2d<f32>;
+[[group(1), binding(3)]]
+var mask: texture_storage_2d<r32float, write>;
+
+// Distance from the camera, which neighbors on the same surface share
+fn view_distance(pixel: vec2<i32>, dimensions: vec2<i32>) -> f32 {
+    let depth = textureLoad(depth_texture, pixel, 0);
+    let uv = (vec2<f32>(pixel) + vec2<f32>(0.5)) / vec2<f32>(dimensions);
+    let world = camera.inverse_view_projection * vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, depth, 1.0);
+    return length(world.xyz / world.w - camera.position.xyz);
+}
+
+// Averages the traced visibility around each pixel, skipping neighbors at a different depth so
+// shadows don't bleed across silhouettes. Half resolution traces are upsampled by the same filter
+[[stage(compute), workgroup_size(8, 8)]]
+fn main([[builtin(global_invocation_id)]] id: vec3<u32>) {
+    let dimensions = textureDimensions(depth_texture);
+    let pixel = vec2<i32>(id.xy);
+    if (pixel.x >= dimensions.x || pixel.y >= dimensions.y) {
+        return;
+    }
+    if (standard_depth(textureLoad(depth_texture, pixel, 0)) >= 1.0) {
+        textureStore(mask, pixel, vec4<f32>(1.0));
+        return;
+    }
+
+    let step = i32(shadows.parameters.x);
+    let radius = i32(shadows.parameters.y);
+    // Only the corner covered at this step is traced
+    let traced_dimensions = (dimensions + vec2<i32>(step - 1)) / step;
+    let center = pixel / step;
+    let distance = view_distance(pixel, dimensions);
+
+    var total = 0.0;
+    var total_weight = 0.0;
+    for (var y = -radius; y <= radius; y = y + 1) {
+        for (var x = -radius; x <= radius; x = x + 1) {
+            let traced = clamp(center + vec2<i32>(x, y), vec2<i32>(0), traced_dimensions - vec2<i32>(1));
+            let source = min(traced * step, dimensions - vec2<i32>(1));
+            let difference = abs(view_distance(source, dimensions) - distance);
+            let depth_weight = clamp(1.0 - difference / (distance * 0.05), 0.0, 1.0);
+            let spatial_weight = 1.0 / (1.0 + f32(x * x + y * y));
+            let weight = depth_weight * spatial_weight;
+            total = total + textureLoad(visibility, traced, 0).r * weight;
+            total_weight = total_weight + weight;
+        }
+    }
+
+    var result = textureLoad(visibility, min(center, traced_dimensions - vec2<i32>(1)), 0).r;
+    if (total_weight > 0.0) {
+        result = total / total_weight;
+    }
+    textureStore(mask, pixel, vec4<f32>(result));
+}
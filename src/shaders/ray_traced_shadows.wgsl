@@ -0,0 +1,167 @@
+[[block]]
+struct Camera {
+    view: mat4x4<f32>;
+    projection: mat4x4<f32>;
+    inverse_view_projection: mat4x4<f32>;
+    position: vec4<f32>;
+    parameters: vec4<f32>;
+    previous_view_projection: mat4x4<f32>;
+    jitter: vec4<f32>;
+    depth: vec4<f32>;
+};
+[[group(0), binding(0)]]
+var<uniform> camera: Camera;
+
+// Spliced: standard_depth
+
+[[block]]
+struct RayTracedShadows {
+    // xyz: towards the sun, w: tangent of its angular radius
+    sun: vec4<f32>;
+    // x: pixels per traced pixel on each axis, y: filter radius, z: rays per pixel, w: frame
+    parameters: vec4<u32>;
+};
+[[block]]
+struct Vectors {
+    values: array<vec4<f32>>;
+};
+[[group(1), binding(0)]]
+var<uniform> shadows: RayTracedShadows;
+[[group(1), binding(1)]]
+var depth_texture: texture_depth_2d;
+[[group(1), binding(2)]]
+var visibility: texture_storage_2d<r32float, write>;
+// Three positions per triangle
+[[group(2), binding(0)]]
+var<storage, read> triangles: Vectors;
+// Two per node, leaves hold their first triangle in min's w and their count in max's w,
+// interior nodes hold their second child with a count of zero
+[[group(2), binding(1)]]
+var<storage, read> nodes: Vectors;
+
+let PI: f32 = 3.14159265;
+let EPSILON: f32 = 0.0001;
+let STACK_SIZE: u32 = 64u;
+
+var<private> seed: u32;
+
+fn random() -> f32 {
+    seed = seed * 747796405u + 2891336453u;
+    var word = ((seed >> ((seed >> 28u) + 4u)) ^ seed) * 277803737u;
+    word = (word >> 22u) ^ word;
+    return f32(word) / 4294967296.0;
+}
+
+fn enters_bounds(origin: vec3<f32>, inverse_direction: vec3<f32>, minimum: vec3<f32>, maximum: vec3<f32>) -> bool {
+    let t0 = (minimum - origin) * inverse_direction;
+    let t1 = (maximum - origin) * inverse_direction;
+    let near = min(t0, t1);
+    let far = max(t0, t1);
+    let enter = max(max(near.x, near.y), near.z);
+    let exit = min(min(far.x, far.y), far.z);
+    return exit >= max(enter, 0.0);
+}
+
+// Whether anything lies along the ray, stopping at the first triangle found
+fn occluded(origin: vec3<f32>, direction: vec3<f32>) -> bool {
+    let inverse_direction = vec3<f32>(1.0) / direction;
+
+    var stack: array<u32, 64>;
+    stack[0] = 0u;
+    var depth = 1u;
+    loop {
+        if (depth == 0u) {
+            break;
+        }
+        depth = depth - 1u;
+        let node = stack[depth];
+        let minimum = nodes.values[node * 2u];
+        let maximum = nodes.values[node * 2u + 1u];
+        if (!enters_bounds(origin, inverse_direction, minimum.xyz, maximum.xyz)) {
+            continue;
+        }
+
+        let count = bitcast<u32>(maximum.w);
+        if (count == 0u) {
+            // The hierarchy is built less than half as deep as the stack, so this only guards
+            // against writing past its end
+            if (depth + 2u <= STACK_SIZE) {
+                stack[depth] = bitcast<u32>(minimum.w);
+                stack[depth + 1u] = node + 1u;
+                depth = depth + 2u;
+            }
+            continue;
+        }
+
+        let first = bitcast<u32>(minimum.w);
+        for (var triangle = first; triangle < first + count; triangle = triangle + 1u) {
+            let p0 = triangles.values[triangle * 3u].xyz;
+            let edge1 = triangles.values[triangle * 3u + 1u].xyz - p0;
+            let edge2 = triangles.values[triangle * 3u + 2u].xyz - p0;
+            let p = cross(direction, edge2);
+            let determinant = dot(edge1, p);
+            if (abs(determinant) < 1.0e-10) {
+                continue;
+            }
+            let inverse_determinant = 1.0 / determinant;
+            let s = origin - p0;
+            let u = dot(s, p) * inverse_determinant;
+            if (u < 0.0 || u > 1.0) {
+                continue;
+            }
+            let q = cross(s, edge1);
+            let v = dot(direction, q) * inverse_determinant;
+            if (v < 0.0 || u + v > 1.0) {
+                continue;
+            }
+            if (dot(edge2, q) * inverse_determinant > EPSILON) {
+                return true;
+            }
+        }
+    }
+    return false;
+}
+
+// Rays towards points on the sun's disk, rotated randomly per pixel and frame
+[[stage(compute), workgroup_size(8, 8)]]
+fn main([[builtin(global_invocation_id)]] id: vec3<u32>) {
+    let dimensions = textureDimensions(depth_texture);
+    let step = i32(shadows.parameters.x);
+    let pixel = vec2<i32>(id.xy) * step;
+    if (pixel.x >= dimensions.x || pixel.y >= dimensions.y) {
+        return;
+    }
+
+    let depth = textureLoad(depth_texture, pixel, 0);
+    if (standard_depth(depth) >= 1.0) {
+        textureStore(visibility, vec2<i32>(id.xy), vec4<f32>(1.0));
+        return;
+    }
+    let uv = (vec2<f32>(pixel) + vec2<f32>(0.5)) / vec2<f32>(dimensions);
+    let world = camera.inverse_view_projection * vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, depth, 1.0);
+    let position = world.xyz / world.w;
+
+    // Pushed off the surface by an amount that grows with distance as depth loses precision
+    let to_camera = camera.position.xyz - position;
+    let bias = max(length(to_camera) * 0.002, EPSILON);
+    let sun = normalize(shadows.sun.xyz);
+    let origin = position + (sun + normalize(to_camera)) * bias;
+
+    let tangent = normalize(cross(sun, select(vec3<f32>(0.0, 1.0, 0.0), vec3<f32>(1.0, 0.0, 0.0), abs(sun.y) > 0.99)));
+    let bitangent = cross(sun, tangent);
+    seed = (id.y * 4096u + id.x) * 9781u + shadows.parameters.w * 6271u + 1u;
+    let rotation = random() * 2.0 * PI;
+
+    let rays = max(shadows.parameters.z, 1u);
+    var lit = 0.0;
+    for (var ray = 0u; ray < rays; ray = ray + 1u) {
+        // Stratified over the disk, a single ray with no radius points straight at the sun
+        let radius = sqrt((f32(ray) + random()) / f32(rays)) * shadows.sun.w;
+        let angle = rotation + f32(ray) * 2.39996323;
+        let direction = normalize(sun + (tangent * cos(angle) + bitangent * sin(angle)) * radius);
+        if (!occluded(origin, direction)) {
+            lit = lit + 1.0;
+        }
+    }
+    textureStore(visibility, vec2<i32>(id.xy), vec4<f32>(lit / f32(rays)));
+}
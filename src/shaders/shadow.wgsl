@@ -0,0 +1,17 @@
+[[block]]
+struct Light {
+    view_projection: mat4x4<f32>;
+    direction: vec4<f32>;
+    color: vec4<f32>;
+};
+[[group(0), binding(0)]]
+var<uniform> light: Light;
+
+// Depth only pass rendering shadow casters from the light, offset from where they were built
+[[stage(vertex)]]
+fn vs_main(
+    [[location(0)]] position: vec3<f32>,
+    [[location(4)]] offset: vec3<f32>,
+) -> [[builtin(position)]] vec4<f32> {
+    return light.view_projection * vec4<f32>(position + offset, 1.0);
+}
@@ -0,0 +1,51 @@
+[[block]]
+struct Sharpen {
+    mode: u32;
+    strength: f32;
+};
+[[group(0), binding(0)]]
+var color_texture: texture_2d<f32>;
+[[group(0), binding(1)]]
+var<uniform> sharpen: Sharpen;
+
+let SHARPEN_UNSHARP: u32 = 1u;
+let SHARPEN_CONTRAST_ADAPTIVE: u32 = 2u;
+
+// Spliced: fullscreen_triangle
+
+fn load(pixel: vec2<i32>) -> vec3<f32> {
+    let clamped = clamp(pixel, vec2<i32>(0), textureDimensions(color_texture) - vec2<i32>(1));
+    return textureLoad(color_texture, clamped, 0).rgb;
+}
+
+[[stage(fragment)]]
+fn fs_main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let pixel = vec2<i32>(in.clip_position.xy);
+    let center = load(pixel);
+    let up = load(pixel + vec2<i32>(0, -1));
+    let down = load(pixel + vec2<i32>(0, 1));
+    let left = load(pixel + vec2<i32>(-1, 0));
+    let right = load(pixel + vec2<i32>(1, 0));
+    let neighbors = up + down + left + right;
+
+    if (sharpen.mode == SHARPEN_UNSHARP) {
+        // Pushes the pixel away from the blurred average of its neighbors
+        let sharpened = center + (center - neighbors * 0.25) * sharpen.strength;
+        return vec4<f32>(clamp(sharpened, vec3<f32>(0.0), vec3<f32>(1.0)), 1.0);
+    }
+
+    if (sharpen.mode == SHARPEN_CONTRAST_ADAPTIVE) {
+        // Sharpens less where the neighborhood already has high contrast, so edges don't ring
+        let minimum = min(center, min(min(up, down), min(left, right)));
+        let maximum = max(center, max(max(up, down), max(left, right)));
+        let headroom = min(minimum, vec3<f32>(1.0) - maximum);
+        let amplitude = sqrt(clamp(headroom / max(maximum, vec3<f32>(0.0001)), vec3<f32>(0.0), vec3<f32>(1.0)));
+        // The negative lobe ranges from -1/8 to -1/5 as strength goes from zero to one
+        let peak = -1.0 / mix(8.0, 5.0, clamp(sharpen.strength, 0.0, 1.0));
+        let weight = amplitude * peak;
+        let sharpened = (center + neighbors * weight) / (vec3<f32>(1.0) + weight * 4.0);
+        return vec4<f32>(clamp(sharpened, vec3<f32>(0.0), vec3<f32>(1.0)), 1.0);
+    }
+
+    return vec4<f32>(center, 1.0);
+}
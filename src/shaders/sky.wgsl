@@ -0,0 +1,125 @@
+[[block]]
+struct Camera {
+    view: mat4x4<f32>;
+    projection: mat4x4<f32>;
+    inverse_view_projection: mat4x4<f32>;
+    position: vec4<f32>;
+    parameters: vec4<f32>;
+    previous_view_projection: mat4x4<f32>;
+    jitter: vec4<f32>;
+    depth: vec4<f32>;
+};
+[[group(0), binding(0)]]
+var<uniform> camera: Camera;
+
+[[block]]
+struct Sky {
+    // xyz: towards the sun, w: cosine of the disc's angular radius
+    sun_direction: vec4<f32>;
+    sun_color: vec4<f32>;
+    // The Perez coefficients A to E, xyz: for luminance and the x and y chromaticities
+    perez: array<vec4<f32>, 5>;
+    // xyz: luminance and chromaticities straight up divided by the Perez function there, w: scale
+    // into scene color
+    zenith: vec4<f32>;
+    // Light from the sun reaching the clouds
+    sun_light: vec4<f32>;
+    // x: coverage, yz: how far the clouds have drifted
+    clouds: vec4<f32>;
+};
+[[group(1), binding(0)]]
+var<uniform> sky: Sky;
+
+// Spliced: far_plane_triangle
+
+fn perez(theta: f32, gamma: f32) -> vec3<f32> {
+    let a = sky.perez[0].xyz;
+    let b = sky.perez[1].xyz;
+    let c = sky.perez[2].xyz;
+    let d = sky.perez[3].xyz;
+    let e = sky.perez[4].xyz;
+    let cos_gamma = cos(gamma);
+    return (vec3<f32>(1.0) + a * exp(b / max(cos(theta), 0.01)))
+        * (vec3<f32>(1.0) + c * exp(d * gamma) + e * cos_gamma * cos_gamma);
+}
+
+fn hash(p: vec2<f32>) -> f32 {
+    return fract(sin(dot(p, vec2<f32>(127.1, 311.7))) * 43758.5453);
+}
+
+fn value_noise(p: vec2<f32>) -> f32 {
+    let cell = floor(p);
+    let f = fract(p);
+    let u = f * f * (vec2<f32>(3.0) - 2.0 * f);
+    let a = hash(cell);
+    let b = hash(cell + vec2<f32>(1.0, 0.0));
+    let c = hash(cell + vec2<f32>(0.0, 1.0));
+    let d = hash(cell + vec2<f32>(1.0, 1.0));
+    return mix(mix(a, b, u.x), mix(c, d, u.x), u.y);
+}
+
+// Layered noise, each octave twice as fine and half as strong as the one before
+fn fbm(p: vec2<f32>) -> f32 {
+    var value = 0.0;
+    var amplitude = 0.5;
+    var position = p;
+    for (var octave = 0; octave < 5; octave = octave + 1) {
+        value = value + value_noise(position) * amplitude;
+        position = position * 2.03 + vec2<f32>(17.0, 9.0);
+        amplitude = amplitude * 0.5;
+    }
+    return value;
+}
+
+// Blends a flat layer of clouds over the sky above the horizon
+fn clouds(color: vec3<f32>, direction: vec3<f32>, ambient: vec3<f32>) -> vec3<f32> {
+    let coverage = sky.clouds.x;
+    if (direction.y <= 0.0 || coverage <= 0.0) {
+        return color;
+    }
+    // Where the ray meets the layer, nearer the horizon the clouds are further away
+    let position = direction.xz / (direction.y + 0.05) * 0.6 + sky.clouds.yz;
+    let noise = fbm(position * 2.0);
+    let density = clamp((noise - (1.0 - coverage)) * 3.0, 0.0, 1.0);
+    if (density <= 0.0) {
+        return color;
+    }
+    // Thicker clouds are darker underneath, and glow around the sun
+    let forward = pow(max(dot(direction, sky.sun_direction.xyz), 0.0), 8.0);
+    let lit = sky.sun_light.rgb * mix(0.9, 0.4, density) * (1.0 + forward * 2.0);
+    let cloud = lit + ambient * 0.6;
+    let horizon = clamp(direction.y * 8.0, 0.0, 1.0);
+    return mix(color, cloud, density * horizon);
+}
+
+[[stage(fragment)]]
+fn fs_main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let far = camera.inverse_view_projection * vec4<f32>(in.ndc, far_depth(), 1.0);
+    let direction = normalize(far.xyz / far.w - camera.position.xyz);
+
+    // Below the horizon the horizon's color darkens, standing in for the ground
+    let up = max(direction.y, 0.0);
+    let theta = acos(up);
+    let cos_gamma = clamp(dot(normalize(vec3<f32>(direction.x, up, direction.z)), sky.sun_direction.xyz), -1.0, 1.0);
+    let luminance_chromaticity = sky.zenith.xyz * perez(theta, acos(cos_gamma));
+
+    // From xyY to XYZ, then to linear sRGB
+    let luminance = max(luminance_chromaticity.x, 0.0);
+    let x = luminance_chromaticity.y;
+    let y = max(luminance_chromaticity.z, 0.0001);
+    let xyz = vec3<f32>(x / y * luminance, luminance, (1.0 - x - y) / y * luminance);
+    var color = vec3<f32>(
+        3.2404542 * xyz.x - 1.5371385 * xyz.y - 0.4985314 * xyz.z,
+        -0.9692660 * xyz.x + 1.8760108 * xyz.y + 0.0415560 * xyz.z,
+        0.0556434 * xyz.x - 0.2040259 * xyz.y + 1.0572252 * xyz.z,
+    );
+    color = max(color, vec3<f32>(0.0)) * sky.zenith.w;
+    let ambient = color;
+
+    if (direction.y < 0.0) {
+        color = color * mix(1.0, 0.3, clamp(-direction.y * 5.0, 0.0, 1.0));
+    } elseif (dot(direction, sky.sun_direction.xyz) > sky.sun_direction.w) {
+        color = color + sky.sun_color.rgb;
+    }
+    return vec4<f32>(clouds(color, direction, ambient), 1.0);
+}
@@ -0,0 +1,45 @@
+[[block]]
+struct Uniform {
+    projection: mat4x4<f32>;
+};
+[[group(0), binding(0)]]
+var<uniform> sprites: Uniform;
+
+[[group(1), binding(0)]]
+var sheet_texture: texture_2d<f32>;
+[[group(1), binding(1)]]
+var sheet_sampler: sampler;
+
+struct VertexInput {
+    [[location(0)]] position: vec2<f32>;
+    [[location(1)]] uv: vec2<f32>;
+    [[location(2)]] tile: vec4<f32>;
+    [[location(3)]] color: vec4<f32>;
+};
+
+struct VertexOutput {
+    [[builtin(position)]] clip_position: vec4<f32>;
+    [[location(0)]] uv: vec2<f32>;
+    [[location(1)]] tile: vec4<f32>;
+    [[location(2)]] color: vec4<f32>;
+};
+
+[[stage(vertex)]]
+fn vs_main(vertex: VertexInput) -> VertexOutput {
+    var out: VertexOutput;
+    out.clip_position = sprites.projection * vec4<f32>(vertex.position, 0.0, 1.0);
+    out.uv = vertex.uv;
+    out.tile = vertex.tile;
+    out.color = vertex.color;
+    return out;
+}
+
+[[stage(fragment)]]
+fn fs_main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    // Tiled sprites count tiles in their coordinates and wrap them into the region of the sheet
+    var uv = in.uv;
+    if (in.tile.z > 0.0 || in.tile.w > 0.0) {
+        uv = in.tile.xy + fract(in.uv) * in.tile.zw;
+    }
+    return textureSample(sheet_texture, sheet_sampler, uv) * in.color;
+}
@@ -0,0 +1,119 @@
+[[block]]
+struct Camera {
+    view: mat4x4<f32>;
+    projection: mat4x4<f32>;
+    inverse_view_projection: mat4x4<f32>;
+    position: vec4<f32>;
+    parameters: vec4<f32>;
+    previous_view_projection: mat4x4<f32>;
+    jitter: vec4<f32>;
+    depth: vec4<f32>;
+};
+[[group(0), binding(0)]]
+var<uniform> camera: Camera;
+
+// Spliced: standard_depth
+
+[[block]]
+struct Ssao {
+    radius: f32;
+    bias: f32;
+    intensity: f32;
+    samples: u32;
+};
+[[group(1), binding(0)]]
+var depth_texture: texture_depth_2d;
+[[group(1), binding(1)]]
+var<uniform> ssao: Ssao;
+// A 64x64 tile of blue noise packed four texels to a component
+[[block]]
+struct BlueNoise {
+    offset: vec4<u32>;
+    texels: array<vec4<u32>, 256>;
+};
+[[group(1), binding(2)]]
+var<uniform> blue_noise: BlueNoise;
+
+fn blue_noise_at(pixel: vec2<f32>) -> f32 {
+    let coordinate = (vec2<u32>(pixel) + blue_noise.offset.xy) % vec2<u32>(64u);
+    let index = coordinate.y * 64u + coordinate.x;
+    let packed = blue_noise.texels[index / 16u][(index / 4u) % 4u];
+    let value = (packed >> ((index % 4u) * 8u)) & 255u;
+    return (f32(value) + 0.5) / 256.0;
+}
+
+// Spliced: fullscreen_triangle
+
+let GOLDEN_ANGLE: f32 = 2.39996323;
+
+fn view_position(pixel: vec2<i32>, dimensions: vec2<i32>) -> vec3<f32> {
+    let pixel = clamp(pixel, vec2<i32>(0), dimensions - vec2<i32>(1));
+    let depth = textureLoad(depth_texture, pixel, 0);
+    let uv = (vec2<f32>(pixel) + vec2<f32>(0.5)) / vec2<f32>(dimensions);
+    let world = camera.inverse_view_projection * vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, depth, 1.0);
+    return (camera.view * vec4<f32>(world.xyz / world.w, 1.0)).xyz;
+}
+
+// Picks the neighbor closest in depth on each axis so normals don't smear across silhouettes
+fn view_normal(pixel: vec2<i32>, dimensions: vec2<i32>, position: vec3<f32>) -> vec3<f32> {
+    let right = view_position(pixel + vec2<i32>(1, 0), dimensions) - position;
+    let left = position - view_position(pixel - vec2<i32>(1, 0), dimensions);
+    let down = view_position(pixel + vec2<i32>(0, 1), dimensions) - position;
+    let up = position - view_position(pixel - vec2<i32>(0, 1), dimensions);
+    let horizontal = select(left, right, abs(right.z) < abs(left.z));
+    let vertical = select(up, down, abs(down.z) < abs(up.z));
+    let normal = normalize(cross(vertical, horizontal));
+    return select(normal, -normal, dot(normal, position) > 0.0);
+}
+
+[[stage(fragment)]]
+fn fs_main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let dimensions = textureDimensions(depth_texture);
+    let pixel = vec2<i32>(in.clip_position.xy);
+    if (standard_depth(textureLoad(depth_texture, pixel, 0)) >= 1.0 || ssao.samples == 0u) {
+        return vec4<f32>(1.0);
+    }
+
+    let position = view_position(pixel, dimensions);
+    let normal = view_normal(pixel, dimensions, position);
+
+    // Rotating the sample kernel per pixel trades banding for noise that the blur removes, and blue
+    // noise leaves little low frequency noise for the small blur to miss
+    let angle = 6.28318530 * blue_noise_at(in.clip_position.xy);
+    let random = vec3<f32>(cos(angle), sin(angle), 0.0);
+    var tangent = random - normal * dot(random, normal);
+    if (dot(tangent, tangent) < 0.000001) {
+        tangent = vec3<f32>(0.0, 0.0, 1.0) - normal * normal.z;
+    }
+    tangent = normalize(tangent);
+    let bitangent = cross(normal, tangent);
+
+    var occlusion = 0.0;
+    for (var index = 0u; index < ssao.samples; index = index + 1u) {
+        // Samples spiral over the hemisphere and cluster towards the center
+        let t = (f32(index) + 0.5) / f32(ssao.samples);
+        let cos_theta = 1.0 - t;
+        let sin_theta = sqrt(1.0 - cos_theta * cos_theta);
+        let phi = f32(index) * GOLDEN_ANGLE;
+        let direction = vec3<f32>(cos(phi) * sin_theta, sin(phi) * sin_theta, cos_theta);
+        let scale = mix(0.1, 1.0, t * t);
+        let sample = position + (tangent * direction.x + bitangent * direction.y + normal * direction.z) * ssao.radius * scale;
+
+        let clip = camera.projection * vec4<f32>(sample, 1.0);
+        let ndc = clip.xy / clip.w;
+        let uv = vec2<f32>(ndc.x * 0.5 + 0.5, 0.5 - ndc.y * 0.5);
+        if (any(uv < vec2<f32>(0.0)) || any(uv > vec2<f32>(1.0))) {
+            continue;
+        }
+
+        let scene_depth = view_position(vec2<i32>(uv * vec2<f32>(dimensions)), dimensions).z;
+        // Geometry far in front of the sample shouldn't darken it
+        let range = clamp(ssao.radius / max(abs(position.z - scene_depth), 0.0001), 0.0, 1.0);
+        if (scene_depth >= sample.z + ssao.bias) {
+            occlusion = occlusion + range;
+        }
+    }
+
+    let visibility = 1.0 - occlusion / f32(ssao.samples);
+    return vec4<f32>(pow(visibility, ssao.intensity));
+}
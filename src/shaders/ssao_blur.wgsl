@@ -0,0 +1,19 @@
+[[group(0), binding(0)]]
+var ambient_occlusion_texture: texture_2d<f32>;
+
+// Spliced: fullscreen_triangle
+
+// Averages a 4x4 block to smooth out the noise from rotating the sample kernel
+[[stage(fragment)]]
+fn fs_main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let dimensions = textureDimensions(ambient_occlusion_texture);
+    let pixel = vec2<i32>(in.clip_position.xy);
+    var total = 0.0;
+    for (var y = -2; y < 2; y = y + 1) {
+        for (var x = -2; x < 2; x = x + 1) {
+            let neighbor = clamp(pixel + vec2<i32>(x, y), vec2<i32>(0), dimensions - vec2<i32>(1));
+            total = total + textureLoad(ambient_occlusion_texture, neighbor, 0).r;
+        }
+    }
+    return vec4<f32>(total / 16.0);
+}
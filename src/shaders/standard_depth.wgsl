@@ -0,0 +1,4 @@
+// Depth as a standard projection would have written it, where farther is greater
+fn standard_depth(depth: f32) -> f32 {
+    return select(depth, 1.0 - depth, camera.depth.x > 0.0);
+}
@@ -0,0 +1,68 @@
+[[block]]
+struct TemporalUpscale {
+    // xy: this frame's jitter in scene pixels, z: one when the history is discarded
+    jitter: vec4<f32>;
+};
+[[group(0), binding(0)]]
+var scene_texture: texture_2d<f32>;
+[[group(0), binding(1)]]
+var linear_sampler: sampler;
+[[group(0), binding(2)]]
+var velocity_texture: texture_2d<f32>;
+[[group(0), binding(3)]]
+var history_texture: texture_2d<f32>;
+[[group(0), binding(4)]]
+var<uniform> temporal: TemporalUpscale;
+
+// How much of a sample landing exactly on an output pixel replaces its history
+let MAX_BLEND: f32 = 0.2;
+
+// Spliced: fullscreen_triangle
+
+fn load(pixel: vec2<i32>) -> vec3<f32> {
+    let clamped = clamp(pixel, vec2<i32>(0), textureDimensions(scene_texture) - vec2<i32>(1));
+    return textureLoad(scene_texture, clamped, 0).rgb;
+}
+
+// Each frame renders the scene at a different sub pixel offset, so over several frames the low
+// resolution samples cover every output pixel. Samples are accumulated where they land and the
+// history follows the velocity buffer, clamped to the current neighborhood to reject ghosts
+[[stage(fragment)]]
+fn fs_main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let scene_size = vec2<f32>(textureDimensions(scene_texture));
+    let current = textureSampleLevel(scene_texture, linear_sampler, in.uv, 0.0).rgb;
+
+    // The scene pixel whose jittered sample is nearest to this output pixel
+    let position = in.uv * scene_size - vec2<f32>(0.5) - temporal.jitter.xy;
+    let pixel = vec2<i32>(floor(position + vec2<f32>(0.5)));
+    let offset = position - vec2<f32>(pixel);
+    let sample = load(pixel);
+
+    var minimum = sample;
+    var maximum = sample;
+    for (var y = -1; y <= 1; y = y + 1) {
+        for (var x = -1; x <= 1; x = x + 1) {
+            let neighbor = load(pixel + vec2<i32>(x, y));
+            minimum = min(minimum, neighbor);
+            maximum = max(maximum, neighbor);
+        }
+    }
+
+    let velocity_pixel = clamp(pixel, vec2<i32>(0), textureDimensions(velocity_texture) - vec2<i32>(1));
+    let velocity = textureLoad(velocity_texture, velocity_pixel, 0).xy;
+    let history_uv = in.uv - velocity;
+    let outside = any(history_uv < vec2<f32>(0.0)) || any(history_uv > vec2<f32>(1.0));
+    if (temporal.jitter.z > 0.0 || outside) {
+        return vec4<f32>(current, 1.0);
+    }
+
+    let history = clamp(
+        textureSampleLevel(history_texture, linear_sampler, history_uv, 0.0).rgb,
+        minimum,
+        maximum,
+    );
+
+    // A gaussian falloff with the distance between the sample and the output pixel
+    let weight = exp(-2.29 * dot(offset, offset));
+    return vec4<f32>(mix(history, sample, weight * MAX_BLEND), 1.0);
+}
@@ -0,0 +1,89 @@
+[[block]]
+struct Camera {
+    view: mat4x4<f32>;
+    projection: mat4x4<f32>;
+    inverse_view_projection: mat4x4<f32>;
+    position: vec4<f32>;
+    parameters: vec4<f32>;
+    previous_view_projection: mat4x4<f32>;
+    jitter: vec4<f32>;
+    depth: vec4<f32>;
+};
+[[group(0), binding(0)]]
+var<uniform> camera: Camera;
+
+// Spliced: standard_depth
+
+[[group(1), binding(0)]]
+var atlas_texture: texture_2d<f32>;
+[[group(1), binding(1)]]
+var atlas_sampler: sampler;
+
+[[group(2), binding(0)]]
+var depth_texture: texture_depth_2d;
+
+struct VertexInput {
+    [[location(0)]] anchor: vec3<f32>;
+    [[location(1)]] offset: vec2<f32>;
+    [[location(2)]] uv: vec2<f32>;
+    [[location(3)]] color: vec4<f32>;
+};
+
+struct VertexOutput {
+    [[builtin(position)]] clip_position: vec4<f32>;
+    [[location(0)]] uv: vec2<f32>;
+    [[location(1)]] color: vec4<f32>;
+    [[location(2)]] clip: vec4<f32>;
+};
+
+// Screen text is already laid out in clip space
+[[stage(vertex)]]
+fn vs_main(vertex: VertexInput) -> VertexOutput {
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(vertex.offset, 0.0, 1.0);
+    out.uv = vertex.uv;
+    out.color = vertex.color;
+    out.clip = out.clip_position;
+    return out;
+}
+
+// Labels are spread out along the camera's right and up axes so they always face it
+[[stage(vertex)]]
+fn vs_label(vertex: VertexInput) -> VertexOutput {
+    let right = vec3<f32>(camera.view[0].x, camera.view[1].x, camera.view[2].x);
+    let up = vec3<f32>(camera.view[0].y, camera.view[1].y, camera.view[2].y);
+    let position = vertex.anchor + right * vertex.offset.x + up * vertex.offset.y;
+
+    var out: VertexOutput;
+    out.clip_position = camera.projection * camera.view * vec4<f32>(position, 1.0);
+    out.uv = vertex.uv;
+    out.color = vertex.color;
+    out.clip = out.clip_position;
+    return out;
+}
+
+// The atlas holds distance fields with the outline at one half, blended across a pixel at any scale
+fn coverage(uv: vec2<f32>) -> f32 {
+    let distance = textureSample(atlas_texture, atlas_sampler, uv).r;
+    let width = max(fwidth(distance), 0.0001);
+    return clamp((distance - 0.5) / width + 0.5, 0.0, 1.0);
+}
+
+[[stage(fragment)]]
+fn fs_main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    return vec4<f32>(in.color.rgb, in.color.a * coverage(in.uv));
+}
+
+// The depth texture is at the render resolution, which can differ from the output
+[[stage(fragment)]]
+fn fs_depth_tested(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let alpha = in.color.a * coverage(in.uv);
+    let ndc = in.clip.xyz / in.clip.w;
+    let dimensions = textureDimensions(depth_texture);
+    let screen_uv = vec2<f32>(ndc.x * 0.5 + 0.5, 0.5 - ndc.y * 0.5);
+    let texel = clamp(vec2<i32>(screen_uv * vec2<f32>(dimensions)), vec2<i32>(0), dimensions - vec2<i32>(1));
+    if (standard_depth(ndc.z) > standard_depth(textureLoad(depth_texture, texel, 0))) {
+        discard;
+    }
+    return vec4<f32>(in.color.rgb, alpha);
+}
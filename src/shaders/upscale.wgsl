@@ -0,0 +1,138 @@
+[[block]]
+struct Upscale {
+    // Input pixels per output pixel
+    ratio: vec4<f32>;
+};
+[[group(0), binding(0)]]
+var scene_texture: texture_2d<f32>;
+[[group(0), binding(1)]]
+var<uniform> upscale: Upscale;
+
+// Spliced: fullscreen_triangle
+
+fn load(pixel: vec2<i32>) -> vec3<f32> {
+    let clamped = clamp(pixel, vec2<i32>(0), textureDimensions(scene_texture) - vec2<i32>(1));
+    return textureLoad(scene_texture, clamped, 0).rgb;
+}
+
+// Edges are found in a compressed range so bright highlights don't dominate them
+fn luma(color: vec3<f32>) -> f32 {
+    let value = dot(color, vec3<f32>(0.2126, 0.7152, 0.0722));
+    return value / (1.0 + value);
+}
+
+struct Accumulator {
+    color: vec3<f32>;
+    weight: f32;
+};
+
+// Approximation of a Lanczos 2 window, lobe narrows the negative lobe along edges
+fn lanczos(accumulator: Accumulator, offset: vec2<f32>, direction: vec2<f32>, stretch: vec2<f32>, lobe: f32, color: vec3<f32>) -> Accumulator {
+    var rotated = vec2<f32>(
+        offset.x * direction.x + offset.y * direction.y,
+        offset.y * direction.x - offset.x * direction.y,
+    );
+    rotated = rotated * stretch;
+    let distance_squared = min(dot(rotated, rotated), 1.0 / lobe);
+    var window = 0.4 * distance_squared - 1.0;
+    var base = lobe * distance_squared - 1.0;
+    window = window * window;
+    base = base * base;
+    window = 1.5625 * window - 0.5625;
+    let weight = window * base;
+
+    var out: Accumulator;
+    out.color = accumulator.color + color * weight;
+    out.weight = accumulator.weight + weight;
+    return out;
+}
+
+// An edge adaptive upscale in the spirit of FSR 1: twelve taps around the sample position are
+// weighted by a Lanczos kernel that stretches along edges, so they stay sharp without stair steps
+[[stage(fragment)]]
+fn fs_main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let position = in.clip_position.xy * upscale.ratio.xy - vec2<f32>(0.5);
+    let base = vec2<i32>(floor(position));
+    let fraction = position - floor(position);
+
+    //    b c
+    //  e f g h
+    //  i j k l
+    //    n o
+    let b = load(base + vec2<i32>(0, -1));
+    let c = load(base + vec2<i32>(1, -1));
+    let e = load(base + vec2<i32>(-1, 0));
+    let f = load(base);
+    let g = load(base + vec2<i32>(1, 0));
+    let h = load(base + vec2<i32>(2, 0));
+    let i = load(base + vec2<i32>(-1, 1));
+    let j = load(base + vec2<i32>(0, 1));
+    let k = load(base + vec2<i32>(1, 1));
+    let l = load(base + vec2<i32>(2, 1));
+    let n = load(base + vec2<i32>(0, 2));
+    let o = load(base + vec2<i32>(1, 2));
+
+    let lb = luma(b);
+    let lc = luma(c);
+    let le = luma(e);
+    let lf = luma(f);
+    let lg = luma(g);
+    let lh = luma(h);
+    let li = luma(i);
+    let lj = luma(j);
+    let lk = luma(k);
+    let ll = luma(l);
+    let ln = luma(n);
+    let lo = luma(o);
+
+    // Gradients at the four nearest pixels, blended by how close the sample is to each
+    let weights = vec4<f32>(
+        (1.0 - fraction.x) * (1.0 - fraction.y),
+        fraction.x * (1.0 - fraction.y),
+        (1.0 - fraction.x) * fraction.y,
+        fraction.x * fraction.y,
+    );
+    let gradient = vec2<f32>(lg - le, lj - lb) * weights.x
+        + vec2<f32>(lh - lf, lk - lc) * weights.y
+        + vec2<f32>(lk - li, ln - lf) * weights.z
+        + vec2<f32>(ll - lj, lo - lg) * weights.w;
+
+    let minimum_luma = min(min(min(lf, lg), min(lj, lk)), min(min(lb, lc), min(ln, lo)));
+    let maximum_luma = max(max(max(lf, lg), max(lj, lk)), max(max(lb, lc), max(ln, lo)));
+    let gradient_length = length(gradient);
+    var direction = vec2<f32>(1.0, 0.0);
+    if (gradient_length > 0.0001) {
+        direction = gradient / gradient_length;
+    }
+
+    // Zero in flat areas, one across a hard edge
+    var edge = clamp(gradient_length * 0.5 / max(maximum_luma - minimum_luma, 0.0001), 0.0, 1.0);
+    edge = edge * edge;
+
+    // Diagonal edges are stretched further so they stay as smooth as straight ones
+    let stretch_amount = 1.0 / max(abs(direction.x), abs(direction.y));
+    let stretch = vec2<f32>(1.0 + (stretch_amount - 1.0) * edge, 1.0 - 0.5 * edge);
+    let lobe = 0.5 - 0.29 * edge;
+
+    var accumulator: Accumulator;
+    accumulator.color = vec3<f32>(0.0);
+    accumulator.weight = 0.0;
+    accumulator = lanczos(accumulator, vec2<f32>(0.0, -1.0) - fraction, direction, stretch, lobe, b);
+    accumulator = lanczos(accumulator, vec2<f32>(1.0, -1.0) - fraction, direction, stretch, lobe, c);
+    accumulator = lanczos(accumulator, vec2<f32>(-1.0, 0.0) - fraction, direction, stretch, lobe, e);
+    accumulator = lanczos(accumulator, vec2<f32>(0.0, 0.0) - fraction, direction, stretch, lobe, f);
+    accumulator = lanczos(accumulator, vec2<f32>(1.0, 0.0) - fraction, direction, stretch, lobe, g);
+    accumulator = lanczos(accumulator, vec2<f32>(2.0, 0.0) - fraction, direction, stretch, lobe, h);
+    accumulator = lanczos(accumulator, vec2<f32>(-1.0, 1.0) - fraction, direction, stretch, lobe, i);
+    accumulator = lanczos(accumulator, vec2<f32>(0.0, 1.0) - fraction, direction, stretch, lobe, j);
+    accumulator = lanczos(accumulator, vec2<f32>(1.0, 1.0) - fraction, direction, stretch, lobe, k);
+    accumulator = lanczos(accumulator, vec2<f32>(2.0, 1.0) - fraction, direction, stretch, lobe, l);
+    accumulator = lanczos(accumulator, vec2<f32>(0.0, 2.0) - fraction, direction, stretch, lobe, n);
+    accumulator = lanczos(accumulator, vec2<f32>(1.0, 2.0) - fraction, direction, stretch, lobe, o);
+
+    // The negative lobes can ring past the nearest pixels, which is clamped away
+    let minimum = min(min(f, g), min(j, k));
+    let maximum = max(max(f, g), max(j, k));
+    let color = clamp(accumulator.color / max(accumulator.weight, 0.0001), minimum, maximum);
+    return vec4<f32>(color, 1.0);
+}
@@ -0,0 +1,33 @@
+[[block]]
+struct Camera {
+    view: mat4x4<f32>;
+    projection: mat4x4<f32>;
+    inverse_view_projection: mat4x4<f32>;
+    position: vec4<f32>;
+    parameters: vec4<f32>;
+    previous_view_projection: mat4x4<f32>;
+    jitter: vec4<f32>;
+};
+[[group(0), binding(0)]]
+var<uniform> camera: Camera;
+
+[[group(1), binding(0)]]
+var depth_texture: texture_depth_2d;
+
+// Spliced: fullscreen_triangle
+
+fn clip_to_uv(clip: vec4<f32>) -> vec2<f32> {
+    let ndc = clip.xy / clip.w;
+    return vec2<f32>(ndc.x * 0.5 + 0.5, 0.5 - ndc.y * 0.5);
+}
+
+// Reprojects every pixel as if it stood still, which covers everything the camera moves past
+[[stage(fragment)]]
+fn fs_camera(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let depth = textureLoad(depth_texture, vec2<i32>(in.clip_position.xy), 0);
+    let ndc = vec4<f32>(in.uv.x * 2.0 - 1.0, 1.0 - in.uv.y * 2.0, depth, 1.0);
+    let world = camera.inverse_view_projection * ndc;
+    let previous = camera.previous_view_projection * vec4<f32>(world.xyz / world.w, 1.0);
+    // Jitter is left out so still pixels have no velocity
+    return vec4<f32>(in.uv + camera.jitter.xy - clip_to_uv(previous), 0.0, 1.0);
+}
@@ -0,0 +1,99 @@
+[[block]]
+struct Camera {
+    view: mat4x4<f32>;
+    projection: mat4x4<f32>;
+    inverse_view_projection: mat4x4<f32>;
+    position: vec4<f32>;
+    parameters: vec4<f32>;
+};
+[[group(0), binding(0)]]
+var<uniform> camera: Camera;
+
+[[group(1), binding(0)]]
+var block_textures: texture_2d_array<f32>;
+[[group(1), binding(1)]]
+var block_sampler: sampler;
+
+[[block]]
+struct Light {
+    view_projection: mat4x4<f32>;
+    direction: vec4<f32>;
+    color: vec4<f32>;
+};
+[[group(2), binding(0)]]
+var<uniform> light: Light;
+[[group(2), binding(1)]]
+var shadow_map: texture_depth_2d;
+[[group(2), binding(2)]]
+var shadow_sampler: sampler_comparison;
+[[group(2), binding(8)]]
+var shadow_mask: texture_2d<f32>;
+
+struct VertexInput {
+    [[location(0)]] position: vec3<f32>;
+    [[location(1)]] normal: vec3<f32>;
+    [[location(2)]] uv: vec2<f32>;
+    [[location(3)]] layer: u32;
+    [[location(4)]] offset: vec3<f32>;
+};
+
+struct VertexOutput {
+    [[builtin(position)]] clip_position: vec4<f32>;
+    [[location(0)]] normal: vec3<f32>;
+    [[location(1)]] uv: vec2<f32>;
+    [[location(2)]] world_position: vec3<f32>;
+    [[location(3), interpolate(flat)]] layer: u32;
+};
+
+[[stage(vertex)]]
+fn vs_main(vertex: VertexInput) -> VertexOutput {
+    var out: VertexOutput;
+    let position = vertex.position + vertex.offset;
+    out.clip_position = camera.projection * camera.view * vec4<f32>(position, 1.0);
+    out.normal = vertex.normal;
+    out.uv = vertex.uv;
+    out.world_position = position;
+    out.layer = vertex.layer;
+    return out;
+}
+
+let AMBIENT: f32 = 0.25;
+let SHADOW_MAP_SIZE: f32 = 2048.0;
+
+// Averages a 3x3 neighborhood of shadow map comparisons to soften the edges
+fn shadow_visibility(position: vec3<f32>) -> f32 {
+    let clip = light.view_projection * vec4<f32>(position, 1.0);
+    let ndc = clip.xyz / clip.w;
+    let uv = vec2<f32>(ndc.x * 0.5 + 0.5, 0.5 - ndc.y * 0.5);
+    if (any(uv < vec2<f32>(0.0)) || any(uv > vec2<f32>(1.0)) || ndc.z < 0.0 || ndc.z > 1.0) {
+        return 1.0;
+    }
+
+    // The sun's tile is in the corner of the atlas it shares with local lights, and filtering
+    // stays inside it
+    let atlas_scale = SHADOW_MAP_SIZE / f32(textureDimensions(shadow_map).x);
+    let texel = 1.0 / SHADOW_MAP_SIZE;
+    var visibility = 0.0;
+    for (var y = -1; y <= 1; y = y + 1) {
+        for (var x = -1; x <= 1; x = x + 1) {
+            let offset = vec2<f32>(f32(x), f32(y)) * texel;
+            let sample_uv = clamp(uv + offset, vec2<f32>(texel), vec2<f32>(1.0 - texel)) * atlas_scale;
+            visibility = visibility + textureSampleCompareLevel(shadow_map, shadow_sampler, sample_uv, ndc.z);
+        }
+    }
+    return visibility / 9.0;
+}
+
+// Sun visibility traced against the models, lit everywhere when shadows aren't ray traced
+fn traced_shadow(pixel: vec2<f32>) -> f32 {
+    let dimensions = textureDimensions(shadow_mask);
+    return textureLoad(shadow_mask, min(vec2<i32>(pixel), dimensions - vec2<i32>(1)), 0).r;
+}
+
+[[stage(fragment)]]
+fn fs_main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let albedo = textureSample(block_textures, block_sampler, in.uv, i32(in.layer)).rgb;
+    let diffuse = max(dot(in.normal, light.direction.xyz), 0.0);
+    let direct = light.color.rgb * diffuse * min(shadow_visibility(in.world_position), traced_shadow(in.clip_position.xy));
+    return vec4<f32>(albedo * (vec3<f32>(AMBIENT) + direct * (1.0 - AMBIENT)), 1.0);
+}
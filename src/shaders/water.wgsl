@@ -0,0 +1,234 @@
+[[block]]
+struct Camera {
+    view: mat4x4<f32>;
+    projection: mat4x4<f32>;
+    inverse_view_projection: mat4x4<f32>;
+    position: vec4<f32>;
+    parameters: vec4<f32>;
+    previous_view_projection: mat4x4<f32>;
+    jitter: vec4<f32>;
+    depth: vec4<f32>;
+};
+[[group(0), binding(0)]]
+var<uniform> camera: Camera;
+
+// Spliced: standard_depth
+
+[[block]]
+struct Water {
+    model: mat4x4<f32>;
+    shallow_color: vec4<f32>;
+    deep_color: vec4<f32>;
+    absorption: vec4<f32>;
+    foam: vec4<f32>;
+    sky_color: vec4<f32>;
+    waves: array<vec4<f32>, 4>;
+    time: f32;
+    refraction_strength: f32;
+    reflection_strength: f32;
+    wave_count: u32;
+    resolution: u32;
+    reflection_steps: u32;
+};
+[[group(1), binding(0)]]
+var<uniform> water: Water;
+
+[[group(2), binding(0)]]
+var depth_texture: texture_depth_2d;
+[[group(2), binding(1)]]
+var scene_color_texture: texture_2d<f32>;
+
+let PI: f32 = 3.14159265;
+let GRAVITY: f32 = 9.8;
+let LIGHT_DIRECTION: vec3<f32> = vec3<f32>(0.3, 1.0, 0.2);
+
+struct GerstnerSample {
+    offset: vec3<f32>;
+    normal: vec3<f32>;
+};
+
+fn gerstner(position: vec2<f32>) -> GerstnerSample {
+    var offset = vec3<f32>(0.0);
+    var tangent = vec3<f32>(1.0, 0.0, 0.0);
+    var binormal = vec3<f32>(0.0, 0.0, 1.0);
+    for (var index = 0u; index < water.wave_count; index = index + 1u) {
+        let wave = water.waves[index];
+        let direction = wave.xy;
+        let steepness = wave.z;
+        let wavenumber = 2.0 * PI / wave.w;
+        let speed = sqrt(GRAVITY / wavenumber);
+        let phase = wavenumber * (dot(direction, position) - speed * water.time);
+        let amplitude = steepness / wavenumber;
+        let sine = sin(phase);
+        let cosine = cos(phase);
+
+        offset = offset + vec3<f32>(
+            direction.x * amplitude * cosine,
+            amplitude * sine,
+            direction.y * amplitude * cosine
+        );
+        tangent = tangent + vec3<f32>(
+            -direction.x * direction.x * steepness * sine,
+            direction.x * steepness * cosine,
+            -direction.x * direction.y * steepness * sine
+        );
+        binormal = binormal + vec3<f32>(
+            -direction.x * direction.y * steepness * sine,
+            direction.y * steepness * cosine,
+            -direction.y * direction.y * steepness * sine
+        );
+    }
+
+    var sample: GerstnerSample;
+    sample.offset = offset;
+    sample.normal = normalize(cross(binormal, tangent));
+    return sample;
+}
+
+struct VertexOutput {
+    [[builtin(position)]] clip_position: vec4<f32>;
+    [[location(0)]] world_position: vec3<f32>;
+    [[location(1)]] rest_position: vec2<f32>;
+};
+
+[[stage(vertex)]]
+fn vs_main([[builtin(vertex_index)]] vertex_index: u32) -> VertexOutput {
+    var corners = array<vec2<f32>, 6>(
+        vec2<f32>(0.0, 0.0),
+        vec2<f32>(0.0, 1.0),
+        vec2<f32>(1.0, 0.0),
+        vec2<f32>(1.0, 0.0),
+        vec2<f32>(0.0, 1.0),
+        vec2<f32>(1.0, 1.0),
+    );
+
+    let quad = vertex_index / 6u;
+    let corner = corners[vertex_index % 6u];
+    let cell = vec2<f32>(f32(quad % water.resolution), f32(quad / water.resolution));
+    let local = (cell + corner) / f32(water.resolution) - vec2<f32>(0.5);
+
+    let rest = water.model * vec4<f32>(local.x, 0.0, local.y, 1.0);
+    let world_position = rest.xyz + gerstner(rest.xz).offset;
+
+    var out: VertexOutput;
+    out.clip_position = camera.projection * camera.view * vec4<f32>(world_position, 1.0);
+    out.world_position = world_position;
+    out.rest_position = rest.xz;
+    return out;
+}
+
+fn smooth_step(edge0: f32, edge1: f32, x: f32) -> f32 {
+    let t = clamp((x - edge0) / (edge1 - edge0), 0.0, 1.0);
+    return t * t * (3.0 - 2.0 * t);
+}
+
+fn viewport_size() -> vec2<f32> {
+    return camera.parameters.zw;
+}
+
+fn load_depth(uv: vec2<f32>) -> f32 {
+    let size = viewport_size();
+    let pixel = clamp(vec2<i32>(uv * size), vec2<i32>(0), vec2<i32>(size) - vec2<i32>(1));
+    return textureLoad(depth_texture, pixel, 0);
+}
+
+fn load_color(uv: vec2<f32>) -> vec3<f32> {
+    let size = viewport_size();
+    let pixel = clamp(vec2<i32>(uv * size), vec2<i32>(0), vec2<i32>(size) - vec2<i32>(1));
+    return textureLoad(scene_color_texture, pixel, 0).rgb;
+}
+
+fn linear_depth(depth: f32) -> f32 {
+    let near = camera.parameters.x;
+    let far = camera.parameters.y;
+    return near * far / (far - standard_depth(depth) * (far - near));
+}
+
+fn world_position_at(uv: vec2<f32>, depth: f32) -> vec3<f32> {
+    let ndc = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, depth, 1.0);
+    let world = camera.inverse_view_projection * ndc;
+    return world.xyz / world.w;
+}
+
+// Marches the reflected ray through the depth buffer, returning the color it hits in rgb and its confidence in w
+fn screen_space_reflection(origin: vec3<f32>, direction: vec3<f32>) -> vec4<f32> {
+    var travelled = 0.1;
+    var step = 0.25;
+    for (var index = 0u; index < water.reflection_steps; index = index + 1u) {
+        let position = origin + direction * travelled;
+        let clip = camera.projection * camera.view * vec4<f32>(position, 1.0);
+        if (clip.w <= 0.0) {
+            break;
+        }
+        let ndc = clip.xyz / clip.w;
+        let uv = vec2<f32>(ndc.x * 0.5 + 0.5, 0.5 - ndc.y * 0.5);
+        if (any(uv < vec2<f32>(0.0)) || any(uv > vec2<f32>(1.0))) {
+            break;
+        }
+
+        let scene_depth = load_depth(uv);
+        let difference = linear_depth(ndc.z) - linear_depth(scene_depth);
+        if (standard_depth(scene_depth) < 1.0 && difference > 0.0 && difference < step * 2.0) {
+            // Fade out near the screen edges where the reflected geometry is about to leave the view
+            let edge = min(min(uv.x, 1.0 - uv.x), min(uv.y, 1.0 - uv.y));
+            return vec4<f32>(load_color(uv), clamp(edge * 10.0, 0.0, 1.0));
+        }
+
+        travelled = travelled + step;
+        step = step * 1.15;
+    }
+    return vec4<f32>(0.0);
+}
+
+[[stage(fragment)]]
+fn fs_main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let size = viewport_size();
+    let uv = in.clip_position.xy / size;
+
+    // Opaque geometry in front of the water hides it
+    let scene_depth = load_depth(uv);
+    if (standard_depth(in.clip_position.z) >= standard_depth(scene_depth)) {
+        discard;
+    }
+
+    var normal = gerstner(in.rest_position).normal;
+    let view_direction = normalize(camera.position.xyz - in.world_position);
+    if (dot(normal, view_direction) < 0.0) {
+        normal = -normal;
+    }
+
+    // Refraction distorts the view of the scene below, unless that would pull in geometry above the water
+    var refracted_uv = uv + normal.xz * water.refraction_strength;
+    var refracted_depth = load_depth(refracted_uv);
+    if (standard_depth(refracted_depth) <= standard_depth(in.clip_position.z)) {
+        refracted_uv = uv;
+        refracted_depth = scene_depth;
+    }
+    let refracted = load_color(refracted_uv);
+
+    // Light is absorbed in proportion to how much water it travels through
+    let floor_position = world_position_at(refracted_uv, refracted_depth);
+    let thickness = select(distance(in.world_position, floor_position), 1000.0, standard_depth(refracted_depth) >= 1.0);
+    let transmittance = exp(-water.absorption.rgb * thickness);
+    let scattered = mix(water.deep_color.rgb, water.shallow_color.rgb, transmittance);
+    var color = refracted * transmittance + scattered * (vec3<f32>(1.0) - transmittance);
+
+    let reflected_direction = reflect(-view_direction, normal);
+    let reflection = screen_space_reflection(in.world_position, reflected_direction);
+    let reflected = mix(water.sky_color.rgb, reflection.rgb, reflection.w);
+    let fresnel = 0.02 + 0.98 * pow(1.0 - max(dot(normal, view_direction), 0.0), 5.0);
+    color = mix(color, reflected, clamp(fresnel * water.reflection_strength, 0.0, 1.0));
+
+    let light_direction = normalize(LIGHT_DIRECTION);
+    let halfway = normalize(light_direction + view_direction);
+    color = color + vec3<f32>(pow(max(dot(normal, halfway), 0.0), 256.0) * 2.0);
+
+    // Foam gathers where the water meets the shore, broken up by a scrolling pattern
+    let shore_depth = distance(in.world_position, world_position_at(uv, scene_depth));
+    let shore = 1.0 - clamp(shore_depth / max(water.foam.w, 0.0001), 0.0, 1.0);
+    let pattern = 0.5 + 0.5 * sin(in.rest_position.x * 3.0 + water.time * 1.3) * sin(in.rest_position.y * 3.0 - water.time * 0.9);
+    let foam = smooth_step(pattern - 0.15, pattern + 0.15, shore);
+    color = mix(color, water.foam.rgb, foam);
+
+    return vec4<f32>(color, 1.0);
+}
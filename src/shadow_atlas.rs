@@ -0,0 +1,226 @@
+use nalgebra_glm as glm;
+
+use crate::{
+    bounds::{Aabb, Frustum},
+    camera::{Camera, Projection},
+    lighting::{PunctualLight, PunctualLightKind, MAX_PUNCTUAL_LIGHTS},
+};
+
+pub const ATLAS_SIZE: u32 = 4096;
+// Views rendered into the atlas each frame, a point light takes six
+pub const MAX_SHADOW_VIEWS: usize = 48;
+// Lights covering the whole screen get the largest tiles, tiles shrink to the smallest before a
+// light goes unshadowed
+const MAX_TILE_SIZE: u32 = 1024;
+const MIN_TILE_SIZE: u32 = 128;
+// How far lights without a range cast shadows
+const UNBOUNDED_SHADOW_RANGE: f32 = 100.0;
+const SHADOW_NEAR_PLANE: f32 = 0.05;
+
+// Looking down each axis in the order the shader picks faces: +x, -x, +y, -y, +z, -z
+const CUBE_FACES: [([f32; 3], [f32; 3]); 6] = [
+    ([1.0, 0.0, 0.0], [0.0, -1.0, 0.0]),
+    ([-1.0, 0.0, 0.0], [0.0, -1.0, 0.0]),
+    ([0.0, 1.0, 0.0], [0.0, 0.0, 1.0]),
+    ([0.0, -1.0, 0.0], [0.0, 0.0, -1.0]),
+    ([0.0, 0.0, 1.0], [0.0, -1.0, 0.0]),
+    ([0.0, 0.0, -1.0], [0.0, -1.0, 0.0]),
+];
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ShadowView {
+    pub view_projection: glm::Mat4,
+    // x, y and size of the tile in texels
+    pub viewport: [u32; 3],
+    // The layers the light casts shadows onto, see layers.rs
+    pub layers: u32,
+}
+
+// The views of a shadowed light, which are consecutive
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct LightShadow {
+    pub light: usize,
+    pub first_view: usize,
+    pub view_count: usize,
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ShadowAssignment {
+    pub views: Vec<ShadowView>,
+    pub lights: Vec<LightShadow>,
+}
+
+impl ShadowAssignment {
+    pub fn light_shadow(&self, light: usize) -> Option<&LightShadow> {
+        self.lights.iter().find(|shadow| shadow.light == light)
+    }
+}
+
+// Gives visible shadow casting spot and point lights tiles of the atlas left around the sun's,
+// sized by how much of the screen they cover, with the largest lights served first
+pub fn assign(
+    punctual_lights: &[PunctualLight],
+    camera: &Camera,
+    frustum: &Frustum,
+    atlas_size: u32,
+    sun_size: u32,
+) -> ShadowAssignment {
+    let mut candidates = punctual_lights
+        .iter()
+        .take(MAX_PUNCTUAL_LIGHTS)
+        .enumerate()
+        .filter(|(_, light)| {
+            light.cast_shadows && !matches!(light.kind, PunctualLightKind::Directional)
+        })
+        .filter_map(|(index, light)| {
+            let range = shadow_range(light);
+            let offset = glm::vec3(range, range, range);
+            let bounds = Aabb {
+                min: light.position - offset,
+                max: light.position + offset,
+            };
+            frustum.intersects_aabb(&bounds).then(|| {
+                (
+                    index,
+                    light,
+                    screen_coverage(camera, &light.position, range),
+                )
+            })
+        })
+        .collect::<Vec<_>>();
+    candidates.sort_by(|a, b| b.2.total_cmp(&a.2));
+
+    let mut assignment = ShadowAssignment::default();
+    let mut allocator = TileAllocator::new(atlas_size);
+    // The sun's tile goes first, in the corner
+    allocator.allocate(sun_size);
+    for (index, light, coverage) in candidates {
+        let faces = light_faces(light);
+        if assignment.views.len() + faces.len() > MAX_SHADOW_VIEWS {
+            continue;
+        }
+
+        let mut tile_size = ((coverage * MAX_TILE_SIZE as f32) as u32)
+            .next_power_of_two()
+            .clamp(MIN_TILE_SIZE, MAX_TILE_SIZE.min(atlas_size));
+        while tile_size >= MIN_TILE_SIZE && allocator.capacity(tile_size) < faces.len() {
+            tile_size /= 2;
+        }
+        if tile_size < MIN_TILE_SIZE {
+            continue;
+        }
+
+        assignment.lights.push(LightShadow {
+            light: index,
+            first_view: assignment.views.len(),
+            view_count: faces.len(),
+        });
+        for view_projection in faces {
+            if let Some(viewport) = allocator.allocate(tile_size) {
+                assignment.views.push(ShadowView {
+                    view_projection,
+                    viewport,
+                    layers: light.layers,
+                });
+            }
+        }
+    }
+    assignment
+}
+
+fn shadow_range(light: &PunctualLight) -> f32 {
+    light
+        .range
+        .filter(|range| *range > SHADOW_NEAR_PLANE)
+        .unwrap_or(UNBOUNDED_SHADOW_RANGE)
+}
+
+// Roughly the fraction of the screen's height the light's range covers
+fn screen_coverage(camera: &Camera, center: &glm::Vec3, radius: f32) -> f32 {
+    let distance = glm::distance(&camera.position, center);
+    if distance <= radius {
+        return 1.0;
+    }
+    let half_height = match camera.projection {
+        Projection::Perspective => distance * (camera.vertical_fov() * 0.5).tan(),
+        Projection::Orthographic { height } => height * 0.5,
+    };
+    (radius / half_height.max(f32::EPSILON)).min(1.0)
+}
+
+// One view through the cone of a spot light, six around a point light
+fn light_faces(light: &PunctualLight) -> Vec<glm::Mat4> {
+    let far = shadow_range(light);
+    match light.kind {
+        PunctualLightKind::Spot {
+            outer_cone_angle, ..
+        } => {
+            let fov = (outer_cone_angle * 2.0 + 0.1).min(170_f32.to_radians());
+            let direction = light
+                .direction
+                .try_normalize(f32::EPSILON)
+                .unwrap_or(-glm::Vec3::z());
+            let up = if direction.y.abs() > 0.99 {
+                glm::Vec3::z()
+            } else {
+                glm::Vec3::y()
+            };
+            let view = glm::look_at_rh(&light.position, &(light.position + direction), &up);
+            vec![glm::perspective_rh_zo(1.0, fov, SHADOW_NEAR_PLANE, far) * view]
+        }
+        _ => {
+            let projection =
+                glm::perspective_rh_zo(1.0, std::f32::consts::FRAC_PI_2, SHADOW_NEAR_PLANE, far);
+            CUBE_FACES
+                .iter()
+                .map(|(direction, up)| {
+                    let target = light.position + glm::Vec3::from(*direction);
+                    projection * glm::look_at_rh(&light.position, &target, &glm::Vec3::from(*up))
+                })
+                .collect()
+        }
+    }
+}
+
+// Hands out square tiles with power of two sizes, splitting free tiles into quarters as needed
+struct TileAllocator {
+    // x, y and size in texels
+    free: Vec<[u32; 3]>,
+}
+
+impl TileAllocator {
+    fn new(size: u32) -> Self {
+        Self {
+            free: vec![[0, 0, size]],
+        }
+    }
+
+    // How many tiles of a size still fit
+    fn capacity(&self, size: u32) -> usize {
+        self.free
+            .iter()
+            .filter(|tile| tile[2] >= size)
+            .map(|tile| ((tile[2] / size) * (tile[2] / size)) as usize)
+            .sum()
+    }
+
+    fn allocate(&mut self, size: u32) -> Option<[u32; 3]> {
+        // The smallest free tile that fits, which keeps larger ones whole
+        let index = self
+            .free
+            .iter()
+            .enumerate()
+            .filter(|(_, tile)| tile[2] >= size)
+            .min_by_key(|(_, tile)| tile[2])
+            .map(|(index, _)| index)?;
+        let mut tile = self.free.swap_remove(index);
+        while tile[2] > size {
+            let half = tile[2] / 2;
+            self.free.push([tile[0] + half, tile[1], half]);
+            self.free.push([tile[0], tile[1] + half, half]);
+            self.free.push([tile[0] + half, tile[1] + half, half]);
+            tile[2] = half;
+        }
+        Some(tile)
+    }
+}
@@ -0,0 +1,220 @@
+use crate::{
+    memory::{self, MemoryCategory, Tracked},
+    settings::{Settings, SharpenMode},
+    shader_snippets,
+    texture::Texture,
+};
+
+#[repr(C)]
+#[derive(Default, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct SharpenUniform {
+    mode: u32,
+    strength: f32,
+    _padding: [u32; 2],
+}
+
+// Sharpens the tone mapped image, which the post process draws into an intermediate texture
+// instead of the output while sharpening is enabled
+pub struct SharpenSystem {
+    uniform_buffer: Tracked<wgpu::Buffer>,
+    enabled: bool,
+    bind_group_layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+    input: Texture,
+    pipeline: wgpu::RenderPipeline,
+}
+
+impl SharpenSystem {
+    pub fn new(
+        device: &wgpu::Device,
+        output_format: wgpu::TextureFormat,
+        dimensions: &[u32; 2],
+    ) -> Self {
+        let uniform_buffer = memory::create_buffer(
+            device,
+            MemoryCategory::Transient,
+            &wgpu::BufferDescriptor {
+                label: Some("Sharpen Uniform Buffer"),
+                size: std::mem::size_of::<SharpenUniform>() as wgpu::BufferAddress,
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            },
+        );
+
+        let bind_group_layout = memory::create_bind_group_layout(
+            device,
+            &wgpu::BindGroupLayoutDescriptor {
+                label: Some("Sharpen Bind Group Layout"),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                ],
+            },
+        );
+
+        let module = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("Sharpen Shader"),
+            source: wgpu::ShaderSource::Wgsl(
+                shader_snippets::splice(include_str!("shaders/sharpen.wgsl")).into(),
+            ),
+        });
+
+        let pipeline_layout = memory::create_pipeline_layout(
+            device,
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("Sharpen Pipeline Layout"),
+                bind_group_layouts: &[&bind_group_layout],
+                push_constant_ranges: &[],
+            },
+        );
+
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Sharpen Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &module,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            fragment: Some(wgpu::FragmentState {
+                module: &module,
+                entry_point: "fs_main",
+                targets: &[wgpu::ColorTargetState {
+                    format: output_format,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                }],
+            }),
+        });
+
+        let input = Self::create_input(device, output_format, dimensions);
+        let bind_group =
+            Self::create_bind_group(device, &bind_group_layout, &uniform_buffer, &input);
+
+        Self {
+            uniform_buffer,
+            enabled: false,
+            bind_group_layout,
+            bind_group,
+            input,
+            pipeline,
+        }
+    }
+
+    fn create_input(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        dimensions: &[u32; 2],
+    ) -> Texture {
+        Texture::create_render_target(
+            device,
+            dimensions[0],
+            dimensions[1],
+            format,
+            "Sharpen Input Texture",
+        )
+    }
+
+    fn create_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        uniform_buffer: &wgpu::Buffer,
+        input: &Texture,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Sharpen Bind Group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&input.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+            ],
+        })
+    }
+
+    pub fn resize(
+        &mut self,
+        device: &wgpu::Device,
+        output_format: wgpu::TextureFormat,
+        dimensions: &[u32; 2],
+    ) {
+        self.input = Self::create_input(device, output_format, dimensions);
+        self.bind_group = Self::create_bind_group(
+            device,
+            &self.bind_group_layout,
+            &self.uniform_buffer,
+            &self.input,
+        );
+    }
+
+    pub fn update(&mut self, queue: &wgpu::Queue, settings: &Settings) {
+        let sharpen = &settings.sharpen;
+        self.enabled = sharpen.mode != SharpenMode::None && sharpen.strength > 0.0;
+        let uniform = SharpenUniform {
+            mode: match sharpen.mode {
+                SharpenMode::None => 0,
+                SharpenMode::Unsharp => 1,
+                SharpenMode::ContrastAdaptive => 2,
+            },
+            strength: sharpen.strength,
+            _padding: [0; 2],
+        };
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[uniform]));
+    }
+
+    // Where the tone mapped image should be drawn this frame
+    pub fn target<'a>(&'a self, output: &'a wgpu::TextureView) -> &'a wgpu::TextureView {
+        if self.enabled {
+            &self.input.view
+        } else {
+            output
+        }
+    }
+
+    pub fn render(&self, encoder: &mut wgpu::CommandEncoder, output: &wgpu::TextureView) {
+        if !self.enabled {
+            return;
+        }
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Sharpen Pass"),
+            color_attachments: &[wgpu::RenderPassColorAttachment {
+                view: output,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: true,
+                },
+            }],
+            depth_stencil_attachment: None,
+        });
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
@@ -0,0 +1,218 @@
+use nalgebra_glm as glm;
+
+use crate::{
+    lines::{LineJoin, LineSpace, PolylineDesc},
+    model::ModelDesc,
+    scene::{LabelHandle, ModelHandle, PolylineHandle, Scene},
+    settings::Settings,
+    text::LabelDesc,
+};
+
+const BONE_COLOR: glm::Vec4 = glm::Vec4::new(0.9, 0.75, 0.3, 1.0);
+const HIGHLIGHT_COLOR: glm::Vec4 = glm::Vec4::new(0.2, 0.8, 1.0, 1.0);
+// Joints without bones to size them by, such as a lone root, are marked this big
+const DEFAULT_JOINT_SIZE: f32 = 0.05;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct JointHandle {
+    pub model: ModelHandle,
+    pub skeleton: usize,
+    pub joint: usize,
+}
+
+// A line drawn for a skeleton, before it's written into one of the scene's polylines
+struct Outline {
+    points: Vec<glm::Vec3>,
+    closed: bool,
+    highlighted: bool,
+}
+
+// Draws the skeletons of models as octahedral bones from each joint to its children, with the
+// joint names as labels. The polylines and labels are kept in the scene and rewritten every
+// frame, emptied when there's less to draw
+#[derive(Default)]
+pub struct SkeletonSystem {
+    polylines: Vec<PolylineHandle>,
+    labels: Vec<LabelHandle>,
+}
+
+impl SkeletonSystem {
+    pub fn update(
+        &mut self,
+        scene: &mut Scene,
+        settings: &Settings,
+        highlighted: Option<JointHandle>,
+    ) {
+        let mut outlines = Vec::new();
+        let mut labels = Vec::new();
+        if settings.skeletons {
+            for (index, model) in scene.models.iter().enumerate() {
+                skeleton_outlines(
+                    model,
+                    ModelHandle(index),
+                    highlighted,
+                    &mut outlines,
+                    settings.joint_names.then_some(&mut labels),
+                );
+            }
+        }
+
+        while self.polylines.len() < outlines.len() {
+            self.polylines
+                .push(scene.spawn_polyline(PolylineDesc::default()));
+        }
+        for (index, handle) in self.polylines.iter().enumerate() {
+            let polyline = match scene.polylines.get_mut(handle.0) {
+                Some(polyline) => polyline,
+                None => continue,
+            };
+            *polyline = match outlines.get_mut(index) {
+                Some(outline) => PolylineDesc {
+                    points: std::mem::take(&mut outline.points),
+                    space: LineSpace::World,
+                    width: if outline.highlighted { 3.0 } else { 1.5 },
+                    color: if outline.highlighted {
+                        HIGHLIGHT_COLOR
+                    } else {
+                        BONE_COLOR
+                    },
+                    join: LineJoin::Miter,
+                    dashes: None,
+                    closed: outline.closed,
+                    depth_test: false,
+                },
+                None => PolylineDesc::default(),
+            };
+        }
+
+        while self.labels.len() < labels.len() {
+            self.labels.push(scene.spawn_label(LabelDesc::default()));
+        }
+        for (index, handle) in self.labels.iter().enumerate() {
+            if let Some(label) = scene.labels.get_mut(handle.0) {
+                *label = labels
+                    .get_mut(index)
+                    .map(std::mem::take)
+                    .unwrap_or_default();
+            }
+        }
+    }
+}
+
+fn skeleton_outlines(
+    model: &ModelDesc,
+    handle: ModelHandle,
+    highlighted: Option<JointHandle>,
+    outlines: &mut Vec<Outline>,
+    mut labels: Option<&mut Vec<LabelDesc>>,
+) {
+    for (skeleton_index, skeleton) in model.skeletons.iter().enumerate() {
+        let positions = skeleton
+            .joints
+            .iter()
+            .map(|joint| {
+                (model.transform.cast::<f32>() * joint.transform)
+                    .column(3)
+                    .xyz()
+            })
+            .collect::<Vec<_>>();
+        let is_highlighted = |joint: usize| {
+            highlighted
+                == Some(JointHandle {
+                    model: handle,
+                    skeleton: skeleton_index,
+                    joint,
+                })
+        };
+
+        // Joints are sized by the bone leading to them, or the first leading away for roots
+        let mut sizes = vec![None; positions.len()];
+        for (index, joint) in skeleton.joints.iter().enumerate() {
+            let parent = match joint.parent.filter(|parent| *parent < positions.len()) {
+                Some(parent) => parent,
+                None => continue,
+            };
+            let length = glm::distance(&positions[parent], &positions[index]);
+            sizes[index] = Some(length);
+            sizes[parent].get_or_insert(length);
+            bone_outlines(
+                &positions[parent],
+                &positions[index],
+                is_highlighted(index) || is_highlighted(parent),
+                outlines,
+            );
+        }
+
+        for (index, joint) in skeleton.joints.iter().enumerate() {
+            let size = sizes[index]
+                .filter(|size| *size > f32::EPSILON)
+                .unwrap_or(DEFAULT_JOINT_SIZE);
+            if is_highlighted(index) {
+                joint_cross(&positions[index], size * 0.2, outlines);
+            }
+            if let Some(labels) = labels.as_mut() {
+                labels.push(LabelDesc {
+                    text: joint
+                        .name
+                        .clone()
+                        .unwrap_or_else(|| format!("Joint {}", index)),
+                    position: positions[index],
+                    size: size * 0.25,
+                    color: if is_highlighted(index) {
+                        HIGHLIGHT_COLOR
+                    } else {
+                        glm::vec4(1.0, 1.0, 1.0, 1.0)
+                    },
+                    depth_test: false,
+                });
+            }
+        }
+    }
+}
+
+// An octahedron from the parent joint to the child, widest a little way along the bone so the
+// direction it points is clear
+fn bone_outlines(
+    start: &glm::Vec3,
+    end: &glm::Vec3,
+    highlighted: bool,
+    outlines: &mut Vec<Outline>,
+) {
+    let bone = end - start;
+    let length = bone.magnitude();
+    if length <= f32::EPSILON {
+        return;
+    }
+    let axis = bone / length;
+    let reference = if axis.y.abs() < 0.9 {
+        glm::Vec3::y()
+    } else {
+        glm::Vec3::x()
+    };
+    let side = axis.cross(&reference).normalize() * length * 0.1;
+    let up = axis.cross(&side);
+    let center = start + axis * length * 0.15;
+    let ring = [center + side, center + up, center - side, center - up];
+    outlines.push(Outline {
+        points: ring.to_vec(),
+        closed: true,
+        highlighted,
+    });
+    for corner in ring {
+        outlines.push(Outline {
+            points: vec![*start, corner, *end],
+            closed: false,
+            highlighted,
+        });
+    }
+}
+
+fn joint_cross(position: &glm::Vec3, size: f32, outlines: &mut Vec<Outline>) {
+    for axis in [glm::Vec3::x(), glm::Vec3::y(), glm::Vec3::z()] {
+        outlines.push(Outline {
+            points: vec![position - axis * size, position + axis * size],
+            closed: false,
+            highlighted: true,
+        });
+    }
+}
@@ -0,0 +1,364 @@
+use nalgebra_glm as glm;
+
+use crate::{
+    lighting::DirectionalLight,
+    memory::{self, MemoryCategory, Tracked},
+    settings::SkySettings,
+    shader_snippets,
+    texture::DepthConfig,
+};
+
+// Sky luminance in thousands of nits is scaled by this, which keeps the clear sky at noon about a
+// quarter as bright as a white surface lit by the sun at an intensity of one
+const SKY_SCALE: f32 = 1.0 / 32.0;
+// The sun's disc is drawn far dimmer than the real sun, which would swamp bloom
+const SUN_DISC_SCALE: f32 = 20.0;
+const SUN_ANGULAR_RADIUS: f32 = 0.0047;
+// Optical depth of the atmosphere straight up for red, green and blue light, from air molecules
+// and from haze, which grows with turbidity
+const RAYLEIGH_DEPTH: [f32; 3] = [0.037, 0.098, 0.24];
+const HAZE_DEPTH: [f32; 3] = [0.0225, 0.03, 0.0414];
+// Clouds drift along this direction
+const WIND_DIRECTION: [f32; 2] = [0.96, 0.28];
+
+// The direction towards the sun, with north along -Z and east along +X
+pub fn sun_direction(settings: &SkySettings) -> glm::Vec3 {
+    let latitude = settings.latitude.clamp(-90.0, 90.0).to_radians();
+    let day = settings.day_of_year as f32;
+    let declination =
+        (-23.44_f32).to_radians() * (2.0 * std::f32::consts::PI * (day + 10.0) / 365.0).cos();
+    let hour_angle = (15.0 * (settings.time_of_day - 12.0)).to_radians();
+
+    let sin_elevation =
+        latitude.sin() * declination.sin() + latitude.cos() * declination.cos() * hour_angle.cos();
+    let elevation = sin_elevation.clamp(-1.0, 1.0).asin();
+    let cos_azimuth = ((declination.sin() - sin_elevation * latitude.sin())
+        / (elevation.cos() * latitude.cos()).max(f32::EPSILON))
+    .clamp(-1.0, 1.0);
+    // Measured from north, the sun is in the west after noon
+    let mut azimuth = cos_azimuth.acos();
+    if hour_angle > 0.0 {
+        azimuth = 2.0 * std::f32::consts::PI - azimuth;
+    }
+    glm::vec3(
+        azimuth.sin() * elevation.cos(),
+        elevation.sin(),
+        -azimuth.cos() * elevation.cos(),
+    )
+}
+
+// Fraction of each color of sunlight that makes it through the atmosphere, using the air mass
+// approximation by Kasten and Young
+fn transmittance(elevation: f32, turbidity: f32) -> glm::Vec3 {
+    let zenith = (90.0 - elevation.to_degrees()).clamp(0.0, 90.0);
+    let air_mass = 1.0 / (zenith.to_radians().cos() + 0.50572 * (96.07995 - zenith).powf(-1.6364));
+    let haze = (turbidity - 1.0).max(0.0);
+    glm::vec3(
+        (-(RAYLEIGH_DEPTH[0] + HAZE_DEPTH[0] * haze) * air_mass).exp(),
+        (-(RAYLEIGH_DEPTH[1] + HAZE_DEPTH[1] * haze) * air_mass).exp(),
+        (-(RAYLEIGH_DEPTH[2] + HAZE_DEPTH[2] * haze) * air_mass).exp(),
+    )
+}
+
+fn luminance(color: &glm::Vec3) -> f32 {
+    color.dot(&glm::vec3(0.2126, 0.7152, 0.0722))
+}
+
+// Points the sun along the time of day, reddened and dimmed by the air it shines through as it
+// sets, and gone once it's below the horizon
+pub fn update_sun(sun: &mut DirectionalLight, settings: &SkySettings) {
+    let direction = sun_direction(settings);
+    let elevation = direction.y.clamp(-1.0, 1.0).asin();
+    let transmitted = transmittance(elevation, settings.turbidity);
+    let overhead = transmittance(std::f32::consts::FRAC_PI_2, settings.turbidity);
+    let horizon = smoothstep(-0.02, 0.03, elevation);
+
+    sun.direction = -direction;
+    sun.color = transmitted / transmitted.max().max(f32::EPSILON);
+    sun.temperature = None;
+    sun.intensity = settings.sun_intensity * horizon * luminance(&transmitted)
+        / luminance(&overhead).max(f32::EPSILON);
+}
+
+fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+// Coefficients of the Perez sky model for luminance and the two chromaticities, from Preetham
+// et al. 1999
+fn perez_coefficients(turbidity: f32) -> [glm::Vec3; 5] {
+    let t = turbidity;
+    [
+        glm::vec3(
+            0.1787 * t - 1.4630,
+            -0.0193 * t - 0.2592,
+            -0.0167 * t - 0.2608,
+        ),
+        glm::vec3(
+            -0.3554 * t + 0.4275,
+            -0.0665 * t + 0.0008,
+            -0.0950 * t + 0.0092,
+        ),
+        glm::vec3(
+            -0.0227 * t + 5.3251,
+            -0.0004 * t + 0.2125,
+            -0.0079 * t + 0.2102,
+        ),
+        glm::vec3(
+            0.1206 * t - 2.5771,
+            -0.0641 * t - 0.8989,
+            -0.0441 * t - 1.6537,
+        ),
+        glm::vec3(
+            -0.0670 * t + 0.3703,
+            -0.0033 * t + 0.0452,
+            -0.0109 * t + 0.0529,
+        ),
+    ]
+}
+
+fn perez(coefficients: &[glm::Vec3; 5], theta: f32, gamma: f32) -> glm::Vec3 {
+    let [a, b, c, d, e] = coefficients;
+    let channel = |index: usize| {
+        (1.0 + a[index] * (b[index] / theta.cos().max(0.01)).exp())
+            * (1.0 + c[index] * (d[index] * gamma).exp() + e[index] * gamma.cos().powi(2))
+    };
+    glm::vec3(channel(0), channel(1), channel(2))
+}
+
+// Luminance in thousands of nits and chromaticity straight up
+fn zenith(turbidity: f32, sun_theta: f32) -> glm::Vec3 {
+    let t = turbidity;
+    let chi = (4.0 / 9.0 - t / 120.0) * (std::f32::consts::PI - 2.0 * sun_theta);
+    let luminance = (4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192;
+    let (theta, theta2, theta3) = (sun_theta, sun_theta.powi(2), sun_theta.powi(3));
+    let x = t * t * (0.00166 * theta3 - 0.00375 * theta2 + 0.00209 * theta)
+        + t * (-0.02903 * theta3 + 0.06377 * theta2 - 0.03202 * theta + 0.00394)
+        + (0.11693 * theta3 - 0.21196 * theta2 + 0.06052 * theta + 0.25886);
+    let y = t * t * (0.00275 * theta3 - 0.00610 * theta2 + 0.00317 * theta)
+        + t * (-0.04214 * theta3 + 0.08970 * theta2 - 0.04153 * theta + 0.00516)
+        + (0.15346 * theta3 - 0.26756 * theta2 + 0.06670 * theta + 0.26688);
+    glm::vec3(luminance.max(0.0), x, y)
+}
+
+#[repr(C)]
+#[derive(Default, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct SkyUniform {
+    // xyz: towards the sun, w: cosine of the disc's angular radius
+    sun_direction: [f32; 4],
+    sun_color: [f32; 4],
+    // The Perez coefficients A to E, xyz: for luminance and the x and y chromaticities
+    perez: [[f32; 4]; 5],
+    // xyz: luminance and chromaticities straight up divided by the Perez function there, w: scale
+    // into scene color that fades out at night
+    zenith: [f32; 4],
+    // Light from the sun reaching the clouds
+    sun_light: [f32; 4],
+    // x: coverage, yz: how far the clouds have drifted
+    clouds: [f32; 4],
+}
+
+impl SkyUniform {
+    fn new(settings: &SkySettings, sun: &DirectionalLight, cloud_offset: &glm::Vec2) -> Self {
+        let direction = sun_direction(settings);
+        let elevation = direction.y.clamp(-1.0, 1.0).asin();
+        // The model only holds while the sun is above the horizon, and the sky darkens through
+        // twilight after it sets
+        let sun_theta = (std::f32::consts::FRAC_PI_2 - elevation).min(1.55);
+        let night = smoothstep(-0.1, 0.02, elevation);
+
+        let coefficients = perez_coefficients(settings.turbidity);
+        let zenith = zenith(settings.turbidity, sun_theta).component_div(&perez(
+            &coefficients,
+            0.0,
+            sun_theta,
+        ));
+        let mut perez = [[0.0; 4]; 5];
+        for (slot, coefficient) in perez.iter_mut().zip(coefficients.iter()) {
+            *slot = glm::vec3_to_vec4(coefficient).into();
+        }
+        let sun_color = sun.radiance() * SUN_DISC_SCALE;
+        Self {
+            sun_direction: [
+                direction.x,
+                direction.y,
+                direction.z,
+                SUN_ANGULAR_RADIUS.cos(),
+            ],
+            sun_color: glm::vec3_to_vec4(&sun_color).into(),
+            perez,
+            zenith: [
+                zenith.x,
+                zenith.y,
+                zenith.z,
+                SKY_SCALE * settings.sun_intensity * night,
+            ],
+            sun_light: glm::vec3_to_vec4(&sun.radiance()).into(),
+            clouds: [
+                settings.cloud_coverage.clamp(0.0, 1.0),
+                cloud_offset.x,
+                cloud_offset.y,
+                0.0,
+            ],
+        }
+    }
+}
+
+// Draws the sky wherever the depth prepass left the background, in the scene and in probes,
+// with a layer of drifting clouds
+pub struct SkySystem {
+    enabled: bool,
+    cloud_offset: glm::Vec2,
+    // Seconds since the probes were last told to capture the sky again
+    since_lighting: f32,
+    uniform_buffer: Tracked<wgpu::Buffer>,
+    bind_group: wgpu::BindGroup,
+    pipeline: wgpu::RenderPipeline,
+}
+
+impl SkySystem {
+    pub fn new(
+        device: &wgpu::Device,
+        camera_bind_group_layout: &wgpu::BindGroupLayout,
+        color_format: wgpu::TextureFormat,
+        depth: DepthConfig,
+    ) -> Self {
+        let uniform_buffer = memory::create_buffer(
+            device,
+            MemoryCategory::Transient,
+            &wgpu::BufferDescriptor {
+                label: Some("Sky Uniform Buffer"),
+                size: std::mem::size_of::<SkyUniform>() as wgpu::BufferAddress,
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            },
+        );
+
+        let bind_group_layout = memory::create_bind_group_layout(
+            device,
+            &wgpu::BindGroupLayoutDescriptor {
+                label: Some("Sky Bind Group Layout"),
+                entries: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                }],
+            },
+        );
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Sky Bind Group"),
+            layout: &bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: uniform_buffer.as_entire_binding(),
+            }],
+        });
+
+        let module = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("Sky Shader"),
+            source: wgpu::ShaderSource::Wgsl(
+                shader_snippets::splice(include_str!("shaders/sky.wgsl")).into(),
+            ),
+        });
+
+        let pipeline_layout = memory::create_pipeline_layout(
+            device,
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("Sky Pipeline Layout"),
+                bind_group_layouts: &[camera_bind_group_layout, &bind_group_layout],
+                push_constant_ranges: &[],
+            },
+        );
+
+        // Drawn on the far plane, so only the background the prepass left untouched passes
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Sky Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &module,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: depth.format,
+                depth_write_enabled: false,
+                depth_compare: depth.compare(wgpu::CompareFunction::LessEqual),
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: depth.multisample(),
+            fragment: Some(wgpu::FragmentState {
+                module: &module,
+                entry_point: "fs_main",
+                targets: &[wgpu::ColorTargetState {
+                    format: color_format,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                }],
+            }),
+        });
+
+        Self {
+            enabled: false,
+            cloud_offset: glm::Vec2::zeros(),
+            since_lighting: 0.0,
+            uniform_buffer,
+            bind_group,
+            pipeline,
+        }
+    }
+
+    // True when the reflection probes and irradiance volume are due to capture the sky again
+    pub fn update(
+        &mut self,
+        queue: &wgpu::Queue,
+        settings: &SkySettings,
+        sun: &DirectionalLight,
+        delta_time: f32,
+    ) -> bool {
+        self.enabled = settings.enabled;
+        if !self.enabled {
+            return false;
+        }
+        self.cloud_offset += glm::Vec2::from(WIND_DIRECTION) * settings.cloud_speed * delta_time;
+        // Kept small so the noise keeps its precision
+        self.cloud_offset = self.cloud_offset.map(|offset| offset.rem_euclid(256.0));
+        queue.write_buffer(
+            &self.uniform_buffer,
+            0,
+            bytemuck::cast_slice(&[SkyUniform::new(settings, sun, &self.cloud_offset)]),
+        );
+
+        if settings.lighting_interval <= 0.0 {
+            return false;
+        }
+        self.since_lighting += delta_time;
+        if self.since_lighting < settings.lighting_interval {
+            return false;
+        }
+        self.since_lighting = 0.0;
+        true
+    }
+
+    pub fn render<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        camera_bind_group: &'a wgpu::BindGroup,
+    ) {
+        if !self.enabled {
+            return;
+        }
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, camera_bind_group, &[]);
+        render_pass.set_bind_group(1, &self.bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
@@ -0,0 +1,548 @@
+use anyhow::{bail, Result};
+use image::GenericImageView;
+use nalgebra_glm as glm;
+use std::ops::Range;
+
+use crate::{
+    memory::{self, MemoryCategory, Tracked},
+    scene::Scene,
+    texture::Texture,
+};
+
+#[derive(Clone)]
+pub struct SpriteSheetDesc {
+    pub image: image::DynamicImage,
+    // Nearest filtering keeps pixel art crisp when scaled up
+    pub pixelated: bool,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct SpriteSheetHandle(pub usize);
+
+#[allow(dead_code)]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum SpriteMode {
+    // The region of the sheet is stretched over the whole sprite
+    Stretch,
+    // Corners keep their size while edges stretch along their length and the center fills the
+    // rest, the border is the left, top, right and bottom inset in texels of the sheet, drawn one
+    // pixel per texel
+    NineSlice { border: glm::Vec4 },
+    // The region of the sheet repeats every tile size in pixels, starting at the top left corner
+    Tiled { tile_size: glm::Vec2 },
+}
+
+// A textured quad drawn over the finished frame
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpriteDesc {
+    // Untextured sprites are filled with their tint
+    pub sheet: Option<SpriteSheetHandle>,
+    // Pixels from the top left corner of the window to the pivot, or world units of the 2D
+    // camera while in 2D mode
+    pub position: glm::Vec2,
+    // Size in pixels, or world units in 2D mode
+    pub size: glm::Vec2,
+    // The point the sprite is placed and rotated around, from its top left (0, 0) to bottom right (1, 1)
+    pub pivot: glm::Vec2,
+    // Radians, clockwise on screen
+    pub rotation: f32,
+    // Region of the sheet drawn, in texture coordinates from the top left corner
+    pub uv_min: glm::Vec2,
+    pub uv_max: glm::Vec2,
+    pub tint: glm::Vec4,
+    pub mode: SpriteMode,
+    // Sprites with a greater z are drawn over those with a lesser one, ties keep scene order
+    pub z: f32,
+    pub visible: bool,
+}
+
+impl Default for SpriteDesc {
+    fn default() -> Self {
+        Self {
+            sheet: None,
+            position: glm::Vec2::zeros(),
+            size: glm::vec2(1.0, 1.0),
+            pivot: glm::Vec2::zeros(),
+            rotation: 0.0,
+            uv_min: glm::Vec2::zeros(),
+            uv_max: glm::vec2(1.0, 1.0),
+            tint: glm::vec4(1.0, 1.0, 1.0, 1.0),
+            mode: SpriteMode::Stretch,
+            z: 0.0,
+            visible: true,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct SpriteVertex {
+    // Pixels from the top left corner of the window
+    position: [f32; 2],
+    uv: [f32; 2],
+    // Region of the sheet repeated across tiled sprites, as its corner and size, zero otherwise
+    tile: [f32; 4],
+    color: [f32; 4],
+}
+
+impl SpriteVertex {
+    const ATTRIBUTES: [wgpu::VertexAttribute; 4] = wgpu::vertex_attr_array![
+        0 => Float32x2,
+        1 => Float32x2,
+        2 => Float32x4,
+        3 => Float32x4
+    ];
+
+    fn layout<'a>() -> wgpu::VertexBufferLayout<'a> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<Self>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &Self::ATTRIBUTES,
+        }
+    }
+}
+
+// Part of a sprite in pixels from its top left corner, before it is pivoted and rotated
+struct SpritePiece {
+    min: glm::Vec2,
+    max: glm::Vec2,
+    uv_min: glm::Vec2,
+    uv_max: glm::Vec2,
+}
+
+// Splits a sprite into the pieces drawn for its mode, given the size of its sheet in texels
+fn sprite_pieces(desc: &SpriteDesc, sheet_dimensions: &glm::Vec2) -> Vec<SpritePiece> {
+    match desc.mode {
+        SpriteMode::Stretch => vec![SpritePiece {
+            min: glm::Vec2::zeros(),
+            max: desc.size,
+            uv_min: desc.uv_min,
+            uv_max: desc.uv_max,
+        }],
+        SpriteMode::Tiled { tile_size } => vec![SpritePiece {
+            min: glm::Vec2::zeros(),
+            max: desc.size,
+            uv_min: glm::Vec2::zeros(),
+            uv_max: desc.size.component_div(&tile_size),
+        }],
+        SpriteMode::NineSlice { border } => {
+            // Borders shrink evenly when the sprite is smaller than them
+            let fit = |size: f32, start: f32, end: f32| {
+                let total = start + end;
+                if total > size && total > 0.0 {
+                    size / total
+                } else {
+                    1.0
+                }
+            };
+            let fit = glm::vec2(
+                fit(desc.size.x, border.x, border.z),
+                fit(desc.size.y, border.y, border.w),
+            );
+            let edges = |size: f32, start: f32, end: f32, fit: f32| {
+                [0.0, start * fit, size - end * fit, size]
+            };
+            let uv_edges = |min: f32, max: f32, start: f32, end: f32, texels: f32| {
+                [min, min + start / texels, max - end / texels, max]
+            };
+            let x = edges(desc.size.x, border.x, border.z, fit.x);
+            let y = edges(desc.size.y, border.y, border.w, fit.y);
+            let u = uv_edges(
+                desc.uv_min.x,
+                desc.uv_max.x,
+                border.x,
+                border.z,
+                sheet_dimensions.x,
+            );
+            let v = uv_edges(
+                desc.uv_min.y,
+                desc.uv_max.y,
+                border.y,
+                border.w,
+                sheet_dimensions.y,
+            );
+            let mut pieces = Vec::with_capacity(9);
+            for row in 0..3 {
+                for column in 0..3 {
+                    if x[column + 1] <= x[column] || y[row + 1] <= y[row] {
+                        continue;
+                    }
+                    pieces.push(SpritePiece {
+                        min: glm::vec2(x[column], y[row]),
+                        max: glm::vec2(x[column + 1], y[row + 1]),
+                        uv_min: glm::vec2(u[column], v[row]),
+                        uv_max: glm::vec2(u[column + 1], v[row + 1]),
+                    });
+                }
+            }
+            pieces
+        }
+    }
+}
+
+// Two triangles for each piece of the sprite
+fn sprite_vertices(desc: &SpriteDesc, sheet_dimensions: &glm::Vec2) -> Vec<SpriteVertex> {
+    let (sin, cos) = desc.rotation.sin_cos();
+    let pivot = desc.pivot.component_mul(&desc.size);
+    let tile = match desc.mode {
+        SpriteMode::Tiled { .. } => [
+            desc.uv_min.x,
+            desc.uv_min.y,
+            desc.uv_max.x - desc.uv_min.x,
+            desc.uv_max.y - desc.uv_min.y,
+        ],
+        _ => [0.0; 4],
+    };
+    let mut vertices = Vec::new();
+    for piece in sprite_pieces(desc, sheet_dimensions) {
+        let corner = |x: bool, y: bool| {
+            let local = glm::vec2(
+                if x { piece.max.x } else { piece.min.x },
+                if y { piece.max.y } else { piece.min.y },
+            ) - pivot;
+            let rotated = glm::vec2(local.x * cos - local.y * sin, local.x * sin + local.y * cos);
+            SpriteVertex {
+                position: (desc.position + rotated).into(),
+                uv: [
+                    if x { piece.uv_max.x } else { piece.uv_min.x },
+                    if y { piece.uv_max.y } else { piece.uv_min.y },
+                ],
+                tile,
+                color: desc.tint.into(),
+            }
+        };
+        let corners = [
+            corner(false, false),
+            corner(true, false),
+            corner(true, true),
+            corner(false, true),
+        ];
+        vertices.extend([0, 1, 2, 0, 2, 3].map(|index| corners[index]));
+    }
+    vertices
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct SpriteUniform {
+    projection: [[f32; 4]; 4],
+}
+
+// Maps pixels with the origin at the top left corner and y pointing down to clip space
+pub fn screen_projection(dimensions: &[u32; 2]) -> glm::Mat4 {
+    glm::ortho(
+        0.0,
+        dimensions[0] as f32,
+        dimensions[1] as f32,
+        0.0,
+        -1.0,
+        1.0,
+    )
+}
+
+struct GpuSpriteSheet {
+    bind_group: wgpu::BindGroup,
+    dimensions: glm::Vec2,
+}
+
+// Consecutive sprites sharing a sheet are drawn together
+struct SpriteBatch {
+    // None draws with the blank sheet
+    sheet: Option<usize>,
+    vertices: Range<u32>,
+}
+
+pub struct SpriteSystem {
+    uniform_buffer: Tracked<wgpu::Buffer>,
+    uniform_bind_group: wgpu::BindGroup,
+    sheet_bind_group_layout: wgpu::BindGroupLayout,
+    linear_sampler: wgpu::Sampler,
+    nearest_sampler: wgpu::Sampler,
+    blank_sheet: GpuSpriteSheet,
+    sheets: Vec<GpuSpriteSheet>,
+    pipeline: wgpu::RenderPipeline,
+    vertex_buffer: Tracked<wgpu::Buffer>,
+    vertex_capacity: usize,
+    batches: Vec<SpriteBatch>,
+}
+
+impl SpriteSystem {
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        output_format: wgpu::TextureFormat,
+    ) -> Result<Self> {
+        let uniform_buffer = memory::create_buffer(
+            device,
+            MemoryCategory::Transient,
+            &wgpu::BufferDescriptor {
+                label: Some("Sprite Uniform Buffer"),
+                size: std::mem::size_of::<SpriteUniform>() as wgpu::BufferAddress,
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            },
+        );
+
+        let uniform_bind_group_layout = memory::create_bind_group_layout(
+            device,
+            &wgpu::BindGroupLayoutDescriptor {
+                label: Some("Sprite Uniform Bind Group Layout"),
+                entries: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                }],
+            },
+        );
+
+        let uniform_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Sprite Uniform Bind Group"),
+            layout: &uniform_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: uniform_buffer.as_entire_binding(),
+            }],
+        });
+
+        let sheet_bind_group_layout = memory::create_bind_group_layout(
+            device,
+            &wgpu::BindGroupLayoutDescriptor {
+                label: Some("Sprite Sheet Bind Group Layout"),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Sampler {
+                            filtering: true,
+                            comparison: false,
+                        },
+                        count: None,
+                    },
+                ],
+            },
+        );
+
+        let linear_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Sprite Sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        let nearest_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Pixelated Sprite Sampler"),
+            ..Default::default()
+        });
+
+        let blank = image::DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(
+            1,
+            1,
+            image::Rgba([255, 255, 255, 255]),
+        ));
+        let blank_texture = Texture::from_image(device, queue, &blank, Some("Blank Sprite Sheet"))?;
+        let blank_sheet = GpuSpriteSheet {
+            bind_group: Self::create_sheet_bind_group(
+                device,
+                &sheet_bind_group_layout,
+                &blank_texture,
+                &nearest_sampler,
+            ),
+            dimensions: glm::vec2(1.0, 1.0),
+        };
+
+        let module = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("Sprite Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("shaders/sprite.wgsl").into()),
+        });
+
+        let pipeline_layout = memory::create_pipeline_layout(
+            device,
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("Sprite Pipeline Layout"),
+                bind_group_layouts: &[&uniform_bind_group_layout, &sheet_bind_group_layout],
+                push_constant_ranges: &[],
+            },
+        );
+
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Sprite Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &module,
+                entry_point: "vs_main",
+                buffers: &[SpriteVertex::layout()],
+            },
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            fragment: Some(wgpu::FragmentState {
+                module: &module,
+                entry_point: "fs_main",
+                targets: &[wgpu::ColorTargetState {
+                    format: output_format,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                }],
+            }),
+        });
+
+        Ok(Self {
+            uniform_buffer,
+            uniform_bind_group,
+            sheet_bind_group_layout,
+            linear_sampler,
+            nearest_sampler,
+            blank_sheet,
+            sheets: Vec::new(),
+            pipeline,
+            vertex_buffer: Self::create_vertex_buffer(device, 0),
+            vertex_capacity: 0,
+            batches: Vec::new(),
+        })
+    }
+
+    fn create_sheet_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        texture: &Texture,
+        sampler: &wgpu::Sampler,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Sprite Sheet Bind Group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&texture.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(sampler),
+                },
+            ],
+        })
+    }
+
+    fn create_vertex_buffer(device: &wgpu::Device, capacity: usize) -> Tracked<wgpu::Buffer> {
+        memory::create_buffer(
+            device,
+            MemoryCategory::Meshes,
+            &wgpu::BufferDescriptor {
+                label: Some("Sprite Vertex Buffer"),
+                size: (capacity.max(1) * std::mem::size_of::<SpriteVertex>())
+                    as wgpu::BufferAddress,
+                usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            },
+        )
+    }
+
+    pub fn update(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        scene: &Scene,
+        projection: &glm::Mat4,
+    ) -> Result<()> {
+        while self.sheets.len() < scene.sprite_sheets.len() {
+            let desc = &scene.sprite_sheets[self.sheets.len()];
+            let (width, height) = desc.image.dimensions();
+            let dimensions = glm::vec2(width as f32, height as f32);
+            let texture = Texture::from_image(device, queue, &desc.image, Some("Sprite Sheet"))?;
+            let sampler = if desc.pixelated {
+                &self.nearest_sampler
+            } else {
+                &self.linear_sampler
+            };
+            let bind_group = Self::create_sheet_bind_group(
+                device,
+                &self.sheet_bind_group_layout,
+                &texture,
+                sampler,
+            );
+            self.sheets.push(GpuSpriteSheet {
+                bind_group,
+                dimensions,
+            });
+        }
+
+        queue.write_buffer(
+            &self.uniform_buffer,
+            0,
+            bytemuck::cast_slice(&[SpriteUniform {
+                projection: (*projection).into(),
+            }]),
+        );
+
+        let mut sprites = scene
+            .sprites
+            .iter()
+            .filter(|sprite| sprite.visible)
+            .collect::<Vec<_>>();
+        sprites.sort_by(|a, b| a.z.total_cmp(&b.z));
+
+        self.batches.clear();
+        let mut vertices = Vec::with_capacity(sprites.len() * 6);
+        for sprite in sprites {
+            let sheet = sprite.sheet.map(|handle| handle.0);
+            let dimensions = match sheet {
+                Some(sheet) => match self.sheets.get(sheet) {
+                    Some(gpu_sheet) => gpu_sheet.dimensions,
+                    None => bail!("Sprite refers to missing sprite sheet {}!", sheet),
+                },
+                None => self.blank_sheet.dimensions,
+            };
+            let start = vertices.len() as u32;
+            vertices.extend(sprite_vertices(sprite, &dimensions));
+            let end = vertices.len() as u32;
+            match self.batches.last_mut() {
+                Some(batch) if batch.sheet == sheet => batch.vertices.end = end,
+                _ => self.batches.push(SpriteBatch {
+                    sheet,
+                    vertices: start..end,
+                }),
+            }
+        }
+
+        if vertices.len() > self.vertex_capacity {
+            self.vertex_capacity = vertices.len().next_power_of_two();
+            self.vertex_buffer = Self::create_vertex_buffer(device, self.vertex_capacity);
+        }
+        if !vertices.is_empty() {
+            queue.write_buffer(&self.vertex_buffer, 0, bytemuck::cast_slice(&vertices));
+        }
+
+        Ok(())
+    }
+
+    pub fn render<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        if self.batches.is_empty() {
+            return;
+        }
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &self.uniform_bind_group, &[]);
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        for batch in self.batches.iter() {
+            let sheet = match batch.sheet {
+                Some(sheet) => &self.sheets[sheet],
+                None => &self.blank_sheet,
+            };
+            render_pass.set_bind_group(1, &sheet.bind_group, &[]);
+            render_pass.draw(batch.vertices.clone(), 0..1);
+        }
+    }
+}
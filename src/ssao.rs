@@ -0,0 +1,404 @@
+use std::rc::Rc;
+
+use crate::{
+    blue_noise::BlueNoise,
+    memory::{self, MemoryCategory, Tracked},
+    render_targets::{FramePhase, RenderTargetPool},
+    settings::Settings,
+    shader_snippets,
+    texture::Texture,
+};
+
+pub const AMBIENT_OCCLUSION_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::R8Unorm;
+
+#[repr(C)]
+#[derive(Default, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct SsaoUniform {
+    radius: f32,
+    bias: f32,
+    intensity: f32,
+    // Zero when screen space ambient occlusion is disabled
+    samples: u32,
+}
+
+impl SsaoUniform {
+    fn new(settings: &Settings) -> Self {
+        let ssao = &settings.ssao;
+        Self {
+            radius: ssao.radius,
+            bias: ssao.bias,
+            intensity: ssao.intensity,
+            samples: if ssao.enabled {
+                ssao.samples.min(settings.effect_quality.max_ssao_samples())
+            } else {
+                0
+            },
+        }
+    }
+}
+
+// Estimates how much nearby geometry occludes ambient light from the depth prepass
+pub struct SsaoSystem {
+    uniform_buffer: Tracked<wgpu::Buffer>,
+    enabled: bool,
+    occlusion: Rc<Texture>,
+    blurred: Rc<Texture>,
+    bind_group_layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+    blur_bind_group_layout: wgpu::BindGroupLayout,
+    blur_bind_group: wgpu::BindGroup,
+    output_bind_group_layout: wgpu::BindGroupLayout,
+    output_bind_group: wgpu::BindGroup,
+    pipeline: wgpu::RenderPipeline,
+    blur_pipeline: wgpu::RenderPipeline,
+}
+
+impl SsaoSystem {
+    pub fn new(
+        device: &wgpu::Device,
+        target_pool: &mut RenderTargetPool,
+        camera_bind_group_layout: &wgpu::BindGroupLayout,
+        depth_texture: &Texture,
+        blue_noise: &BlueNoise,
+        dimensions: &[u32; 2],
+    ) -> Self {
+        let uniform_buffer = memory::create_buffer(
+            device,
+            MemoryCategory::Transient,
+            &wgpu::BufferDescriptor {
+                label: Some("SSAO Uniform Buffer"),
+                size: std::mem::size_of::<SsaoUniform>() as wgpu::BufferAddress,
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            },
+        );
+
+        let bind_group_layout = memory::create_bind_group_layout(
+            device,
+            &wgpu::BindGroupLayoutDescriptor {
+                label: Some("SSAO Bind Group Layout"),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Depth,
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 2,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                ],
+            },
+        );
+
+        let occlusion_texture_layout = |label| {
+            memory::create_bind_group_layout(
+                device,
+                &wgpu::BindGroupLayoutDescriptor {
+                    label: Some(label),
+                    entries: &[wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            multisampled: false,
+                        },
+                        count: None,
+                    }],
+                },
+            )
+        };
+        let blur_bind_group_layout = occlusion_texture_layout("SSAO Blur Bind Group Layout");
+        let output_bind_group_layout =
+            occlusion_texture_layout("Ambient Occlusion Bind Group Layout");
+
+        let module = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("SSAO Shader"),
+            source: wgpu::ShaderSource::Wgsl(
+                shader_snippets::splice(include_str!("shaders/ssao.wgsl")).into(),
+            ),
+        });
+
+        let pipeline_layout = memory::create_pipeline_layout(
+            device,
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("SSAO Pipeline Layout"),
+                bind_group_layouts: &[camera_bind_group_layout, &bind_group_layout],
+                push_constant_ranges: &[],
+            },
+        );
+
+        let pipeline = Self::create_pipeline(device, "SSAO Pipeline", &pipeline_layout, &module);
+
+        let blur_module = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("SSAO Blur Shader"),
+            source: wgpu::ShaderSource::Wgsl(
+                shader_snippets::splice(include_str!("shaders/ssao_blur.wgsl")).into(),
+            ),
+        });
+
+        let blur_pipeline_layout = memory::create_pipeline_layout(
+            device,
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("SSAO Blur Pipeline Layout"),
+                bind_group_layouts: &[&blur_bind_group_layout],
+                push_constant_ranges: &[],
+            },
+        );
+
+        let blur_pipeline = Self::create_pipeline(
+            device,
+            "SSAO Blur Pipeline",
+            &blur_pipeline_layout,
+            &blur_module,
+        );
+
+        let (occlusion, blurred) = Self::create_targets(device, target_pool, dimensions);
+
+        let bind_group = Self::create_bind_group(
+            device,
+            &bind_group_layout,
+            &uniform_buffer,
+            depth_texture,
+            blue_noise,
+        );
+        let blur_bind_group =
+            Self::create_texture_bind_group(device, &blur_bind_group_layout, &occlusion);
+        let output_bind_group =
+            Self::create_texture_bind_group(device, &output_bind_group_layout, &blurred);
+
+        Self {
+            uniform_buffer,
+            enabled: false,
+            occlusion,
+            blurred,
+            bind_group_layout,
+            bind_group,
+            blur_bind_group_layout,
+            blur_bind_group,
+            output_bind_group_layout,
+            output_bind_group,
+            pipeline,
+            blur_pipeline,
+        }
+    }
+
+    fn create_pipeline(
+        device: &wgpu::Device,
+        label: &str,
+        layout: &wgpu::PipelineLayout,
+        module: &wgpu::ShaderModule,
+    ) -> wgpu::RenderPipeline {
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some(label),
+            layout: Some(layout),
+            vertex: wgpu::VertexState {
+                module,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            fragment: Some(wgpu::FragmentState {
+                module,
+                entry_point: "fs_main",
+                targets: &[wgpu::ColorTargetState {
+                    format: AMBIENT_OCCLUSION_FORMAT,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                }],
+            }),
+        })
+    }
+
+    // The blurred occlusion is also read by probe faces, which are drawn before it's updated
+    fn create_targets(
+        device: &wgpu::Device,
+        target_pool: &mut RenderTargetPool,
+        dimensions: &[u32; 2],
+    ) -> (Rc<Texture>, Rc<Texture>) {
+        let mut target = |label, lifetime| {
+            target_pool.acquire(
+                device,
+                dimensions[0],
+                dimensions[1],
+                AMBIENT_OCCLUSION_FORMAT,
+                label,
+                lifetime,
+            )
+        };
+        (
+            target(
+                "Ambient Occlusion Texture",
+                FramePhase::AmbientOcclusion..=FramePhase::AmbientOcclusion,
+            ),
+            target(
+                "Blurred Ambient Occlusion Texture",
+                FramePhase::Probes..=FramePhase::Scene,
+            ),
+        )
+    }
+
+    fn create_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        uniform_buffer: &wgpu::Buffer,
+        depth_texture: &Texture,
+        blue_noise: &BlueNoise,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("SSAO Bind Group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&depth_texture.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: blue_noise.buffer().as_entire_binding(),
+                },
+            ],
+        })
+    }
+
+    fn create_texture_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        texture: &Texture,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Ambient Occlusion Bind Group"),
+            layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&texture.view),
+            }],
+        })
+    }
+
+    // Bound by materials to read the blurred occlusion at their pixel
+    pub fn output_bind_group_layout(&self) -> &wgpu::BindGroupLayout {
+        &self.output_bind_group_layout
+    }
+
+    pub fn output_bind_group(&self) -> &wgpu::BindGroup {
+        &self.output_bind_group
+    }
+
+    // The blurred occlusion, for passes that bind it alongside other resources
+    pub fn texture(&self) -> &Texture {
+        &self.blurred
+    }
+
+    pub fn resize(
+        &mut self,
+        device: &wgpu::Device,
+        target_pool: &mut RenderTargetPool,
+        depth_texture: &Texture,
+        blue_noise: &BlueNoise,
+        dimensions: &[u32; 2],
+    ) {
+        let (occlusion, blurred) = Self::create_targets(device, target_pool, dimensions);
+        self.bind_group = Self::create_bind_group(
+            device,
+            &self.bind_group_layout,
+            &self.uniform_buffer,
+            depth_texture,
+            blue_noise,
+        );
+        self.blur_bind_group =
+            Self::create_texture_bind_group(device, &self.blur_bind_group_layout, &occlusion);
+        self.output_bind_group =
+            Self::create_texture_bind_group(device, &self.output_bind_group_layout, &blurred);
+        self.occlusion = occlusion;
+        self.blurred = blurred;
+    }
+
+    pub fn update(&mut self, queue: &wgpu::Queue, settings: &Settings) {
+        let uniform = SsaoUniform::new(settings);
+        self.enabled = uniform.samples > 0;
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[uniform]));
+    }
+
+    pub fn render(&self, encoder: &mut wgpu::CommandEncoder, camera_bind_group: &wgpu::BindGroup) {
+        // Clearing to white leaves surfaces unoccluded while disabled
+        if !self.enabled {
+            encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("SSAO Clear Pass"),
+                color_attachments: &[wgpu::RenderPassColorAttachment {
+                    view: &self.blurred.view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::WHITE),
+                        store: true,
+                    },
+                }],
+                depth_stencil_attachment: None,
+            });
+            return;
+        }
+
+        {
+            let mut ssao_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("SSAO Pass"),
+                color_attachments: &[wgpu::RenderPassColorAttachment {
+                    view: &self.occlusion.view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::WHITE),
+                        store: true,
+                    },
+                }],
+                depth_stencil_attachment: None,
+            });
+            ssao_pass.set_pipeline(&self.pipeline);
+            ssao_pass.set_bind_group(0, camera_bind_group, &[]);
+            ssao_pass.set_bind_group(1, &self.bind_group, &[]);
+            ssao_pass.draw(0..3, 0..1);
+        }
+
+        let mut blur_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("SSAO Blur Pass"),
+            color_attachments: &[wgpu::RenderPassColorAttachment {
+                view: &self.blurred.view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::WHITE),
+                    store: true,
+                },
+            }],
+            depth_stencil_attachment: None,
+        });
+        blur_pass.set_pipeline(&self.blur_pipeline);
+        blur_pass.set_bind_group(0, &self.blur_bind_group, &[]);
+        blur_pass.draw(0..3, 0..1);
+    }
+}
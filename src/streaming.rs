@@ -0,0 +1,233 @@
+use anyhow::{Context, Result};
+use instant::Instant;
+use std::{
+    io::{BufRead, BufReader, Write},
+    net::{TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        mpsc::{sync_channel, SyncSender, TrySendError},
+        Arc, Condvar, Mutex,
+    },
+    thread,
+    time::Duration,
+};
+
+const FRAME_INTERVAL: Duration = Duration::from_millis(1000 / 30);
+const JPEG_QUALITY: u8 = 80;
+const BOUNDARY: &str = "frame";
+
+const INDEX_PAGE: &str = r#"<!DOCTYPE html>
+<html>
+<head><title>Dragonglass Renderer</title></head>
+<body style="margin: 0; background: black; display: flex; justify-content: center;">
+<img src="/stream" style="max-width: 100%; max-height: 100vh;">
+</body>
+</html>
+"#;
+
+#[derive(Default)]
+struct LatestFrame {
+    id: u64,
+    jpeg: Arc<Vec<u8>>,
+}
+
+#[derive(Default)]
+struct SharedState {
+    frame: Mutex<LatestFrame>,
+    frame_available: Condvar,
+    viewers: AtomicUsize,
+    // Set from when a frame is asked for until it's read back, which waits while the window is
+    // minimized, see Capturing
+    capturing: AtomicBool,
+}
+
+// Clears the capture in progress once the frame arrives, or is dropped with the device that was
+// going to read it back
+struct Capturing(Arc<SharedState>);
+
+impl Drop for Capturing {
+    fn drop(&mut self) {
+        self.0.capturing.store(false, Ordering::Relaxed);
+    }
+}
+
+// Serves rendered frames to browsers as a motion JPEG stream
+//
+//   /           a page displaying the stream
+//   /stream     multipart JPEG stream
+//   /frame.jpg  the most recent frame
+pub struct FrameStreamer {
+    state: Arc<SharedState>,
+    frames: SyncSender<image::RgbaImage>,
+    last_capture: Instant,
+}
+
+impl FrameStreamer {
+    pub fn start(address: &str) -> Result<Self> {
+        let listener = TcpListener::bind(address)
+            .with_context(|| format!("Failed to bind the frame streamer to {}!", address))?;
+        println!("Streaming frames on http://{}", address);
+
+        let state = Arc::new(SharedState::default());
+
+        // Encoding happens off the main thread, frames arriving while it is busy are dropped
+        let (frames, pending_frames) = sync_channel::<image::RgbaImage>(1);
+        let encoder_state = state.clone();
+        thread::spawn(move || {
+            for frame in pending_frames {
+                match Self::encode(frame) {
+                    Ok(jpeg) => {
+                        let mut latest = encoder_state.frame.lock().unwrap();
+                        latest.id += 1;
+                        latest.jpeg = Arc::new(jpeg);
+                        encoder_state.frame_available.notify_all();
+                    }
+                    Err(error) => eprintln!("Failed to encode streamed frame: {}", error),
+                }
+            }
+        });
+
+        let server_state = state.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(error) => {
+                        eprintln!("Failed to accept stream connection: {}", error);
+                        continue;
+                    }
+                };
+                let state = server_state.clone();
+                thread::spawn(move || {
+                    // Viewers closing the page end the connection mid-stream
+                    let _ = Self::handle_connection(stream, &state);
+                });
+            }
+        });
+
+        Ok(Self {
+            state,
+            frames,
+            last_capture: Instant::now(),
+        })
+    }
+
+    // Frames are only captured while someone is watching
+    pub fn wants_frame(&self) -> bool {
+        self.state.viewers.load(Ordering::Relaxed) > 0
+            && self.last_capture.elapsed() >= FRAME_INTERVAL
+            && !self.state.capturing.load(Ordering::Relaxed)
+    }
+
+    // Handed the frame once it's read back, which failing only costs viewers that frame
+    pub fn capture(&mut self) -> impl FnOnce(Result<image::RgbaImage>) + 'static {
+        self.last_capture = Instant::now();
+        self.state.capturing.store(true, Ordering::Relaxed);
+        let capturing = Capturing(self.state.clone());
+        let frames = self.frames.clone();
+        move |result| {
+            drop(capturing);
+            match result {
+                Ok(frame) => {
+                    if let Err(TrySendError::Disconnected(_)) = frames.try_send(frame) {
+                        eprintln!("The frame encoder has stopped!");
+                    }
+                }
+                Err(error) => eprintln!("Failed to capture a streamed frame: {}", error),
+            }
+        }
+    }
+
+    fn encode(frame: image::RgbaImage) -> Result<Vec<u8>> {
+        let (width, height) = frame.dimensions();
+        let rgb = image::DynamicImage::ImageRgba8(frame).to_rgb8();
+        let mut jpeg = Vec::new();
+        image::codecs::jpeg::JpegEncoder::new_with_quality(&mut jpeg, JPEG_QUALITY).encode(
+            &rgb,
+            width,
+            height,
+            image::ColorType::Rgb8,
+        )?;
+        Ok(jpeg)
+    }
+
+    fn handle_connection(stream: TcpStream, state: &SharedState) -> Result<()> {
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let mut request_line = String::new();
+        reader.read_line(&mut request_line)?;
+
+        // The rest of the request headers are not needed
+        let mut header = String::new();
+        while reader.read_line(&mut header)? > 0 && !header.trim().is_empty() {
+            header.clear();
+        }
+
+        let path = request_line.split_whitespace().nth(1).unwrap_or("/");
+        let mut writer = stream;
+        match path {
+            "/" | "/index.html" => write!(
+                writer,
+                "HTTP/1.1 200 OK\r\nContent-Type: text/html\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                INDEX_PAGE.len(),
+                INDEX_PAGE
+            )?,
+            "/frame.jpg" => {
+                // Wake the capture loop and wait for a fresh frame
+                let current_id = state.frame.lock().unwrap().id;
+                state.viewers.fetch_add(1, Ordering::Relaxed);
+                let (_, jpeg) = Self::next_frame(state, current_id);
+                state.viewers.fetch_sub(1, Ordering::Relaxed);
+                write!(
+                    writer,
+                    "HTTP/1.1 200 OK\r\nContent-Type: image/jpeg\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    jpeg.len()
+                )?;
+                writer.write_all(&jpeg)?;
+            }
+            "/stream" => {
+                state.viewers.fetch_add(1, Ordering::Relaxed);
+                let result = Self::stream_frames(&mut writer, state);
+                state.viewers.fetch_sub(1, Ordering::Relaxed);
+                result?;
+            }
+            _ => write!(
+                writer,
+                "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+            )?,
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    fn stream_frames(writer: &mut TcpStream, state: &SharedState) -> Result<()> {
+        write!(
+            writer,
+            "HTTP/1.1 200 OK\r\nContent-Type: multipart/x-mixed-replace; boundary={}\r\nCache-Control: no-cache\r\nConnection: close\r\n\r\n",
+            BOUNDARY
+        )?;
+
+        let mut last_id = 0;
+        loop {
+            let (id, jpeg) = Self::next_frame(state, last_id);
+            last_id = id;
+            write!(
+                writer,
+                "--{}\r\nContent-Type: image/jpeg\r\nContent-Length: {}\r\n\r\n",
+                BOUNDARY,
+                jpeg.len()
+            )?;
+            writer.write_all(&jpeg)?;
+            writer.write_all(b"\r\n")?;
+            writer.flush()?;
+        }
+    }
+
+    // Blocks until a frame newer than the last one seen has been encoded
+    fn next_frame(state: &SharedState, last_id: u64) -> (u64, Arc<Vec<u8>>) {
+        let mut latest = state.frame.lock().unwrap();
+        while latest.id == last_id {
+            latest = state.frame_available.wait(latest).unwrap();
+        }
+        (latest.id, latest.jpeg.clone())
+    }
+}
@@ -0,0 +1,302 @@
+use nalgebra_glm as glm;
+
+use crate::memory::{self, MemoryCategory, Tracked};
+use crate::{shader_snippets, texture::Texture};
+
+// Frames before the jitter pattern repeats
+const JITTER_SAMPLES: u64 = 8;
+
+#[repr(C)]
+#[derive(Default, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct TemporalUpscaleUniform {
+    jitter: [f32; 4],
+}
+
+fn halton(mut index: u64, base: u64) -> f32 {
+    let mut fraction = 1.0;
+    let mut result = 0.0;
+    while index > 0 {
+        fraction /= base as f32;
+        result += fraction * (index % base) as f32;
+        index /= base;
+    }
+    result
+}
+
+// Sub pixel offset of the frame in pixels, spread evenly over the pixel by a Halton sequence
+pub fn jitter(frame_index: u64) -> glm::Vec2 {
+    let index = frame_index % JITTER_SAMPLES + 1;
+    glm::vec2(halton(index, 2) - 0.5, halton(index, 3) - 0.5)
+}
+
+// Reconstructs the output resolution from jittered frames rendered below it, accumulating them
+// in a history that follows the velocity buffer. At full resolution it doubles as antialiasing
+pub struct TemporalUpscaleSystem {
+    uniform_buffer: Tracked<wgpu::Buffer>,
+    enabled: bool,
+    // The history is only trusted once it holds a frame of the current size
+    history_valid: bool,
+    bind_group_layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+    sampler: wgpu::Sampler,
+    history: Texture,
+    output: Texture,
+    dimensions: [u32; 2],
+    pipeline: wgpu::RenderPipeline,
+}
+
+impl TemporalUpscaleSystem {
+    pub fn new(device: &wgpu::Device, scene_color: &Texture, velocity_texture: &Texture) -> Self {
+        let uniform_buffer = memory::create_buffer(
+            device,
+            MemoryCategory::Transient,
+            &wgpu::BufferDescriptor {
+                label: Some("Temporal Upscale Uniform Buffer"),
+                size: std::mem::size_of::<TemporalUpscaleUniform>() as wgpu::BufferAddress,
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            },
+        );
+
+        let texture_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        };
+        let bind_group_layout = memory::create_bind_group_layout(
+            device,
+            &wgpu::BindGroupLayoutDescriptor {
+                label: Some("Temporal Upscale Bind Group Layout"),
+                entries: &[
+                    texture_entry(0),
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Sampler {
+                            filtering: true,
+                            comparison: false,
+                        },
+                        count: None,
+                    },
+                    texture_entry(2),
+                    texture_entry(3),
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 4,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                ],
+            },
+        );
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Temporal Upscale Sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+
+        let module = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("Temporal Upscale Shader"),
+            source: wgpu::ShaderSource::Wgsl(
+                shader_snippets::splice(include_str!("shaders/temporal_upscale.wgsl")).into(),
+            ),
+        });
+
+        let pipeline_layout = memory::create_pipeline_layout(
+            device,
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("Temporal Upscale Pipeline Layout"),
+                bind_group_layouts: &[&bind_group_layout],
+                push_constant_ranges: &[],
+            },
+        );
+
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Temporal Upscale Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &module,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            fragment: Some(wgpu::FragmentState {
+                module: &module,
+                entry_point: "fs_main",
+                targets: &[wgpu::ColorTargetState {
+                    format: Texture::HDR_FORMAT,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                }],
+            }),
+        });
+
+        let history = Self::create_target(device, &[1, 1], "Temporal History Texture");
+        let output = Self::create_target(device, &[1, 1], "Temporal Upscale Output Texture");
+        let bind_group = Self::create_bind_group(
+            device,
+            &bind_group_layout,
+            &uniform_buffer,
+            &sampler,
+            scene_color,
+            velocity_texture,
+            &history,
+        );
+
+        Self {
+            uniform_buffer,
+            enabled: false,
+            history_valid: false,
+            bind_group_layout,
+            bind_group,
+            sampler,
+            history,
+            output,
+            dimensions: [1, 1],
+            pipeline,
+        }
+    }
+
+    fn create_target(device: &wgpu::Device, dimensions: &[u32; 2], label: &str) -> Texture {
+        Texture::create_render_target(
+            device,
+            dimensions[0],
+            dimensions[1],
+            Texture::HDR_FORMAT,
+            label,
+        )
+    }
+
+    fn create_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        uniform_buffer: &wgpu::Buffer,
+        sampler: &wgpu::Sampler,
+        scene_color: &Texture,
+        velocity_texture: &Texture,
+        history: &Texture,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Temporal Upscale Bind Group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&scene_color.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(&velocity_texture.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::TextureView(&history.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+            ],
+        })
+    }
+
+    pub fn resize(
+        &mut self,
+        device: &wgpu::Device,
+        scene_color: &Texture,
+        velocity_texture: &Texture,
+        output_dimensions: &[u32; 2],
+        enabled: bool,
+    ) {
+        self.enabled = enabled;
+        self.history_valid = false;
+        // Placeholders keep the targets from taking up memory while unused
+        let dimensions = if enabled { *output_dimensions } else { [1, 1] };
+        self.dimensions = dimensions;
+        self.history = Self::create_target(device, &dimensions, "Temporal History Texture");
+        self.output = Self::create_target(device, &dimensions, "Temporal Upscale Output Texture");
+        self.bind_group = Self::create_bind_group(
+            device,
+            &self.bind_group_layout,
+            &self.uniform_buffer,
+            &self.sampler,
+            scene_color,
+            velocity_texture,
+            &self.history,
+        );
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn texture(&self) -> &Texture {
+        &self.output
+    }
+
+    // Jitter is the offset the frame was rendered with, in pixels of the scene color
+    pub fn update(&mut self, queue: &wgpu::Queue, jitter: glm::Vec2) {
+        let reset = if self.history_valid { 0.0 } else { 1.0 };
+        let uniform = TemporalUpscaleUniform {
+            jitter: [jitter.x, jitter.y, reset, 0.0],
+        };
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[uniform]));
+    }
+
+    // Needs the velocity buffer, the result is kept as the next frame's history
+    pub fn render(&mut self, encoder: &mut wgpu::CommandEncoder) {
+        if !self.enabled {
+            return;
+        }
+
+        {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Temporal Upscale Pass"),
+                color_attachments: &[wgpu::RenderPassColorAttachment {
+                    view: &self.output.view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                        store: true,
+                    },
+                }],
+                depth_stencil_attachment: None,
+            });
+            render_pass.set_pipeline(&self.pipeline);
+            render_pass.set_bind_group(0, &self.bind_group, &[]);
+            render_pass.draw(0..3, 0..1);
+        }
+
+        encoder.copy_texture_to_texture(
+            self.output.texture.as_image_copy(),
+            self.history.texture.as_image_copy(),
+            wgpu::Extent3d {
+                width: self.dimensions[0],
+                height: self.dimensions[1],
+                depth_or_array_layers: 1,
+            },
+        );
+        self.history_valid = true;
+    }
+}
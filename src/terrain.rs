@@ -0,0 +1,779 @@
+use anyhow::{bail, Result};
+use nalgebra_glm as glm;
+
+use crate::{
+    bounds::{Aabb, Frustum},
+    camera::Camera,
+    memory::{self, MemoryCategory, Tracked},
+    scene::Scene,
+    settings::EffectQuality,
+    texture::{DepthConfig, Texture},
+};
+
+pub const MAX_TERRAIN_LAYERS: usize = 4;
+
+// Each level of detail halves the vertex density of the one before it
+const MAX_LOD_COUNT: u32 = 5;
+
+// A chunk drops to a coarser level of detail each time the distance
+// to the camera doubles past this multiple of the chunk's width
+const LOD_DISTANCE_FACTOR: f32 = 1.5;
+
+// Skirts hang below the chunk edges to hide cracks between neighboring levels of detail
+const SKIRT_DEPTH_FACTOR: f32 = 0.1;
+
+#[derive(Clone)]
+pub struct TerrainLayer {
+    pub albedo: image::DynamicImage,
+    // Number of times the albedo repeats across the whole terrain
+    pub tiling: f32,
+}
+
+#[derive(Clone)]
+pub struct TerrainDesc {
+    pub heightmap: image::DynamicImage,
+    // World space position of the corner of the terrain with the smallest X and Z. Chunks are
+    // built relative to it, so moving the terrain doesn't build them again
+    pub origin: glm::Vec3,
+    // World space extents along X and Z
+    pub size: glm::Vec2,
+    pub height_scale: f32,
+    // Quads along each side of a chunk at full detail
+    pub chunk_size: u32,
+    // Each channel holds the weight of the layer with the same index
+    pub splat_map: Option<image::DynamicImage>,
+    pub layers: Vec<TerrainLayer>,
+}
+
+impl Default for TerrainDesc {
+    fn default() -> Self {
+        Self {
+            heightmap: image::DynamicImage::ImageLuma8(image::GrayImage::new(2, 2)),
+            origin: glm::vec3(-50.0, 0.0, -50.0),
+            size: glm::vec2(100.0, 100.0),
+            height_scale: 10.0,
+            chunk_size: 32,
+            splat_map: None,
+            layers: Vec::new(),
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Default, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct TerrainVertex {
+    position: [f32; 3],
+    normal: [f32; 3],
+    uv: [f32; 2],
+}
+
+impl TerrainVertex {
+    const ATTRIBUTES: [wgpu::VertexAttribute; 3] =
+        wgpu::vertex_attr_array![0 => Float32x3, 1 => Float32x3, 2 => Float32x2];
+
+    fn layout<'a>() -> wgpu::VertexBufferLayout<'a> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<Self>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &Self::ATTRIBUTES,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Default, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct TerrainUniform {
+    layer_tiling: [f32; 4],
+    layer_count: u32,
+    _padding: [u32; 3],
+}
+
+impl TerrainUniform {
+    fn new(desc: &TerrainDesc) -> Self {
+        let mut layer_tiling = [1.0; 4];
+        for (tiling, layer) in layer_tiling.iter_mut().zip(desc.layers.iter()) {
+            *tiling = layer.tiling;
+        }
+        Self {
+            layer_tiling,
+            layer_count: desc.layers.len().clamp(1, MAX_TERRAIN_LAYERS) as u32,
+            _padding: [0; 3],
+        }
+    }
+}
+
+struct Heightfield {
+    width: u32,
+    depth: u32,
+    heights: Vec<f32>,
+    spacing: glm::Vec2,
+}
+
+impl Heightfield {
+    fn new(desc: &TerrainDesc) -> Result<Self> {
+        let heightmap = desc.heightmap.to_luma16();
+        let (width, depth) = heightmap.dimensions();
+        if width < 2 || depth < 2 {
+            bail!("Terrain heightmaps must be at least 2x2 pixels!");
+        }
+        let heights = heightmap
+            .pixels()
+            .map(|pixel| pixel.0[0] as f32 / u16::MAX as f32 * desc.height_scale)
+            .collect();
+        Ok(Self {
+            width,
+            depth,
+            heights,
+            spacing: glm::vec2(
+                desc.size.x / (width - 1) as f32,
+                desc.size.y / (depth - 1) as f32,
+            ),
+        })
+    }
+
+    fn height(&self, x: i64, z: i64) -> f32 {
+        let x = x.clamp(0, self.width as i64 - 1) as usize;
+        let z = z.clamp(0, self.depth as i64 - 1) as usize;
+        self.heights[z * self.width as usize + x]
+    }
+
+    // Central differences of the heightmap give the surface slope along each axis
+    fn normal(&self, x: i64, z: i64) -> glm::Vec3 {
+        let slope_x = (self.height(x + 1, z) - self.height(x - 1, z)) / (2.0 * self.spacing.x);
+        let slope_z = (self.height(x, z + 1) - self.height(x, z - 1)) / (2.0 * self.spacing.y);
+        glm::normalize(&glm::vec3(-slope_x, 1.0, -slope_z))
+    }
+
+    fn vertex(&self, x: u32, z: u32) -> TerrainVertex {
+        // Chunks that overhang the heightmap collapse onto its last row or column
+        let x = x.min(self.width - 1);
+        let z = z.min(self.depth - 1);
+        let position = glm::vec3(
+            x as f32 * self.spacing.x,
+            self.height(x as i64, z as i64),
+            z as f32 * self.spacing.y,
+        );
+        TerrainVertex {
+            position: position.into(),
+            normal: self.normal(x as i64, z as i64).into(),
+            uv: [
+                x as f32 / (self.width - 1) as f32,
+                z as f32 / (self.depth - 1) as f32,
+            ],
+        }
+    }
+}
+
+// Where the terrain's corner is relative to the scene's origin, added to every vertex. At the same
+// location as the voxels' offset, since both draw shadows with shadow.wgsl
+fn offset_layout<'a>() -> wgpu::VertexBufferLayout<'a> {
+    const ATTRIBUTES: [wgpu::VertexAttribute; 1] = wgpu::vertex_attr_array![4 => Float32x3];
+    wgpu::VertexBufferLayout {
+        array_stride: std::mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
+        step_mode: wgpu::VertexStepMode::Instance,
+        attributes: &ATTRIBUTES,
+    }
+}
+
+struct TerrainChunk {
+    vertex_buffer: Tracked<wgpu::Buffer>,
+    // Relative to the terrain's corner
+    bounds: Aabb,
+    width: f32,
+}
+
+struct LodIndices {
+    buffer: Tracked<wgpu::Buffer>,
+    count: u32,
+}
+
+struct GpuTerrain {
+    uniform_buffer: Tracked<wgpu::Buffer>,
+    bind_group: wgpu::BindGroup,
+    chunks: Vec<TerrainChunk>,
+    lods: Vec<LodIndices>,
+    offset_buffer: Tracked<wgpu::Buffer>,
+    offset: glm::Vec3,
+    _splat_map: Texture,
+    _layers: Texture,
+}
+
+pub struct TerrainSystem {
+    terrain_bind_group_layout: wgpu::BindGroupLayout,
+    pipeline: wgpu::RenderPipeline,
+    depth_pipeline: wgpu::RenderPipeline,
+    shadow_pipeline: wgpu::RenderPipeline,
+    terrains: Vec<GpuTerrain>,
+}
+
+impl TerrainSystem {
+    pub fn new(
+        device: &wgpu::Device,
+        camera_bind_group_layout: &wgpu::BindGroupLayout,
+        light_bind_group_layout: &wgpu::BindGroupLayout,
+        shadow_caster_bind_group_layout: &wgpu::BindGroupLayout,
+        color_format: wgpu::TextureFormat,
+        depth: DepthConfig,
+    ) -> Self {
+        let sampler_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Sampler {
+                filtering: true,
+                comparison: false,
+            },
+            count: None,
+        };
+
+        let terrain_bind_group_layout = memory::create_bind_group_layout(
+            device,
+            &wgpu::BindGroupLayoutDescriptor {
+                label: Some("Terrain Bind Group Layout"),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                    sampler_entry(2),
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 3,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                            view_dimension: wgpu::TextureViewDimension::D2Array,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                    sampler_entry(4),
+                ],
+            },
+        );
+
+        let module = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("Terrain Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("shaders/terrain.wgsl").into()),
+        });
+
+        let pipeline_layout = memory::create_pipeline_layout(
+            device,
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("Terrain Pipeline Layout"),
+                bind_group_layouts: &[
+                    camera_bind_group_layout,
+                    &terrain_bind_group_layout,
+                    light_bind_group_layout,
+                ],
+                push_constant_ranges: &[],
+            },
+        );
+
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Terrain Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &module,
+                entry_point: "vs_main",
+                buffers: &[TerrainVertex::layout(), offset_layout()],
+            },
+            // Skirts face outwards on some edges and inwards on others, so nothing is culled
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: None,
+                ..Default::default()
+            },
+            // The depth prepass has already written the visible surfaces
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: depth.format,
+                depth_write_enabled: true,
+                depth_compare: depth.compare(wgpu::CompareFunction::LessEqual),
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: depth.multisample(),
+            fragment: Some(wgpu::FragmentState {
+                module: &module,
+                entry_point: "fs_main",
+                targets: &[wgpu::ColorTargetState {
+                    format: color_format,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                }],
+            }),
+        });
+
+        let depth_pipeline_layout = memory::create_pipeline_layout(
+            device,
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("Terrain Depth Pipeline Layout"),
+                bind_group_layouts: &[camera_bind_group_layout],
+                push_constant_ranges: &[],
+            },
+        );
+
+        let depth_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Terrain Depth Pipeline"),
+            layout: Some(&depth_pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &module,
+                entry_point: "vs_main",
+                buffers: &[TerrainVertex::layout(), offset_layout()],
+            },
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: None,
+                ..Default::default()
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: depth.format,
+                depth_write_enabled: true,
+                depth_compare: depth.compare(wgpu::CompareFunction::Less),
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState::default(),
+            fragment: None,
+        });
+
+        let shadow_module = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("Terrain Shadow Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("shaders/shadow.wgsl").into()),
+        });
+
+        let shadow_pipeline_layout = memory::create_pipeline_layout(
+            device,
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("Terrain Shadow Pipeline Layout"),
+                bind_group_layouts: &[shadow_caster_bind_group_layout],
+                push_constant_ranges: &[],
+            },
+        );
+
+        let shadow_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Terrain Shadow Pipeline"),
+            layout: Some(&shadow_pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shadow_module,
+                entry_point: "vs_main",
+                buffers: &[TerrainVertex::layout(), offset_layout()],
+            },
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: None,
+                ..Default::default()
+            },
+            // Biasing the depth keeps lit surfaces from shadowing themselves
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: depth.shadow().format,
+                depth_write_enabled: true,
+                depth_compare: depth.compare(wgpu::CompareFunction::Less),
+                stencil: wgpu::StencilState::default(),
+                bias: depth.bias(wgpu::DepthBiasState {
+                    constant: 2,
+                    slope_scale: 2.0,
+                    clamp: 0.0,
+                }),
+            }),
+            multisample: wgpu::MultisampleState::default(),
+            fragment: None,
+        });
+
+        Self {
+            terrain_bind_group_layout,
+            pipeline,
+            depth_pipeline,
+            shadow_pipeline,
+            terrains: Vec::new(),
+        }
+    }
+
+    fn create_terrain(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        desc: &TerrainDesc,
+    ) -> Result<GpuTerrain> {
+        if desc.chunk_size == 0 {
+            bail!("Terrain chunk size must be greater than zero!");
+        }
+        if desc.layers.len() > MAX_TERRAIN_LAYERS {
+            bail!(
+                "Terrains support at most {} layers, found {}!",
+                MAX_TERRAIN_LAYERS,
+                desc.layers.len()
+            );
+        }
+
+        let heightfield = Heightfield::new(desc)?;
+        let chunk_size = desc.chunk_size;
+        let chunks_x = (heightfield.width - 1).div_ceil(chunk_size);
+        let chunks_z = (heightfield.depth - 1).div_ceil(chunk_size);
+        let skirt_depth = desc.height_scale * SKIRT_DEPTH_FACTOR;
+
+        let mut chunks = Vec::with_capacity((chunks_x * chunks_z) as usize);
+        for chunk_z in 0..chunks_z {
+            for chunk_x in 0..chunks_x {
+                let (start_x, start_z) = (chunk_x * chunk_size, chunk_z * chunk_size);
+                let mut vertices = Vec::new();
+                for z in 0..=chunk_size {
+                    for x in 0..=chunk_size {
+                        vertices.push(heightfield.vertex(start_x + x, start_z + z));
+                    }
+                }
+                for edge in Self::edges(chunk_size) {
+                    for (x, z) in edge {
+                        let mut vertex = heightfield.vertex(start_x + x, start_z + z);
+                        vertex.position[1] -= skirt_depth;
+                        vertices.push(vertex);
+                    }
+                }
+
+                let mut bounds = Aabb::default();
+                for vertex in vertices.iter() {
+                    bounds.expand_to_include(&glm::Vec3::from(vertex.position));
+                }
+
+                let vertex_buffer = memory::create_buffer_init(
+                    device,
+                    MemoryCategory::Meshes,
+                    &wgpu::util::BufferInitDescriptor {
+                        label: Some("Terrain Chunk Vertex Buffer"),
+                        contents: bytemuck::cast_slice(&vertices),
+                        usage: wgpu::BufferUsages::VERTEX,
+                    },
+                );
+
+                chunks.push(TerrainChunk {
+                    vertex_buffer,
+                    bounds,
+                    width: chunk_size as f32 * heightfield.spacing.x.max(heightfield.spacing.y),
+                });
+            }
+        }
+
+        // Every chunk shares the same vertex layout, so the index buffers are shared as well
+        let lod_count = (chunk_size.trailing_zeros() + 1).min(MAX_LOD_COUNT);
+        let lods = (0..lod_count)
+            .map(|lod| {
+                let indices = Self::lod_indices(chunk_size, 1 << lod);
+                LodIndices {
+                    buffer: memory::create_buffer_init(
+                        device,
+                        MemoryCategory::Meshes,
+                        &wgpu::util::BufferInitDescriptor {
+                            label: Some("Terrain Index Buffer"),
+                            contents: bytemuck::cast_slice(&indices),
+                            usage: wgpu::BufferUsages::INDEX,
+                        },
+                    ),
+                    count: indices.len() as u32,
+                }
+            })
+            .collect();
+
+        let splat_image = match desc.splat_map.as_ref() {
+            Some(splat_map) => splat_map.clone(),
+            None => image::DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(
+                1,
+                1,
+                image::Rgba([255, 0, 0, 0]),
+            )),
+        };
+        let splat_map = Texture::from_image_with_format(
+            device,
+            queue,
+            &splat_image,
+            wgpu::TextureFormat::Rgba8Unorm,
+            Some("Terrain Splat Texture"),
+        )?;
+
+        // Texture arrays need matching dimensions, so every layer is resized to match the first
+        let mut layer_images = desc
+            .layers
+            .iter()
+            .map(|layer| layer.albedo.to_rgba8())
+            .collect::<Vec<_>>();
+        if layer_images.is_empty() {
+            layer_images.push(image::RgbaImage::from_pixel(
+                1,
+                1,
+                image::Rgba([128, 128, 128, 255]),
+            ));
+        }
+        let (width, height) = layer_images[0].dimensions();
+        for image in layer_images.iter_mut() {
+            if image.dimensions() != (width, height) {
+                *image = image::imageops::resize(
+                    image,
+                    width,
+                    height,
+                    image::imageops::FilterType::Triangle,
+                );
+            }
+        }
+        let layers = Texture::array_from_images(
+            device,
+            queue,
+            &layer_images,
+            wgpu::TextureFormat::Rgba8UnormSrgb,
+            Some("Terrain Layer Texture Array"),
+        )?;
+
+        let offset_buffer = memory::create_buffer(
+            device,
+            MemoryCategory::Transient,
+            &wgpu::BufferDescriptor {
+                label: Some("Terrain Offset Buffer"),
+                size: std::mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
+                usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            },
+        );
+
+        let uniform_buffer = memory::create_buffer(
+            device,
+            MemoryCategory::Transient,
+            &wgpu::BufferDescriptor {
+                label: Some("Terrain Uniform Buffer"),
+                size: std::mem::size_of::<TerrainUniform>() as wgpu::BufferAddress,
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            },
+        );
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Terrain Bind Group"),
+            layout: &self.terrain_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&splat_map.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::Sampler(&splat_map.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::TextureView(&layers.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: wgpu::BindingResource::Sampler(&layers.sampler),
+                },
+            ],
+        });
+
+        Ok(GpuTerrain {
+            uniform_buffer,
+            bind_group,
+            chunks,
+            lods,
+            offset_buffer,
+            offset: glm::Vec3::zeros(),
+            _splat_map: splat_map,
+            _layers: layers,
+        })
+    }
+
+    // Grid coordinates along the top, bottom, left, and right edges of a chunk
+    fn edges(chunk_size: u32) -> [Vec<(u32, u32)>; 4] {
+        let range = 0..=chunk_size;
+        [
+            range.clone().map(|x| (x, 0)).collect(),
+            range.clone().map(|x| (x, chunk_size)).collect(),
+            range.clone().map(|z| (0, z)).collect(),
+            range.map(|z| (chunk_size, z)).collect(),
+        ]
+    }
+
+    fn lod_indices(chunk_size: u32, step: u32) -> Vec<u32> {
+        let row = chunk_size + 1;
+        let grid = |x: u32, z: u32| z * row + x;
+        let skirt = |edge: u32, offset: u32| row * row + edge * row + offset;
+
+        let mut indices = Vec::new();
+        for z in (0..chunk_size).step_by(step as usize) {
+            for x in (0..chunk_size).step_by(step as usize) {
+                let (x1, z1) = (x + step, z + step);
+                indices.extend_from_slice(&[
+                    grid(x, z),
+                    grid(x, z1),
+                    grid(x1, z),
+                    grid(x1, z),
+                    grid(x, z1),
+                    grid(x1, z1),
+                ]);
+            }
+        }
+
+        for offset in (0..chunk_size).step_by(step as usize) {
+            let next = offset + step;
+            let edges = [
+                (grid(offset, 0), grid(next, 0)),
+                (grid(offset, chunk_size), grid(next, chunk_size)),
+                (grid(0, offset), grid(0, next)),
+                (grid(chunk_size, offset), grid(chunk_size, next)),
+            ];
+            for (edge, (start, end)) in edges.into_iter().enumerate() {
+                let edge = edge as u32;
+                let (skirt_start, skirt_end) = (skirt(edge, offset), skirt(edge, next));
+                indices.extend_from_slice(&[start, skirt_start, end, end, skirt_start, skirt_end]);
+            }
+        }
+
+        indices
+    }
+
+    pub fn update(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        scene: &Scene,
+    ) -> Result<()> {
+        while self.terrains.len() < scene.terrains.len() {
+            let terrain =
+                self.create_terrain(device, queue, &scene.terrains[self.terrains.len()])?;
+            self.terrains.push(terrain);
+        }
+
+        for (desc, terrain) in scene.terrains.iter().zip(self.terrains.iter_mut()) {
+            terrain.offset = scene.local_point(&desc.origin);
+            let offset: [f32; 3] = terrain.offset.into();
+            queue.write_buffer(&terrain.offset_buffer, 0, bytemuck::cast_slice(&offset));
+            queue.write_buffer(
+                &terrain.uniform_buffer,
+                0,
+                bytemuck::cast_slice(&[TerrainUniform::new(desc)]),
+            );
+        }
+
+        Ok(())
+    }
+
+    // Bounds of every terrain, used to fit the shadow map
+    pub fn bounds(&self) -> Aabb {
+        let mut bounds = Aabb::default();
+        for terrain in self.terrains.iter() {
+            for chunk in terrain.chunks.iter() {
+                bounds.merge(&chunk.bounds.translated(&terrain.offset));
+            }
+        }
+        bounds
+    }
+
+    // Shadows are rendered at full detail so they line up with the surfaces receiving them
+    pub fn render_shadows<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        shadow_caster_bind_group: &'a wgpu::BindGroup,
+        light_frustum: &Frustum,
+    ) {
+        render_pass.set_pipeline(&self.shadow_pipeline);
+        render_pass.set_bind_group(0, shadow_caster_bind_group, &[]);
+        for terrain in self.terrains.iter() {
+            let lod = &terrain.lods[0];
+            render_pass.set_index_buffer(lod.buffer.slice(..), wgpu::IndexFormat::Uint32);
+            render_pass.set_vertex_buffer(1, terrain.offset_buffer.slice(..));
+            for chunk in terrain.chunks.iter() {
+                if !light_frustum.intersects_aabb(&chunk.bounds.translated(&terrain.offset)) {
+                    continue;
+                }
+                render_pass.set_vertex_buffer(0, chunk.vertex_buffer.slice(..));
+                render_pass.draw_indexed(0..lod.count, 0, 0..1);
+            }
+        }
+    }
+
+    // Chunks select the same level of detail here as in the main pass so their depths match
+    pub fn render_depth<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        camera_bind_group: &'a wgpu::BindGroup,
+        camera: &Camera,
+        frustum: &Frustum,
+        quality: EffectQuality,
+    ) {
+        render_pass.set_pipeline(&self.depth_pipeline);
+        render_pass.set_bind_group(0, camera_bind_group, &[]);
+        for terrain in self.terrains.iter() {
+            Self::draw_chunks(render_pass, terrain, camera, frustum, quality);
+        }
+    }
+
+    pub fn render<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        camera_bind_group: &'a wgpu::BindGroup,
+        light_bind_group: &'a wgpu::BindGroup,
+        camera: &Camera,
+        frustum: &Frustum,
+        quality: EffectQuality,
+    ) {
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, camera_bind_group, &[]);
+        render_pass.set_bind_group(2, light_bind_group, &[]);
+        for terrain in self.terrains.iter() {
+            render_pass.set_bind_group(1, &terrain.bind_group, &[]);
+            Self::draw_chunks(render_pass, terrain, camera, frustum, quality);
+        }
+    }
+
+    fn draw_chunks<'a>(
+        render_pass: &mut wgpu::RenderPass<'a>,
+        terrain: &'a GpuTerrain,
+        camera: &Camera,
+        frustum: &Frustum,
+        quality: EffectQuality,
+    ) {
+        render_pass.set_vertex_buffer(1, terrain.offset_buffer.slice(..));
+        for chunk in terrain.chunks.iter() {
+            let bounds = chunk.bounds.translated(&terrain.offset);
+            if !frustum.intersects_aabb(&bounds) {
+                continue;
+            }
+            let lod_index = Self::select_lod(chunk, &bounds, camera, terrain.lods.len(), quality);
+            let lod = &terrain.lods[lod_index];
+            render_pass.set_vertex_buffer(0, chunk.vertex_buffer.slice(..));
+            render_pass.set_index_buffer(lod.buffer.slice(..), wgpu::IndexFormat::Uint32);
+            render_pass.draw_indexed(0..lod.count, 0, 0..1);
+        }
+    }
+
+    fn select_lod(
+        chunk: &TerrainChunk,
+        bounds: &Aabb,
+        camera: &Camera,
+        lod_count: usize,
+        quality: EffectQuality,
+    ) -> usize {
+        let radius = bounds.extents().magnitude() * 0.5;
+        let distance = (glm::distance(&camera.position, &bounds.center()) - radius).max(0.0);
+        let switch_distance = chunk.width * LOD_DISTANCE_FACTOR * quality.lod_distance_scale();
+        let lod = (1.0 + distance / switch_distance).log2().floor() as usize;
+        lod.min(lod_count - 1)
+    }
+}
@@ -0,0 +1,650 @@
+use ab_glyph::{Font, FontRef, GlyphId, PxScale, ScaleFont};
+use anyhow::Result;
+use nalgebra_glm as glm;
+use std::{collections::HashMap, ops::Range};
+
+use crate::memory::{self, MemoryCategory, Tracked};
+use crate::texture::Texture;
+use crate::{scene::Scene, shader_snippets};
+
+const FONT: &[u8] = include_bytes!("../assets/fonts/Hack-Regular.ttf");
+
+const ATLAS_SIZE: u32 = 1024;
+
+// Glyphs are rasterized once at this size, their distance fields scale to any other
+const SDF_GLYPH_SIZE: f32 = 48.0;
+
+// Distance in texels at which the field saturates, also the empty border around each glyph
+const SDF_SPREAD: u32 = 6;
+
+// Screen space text drawn over the finished frame
+#[derive(Debug, Clone, PartialEq)]
+pub struct Text {
+    pub text: String,
+    // Logical pixels from the top left corner of the window to the top left corner of the first
+    // line, scaled with the window and the interface
+    pub position: glm::Vec2,
+    // Font size in logical pixels
+    pub size: f32,
+    pub color: glm::Vec4,
+}
+
+// Text anchored to a point in the world, turned to face the camera
+#[derive(Debug, Clone, PartialEq)]
+pub struct LabelDesc {
+    pub text: String,
+    // The bottom center of the text
+    pub position: glm::Vec3,
+    // Font size in world units
+    pub size: f32,
+    pub color: glm::Vec4,
+    // Hidden behind opaque geometry when set, otherwise drawn over everything
+    pub depth_test: bool,
+}
+
+impl Default for LabelDesc {
+    fn default() -> Self {
+        Self {
+            text: String::new(),
+            position: glm::Vec3::zeros(),
+            size: 1.0,
+            color: glm::vec4(1.0, 1.0, 1.0, 1.0),
+            depth_test: true,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct TextVertex {
+    // Where labels are anchored in the world, unused by screen text
+    anchor: [f32; 3],
+    // Clip space position of screen text, or the offset from the anchor in world units for labels
+    offset: [f32; 2],
+    uv: [f32; 2],
+    color: [f32; 4],
+}
+
+impl TextVertex {
+    const ATTRIBUTES: [wgpu::VertexAttribute; 4] = wgpu::vertex_attr_array![
+        0 => Float32x3,
+        1 => Float32x2,
+        2 => Float32x2,
+        3 => Float32x4
+    ];
+
+    fn layout<'a>() -> wgpu::VertexBufferLayout<'a> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<Self>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &Self::ATTRIBUTES,
+        }
+    }
+}
+
+// Sizes are in texels of the atlas
+#[derive(Debug, Copy, Clone)]
+struct AtlasGlyph {
+    // From the pen position on the baseline to the top left corner of the distance field
+    offset: glm::Vec2,
+    extent: glm::Vec2,
+    uv_min: glm::Vec2,
+    uv_max: glm::Vec2,
+}
+
+struct AtlasFull;
+
+// Signed distance to the glyph outline, stored so the outline sits at one half and the
+// field increases towards the inside of the glyph
+fn distance_field(coverage: &[f32], width: u32, height: u32) -> Vec<u8> {
+    let [width, height, spread] = [width as i32, height as i32, SDF_SPREAD as i32];
+    let inside = |x: i32, y: i32| {
+        x >= 0 && y >= 0 && x < width && y < height && coverage[(y * width + x) as usize] >= 0.5
+    };
+    let mut field = Vec::with_capacity((width * height) as usize);
+    for y in 0..height {
+        for x in 0..width {
+            let state = inside(x, y);
+            let mut nearest = spread as f32;
+            for offset_y in -spread..=spread {
+                for offset_x in -spread..=spread {
+                    if inside(x + offset_x, y + offset_y) != state {
+                        let distance = ((offset_x * offset_x + offset_y * offset_y) as f32).sqrt();
+                        nearest = nearest.min(distance);
+                    }
+                }
+            }
+            // Neighbouring texels on either side of the outline are half a texel away from it
+            let distance = if state { nearest - 0.5 } else { 0.5 - nearest };
+            let value = 0.5 + distance / (2.0 * spread as f32);
+            field.push((value.clamp(0.0, 1.0) * 255.0).round() as u8);
+        }
+    }
+    field
+}
+
+// Distance fields of glyphs packed into rows, from the top of the atlas down
+struct GlyphAtlas {
+    texture: Tracked<wgpu::Texture>,
+    // Glyphs without an outline such as spaces are cached as None
+    glyphs: HashMap<GlyphId, Option<AtlasGlyph>>,
+    cursor: [u32; 2],
+    row_height: u32,
+}
+
+impl GlyphAtlas {
+    fn new(device: &wgpu::Device) -> Self {
+        let texture = memory::create_texture(
+            device,
+            MemoryCategory::Textures,
+            &wgpu::TextureDescriptor {
+                label: Some("Glyph Atlas"),
+                size: wgpu::Extent3d {
+                    width: ATLAS_SIZE,
+                    height: ATLAS_SIZE,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: wgpu::TextureFormat::R8Unorm,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            },
+        );
+        Self {
+            texture,
+            glyphs: HashMap::new(),
+            cursor: [0, 0],
+            row_height: 0,
+        }
+    }
+
+    fn clear(&mut self) {
+        self.glyphs.clear();
+        self.cursor = [0, 0];
+        self.row_height = 0;
+    }
+
+    fn allocate(&mut self, width: u32, height: u32) -> Option<[u32; 2]> {
+        if width > ATLAS_SIZE {
+            return None;
+        }
+        if self.cursor[0] + width > ATLAS_SIZE {
+            self.cursor = [0, self.cursor[1] + self.row_height];
+            self.row_height = 0;
+        }
+        if self.cursor[1] + height > ATLAS_SIZE {
+            return None;
+        }
+        let origin = self.cursor;
+        self.cursor[0] += width;
+        self.row_height = self.row_height.max(height);
+        Some(origin)
+    }
+
+    fn glyph(
+        &mut self,
+        queue: &wgpu::Queue,
+        font: &FontRef,
+        id: GlyphId,
+    ) -> Result<Option<AtlasGlyph>, AtlasFull> {
+        if let Some(glyph) = self.glyphs.get(&id) {
+            return Ok(*glyph);
+        }
+
+        let outline = match font.outline_glyph(id.with_scale(SDF_GLYPH_SIZE)) {
+            Some(outline) => outline,
+            None => {
+                self.glyphs.insert(id, None);
+                return Ok(None);
+            }
+        };
+        let bounds = outline.px_bounds();
+        let width = bounds.width() as u32 + SDF_SPREAD * 2;
+        let height = bounds.height() as u32 + SDF_SPREAD * 2;
+        let origin = self.allocate(width, height).ok_or(AtlasFull)?;
+
+        let mut coverage = vec![0.0; (width * height) as usize];
+        outline.draw(|x, y, value| {
+            coverage[((y + SDF_SPREAD) * width + x + SDF_SPREAD) as usize] = value;
+        });
+        queue.write_texture(
+            wgpu::ImageCopyTexture {
+                texture: &self.texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d {
+                    x: origin[0],
+                    y: origin[1],
+                    z: 0,
+                },
+                aspect: wgpu::TextureAspect::All,
+            },
+            &distance_field(&coverage, width, height),
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: std::num::NonZeroU32::new(width),
+                rows_per_image: std::num::NonZeroU32::new(height),
+            },
+            wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+        );
+
+        let uv_min = glm::vec2(origin[0] as f32, origin[1] as f32) / ATLAS_SIZE as f32;
+        let extent = glm::vec2(width as f32, height as f32);
+        let glyph = AtlasGlyph {
+            offset: glm::vec2(bounds.min.x, bounds.min.y) - glm::vec2(1.0, 1.0) * SDF_SPREAD as f32,
+            extent,
+            uv_min,
+            uv_max: uv_min + extent / ATLAS_SIZE as f32,
+        };
+        self.glyphs.insert(id, Some(glyph));
+        Ok(Some(glyph))
+    }
+}
+
+// A glyph placed relative to the top left corner of its text, in texels of the atlas
+struct GlyphQuad {
+    min: glm::Vec2,
+    max: glm::Vec2,
+    uv_min: glm::Vec2,
+    uv_max: glm::Vec2,
+}
+
+impl GlyphQuad {
+    // Two triangles with positions mapped by the given function
+    fn vertices(
+        &self,
+        color: &glm::Vec4,
+        vertex: impl Fn(glm::Vec2) -> ([f32; 3], [f32; 2]),
+    ) -> [TextVertex; 6] {
+        let corner = |x: bool, y: bool| {
+            let position = glm::vec2(
+                if x { self.max.x } else { self.min.x },
+                if y { self.max.y } else { self.min.y },
+            );
+            let (anchor, offset) = vertex(position);
+            TextVertex {
+                anchor,
+                offset,
+                uv: [
+                    if x { self.uv_max.x } else { self.uv_min.x },
+                    if y { self.uv_max.y } else { self.uv_min.y },
+                ],
+                color: (*color).into(),
+            }
+        };
+        let corners = [
+            corner(false, false),
+            corner(true, false),
+            corner(true, true),
+            corner(false, true),
+        ];
+        [0, 1, 2, 0, 2, 3].map(|index| corners[index])
+    }
+}
+
+struct TextLayout {
+    quads: Vec<GlyphQuad>,
+    // Size of the laid out text in texels of the atlas
+    extent: glm::Vec2,
+}
+
+pub struct TextSystem {
+    font: FontRef<'static>,
+    atlas: GlyphAtlas,
+    atlas_bind_group: wgpu::BindGroup,
+    depth_bind_group_layout: wgpu::BindGroupLayout,
+    depth_bind_group: wgpu::BindGroup,
+    screen_pipeline: wgpu::RenderPipeline,
+    label_pipeline: wgpu::RenderPipeline,
+    depth_tested_label_pipeline: wgpu::RenderPipeline,
+    vertex_buffer: Tracked<wgpu::Buffer>,
+    vertex_capacity: usize,
+    // Ranges of the vertex buffer drawn by each pipeline
+    screen_vertices: Range<u32>,
+    label_vertices: Range<u32>,
+    depth_tested_label_vertices: Range<u32>,
+}
+
+impl TextSystem {
+    pub fn new(
+        device: &wgpu::Device,
+        camera_bind_group_layout: &wgpu::BindGroupLayout,
+        output_format: wgpu::TextureFormat,
+        depth_texture: &Texture,
+    ) -> Result<Self> {
+        let font = FontRef::try_from_slice(FONT)?;
+        let atlas = GlyphAtlas::new(device);
+
+        let atlas_bind_group_layout = memory::create_bind_group_layout(
+            device,
+            &wgpu::BindGroupLayoutDescriptor {
+                label: Some("Glyph Atlas Bind Group Layout"),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Sampler {
+                            filtering: true,
+                            comparison: false,
+                        },
+                        count: None,
+                    },
+                ],
+            },
+        );
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Glyph Atlas Sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let view = atlas
+            .texture
+            .create_view(&wgpu::TextureViewDescriptor::default());
+        let atlas_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Glyph Atlas Bind Group"),
+            layout: &atlas_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&sampler),
+                },
+            ],
+        });
+
+        // Labels compare against the scene depth themselves, the text pass has no depth attachment
+        // because it is drawn at the output resolution
+        let depth_bind_group_layout = memory::create_bind_group_layout(
+            device,
+            &wgpu::BindGroupLayoutDescriptor {
+                label: Some("Text Depth Bind Group Layout"),
+                entries: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Depth,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                }],
+            },
+        );
+
+        let depth_bind_group =
+            Self::create_depth_bind_group(device, &depth_bind_group_layout, depth_texture);
+
+        let module = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("Text Shader"),
+            source: wgpu::ShaderSource::Wgsl(
+                shader_snippets::splice(include_str!("shaders/text.wgsl")).into(),
+            ),
+        });
+
+        let pipeline_layout = memory::create_pipeline_layout(
+            device,
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("Text Pipeline Layout"),
+                bind_group_layouts: &[
+                    camera_bind_group_layout,
+                    &atlas_bind_group_layout,
+                    &depth_bind_group_layout,
+                ],
+                push_constant_ranges: &[],
+            },
+        );
+
+        let create_pipeline = |label, vertex_entry_point, fragment_entry_point| {
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some(label),
+                layout: Some(&pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: &module,
+                    entry_point: vertex_entry_point,
+                    buffers: &[TextVertex::layout()],
+                },
+                primitive: wgpu::PrimitiveState::default(),
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState::default(),
+                fragment: Some(wgpu::FragmentState {
+                    module: &module,
+                    entry_point: fragment_entry_point,
+                    targets: &[wgpu::ColorTargetState {
+                        format: output_format,
+                        blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    }],
+                }),
+            })
+        };
+
+        let screen_pipeline = create_pipeline("Text Pipeline", "vs_main", "fs_main");
+        let label_pipeline = create_pipeline("Label Pipeline", "vs_label", "fs_main");
+        let depth_tested_label_pipeline =
+            create_pipeline("Depth Tested Label Pipeline", "vs_label", "fs_depth_tested");
+
+        Ok(Self {
+            font,
+            atlas,
+            atlas_bind_group,
+            depth_bind_group_layout,
+            depth_bind_group,
+            screen_pipeline,
+            label_pipeline,
+            depth_tested_label_pipeline,
+            vertex_buffer: Self::create_vertex_buffer(device, 0),
+            vertex_capacity: 0,
+            screen_vertices: 0..0,
+            label_vertices: 0..0,
+            depth_tested_label_vertices: 0..0,
+        })
+    }
+
+    fn create_depth_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        depth_texture: &Texture,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Text Depth Bind Group"),
+            layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&depth_texture.view),
+            }],
+        })
+    }
+
+    pub fn resize(&mut self, device: &wgpu::Device, depth_texture: &Texture) {
+        self.depth_bind_group =
+            Self::create_depth_bind_group(device, &self.depth_bind_group_layout, depth_texture);
+    }
+
+    fn create_vertex_buffer(device: &wgpu::Device, capacity: usize) -> Tracked<wgpu::Buffer> {
+        memory::create_buffer(
+            device,
+            MemoryCategory::Meshes,
+            &wgpu::BufferDescriptor {
+                label: Some("Text Vertex Buffer"),
+                size: (capacity.max(1) * std::mem::size_of::<TextVertex>()) as wgpu::BufferAddress,
+                usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            },
+        )
+    }
+
+    // Lays out the text and rasterizes any glyphs missing from the atlas
+    pub fn update(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        texts: &[Text],
+        scene: &Scene,
+        dimensions: &[u32; 2],
+    ) {
+        // A full atlas is cleared and everything laid out again, whatever still doesn't fit is left out
+        let mut full = false;
+        let mut vertices = self.vertices(queue, texts, scene, dimensions, &mut full);
+        if full {
+            self.atlas.clear();
+            vertices = self.vertices(queue, texts, scene, dimensions, &mut full);
+        }
+
+        if vertices.len() > self.vertex_capacity {
+            self.vertex_capacity = vertices.len().next_power_of_two();
+            self.vertex_buffer = Self::create_vertex_buffer(device, self.vertex_capacity);
+        }
+        if !vertices.is_empty() {
+            queue.write_buffer(&self.vertex_buffer, 0, bytemuck::cast_slice(&vertices));
+        }
+    }
+
+    // Labels hidden by the scene come first, then labels drawn over it, then screen text on top
+    fn vertices(
+        &mut self,
+        queue: &wgpu::Queue,
+        texts: &[Text],
+        scene: &Scene,
+        dimensions: &[u32; 2],
+        full: &mut bool,
+    ) -> Vec<TextVertex> {
+        let mut vertices = Vec::new();
+
+        for depth_test in [true, false] {
+            let start = vertices.len() as u32;
+            for label in scene
+                .labels
+                .iter()
+                .filter(|label| label.depth_test == depth_test)
+            {
+                let layout = self.layout(queue, &label.text, full);
+                let scale = label.size / SDF_GLYPH_SIZE;
+                let anchor = scene.local_point(&label.position).into();
+                for quad in layout.quads.iter() {
+                    vertices.extend(quad.vertices(&label.color, |position| {
+                        let offset = glm::vec2(
+                            position.x - layout.extent.x * 0.5,
+                            layout.extent.y - position.y,
+                        ) * scale;
+                        (anchor, offset.into())
+                    }));
+                }
+            }
+            let range = start..vertices.len() as u32;
+            if depth_test {
+                self.depth_tested_label_vertices = range;
+            } else {
+                self.label_vertices = range;
+            }
+        }
+
+        let start = vertices.len() as u32;
+        for text in texts.iter() {
+            let layout = self.layout(queue, &text.text, full);
+            let scale = text.size / SDF_GLYPH_SIZE;
+            for quad in layout.quads.iter() {
+                vertices.extend(quad.vertices(&text.color, |position| {
+                    let pixel = text.position + position * scale;
+                    let clip = [
+                        pixel.x / dimensions[0] as f32 * 2.0 - 1.0,
+                        1.0 - pixel.y / dimensions[1] as f32 * 2.0,
+                    ];
+                    ([0.0; 3], clip)
+                }));
+            }
+        }
+        self.screen_vertices = start..vertices.len() as u32;
+        vertices
+    }
+
+    // Glyphs that don't fit in the atlas are skipped and flag it as full
+    fn layout(&mut self, queue: &wgpu::Queue, text: &str, full: &mut bool) -> TextLayout {
+        let font = self.font.as_scaled(PxScale::from(SDF_GLYPH_SIZE));
+        let line_height = font.height() + font.line_gap();
+        let mut caret = glm::vec2(0.0, font.ascent());
+        let mut width: f32 = 0.0;
+        let mut previous = None;
+        let mut quads = Vec::new();
+        for character in text.chars() {
+            if character == '\n' {
+                caret = glm::vec2(0.0, caret.y + line_height);
+                previous = None;
+                continue;
+            }
+            if character.is_control() {
+                continue;
+            }
+
+            let id = font.glyph_id(character);
+            if let Some(previous) = previous {
+                caret.x += font.kern(previous, id);
+            }
+            previous = Some(id);
+
+            match self.atlas.glyph(queue, &self.font, id) {
+                Ok(Some(glyph)) => {
+                    let min = caret + glyph.offset;
+                    quads.push(GlyphQuad {
+                        min,
+                        max: min + glyph.extent,
+                        uv_min: glyph.uv_min,
+                        uv_max: glyph.uv_max,
+                    });
+                }
+                Ok(None) => {}
+                Err(AtlasFull) => *full = true,
+            }
+            caret.x += font.h_advance(id);
+            width = width.max(caret.x);
+        }
+        TextLayout {
+            quads,
+            extent: glm::vec2(width, caret.y - font.descent()),
+        }
+    }
+
+    pub fn render<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        camera_bind_group: &'a wgpu::BindGroup,
+    ) {
+        render_pass.set_bind_group(0, camera_bind_group, &[]);
+        render_pass.set_bind_group(1, &self.atlas_bind_group, &[]);
+        render_pass.set_bind_group(2, &self.depth_bind_group, &[]);
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        for (pipeline, vertices) in [
+            (
+                &self.depth_tested_label_pipeline,
+                &self.depth_tested_label_vertices,
+            ),
+            (&self.label_pipeline, &self.label_vertices),
+            (&self.screen_pipeline, &self.screen_vertices),
+        ] {
+            if vertices.is_empty() {
+                continue;
+            }
+            render_pass.set_pipeline(pipeline);
+            render_pass.draw(vertices.clone(), 0..1);
+        }
+    }
+}
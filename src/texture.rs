@@ -1,8 +1,405 @@
-use anyhow::Result;
-use image::GenericImageView;
+use anyhow::{bail, Context, Result};
+use nalgebra_glm as glm;
+
+use crate::memory::{self, MemoryCategory, Tracked};
+
+// A magenta checker drawn in place of images that failed to load
+pub fn missing_image() -> image::DynamicImage {
+    image::DynamicImage::ImageRgba8(checkerboard(64, 8, [255, 0, 255, 255], [16, 0, 16, 255]))
+}
+
+// Generated images that can be added to a model for its materials to use
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ProceduralTexture {
+    Checkerboard,
+    GradientRamp,
+    PerlinNoise,
+    WorleyNoise,
+    // Normals of the perlin noise as a height map
+    NoiseNormals,
+    BlueNoise,
+}
+
+impl ProceduralTexture {
+    pub const ALL: [Self; 6] = [
+        Self::Checkerboard,
+        Self::GradientRamp,
+        Self::PerlinNoise,
+        Self::WorleyNoise,
+        Self::NoiseNormals,
+        Self::BlueNoise,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Checkerboard => "Checkerboard",
+            Self::GradientRamp => "Gradient ramp",
+            Self::PerlinNoise => "Perlin noise",
+            Self::WorleyNoise => "Worley noise",
+            Self::NoiseNormals => "Noise normal map",
+            Self::BlueNoise => "Blue noise",
+        }
+    }
+
+    pub fn generate(&self, size: u32) -> image::DynamicImage {
+        let gray = |image: image::GrayImage| image::DynamicImage::ImageLuma8(image).into_rgba8();
+        image::DynamicImage::ImageRgba8(match self {
+            Self::Checkerboard => checkerboard(size, size / 8, [255; 4], [32, 32, 32, 255]),
+            Self::GradientRamp => gradient_ramp(
+                size,
+                size,
+                &[
+                    (0.0, glm::vec4(0.0, 0.0, 0.0, 1.0)),
+                    (1.0, glm::vec4(1.0, 1.0, 1.0, 1.0)),
+                ],
+            ),
+            Self::PerlinNoise => gray(perlin_noise(size, 8, 4, 0)),
+            Self::WorleyNoise => gray(worley_noise(size, 8, 0)),
+            Self::NoiseNormals => normal_map_from_height(&perlin_noise(size, 8, 4, 0), 4.0),
+            // Generating blue noise slows down quickly with its size, so larger images repeat it
+            Self::BlueNoise => {
+                let tile = blue_noise(size.min(64), 0);
+                let tiled = image::GrayImage::from_fn(size, size, |x, y| {
+                    *tile.get_pixel(x % tile.width(), y % tile.height())
+                });
+                gray(tiled)
+            }
+        })
+    }
+}
+
+// Square cells of two alternating colors, tileable when the size is a multiple of twice the cell
+pub fn checkerboard(size: u32, cell: u32, first: [u8; 4], second: [u8; 4]) -> image::RgbaImage {
+    let cell = cell.max(1);
+    image::RgbaImage::from_fn(size, size, |x, y| {
+        if (x / cell + y / cell).is_multiple_of(2) {
+            image::Rgba(first)
+        } else {
+            image::Rgba(second)
+        }
+    })
+}
+
+// Blends between colors from left to right. Stops are positions from 0 to 1 in increasing order
+// with colors from 0 to 1
+pub fn gradient_ramp(width: u32, height: u32, stops: &[(f32, glm::Vec4)]) -> image::RgbaImage {
+    let color = |position: f32| -> glm::Vec4 {
+        let next = stops
+            .iter()
+            .position(|(stop, _)| *stop >= position)
+            .unwrap_or(stops.len());
+        match (
+            next.checked_sub(1).map(|index| stops[index]),
+            stops.get(next).copied(),
+        ) {
+            (Some((start, from)), Some((end, to))) => {
+                let amount = (position - start) / (end - start).max(f32::EPSILON);
+                glm::lerp(&from, &to, amount)
+            }
+            (Some((_, color)), None) | (None, Some((_, color))) => color,
+            (None, None) => glm::Vec4::zeros(),
+        }
+    };
+    let columns = (0..width)
+        .map(|x| {
+            let position = (x as f32 + 0.5) / width as f32;
+            let color = color(position).map(|channel| (channel.clamp(0.0, 1.0) * 255.0).round());
+            image::Rgba([color.x as u8, color.y as u8, color.z as u8, color.w as u8])
+        })
+        .collect::<Vec<_>>();
+    image::RgbaImage::from_fn(width, height, |x, _| columns[x as usize])
+}
+
+// Fractal gradient noise that tiles across the image. The period is the number of lattice cells
+// across the first octave, with each octave having twice as many
+pub fn perlin_noise(size: u32, period: u32, octaves: u32, seed: u32) -> image::GrayImage {
+    image::GrayImage::from_fn(size, size, |x, y| {
+        let point = glm::vec3(x as f32, y as f32, 0.0) / size as f32;
+        image::Luma([to_byte(fractal_perlin(
+            point,
+            [period, period, 1],
+            octaves,
+            seed,
+        ))])
+    })
+}
+
+// Distance to the nearest of one randomly placed point per cell, dark at the points and tileable
+pub fn worley_noise(size: u32, cells: u32, seed: u32) -> image::GrayImage {
+    image::GrayImage::from_fn(size, size, |x, y| {
+        let point = glm::vec3(x as f32, y as f32, 0.0) / size as f32;
+        image::Luma([to_byte(worley(point, [cells, cells, 1], seed))])
+    })
+}
+
+// Texels of a cube of fractal gradient noise in x, then y, then z order, for Texture::from_volume
+#[allow(dead_code)]
+pub fn perlin_volume(size: u32, period: u32, octaves: u32, seed: u32) -> Vec<u8> {
+    volume(size, |point| {
+        fractal_perlin(point, [period; 3], octaves, seed)
+    })
+}
+
+#[allow(dead_code)]
+pub fn worley_volume(size: u32, cells: u32, seed: u32) -> Vec<u8> {
+    volume(size, |point| worley(point, [cells; 3], seed))
+}
+
+// Slopes of a height map as a tangent space normal map with green pointing up the image, wrapping
+// at the edges so tileable heights give tileable normals
+pub fn normal_map_from_height(height: &image::GrayImage, strength: f32) -> image::RgbaImage {
+    let (width, rows) = height.dimensions();
+    let sample = |x: i64, y: i64| {
+        let x = x.rem_euclid(width as i64) as u32;
+        let y = y.rem_euclid(rows as i64) as u32;
+        height.get_pixel(x, y).0[0] as f32 / 255.0
+    };
+    image::RgbaImage::from_fn(width, rows, |x, y| {
+        let (x, y) = (x as i64, y as i64);
+        let dx = (sample(x + 1, y) - sample(x - 1, y)) * 0.5 * strength;
+        let dy = (sample(x, y + 1) - sample(x, y - 1)) * 0.5 * strength;
+        let normal = glm::normalize(&glm::vec3(-dx, dy, 1.0));
+        let encoded = normal.map(|channel| ((channel * 0.5 + 0.5) * 255.0).round());
+        image::Rgba([encoded.x as u8, encoded.y as u8, encoded.z as u8, 255])
+    })
+}
+
+// Thresholds without low frequencies, so neighboring pixels get very different values and
+// patterns made from them look like evenly spread noise rather than clumps. Tileable, made with
+// the void and cluster method
+pub fn blue_noise(size: u32, seed: u32) -> image::GrayImage {
+    let size = size.max(1);
+    let mut field = VoidAndCluster::new(size);
+    let count = field.energy.len();
+
+    // Starts from a few random pixels spread out until they're as even as they can be
+    for index in 0..count {
+        let (x, y) = (index as u32 % size, index as u32 / size);
+        if hash(x, y, 0, seed).is_multiple_of(10) {
+            field.toggle(index, true);
+        }
+    }
+    if !field.pattern.contains(&true) {
+        field.toggle(0, true);
+    }
+    loop {
+        let cluster = field.tightest_cluster();
+        field.toggle(cluster, false);
+        let void = field.largest_void();
+        field.toggle(void, true);
+        if void == cluster {
+            break;
+        }
+    }
+
+    // Pixels taken out of the tightest clusters first rank lowest, then pixels put into the
+    // largest voids rank above them
+    let initial = field.clone();
+    let ones = field.pattern.iter().filter(|set| **set).count();
+    let mut ranks = vec![0; count];
+    for rank in (0..ones).rev() {
+        let cluster = field.tightest_cluster();
+        field.toggle(cluster, false);
+        ranks[cluster] = rank;
+    }
+    field = initial;
+    for rank in ones..count {
+        let void = field.largest_void();
+        field.toggle(void, true);
+        ranks[void] = rank;
+    }
+
+    image::GrayImage::from_fn(size, size, |x, y| {
+        image::Luma([(ranks[(y * size + x) as usize] * 256 / count) as u8])
+    })
+}
+
+// Set pixels and how crowded each pixel is by them, wrapping at the edges
+#[derive(Clone)]
+struct VoidAndCluster {
+    size: i64,
+    pattern: Vec<bool>,
+    energy: Vec<f32>,
+    // Gaussian falloff by offset, cut off where it no longer matters
+    kernel: Vec<(i64, i64, f32)>,
+}
+
+impl VoidAndCluster {
+    const SIGMA: f32 = 1.5;
+    const RADIUS: i64 = 6;
+
+    fn new(size: u32) -> Self {
+        let size = size as i64;
+        let radius = Self::RADIUS.min(size / 2);
+        let mut kernel = Vec::new();
+        for y in -radius..=radius {
+            for x in -radius..=radius {
+                let distance2 = (x * x + y * y) as f32;
+                kernel.push((x, y, (-distance2 / (2.0 * Self::SIGMA * Self::SIGMA)).exp()));
+            }
+        }
+        Self {
+            size,
+            pattern: vec![false; (size * size) as usize],
+            energy: vec![0.0; (size * size) as usize],
+            kernel,
+        }
+    }
+
+    fn toggle(&mut self, index: usize, set: bool) {
+        if self.pattern[index] == set {
+            return;
+        }
+        self.pattern[index] = set;
+        let sign = if set { 1.0 } else { -1.0 };
+        let (x, y) = (index as i64 % self.size, index as i64 / self.size);
+        for (offset_x, offset_y, weight) in self.kernel.iter() {
+            let neighbor_x = (x + offset_x).rem_euclid(self.size);
+            let neighbor_y = (y + offset_y).rem_euclid(self.size);
+            self.energy[(neighbor_y * self.size + neighbor_x) as usize] += sign * weight;
+        }
+    }
+
+    // The set pixel most crowded by others
+    fn tightest_cluster(&self) -> usize {
+        self.most_crowded(true, |a, b| a > b)
+    }
+
+    // The unset pixel least crowded by set ones
+    fn largest_void(&self) -> usize {
+        self.most_crowded(false, |a, b| a < b)
+    }
+
+    fn most_crowded(&self, set: bool, better: impl Fn(f32, f32) -> bool) -> usize {
+        let mut best: Option<usize> = None;
+        for (index, energy) in self.energy.iter().enumerate() {
+            if self.pattern[index] == set
+                && best.is_none_or(|best| better(*energy, self.energy[best]))
+            {
+                best = Some(index);
+            }
+        }
+        best.unwrap_or_default()
+    }
+}
+
+fn volume(size: u32, value: impl Fn(glm::Vec3) -> f32) -> Vec<u8> {
+    let mut texels = Vec::with_capacity((size * size * size) as usize);
+    for z in 0..size {
+        for y in 0..size {
+            for x in 0..size {
+                let point = glm::vec3(x as f32, y as f32, z as f32) / size as f32;
+                texels.push(to_byte(value(point)));
+            }
+        }
+    }
+    texels
+}
+
+fn to_byte(value: f32) -> u8 {
+    (value.clamp(0.0, 1.0) * 255.0).round() as u8
+}
+
+fn hash(x: u32, y: u32, z: u32, seed: u32) -> u32 {
+    let mut hash = x.wrapping_mul(0x8da6_b343)
+        ^ y.wrapping_mul(0xd816_3841)
+        ^ z.wrapping_mul(0xcb1a_b31f)
+        ^ seed.wrapping_mul(0x1656_67b1);
+    hash ^= hash >> 15;
+    hash = hash.wrapping_mul(0x2c1b_3c6d);
+    hash ^= hash >> 12;
+    hash = hash.wrapping_mul(0x297a_2d39);
+    hash ^ (hash >> 15)
+}
+
+// Lattice points wrap around the period so the noise tiles
+fn lattice(cell: glm::Vec3, offset: [i64; 3], period: [u32; 3]) -> [u32; 3] {
+    [0, 1, 2].map(|axis| (cell[axis] as i64 + offset[axis]).rem_euclid(period[axis] as i64) as u32)
+}
+
+// Gradient noise of a point in cells, from about -1 to 1
+fn perlin(point: glm::Vec3, period: [u32; 3], seed: u32) -> f32 {
+    const GRADIENTS: [[f32; 3]; 12] = [
+        [1.0, 1.0, 0.0],
+        [-1.0, 1.0, 0.0],
+        [1.0, -1.0, 0.0],
+        [-1.0, -1.0, 0.0],
+        [1.0, 0.0, 1.0],
+        [-1.0, 0.0, 1.0],
+        [1.0, 0.0, -1.0],
+        [-1.0, 0.0, -1.0],
+        [0.0, 1.0, 1.0],
+        [0.0, -1.0, 1.0],
+        [0.0, 1.0, -1.0],
+        [0.0, -1.0, -1.0],
+    ];
+    let cell = point.map(f32::floor);
+    let local = point - cell;
+    let fade = local.map(|t| t * t * t * (t * (t * 6.0 - 15.0) + 10.0));
+    let mut corners = [0.0; 8];
+    for (corner, value) in corners.iter_mut().enumerate() {
+        let offset = [corner & 1, (corner >> 1) & 1, corner >> 2].map(|offset| offset as i64);
+        let [x, y, z] = lattice(cell, offset, period);
+        let gradient = glm::Vec3::from(GRADIENTS[(hash(x, y, z, seed) % 12) as usize]);
+        let to_point = local - glm::vec3(offset[0] as f32, offset[1] as f32, offset[2] as f32);
+        *value = gradient.dot(&to_point);
+    }
+    let x = |low: f32, high: f32| glm::lerp_scalar(low, high, fade.x);
+    let y = |low: f32, high: f32| glm::lerp_scalar(low, high, fade.y);
+    let near = y(x(corners[0], corners[1]), x(corners[2], corners[3]));
+    let far = y(x(corners[4], corners[5]), x(corners[6], corners[7]));
+    glm::lerp_scalar(near, far, fade.z)
+}
+
+// Octaves of perlin noise across a point from 0 to 1, mapped to about 0 to 1
+fn fractal_perlin(point: glm::Vec3, period: [u32; 3], octaves: u32, seed: u32) -> f32 {
+    let mut total = 0.0;
+    let mut amplitude = 0.5;
+    let mut period = period.map(|cells| cells.max(1));
+    for octave in 0..octaves.max(1) {
+        let scale = glm::vec3(period[0] as f32, period[1] as f32, period[2] as f32);
+        total += perlin(
+            point.component_mul(&scale),
+            period,
+            seed.wrapping_add(octave),
+        ) * amplitude;
+        amplitude *= 0.5;
+        // Flat axes stay a single cell deep
+        period = period.map(|cells| if cells > 1 { cells * 2 } else { cells });
+    }
+    total + 0.5
+}
+
+// Distance from a point from 0 to 1 to the nearest feature point, in cells
+fn worley(point: glm::Vec3, cells: [u32; 3], seed: u32) -> f32 {
+    let cells = cells.map(|cells| cells.max(1));
+    let scale = glm::vec3(cells[0] as f32, cells[1] as f32, cells[2] as f32);
+    let point = point.component_mul(&scale);
+    let cell = point.map(f32::floor);
+    let mut nearest = f32::MAX;
+    for z in -1..=1 {
+        for y in -1..=1 {
+            for x in -1..=1 {
+                let [hx, hy, hz] = lattice(cell, [x, y, z], cells);
+                let hashed = hash(hx, hy, hz, seed);
+                let jitter = |shift: u32| ((hashed >> shift) & 0x3ff) as f32 / 1023.0;
+                // Flat axes keep their points on the plane that is sampled
+                let feature = glm::vec3(
+                    jitter(0),
+                    jitter(10),
+                    if cells[2] > 1 { jitter(20) } else { 0.0 },
+                );
+                let neighbor = cell + glm::vec3(x as f32, y as f32, z as f32) + feature;
+                nearest = nearest.min(glm::distance(&point, &neighbor));
+            }
+        }
+    }
+    nearest
+}
 
 pub struct Texture {
-    pub texture: wgpu::Texture,
+    #[allow(dead_code)]
+    pub texture: Tracked<wgpu::Texture>,
     pub view: wgpu::TextureView,
     pub sampler: wgpu::Sampler,
 }
@@ -19,30 +416,50 @@ impl Texture {
         Self::from_image(device, queue, &img, Some(label))
     }
 
-    #[allow(dead_code)]
     pub fn from_image(
         device: &wgpu::Device,
         queue: &wgpu::Queue,
         img: &image::DynamicImage,
         label: Option<&str>,
     ) -> Result<Self> {
-        let rgba = img.as_rgba8().unwrap();
-        let dimensions = img.dimensions();
+        Self::from_image_with_format(
+            device,
+            queue,
+            img,
+            wgpu::TextureFormat::Rgba8UnormSrgb,
+            label,
+        )
+    }
+
+    // Non-color data such as normal maps must not be stored as sRGB
+    pub fn from_image_with_format(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        img: &image::DynamicImage,
+        format: wgpu::TextureFormat,
+        label: Option<&str>,
+    ) -> Result<Self> {
+        let rgba = img.to_rgba8();
+        let dimensions = rgba.dimensions();
 
         let size = wgpu::Extent3d {
             width: dimensions.0,
             height: dimensions.1,
             depth_or_array_layers: 1,
         };
-        let texture = device.create_texture(&wgpu::TextureDescriptor {
-            label,
-            size,
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: wgpu::TextureDimension::D2,
-            format: wgpu::TextureFormat::Rgba8UnormSrgb,
-            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
-        });
+        let texture = memory::create_texture(
+            device,
+            MemoryCategory::Textures,
+            &wgpu::TextureDescriptor {
+                label,
+                size,
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            },
+        );
 
         queue.write_texture(
             wgpu::ImageCopyTexture {
@@ -51,7 +468,7 @@ impl Texture {
                 origin: wgpu::Origin3d::ZERO,
                 aspect: wgpu::TextureAspect::All,
             },
-            rgba,
+            &rgba,
             wgpu::ImageDataLayout {
                 offset: 0,
                 bytes_per_row: std::num::NonZeroU32::new(4 * dimensions.0),
@@ -78,12 +495,217 @@ impl Texture {
         })
     }
 
+    // All images must share the same dimensions
+    pub fn array_from_images(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        images: &[image::RgbaImage],
+        format: wgpu::TextureFormat,
+        label: Option<&str>,
+    ) -> Result<Self> {
+        let (width, height) = images
+            .first()
+            .map(|image| image.dimensions())
+            .context("Texture arrays require at least one image!")?;
+
+        let size = wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: images.len() as u32,
+        };
+        let texture = memory::create_texture(
+            device,
+            MemoryCategory::Textures,
+            &wgpu::TextureDescriptor {
+                label,
+                size,
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            },
+        );
+
+        for (layer, image) in images.iter().enumerate() {
+            if image.dimensions() != (width, height) {
+                bail!("All images in a texture array must have the same dimensions!");
+            }
+            queue.write_texture(
+                wgpu::ImageCopyTexture {
+                    texture: &texture,
+                    mip_level: 0,
+                    origin: wgpu::Origin3d {
+                        x: 0,
+                        y: 0,
+                        z: layer as u32,
+                    },
+                    aspect: wgpu::TextureAspect::All,
+                },
+                image,
+                wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: std::num::NonZeroU32::new(4 * width),
+                    rows_per_image: std::num::NonZeroU32::new(height),
+                },
+                wgpu::Extent3d {
+                    width,
+                    height,
+                    depth_or_array_layers: 1,
+                },
+            );
+        }
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(wgpu::TextureViewDimension::D2Array),
+            ..Default::default()
+        });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::Repeat,
+            address_mode_v: wgpu::AddressMode::Repeat,
+            address_mode_w: wgpu::AddressMode::Repeat,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+
+        Ok(Self {
+            texture,
+            view,
+            sampler,
+        })
+    }
+
+    // A cube of single channel texels, such as procedural noise, sampled with repeating wrapping
+    #[allow(dead_code)]
+    pub fn from_volume(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        size: u32,
+        texels: &[u8],
+        label: Option<&str>,
+    ) -> Result<Self> {
+        if texels.len() != (size * size * size) as usize {
+            bail!(
+                "A volume of size {} needs {} texels!",
+                size,
+                size * size * size
+            );
+        }
+        let extent = wgpu::Extent3d {
+            width: size,
+            height: size,
+            depth_or_array_layers: size,
+        };
+        let texture = memory::create_texture(
+            device,
+            MemoryCategory::Textures,
+            &wgpu::TextureDescriptor {
+                label,
+                size: extent,
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D3,
+                format: wgpu::TextureFormat::R8Unorm,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            },
+        );
+        queue.write_texture(
+            wgpu::ImageCopyTexture {
+                texture: &texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            texels,
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: std::num::NonZeroU32::new(size),
+                rows_per_image: std::num::NonZeroU32::new(size),
+            },
+            extent,
+        );
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::Repeat,
+            address_mode_v: wgpu::AddressMode::Repeat,
+            address_mode_w: wgpu::AddressMode::Repeat,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+        Ok(Self {
+            texture,
+            view,
+            sampler,
+        })
+    }
+
     pub const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float; // 1.
 
+    // The scene's depth along with a stencil, which can't be copied out like DEPTH_FORMAT can
+    pub const DEPTH_STENCIL_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth24PlusStencil8;
+
+    // The scene is lit in linear HDR and resolved to the surface format at the end of the frame
+    pub const HDR_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+    // Screen space motion since the previous frame, in texture coordinates
+    pub const VELOCITY_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rg16Float;
+
+    pub fn create_render_target(
+        device: &wgpu::Device,
+        width: u32,
+        height: u32,
+        format: wgpu::TextureFormat,
+        label: &str,
+    ) -> Self {
+        let texture = memory::create_texture(
+            device,
+            MemoryCategory::Targets,
+            &wgpu::TextureDescriptor {
+                label: Some(label),
+                size: wgpu::Extent3d {
+                    width,
+                    height,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                    | wgpu::TextureUsages::TEXTURE_BINDING
+                    | wgpu::TextureUsages::COPY_SRC
+                    | wgpu::TextureUsages::COPY_DST,
+            },
+        );
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+
+        Self {
+            texture,
+            view,
+            sampler,
+        }
+    }
+
+    // The view only has the depth aspect so it can be sampled, see create_attachment_view
     pub fn create_depth_texture(
         device: &wgpu::Device,
         width: u32,
         height: u32,
+        depth: DepthConfig,
         label: &str,
     ) -> Self {
         let size = wgpu::Extent3d {
@@ -97,13 +719,17 @@ impl Texture {
             mip_level_count: 1,
             sample_count: 1,
             dimension: wgpu::TextureDimension::D2,
-            format: Self::DEPTH_FORMAT,
+            format: depth.format,
             usage: wgpu::TextureUsages::RENDER_ATTACHMENT // 3.
-                | wgpu::TextureUsages::TEXTURE_BINDING,
+                | wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::COPY_SRC,
         };
-        let texture = device.create_texture(&desc);
+        let texture = memory::create_texture(device, MemoryCategory::Targets, &desc);
 
-        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            aspect: wgpu::TextureAspect::DepthOnly,
+            ..Default::default()
+        });
         let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
             // 4.
             address_mode_u: wgpu::AddressMode::ClampToEdge,
@@ -112,7 +738,7 @@ impl Texture {
             mag_filter: wgpu::FilterMode::Linear,
             min_filter: wgpu::FilterMode::Linear,
             mipmap_filter: wgpu::FilterMode::Nearest,
-            compare: Some(wgpu::CompareFunction::LessEqual), // 5.
+            compare: Some(depth.compare(wgpu::CompareFunction::LessEqual)), // 5.
             lod_min_clamp: -100.0,
             lod_max_clamp: 100.0,
             ..Default::default()
@@ -124,4 +750,168 @@ impl Texture {
             sampler,
         }
     }
+
+    // Depth and stencil are attached together through a view of every aspect
+    pub fn create_attachment_view(&self) -> wgpu::TextureView {
+        self.texture
+            .create_view(&wgpu::TextureViewDescriptor::default())
+    }
+}
+
+// How depth is stored, chosen at startup since every depth tested pipeline is built around it
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct DepthConfig {
+    // Texture::DEPTH_STENCIL_FORMAT, or Texture::DEPTH_FORMAT without a stencil
+    pub format: wgpu::TextureFormat,
+    // The near plane is at one and the far plane at zero, which pairs the precision floating
+    // point has near zero with the precision perspective loses with distance
+    pub reversed: bool,
+    // Samples per pixel of the passes drawing opaque surfaces and the sky, which are resolved into
+    // the scene color. Everything after them, and the depth prepass, stays at one sample
+    pub samples: u32,
+}
+
+impl DepthConfig {
+    // Reversed depth only pays off in floating point, which has no stencil to go with it
+    pub fn new(stencil: bool, reversed: bool, samples: u32) -> Self {
+        let format = if stencil && !reversed {
+            Texture::DEPTH_STENCIL_FORMAT
+        } else {
+            Texture::DEPTH_FORMAT
+        };
+        Self {
+            format,
+            reversed,
+            samples,
+        }
+    }
+
+    // Shadow maps never have a stencil or multisampling but are reversed along with the scene
+    pub fn shadow(&self) -> Self {
+        Self {
+            format: Texture::DEPTH_FORMAT,
+            samples: 1,
+            ..*self
+        }
+    }
+
+    // For pipelines drawn in the opaque pass
+    pub fn multisample(&self) -> wgpu::MultisampleState {
+        wgpu::MultisampleState {
+            count: self.samples,
+            ..Default::default()
+        }
+    }
+
+    pub fn has_stencil(&self) -> bool {
+        self.format == Texture::DEPTH_STENCIL_FORMAT
+    }
+
+    // Depth is cleared to the far plane
+    pub fn far(&self) -> f32 {
+        if self.reversed {
+            0.0
+        } else {
+            1.0
+        }
+    }
+
+    // Compare functions are written for standard depth, where nearer is less
+    pub fn compare(&self, compare: wgpu::CompareFunction) -> wgpu::CompareFunction {
+        if !self.reversed {
+            return compare;
+        }
+        match compare {
+            wgpu::CompareFunction::Less => wgpu::CompareFunction::Greater,
+            wgpu::CompareFunction::LessEqual => wgpu::CompareFunction::GreaterEqual,
+            wgpu::CompareFunction::Greater => wgpu::CompareFunction::Less,
+            wgpu::CompareFunction::GreaterEqual => wgpu::CompareFunction::LessEqual,
+            compare => compare,
+        }
+    }
+
+    // Biases are written for standard depth as well, pushing depth away from the light
+    pub fn bias(&self, bias: wgpu::DepthBiasState) -> wgpu::DepthBiasState {
+        if self.reversed {
+            wgpu::DepthBiasState {
+                constant: -bias.constant,
+                slope_scale: -bias.slope_scale,
+                ..bias
+            }
+        } else {
+            bias
+        }
+    }
+
+    // Maps depth z to w - z, which puts the near plane of a standard projection at one
+    pub fn projection(&self, projection: glm::Mat4) -> glm::Mat4 {
+        if !self.reversed {
+            return projection;
+        }
+        let mut reverse = glm::Mat4::identity();
+        reverse[(2, 2)] = -1.0;
+        reverse[(2, 3)] = 1.0;
+        reverse * projection
+    }
+
+    // Depth as a standard projection would have written it
+    pub fn standard(&self, depth: f32) -> f32 {
+        if self.reversed {
+            1.0 - depth
+        } else {
+            depth
+        }
+    }
+}
+
+// The color and depth the opaque pass draws into while multisampling, resolved into a single
+// sampled target at the end of the pass. Its depth starts cleared, since the depth prepass is
+// single sampled
+pub struct MultisampledTarget {
+    _color: Tracked<wgpu::Texture>,
+    pub color_view: wgpu::TextureView,
+    _depth: Tracked<wgpu::Texture>,
+    pub depth_view: wgpu::TextureView,
+}
+
+impl MultisampledTarget {
+    // Only made when multisampling is on
+    pub fn new(
+        device: &wgpu::Device,
+        dimensions: &[u32; 2],
+        format: wgpu::TextureFormat,
+        depth: DepthConfig,
+        label: &str,
+    ) -> Option<Self> {
+        if depth.samples <= 1 {
+            return None;
+        }
+        let create = |label: &str, format| {
+            memory::create_texture(
+                device,
+                MemoryCategory::Targets,
+                &wgpu::TextureDescriptor {
+                    label: Some(label),
+                    size: wgpu::Extent3d {
+                        width: dimensions[0],
+                        height: dimensions[1],
+                        depth_or_array_layers: 1,
+                    },
+                    mip_level_count: 1,
+                    sample_count: depth.samples,
+                    dimension: wgpu::TextureDimension::D2,
+                    format,
+                    usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+                },
+            )
+        };
+        let color = create(&format!("{} Multisampled Color", label), format);
+        let depth = create(&format!("{} Multisampled Depth", label), depth.format);
+        Some(Self {
+            color_view: color.create_view(&wgpu::TextureViewDescriptor::default()),
+            _color: color,
+            depth_view: depth.create_view(&wgpu::TextureViewDescriptor::default()),
+            _depth: depth,
+        })
+    }
 }
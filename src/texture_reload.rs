@@ -0,0 +1,83 @@
+use instant::Instant;
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use crate::scene::{ModelHandle, Scene};
+
+// How often the files are checked for changes
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+// Reloads the images of models whose files change on disk, so textures painted in another tool
+// show up as they're saved. Files are polled rather than watched, which works the same everywhere
+#[derive(Default)]
+pub struct TextureWatcher {
+    // Each file and when it was last modified, by model and image
+    files: HashMap<(ModelHandle, usize), (PathBuf, SystemTime)>,
+    // The scene's models revision and number of models the files were gathered at
+    gathered: Option<(u64, usize)>,
+    last_poll: Option<Instant>,
+}
+
+impl TextureWatcher {
+    // Returns a message for each image reloaded or that failed to reload
+    pub fn update(&mut self, scene: &mut Scene, enabled: bool) -> Vec<String> {
+        if !enabled {
+            self.gathered = None;
+            return Vec::new();
+        }
+
+        let gathered = (scene.models_revision(), scene.models.len());
+        if self.gathered != Some(gathered) {
+            self.gathered = Some(gathered);
+            self.files = gather_files(scene);
+        }
+
+        if self
+            .last_poll
+            .is_some_and(|last_poll| last_poll.elapsed() < POLL_INTERVAL)
+        {
+            return Vec::new();
+        }
+        self.last_poll = Some(Instant::now());
+
+        let mut messages = Vec::new();
+        for ((model, image), (path, modified)) in self.files.iter_mut() {
+            match last_modified(path) {
+                Some(time) if time != *modified => *modified = time,
+                _ => continue,
+            }
+            // A file still being written fails to decode, and is read again once it's saved
+            match image::open(&path) {
+                Ok(source) => {
+                    if scene.replace_image(*model, *image, source) {
+                        messages.push(format!("Reloaded {}", path.display()));
+                    }
+                }
+                Err(error) => {
+                    messages.push(format!("Failed to reload {}: {}", path.display(), error))
+                }
+            }
+        }
+        messages
+    }
+}
+
+fn gather_files(scene: &Scene) -> HashMap<(ModelHandle, usize), (PathBuf, SystemTime)> {
+    let mut files = HashMap::new();
+    for (index, model) in scene.models.iter().enumerate() {
+        for (image, path) in model.image_files.iter() {
+            // Files that can't be read, such as in the browser, aren't watched
+            if let Some(modified) = last_modified(path) {
+                files.insert((ModelHandle(index), *image), (path.clone(), modified));
+            }
+        }
+    }
+    files
+}
+
+fn last_modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).ok()?.modified().ok()
+}
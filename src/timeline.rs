@@ -0,0 +1,123 @@
+use crate::{
+    animation::Animations,
+    scene::{ModelHandle, Scene},
+};
+
+const SCRUBBER_WIDTH: f32 = 320.0;
+
+// A playback bar along the bottom of the window for the animation clips of loaded models, shown
+// while any model has clips
+#[derive(Default)]
+pub struct Timeline {
+    // The animated model being played, the first one when unset
+    model: Option<ModelHandle>,
+}
+
+impl Timeline {
+    pub fn show(&mut self, context: &egui::CtxRef, scene: &mut Scene) {
+        let animated = scene
+            .models
+            .iter()
+            .enumerate()
+            .filter(|(_, model)| model.animations.is_some())
+            .map(|(index, model)| {
+                let name = model
+                    .name
+                    .clone()
+                    .unwrap_or_else(|| format!("Model {}", index));
+                (ModelHandle(index), name)
+            })
+            .collect::<Vec<_>>();
+        let mut model = match self
+            .model
+            .filter(|model| animated.iter().any(|(handle, _)| handle == model))
+            .or_else(|| animated.first().map(|(handle, _)| *handle))
+        {
+            Some(model) => model,
+            None => return,
+        };
+
+        egui::Window::new("Animation")
+            .anchor(egui::Align2::CENTER_BOTTOM, [0.0, -8.0])
+            .resizable(false)
+            .show(context, |ui| {
+                if animated.len() > 1 {
+                    let selected = animated
+                        .iter()
+                        .find(|(handle, _)| *handle == model)
+                        .map(|(_, name)| name.clone())
+                        .unwrap_or_default();
+                    egui::ComboBox::from_label("Model")
+                        .selected_text(selected)
+                        .show_ui(ui, |ui| {
+                            for (handle, name) in animated.iter() {
+                                ui.selectable_value(&mut model, *handle, name);
+                            }
+                        });
+                }
+                if let Some(animations) = scene
+                    .models
+                    .get_mut(model.0)
+                    .and_then(|model| model.animations.as_mut())
+                {
+                    playback_controls(ui, animations);
+                }
+            });
+        self.model = Some(model);
+    }
+}
+
+fn playback_controls(ui: &mut egui::Ui, animations: &mut Animations) {
+    let clip_name = |index: usize| {
+        animations.clips[index]
+            .name
+            .clone()
+            .unwrap_or_else(|| format!("Clip {}", index))
+    };
+    let mut clip = animations.player.clip;
+    egui::ComboBox::from_label("Clip")
+        .selected_text(clip_name(clip.min(animations.clips.len() - 1)))
+        .show_ui(ui, |ui| {
+            for index in 0..animations.clips.len() {
+                ui.selectable_value(&mut clip, index, clip_name(index));
+            }
+        });
+    let player = &mut animations.player;
+    if clip != player.clip {
+        player.clip = clip;
+        player.time = 0.0;
+    }
+
+    ui.horizontal(|ui| {
+        let label = if player.playing { "Pause" } else { "Play" };
+        if ui.button(label).clicked() {
+            player.playing = !player.playing;
+        }
+        ui.checkbox(&mut player.looping, "Loop");
+        ui.add(
+            egui::DragValue::new(&mut player.speed)
+                .speed(0.01)
+                .clamp_range(-4.0..=4.0)
+                .suffix("x"),
+        );
+        ui.label("Speed");
+    });
+
+    let duration = animations
+        .clips
+        .get(player.clip)
+        .map_or(0.0, |clip| clip.duration);
+    let mut time = player.time;
+    ui.spacing_mut().slider_width = SCRUBBER_WIDTH;
+    let scrubbed = ui
+        .add(
+            egui::Slider::new(&mut time, 0.0..=duration)
+                .suffix("s")
+                .text(format!("/ {:.2}s", duration)),
+        )
+        .changed();
+    // Dragging the scrubber pauses playback at the time it's dragged to
+    if scrubbed {
+        player.seek(time);
+    }
+}
@@ -0,0 +1,227 @@
+use std::rc::Rc;
+
+use crate::memory::{self, MemoryCategory, Tracked};
+use crate::render_targets::{FramePhase, RenderTargetPool};
+use crate::{shader_snippets, texture::Texture};
+
+#[repr(C)]
+#[derive(Default, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct UpscaleUniform {
+    ratio: [f32; 4],
+}
+
+// Upscales the HDR scene color to the output resolution with an edge adaptive filter before post
+// processing. Without it the post process filters the scene color bilinearly instead
+pub struct UpscaleSystem {
+    uniform_buffer: Tracked<wgpu::Buffer>,
+    enabled: bool,
+    bind_group_layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+    output: Rc<Texture>,
+    pipeline: wgpu::RenderPipeline,
+}
+
+impl UpscaleSystem {
+    pub fn new(
+        device: &wgpu::Device,
+        target_pool: &mut RenderTargetPool,
+        scene_color: &Texture,
+    ) -> Self {
+        let uniform_buffer = memory::create_buffer(
+            device,
+            MemoryCategory::Transient,
+            &wgpu::BufferDescriptor {
+                label: Some("Upscale Uniform Buffer"),
+                size: std::mem::size_of::<UpscaleUniform>() as wgpu::BufferAddress,
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            },
+        );
+
+        let bind_group_layout = memory::create_bind_group_layout(
+            device,
+            &wgpu::BindGroupLayoutDescriptor {
+                label: Some("Upscale Bind Group Layout"),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                ],
+            },
+        );
+
+        let module = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("Upscale Shader"),
+            source: wgpu::ShaderSource::Wgsl(
+                shader_snippets::splice(include_str!("shaders/upscale.wgsl")).into(),
+            ),
+        });
+
+        let pipeline_layout = memory::create_pipeline_layout(
+            device,
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("Upscale Pipeline Layout"),
+                bind_group_layouts: &[&bind_group_layout],
+                push_constant_ranges: &[],
+            },
+        );
+
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Upscale Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &module,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            fragment: Some(wgpu::FragmentState {
+                module: &module,
+                entry_point: "fs_main",
+                targets: &[wgpu::ColorTargetState {
+                    format: Texture::HDR_FORMAT,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                }],
+            }),
+        });
+
+        let bind_group =
+            Self::create_bind_group(device, &bind_group_layout, &uniform_buffer, scene_color);
+        let output = Self::create_output(device, target_pool, &[1, 1]);
+
+        Self {
+            uniform_buffer,
+            enabled: false,
+            bind_group_layout,
+            bind_group,
+            output,
+            pipeline,
+        }
+    }
+
+    fn create_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        uniform_buffer: &wgpu::Buffer,
+        scene_color: &Texture,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Upscale Bind Group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&scene_color.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+            ],
+        })
+    }
+
+    fn create_output(
+        device: &wgpu::Device,
+        target_pool: &mut RenderTargetPool,
+        dimensions: &[u32; 2],
+    ) -> Rc<Texture> {
+        target_pool.acquire(
+            device,
+            dimensions[0],
+            dimensions[1],
+            Texture::HDR_FORMAT,
+            "Upscale Output Texture",
+            FramePhase::Upscale..=FramePhase::PostProcess,
+        )
+    }
+
+    // Only upscales when asked to and the scene is rendered below the output resolution
+    #[allow(clippy::too_many_arguments)]
+    pub fn resize(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        target_pool: &mut RenderTargetPool,
+        scene_color: &Texture,
+        render_dimensions: &[u32; 2],
+        output_dimensions: &[u32; 2],
+        enabled: bool,
+    ) {
+        self.enabled = enabled
+            && (render_dimensions[0] < output_dimensions[0]
+                || render_dimensions[1] < output_dimensions[1]);
+        self.bind_group = Self::create_bind_group(
+            device,
+            &self.bind_group_layout,
+            &self.uniform_buffer,
+            scene_color,
+        );
+        // A placeholder keeps the output from taking up memory while unused
+        let dimensions = if self.enabled {
+            *output_dimensions
+        } else {
+            [1, 1]
+        };
+        self.output = Self::create_output(device, target_pool, &dimensions);
+        let uniform = UpscaleUniform {
+            ratio: [
+                render_dimensions[0] as f32 / output_dimensions[0] as f32,
+                render_dimensions[1] as f32 / output_dimensions[1] as f32,
+                0.0,
+                0.0,
+            ],
+        };
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[uniform]));
+    }
+
+    // What the post process should read the scene color from
+    pub fn texture<'a>(&'a self, scene_color: &'a Texture) -> &'a Texture {
+        if self.enabled {
+            &self.output
+        } else {
+            scene_color
+        }
+    }
+
+    pub fn render(&self, encoder: &mut wgpu::CommandEncoder) {
+        if !self.enabled {
+            return;
+        }
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Upscale Pass"),
+            color_attachments: &[wgpu::RenderPassColorAttachment {
+                view: &self.output.view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: true,
+                },
+            }],
+            depth_stencil_attachment: None,
+        });
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
@@ -0,0 +1,174 @@
+use crate::{bounds::Frustum, memory, model::ModelSystem, shader_snippets, texture::Texture};
+
+// Screen space motion of every pixel since the previous frame, from the camera everywhere and
+// from models where they were drawn
+pub struct VelocitySystem {
+    texture: Texture,
+    bind_group_layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+    camera_pipeline: wgpu::RenderPipeline,
+}
+
+impl VelocitySystem {
+    pub fn new(
+        device: &wgpu::Device,
+        camera_bind_group_layout: &wgpu::BindGroupLayout,
+        depth_texture: &Texture,
+        dimensions: &[u32; 2],
+    ) -> Self {
+        let bind_group_layout = memory::create_bind_group_layout(
+            device,
+            &wgpu::BindGroupLayoutDescriptor {
+                label: Some("Velocity Bind Group Layout"),
+                entries: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Depth,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                }],
+            },
+        );
+
+        let module = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("Velocity Shader"),
+            source: wgpu::ShaderSource::Wgsl(
+                shader_snippets::splice(include_str!("shaders/velocity.wgsl")).into(),
+            ),
+        });
+
+        let pipeline_layout = memory::create_pipeline_layout(
+            device,
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("Velocity Pipeline Layout"),
+                bind_group_layouts: &[camera_bind_group_layout, &bind_group_layout],
+                push_constant_ranges: &[],
+            },
+        );
+
+        let camera_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Camera Velocity Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &module,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            fragment: Some(wgpu::FragmentState {
+                module: &module,
+                entry_point: "fs_camera",
+                targets: &[wgpu::ColorTargetState {
+                    format: Texture::VELOCITY_FORMAT,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                }],
+            }),
+        });
+
+        let texture = Self::create_texture(device, dimensions);
+        let bind_group = Self::create_bind_group(device, &bind_group_layout, depth_texture);
+
+        Self {
+            texture,
+            bind_group_layout,
+            bind_group,
+            camera_pipeline,
+        }
+    }
+
+    fn create_texture(device: &wgpu::Device, dimensions: &[u32; 2]) -> Texture {
+        Texture::create_render_target(
+            device,
+            dimensions[0],
+            dimensions[1],
+            Texture::VELOCITY_FORMAT,
+            "Velocity Texture",
+        )
+    }
+
+    fn create_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        depth_texture: &Texture,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Velocity Bind Group"),
+            layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&depth_texture.view),
+            }],
+        })
+    }
+
+    pub fn texture(&self) -> &Texture {
+        &self.texture
+    }
+
+    pub fn resize(
+        &mut self,
+        device: &wgpu::Device,
+        depth_texture: &Texture,
+        dimensions: &[u32; 2],
+    ) {
+        self.texture = Self::create_texture(device, dimensions);
+        self.bind_group = Self::create_bind_group(device, &self.bind_group_layout, depth_texture);
+    }
+
+    // Needs the depth prepass, only effects that use the velocity ask for it
+    pub fn render(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        camera_bind_group: &wgpu::BindGroup,
+        depth_attachment: &wgpu::TextureView,
+        model_system: &ModelSystem,
+        frustum: &Frustum,
+        camera_layers: u32,
+    ) {
+        {
+            let mut camera_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Camera Velocity Pass"),
+                color_attachments: &[wgpu::RenderPassColorAttachment {
+                    view: &self.texture.view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                        store: true,
+                    },
+                }],
+                depth_stencil_attachment: None,
+            });
+            camera_pass.set_pipeline(&self.camera_pipeline);
+            camera_pass.set_bind_group(0, camera_bind_group, &[]);
+            camera_pass.set_bind_group(1, &self.bind_group, &[]);
+            camera_pass.draw(0..3, 0..1);
+        }
+
+        let mut object_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Object Velocity Pass"),
+            color_attachments: &[wgpu::RenderPassColorAttachment {
+                view: &self.texture.view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: true,
+                },
+            }],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: depth_attachment,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: true,
+                }),
+                stencil_ops: None,
+            }),
+        });
+        model_system.render_velocity(&mut object_pass, camera_bind_group, frustum, camera_layers);
+    }
+}
@@ -0,0 +1,165 @@
+use nalgebra_glm as glm;
+
+use crate::{
+    blit::{BlitPipeline, BlitUniform},
+    camera::{Camera, Projection},
+    memory::{self, MemoryCategory, Tracked},
+    texture::Texture,
+};
+
+// How a viewport looks at its camera's target, the axis aligned views are orthographic
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ViewMode {
+    Perspective,
+    // Looking down the Y axis
+    Top,
+    // Looking down the Z axis
+    Front,
+    // Looking down the X axis
+    Side,
+}
+
+pub struct Viewport {
+    // Left, top, width and height as fractions of the window
+    pub rectangle: glm::Vec4,
+    // Follows the main camera when none is given
+    pub camera: Option<Camera>,
+    pub view_mode: ViewMode,
+}
+
+impl Viewport {
+    pub fn new(rectangle: glm::Vec4, view_mode: ViewMode) -> Self {
+        Self {
+            rectangle,
+            camera: None,
+            view_mode,
+        }
+    }
+
+    // Side by side views, the first following the main camera and the rest starting from it
+    pub fn split_screen(camera: &Camera, count: usize) -> Vec<Self> {
+        let width = 1.0 / count.max(1) as f32;
+        (0..count.max(1))
+            .map(|index| Self {
+                rectangle: glm::vec4(index as f32 * width, 0.0, width, 1.0),
+                camera: (index > 0).then(|| camera.clone()),
+                view_mode: ViewMode::Perspective,
+            })
+            .collect()
+    }
+
+    // The perspective view with top, front and side views of the same target around it
+    pub fn quad() -> Vec<Self> {
+        [
+            (0.0, 0.0, ViewMode::Perspective),
+            (0.5, 0.0, ViewMode::Top),
+            (0.0, 0.5, ViewMode::Front),
+            (0.5, 0.5, ViewMode::Side),
+        ]
+        .iter()
+        .map(|(x, y, view_mode)| Self::new(glm::vec4(*x, *y, 0.5, 0.5), *view_mode))
+        .collect()
+    }
+
+    // The rectangle in pixels, kept inside the window and at least a pixel in size
+    pub fn pixel_rectangle(&self, dimensions: &[u32; 2]) -> [u32; 4] {
+        let [width, height] = [dimensions[0].max(1), dimensions[1].max(1)];
+        let to_pixels = |fraction: f32, size: u32| (fraction.clamp(0.0, 1.0) * size as f32) as u32;
+        let x = to_pixels(self.rectangle.x, width).min(width - 1);
+        let y = to_pixels(self.rectangle.y, height).min(height - 1);
+        [
+            x,
+            y,
+            to_pixels(self.rectangle.z, width).clamp(1, width - x),
+            to_pixels(self.rectangle.w, height).clamp(1, height - y),
+        ]
+    }
+
+    // The camera the viewport renders with, framing the same area as the perspective view does
+    // at its target when looking along an axis
+    pub fn view_camera(&self, main_camera: &Camera, pixel_rectangle: &[u32; 4]) -> Camera {
+        let base = self.camera.as_ref().unwrap_or(main_camera);
+        let mut camera = base.clone();
+        camera.aspect_ratio = Some(pixel_rectangle[2] as f32 / pixel_rectangle[3] as f32);
+
+        let (axis, up) = match self.view_mode {
+            ViewMode::Perspective => return camera,
+            ViewMode::Top => (glm::Vec3::y(), -glm::Vec3::z()),
+            ViewMode::Front => (glm::Vec3::z(), glm::Vec3::y()),
+            ViewMode::Side => (glm::Vec3::x(), glm::Vec3::y()),
+        };
+        let distance = glm::distance(&base.position, &base.target);
+        camera.projection = Projection::Orthographic {
+            height: 2.0 * distance * (base.vertical_fov() * 0.5).tan(),
+        };
+        // Pulled back so geometry on both sides of the target is in view
+        camera.position = base.target + axis * base.z_far * 0.5;
+        camera.up = up;
+        camera
+    }
+}
+
+// Where each viewport's frame is rendered before it is copied into its rectangle
+pub struct ViewportTarget {
+    texture: Texture,
+    bind_group: wgpu::BindGroup,
+    blit_pipeline: BlitPipeline,
+    // Only read through the bind group
+    #[allow(dead_code)]
+    uniform_buffer: Tracked<wgpu::Buffer>,
+}
+
+impl ViewportTarget {
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat, dimensions: &[u32; 2]) -> Self {
+        let texture = Texture::create_render_target(
+            device,
+            dimensions[0],
+            dimensions[1],
+            format,
+            "Viewport Target Texture",
+        );
+        // The frame was already rendered with the viewport's aspect ratio, so it is stretched back
+        let uniform_buffer = memory::create_buffer_init(
+            device,
+            MemoryCategory::Transient,
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("Viewport Blit Uniform Buffer"),
+                contents: bytemuck::cast_slice(&[BlitUniform::stretch()]),
+                usage: wgpu::BufferUsages::UNIFORM,
+            },
+        );
+        let blit_pipeline = BlitPipeline::new(device, format);
+        let bind_group = blit_pipeline.create_bind_group(device, &texture, &uniform_buffer);
+        Self {
+            texture,
+            bind_group,
+            blit_pipeline,
+            uniform_buffer,
+        }
+    }
+
+    pub fn view(&self) -> &wgpu::TextureView {
+        &self.texture.view
+    }
+
+    pub fn texture(&self) -> &wgpu::Texture {
+        &self.texture.texture
+    }
+
+    // The first viewport clears the rest of the window
+    pub fn blit(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
+        pixel_rectangle: [u32; 4],
+        first: bool,
+    ) {
+        let load = if first {
+            wgpu::LoadOp::Clear(wgpu::Color::BLACK)
+        } else {
+            wgpu::LoadOp::Load
+        };
+        self.blit_pipeline
+            .blit(encoder, view, &self.bind_group, load, Some(pixel_rectangle));
+    }
+}
@@ -0,0 +1,880 @@
+use anyhow::Result;
+use nalgebra_glm as glm;
+use std::{collections::HashMap, sync::Arc};
+#[cfg(not(target_arch = "wasm32"))]
+use std::{
+    sync::{
+        mpsc::{channel, Receiver, Sender},
+        Mutex,
+    },
+    thread,
+};
+
+use crate::{
+    bounds::{Aabb, Frustum},
+    memory::{self, MemoryCategory, Tracked},
+    scene::Scene,
+    texture::{DepthConfig, Texture},
+};
+
+// Blocks along each side of a chunk
+pub const CHUNK_SIZE: i32 = 32;
+
+// Chunks are meshed with a border of their neighbours' blocks so faces between chunks are culled
+const PADDED_SIZE: i32 = CHUNK_SIZE + 2;
+
+pub type BlockId = u16;
+
+// Empty space, every other block id refers to a block of the world
+pub const AIR: BlockId = 0;
+
+// Layers of the world's texture array shown on each side of a block
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct BlockDesc {
+    pub top: u32,
+    pub side: u32,
+    pub bottom: u32,
+}
+
+impl BlockDesc {
+    #[allow(dead_code)]
+    pub fn uniform(layer: u32) -> Self {
+        Self {
+            top: layer,
+            side: layer,
+            bottom: layer,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct VoxelChunk {
+    blocks: Vec<BlockId>,
+    // Changes whenever the chunk needs meshing again
+    revision: u64,
+}
+
+impl VoxelChunk {
+    fn new() -> Self {
+        Self {
+            blocks: vec![AIR; (CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE) as usize],
+            revision: 0,
+        }
+    }
+
+    fn index(local: [i32; 3]) -> usize {
+        ((local[2] * CHUNK_SIZE + local[1]) * CHUNK_SIZE + local[0]) as usize
+    }
+}
+
+#[derive(Clone)]
+pub struct VoxelWorldDesc {
+    // Layers of the block texture array, every image is resized to match the first
+    pub textures: Vec<image::DynamicImage>,
+    // Block id n is drawn with the block at index n - 1
+    pub blocks: Vec<BlockDesc>,
+    // World space position of the corner of block (0, 0, 0). Chunks are meshed relative to it, so
+    // moving the world doesn't mesh them again
+    pub origin: glm::Vec3,
+    // Edge length of a block in world units, baked into the chunk meshes
+    pub voxel_size: f32,
+    chunks: HashMap<[i32; 3], VoxelChunk>,
+    next_revision: u64,
+}
+
+impl Default for VoxelWorldDesc {
+    fn default() -> Self {
+        Self {
+            textures: Vec::new(),
+            blocks: Vec::new(),
+            origin: glm::Vec3::zeros(),
+            voxel_size: 1.0,
+            chunks: HashMap::new(),
+            next_revision: 1,
+        }
+    }
+}
+
+impl VoxelWorldDesc {
+    fn split(position: [i32; 3]) -> ([i32; 3], [i32; 3]) {
+        (
+            position.map(|value| value.div_euclid(CHUNK_SIZE)),
+            position.map(|value| value.rem_euclid(CHUNK_SIZE)),
+        )
+    }
+
+    pub fn block(&self, position: [i32; 3]) -> BlockId {
+        let (chunk, local) = Self::split(position);
+        self.chunks
+            .get(&chunk)
+            .map(|chunk| chunk.blocks[VoxelChunk::index(local)])
+            .unwrap_or(AIR)
+    }
+
+    #[allow(dead_code)]
+    pub fn set_block(&mut self, position: [i32; 3], block: BlockId) {
+        let (coordinate, local) = Self::split(position);
+        if block == AIR && !self.chunks.contains_key(&coordinate) {
+            return;
+        }
+        let revision = self.next_revision;
+        self.next_revision += 1;
+
+        let chunk = self
+            .chunks
+            .entry(coordinate)
+            .or_insert_with(VoxelChunk::new);
+        let index = VoxelChunk::index(local);
+        if chunk.blocks[index] == block {
+            return;
+        }
+        chunk.blocks[index] = block;
+        chunk.revision = revision;
+
+        // Blocks on the border of a chunk also hide or reveal faces of the neighbouring chunk
+        for axis in 0..3 {
+            let step = match local[axis] {
+                0 => -1,
+                value if value == CHUNK_SIZE - 1 => 1,
+                _ => continue,
+            };
+            let mut neighbour = coordinate;
+            neighbour[axis] += step;
+            if let Some(chunk) = self.chunks.get_mut(&neighbour) {
+                chunk.revision = revision;
+            }
+        }
+    }
+
+    // Blocks of a chunk surrounded by a border of its neighbours' blocks
+    fn padded_blocks(&self, coordinate: [i32; 3]) -> Vec<BlockId> {
+        let origin = coordinate.map(|value| value * CHUNK_SIZE - 1);
+        let mut blocks = Vec::with_capacity((PADDED_SIZE * PADDED_SIZE * PADDED_SIZE) as usize);
+        for z in 0..PADDED_SIZE {
+            for y in 0..PADDED_SIZE {
+                for x in 0..PADDED_SIZE {
+                    blocks.push(self.block([origin[0] + x, origin[1] + y, origin[2] + z]));
+                }
+            }
+        }
+        blocks
+    }
+}
+
+#[repr(C)]
+#[derive(Default, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct VoxelVertex {
+    position: [f32; 3],
+    normal: [f32; 3],
+    uv: [f32; 2],
+    layer: u32,
+}
+
+impl VoxelVertex {
+    const ATTRIBUTES: [wgpu::VertexAttribute; 4] = wgpu::vertex_attr_array![
+        0 => Float32x3,
+        1 => Float32x3,
+        2 => Float32x2,
+        3 => Uint32
+    ];
+
+    fn layout<'a>() -> wgpu::VertexBufferLayout<'a> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<Self>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &Self::ATTRIBUTES,
+        }
+    }
+}
+
+// Where the world's corner is relative to the scene's origin, added to every vertex. At the same
+// location as the terrain's offset, since both draw shadows with shadow.wgsl
+fn offset_layout<'a>() -> wgpu::VertexBufferLayout<'a> {
+    const ATTRIBUTES: [wgpu::VertexAttribute; 1] = wgpu::vertex_attr_array![4 => Float32x3];
+    wgpu::VertexBufferLayout {
+        array_stride: std::mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
+        step_mode: wgpu::VertexStepMode::Instance,
+        attributes: &ATTRIBUTES,
+    }
+}
+
+struct MeshJob {
+    world: usize,
+    coordinate: [i32; 3],
+    revision: u64,
+    blocks: Vec<BlockId>,
+    block_descs: Arc<Vec<BlockDesc>>,
+    voxel_size: f32,
+}
+
+struct MeshResult {
+    world: usize,
+    coordinate: [i32; 3],
+    revision: u64,
+    vertices: Vec<VoxelVertex>,
+    indices: Vec<u32>,
+}
+
+// Meshes chunks on worker threads, which exit once it's dropped
+#[cfg(not(target_arch = "wasm32"))]
+struct Mesher {
+    jobs: Sender<MeshJob>,
+    results: Receiver<MeshResult>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Mesher {
+    fn new() -> Self {
+        let (jobs, pending_jobs) = channel::<MeshJob>();
+        let (finished, results) = channel::<MeshResult>();
+        let pending_jobs = Arc::new(Mutex::new(pending_jobs));
+        let worker_count = thread::available_parallelism()
+            .map(|count| count.get().saturating_sub(1).max(1))
+            .unwrap_or(1);
+        for _ in 0..worker_count {
+            let pending_jobs = pending_jobs.clone();
+            let finished = finished.clone();
+            thread::spawn(move || loop {
+                let job = match pending_jobs.lock().unwrap().recv() {
+                    Ok(job) => job,
+                    Err(_) => break,
+                };
+                if finished.send(greedy_mesh(job)).is_err() {
+                    break;
+                }
+            });
+        }
+        Self { jobs, results }
+    }
+
+    fn queue(&mut self, job: MeshJob) -> Result<()> {
+        if self.jobs.send(job).is_err() {
+            anyhow::bail!("The voxel meshing workers have stopped!");
+        }
+        Ok(())
+    }
+
+    fn finished(&mut self) -> impl Iterator<Item = MeshResult> + '_ {
+        self.results.try_iter()
+    }
+}
+
+// Browsers can't spawn threads, so chunks are meshed on the main thread as they're queued
+#[cfg(target_arch = "wasm32")]
+#[derive(Default)]
+struct Mesher {
+    results: Vec<MeshResult>,
+}
+
+#[cfg(target_arch = "wasm32")]
+impl Mesher {
+    fn new() -> Self {
+        Self::default()
+    }
+
+    fn queue(&mut self, job: MeshJob) -> Result<()> {
+        self.results.push(greedy_mesh(job));
+        Ok(())
+    }
+
+    fn finished(&mut self) -> impl Iterator<Item = MeshResult> + '_ {
+        self.results.drain(..)
+    }
+}
+
+// Merges neighbouring faces with the same texture into larger quads, one slice of the chunk at a time
+fn greedy_mesh(job: MeshJob) -> MeshResult {
+    let padded = |position: [i32; 3]| {
+        job.blocks[(((position[2] + 1) * PADDED_SIZE + position[1] + 1) * PADDED_SIZE
+            + position[0]
+            + 1) as usize]
+    };
+    let chunk_origin = job.coordinate.map(|value| (value * CHUNK_SIZE) as f32);
+
+    let mut vertices = Vec::new();
+    let mut indices = Vec::new();
+    let size = CHUNK_SIZE as usize;
+    let mut mask: Vec<Option<u32>> = vec![None; size * size];
+    for axis in 0..3 {
+        let (u, v) = ((axis + 1) % 3, (axis + 2) % 3);
+        for positive in [true, false] {
+            let mut normal = [0.0; 3];
+            normal[axis] = if positive { 1.0 } else { -1.0 };
+            for slice in 0..CHUNK_SIZE {
+                // Faces of solid blocks facing empty space, keyed by their texture layer
+                for j in 0..CHUNK_SIZE {
+                    for i in 0..CHUNK_SIZE {
+                        let mut position = [0; 3];
+                        position[axis] = slice;
+                        position[u] = i;
+                        position[v] = j;
+                        let block = padded(position);
+                        position[axis] += if positive { 1 } else { -1 };
+                        mask[(j * CHUNK_SIZE + i) as usize] =
+                            if block != AIR && padded(position) == AIR {
+                                let desc = job
+                                    .block_descs
+                                    .get(block as usize - 1)
+                                    .copied()
+                                    .unwrap_or_else(|| BlockDesc::uniform(0));
+                                Some(match (axis, positive) {
+                                    (1, true) => desc.top,
+                                    (1, false) => desc.bottom,
+                                    _ => desc.side,
+                                })
+                            } else {
+                                None
+                            };
+                    }
+                }
+
+                for j in 0..size {
+                    let mut i = 0;
+                    while i < size {
+                        let layer = match mask[j * size + i] {
+                            Some(layer) => layer,
+                            None => {
+                                i += 1;
+                                continue;
+                            }
+                        };
+                        let mut width = 1;
+                        while i + width < size && mask[j * size + i + width] == Some(layer) {
+                            width += 1;
+                        }
+                        let mut height = 1;
+                        while j + height < size
+                            && (0..width)
+                                .all(|offset| mask[(j + height) * size + i + offset] == Some(layer))
+                        {
+                            height += 1;
+                        }
+                        for row in j..j + height {
+                            for column in i..i + width {
+                                mask[row * size + column] = None;
+                            }
+                        }
+
+                        let mut base = [0.0; 3];
+                        base[axis] = (slice + if positive { 1 } else { 0 }) as f32;
+                        base[u] = i as f32;
+                        base[v] = j as f32;
+                        let corner = |along_u: f32, along_v: f32| {
+                            let mut corner = base;
+                            corner[u] += along_u;
+                            corner[v] += along_v;
+                            let block = glm::vec3(
+                                chunk_origin[0] + corner[0],
+                                chunk_origin[1] + corner[1],
+                                chunk_origin[2] + corner[2],
+                            );
+                            // Sides keep their textures upright
+                            let uv = match axis {
+                                0 => [block.z, -block.y],
+                                1 => [block.x, block.z],
+                                _ => [block.x, -block.y],
+                            };
+                            VoxelVertex {
+                                position: (block * job.voxel_size).into(),
+                                normal,
+                                uv,
+                                layer,
+                            }
+                        };
+                        let start = vertices.len() as u32;
+                        let (width, height) = (width as f32, height as f32);
+                        vertices.extend([
+                            corner(0.0, 0.0),
+                            corner(width, 0.0),
+                            corner(width, height),
+                            corner(0.0, height),
+                        ]);
+                        // The u and v axes wind counterclockwise around the positive direction
+                        let quad = if positive {
+                            [0, 1, 2, 0, 2, 3]
+                        } else {
+                            [0, 2, 1, 0, 3, 2]
+                        };
+                        indices.extend(quad.map(|index| start + index));
+                        i += width as usize;
+                    }
+                }
+            }
+        }
+    }
+
+    MeshResult {
+        world: job.world,
+        coordinate: job.coordinate,
+        revision: job.revision,
+        vertices,
+        indices,
+    }
+}
+
+struct ChunkMesh {
+    vertex_buffer: Tracked<wgpu::Buffer>,
+    index_buffer: Tracked<wgpu::Buffer>,
+    index_count: u32,
+    // Relative to the world's corner
+    bounds: Aabb,
+}
+
+struct GpuVoxelChunk {
+    revision: u64,
+    // Chunks without visible faces have no mesh
+    mesh: Option<ChunkMesh>,
+}
+
+struct GpuVoxelWorld {
+    bind_group: wgpu::BindGroup,
+    offset_buffer: Tracked<wgpu::Buffer>,
+    offset: glm::Vec3,
+    chunks: HashMap<[i32; 3], GpuVoxelChunk>,
+    // Revisions sent to the workers and not yet meshed
+    pending: HashMap<[i32; 3], u64>,
+    block_descs: Arc<Vec<BlockDesc>>,
+    _textures: Texture,
+}
+
+pub struct VoxelSystem {
+    bind_group_layout: wgpu::BindGroupLayout,
+    sampler: wgpu::Sampler,
+    pipeline: wgpu::RenderPipeline,
+    depth_pipeline: wgpu::RenderPipeline,
+    shadow_pipeline: wgpu::RenderPipeline,
+    worlds: Vec<GpuVoxelWorld>,
+    mesher: Mesher,
+}
+
+impl VoxelSystem {
+    pub fn new(
+        device: &wgpu::Device,
+        camera_bind_group_layout: &wgpu::BindGroupLayout,
+        light_bind_group_layout: &wgpu::BindGroupLayout,
+        shadow_caster_bind_group_layout: &wgpu::BindGroupLayout,
+        color_format: wgpu::TextureFormat,
+        depth: DepthConfig,
+    ) -> Self {
+        let bind_group_layout = memory::create_bind_group_layout(
+            device,
+            &wgpu::BindGroupLayoutDescriptor {
+                label: Some("Voxel Bind Group Layout"),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                            view_dimension: wgpu::TextureViewDimension::D2Array,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Sampler {
+                            filtering: true,
+                            comparison: false,
+                        },
+                        count: None,
+                    },
+                ],
+            },
+        );
+
+        // Blocks keep the crisp texels they are known for
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Voxel Sampler"),
+            address_mode_u: wgpu::AddressMode::Repeat,
+            address_mode_v: wgpu::AddressMode::Repeat,
+            ..Default::default()
+        });
+
+        let module = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("Voxel Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("shaders/voxel.wgsl").into()),
+        });
+
+        let primitive = wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            front_face: wgpu::FrontFace::Ccw,
+            cull_mode: Some(wgpu::Face::Back),
+            ..Default::default()
+        };
+
+        let pipeline_layout = memory::create_pipeline_layout(
+            device,
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("Voxel Pipeline Layout"),
+                bind_group_layouts: &[
+                    camera_bind_group_layout,
+                    &bind_group_layout,
+                    light_bind_group_layout,
+                ],
+                push_constant_ranges: &[],
+            },
+        );
+
+        // The depth prepass has already written the visible surfaces
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Voxel Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &module,
+                entry_point: "vs_main",
+                buffers: &[VoxelVertex::layout(), offset_layout()],
+            },
+            primitive,
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: depth.format,
+                depth_write_enabled: true,
+                depth_compare: depth.compare(wgpu::CompareFunction::LessEqual),
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: depth.multisample(),
+            fragment: Some(wgpu::FragmentState {
+                module: &module,
+                entry_point: "fs_main",
+                targets: &[wgpu::ColorTargetState {
+                    format: color_format,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                }],
+            }),
+        });
+
+        let depth_pipeline_layout = memory::create_pipeline_layout(
+            device,
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("Voxel Depth Pipeline Layout"),
+                bind_group_layouts: &[camera_bind_group_layout],
+                push_constant_ranges: &[],
+            },
+        );
+
+        let depth_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Voxel Depth Pipeline"),
+            layout: Some(&depth_pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &module,
+                entry_point: "vs_main",
+                buffers: &[VoxelVertex::layout(), offset_layout()],
+            },
+            primitive,
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: depth.format,
+                depth_write_enabled: true,
+                depth_compare: depth.compare(wgpu::CompareFunction::Less),
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState::default(),
+            fragment: None,
+        });
+
+        let shadow_module = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("Voxel Shadow Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("shaders/shadow.wgsl").into()),
+        });
+
+        let shadow_pipeline_layout = memory::create_pipeline_layout(
+            device,
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("Voxel Shadow Pipeline Layout"),
+                bind_group_layouts: &[shadow_caster_bind_group_layout],
+                push_constant_ranges: &[],
+            },
+        );
+
+        // Back faces are rendered too, so light can't leak through single block walls
+        let shadow_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Voxel Shadow Pipeline"),
+            layout: Some(&shadow_pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shadow_module,
+                entry_point: "vs_main",
+                buffers: &[VoxelVertex::layout(), offset_layout()],
+            },
+            primitive: wgpu::PrimitiveState {
+                cull_mode: None,
+                ..primitive
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: depth.shadow().format,
+                depth_write_enabled: true,
+                depth_compare: depth.compare(wgpu::CompareFunction::Less),
+                stencil: wgpu::StencilState::default(),
+                bias: depth.bias(wgpu::DepthBiasState {
+                    constant: 2,
+                    slope_scale: 2.0,
+                    clamp: 0.0,
+                }),
+            }),
+            multisample: wgpu::MultisampleState::default(),
+            fragment: None,
+        });
+
+        Self {
+            bind_group_layout,
+            sampler,
+            pipeline,
+            depth_pipeline,
+            shadow_pipeline,
+            worlds: Vec::new(),
+            mesher: Mesher::new(),
+        }
+    }
+
+    fn create_world(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        desc: &VoxelWorldDesc,
+    ) -> Result<GpuVoxelWorld> {
+        // Texture arrays need matching dimensions, so every layer is resized to match the first
+        let mut images = desc
+            .textures
+            .iter()
+            .map(|texture| texture.to_rgba8())
+            .collect::<Vec<_>>();
+        if images.is_empty() {
+            images.push(image::RgbaImage::from_pixel(
+                1,
+                1,
+                image::Rgba([128, 128, 128, 255]),
+            ));
+        }
+        let (width, height) = images[0].dimensions();
+        for image in images.iter_mut() {
+            if image.dimensions() != (width, height) {
+                *image = image::imageops::resize(
+                    image,
+                    width,
+                    height,
+                    image::imageops::FilterType::Triangle,
+                );
+            }
+        }
+        let textures = Texture::array_from_images(
+            device,
+            queue,
+            &images,
+            wgpu::TextureFormat::Rgba8UnormSrgb,
+            Some("Voxel Texture Array"),
+        )?;
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Voxel Bind Group"),
+            layout: &self.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&textures.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+            ],
+        });
+
+        let offset_buffer = memory::create_buffer(
+            device,
+            MemoryCategory::Transient,
+            &wgpu::BufferDescriptor {
+                label: Some("Voxel Offset Buffer"),
+                size: std::mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
+                usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            },
+        );
+
+        Ok(GpuVoxelWorld {
+            bind_group,
+            offset_buffer,
+            offset: glm::Vec3::zeros(),
+            chunks: HashMap::new(),
+            pending: HashMap::new(),
+            block_descs: Arc::new(desc.blocks.clone()),
+            _textures: textures,
+        })
+    }
+
+    pub fn update(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        scene: &Scene,
+    ) -> Result<()> {
+        while self.worlds.len() < scene.voxel_worlds.len() {
+            let world = self.create_world(device, queue, &scene.voxel_worlds[self.worlds.len()])?;
+            self.worlds.push(world);
+        }
+
+        // Meshes finished since the last frame replace older ones
+        for result in self.mesher.finished() {
+            let world = match self.worlds.get_mut(result.world) {
+                Some(world) => world,
+                None => continue,
+            };
+            if world.pending.get(&result.coordinate) == Some(&result.revision) {
+                world.pending.remove(&result.coordinate);
+            }
+            if let Some(chunk) = world.chunks.get(&result.coordinate) {
+                if chunk.revision >= result.revision {
+                    continue;
+                }
+            }
+            let mesh = (!result.indices.is_empty()).then(|| {
+                let mut bounds = Aabb::default();
+                for vertex in result.vertices.iter() {
+                    bounds.expand_to_include(&glm::Vec3::from(vertex.position));
+                }
+                ChunkMesh {
+                    vertex_buffer: memory::create_buffer_init(
+                        device,
+                        MemoryCategory::Meshes,
+                        &wgpu::util::BufferInitDescriptor {
+                            label: Some("Voxel Chunk Vertex Buffer"),
+                            contents: bytemuck::cast_slice(&result.vertices),
+                            usage: wgpu::BufferUsages::VERTEX,
+                        },
+                    ),
+                    index_buffer: memory::create_buffer_init(
+                        device,
+                        MemoryCategory::Meshes,
+                        &wgpu::util::BufferInitDescriptor {
+                            label: Some("Voxel Chunk Index Buffer"),
+                            contents: bytemuck::cast_slice(&result.indices),
+                            usage: wgpu::BufferUsages::INDEX,
+                        },
+                    ),
+                    index_count: result.indices.len() as u32,
+                    bounds,
+                }
+            });
+            world.chunks.insert(
+                result.coordinate,
+                GpuVoxelChunk {
+                    revision: result.revision,
+                    mesh,
+                },
+            );
+        }
+
+        for (index, (desc, world)) in scene
+            .voxel_worlds
+            .iter()
+            .zip(self.worlds.iter_mut())
+            .enumerate()
+        {
+            world.offset = scene.local_point(&desc.origin);
+            let offset: [f32; 3] = world.offset.into();
+            queue.write_buffer(&world.offset_buffer, 0, bytemuck::cast_slice(&offset));
+
+            world
+                .chunks
+                .retain(|coordinate, _| desc.chunks.contains_key(coordinate));
+
+            for (coordinate, chunk) in desc.chunks.iter() {
+                let meshed = world.chunks.get(coordinate).map(|chunk| chunk.revision);
+                let pending = world.pending.get(coordinate).copied();
+                if meshed == Some(chunk.revision) || pending == Some(chunk.revision) {
+                    continue;
+                }
+                world.pending.insert(*coordinate, chunk.revision);
+                let job = MeshJob {
+                    world: index,
+                    coordinate: *coordinate,
+                    revision: chunk.revision,
+                    blocks: desc.padded_blocks(*coordinate),
+                    block_descs: world.block_descs.clone(),
+                    voxel_size: desc.voxel_size,
+                };
+                self.mesher.queue(job)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    // Bounds of every meshed chunk, used to fit the shadow map
+    pub fn bounds(&self) -> Aabb {
+        let mut bounds = Aabb::default();
+        for world in self.worlds.iter() {
+            for mesh in Self::meshes(world) {
+                bounds.merge(&mesh.bounds.translated(&world.offset));
+            }
+        }
+        bounds
+    }
+
+    fn meshes(world: &GpuVoxelWorld) -> impl Iterator<Item = &ChunkMesh> {
+        world
+            .chunks
+            .values()
+            .filter_map(|chunk| chunk.mesh.as_ref())
+    }
+
+    // Chunks of the world inside the frustum
+    fn draw_meshes<'a>(
+        render_pass: &mut wgpu::RenderPass<'a>,
+        world: &'a GpuVoxelWorld,
+        frustum: &Frustum,
+    ) {
+        render_pass.set_vertex_buffer(1, world.offset_buffer.slice(..));
+        for mesh in Self::meshes(world) {
+            if frustum.intersects_aabb(&mesh.bounds.translated(&world.offset)) {
+                render_pass.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
+                render_pass
+                    .set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+                render_pass.draw_indexed(0..mesh.index_count, 0, 0..1);
+            }
+        }
+    }
+
+    pub fn render_shadows<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        shadow_caster_bind_group: &'a wgpu::BindGroup,
+        light_frustum: &Frustum,
+    ) {
+        render_pass.set_pipeline(&self.shadow_pipeline);
+        render_pass.set_bind_group(0, shadow_caster_bind_group, &[]);
+        for world in self.worlds.iter() {
+            Self::draw_meshes(render_pass, world, light_frustum);
+        }
+    }
+
+    pub fn render_depth<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        camera_bind_group: &'a wgpu::BindGroup,
+        frustum: &Frustum,
+    ) {
+        render_pass.set_pipeline(&self.depth_pipeline);
+        render_pass.set_bind_group(0, camera_bind_group, &[]);
+        for world in self.worlds.iter() {
+            Self::draw_meshes(render_pass, world, frustum);
+        }
+    }
+
+    pub fn render<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        camera_bind_group: &'a wgpu::BindGroup,
+        light_bind_group: &'a wgpu::BindGroup,
+        frustum: &Frustum,
+    ) {
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, camera_bind_group, &[]);
+        render_pass.set_bind_group(2, light_bind_group, &[]);
+        for world in self.worlds.iter() {
+            render_pass.set_bind_group(1, &world.bind_group, &[]);
+            Self::draw_meshes(render_pass, world, frustum);
+        }
+    }
+}
@@ -0,0 +1,445 @@
+use anyhow::{bail, Result};
+use nalgebra_glm as glm;
+use std::rc::Rc;
+
+use crate::{
+    memory::{self, MemoryCategory, Tracked},
+    render_targets::{FramePhase, RenderTargetPool},
+    scene::Scene,
+    settings::EffectQuality,
+    shader_snippets,
+    texture::Texture,
+};
+
+pub const MAX_WAVES: usize = 4;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct GerstnerWave {
+    pub direction: glm::Vec2,
+    pub wavelength: f32,
+    // Zero gives a sine wave, one gives sharp crests
+    pub steepness: f32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct WaterMaterial {
+    pub shallow_color: glm::Vec3,
+    pub deep_color: glm::Vec3,
+    // Fraction of each color channel absorbed per world unit of water
+    pub absorption: glm::Vec3,
+    pub foam_color: glm::Vec3,
+    // Reflected wherever screen space reflections find no geometry
+    pub sky_color: glm::Vec3,
+    // Water shallower than this is covered in foam
+    pub foam_distance: f32,
+    pub refraction_strength: f32,
+    pub reflection_strength: f32,
+    pub waves: Vec<GerstnerWave>,
+}
+
+impl Default for WaterMaterial {
+    fn default() -> Self {
+        Self {
+            shallow_color: glm::vec3(0.1, 0.45, 0.5),
+            deep_color: glm::vec3(0.01, 0.05, 0.12),
+            absorption: glm::vec3(0.45, 0.1, 0.07),
+            foam_color: glm::vec3(0.9, 0.95, 1.0),
+            sky_color: glm::vec3(0.45, 0.6, 0.8),
+            foam_distance: 0.4,
+            refraction_strength: 0.03,
+            reflection_strength: 1.0,
+            waves: vec![
+                GerstnerWave {
+                    direction: glm::vec2(1.0, 0.0),
+                    wavelength: 12.0,
+                    steepness: 0.2,
+                },
+                GerstnerWave {
+                    direction: glm::vec2(0.6, 0.8),
+                    wavelength: 6.0,
+                    steepness: 0.15,
+                },
+                GerstnerWave {
+                    direction: glm::vec2(-0.4, 0.9),
+                    wavelength: 2.5,
+                    steepness: 0.1,
+                },
+            ],
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct WaterDesc {
+    // Maps a unit plane on the local XZ axes, centered on the origin, into the world
+    pub transform: glm::Mat4,
+    // Quads along each side of the plane mesh
+    pub resolution: u32,
+    pub material: WaterMaterial,
+}
+
+impl Default for WaterDesc {
+    fn default() -> Self {
+        Self {
+            transform: glm::scale(&glm::Mat4::identity(), &glm::vec3(100.0, 1.0, 100.0)),
+            resolution: 128,
+            material: WaterMaterial::default(),
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Default, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct WaterUniform {
+    model: [[f32; 4]; 4],
+    shallow_color: [f32; 4],
+    deep_color: [f32; 4],
+    absorption: [f32; 4],
+    // w: foam distance
+    foam: [f32; 4],
+    sky_color: [f32; 4],
+    // xy: direction, z: steepness, w: wavelength
+    waves: [[f32; 4]; MAX_WAVES],
+    time: f32,
+    refraction_strength: f32,
+    reflection_strength: f32,
+    wave_count: u32,
+    resolution: u32,
+    reflection_steps: u32,
+    _padding: [u32; 2],
+}
+
+impl WaterUniform {
+    // The transform is relative to the scene's origin
+    fn new(desc: &WaterDesc, transform: &glm::Mat4, time: f32, quality: EffectQuality) -> Self {
+        let material = &desc.material;
+        let mut waves = [[0.0; 4]; MAX_WAVES];
+        for (gpu_wave, wave) in waves.iter_mut().zip(material.waves.iter()) {
+            let direction = glm::normalize(&wave.direction);
+            *gpu_wave = [direction.x, direction.y, wave.steepness, wave.wavelength];
+        }
+        Self {
+            model: (*transform).into(),
+            shallow_color: glm::vec3_to_vec4(&material.shallow_color).into(),
+            deep_color: glm::vec3_to_vec4(&material.deep_color).into(),
+            absorption: glm::vec3_to_vec4(&material.absorption).into(),
+            foam: [
+                material.foam_color.x,
+                material.foam_color.y,
+                material.foam_color.z,
+                material.foam_distance,
+            ],
+            sky_color: glm::vec3_to_vec4(&material.sky_color).into(),
+            waves,
+            time,
+            refraction_strength: material.refraction_strength,
+            reflection_strength: material.reflection_strength,
+            wave_count: material.waves.len().min(MAX_WAVES) as u32,
+            resolution: desc.resolution,
+            reflection_steps: quality.reflection_steps(),
+            _padding: [0; 2],
+        }
+    }
+}
+
+struct GpuWater {
+    uniform_buffer: Tracked<wgpu::Buffer>,
+    bind_group: wgpu::BindGroup,
+}
+
+pub struct WaterSystem {
+    water_bind_group_layout: wgpu::BindGroupLayout,
+    scene_bind_group_layout: wgpu::BindGroupLayout,
+    scene_bind_group: wgpu::BindGroup,
+    // Refraction and screen space reflections read the scene as it was before the water was drawn
+    scene_color_copy: Rc<Texture>,
+    pipeline: wgpu::RenderPipeline,
+    waters: Vec<GpuWater>,
+    time: f32,
+}
+
+impl WaterSystem {
+    pub fn new(
+        device: &wgpu::Device,
+        target_pool: &mut RenderTargetPool,
+        camera_bind_group_layout: &wgpu::BindGroupLayout,
+        color_format: wgpu::TextureFormat,
+        depth_texture: &Texture,
+        dimensions: &[u32; 2],
+    ) -> Self {
+        let water_bind_group_layout = memory::create_bind_group_layout(
+            device,
+            &wgpu::BindGroupLayoutDescriptor {
+                label: Some("Water Bind Group Layout"),
+                entries: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                }],
+            },
+        );
+
+        let scene_bind_group_layout = memory::create_bind_group_layout(
+            device,
+            &wgpu::BindGroupLayoutDescriptor {
+                label: Some("Water Scene Bind Group Layout"),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Depth,
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                ],
+            },
+        );
+
+        let scene_color_copy =
+            Self::create_scene_color_copy(device, target_pool, color_format, dimensions);
+        let scene_bind_group = Self::create_scene_bind_group(
+            device,
+            &scene_bind_group_layout,
+            depth_texture,
+            &scene_color_copy,
+        );
+
+        let module = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("Water Shader"),
+            source: wgpu::ShaderSource::Wgsl(
+                shader_snippets::splice(include_str!("shaders/water.wgsl")).into(),
+            ),
+        });
+
+        let pipeline_layout = memory::create_pipeline_layout(
+            device,
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("Water Pipeline Layout"),
+                bind_group_layouts: &[
+                    camera_bind_group_layout,
+                    &water_bind_group_layout,
+                    &scene_bind_group_layout,
+                ],
+                push_constant_ranges: &[],
+            },
+        );
+
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Water Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &module,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            // The surface is visible from above and below
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: None,
+                ..Default::default()
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            fragment: Some(wgpu::FragmentState {
+                module: &module,
+                entry_point: "fs_main",
+                targets: &[wgpu::ColorTargetState {
+                    format: color_format,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                }],
+            }),
+        });
+
+        Self {
+            water_bind_group_layout,
+            scene_bind_group_layout,
+            scene_bind_group,
+            scene_color_copy,
+            pipeline,
+            waters: Vec::new(),
+            time: 0.0,
+        }
+    }
+
+    // Copied and read while drawing the scene, including probe faces
+    fn create_scene_color_copy(
+        device: &wgpu::Device,
+        target_pool: &mut RenderTargetPool,
+        color_format: wgpu::TextureFormat,
+        dimensions: &[u32; 2],
+    ) -> Rc<Texture> {
+        target_pool.acquire(
+            device,
+            dimensions[0],
+            dimensions[1],
+            color_format,
+            "Water Scene Color Copy",
+            FramePhase::Probes..=FramePhase::Scene,
+        )
+    }
+
+    fn create_scene_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        depth_texture: &Texture,
+        scene_color_copy: &Texture,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Water Scene Bind Group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&depth_texture.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&scene_color_copy.view),
+                },
+            ],
+        })
+    }
+
+    pub fn resize(
+        &mut self,
+        device: &wgpu::Device,
+        target_pool: &mut RenderTargetPool,
+        color_format: wgpu::TextureFormat,
+        depth_texture: &Texture,
+        dimensions: &[u32; 2],
+    ) {
+        self.scene_color_copy =
+            Self::create_scene_color_copy(device, target_pool, color_format, dimensions);
+        self.scene_bind_group = Self::create_scene_bind_group(
+            device,
+            &self.scene_bind_group_layout,
+            depth_texture,
+            &self.scene_color_copy,
+        );
+    }
+
+    fn create_water(&self, device: &wgpu::Device, desc: &WaterDesc) -> Result<GpuWater> {
+        if desc.resolution == 0 {
+            bail!("Water resolution must be greater than zero!");
+        }
+        if desc.material.waves.len() > MAX_WAVES {
+            bail!(
+                "Water supports at most {} waves, found {}!",
+                MAX_WAVES,
+                desc.material.waves.len()
+            );
+        }
+
+        let uniform_buffer = memory::create_buffer(
+            device,
+            MemoryCategory::Transient,
+            &wgpu::BufferDescriptor {
+                label: Some("Water Uniform Buffer"),
+                size: std::mem::size_of::<WaterUniform>() as wgpu::BufferAddress,
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            },
+        );
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Water Bind Group"),
+            layout: &self.water_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: uniform_buffer.as_entire_binding(),
+            }],
+        });
+
+        Ok(GpuWater {
+            uniform_buffer,
+            bind_group,
+        })
+    }
+
+    pub fn update(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        scene: &Scene,
+        delta_time: f32,
+        quality: EffectQuality,
+    ) -> Result<()> {
+        self.time += delta_time;
+
+        while self.waters.len() < scene.waters.len() {
+            let water = self.create_water(device, &scene.waters[self.waters.len()])?;
+            self.waters.push(water);
+        }
+
+        for (desc, water) in scene.waters.iter().zip(self.waters.iter()) {
+            queue.write_buffer(
+                &water.uniform_buffer,
+                0,
+                bytemuck::cast_slice(&[WaterUniform::new(
+                    desc,
+                    &scene.local_transform(&desc.transform.cast()),
+                    self.time,
+                    quality,
+                )]),
+            );
+        }
+
+        Ok(())
+    }
+
+    pub fn copy_scene_color(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        scene_color: &Texture,
+        dimensions: &[u32; 2],
+        scene: &Scene,
+    ) {
+        if scene.waters.is_empty() {
+            return;
+        }
+        encoder.copy_texture_to_texture(
+            scene_color.texture.as_image_copy(),
+            self.scene_color_copy.texture.as_image_copy(),
+            wgpu::Extent3d {
+                width: dimensions[0],
+                height: dimensions[1],
+                depth_or_array_layers: 1,
+            },
+        );
+    }
+
+    pub fn render<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        camera_bind_group: &'a wgpu::BindGroup,
+        scene: &Scene,
+    ) {
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, camera_bind_group, &[]);
+        render_pass.set_bind_group(2, &self.scene_bind_group, &[]);
+        for (desc, water) in scene.waters.iter().zip(self.waters.iter()) {
+            render_pass.set_bind_group(1, &water.bind_group, &[]);
+            render_pass.draw(0..desc.resolution * desc.resolution * 6, 0..1);
+        }
+    }
+}
@@ -0,0 +1,59 @@
+use anyhow::{anyhow, bail, Context, Result};
+use wasm_bindgen::JsCast;
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{Request, RequestInit, RequestMode, Response};
+use winit::{platform::web::WindowExtWebSys, window::Window};
+
+// Errors thrown by the browser are plain javascript values, so only their description is kept
+fn js_error(value: wasm_bindgen::JsValue) -> anyhow::Error {
+    anyhow!("{:?}", value)
+}
+
+// The window draws into a canvas that has to be placed on the page before it is visible
+pub fn attach_canvas(window: &Window) -> Result<()> {
+    let body = web_sys::window()
+        .and_then(|window| window.document())
+        .and_then(|document| document.body())
+        .context("Failed to find the document body!")?;
+    body.append_child(&window.canvas()).map_err(js_error)?;
+    Ok(())
+}
+
+// The asset named by the page's query string, such as `?asset=models/helmet.glb`
+pub fn asset_url() -> Option<String> {
+    let search = web_sys::window()?.location().search().ok()?;
+    search
+        .trim_start_matches('?')
+        .split('&')
+        .find_map(|parameter| parameter.strip_prefix("asset="))
+        .map(str::to_string)
+}
+
+// Files aren't available in the browser, so assets are requested from the server instead
+pub async fn fetch(url: &str) -> Result<Vec<u8>> {
+    let window = web_sys::window().context("Failed to find the browser window!")?;
+
+    let mut init = RequestInit::new();
+    init.method("GET");
+    init.mode(RequestMode::Cors);
+    let request = Request::new_with_str_and_init(url, &init).map_err(js_error)?;
+
+    let response = JsFuture::from(window.fetch_with_request(&request))
+        .await
+        .map_err(js_error)?
+        .dyn_into::<Response>()
+        .map_err(js_error)?;
+    if !response.ok() {
+        bail!(
+            "Failed to fetch {}: {} {}",
+            url,
+            response.status(),
+            response.status_text()
+        );
+    }
+
+    let buffer = JsFuture::from(response.array_buffer().map_err(js_error)?)
+        .await
+        .map_err(js_error)?;
+    Ok(js_sys::Uint8Array::new(&buffer).to_vec())
+}
@@ -0,0 +1,92 @@
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use winit::{
+    monitor::{MonitorHandle, VideoMode},
+    window::{Fullscreen, Window},
+};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum WindowMode {
+    Windowed,
+    // Covers the monitor with an undecorated window, switching to it is instant
+    Borderless,
+    // Takes over the monitor's video mode, which can lower latency on some platforms
+    Exclusive,
+}
+
+impl FromStr for WindowMode {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        Ok(match value.trim() {
+            "windowed" => Self::Windowed,
+            "borderless" => Self::Borderless,
+            "exclusive" => Self::Exclusive,
+            _ => bail!(
+                "Unknown window mode '{}', expected one of: windowed, borderless, exclusive",
+                value
+            ),
+        })
+    }
+}
+
+impl WindowMode {
+    pub fn of(window: &Window) -> Self {
+        match window.fullscreen() {
+            None => Self::Windowed,
+            Some(Fullscreen::Borderless(_)) => Self::Borderless,
+            Some(Fullscreen::Exclusive(_)) => Self::Exclusive,
+        }
+    }
+
+    pub fn next(self) -> Self {
+        match self {
+            Self::Windowed => Self::Borderless,
+            Self::Borderless => Self::Exclusive,
+            Self::Exclusive => Self::Windowed,
+        }
+    }
+}
+
+// Falls back to borderless when the monitor reports no video modes, as on the web
+pub fn set_window_mode(window: &Window, window_mode: WindowMode) {
+    let monitor = window.current_monitor();
+    let fullscreen = match window_mode {
+        WindowMode::Windowed => None,
+        WindowMode::Borderless => Some(Fullscreen::Borderless(monitor)),
+        WindowMode::Exclusive => match monitor.as_ref().and_then(best_video_mode) {
+            Some(video_mode) => Some(Fullscreen::Exclusive(video_mode)),
+            None => {
+                eprintln!("Warning: No video modes available, using borderless fullscreen");
+                Some(Fullscreen::Borderless(monitor))
+            }
+        },
+    };
+    window.set_fullscreen(fullscreen);
+}
+
+// The monitor's video modes from the largest and fastest down
+fn video_modes(monitor: &MonitorHandle) -> Vec<VideoMode> {
+    let mut video_modes = monitor.video_modes().collect::<Vec<_>>();
+    video_modes.sort_by_key(|video_mode| {
+        let size = video_mode.size();
+        std::cmp::Reverse((
+            size.width * size.height,
+            video_mode.refresh_rate(),
+            video_mode.bit_depth(),
+        ))
+    });
+    video_modes
+}
+
+// Prefers the monitor's native resolution so the desktop isn't rescaled
+fn best_video_mode(monitor: &MonitorHandle) -> Option<VideoMode> {
+    let native_size = monitor.size();
+    let video_modes = video_modes(monitor);
+    video_modes
+        .iter()
+        .find(|video_mode| video_mode.size() == native_size)
+        .or_else(|| video_modes.first())
+        .cloned()
+}
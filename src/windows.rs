@@ -0,0 +1,157 @@
+use anyhow::{Context, Result};
+use nalgebra_glm as glm;
+use raw_window_handle::HasRawWindowHandle;
+
+use crate::{
+    blit::{BlitPipeline, BlitUniform},
+    camera::Camera,
+    memory::{self, MemoryCategory, Tracked},
+    texture::Texture,
+};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct WindowHandle(pub usize);
+
+// A window besides the main one, showing the scene from its own camera. It shares the device,
+// the scene's GPU resources and the main view's render targets, so it is rendered at the main
+// view's resolution and fit into the window
+pub struct SecondaryWindow {
+    pub camera: Camera,
+    pub dimensions: [u32; 2],
+    // Kept apart from the main view's so velocities are measured against this camera
+    pub previous_view_projection: Option<glm::Mat4>,
+    // Released while switching adapters until the window is attached to the new device
+    pub surface: Option<WindowSurface>,
+}
+
+impl SecondaryWindow {
+    pub fn new(camera: Camera, dimensions: [u32; 2]) -> Self {
+        Self {
+            camera,
+            dimensions,
+            previous_view_projection: None,
+            surface: None,
+        }
+    }
+}
+
+pub struct WindowSurface {
+    surface: wgpu::Surface,
+    config: wgpu::SurfaceConfiguration,
+    uniform_buffer: Tracked<wgpu::Buffer>,
+    blit_pipeline: BlitPipeline,
+    // The scene is rendered here first, recreated when the main view changes size or format
+    target: Option<WindowTarget>,
+}
+
+struct WindowTarget {
+    texture: Texture,
+    format: wgpu::TextureFormat,
+    dimensions: [u32; 2],
+    bind_group: wgpu::BindGroup,
+}
+
+impl WindowSurface {
+    pub fn new(
+        instance: &wgpu::Instance,
+        adapter: &wgpu::Adapter,
+        device: &wgpu::Device,
+        window_handle: &impl HasRawWindowHandle,
+        dimensions: &[u32; 2],
+    ) -> Result<Self> {
+        let surface = unsafe { instance.create_surface(window_handle) };
+
+        let format = surface
+            .get_preferred_format(adapter)
+            .context("Failed to get preferred surface format!")?;
+
+        let config = wgpu::SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            format,
+            width: dimensions[0].max(1),
+            height: dimensions[1].max(1),
+            present_mode: wgpu::PresentMode::Fifo,
+        };
+        surface.configure(device, &config);
+
+        let uniform_buffer = memory::create_buffer(
+            device,
+            MemoryCategory::Transient,
+            &wgpu::BufferDescriptor {
+                label: Some("Window Blit Uniform Buffer"),
+                size: std::mem::size_of::<BlitUniform>() as wgpu::BufferAddress,
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            },
+        );
+
+        Ok(Self {
+            surface,
+            config,
+            uniform_buffer,
+            blit_pipeline: BlitPipeline::new(device, format),
+            target: None,
+        })
+    }
+
+    pub fn resize(&mut self, device: &wgpu::Device, dimensions: &[u32; 2]) {
+        if dimensions[0] == 0 || dimensions[1] == 0 {
+            return;
+        }
+        self.config.width = dimensions[0];
+        self.config.height = dimensions[1];
+        self.surface.configure(device, &self.config);
+    }
+
+    pub fn current_texture(&self) -> Result<wgpu::SurfaceTexture, wgpu::SurfaceError> {
+        self.surface.get_current_texture()
+    }
+
+    // Where the scene is rendered, in the format and at the size of the main view
+    pub fn prepare_target(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        format: wgpu::TextureFormat,
+        dimensions: &[u32; 2],
+    ) -> &wgpu::TextureView {
+        let target = match self.target.take() {
+            Some(target) if target.format == format && target.dimensions == *dimensions => target,
+            _ => {
+                let texture = Texture::create_render_target(
+                    device,
+                    dimensions[0],
+                    dimensions[1],
+                    format,
+                    "Window Target Texture",
+                );
+                let bind_group =
+                    self.blit_pipeline
+                        .create_bind_group(device, &texture, &self.uniform_buffer);
+                WindowTarget {
+                    texture,
+                    format,
+                    dimensions: *dimensions,
+                    bind_group,
+                }
+            }
+        };
+
+        let uniform = BlitUniform::fit(dimensions, &[self.config.width, self.config.height]);
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[uniform]));
+
+        &self.target.insert(target).texture.view
+    }
+
+    pub fn blit(&self, encoder: &mut wgpu::CommandEncoder, view: &wgpu::TextureView) {
+        if let Some(target) = self.target.as_ref() {
+            self.blit_pipeline.blit(
+                encoder,
+                view,
+                &target.bind_group,
+                wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                None,
+            );
+        }
+    }
+}